@@ -0,0 +1,1622 @@
+// Typed async client for the SCADA backend API. Requests and responses use the same
+// scada-models types as the server, so agents built on this client cannot drift from it.
+//
+//     let mut client = Client::new("http://scada:8080");
+//     client.login("admin", &password).await?;
+//     let machines = client.list_machines(None).await?.machines;
+//
+// Edge agents authenticate with their machine API key instead of logging in:
+//
+//     let agent = Client::new("http://scada:8080").with_token(api_key);
+//     agent.update_machine_speed(&SpeedUpdateRequest {
+//         speed: 42.0, message: None, metrics: vec![], device_timestamp: None, lot_number: None, product: None,
+//     }).await?;
+//
+// The SSE feed (GET /api/machines/stream) is meant for browsers' EventSource and is not wrapped.
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
+
+pub use scada_models as models;
+use scada_models::*;
+
+#[derive(Debug)]
+pub enum Error {
+    // The request never produced a response, or the response body was not what the API promises
+    Http(reqwest::Error),
+    // The server answered with an error status and message
+    Api {
+        status: StatusCode,
+        error: String,
+        // Set on 5xx responses; quote it when reporting a server failure
+        incident_id: Option<String>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "request failed: {}", e),
+            Error::Api { status, error, incident_id: Some(incident_id) } => {
+                write!(f, "{}: {} (incident {})", status, error, incident_id)
+            },
+            Error::Api { status, error, incident_id: None } => write!(f, "{}: {}", status, error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Body of every error response
+#[derive(Deserialize)]
+struct ApiError {
+    error: String,
+    incident_id: Option<String>,
+}
+
+// Filters for the expired contracts report; unset fields are left out of the query
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ExpiredContractFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_of: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub machine_type: Option<String>,
+}
+
+// Query string pairs, leaving out parameters that were not given
+fn query<const N: usize>(pairs: [(&'static str, Option<String>); N]) -> Vec<(&'static str, String)> {
+    pairs.into_iter().filter_map(|(name, value)| value.map(|value| (name, value))).collect()
+}
+
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl Client {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    // Use a preconfigured reqwest client, e.g. with timeouts or a proxy
+    pub fn with_http_client(http: reqwest::Client, base_url: impl Into<String>) -> Self {
+        Self {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: None,
+        }
+    }
+
+    // Authenticate with a user token or a machine API key
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.base_url, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn checked(request: RequestBuilder) -> Result<reqwest::Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let (error, incident_id) = match serde_json::from_str::<ApiError>(&body) {
+            Ok(api_error) => (api_error.error, api_error.incident_id),
+            Err(_) => (body, None),
+        };
+        Err(Error::Api { status, error, incident_id })
+    }
+
+    async fn send<T: DeserializeOwned>(request: RequestBuilder) -> Result<T> {
+        Ok(Self::checked(request).await?.json().await?)
+    }
+
+    async fn send_empty(request: RequestBuilder) -> Result<()> {
+        Self::checked(request).await?;
+        Ok(())
+    }
+
+    async fn send_bytes(request: RequestBuilder) -> Result<Vec<u8>> {
+        Ok(Self::checked(request).await?.bytes().await?.to_vec())
+    }
+
+    // Authentication and server
+
+    // POST /api/login; the returned token is used for later requests
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<LoginResponse> {
+        self.login_with(LoginRequest {
+            username: username.to_string(),
+            password: password.to_string(),
+            new_password: None,
+        })
+        .await
+    }
+
+    // POST /api/login for an account whose password expired, setting a new one
+    pub async fn login_changing_password(&mut self, username: &str, password: &str, new_password: &str) -> Result<LoginResponse> {
+        self.login_with(LoginRequest {
+            username: username.to_string(),
+            password: password.to_string(),
+            new_password: Some(new_password.to_string()),
+        })
+        .await
+    }
+
+    async fn login_with(&mut self, request: LoginRequest) -> Result<LoginResponse> {
+        let response: LoginResponse = Self::send(self.request(Method::POST, "/api/login").json(&request)).await?;
+        self.token = Some(response.token.clone());
+        Ok(response)
+    }
+
+    // GET /api/password-policy
+    pub async fn get_password_policy(&self) -> Result<PasswordPolicyResponse> {
+        Self::send(self.request(Method::GET, "/api/password-policy")).await
+    }
+
+    // POST /api/token/refresh; the new access token is used for later requests. Keep the
+    // returned refresh token, the one passed in no longer works.
+    pub async fn refresh(&mut self, refresh_token: &str) -> Result<LoginResponse> {
+        let request = RefreshTokenRequest {
+            refresh_token: refresh_token.to_string(),
+        };
+        let response: LoginResponse = Self::send(self.request(Method::POST, "/api/token/refresh").json(&request)).await?;
+        self.token = Some(response.token.clone());
+        Ok(response)
+    }
+
+    // POST /api/logout; pass the refresh token to end the whole session
+    pub async fn logout(&mut self, refresh_token: Option<&str>) -> Result<()> {
+        let request = LogoutRequest {
+            refresh_token: refresh_token.map(str::to_string),
+        };
+        Self::send_empty(self.request(Method::POST, "/api/logout").json(&request)).await?;
+        self.token = None;
+        Ok(())
+    }
+
+    // GET /api/info
+    pub async fn get_info(&self) -> Result<InfoResponse> {
+        Self::send(self.request(Method::GET, "/api/info")).await
+    }
+
+    // Machines
+
+    // GET /api/machines
+    pub async fn list_machines(&self, locale: Option<&str>) -> Result<MachineListResponse> {
+        let params = query([("locale", locale.map(str::to_string))]);
+        Self::send(self.request(Method::GET, "/api/machines").query(&params)).await
+    }
+
+    // GET /api/machines?include_archived=true
+    pub async fn list_all_machines(&self, locale: Option<&str>) -> Result<MachineListResponse> {
+        let params = query([("locale", locale.map(str::to_string)), ("include_archived", Some("true".to_string()))]);
+        Self::send(self.request(Method::GET, "/api/machines").query(&params)).await
+    }
+
+    // GET /api/machines?sort=health; worst health score first
+    pub async fn list_machines_by_health(&self) -> Result<MachineListResponse> {
+        let params = query([("sort", Some("health".to_string()))]);
+        Self::send(self.request(Method::GET, "/api/machines").query(&params)).await
+    }
+
+    // POST /api/machines
+    pub async fn create_machine(&self, machine: &CreateMachineRequest) -> Result<MachineResponse> {
+        Self::send(self.request(Method::POST, "/api/machines").json(machine)).await
+    }
+
+    // PUT /api/machines/{id}
+    pub async fn update_machine(&self, machine_id: i64, update: &UpdateMachineRequest) -> Result<MachineResponse> {
+        Self::send(self.request(Method::PUT, &format!("/api/machines/{}", machine_id)).json(update)).await
+    }
+
+    // DELETE /api/machines/{id}; archives the machine, keeping its data
+    pub async fn archive_machine(&self, machine_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/machines/{}", machine_id))).await
+    }
+
+    // POST /api/machines/{id}/restore
+    pub async fn restore_machine(&self, machine_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::POST, &format!("/api/machines/{}/restore", machine_id))).await
+    }
+
+    // POST /api/machines/update, authenticated with the machine's API key
+    pub async fn update_machine_speed(&self, update: &SpeedUpdateRequest) -> Result<UpdateResponse> {
+        Self::send(self.request(Method::POST, "/api/machines/update").json(update)).await
+    }
+
+    // GET /api/machines/me, authenticated with the machine's API key
+    pub async fn get_own_machine(&self) -> Result<MachineSelfResponse> {
+        Self::send(self.request(Method::GET, "/api/machines/me")).await
+    }
+
+    // GET /api/machines/changes; waits up to `timeout` seconds for a change after `cursor`
+    pub async fn wait_for_machine_changes(
+        &self,
+        cursor: Option<i64>,
+        timeout: Option<u64>,
+        locale: Option<&str>,
+    ) -> Result<MachineChangesResponse> {
+        let params = query([
+            ("cursor", cursor.map(|v| v.to_string())),
+            ("timeout", timeout.map(|v| v.to_string())),
+            ("locale", locale.map(str::to_string)),
+        ]);
+        Self::send(self.request(Method::GET, "/api/machines/changes").query(&params)).await
+    }
+
+    // GET /api/machines/{id}/full
+    pub async fn get_machine_detail(&self, machine_id: i64, locale: Option<&str>) -> Result<MachineDetailResponse> {
+        let params = query([("locale", locale.map(str::to_string))]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/full", machine_id)).query(&params)).await
+    }
+
+    // GET /api/machines/{id}/history
+    pub async fn get_history(
+        &self,
+        machine_id: i64,
+        limit: Option<i64>,
+        range: Option<&str>,
+        batch: Option<i64>,
+    ) -> Result<HistoryResponse> {
+        let params = query([
+            ("limit", limit.map(|v| v.to_string())),
+            ("range", range.map(str::to_string)),
+            ("batch", batch.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/history", machine_id)).query(&params)).await
+    }
+
+    // GET /api/machines/{id}/history?from=&to=; resolution is "raw", "hour" or "day", chosen
+    // from the window's length when None
+    pub async fn get_history_between(
+        &self,
+        machine_id: i64,
+        from: i64,
+        to: i64,
+        resolution: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<HistoryResponse> {
+        let params = query([
+            ("from", Some(from.to_string())),
+            ("to", Some(to.to_string())),
+            ("resolution", resolution.map(str::to_string)),
+            ("limit", limit.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/history", machine_id)).query(&params)).await
+    }
+
+    // GET /api/machines/{id}/gaps; min_gap like "10m"
+    pub async fn get_machine_gaps(
+        &self,
+        machine_id: i64,
+        min_gap: Option<&str>,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<MachineGapsResponse> {
+        let params = query([
+            ("min_gap", min_gap.map(str::to_string)),
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/gaps", machine_id)).query(&params)).await
+    }
+
+    // POST /api/machines/{id}/history/export; poll the job with get_job
+    pub async fn export_history(&self, machine_id: i64, export: &HistoryExportRequest) -> Result<JobCreatedResponse> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/history/export", machine_id)).json(export)).await
+    }
+
+    // POST /api/export-requests
+    pub async fn create_export_request(&self, request: &CreateExportRequest) -> Result<ExportRequest> {
+        Self::send(self.request(Method::POST, "/api/export-requests").json(request)).await
+    }
+
+    // GET /api/export-requests
+    pub async fn list_export_requests(&self, status: Option<&str>, machine_id: Option<i64>) -> Result<ExportRequestListResponse> {
+        let params = query([("status", status.map(str::to_string)), ("machine_id", machine_id.map(|v| v.to_string()))]);
+        Self::send(self.request(Method::GET, "/api/export-requests").query(&params)).await
+    }
+
+    // GET /api/export-requests/{id}
+    pub async fn get_export_request(&self, request_id: i64) -> Result<ExportRequest> {
+        Self::send(self.request(Method::GET, &format!("/api/export-requests/{}", request_id))).await
+    }
+
+    // POST /api/export-requests/{id}/approve; the export job is in the returned job_id
+    pub async fn approve_export_request(&self, request_id: i64, decision: &DecideExportRequest) -> Result<ExportRequest> {
+        Self::send(self.request(Method::POST, &format!("/api/export-requests/{}/approve", request_id)).json(decision)).await
+    }
+
+    // POST /api/export-requests/{id}/reject
+    pub async fn reject_export_request(&self, request_id: i64, decision: &DecideExportRequest) -> Result<ExportRequest> {
+        Self::send(self.request(Method::POST, &format!("/api/export-requests/{}/reject", request_id)).json(decision)).await
+    }
+
+    // POST /api/machines/{id}/history/shift; poll the job with get_job
+    pub async fn shift_history(&self, machine_id: i64, shift: &HistoryShiftRequest) -> Result<JobCreatedResponse> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/history/shift", machine_id)).json(shift)).await
+    }
+
+    // GET /api/machines/{id}/history/shifts
+    pub async fn list_history_shifts(&self, machine_id: i64) -> Result<HistoryShiftListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/history/shifts", machine_id))).await
+    }
+
+    // POST /api/history-shifts/{id}/undo; poll the job with get_job
+    pub async fn undo_history_shift(&self, shift_id: i64) -> Result<JobCreatedResponse> {
+        Self::send(self.request(Method::POST, &format!("/api/history-shifts/{}/undo", shift_id))).await
+    }
+
+    // POST /api/machines/{id}/merge
+    pub async fn merge_machines(&self, machine_id: i64, merge: &MergeMachinesRequest) -> Result<MachineMergeResponse> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/merge", machine_id)).json(merge)).await
+    }
+
+    // POST /api/machines/{id}/decommission
+    pub async fn decommission_machine(&self, machine_id: i64) -> Result<JobCreatedResponse> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/decommission", machine_id))).await
+    }
+
+    // GET /api/machine-archives
+    pub async fn list_machine_archives(&self) -> Result<MachineArchiveListResponse> {
+        Self::send(self.request(Method::GET, "/api/machine-archives")).await
+    }
+
+    // GET /api/machine-archives/{id}/download
+    pub async fn download_machine_archive(&self, archive_id: i64) -> Result<Vec<u8>> {
+        Self::send_bytes(self.request(Method::GET, &format!("/api/machine-archives/{}/download", archive_id))).await
+    }
+
+    // GET /api/machines/{id}/metrics
+    pub async fn get_machine_metrics(&self, machine_id: i64) -> Result<MachineMetricListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/metrics", machine_id))).await
+    }
+
+    // GET /api/machines/{id}/display-names
+    pub async fn list_display_names(&self, machine_id: i64) -> Result<DisplayNameListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/display-names", machine_id))).await
+    }
+
+    // PUT /api/machines/{id}/display-names/{locale}
+    pub async fn set_display_name(
+        &self,
+        machine_id: i64,
+        locale: &str,
+        display_name: &SetDisplayNameRequest,
+    ) -> Result<MachineDisplayName> {
+        let path = format!("/api/machines/{}/display-names/{}", machine_id, locale);
+        Self::send(self.request(Method::PUT, &path).json(display_name)).await
+    }
+
+    // DELETE /api/machines/{id}/display-names/{locale}
+    pub async fn delete_display_name(&self, machine_id: i64, locale: &str) -> Result<()> {
+        let path = format!("/api/machines/{}/display-names/{}", machine_id, locale);
+        Self::send_empty(self.request(Method::DELETE, &path)).await
+    }
+
+    // Trend annotations
+
+    // GET /api/machines/{id}/annotations
+    pub async fn list_annotations(&self, machine_id: i64, from: Option<i64>, to: Option<i64>) -> Result<TrendAnnotationListResponse> {
+        let params = query([
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/annotations", machine_id)).query(&params)).await
+    }
+
+    // POST /api/machines/{id}/annotations
+    pub async fn add_annotation(&self, machine_id: i64, annotation: &CreateTrendAnnotationRequest) -> Result<TrendAnnotation> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/annotations", machine_id)).json(annotation)).await
+    }
+
+    // DELETE /api/annotations/{id}
+    pub async fn delete_annotation(&self, annotation_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/annotations/{}", annotation_id))).await
+    }
+
+    // Batches
+
+    // GET /api/machines/{id}/batches
+    pub async fn list_machine_batches(&self, machine_id: i64, from: Option<i64>, to: Option<i64>) -> Result<MachineBatchListResponse> {
+        let params = query([
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/batches", machine_id)).query(&params)).await
+    }
+
+    // POST /api/machines/{id}/batches
+    pub async fn start_batch(&self, machine_id: i64, batch: &StartBatchRequest) -> Result<MachineBatch> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/batches", machine_id)).json(batch)).await
+    }
+
+    // POST /api/batches/{id}/end
+    pub async fn end_batch(&self, batch_id: i64) -> Result<MachineBatch> {
+        Self::send(self.request(Method::POST, &format!("/api/batches/{}/end", batch_id))).await
+    }
+
+    // GET /api/batches?lot_number=
+    pub async fn find_lot_batches(&self, lot_number: &str) -> Result<MachineBatchListResponse> {
+        Self::send(self.request(Method::GET, "/api/batches").query(&[("lot_number", lot_number)])).await
+    }
+
+    // Changeovers
+
+    // GET /api/machines/{id}/changeovers
+    pub async fn list_machine_changeovers(&self, machine_id: i64, from: Option<i64>, to: Option<i64>) -> Result<ChangeoverListResponse> {
+        let params = query([
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/changeovers", machine_id)).query(&params)).await
+    }
+
+    // POST /api/machines/{id}/changeovers
+    pub async fn start_changeover(&self, machine_id: i64, changeover: &StartChangeoverRequest) -> Result<Changeover> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/changeovers", machine_id)).json(changeover)).await
+    }
+
+    // POST /api/changeovers/{id}/end
+    pub async fn end_changeover(&self, changeover_id: i64) -> Result<Changeover> {
+        Self::send(self.request(Method::POST, &format!("/api/changeovers/{}/end", changeover_id))).await
+    }
+
+    // Operator assignments
+
+    // POST /api/machines/{id}/assignments
+    pub async fn assign_operator(&self, machine_id: i64, assignment: &AssignOperatorRequest) -> Result<OperatorAssignment> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/assignments", machine_id)).json(assignment)).await
+    }
+
+    // DELETE /api/assignments/{id}
+    pub async fn delete_assignment(&self, assignment_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/assignments/{}", assignment_id))).await
+    }
+
+    // GET /api/shift-schedule; dates are local, like "2024-03-18"
+    pub async fn get_shift_schedule(
+        &self,
+        from: Option<&str>,
+        to: Option<&str>,
+        machine_id: Option<i64>,
+    ) -> Result<ShiftScheduleResponse> {
+        let params = query([
+            ("from", from.map(str::to_string)),
+            ("to", to.map(str::to_string)),
+            ("machine_id", machine_id.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, "/api/shift-schedule").query(&params)).await
+    }
+
+    // Maintenance comments
+
+    // GET /api/machines/{id}/comments; status is "open" or "resolved"
+    pub async fn get_comments(&self, machine_id: i64, status: Option<&str>) -> Result<CommentListResponse> {
+        let params = query([("status", status.map(str::to_string))]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/comments", machine_id)).query(&params)).await
+    }
+
+    // POST /api/machines/{id}/comments
+    pub async fn add_comment(&self, machine_id: i64, comment: &AddCommentRequest) -> Result<MaintenanceComment> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/comments", machine_id)).json(comment)).await
+    }
+
+    // GET /api/comments
+    pub async fn list_recent_comments(
+        &self,
+        priority: Option<&str>,
+        status: Option<&str>,
+        since: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<CommentFeedResponse> {
+        let params = query([
+            ("priority", priority.map(str::to_string)),
+            ("status", status.map(str::to_string)),
+            ("since", since.map(|v| v.to_string())),
+            ("limit", limit.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, "/api/comments").query(&params)).await
+    }
+
+    // POST /api/comments/{id}/pin
+    pub async fn pin_comment(&self, comment_id: i64) -> Result<MaintenanceComment> {
+        Self::send(self.request(Method::POST, &format!("/api/comments/{}/pin", comment_id))).await
+    }
+
+    // DELETE /api/comments/{id}/pin
+    pub async fn unpin_comment(&self, comment_id: i64) -> Result<MaintenanceComment> {
+        Self::send(self.request(Method::DELETE, &format!("/api/comments/{}/pin", comment_id))).await
+    }
+
+    // POST /api/comments/{id}/resolve
+    pub async fn resolve_comment(&self, comment_id: i64) -> Result<MaintenanceComment> {
+        Self::send(self.request(Method::POST, &format!("/api/comments/{}/resolve", comment_id))).await
+    }
+
+    // DELETE /api/comments/{id}/resolve
+    pub async fn unresolve_comment(&self, comment_id: i64) -> Result<MaintenanceComment> {
+        Self::send(self.request(Method::DELETE, &format!("/api/comments/{}/resolve", comment_id))).await
+    }
+
+    // GET /api/comments/export; format is "csv" (default) or "xlsx"
+    pub async fn export_comments(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+        priority: Option<&str>,
+        format: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let params = query([
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+            ("priority", priority.map(str::to_string)),
+            ("format", format.map(str::to_string)),
+        ]);
+        Self::send_bytes(self.request(Method::GET, "/api/comments/export").query(&params)).await
+    }
+
+    // GET /api/comment-categories
+    pub async fn list_comment_categories(&self) -> Result<CommentCategoryListResponse> {
+        Self::send(self.request(Method::GET, "/api/comment-categories")).await
+    }
+
+    // POST /api/comment-categories
+    pub async fn create_comment_category(&self, category: &CreateCommentCategoryRequest) -> Result<CommentCategory> {
+        Self::send(self.request(Method::POST, "/api/comment-categories").json(category)).await
+    }
+
+    // GET /api/machines/{id}/api-keys
+    pub async fn list_machine_api_keys(&self, machine_id: i64) -> Result<MachineApiKeyListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/api-keys", machine_id))).await
+    }
+
+    // POST /api/machines/{id}/api-keys; the response is the only time the key is shown
+    pub async fn create_machine_api_key(&self, machine_id: i64, key: &CreateMachineApiKeyRequest) -> Result<MachineApiKey> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/api-keys", machine_id)).json(key)).await
+    }
+
+    // POST /api/machines/{id}/rotate-key
+    pub async fn rotate_machine_key(&self, machine_id: i64, rotation: &RotateMachineKeyRequest) -> Result<MachineKeyRotationResponse> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/rotate-key", machine_id)).json(rotation)).await
+    }
+
+    // PUT /api/machine-api-keys/{id}
+    pub async fn update_machine_api_key(&self, key_id: i64, update: &UpdateMachineApiKeyRequest) -> Result<MachineApiKey> {
+        Self::send(self.request(Method::PUT, &format!("/api/machine-api-keys/{}", key_id)).json(update)).await
+    }
+
+    // DELETE /api/machine-api-keys/{id}
+    pub async fn revoke_machine_api_key(&self, key_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/machine-api-keys/{}", key_id))).await
+    }
+
+    // Documents
+
+    // GET /api/machines/{id}/documents
+    pub async fn list_documents(&self, machine_id: i64) -> Result<DocumentListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/documents", machine_id))).await
+    }
+
+    // POST /api/machines/{id}/documents
+    pub async fn create_document(&self, machine_id: i64, document: &CreateDocumentRequest) -> Result<MachineDocument> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/documents", machine_id)).json(document)).await
+    }
+
+    // PUT /api/documents/{id}
+    pub async fn update_document(&self, document_id: i64, update: &UpdateDocumentRequest) -> Result<MachineDocument> {
+        Self::send(self.request(Method::PUT, &format!("/api/documents/{}", document_id)).json(update)).await
+    }
+
+    // DELETE /api/documents/{id}
+    pub async fn delete_document(&self, document_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/documents/{}", document_id))).await
+    }
+
+    // PUT /api/documents/{id}/file
+    pub async fn upload_document_file(&self, document_id: i64, file_name: &str, contents: Vec<u8>) -> Result<MachineDocument> {
+        let request = self
+            .request(Method::PUT, &format!("/api/documents/{}/file", document_id))
+            .query(&[("file_name", file_name)])
+            .body(contents);
+        Self::send(request).await
+    }
+
+    // GET /api/documents/{id}/file
+    pub async fn download_document_file(&self, document_id: i64) -> Result<Vec<u8>> {
+        Self::send_bytes(self.request(Method::GET, &format!("/api/documents/{}/file", document_id))).await
+    }
+
+    // Work orders
+
+    // GET /api/machines/{id}/work-orders
+    pub async fn list_work_orders(&self, machine_id: i64, status: Option<&str>) -> Result<WorkOrderListResponse> {
+        let params = query([("status", status.map(str::to_string))]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/work-orders", machine_id)).query(&params)).await
+    }
+
+    // POST /api/machines/{id}/work-orders
+    pub async fn create_work_order(&self, machine_id: i64, work_order: &CreateWorkOrderRequest) -> Result<WorkOrderDetailResponse> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/work-orders", machine_id)).json(work_order)).await
+    }
+
+    // GET /api/work-orders/{id}
+    pub async fn get_work_order(&self, work_order_id: i64) -> Result<WorkOrderDetailResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/work-orders/{}", work_order_id))).await
+    }
+
+    // PUT /api/work-orders/{id}
+    pub async fn update_work_order(&self, work_order_id: i64, update: &UpdateWorkOrderRequest) -> Result<WorkOrderDetailResponse> {
+        Self::send(self.request(Method::PUT, &format!("/api/work-orders/{}", work_order_id)).json(update)).await
+    }
+
+    // POST /api/work-orders/{id}/checklist
+    pub async fn attach_work_order_checklist(
+        &self,
+        work_order_id: i64,
+        checklist: &AttachChecklistRequest,
+    ) -> Result<WorkOrderDetailResponse> {
+        let path = format!("/api/work-orders/{}/checklist", work_order_id);
+        Self::send(self.request(Method::POST, &path).json(checklist)).await
+    }
+
+    // PUT /api/work-orders/{id}/steps/{step_id}
+    pub async fn update_work_order_step(
+        &self,
+        work_order_id: i64,
+        step_id: i64,
+        update: &UpdateWorkOrderStepRequest,
+    ) -> Result<WorkOrderDetailResponse> {
+        let path = format!("/api/work-orders/{}/steps/{}", work_order_id, step_id);
+        Self::send(self.request(Method::PUT, &path).json(update)).await
+    }
+
+    // POST /api/work-orders/{id}/sign-off
+    pub async fn sign_off_work_order(&self, work_order_id: i64) -> Result<WorkOrderDetailResponse> {
+        Self::send(self.request(Method::POST, &format!("/api/work-orders/{}/sign-off", work_order_id))).await
+    }
+
+    // GET /api/work-orders/{id}/permits
+    pub async fn list_work_permits(&self, work_order_id: i64) -> Result<WorkPermitListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/work-orders/{}/permits", work_order_id))).await
+    }
+
+    // POST /api/work-orders/{id}/permits
+    pub async fn request_work_permit(&self, work_order_id: i64, permit: &CreateWorkPermitRequest) -> Result<WorkPermit> {
+        Self::send(self.request(Method::POST, &format!("/api/work-orders/{}/permits", work_order_id)).json(permit)).await
+    }
+
+    // POST /api/permits/{id}/approve
+    pub async fn approve_work_permit(&self, permit_id: i64, approval: &ApproveWorkPermitRequest) -> Result<WorkPermit> {
+        Self::send(self.request(Method::POST, &format!("/api/permits/{}/approve", permit_id)).json(approval)).await
+    }
+
+    // POST /api/permits/{id}/close
+    pub async fn close_work_permit(&self, permit_id: i64) -> Result<WorkPermit> {
+        Self::send(self.request(Method::POST, &format!("/api/permits/{}/close", permit_id))).await
+    }
+
+    // GET /api/work-orders/{id}/labor
+    pub async fn list_labor(&self, work_order_id: i64) -> Result<LaborListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/work-orders/{}/labor", work_order_id))).await
+    }
+
+    // POST /api/work-orders/{id}/labor/start
+    pub async fn start_labor(&self, work_order_id: i64) -> Result<LaborEntry> {
+        Self::send(self.request(Method::POST, &format!("/api/work-orders/{}/labor/start", work_order_id))).await
+    }
+
+    // POST /api/work-orders/{id}/labor/stop
+    pub async fn stop_labor(&self, work_order_id: i64) -> Result<LaborEntry> {
+        Self::send(self.request(Method::POST, &format!("/api/work-orders/{}/labor/stop", work_order_id))).await
+    }
+
+    // GET /api/checklist-templates
+    pub async fn list_checklist_templates(&self) -> Result<ChecklistTemplateListResponse> {
+        Self::send(self.request(Method::GET, "/api/checklist-templates")).await
+    }
+
+    // POST /api/checklist-templates
+    pub async fn create_checklist_template(&self, template: &CreateChecklistTemplateRequest) -> Result<ChecklistTemplateResponse> {
+        Self::send(self.request(Method::POST, "/api/checklist-templates").json(template)).await
+    }
+
+    // Costs and contracts
+
+    // GET /api/machines/{id}/costs
+    pub async fn get_machine_costs(
+        &self,
+        machine_id: i64,
+        from: Option<i64>,
+        to: Option<i64>,
+        period: Option<&str>,
+    ) -> Result<MachineCostResponse> {
+        let params = query([
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+            ("period", period.map(str::to_string)),
+        ]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/costs", machine_id)).query(&params)).await
+    }
+
+    // POST /api/machines/{id}/costs
+    pub async fn create_cost(&self, machine_id: i64, cost: &CreateCostRequest) -> Result<CostEntry> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/costs", machine_id)).json(cost)).await
+    }
+
+    // GET /api/machines/{id}/contracts
+    pub async fn list_contracts(&self, machine_id: i64) -> Result<ContractListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/contracts", machine_id))).await
+    }
+
+    // POST /api/machines/{id}/contracts
+    pub async fn create_contract(&self, machine_id: i64, contract: &CreateContractRequest) -> Result<MachineContract> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/contracts", machine_id)).json(contract)).await
+    }
+
+    // PUT /api/contracts/{id}
+    pub async fn update_contract(&self, contract_id: i64, update: &UpdateContractRequest) -> Result<MachineContract> {
+        Self::send(self.request(Method::PUT, &format!("/api/contracts/{}", contract_id)).json(update)).await
+    }
+
+    // DELETE /api/contracts/{id}
+    pub async fn delete_contract(&self, contract_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/contracts/{}", contract_id))).await
+    }
+
+    // Notifications
+
+    // GET /api/notifications
+    pub async fn list_notifications(&self, unacknowledged: Option<bool>) -> Result<NotificationListResponse> {
+        let params = query([("unacknowledged", unacknowledged.map(|v| v.to_string()))]);
+        Self::send(self.request(Method::GET, "/api/notifications").query(&params)).await
+    }
+
+    // POST /api/machines/{id}/snooze
+    pub async fn snooze_machine(&self, machine_id: i64, until: i64) -> Result<MachineSnooze> {
+        let path = format!("/api/machines/{}/snooze", machine_id);
+        Self::send(self.request(Method::POST, &path).query(&[("until", until)])).await
+    }
+
+    // DELETE /api/machines/{id}/snooze
+    pub async fn unsnooze_machine(&self, machine_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/machines/{}/snooze", machine_id))).await
+    }
+
+    // GET /api/users/me/snoozes
+    pub async fn list_own_snoozes(&self) -> Result<MachineSnoozeListResponse> {
+        Self::send(self.request(Method::GET, "/api/users/me/snoozes")).await
+    }
+
+    // POST /api/notifications/{id}/acknowledge
+    pub async fn acknowledge_notification(&self, notification_id: i64) -> Result<Notification> {
+        Self::send(self.request(Method::POST, &format!("/api/notifications/{}/acknowledge", notification_id))).await
+    }
+
+    // Alarms
+
+    // GET /api/machines/{id}/alarm-rules
+    pub async fn list_alarm_rules(&self, machine_id: i64) -> Result<AlarmRuleListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/alarm-rules", machine_id))).await
+    }
+
+    // POST /api/machines/{id}/alarm-rules
+    pub async fn create_alarm_rule(&self, machine_id: i64, rule: &CreateAlarmRuleRequest) -> Result<AlarmRule> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/alarm-rules", machine_id)).json(rule)).await
+    }
+
+    // PUT /api/alarm-rules/{id}
+    pub async fn update_alarm_rule(&self, rule_id: i64, update: &UpdateAlarmRuleRequest) -> Result<AlarmRule> {
+        Self::send(self.request(Method::PUT, &format!("/api/alarm-rules/{}", rule_id)).json(update)).await
+    }
+
+    // POST /api/alarm-rules/backtest
+    pub async fn backtest_alarm_rule(&self, backtest: &BacktestAlarmRuleRequest) -> Result<Backtest> {
+        Self::send(self.request(Method::POST, "/api/alarm-rules/backtest").json(backtest)).await
+    }
+
+    // GET /api/alarms
+    pub async fn list_alarms(&self, machine_id: Option<i64>, active: Option<bool>, batch: Option<i64>) -> Result<AlarmListResponse> {
+        let params = query([
+            ("machine_id", machine_id.map(|v| v.to_string())),
+            ("active", active.map(|v| v.to_string())),
+            ("batch", batch.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, "/api/alarms").query(&params)).await
+    }
+
+    // POST /api/alarms/{id}/acknowledge
+    pub async fn acknowledge_alarm(&self, alarm_id: i64) -> Result<Alarm> {
+        Self::send(self.request(Method::POST, &format!("/api/alarms/{}/acknowledge", alarm_id))).await
+    }
+
+    // PUT /api/alarms/{id}/annotation
+    pub async fn annotate_alarm(&self, alarm_id: i64, annotation: &AnnotateAlarmRequest) -> Result<Alarm> {
+        Self::send(self.request(Method::PUT, &format!("/api/alarms/{}/annotation", alarm_id)).json(annotation)).await
+    }
+
+    // GET /api/alarms/export; returns the file contents
+    pub async fn export_alarms(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+        machine_id: Option<i64>,
+        format: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let params = query([
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+            ("machine_id", machine_id.map(|v| v.to_string())),
+            ("format", format.map(str::to_string)),
+        ]);
+        Self::send_bytes(self.request(Method::GET, "/api/alarms/export").query(&params)).await
+    }
+
+    // GET /api/compliance-limits
+    pub async fn list_compliance_limits(&self, machine_id: Option<i64>, metric: Option<&str>) -> Result<ComplianceLimitListResponse> {
+        let params = query([
+            ("machine_id", machine_id.map(|v| v.to_string())),
+            ("metric", metric.map(str::to_string)),
+        ]);
+        Self::send(self.request(Method::GET, "/api/compliance-limits").query(&params)).await
+    }
+
+    // POST /api/compliance-limits
+    pub async fn create_compliance_limit(&self, limit: &CreateComplianceLimitRequest) -> Result<ComplianceLimit> {
+        Self::send(self.request(Method::POST, "/api/compliance-limits").json(limit)).await
+    }
+
+    // PUT /api/compliance-limits/{id}
+    pub async fn update_compliance_limit(&self, limit_id: i64, update: &UpdateComplianceLimitRequest) -> Result<ComplianceLimit> {
+        Self::send(self.request(Method::PUT, &format!("/api/compliance-limits/{}", limit_id)).json(update)).await
+    }
+
+    // DELETE /api/compliance-limits/{id}; refused once exceedances of the limit are logged
+    pub async fn delete_compliance_limit(&self, limit_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/compliance-limits/{}", limit_id))).await
+    }
+
+    // GET /api/compliance-exceedances
+    pub async fn list_compliance_exceedances(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+        machine_id: Option<i64>,
+        active: Option<bool>,
+    ) -> Result<ComplianceExceedanceListResponse> {
+        let params = query([
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+            ("machine_id", machine_id.map(|v| v.to_string())),
+            ("active", active.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, "/api/compliance-exceedances").query(&params)).await
+    }
+
+    // GET /api/failure-thresholds
+    pub async fn list_failure_thresholds(
+        &self,
+        machine_id: Option<i64>,
+        metric: Option<&str>,
+    ) -> Result<FailureThresholdListResponse> {
+        let params = query([
+            ("machine_id", machine_id.map(|v| v.to_string())),
+            ("metric", metric.map(str::to_string)),
+        ]);
+        Self::send(self.request(Method::GET, "/api/failure-thresholds").query(&params)).await
+    }
+
+    // POST /api/failure-thresholds
+    pub async fn create_failure_threshold(&self, threshold: &CreateFailureThresholdRequest) -> Result<FailureThreshold> {
+        Self::send(self.request(Method::POST, "/api/failure-thresholds").json(threshold)).await
+    }
+
+    // DELETE /api/failure-thresholds/{id}
+    pub async fn delete_failure_threshold(&self, threshold_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/failure-thresholds/{}", threshold_id))).await
+    }
+
+    // GET /api/predictions
+    pub async fn list_predictions(&self) -> Result<FailurePredictionListResponse> {
+        Self::send(self.request(Method::GET, "/api/predictions")).await
+    }
+
+    // GET /api/advisories; only open ones unless `all`
+    pub async fn list_advisories(&self, machine_id: Option<i64>, all: bool) -> Result<MaintenanceAdvisoryListResponse> {
+        let params = query([
+            ("machine_id", machine_id.map(|v| v.to_string())),
+            ("all", all.then(|| "true".to_string())),
+        ]);
+        Self::send(self.request(Method::GET, "/api/advisories").query(&params)).await
+    }
+
+    // POST /api/advisories/{id}/acknowledge
+    pub async fn acknowledge_advisory(&self, advisory_id: i64) -> Result<MaintenanceAdvisory> {
+        Self::send(self.request(Method::POST, &format!("/api/advisories/{}/acknowledge", advisory_id))).await
+    }
+
+    // POST /api/admin/predictions/run
+    pub async fn run_predictions(&self) -> Result<JobCreatedResponse> {
+        Self::send(self.request(Method::POST, "/api/admin/predictions/run")).await
+    }
+
+    // GET /api/reports/compliance-exceedances; month is "YYYY-MM", returns the file contents
+    pub async fn compliance_exceedance_report(
+        &self,
+        month: Option<&str>,
+        machine_id: Option<i64>,
+        format: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let params = query([
+            ("month", month.map(str::to_string)),
+            ("machine_id", machine_id.map(|v| v.to_string())),
+            ("format", format.map(str::to_string)),
+        ]);
+        Self::send_bytes(self.request(Method::GET, "/api/reports/compliance-exceedances").query(&params)).await
+    }
+
+    // GET /api/alarm-work-order-policy
+    pub async fn get_alarm_work_order_policy(&self) -> Result<AlarmWorkOrderPolicy> {
+        Self::send(self.request(Method::GET, "/api/alarm-work-order-policy")).await
+    }
+
+    // PUT /api/alarm-work-order-policy
+    pub async fn update_alarm_work_order_policy(&self, policy: &UpdateAlarmWorkOrderPolicyRequest) -> Result<AlarmWorkOrderPolicy> {
+        Self::send(self.request(Method::PUT, "/api/alarm-work-order-policy").json(policy)).await
+    }
+
+    // GET /api/alarm-root-causes
+    pub async fn list_alarm_root_causes(&self) -> Result<AlarmRootCauseListResponse> {
+        Self::send(self.request(Method::GET, "/api/alarm-root-causes")).await
+    }
+
+    // POST /api/alarm-root-causes
+    pub async fn create_alarm_root_cause(&self, root_cause: &CreateAlarmRootCauseRequest) -> Result<AlarmRootCause> {
+        Self::send(self.request(Method::POST, "/api/alarm-root-causes").json(root_cause)).await
+    }
+
+    // GET /api/alarm-presentation
+    pub async fn list_alarm_presentation(&self) -> Result<AlarmPresentationListResponse> {
+        Self::send(self.request(Method::GET, "/api/alarm-presentation")).await
+    }
+
+    // PUT /api/alarm-presentation/{severity}
+    pub async fn update_alarm_presentation(
+        &self,
+        severity: &str,
+        update: &UpdateAlarmPresentationRequest,
+    ) -> Result<AlarmPresentation> {
+        Self::send(self.request(Method::PUT, &format!("/api/alarm-presentation/{}", severity)).json(update)).await
+    }
+
+    // Commands
+
+    // GET /api/command-types
+    pub async fn list_command_types(&self, machine_type: Option<&str>) -> Result<CommandTypeListResponse> {
+        let params = query([("machine_type", machine_type.map(str::to_string))]);
+        Self::send(self.request(Method::GET, "/api/command-types").query(&params)).await
+    }
+
+    // POST /api/command-types
+    pub async fn create_command_type(&self, command_type: &CreateCommandTypeRequest) -> Result<CommandType> {
+        Self::send(self.request(Method::POST, "/api/command-types").json(command_type)).await
+    }
+
+    // PUT /api/command-types/{id}
+    pub async fn update_command_type(&self, command_type_id: i64, update: &UpdateCommandTypeRequest) -> Result<CommandType> {
+        Self::send(self.request(Method::PUT, &format!("/api/command-types/{}", command_type_id)).json(update)).await
+    }
+
+    // DELETE /api/command-types/{id}
+    pub async fn delete_command_type(&self, command_type_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/command-types/{}", command_type_id))).await
+    }
+
+    // POST /api/machines/{id}/commands
+    pub async fn send_command(&self, machine_id: i64, command: &SendCommandRequest) -> Result<MachineCommand> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/{}/commands", machine_id)).json(command)).await
+    }
+
+    // PUT /api/machines/{id}/lockout; cancels pending commands the lockout blocks
+    pub async fn lock_out_machine(&self, machine_id: i64, lockout: &LockoutRequest) -> Result<Machine> {
+        Self::send(self.request(Method::PUT, &format!("/api/machines/{}/lockout", machine_id)).json(lockout)).await
+    }
+
+    // DELETE /api/machines/{id}/lockout
+    pub async fn release_lockout(&self, machine_id: i64) -> Result<Machine> {
+        Self::send(self.request(Method::DELETE, &format!("/api/machines/{}/lockout", machine_id))).await
+    }
+
+    // DELETE /api/commands/{id}; only commands not yet delivered can be cancelled
+    pub async fn cancel_command(&self, command_id: i64) -> Result<MachineCommand> {
+        Self::send(self.request(Method::DELETE, &format!("/api/commands/{}", command_id))).await
+    }
+
+    // GET /api/machines/{id}/commands
+    pub async fn list_machine_commands(
+        &self,
+        machine_id: i64,
+        status: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<MachineCommandListResponse> {
+        let params = query([
+            ("status", status.map(str::to_string)),
+            ("limit", limit.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, &format!("/api/machines/{}/commands", machine_id)).query(&params)).await
+    }
+
+    // GET /api/machines/commands (machine API key); returned commands count as delivered
+    pub async fn poll_commands(&self) -> Result<MachineCommandListResponse> {
+        Self::send(self.request(Method::GET, "/api/machines/commands")).await
+    }
+
+    // POST /api/machines/commands/{id}/result (machine API key)
+    pub async fn report_command_result(&self, command_id: i64, outcome: &CommandResultRequest) -> Result<MachineCommand> {
+        Self::send(self.request(Method::POST, &format!("/api/machines/commands/{}/result", command_id)).json(outcome)).await
+    }
+
+    // Units and precision
+
+    // GET /api/units
+    pub async fn list_units(&self) -> Result<UnitListResponse> {
+        Self::send(self.request(Method::GET, "/api/units")).await
+    }
+
+    // POST /api/units
+    pub async fn create_unit(&self, unit: &Unit) -> Result<Unit> {
+        Self::send(self.request(Method::POST, "/api/units").json(unit)).await
+    }
+
+    // GET /api/metric-precision
+    pub async fn list_metric_precision(&self) -> Result<MetricPrecisionListResponse> {
+        Self::send(self.request(Method::GET, "/api/metric-precision")).await
+    }
+
+    // PUT /api/metric-precision/{metric}
+    pub async fn update_metric_precision(&self, metric: &str, update: &UpdateMetricPrecisionRequest) -> Result<MetricPrecision> {
+        Self::send(self.request(Method::PUT, &format!("/api/metric-precision/{}", metric)).json(update)).await
+    }
+
+    // Reports
+
+    // GET /api/reports/comments-by-category
+    pub async fn comments_by_category_report(&self, from: Option<i64>, to: Option<i64>) -> Result<CategoryReportResponse> {
+        let params = query([("from", from.map(|v| v.to_string())), ("to", to.map(|v| v.to_string()))]);
+        Self::send(self.request(Method::GET, "/api/reports/comments-by-category").query(&params)).await
+    }
+
+    // GET /api/reports/alarm-root-causes
+    pub async fn alarm_root_cause_report(&self, from: Option<i64>, to: Option<i64>) -> Result<RootCauseReportResponse> {
+        let params = query([("from", from.map(|v| v.to_string())), ("to", to.map(|v| v.to_string()))]);
+        Self::send(self.request(Method::GET, "/api/reports/alarm-root-causes").query(&params)).await
+    }
+
+    // GET /api/reports/labor-hours
+    pub async fn labor_hours_report(
+        &self,
+        range: Option<&str>,
+        from: Option<i64>,
+        to: Option<i64>,
+        machine_id: Option<i64>,
+    ) -> Result<LaborReportResponse> {
+        let params = query([
+            ("range", range.map(str::to_string)),
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+            ("machine_id", machine_id.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, "/api/reports/labor-hours").query(&params)).await
+    }
+
+    // GET /api/reports/changeovers
+    pub async fn changeover_report(
+        &self,
+        range: Option<&str>,
+        from: Option<i64>,
+        to: Option<i64>,
+        machine_id: Option<i64>,
+    ) -> Result<ChangeoverReportResponse> {
+        let params = query([
+            ("range", range.map(str::to_string)),
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+            ("machine_id", machine_id.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, "/api/reports/changeovers").query(&params)).await
+    }
+
+    // GET /api/reports/operators
+    pub async fn operator_report(
+        &self,
+        range: Option<&str>,
+        from: Option<i64>,
+        to: Option<i64>,
+        machine_id: Option<i64>,
+    ) -> Result<OperatorReportResponse> {
+        let params = query([
+            ("range", range.map(str::to_string)),
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+            ("machine_id", machine_id.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, "/api/reports/operators").query(&params)).await
+    }
+
+    // GET /api/reports/expired-contracts
+    pub async fn expired_contracts_report(&self, filter: &ExpiredContractFilter) -> Result<ExpiredContractReportResponse> {
+        Self::send(self.request(Method::GET, "/api/reports/expired-contracts").query(filter)).await
+    }
+
+    // GET /api/reports/history-gaps
+    pub async fn history_gaps_report(&self, min_gap: Option<&str>, from: Option<i64>, to: Option<i64>) -> Result<GapReportResponse> {
+        let params = query([
+            ("min_gap", min_gap.map(str::to_string)),
+            ("from", from.map(|v| v.to_string())),
+            ("to", to.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, "/api/reports/history-gaps").query(&params)).await
+    }
+
+    // Background jobs
+
+    // GET /api/jobs/{id}
+    pub async fn get_job(&self, job_id: &str) -> Result<JobResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/jobs/{}", job_id))).await
+    }
+
+    // GET /api/jobs/{id}/artifact
+    pub async fn download_job_artifact(&self, job_id: &str) -> Result<Vec<u8>> {
+        Self::send_bytes(self.request(Method::GET, &format!("/api/jobs/{}/artifact", job_id))).await
+    }
+
+    // GET /api/jobs/{id}/artifact/link
+    pub async fn create_download_link(&self, job_id: &str, expires_in: Option<i64>) -> Result<DownloadLinkResponse> {
+        let params = query([("expires_in", expires_in.map(|v| v.to_string()))]);
+        Self::send(self.request(Method::GET, &format!("/api/jobs/{}/artifact/link", job_id)).query(&params)).await
+    }
+
+    // Users
+
+    // GET /api/users
+    pub async fn list_users(&self) -> Result<UserListResponse> {
+        Self::send(self.request(Method::GET, "/api/users")).await
+    }
+
+    // POST /api/users
+    pub async fn create_user(&self, user: &CreateUserRequest) -> Result<User> {
+        Self::send(self.request(Method::POST, "/api/users").json(user)).await
+    }
+
+    // PUT /api/users/{id}
+    pub async fn update_user(&self, user_id: i64, update: &UpdateUserRequest) -> Result<User> {
+        Self::send(self.request(Method::PUT, &format!("/api/users/{}", user_id)).json(update)).await
+    }
+
+    // POST /api/users/me/password; every other session of the user ends, and the new
+    // access token is used for later requests
+    pub async fn change_password(&mut self, current_password: &str, new_password: &str) -> Result<LoginResponse> {
+        let request = ChangePasswordRequest {
+            current_password: current_password.to_string(),
+            new_password: new_password.to_string(),
+        };
+        let response: LoginResponse = Self::send(self.request(Method::POST, "/api/users/me/password").json(&request)).await?;
+        self.token = Some(response.token.clone());
+        Ok(response)
+    }
+
+    // POST /api/password-reset/request; succeeds whether or not the address is known
+    pub async fn request_password_reset(&self, email: &str) -> Result<()> {
+        let request = PasswordResetRequest { email: email.to_string() };
+        Self::send_empty(self.request(Method::POST, "/api/password-reset/request").json(&request)).await
+    }
+
+    // POST /api/password-reset/confirm with the token from the reset email
+    pub async fn confirm_password_reset(&self, token: &str, new_password: &str) -> Result<()> {
+        let request = PasswordResetConfirmRequest {
+            token: token.to_string(),
+            new_password: new_password.to_string(),
+        };
+        Self::send_empty(self.request(Method::POST, "/api/password-reset/confirm").json(&request)).await
+    }
+
+    // POST /api/users/{id}/unlock
+    pub async fn unlock_user(&self, user_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::POST, &format!("/api/users/{}/unlock", user_id))).await
+    }
+
+    // POST /api/users/{id}/deactivate
+    pub async fn deactivate_user(&self, user_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::POST, &format!("/api/users/{}/deactivate", user_id))).await
+    }
+
+    // POST /api/users/{id}/reactivate
+    pub async fn reactivate_user(&self, user_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::POST, &format!("/api/users/{}/reactivate", user_id))).await
+    }
+
+    // POST /api/users/{id}/revoke-tokens
+    pub async fn revoke_user_tokens(&self, user_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::POST, &format!("/api/users/{}/revoke-tokens", user_id))).await
+    }
+
+    // GET /api/users/me/sessions
+    pub async fn list_own_sessions(&self) -> Result<SessionListResponse> {
+        Self::send(self.request(Method::GET, "/api/users/me/sessions")).await
+    }
+
+    // GET /api/users/me/views/machines
+    pub async fn get_machine_list_view(&self) -> Result<MachineListView> {
+        Self::send(self.request(Method::GET, "/api/users/me/views/machines")).await
+    }
+
+    // PUT /api/users/me/views/machines
+    pub async fn set_machine_list_view(&self, view: &MachineListView) -> Result<MachineListView> {
+        Self::send(self.request(Method::PUT, "/api/users/me/views/machines").json(view)).await
+    }
+
+    // GET /api/users/{id}/sessions
+    pub async fn list_user_sessions(&self, user_id: i64) -> Result<SessionListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/users/{}/sessions", user_id))).await
+    }
+
+    // GET /api/users/{id}/logins
+    pub async fn list_user_logins(&self, user_id: i64, limit: Option<i64>) -> Result<LoginHistoryResponse> {
+        let params = query([("limit", limit.map(|v| v.to_string()))]);
+        Self::send(self.request(Method::GET, &format!("/api/users/{}/logins", user_id)).query(&params)).await
+    }
+
+    // DELETE /api/sessions/{id}
+    pub async fn revoke_session(&self, session_id: &str) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/sessions/{}", session_id))).await
+    }
+
+    // POST /api/admin/impersonate/{user_id}
+    pub async fn impersonate_user(&self, user_id: i64, request: &ImpersonateRequest) -> Result<ImpersonationResponse> {
+        Self::send(self.request(Method::POST, &format!("/api/admin/impersonate/{}", user_id)).json(request)).await
+    }
+
+    // GET /api/admin/impersonations
+    pub async fn list_impersonations(&self) -> Result<ImpersonationListResponse> {
+        Self::send(self.request(Method::GET, "/api/admin/impersonations")).await
+    }
+
+    // GET /api/service-accounts
+    pub async fn list_service_accounts(&self) -> Result<ServiceAccountListResponse> {
+        Self::send(self.request(Method::GET, "/api/service-accounts")).await
+    }
+
+    // POST /api/service-accounts; the response is the only time the key is shown
+    pub async fn create_service_account(&self, account: &CreateServiceAccountRequest) -> Result<ServiceAccount> {
+        Self::send(self.request(Method::POST, "/api/service-accounts").json(account)).await
+    }
+
+    // PUT /api/service-accounts/{id}
+    pub async fn update_service_account(&self, account_id: i64, update: &UpdateServiceAccountRequest) -> Result<ServiceAccount> {
+        Self::send(self.request(Method::PUT, &format!("/api/service-accounts/{}", account_id)).json(update)).await
+    }
+
+    // POST /api/service-accounts/{id}/rotate-key
+    pub async fn rotate_service_account_key(&self, account_id: i64, rotation: &RotateMachineKeyRequest) -> Result<ServiceAccount> {
+        Self::send(self.request(Method::POST, &format!("/api/service-accounts/{}/rotate-key", account_id)).json(rotation)).await
+    }
+
+    // DELETE /api/service-accounts/{id}
+    pub async fn disable_service_account(&self, account_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/service-accounts/{}", account_id))).await
+    }
+
+    // GET /api/users/{id}/certifications
+    pub async fn list_user_certifications(&self, user_id: i64) -> Result<CertificationListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/users/{}/certifications", user_id))).await
+    }
+
+    // PUT /api/users/{id}/certifications/{skill}
+    pub async fn set_certification(&self, user_id: i64, skill: &str, certification: &SetCertificationRequest) -> Result<Certification> {
+        let path = format!("/api/users/{}/certifications/{}", user_id, skill);
+        Self::send(self.request(Method::PUT, &path).json(certification)).await
+    }
+
+    // DELETE /api/users/{id}/certifications/{skill}
+    pub async fn delete_certification(&self, user_id: i64, skill: &str) -> Result<()> {
+        let path = format!("/api/users/{}/certifications/{}", user_id, skill);
+        Self::send_empty(self.request(Method::DELETE, &path)).await
+    }
+
+    // GET /api/certifications
+    pub async fn list_certifications(&self, skill: Option<&str>, expires_before: Option<i64>) -> Result<CertificationListResponse> {
+        let params = query([
+            ("skill", skill.map(str::to_string)),
+            ("expires_before", expires_before.map(|v| v.to_string())),
+        ]);
+        Self::send(self.request(Method::GET, "/api/certifications").query(&params)).await
+    }
+
+    // GET /api/users/{id}/machines
+    pub async fn list_machine_access(&self, user_id: i64) -> Result<MachineAccessListResponse> {
+        Self::send(self.request(Method::GET, &format!("/api/users/{}/machines", user_id))).await
+    }
+
+    // PUT /api/users/{id}/machines/{machine_id}
+    pub async fn grant_machine_access(&self, user_id: i64, machine_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::PUT, &format!("/api/users/{}/machines/{}", user_id, machine_id))).await
+    }
+
+    // DELETE /api/users/{id}/machines/{machine_id}
+    pub async fn revoke_machine_access(&self, user_id: i64, machine_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/users/{}/machines/{}", user_id, machine_id))).await
+    }
+
+    // Administration
+
+    // POST /api/admin/sandbox
+    pub async fn create_sandbox(&self, sandbox: &SandboxRequest) -> Result<JobCreatedResponse> {
+        Self::send(self.request(Method::POST, "/api/admin/sandbox").json(sandbox)).await
+    }
+
+    // POST /api/admin/backup
+    pub async fn create_backup(&self) -> Result<BackupFile> {
+        Self::send(self.request(Method::POST, "/api/admin/backup")).await
+    }
+
+    // POST /api/admin/backup?download=true; the backup file's contents
+    pub async fn download_backup(&self) -> Result<Vec<u8>> {
+        let params = query([("download", Some("true".to_string()))]);
+        Self::send_bytes(self.request(Method::POST, "/api/admin/backup").query(&params)).await
+    }
+
+    // GET /api/admin/backups
+    pub async fn list_backups(&self) -> Result<BackupListResponse> {
+        Self::send(self.request(Method::GET, "/api/admin/backups")).await
+    }
+
+    // GET /api/admin/read-only
+    pub async fn get_read_only(&self) -> Result<ReadOnlyResponse> {
+        Self::send(self.request(Method::GET, "/api/admin/read-only")).await
+    }
+
+    // PUT /api/admin/read-only
+    pub async fn update_read_only(&self, enabled: bool) -> Result<ReadOnlyResponse> {
+        Self::send(self.request(Method::PUT, "/api/admin/read-only").json(&UpdateReadOnlyRequest { enabled })).await
+    }
+
+    // GET /api/admin/features
+    pub async fn list_feature_flags(&self) -> Result<Vec<FeatureFlag>> {
+        Self::send(self.request(Method::GET, "/api/admin/features")).await
+    }
+
+    // PUT /api/admin/features/{name}
+    pub async fn update_feature_flag(&self, name: &str, enabled: bool) -> Result<FeatureFlag> {
+        let path = format!("/api/admin/features/{}", name);
+        Self::send(self.request(Method::PUT, &path).json(&UpdateFeatureFlagRequest { enabled })).await
+    }
+
+    // GET /api/admin/usage
+    pub async fn get_api_usage(&self, from: Option<i64>, to: Option<i64>) -> Result<ApiUsageResponse> {
+        let params = query([("from", from.map(|v| v.to_string())), ("to", to.map(|v| v.to_string()))]);
+        Self::send(self.request(Method::GET, "/api/admin/usage").query(&params)).await
+    }
+
+    // POST /api/admin/history/purge
+    pub async fn purge_history(&self, machine_id: Option<i64>) -> Result<JobCreatedResponse> {
+        let params = query([("machine_id", machine_id.map(|v| v.to_string()))]);
+        Self::send(self.request(Method::POST, "/api/admin/history/purge").query(&params)).await
+    }
+
+    // Distribution lists
+
+    // GET /api/kiosk/rotation
+    pub async fn kiosk_rotation(&self, group: Option<&str>, locale: Option<&str>) -> Result<KioskRotationResponse> {
+        let params = query([("group", group.map(str::to_string)), ("locale", locale.map(str::to_string))]);
+        Self::send(self.request(Method::GET, "/api/kiosk/rotation").query(&params)).await
+    }
+
+    // GET /api/kiosk/groups
+    pub async fn list_kiosk_groups(&self) -> Result<KioskGroupListResponse> {
+        Self::send(self.request(Method::GET, "/api/kiosk/groups")).await
+    }
+
+    // PUT /api/kiosk/groups/{name}
+    pub async fn set_kiosk_group(&self, name: &str, group: &SetKioskGroupRequest) -> Result<KioskGroup> {
+        Self::send(self.request(Method::PUT, &format!("/api/kiosk/groups/{}", name)).json(group)).await
+    }
+
+    // DELETE /api/kiosk/groups/{name}
+    pub async fn delete_kiosk_group(&self, name: &str) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/kiosk/groups/{}", name))).await
+    }
+
+    // GET /api/display-tokens
+    pub async fn list_display_tokens(&self) -> Result<DisplayTokenListResponse> {
+        Self::send(self.request(Method::GET, "/api/display-tokens")).await
+    }
+
+    // POST /api/display-tokens; the response is the only time the token is shown
+    pub async fn create_display_token(&self, display_token: &CreateDisplayTokenRequest) -> Result<DisplayToken> {
+        Self::send(self.request(Method::POST, "/api/display-tokens").json(display_token)).await
+    }
+
+    // DELETE /api/display-tokens/{id}
+    pub async fn revoke_display_token(&self, token_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/display-tokens/{}", token_id))).await
+    }
+
+    // GET /api/products
+    pub async fn list_products(&self) -> Result<ProductListResponse> {
+        Self::send(self.request(Method::GET, "/api/products")).await
+    }
+
+    // PUT /api/products/{sku}
+    pub async fn set_product(&self, sku: &str, product: &SetProductRequest) -> Result<Product> {
+        Self::send(self.request(Method::PUT, &format!("/api/products/{}", sku)).json(product)).await
+    }
+
+    // DELETE /api/products/{sku}
+    pub async fn delete_product(&self, sku: &str) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/products/{}", sku))).await
+    }
+
+    // GET /api/kpis; with a range, speed and metrics are averaged over it
+    pub async fn get_kpis(&self, range: Option<&str>) -> Result<KpiValuesResponse> {
+        let params = query([("range", range.map(str::to_string))]);
+        Self::send(self.request(Method::GET, "/api/kpis").query(&params)).await
+    }
+
+    // GET /api/analytics/benchmark; metric and period default to avg_speed and month
+    pub async fn benchmark_machines(
+        &self,
+        machine_type: &str,
+        metric: Option<&str>,
+        period: Option<&str>,
+    ) -> Result<BenchmarkResponse> {
+        let params = query([
+            ("machine_type", Some(machine_type.to_string())),
+            ("metric", metric.map(str::to_string)),
+            ("period", period.map(str::to_string)),
+        ]);
+        Self::send(self.request(Method::GET, "/api/analytics/benchmark").query(&params)).await
+    }
+
+    // GET /api/health-scores; the latest scored day unless `day` (YYYY-MM-DD) is given
+    pub async fn list_health_scores(&self, day: Option<&str>) -> Result<HealthScoreListResponse> {
+        let params = query([("day", day.map(str::to_string))]);
+        Self::send(self.request(Method::GET, "/api/health-scores").query(&params)).await
+    }
+
+    // GET /api/kpi-definitions
+    pub async fn list_kpi_definitions(&self) -> Result<KpiDefinitionListResponse> {
+        Self::send(self.request(Method::GET, "/api/kpi-definitions")).await
+    }
+
+    // POST /api/kpi-definitions
+    pub async fn create_kpi_definition(&self, kpi: &SetKpiDefinitionRequest) -> Result<KpiDefinition> {
+        Self::send(self.request(Method::POST, "/api/kpi-definitions").json(kpi)).await
+    }
+
+    // PUT /api/kpi-definitions/{id}
+    pub async fn update_kpi_definition(&self, kpi_id: i64, kpi: &SetKpiDefinitionRequest) -> Result<KpiDefinition> {
+        Self::send(self.request(Method::PUT, &format!("/api/kpi-definitions/{}", kpi_id)).json(kpi)).await
+    }
+
+    // DELETE /api/kpi-definitions/{id}
+    pub async fn delete_kpi_definition(&self, kpi_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/kpi-definitions/{}", kpi_id))).await
+    }
+
+    // GET /api/distribution-lists
+    pub async fn list_distribution_lists(&self) -> Result<DistributionListListResponse> {
+        Self::send(self.request(Method::GET, "/api/distribution-lists")).await
+    }
+
+    // GET /api/distribution-lists/{id}
+    pub async fn get_distribution_list(&self, list_id: i64) -> Result<DistributionList> {
+        Self::send(self.request(Method::GET, &format!("/api/distribution-lists/{}", list_id))).await
+    }
+
+    // POST /api/distribution-lists
+    pub async fn create_distribution_list(&self, list: &CreateDistributionListRequest) -> Result<DistributionList> {
+        Self::send(self.request(Method::POST, "/api/distribution-lists").json(list)).await
+    }
+
+    // PUT /api/distribution-lists/{id}
+    pub async fn update_distribution_list(&self, list_id: i64, update: &UpdateDistributionListRequest) -> Result<DistributionList> {
+        Self::send(self.request(Method::PUT, &format!("/api/distribution-lists/{}", list_id)).json(update)).await
+    }
+
+    // DELETE /api/distribution-lists/{id}
+    pub async fn delete_distribution_list(&self, list_id: i64) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, &format!("/api/distribution-lists/{}", list_id))).await
+    }
+
+    // POST /api/distribution-lists/{id}/test
+    pub async fn send_distribution_list_test(&self, list_id: i64) -> Result<DistributionList> {
+        Self::send(self.request(Method::POST, &format!("/api/distribution-lists/{}/test", list_id))).await
+    }
+
+    // Site settings
+
+    // GET /api/site-settings
+    pub async fn get_site_settings(&self) -> Result<SiteSettings> {
+        Self::send(self.request(Method::GET, "/api/site-settings")).await
+    }
+
+    // PUT /api/site-settings
+    pub async fn update_site_settings(&self, update: &UpdateSiteSettingsRequest) -> Result<SiteSettings> {
+        Self::send(self.request(Method::PUT, "/api/site-settings").json(update)).await
+    }
+
+    // PUT /api/site-settings/logo
+    pub async fn upload_site_logo(&self, content_type: &str, contents: Vec<u8>) -> Result<SiteSettings> {
+        let request = self
+            .request(Method::PUT, "/api/site-settings/logo")
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(contents);
+        Self::send(request).await
+    }
+
+    // GET /api/site-settings/logo
+    pub async fn download_site_logo(&self) -> Result<Vec<u8>> {
+        Self::send_bytes(self.request(Method::GET, "/api/site-settings/logo")).await
+    }
+
+    // DELETE /api/site-settings/logo
+    pub async fn delete_site_logo(&self) -> Result<()> {
+        Self::send_empty(self.request(Method::DELETE, "/api/site-settings/logo")).await
+    }
+
+    // Chaos testing, only available when the server runs with SCADA_DEV_CHAOS
+
+    // GET /api/dev/chaos
+    pub async fn get_chaos(&self) -> Result<ChaosStatus> {
+        Self::send(self.request(Method::GET, "/api/dev/chaos")).await
+    }
+
+    // DELETE /api/dev/chaos
+    pub async fn reset_chaos(&self) -> Result<ChaosStatus> {
+        Self::send(self.request(Method::DELETE, "/api/dev/chaos")).await
+    }
+
+    // PUT /api/dev/chaos/latency
+    pub async fn set_chaos_latency(&self, latency: &ChaosLatency) -> Result<ChaosStatus> {
+        Self::send(self.request(Method::PUT, "/api/dev/chaos/latency").json(latency)).await
+    }
+
+    // PUT /api/dev/chaos/db-failures
+    pub async fn set_chaos_db_failures(&self, count: u32) -> Result<ChaosStatus> {
+        Self::send(self.request(Method::PUT, "/api/dev/chaos/db-failures").json(&FailDbCallsRequest { count })).await
+    }
+
+    // POST /api/dev/chaos/tasks/{name}/kill
+    pub async fn kill_background_task(&self, name: &str) -> Result<ChaosStatus> {
+        Self::send(self.request(Method::POST, &format!("/api/dev/chaos/tasks/{}/kill", name))).await
+    }
+}
@@ -0,0 +1,2541 @@
+// Request and response types shared by the SCADA server and its clients. Database row
+// derives are behind the "sqlx" feature so clients do not pull in the database driver.
+// The crate only needs `alloc`, so it also builds for wasm32 and browser frontends can
+// reuse the types and validation rules below instead of duplicating them.
+#![no_std]
+
+extern crate alloc;
+// The sqlx row derives expand to paths under `std`; the server is std anyway
+#[cfg(feature = "sqlx")]
+extern crate std;
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+// Row derives converting a column with try_from format the error with to_string
+#[cfg(feature = "sqlx")]
+use alloc::string::ToString;
+use serde::{Deserialize, Serialize};
+
+pub const USER_ROLES: &[&str] = &["admin", "manager", "technician"];
+pub const RANGE_NAMES: &[&str] = &["last_24h", "today", "this_shift", "last_shift"];
+
+// Metric names double as variables in alarm expressions, so keep them identifier-like
+pub fn is_valid_metric_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+// Just enough to catch typos; the mail server has the final say
+pub fn is_valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.chars().any(|c| c.is_whitespace() || c == '<' || c == '>')
+                && !domain.contains('@')
+        },
+        None => false,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Machine {
+    pub id: i64,
+    pub name: String,
+    pub code: String,
+    pub location: Option<String>,
+    pub machine_type: Option<String>,
+    pub current_speed: f64,
+    pub status_message: String,
+    pub is_online: bool,
+    // Set while the machine is under lockout/tagout; commands that could start it are refused
+    pub locked_out_at: Option<i64>,
+    pub locked_out_by: Option<String>,
+    pub lockout_reason: Option<String>,
+    pub last_update: i64,
+    pub updated_at: i64,
+    // Expected seconds between reports; the server default applies when unset
+    pub report_interval: Option<i64>,
+    // Device clock minus server clock in seconds at the last update that carried a device timestamp
+    pub clock_drift: Option<i64>,
+    // Days of speed history kept; the server default applies when unset, 0 keeps it forever
+    pub history_retention_days: Option<i64>,
+    // Manager who approves exports of the machine's history when approval is required
+    pub data_owner: Option<String>,
+    // Set while the machine is archived, see DELETE /api/machines/{id}
+    pub archived_at: Option<i64>,
+    pub archived_by: Option<String>,
+    // Latest daily health score, 0-100 with lower being worse; unset until first computed
+    pub health_score: Option<f64>,
+    // Localized label, only filled in when the client asks for a locale
+    #[cfg_attr(feature = "sqlx", sqlx(default))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineResponse {
+    pub id: i64,
+    pub name: String,
+    pub code: String,
+    // Only present when a key was just created; the server keeps no readable copy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    // Secret for signing updates with the new key, when the server accepts signed updates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_secret: Option<String>,
+    pub location: Option<String>,
+    pub machine_type: Option<String>,
+    // Networks the machine's API keys are accepted from; any network when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_cidrs: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateMachineRequest {
+    pub name: String,
+    pub code: String,
+    pub location: Option<String>,
+    pub machine_type: Option<String>,
+    pub target_speed: Option<f64>,
+    // Seconds between the machine's speed updates
+    pub report_interval: Option<i64>,
+    // Skip history rows for updates that repeat the previous values (default true)
+    pub dedup_updates: Option<bool>,
+}
+
+// What an edge agent learns about itself from GET /api/machines/me
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MachineSelfResponse {
+    pub id: i64,
+    pub name: String,
+    pub code: String,
+    pub location: Option<String>,
+    pub machine_type: Option<String>,
+    pub target_speed: Option<f64>,
+    pub report_interval: Option<i64>,
+    // Commands waiting for the next poll of GET /api/machines/commands
+    #[serde(default)]
+    pub pending_commands: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SpeedUpdateRequest {
+    pub speed: f64,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub metrics: Vec<MetricReading>,
+    // Unix time on the device's own clock when it sent the update, for drift monitoring
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_timestamp: Option<i64>,
+    // Lot the machine is running; a different one than before starts a new batch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lot_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MetricReading {
+    pub name: String,
+    pub value: f64,
+    pub unit: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+    // Replaces an expired password as part of the login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_password: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginResponse {
+    pub token: String,
+    pub role: String,
+    pub username: String,
+    // When the access token stops being accepted; refresh before that
+    pub expires_at: i64,
+    // Single-use token for POST /api/token/refresh
+    pub refresh_token: String,
+    pub refresh_expires_at: i64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ImpersonateRequest {
+    // Why the admin needs to act as the user, kept in the audit trail
+    pub reason: Option<String>,
+}
+
+// A short-lived access token acting as another user; it has no refresh token
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImpersonationResponse {
+    pub token: String,
+    pub username: String,
+    pub role: String,
+    pub expires_at: i64,
+    pub impersonated_by: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Impersonation {
+    pub id: i64,
+    pub admin_username: String,
+    pub username: String,
+    pub reason: Option<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImpersonationListResponse {
+    pub impersonations: Vec<Impersonation>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct LogoutRequest {
+    // Also revoke the refresh token issued with the session
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PasswordResetConfirmRequest {
+    // Token from the reset email
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordRuleViolation {
+    // min_length, character_class, banned, contains_username or expired
+    pub rule: String,
+    pub message: String,
+}
+
+// Error body for a password the policy refuses
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordPolicyErrorResponse {
+    pub error: String,
+    pub violations: Vec<PasswordRuleViolation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordPolicyResponse {
+    pub min_length: usize,
+    pub required_classes: Vec<String>,
+    // None when passwords never expire
+    pub max_age_days: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub role: String,
+    // Where password reset links are sent
+    #[serde(default)]
+    pub email: Option<String>,
+    // Time of the last successful login
+    #[serde(default)]
+    pub last_login: Option<i64>,
+    // Disabled accounts cannot sign in until an admin reactivates them
+    #[serde(default = "active")]
+    pub is_active: bool,
+    #[serde(default)]
+    pub deactivated_at: Option<i64>,
+    // "inactivity" when disabled for going unused, "admin" when disabled by an admin
+    #[serde(default)]
+    pub deactivation_reason: Option<String>,
+}
+
+fn active() -> bool {
+    true
+}
+
+// A login attempt, successful or not. Failed attempts are kept for unknown usernames too.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct LoginHistoryEntry {
+    pub id: i64,
+    pub username: String,
+    pub succeeded: bool,
+    // "invalid_credentials", "locked", "password_expired" or "disabled" for failed attempts
+    pub failure_reason: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginHistoryResponse {
+    pub logins: Vec<LoginHistoryEntry>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub password: String,
+    pub role: String,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MaintenanceComment {
+    pub id: i64,
+    pub machine_id: i64,
+    pub comment: String,
+    pub priority: String,
+    pub username: String,
+    pub created_at: i64,
+    pub category_id: Option<i64>,
+    // Pinned comments are listed first while they are unresolved
+    pub pinned: bool,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<i64>,
+}
+
+// A comment in the plant-wide feed, with enough machine context to act on it
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct CommentFeedEntry {
+    pub id: i64,
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub machine_code: String,
+    pub comment: String,
+    pub priority: String,
+    pub username: String,
+    pub created_at: i64,
+    pub category_id: Option<i64>,
+    pub pinned: bool,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommentFeedResponse {
+    pub comments: Vec<CommentFeedEntry>,
+}
+
+// A comment joined with its machine and category, as exported for maintenance reviews
+#[derive(Debug)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct CommentExportRecord {
+    pub created_at: i64,
+    pub machine_name: String,
+    pub machine_code: String,
+    pub location: Option<String>,
+    pub username: String,
+    pub priority: String,
+    pub category: Option<String>,
+    pub comment: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AddCommentRequest {
+    pub comment: String,
+    pub priority: Option<String>,
+    pub category_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct SpeedHistory {
+    // The average over the bucket when served from rollups
+    pub speed: f64,
+    pub message: Option<String>,
+    pub timestamp: i64,
+    // Only set on rollups: the bucket's lowest and highest speed and number of readings
+    #[cfg_attr(feature = "sqlx", sqlx(default))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_speed: Option<f64>,
+    #[cfg_attr(feature = "sqlx", sqlx(default))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_speed: Option<f64>,
+    #[cfg_attr(feature = "sqlx", sqlx(default))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineListResponse {
+    pub machines: Vec<Machine>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineChangesResponse {
+    pub machines: Vec<Machine>,
+    // Ids of machines archived since the cursor, to drop from the client's view
+    #[serde(default)]
+    pub archived: Vec<i64>,
+    // Change sequence number of the last change delivered, to pass back as `cursor`
+    #[serde(default)]
+    pub cursor: i64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommentListResponse {
+    pub comments: Vec<MaintenanceComment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryResponse {
+    pub history: Vec<SpeedHistory>,
+    // "raw" for individual readings, "hour" or "day" when served from rollups
+    #[serde(default)]
+    pub resolution: String,
+    // Annotations overlapping the requested window, for charts to mark
+    #[serde(default)]
+    pub annotations: Vec<TrendAnnotation>,
+}
+
+// A note on a machine's trend at a point in time, or over a range when ends_at is set, such
+// as a die change or a new raw material lot
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct TrendAnnotation {
+    pub id: i64,
+    pub machine_id: i64,
+    pub starts_at: i64,
+    pub ends_at: Option<i64>,
+    pub text: String,
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+// A production lot run on a machine between started_at and ended_at, or until now while it
+// is still running
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MachineBatch {
+    pub id: i64,
+    pub machine_id: i64,
+    pub lot_number: String,
+    pub product: Option<String>,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    // None when the machine reported the lot itself
+    pub started_by: Option<String>,
+    pub ended_by: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StartBatchRequest {
+    pub lot_number: String,
+    #[serde(default)]
+    pub product: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineBatchListResponse {
+    pub batches: Vec<MachineBatch>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Changeover {
+    pub id: i64,
+    pub machine_id: i64,
+    pub from_product: Option<String>,
+    pub to_product: Option<String>,
+    // "product_change" when detected from the reported product, "operator" when marked by hand
+    pub source: String,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    pub started_by: Option<String>,
+    pub ended_by: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StartChangeoverRequest {
+    #[serde(default)]
+    pub to_product: Option<String>,
+}
+
+// An operator assigned to a machine for one shift
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct OperatorAssignment {
+    pub id: i64,
+    pub machine_id: i64,
+    pub username: String,
+    // Local day the shift starts on, e.g. "2024-03-18"
+    pub shift_date: String,
+    // Index into the site's shift_names
+    pub shift: i64,
+    // The shift's window as it was configured when the operator was assigned
+    pub starts_at: i64,
+    pub ends_at: i64,
+    pub assigned_by: String,
+    pub assigned_at: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AssignOperatorRequest {
+    pub username: String,
+    pub shift_date: String,
+    pub shift: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShiftScheduleResponse {
+    pub from: String,
+    pub to: String,
+    pub shift_names: Vec<String>,
+    pub assignments: Vec<OperatorAssignment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct OperatorReportEntry {
+    pub username: String,
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub shifts: i64,
+    pub hours: f64,
+    // Average reported speed while the operator was assigned; None without readings
+    pub avg_speed: Option<f64>,
+    pub alarms: i64,
+    pub flagged_readings: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OperatorReportResponse {
+    pub plant_name: String,
+    pub from: i64,
+    pub to: i64,
+    pub operators: Vec<OperatorReportEntry>,
+}
+
+// A machine's place among the machines of its type in a benchmark
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BenchmarkEntry {
+    pub machine_id: i64,
+    pub name: String,
+    pub code: String,
+    pub location: Option<String>,
+    pub value: f64,
+    // Speed readings the value is based on
+    pub samples: i64,
+    // 1 for the best value; machines with equal values share a rank
+    pub rank: i64,
+    // Percent of the other machines with a lower value
+    pub percentile: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BenchmarkStatistics {
+    pub machines: i64,
+    pub min: f64,
+    pub p10: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BenchmarkResponse {
+    pub machine_type: String,
+    pub metric: String,
+    pub period: String,
+    pub from: i64,
+    pub to: i64,
+    // None when no machine of the type has readings in the period
+    pub statistics: Option<BenchmarkStatistics>,
+    pub machines: Vec<BenchmarkEntry>,
+}
+
+// A machine's daily health score and the parts it was built from, over the week before `day`
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MachineHealthScore {
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub machine_code: String,
+    pub location: Option<String>,
+    pub day: String,
+    pub score: f64,
+    pub alarm_rate: f64,
+    pub anomalies: i64,
+    pub data_coverage: f64,
+    pub downtime_change: Option<f64>,
+    pub computed_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthScoreListResponse {
+    // None before the first scores are computed
+    pub day: Option<String>,
+    // Worst first
+    pub scores: Vec<MachineHealthScore>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub file_name: String,
+    pub size_bytes: i64,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupListResponse {
+    // Newest first
+    pub backups: Vec<BackupFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeoverListResponse {
+    pub changeovers: Vec<Changeover>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ChangeoverReportEntry {
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub from_product: Option<String>,
+    pub to_product: Option<String>,
+    pub month: String,
+    pub changeovers: i64,
+    pub total_minutes: f64,
+    pub avg_minutes: f64,
+    pub min_minutes: f64,
+    pub max_minutes: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeoverReportResponse {
+    pub plant_name: String,
+    pub from: i64,
+    pub to: i64,
+    pub changeovers: Vec<ChangeoverReportEntry>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateTrendAnnotationRequest {
+    pub starts_at: i64,
+    #[serde(default)]
+    pub ends_at: Option<i64>,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrendAnnotationListResponse {
+    pub annotations: Vec<TrendAnnotation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct SpeedAggregates {
+    pub samples: i64,
+    pub avg_speed: Option<f64>,
+    pub min_speed: Option<f64>,
+    pub max_speed: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineDetailResponse {
+    pub machine: Machine,
+    pub last_24h: SpeedAggregates,
+    pub recent_comments: Vec<MaintenanceComment>,
+    pub open_work_orders: Vec<WorkOrder>,
+    // Operators assigned to the machine for the shift running now
+    #[serde(default)]
+    pub operators: Vec<OperatorAssignment>,
+    // Approved permits whose validity window includes now
+    #[serde(default)]
+    pub active_permits: Vec<WorkPermit>,
+    // Alarms still raised or not yet acknowledged, most severe first
+    #[serde(default)]
+    pub active_alarms: Vec<Alarm>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateResponse {
+    pub success: bool,
+    pub timestamp: i64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flagged: Vec<String>,
+    // The update repeated the previous values, so only the machine's last_update was refreshed
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    pub deduplicated: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateUserRequest {
+    pub password: Option<String>,
+    pub role: Option<String>,
+    pub is_active: Option<bool>,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateMachineRequest {
+    pub name: Option<String>,
+    pub code: Option<String>,
+    pub location: Option<String>,
+    pub machine_type: Option<String>,
+    pub target_speed: Option<f64>,
+    pub report_interval: Option<i64>,
+    pub dedup_updates: Option<bool>,
+    pub history_retention_days: Option<i64>,
+    // Username of a manager or admin; an empty string clears it
+    pub data_owner: Option<String>,
+    // Networks the machine's API keys are accepted from, e.g. ["10.20.0.0/16"]; an empty
+    // list lifts the restriction
+    pub allowed_cidrs: Option<Vec<String>>,
+    pub regenerate_api_key: Option<bool>,
+}
+
+// Machine fields a machine list can show, sort and filter by
+pub const MACHINE_LIST_COLUMNS: &[&str] = &[
+    "name",
+    "display_name",
+    "code",
+    "location",
+    "machine_type",
+    "current_speed",
+    "status_message",
+    "is_online",
+    "last_update",
+    "updated_at",
+    "report_interval",
+    "clock_drift",
+];
+
+// A user's machine list layout, saved so it follows them across devices and browsers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MachineListView {
+    // Visible columns, in display order
+    pub columns: Vec<String>,
+    pub sort_by: String,
+    #[serde(default)]
+    pub sort_descending: bool,
+    // Filters applied when the list opens, by column, e.g. {"location": "Hall 2"}
+    #[serde(default)]
+    pub filters: BTreeMap<String, String>,
+    // When the view was last saved; null while the defaults apply
+    #[serde(default, skip_deserializing)]
+    pub updated_at: Option<i64>,
+}
+
+impl Default for MachineListView {
+    fn default() -> Self {
+        Self {
+            columns: ["name", "code", "location", "current_speed", "status_message", "is_online", "last_update"]
+                .iter()
+                .map(|column| String::from(*column))
+                .collect(),
+            sort_by: String::from("name"),
+            sort_descending: false,
+            filters: BTreeMap::new(),
+            updated_at: None,
+        }
+    }
+}
+
+// Shortest and longest time a wallboard may show one machine
+pub const KIOSK_ROTATION_RANGE: (i64, i64) = (5, 3600);
+
+// A named set of machines a wallboard cycles through, in order
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KioskGroup {
+    pub name: String,
+    // Seconds per machine; the server default applies when unset
+    pub rotation_interval_secs: Option<i64>,
+    pub machine_ids: Vec<i64>,
+    pub updated_by: String,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KioskGroupListResponse {
+    pub groups: Vec<KioskGroup>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetKioskGroupRequest {
+    // Machines in the order they are shown
+    pub machine_ids: Vec<i64>,
+    pub rotation_interval_secs: Option<i64>,
+}
+
+// A read-only token for a wall-mounted display
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct DisplayToken {
+    pub id: i64,
+    pub name: String,
+    // Kiosk group whose machines the display shows; every machine when unset
+    pub kiosk_group: Option<String>,
+    pub expires_at: Option<i64>,
+    pub created_by: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    // The token itself, only returned when it is created
+    #[cfg_attr(feature = "sqlx", sqlx(default))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisplayTokenListResponse {
+    pub display_tokens: Vec<DisplayToken>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateDisplayTokenRequest {
+    pub name: String,
+    #[serde(default)]
+    pub kiosk_group: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+// A product in the catalog, identified by the SKU batches name in their product field
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Product {
+    pub sku: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub standards: Vec<ProductSpeedStandard>,
+    pub updated_by: String,
+    pub updated_at: i64,
+}
+
+// Speed machines of a type are expected to run the product at
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProductSpeedStandard {
+    pub machine_type: String,
+    pub standard_speed: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProductListResponse {
+    pub products: Vec<Product>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetProductRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub standards: Vec<ProductSpeedStandard>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct KioskTrendPoint {
+    // Start of the minute
+    pub timestamp: i64,
+    pub speed: f64,
+}
+
+// One full-screen page of a wallboard
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KioskSlide {
+    pub machine: Machine,
+    // The running product's standard speed for the machine type, else the machine's target
+    pub target_speed: Option<f64>,
+    // SKU of the product in the running batch
+    #[serde(default)]
+    pub product: Option<String>,
+    pub last_hour: SpeedAggregates,
+    // Average speed per minute over the last hour, oldest first
+    pub trend: Vec<KioskTrendPoint>,
+    pub active_alarms: i64,
+    // Severity of the most severe active alarm
+    pub alarm_severity: Option<String>,
+    pub open_work_orders: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KioskRotationResponse {
+    // Null when rotating through every machine
+    pub group: Option<String>,
+    // Seconds to show each slide before moving to the next
+    pub rotation_interval_secs: i64,
+    pub generated_at: i64,
+    pub slides: Vec<KioskSlide>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserListResponse {
+    pub users: Vec<User>,
+}
+
+// A signed-in device of a user
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Session {
+    pub id: String,
+    pub user_agent: Option<String>,
+    // Address the session was last refreshed from
+    pub ip: Option<String>,
+    pub created_at: i64,
+    // Last login or token refresh, so at most one access token lifetime behind
+    pub last_seen_at: i64,
+    pub expires_at: i64,
+    // The session making the request
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionListResponse {
+    pub sessions: Vec<Session>,
+}
+
+// A machine granted to a user; technicians see only their granted machines
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MachineAccessGrant {
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub granted_by: Option<String>,
+    pub granted_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineAccessListResponse {
+    pub username: String,
+    // Managers and admins see every machine whatever is granted
+    pub sees_all_machines: bool,
+    pub machines: Vec<MachineAccessGrant>,
+}
+#[derive(Debug)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct JobRecord {
+    pub id: String,
+    pub kind: String,
+    pub status: String,
+    pub progress: f64,
+    pub result: Option<String>,
+    pub error: Option<String>,
+    pub created_by: String,
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub artifact_path: Option<String>,
+    pub artifact_name: Option<String>,
+    pub artifact_content_type: Option<String>,
+    pub artifact_expires_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobArtifact {
+    pub file_name: String,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobResponse {
+    pub id: String,
+    pub kind: String,
+    pub status: String,
+    pub progress: f64,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub created_by: String,
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub artifact: Option<JobArtifact>,
+}
+
+impl From<JobRecord> for JobResponse {
+    fn from(job: JobRecord) -> Self {
+        Self {
+            id: job.id,
+            kind: job.kind,
+            status: job.status,
+            progress: job.progress,
+            result: job.result.and_then(|r| serde_json::from_str(&r).ok()),
+            error: job.error,
+            created_by: job.created_by,
+            created_at: job.created_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            artifact: match (job.artifact_path, job.artifact_name, job.artifact_expires_at) {
+                (Some(_), Some(file_name), Some(expires_at)) => Some(JobArtifact { file_name, expires_at }),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobCreatedResponse {
+    pub job_id: String,
+    pub status: String,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MachineArchiveRecord {
+    pub id: i64,
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub machine_code: Option<String>,
+    pub file_path: String,
+    pub file_name: String,
+    pub size_bytes: i64,
+    pub sha256: String,
+    pub row_counts: String,
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+// Archive left behind by a decommissioned machine
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineArchive {
+    pub id: i64,
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub machine_code: Option<String>,
+    pub file_name: String,
+    pub size_bytes: i64,
+    pub sha256: String,
+    // Rows archived from each table
+    pub row_counts: serde_json::Value,
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+impl From<MachineArchiveRecord> for MachineArchive {
+    fn from(archive: MachineArchiveRecord) -> Self {
+        Self {
+            id: archive.id,
+            machine_id: archive.machine_id,
+            machine_name: archive.machine_name,
+            machine_code: archive.machine_code,
+            file_name: archive.file_name,
+            size_bytes: archive.size_bytes,
+            sha256: archive.sha256,
+            row_counts: serde_json::from_str(&archive.row_counts).unwrap_or_default(),
+            created_by: archive.created_by,
+            created_at: archive.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineArchiveListResponse {
+    pub archives: Vec<MachineArchive>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HistoryExportRequest {
+    pub range: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HistoryShiftRequest {
+    // Range of history to move, as `[from, to)`
+    pub from: i64,
+    pub to: i64,
+    // Seconds to add to each timestamp; negative moves history earlier
+    pub offset_seconds: i64,
+    pub reason: Option<String>,
+}
+
+// A correction that moved a range of a machine's history in time
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct HistoryShift {
+    pub id: i64,
+    pub machine_id: i64,
+    pub range_from: i64,
+    pub range_to: i64,
+    pub offset_seconds: i64,
+    pub rows_shifted: i64,
+    pub reason: Option<String>,
+    pub job_id: Option<String>,
+    pub created_by: String,
+    pub created_at: i64,
+    pub undone_by: Option<String>,
+    pub undone_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryShiftListResponse {
+    pub shifts: Vec<HistoryShift>,
+}
+
+// A request to export a machine's history, which a manager approves before the export runs
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ExportRequest {
+    pub id: i64,
+    pub machine_id: i64,
+    // Window to export, as `[range_from, range_to)`
+    pub range_from: i64,
+    pub range_to: i64,
+    pub reason: String,
+    // pending, approved or rejected
+    pub status: String,
+    pub requested_by: String,
+    pub requested_at: i64,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<i64>,
+    pub decision_note: Option<String>,
+    // The export job, queued on approval and owned by the requester
+    pub job_id: Option<String>,
+    pub downloads: i64,
+    pub last_downloaded_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateExportRequest {
+    pub machine_id: i64,
+    pub range: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct DecideExportRequest {
+    // Optional on approval, required on rejection
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportRequestListResponse {
+    pub requests: Vec<ExportRequest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadLinkResponse {
+    pub url: String,
+    pub expires_at: i64,
+}
+
+pub const ALARM_SEVERITIES: &[&str] = &["info", "warning", "critical"];
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct AlarmPresentation {
+    pub severity: String,
+    pub sound_id: Option<String>,
+    pub color: String,
+    pub auto_popup: bool,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlarmPresentationListResponse {
+    pub severities: Vec<AlarmPresentation>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateAlarmPresentationRequest {
+    pub sound_id: Option<String>,
+    pub color: String,
+    pub auto_popup: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct CommentCategory {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateCommentCategoryRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommentCategoryListResponse {
+    pub categories: Vec<CommentCategory>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct CategoryReportEntry {
+    pub category_id: Option<i64>,
+    pub category: Option<String>,
+    pub comments: i64,
+    pub high_priority: i64,
+    pub machines: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CategoryReportResponse {
+    pub plant_name: String,
+    pub from: i64,
+    pub to: i64,
+    pub categories: Vec<CategoryReportEntry>,
+}
+
+pub const DOCUMENT_TYPES: &[&str] = &["manual", "drawing", "sop", "other"];
+
+#[derive(Debug)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MachineDocumentRecord {
+    pub id: i64,
+    pub machine_id: i64,
+    pub title: String,
+    pub doc_type: String,
+    pub url: Option<String>,
+    pub file_path: Option<String>,
+    pub file_name: Option<String>,
+    pub content_type: Option<String>,
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineDocument {
+    pub id: i64,
+    pub machine_id: i64,
+    pub title: String,
+    pub doc_type: String,
+    pub url: Option<String>,
+    pub file_name: Option<String>,
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+impl From<MachineDocumentRecord> for MachineDocument {
+    fn from(document: MachineDocumentRecord) -> Self {
+        // Uploaded files are served by the API, so point clients at the download route
+        let url = match document.file_path {
+            Some(_) => Some(format!("/api/documents/{}/file", document.id)),
+            None => document.url,
+        };
+        Self {
+            id: document.id,
+            machine_id: document.machine_id,
+            title: document.title,
+            doc_type: document.doc_type,
+            url,
+            file_name: document.file_name,
+            created_by: document.created_by,
+            created_at: document.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateDocumentRequest {
+    pub title: String,
+    pub doc_type: String,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateDocumentRequest {
+    pub title: Option<String>,
+    pub doc_type: Option<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentListResponse {
+    pub documents: Vec<MachineDocument>,
+}
+
+pub const WORK_ORDER_STATUSES: &[&str] = &["open", "in_progress", "completed", "cancelled"];
+pub const PRIORITIES: &[&str] = &["low", "normal", "high", "critical"];
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct WorkOrder {
+    pub id: i64,
+    pub machine_id: i64,
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: String,
+    pub status: String,
+    pub category_id: Option<i64>,
+    pub assigned_to: Option<String>,
+    pub created_by: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub signed_off_by: Option<String>,
+    pub signed_off_at: Option<i64>,
+    // The critical alarm this work order was created for, if it was created automatically
+    pub alarm_id: Option<i64>,
+    // Certification the assignee must hold, e.g. "electrical"
+    pub required_skill: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct WorkOrderStep {
+    pub id: i64,
+    pub position: i64,
+    pub text: String,
+    pub required: bool,
+    pub completed_by: Option<String>,
+    pub completed_at: Option<i64>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkOrderDetailResponse {
+    #[serde(flatten)]
+    pub work_order: WorkOrder,
+    pub checklist: Vec<WorkOrderStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkOrderListResponse {
+    pub work_orders: Vec<WorkOrder>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateWorkOrderRequest {
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: Option<String>,
+    pub category_id: Option<i64>,
+    pub assigned_to: Option<String>,
+    pub checklist_template_id: Option<i64>,
+    pub required_skill: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateWorkOrderRequest {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub priority: Option<String>,
+    pub status: Option<String>,
+    pub category_id: Option<i64>,
+    pub assigned_to: Option<String>,
+    pub required_skill: Option<String>,
+}
+
+// A permit to work on a machine, see the Permits to Work section of API.md
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkPermit {
+    pub id: i64,
+    pub work_order_id: i64,
+    pub machine_id: i64,
+    pub permit_type: String,
+    pub description: Option<String>,
+    pub isolation_points: Vec<String>,
+    pub valid_from: i64,
+    pub valid_until: i64,
+    // "requested", "approved" or "closed"
+    pub status: String,
+    pub requested_by: String,
+    pub requested_at: i64,
+    pub approved_by: Option<String>,
+    pub approved_at: Option<i64>,
+    pub approval_signature: Option<String>,
+    pub closed_by: Option<String>,
+    pub closed_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkPermitListResponse {
+    pub permits: Vec<WorkPermit>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateWorkPermitRequest {
+    pub permit_type: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub isolation_points: Vec<String>,
+    pub valid_from: i64,
+    pub valid_until: i64,
+}
+
+// The approver signs with their full name and confirms it with their password
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApproveWorkPermitRequest {
+    pub signature: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AttachChecklistRequest {
+    pub template_id: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateWorkOrderStepRequest {
+    pub completed: bool,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ChecklistTemplate {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ChecklistTemplateStep {
+    pub text: String,
+    pub required: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChecklistTemplateResponse {
+    #[serde(flatten)]
+    pub template: ChecklistTemplate,
+    pub steps: Vec<ChecklistTemplateStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChecklistTemplateListResponse {
+    pub templates: Vec<ChecklistTemplateResponse>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateChecklistTemplateRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub steps: Vec<ChecklistTemplateStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct LaborEntry {
+    pub id: i64,
+    pub work_order_id: i64,
+    pub username: String,
+    pub started_at: i64,
+    pub stopped_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LaborListResponse {
+    pub entries: Vec<LaborEntry>,
+    pub total_hours: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct LaborReportEntry {
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub month: String,
+    pub hours: f64,
+    pub entries: i64,
+    pub technicians: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LaborReportResponse {
+    pub plant_name: String,
+    pub from: i64,
+    pub to: i64,
+    pub machines: Vec<LaborReportEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryGap {
+    pub start: i64,
+    pub end: i64,
+    // Seconds without recorded data
+    pub duration: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineGapsResponse {
+    pub machine_id: i64,
+    // Start of the analysed range, never before the machine was created
+    pub from: i64,
+    pub to: i64,
+    pub min_gap: i64,
+    pub total_gap_seconds: i64,
+    // Fraction of the range with data, 0.0 to 1.0
+    pub coverage: f64,
+    pub gaps: Vec<HistoryGap>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GapReportEntry {
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub gaps: i64,
+    pub total_gap_seconds: i64,
+    pub longest_gap: i64,
+    pub coverage: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GapReportResponse {
+    pub plant_name: String,
+    pub from: i64,
+    pub to: i64,
+    pub min_gap: i64,
+    pub gaps: i64,
+    pub total_gap_seconds: i64,
+    pub machines: Vec<GapReportEntry>,
+}
+
+pub const COST_PERIODS: &[&str] = &["month", "year"];
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct CostEntry {
+    pub id: i64,
+    pub machine_id: i64,
+    pub work_order_id: Option<i64>,
+    pub description: String,
+    pub amount: f64,
+    pub incurred_at: i64,
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateCostRequest {
+    pub description: String,
+    pub amount: f64,
+    pub work_order_id: Option<i64>,
+    pub incurred_at: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CostSummary {
+    pub labor_hours: f64,
+    pub labor_cost: f64,
+    pub parts_cost: f64,
+    pub total_cost: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CostPeriod {
+    pub period: String,
+    #[serde(flatten)]
+    pub summary: CostSummary,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineCostResponse {
+    pub machine_id: i64,
+    pub from: i64,
+    pub to: i64,
+    pub labor_rate: f64,
+    pub periods: Vec<CostPeriod>,
+    pub total: CostSummary,
+}
+
+pub const CONTRACT_KINDS: &[&str] = &["warranty", "service_contract"];
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Certification {
+    pub id: i64,
+    pub username: String,
+    pub skill: String,
+    pub reference: Option<String>,
+    pub issued_at: Option<i64>,
+    // None for certifications that never expire
+    pub expires_at: Option<i64>,
+    pub notified_at: Option<i64>,
+    pub recorded_by: String,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetCertificationRequest {
+    pub reference: Option<String>,
+    pub issued_at: Option<i64>,
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CertificationListResponse {
+    pub certifications: Vec<Certification>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MachineContract {
+    pub id: i64,
+    pub machine_id: i64,
+    pub kind: String,
+    pub vendor: String,
+    pub reference: Option<String>,
+    pub coverage: Option<String>,
+    pub starts_at: Option<i64>,
+    pub expires_at: i64,
+    pub notified_at: Option<i64>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateContractRequest {
+    pub kind: String,
+    pub vendor: String,
+    pub reference: Option<String>,
+    pub coverage: Option<String>,
+    pub starts_at: Option<i64>,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateContractRequest {
+    pub vendor: Option<String>,
+    pub reference: Option<String>,
+    pub coverage: Option<String>,
+    pub starts_at: Option<i64>,
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContractListResponse {
+    pub contracts: Vec<MachineContract>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ExpiredContractEntry {
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub location: Option<String>,
+    pub machine_type: Option<String>,
+    pub contract_id: i64,
+    pub kind: String,
+    pub vendor: String,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExpiredContractReportResponse {
+    pub plant_name: String,
+    pub as_of: i64,
+    pub contracts: Vec<ExpiredContractEntry>,
+}
+
+// Longest a machine's notifications can be snoozed at once
+pub const MAX_SNOOZE_SECS: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MachineSnooze {
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub until: i64,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineSnoozeListResponse {
+    pub snoozes: Vec<MachineSnooze>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Notification {
+    pub id: i64,
+    pub kind: String,
+    pub machine_id: Option<i64>,
+    pub message: String,
+    pub created_at: i64,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationListResponse {
+    pub notifications: Vec<Notification>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MachineDisplayName {
+    pub locale: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisplayNameListResponse {
+    pub display_names: Vec<MachineDisplayName>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetDisplayNameRequest {
+    pub display_name: String,
+}
+
+pub const MAX_METRIC_DECIMALS: u32 = 6;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MetricPrecision {
+    pub metric: String,
+    #[cfg_attr(feature = "sqlx", sqlx(try_from = "i64"))]
+    pub decimals: u32,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricPrecisionListResponse {
+    pub metrics: Vec<MetricPrecision>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateMetricPrecisionRequest {
+    pub decimals: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Unit {
+    pub symbol: String,
+    pub dimension: String,
+    pub factor: f64,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitListResponse {
+    pub units: Vec<Unit>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MachineMetric {
+    pub metric: String,
+    pub unit: String,
+    pub value: f64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineMetricListResponse {
+    pub metrics: Vec<MachineMetric>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct AlarmRule {
+    pub id: i64,
+    pub machine_id: i64,
+    pub name: String,
+    pub expression: String,
+    pub severity: String,
+    pub enabled: bool,
+    pub created_by: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlarmRuleListResponse {
+    pub rules: Vec<AlarmRule>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateAlarmRuleRequest {
+    pub name: String,
+    pub expression: String,
+    pub severity: String,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateAlarmRuleRequest {
+    pub name: Option<String>,
+    pub expression: Option<String>,
+    pub severity: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+// A regulatory limit on a metric, tracked separately from operational alarm rules. Applies to
+// every machine reporting the metric when machine_id is null.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ComplianceLimit {
+    pub id: i64,
+    pub name: String,
+    pub metric: String,
+    pub machine_id: Option<i64>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub unit: Option<String>,
+    // Permit or regulation clause the limit comes from
+    pub reference: Option<String>,
+    pub enabled: bool,
+    pub created_by: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ComplianceLimitListResponse {
+    pub limits: Vec<ComplianceLimit>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateComplianceLimitRequest {
+    pub name: String,
+    pub metric: String,
+    pub machine_id: Option<i64>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub unit: Option<String>,
+    pub reference: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateComplianceLimitRequest {
+    pub name: Option<String>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub unit: Option<String>,
+    pub reference: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+// A period a machine's metric spent outside a compliance limit, with the limit as it stood
+// then. ended_at is null while the metric is still outside.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ComplianceExceedance {
+    pub id: i64,
+    pub limit_id: i64,
+    pub machine_id: i64,
+    pub limit_name: String,
+    pub metric: String,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub unit: Option<String>,
+    pub reference: Option<String>,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    // Furthest value outside the limit seen during the period
+    pub peak_value: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ComplianceExceedanceListResponse {
+    pub exceedances: Vec<ComplianceExceedance>,
+}
+
+// A level the trend prediction model extrapolates a metric towards, for one machine or every
+// machine when machine_id is null, in the unit the machine reports the metric in
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct FailureThreshold {
+    pub id: i64,
+    pub metric: String,
+    pub machine_id: Option<i64>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailureThresholdListResponse {
+    pub thresholds: Vec<FailureThreshold>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateFailureThresholdRequest {
+    pub metric: String,
+    pub machine_id: Option<i64>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+}
+
+// The latest failure risk predicted for a machine, from 0 to 1
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct FailurePrediction {
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub model: String,
+    pub risk: f64,
+    pub metric: Option<String>,
+    pub reason: String,
+    pub predicted_failure_at: Option<i64>,
+    pub computed_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailurePredictionListResponse {
+    // Highest risk first
+    pub predictions: Vec<FailurePrediction>,
+}
+
+// A predicted failure, open while the machine's risk stays at or above the advisory level.
+// Unlike an alarm it reports something expected rather than a condition that exists.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MaintenanceAdvisory {
+    pub id: i64,
+    pub machine_id: i64,
+    pub machine_name: String,
+    pub model: String,
+    pub risk: f64,
+    pub peak_risk: f64,
+    pub metric: Option<String>,
+    pub reason: String,
+    pub predicted_failure_at: Option<i64>,
+    pub raised_at: i64,
+    pub updated_at: i64,
+    pub cleared_at: Option<i64>,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MaintenanceAdvisoryListResponse {
+    pub advisories: Vec<MaintenanceAdvisory>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BacktestAlarmRuleRequest {
+    pub machine_id: i64,
+    pub expression: String,
+    pub days: Option<i64>,
+}
+
+pub const MAX_BACKTEST_DAYS: i64 = 90;
+
+pub const KPI_AGGREGATIONS: &[&str] = &["sum", "avg", "min", "max", "count"];
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct KpiDefinition {
+    pub id: i64,
+    pub label: String,
+    pub aggregation: String,
+    // Value taken from each machine, e.g. `speed * 60`; unused for "count"
+    pub expression: Option<String>,
+    // Condition a machine must meet to be included, e.g. `is_online == 1`
+    pub filter: Option<String>,
+    pub location: Option<String>,
+    pub machine_type: Option<String>,
+    pub kiosk_group: Option<String>,
+    pub target: Option<f64>,
+    pub unit: Option<String>,
+    pub position: i64,
+    pub updated_by: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KpiDefinitionListResponse {
+    pub kpis: Vec<KpiDefinition>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetKpiDefinitionRequest {
+    pub label: String,
+    pub aggregation: String,
+    pub expression: Option<String>,
+    pub filter: Option<String>,
+    pub location: Option<String>,
+    pub machine_type: Option<String>,
+    pub kiosk_group: Option<String>,
+    pub target: Option<f64>,
+    pub unit: Option<String>,
+    // Order on the dashboard, lowest first; defaults to 0
+    pub position: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KpiValue {
+    pub id: i64,
+    pub label: String,
+    // None when no machine in scope has the values the expression needs
+    pub value: Option<f64>,
+    pub target: Option<f64>,
+    pub unit: Option<String>,
+    // Machines that contributed to the value
+    pub machine_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KpiValuesResponse {
+    pub generated_at: i64,
+    // Window the values were averaged over, when one was asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<i64>,
+    pub kpis: Vec<KpiValue>,
+}
+
+pub const MACHINE_KEY_SCOPES: &[&str] = &["telemetry:write", "commands:read", "config:read"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineApiKey {
+    pub id: i64,
+    pub machine_id: i64,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+    // Set on a key replaced by a rotation; it stops working at this time
+    pub expires_at: Option<i64>,
+    // The key itself, only returned when it is created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    // Secret for signing updates with the key, returned along with it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_secret: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineApiKeyListResponse {
+    pub keys: Vec<MachineApiKey>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateMachineApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateMachineApiKeyRequest {
+    pub name: Option<String>,
+    pub scopes: Option<Vec<String>>,
+}
+
+// Either an email address or a user, whose account address is used
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DistributionListMember {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DistributionList {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub members: Vec<DistributionListMember>,
+    // Addresses the list reaches right now, with users resolved and duplicates removed
+    pub recipients: Vec<String>,
+    pub created_by: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DistributionListListResponse {
+    pub lists: Vec<DistributionList>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateDistributionListRequest {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub members: Vec<DistributionListMember>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateDistributionListRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    // Replaces all members
+    pub members: Option<Vec<DistributionListMember>>,
+}
+
+// Scopes a service account can hold; each opens a fixed set of operator endpoints
+pub const SERVICE_ACCOUNT_SCOPES: &[&str] =
+    &["machines:read", "alarms:read", "work_orders:read", "work_orders:write", "reports:read"];
+
+// A non-interactive account for an integration such as an MES or ERP, authenticated by its
+// own API key instead of a user's password
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServiceAccount {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub scopes: Vec<String>,
+    pub created_by: String,
+    pub created_at: i64,
+    pub rotated_at: Option<i64>,
+    // Set after a rotation with a grace period; the previous key works until then
+    pub previous_key_expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub disabled_at: Option<i64>,
+    // The key itself, only returned when the account is created or its key rotated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServiceAccountListResponse {
+    pub service_accounts: Vec<ServiceAccount>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateServiceAccountRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateServiceAccountRequest {
+    pub description: Option<String>,
+    pub scopes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RotateMachineKeyRequest {
+    // Seconds the old key keeps working; defaults to the server's grace period, 0 ends it at once
+    pub grace_secs: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineKeyRotationResponse {
+    pub api_key: String,
+    // Secret for signing updates with the new key, when the server accepts signed updates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_secret: Option<String>,
+    // The old key while its grace period runs, revocable like any other key
+    pub previous_key: Option<MachineApiKey>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MergeMachinesRequest {
+    // Duplicate record to merge into the machine in the path and then delete
+    pub source_id: i64,
+    // Report what would change without changing anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineMergeResponse {
+    pub machine_id: i64,
+    pub merged_machine_id: i64,
+    pub dry_run: bool,
+    // Rows moved onto the surviving machine, per table
+    pub moved: BTreeMap<String, i64>,
+    // Rows dropped because the surviving machine already had an equivalent, per table
+    pub dropped: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandType {
+    pub id: i64,
+    pub machine_type: String,
+    pub name: String,
+    pub description: Option<String>,
+    // JSON Schema the parameters of every command of this type must match
+    pub parameters_schema: serde_json::Value,
+    // Least privileged role allowed to send it; higher roles may too
+    pub min_role: String,
+    // Whether it may be sent while the machine is locked out, e.g. a stop or a diagnostic read
+    pub allowed_during_lockout: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandTypeListResponse {
+    pub command_types: Vec<CommandType>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateCommandTypeRequest {
+    pub machine_type: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub parameters_schema: serde_json::Value,
+    // Defaults to "manager"
+    pub min_role: Option<String>,
+    #[serde(default)]
+    pub allowed_during_lockout: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateCommandTypeRequest {
+    pub description: Option<String>,
+    pub parameters_schema: Option<serde_json::Value>,
+    pub min_role: Option<String>,
+    pub allowed_during_lockout: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LockoutRequest {
+    pub reason: String,
+}
+
+pub const COMMAND_STATUSES: &[&str] = &["pending", "delivered", "completed", "failed", "expired", "cancelled"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineCommand {
+    pub id: i64,
+    pub machine_id: i64,
+    pub command_type: String,
+    pub parameters: serde_json::Value,
+    pub status: String,
+    pub created_by: String,
+    pub created_at: i64,
+    pub delivered_at: Option<i64>,
+    // When the command finished, expired or was cancelled
+    pub completed_at: Option<i64>,
+    // What the machine reported back with the outcome
+    pub result: Option<String>,
+    // A command still pending at this time expires instead of being delivered
+    pub expires_at: Option<i64>,
+    pub cancelled_by: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineCommandListResponse {
+    pub commands: Vec<MachineCommand>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SendCommandRequest {
+    pub command_type: String,
+    #[serde(default = "empty_parameters")]
+    pub parameters: serde_json::Value,
+    // Seconds the command may wait for delivery; the server default when omitted
+    pub ttl_secs: Option<i64>,
+}
+
+fn empty_parameters() -> serde_json::Value {
+    serde_json::Value::Object(serde_json::Map::new())
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CommandResultRequest {
+    pub success: bool,
+    pub result: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Alarm {
+    pub id: i64,
+    pub rule_id: i64,
+    pub machine_id: i64,
+    pub severity: String,
+    pub message: String,
+    pub raised_at: i64,
+    pub cleared_at: Option<i64>,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<i64>,
+    pub root_cause: Option<String>,
+    pub notes: Option<String>,
+    pub annotated_by: Option<String>,
+    pub annotated_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlarmListResponse {
+    pub alarms: Vec<Alarm>,
+}
+
+// How the assignee of a work order created from an alarm is chosen: nobody, the user in
+// `assign_to`, or the technician with access to the machine who has the fewest open work
+// orders, falling back to `assign_to`
+pub const ALARM_WORK_ORDER_ASSIGNEE_RULES: &[&str] = &["none", "user", "machine_technician"];
+
+// Minutes a critical alarm may be required to persist before a work order is created
+pub const ALARM_WORK_ORDER_PERSIST_RANGE: (i64, i64) = (1, 1440);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct AlarmWorkOrderPolicy {
+    pub enabled: bool,
+    pub persist_minutes: i64,
+    pub priority: String,
+    pub checklist_template_id: Option<i64>,
+    pub assignee_rule: String,
+    pub assign_to: Option<String>,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<i64>,
+}
+
+impl Default for AlarmWorkOrderPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            persist_minutes: 15,
+            priority: String::from("high"),
+            checklist_template_id: None,
+            assignee_rule: String::from("none"),
+            assign_to: None,
+            updated_by: None,
+            updated_at: None,
+        }
+    }
+}
+
+// Replaces the whole policy; omitted fields take their defaults
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateAlarmWorkOrderPolicyRequest {
+    pub enabled: bool,
+    pub persist_minutes: Option<i64>,
+    pub priority: Option<String>,
+    pub checklist_template_id: Option<i64>,
+    pub assignee_rule: Option<String>,
+    pub assign_to: Option<String>,
+}
+
+// Root cause and notes recorded on a cleared alarm; omitted fields are cleared
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AnnotateAlarmRequest {
+    pub root_cause: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct AlarmRootCause {
+    pub code: String,
+    pub description: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateAlarmRootCauseRequest {
+    pub code: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlarmRootCauseListResponse {
+    pub root_causes: Vec<AlarmRootCause>,
+}
+
+// An alarm joined with its machine, as exported for alarm reviews
+#[derive(Debug)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct AlarmExportRecord {
+    pub raised_at: i64,
+    pub cleared_at: Option<i64>,
+    pub machine_name: String,
+    pub machine_code: String,
+    pub severity: String,
+    pub message: String,
+    pub acknowledged_by: Option<String>,
+    pub root_cause: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct RootCauseReportEntry {
+    pub root_cause: Option<String>,
+    pub alarms: i64,
+    pub machines: i64,
+    pub active_seconds: i64,
+    pub mean_time_to_clear_seconds: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RootCauseReportResponse {
+    pub plant_name: String,
+    pub from: i64,
+    pub to: i64,
+    pub root_causes: Vec<RootCauseReportEntry>,
+}
+
+pub const MAX_REPLAY_SPEED: u32 = 60;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct ReplayReading {
+    pub machine_id: i64,
+    pub metric: String,
+    pub value: f64,
+    pub unit: Option<String>,
+    pub message: Option<String>,
+    pub timestamp: i64,
+}
+
+// Replayed history is always marked as sandbox data so clients never mistake it for live state
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplayEvent {
+    pub sandbox: bool,
+    #[serde(flatten)]
+    pub reading: ReplayReading,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SandboxRequest {
+    pub history_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InfoResponse {
+    pub name: String,
+    pub version: String,
+    pub features: BTreeMap<String, bool>,
+    pub read_only: bool,
+    pub site: SiteSettings,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SiteSettings {
+    pub plant_name: String,
+    // Locale for clients that send neither `?locale=` nor Accept-Language
+    pub default_locale: Option<String>,
+    // One name per configured shift, in order of their start times
+    pub shift_names: Vec<String>,
+    pub logo_url: Option<String>,
+    pub updated_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateSiteSettingsRequest {
+    pub plant_name: Option<String>,
+    // An empty string clears the default
+    pub default_locale: Option<String>,
+    pub shift_names: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiUsageEndpoint {
+    // Method and route, e.g. "POST /api/machines/update"
+    pub endpoint: String,
+    pub requests: i64,
+    pub client_errors: i64,
+    pub server_errors: i64,
+    pub avg_duration_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiUsagePrincipal {
+    // "user", "machine_key", "service_account", "anonymous" or "invalid" (an unreadable or
+    // expired token)
+    pub kind: String,
+    // Username, the name of the machine a key belongs to, or the service account's name
+    pub name: Option<String>,
+    pub machine_id: Option<i64>,
+    // Set for an additional key of the machine; the machine's own key has none
+    pub api_key_id: Option<i64>,
+    pub requests: i64,
+    pub client_errors: i64,
+    pub server_errors: i64,
+    pub error_rate: f64,
+    // Busiest endpoints first, at most ten
+    pub endpoints: Vec<ApiUsageEndpoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiUsageResponse {
+    pub from: i64,
+    pub to: i64,
+    pub requests: i64,
+    // Busiest callers first
+    pub principals: Vec<ApiUsagePrincipal>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateFeatureFlagRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadOnlyResponse {
+    pub read_only: bool,
+    pub reason: Option<String>,
+    pub since: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateReadOnlyRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChaosLatency {
+    pub millis: u64,
+    pub path_prefix: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChaosStatus {
+    pub latency: Option<ChaosLatency>,
+    pub failing_db_calls: u32,
+    // Background task name -> still running
+    pub tasks: BTreeMap<String, bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FailDbCallsRequest {
+    pub count: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Trip {
+    pub tripped_at: i64,
+    pub cleared_at: Option<i64>,
+    pub duration_seconds: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backtest {
+    pub machine_id: i64,
+    pub from: i64,
+    pub to: i64,
+    pub evaluations: i64,
+    pub trips: i64,
+    pub active_seconds: i64,
+    pub timeline: Vec<Trip>,
+}
@@ -0,0 +1,766 @@
+-- Schema as of the switch to versioned migrations. Databases created before then are
+-- brought up to date by database::upgrade_legacy_schema before this runs, which is why
+-- every statement here tolerates existing objects.
+
+CREATE TABLE IF NOT EXISTS machines (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    code TEXT NOT NULL UNIQUE,
+    api_key TEXT NOT NULL UNIQUE,
+    location TEXT,
+    machine_type TEXT,
+    current_speed REAL DEFAULT 0.0,
+    status_message TEXT DEFAULT '',
+    last_update INTEGER DEFAULT 0,
+    is_online BOOLEAN DEFAULT 0,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    updated_at INTEGER DEFAULT 0,
+    target_speed REAL,
+    report_interval INTEGER,
+    -- Comma-separated networks machine API keys are accepted from; any while NULL
+    allowed_cidrs TEXT,
+    clock_drift INTEGER,
+    dedup_updates BOOLEAN DEFAULT 1
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('admin', 'manager', 'technician')),
+    token TEXT UNIQUE,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    tokens_revoked_at INTEGER,
+    email TEXT,
+    password_changed_at INTEGER,
+    last_login INTEGER,
+    is_active INTEGER NOT NULL DEFAULT 1,
+    deactivated_at INTEGER,
+    deactivation_reason TEXT,
+    reactivated_at INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS maintenance_comments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    comment TEXT NOT NULL,
+    priority TEXT DEFAULT 'normal' CHECK (priority IN ('low', 'normal', 'high', 'critical')),
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    category_id INTEGER REFERENCES comment_categories (id),
+    pinned BOOLEAN NOT NULL DEFAULT 0,
+    resolved_by TEXT,
+    resolved_at INTEGER,
+    updated_at INTEGER,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE TABLE IF NOT EXISTS speed_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    speed REAL NOT NULL,
+    message TEXT,
+    timestamp INTEGER DEFAULT (strftime('%s', 'now')),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE TABLE IF NOT EXISTS jobs (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'running', 'completed', 'failed')),
+    progress REAL NOT NULL DEFAULT 0.0,
+    result TEXT,
+    error TEXT,
+    created_by TEXT NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    started_at INTEGER,
+    finished_at INTEGER,
+    artifact_path TEXT,
+    artifact_name TEXT,
+    artifact_content_type TEXT,
+    artifact_expires_at INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS alarm_presentation (
+    severity TEXT PRIMARY KEY CHECK (severity IN ('info', 'warning', 'critical')),
+    sound_id TEXT,
+    color TEXT NOT NULL,
+    auto_popup BOOLEAN NOT NULL DEFAULT 0,
+    updated_at INTEGER DEFAULT (strftime('%s', 'now'))
+);
+
+-- Default presentation policy, editable by admins
+INSERT OR IGNORE INTO alarm_presentation (severity, sound_id, color, auto_popup) VALUES
+    ('info', NULL, '#2F80ED', 0),
+    ('warning', 'chime', '#F2C94C', 0),
+    ('critical', 'siren', '#EB5757', 1);
+
+CREATE TABLE IF NOT EXISTS comment_categories (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT,
+    created_at INTEGER DEFAULT (strftime('%s', 'now'))
+);
+
+INSERT OR IGNORE INTO comment_categories (name) VALUES
+    ('mechanical'), ('electrical'), ('software'), ('safety');
+
+CREATE TABLE IF NOT EXISTS machine_documents (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    doc_type TEXT NOT NULL CHECK (doc_type IN ('manual', 'drawing', 'sop', 'other')),
+    url TEXT,
+    file_path TEXT,
+    file_name TEXT,
+    content_type TEXT,
+    created_by TEXT NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE TABLE IF NOT EXISTS work_orders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT,
+    priority TEXT NOT NULL DEFAULT 'normal' CHECK (priority IN ('low', 'normal', 'high', 'critical')),
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'in_progress', 'completed', 'cancelled')),
+    category_id INTEGER,
+    assigned_to TEXT,
+    created_by TEXT NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    updated_at INTEGER DEFAULT (strftime('%s', 'now')),
+    signed_off_by TEXT,
+    signed_off_at INTEGER,
+    alarm_id INTEGER REFERENCES alarms (id),
+    required_skill TEXT,
+    FOREIGN KEY (machine_id) REFERENCES machines (id),
+    FOREIGN KEY (category_id) REFERENCES comment_categories (id)
+);
+
+CREATE TABLE IF NOT EXISTS checklist_templates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT,
+    created_at INTEGER DEFAULT (strftime('%s', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS checklist_template_steps (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    template_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    required BOOLEAN NOT NULL DEFAULT 1,
+    FOREIGN KEY (template_id) REFERENCES checklist_templates (id)
+);
+
+CREATE TABLE IF NOT EXISTS work_order_steps (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    work_order_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    required BOOLEAN NOT NULL DEFAULT 1,
+    completed_by TEXT,
+    completed_at INTEGER,
+    notes TEXT,
+    FOREIGN KEY (work_order_id) REFERENCES work_orders (id)
+);
+
+CREATE TABLE IF NOT EXISTS work_order_labor (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    work_order_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    stopped_at INTEGER,
+    FOREIGN KEY (work_order_id) REFERENCES work_orders (id)
+);
+
+CREATE TABLE IF NOT EXISTS maintenance_costs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    work_order_id INTEGER,
+    description TEXT NOT NULL,
+    amount REAL NOT NULL CHECK (amount >= 0),
+    incurred_at INTEGER NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    FOREIGN KEY (machine_id) REFERENCES machines (id),
+    FOREIGN KEY (work_order_id) REFERENCES work_orders (id)
+);
+
+CREATE TABLE IF NOT EXISTS machine_contracts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('warranty', 'service_contract')),
+    vendor TEXT NOT NULL,
+    reference TEXT,
+    coverage TEXT,
+    starts_at INTEGER,
+    expires_at INTEGER NOT NULL,
+    notified_at INTEGER,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Technician skills; a work order requiring a skill goes only to holders of a valid certification
+CREATE TABLE IF NOT EXISTS certifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL,
+    skill TEXT NOT NULL,
+    reference TEXT,
+    issued_at INTEGER,
+    expires_at INTEGER,
+    notified_at INTEGER,
+    recorded_by TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    UNIQUE (username, skill)
+);
+
+CREATE TABLE IF NOT EXISTS notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    machine_id INTEGER,
+    message TEXT NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    acknowledged_by TEXT,
+    acknowledged_at INTEGER,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE TABLE IF NOT EXISTS machine_display_names (
+    machine_id INTEGER NOT NULL,
+    locale TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    PRIMARY KEY (machine_id, locale),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE TABLE IF NOT EXISTS metric_precision (
+    metric TEXT PRIMARY KEY,
+    decimals INTEGER NOT NULL CHECK (decimals BETWEEN 0 AND 6),
+    updated_at INTEGER DEFAULT (strftime('%s', 'now'))
+);
+
+-- Default rounding policy, editable by admins
+INSERT OR IGNORE INTO metric_precision (metric, decimals) VALUES ('speed', 1);
+
+CREATE TABLE IF NOT EXISTS units (
+    symbol TEXT PRIMARY KEY,
+    dimension TEXT NOT NULL,
+    factor REAL NOT NULL CHECK (factor > 0),
+    description TEXT
+);
+
+-- Factors convert into the base unit of each dimension
+INSERT OR IGNORE INTO units (symbol, dimension, factor, description) VALUES
+    ('Pa', 'pressure', 1, 'Pascal'),
+    ('kPa', 'pressure', 1000, 'Kilopascal'),
+    ('bar', 'pressure', 100000, 'Bar'),
+    ('psi', 'pressure', 6894.757, 'Pounds per square inch'),
+    ('degC', 'temperature', 1, 'Degrees Celsius'),
+    ('m/s', 'velocity', 1, 'Meters per second'),
+    ('m/min', 'velocity', 0.0166666667, 'Meters per minute'),
+    ('rpm', 'rotational_speed', 1, 'Revolutions per minute'),
+    ('W', 'power', 1, 'Watt'),
+    ('kW', 'power', 1000, 'Kilowatt'),
+    ('A', 'current', 1, 'Ampere'),
+    ('V', 'voltage', 1, 'Volt'),
+    ('%', 'ratio', 1, 'Percent'),
+    ('count', 'count', 1, 'Items');
+
+CREATE TABLE IF NOT EXISTS machine_metrics (
+    machine_id INTEGER NOT NULL,
+    metric TEXT NOT NULL,
+    unit TEXT NOT NULL,
+    value REAL NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (machine_id, metric),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE TABLE IF NOT EXISTS metric_readings (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    metric TEXT NOT NULL,
+    value REAL NOT NULL,
+    unit TEXT NOT NULL,
+    flagged BOOLEAN NOT NULL DEFAULT 0,
+    timestamp INTEGER NOT NULL,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE TABLE IF NOT EXISTS alarm_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    expression TEXT NOT NULL,
+    severity TEXT NOT NULL CHECK (severity IN ('info', 'warning', 'critical')),
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    created_by TEXT NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    updated_at INTEGER DEFAULT (strftime('%s', 'now')),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE TABLE IF NOT EXISTS alarms (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rule_id INTEGER NOT NULL,
+    machine_id INTEGER NOT NULL,
+    severity TEXT NOT NULL,
+    message TEXT NOT NULL,
+    raised_at INTEGER NOT NULL,
+    cleared_at INTEGER,
+    acknowledged_by TEXT,
+    acknowledged_at INTEGER,
+    root_cause TEXT REFERENCES alarm_root_causes (code),
+    notes TEXT,
+    annotated_by TEXT,
+    annotated_at INTEGER,
+    FOREIGN KEY (rule_id) REFERENCES alarm_rules (id),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Turns critical alarms that stay active into work orders; a single row, disabled until saved
+CREATE TABLE IF NOT EXISTS alarm_work_order_policy (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    enabled BOOLEAN NOT NULL DEFAULT 0,
+    persist_minutes INTEGER NOT NULL,
+    priority TEXT NOT NULL,
+    checklist_template_id INTEGER,
+    assignee_rule TEXT NOT NULL,
+    assign_to TEXT,
+    updated_by TEXT,
+    updated_at INTEGER,
+    FOREIGN KEY (checklist_template_id) REFERENCES checklist_templates (id)
+);
+
+CREATE TABLE IF NOT EXISTS alarm_root_causes (
+    code TEXT PRIMARY KEY,
+    description TEXT,
+    created_at INTEGER DEFAULT (strftime('%s', 'now'))
+);
+
+INSERT OR IGNORE INTO alarm_root_causes (code) VALUES
+    ('mechanical'), ('electrical'), ('process'), ('material'), ('operator'), ('sensor'), ('unknown');
+
+-- Requests per hour by caller and endpoint; machine keys are identified by their hash
+CREATE TABLE IF NOT EXISTS api_usage (
+    hour INTEGER NOT NULL,
+    principal_kind TEXT NOT NULL,
+    principal TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    requests INTEGER NOT NULL,
+    client_errors INTEGER NOT NULL,
+    server_errors INTEGER NOT NULL,
+    total_ms INTEGER NOT NULL,
+    PRIMARY KEY (hour, principal_kind, principal, endpoint)
+);
+
+-- Named groups of email recipients that reports and alerts are sent to
+CREATE TABLE IF NOT EXISTS distribution_lists (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT,
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+-- Each member is an email address or a username, never both
+CREATE TABLE IF NOT EXISTS distribution_list_members (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    list_id INTEGER NOT NULL,
+    email TEXT,
+    username TEXT,
+    CHECK ((email IS NULL) != (username IS NULL)),
+    FOREIGN KEY (list_id) REFERENCES distribution_lists (id)
+);
+
+-- Archives of decommissioned machines; the machines themselves and their data are gone
+CREATE TABLE IF NOT EXISTS machine_archives (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    machine_name TEXT NOT NULL,
+    machine_code TEXT,
+    file_path TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    row_counts TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+-- Corrections that moved a range of a machine's history in time, kept so they can be undone
+CREATE TABLE IF NOT EXISTS history_shifts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    range_from INTEGER NOT NULL,
+    range_to INTEGER NOT NULL,
+    offset_seconds INTEGER NOT NULL,
+    rows_shifted INTEGER NOT NULL,
+    reason TEXT,
+    job_id TEXT,
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    undone_by TEXT,
+    undone_at INTEGER,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- The history rows each shift moved
+CREATE TABLE IF NOT EXISTS history_shift_rows (
+    shift_id INTEGER NOT NULL,
+    history_id INTEGER NOT NULL,
+    PRIMARY KEY (shift_id, history_id),
+    FOREIGN KEY (shift_id) REFERENCES history_shifts (id)
+);
+
+-- Nonces of signed machine updates, kept until their timestamp is too old to be accepted
+CREATE TABLE IF NOT EXISTS signed_request_nonces (
+    machine_id INTEGER NOT NULL,
+    nonce TEXT NOT NULL,
+    expires_at INTEGER NOT NULL,
+    PRIMARY KEY (machine_id, nonce)
+);
+
+-- How each user has customized list views (columns, sort, default filters), as JSON
+CREATE TABLE IF NOT EXISTS user_list_views (
+    username TEXT NOT NULL,
+    view TEXT NOT NULL,
+    settings TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (username, view)
+);
+
+-- Machines whose notifications a user has muted until a given time
+CREATE TABLE IF NOT EXISTS machine_snoozes (
+    username TEXT NOT NULL,
+    machine_id INTEGER NOT NULL,
+    until INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (username, machine_id),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Notes on a machine's trend explaining changes in its readings; ends_at is NULL for a
+-- single point in time
+CREATE TABLE IF NOT EXISTS trend_annotations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    starts_at INTEGER NOT NULL,
+    ends_at INTEGER,
+    text TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_trend_annotations_machine ON trend_annotations (machine_id, starts_at);
+-- Production lots run on a machine; ended_at is NULL while a batch is running, and at
+-- most one batch per machine runs at a time. started_by is NULL when the machine reported it.
+CREATE TABLE IF NOT EXISTS machine_batches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    lot_number TEXT NOT NULL,
+    product TEXT,
+    started_at INTEGER NOT NULL,
+    ended_at INTEGER,
+    started_by TEXT,
+    ended_by TEXT,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Changeovers between products; detected ones (source 'product_change') start at the last
+-- running reading of the old batch and end when the machine runs again, operator-marked
+-- ones (source 'operator') run until ended by hand. ended_at is NULL while in progress.
+CREATE TABLE IF NOT EXISTS changeovers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    from_product TEXT,
+    to_product TEXT,
+    source TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    ended_at INTEGER,
+    started_by TEXT,
+    ended_by TEXT,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Operators assigned to machines per shift. The shift's window is resolved when the
+-- operator is assigned, so later changes to the shift calendar don't move past shifts.
+CREATE TABLE IF NOT EXISTS operator_assignments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    shift_date TEXT NOT NULL,
+    shift INTEGER NOT NULL,
+    starts_at INTEGER NOT NULL,
+    ends_at INTEGER NOT NULL,
+    assigned_by TEXT NOT NULL,
+    assigned_at INTEGER NOT NULL,
+    UNIQUE (machine_id, shift_date, shift, username),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Product catalog; a machine running a batch of a product is measured against the
+-- product's standard speed for its machine type instead of its own target
+CREATE TABLE IF NOT EXISTS products (
+    sku TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT,
+    updated_by TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS product_speed_standards (
+    sku TEXT NOT NULL,
+    machine_type TEXT NOT NULL,
+    standard_speed REAL NOT NULL,
+    PRIMARY KEY (sku, machine_type),
+    FOREIGN KEY (sku) REFERENCES products (sku)
+);
+
+-- Every login attempt with where it came from, for auditing access
+CREATE TABLE IF NOT EXISTS login_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL,
+    succeeded BOOLEAN NOT NULL,
+    failure_reason TEXT,
+    ip TEXT,
+    user_agent TEXT,
+    created_at INTEGER NOT NULL
+);
+
+-- Plant-specific headline numbers aggregated over the machines' current values
+CREATE TABLE IF NOT EXISTS kpi_definitions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    label TEXT NOT NULL,
+    aggregation TEXT NOT NULL CHECK (aggregation IN ('sum', 'avg', 'min', 'max', 'count')),
+    expression TEXT,
+    filter TEXT,
+    location TEXT,
+    machine_type TEXT,
+    kiosk_group TEXT,
+    target REAL,
+    unit TEXT,
+    position INTEGER NOT NULL DEFAULT 0,
+    updated_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+-- Named sets of machines that wallboards cycle through, with their own rotation interval
+CREATE TABLE IF NOT EXISTS kiosk_groups (
+    name TEXT PRIMARY KEY,
+    rotation_interval_secs INTEGER,
+    updated_by TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS kiosk_group_machines (
+    group_name TEXT NOT NULL,
+    machine_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (group_name, machine_id),
+    FOREIGN KEY (group_name) REFERENCES kiosk_groups (name),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Machines a technician may see; managers and admins see all machines regardless
+CREATE TABLE IF NOT EXISTS user_machine_access (
+    username TEXT NOT NULL,
+    machine_id INTEGER NOT NULL,
+    granted_by TEXT,
+    granted_at INTEGER NOT NULL,
+    PRIMARY KEY (username, machine_id),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Branding and naming of the site; a single row, missing values fall back to defaults
+CREATE TABLE IF NOT EXISTS site_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    plant_name TEXT,
+    default_locale TEXT,
+    shift_names TEXT,
+    logo_content_type TEXT,
+    updated_by TEXT,
+    updated_at INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS feature_flags (
+    name TEXT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL DEFAULT 0,
+    updated_by TEXT,
+    updated_at INTEGER
+);
+
+-- Only a hash of each refresh token is stored; a used token is revoked and points at its replacement
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    token_hash TEXT NOT NULL UNIQUE,
+    username TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    revoked_at INTEGER,
+    replaced_by INTEGER REFERENCES refresh_tokens (id),
+    session_id TEXT
+);
+
+-- Signed-in devices of each user; a session lasts as long as its refresh tokens
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    username TEXT NOT NULL,
+    user_agent TEXT,
+    ip TEXT,
+    created_at INTEGER NOT NULL,
+    last_seen_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    revoked_at INTEGER
+);
+
+-- Access tokens logged out before their expiry, by JWT id
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+    jti TEXT PRIMARY KEY,
+    expires_at INTEGER NOT NULL
+);
+
+-- Consecutive failed logins per username, for brute-force lockout
+CREATE TABLE IF NOT EXISTS login_attempts (
+    username TEXT PRIMARY KEY,
+    failed_attempts INTEGER NOT NULL,
+    last_failed_at INTEGER NOT NULL,
+    locked_until INTEGER
+);
+
+-- Commands a machine type accepts, with a JSON Schema for their parameters
+CREATE TABLE IF NOT EXISTS command_types (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_type TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    parameters_schema TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    min_role TEXT NOT NULL DEFAULT 'manager',
+    UNIQUE (machine_type, name)
+);
+
+-- Commands queued for machines; gateways poll for pending ones and report the outcome
+CREATE TABLE IF NOT EXISTS machine_commands (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    command_type TEXT NOT NULL,
+    parameters TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    delivered_at INTEGER,
+    completed_at INTEGER,
+    result TEXT,
+    expires_at INTEGER,
+    cancelled_by TEXT,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Extra API keys of a machine, each limited to some scopes (comma-separated). Like
+-- machines.api_key, api_key holds the SHA-256 hash of the key, never the key itself.
+CREATE TABLE IF NOT EXISTS machine_api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    api_key TEXT NOT NULL UNIQUE,
+    scopes TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    revoked_at INTEGER,
+    expires_at INTEGER,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Audit trail of admins acting as other users
+CREATE TABLE IF NOT EXISTS impersonations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    admin_username TEXT NOT NULL,
+    username TEXT NOT NULL,
+    reason TEXT,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+-- Non-interactive accounts for integrations; keys are stored hashed like machine keys
+CREATE TABLE IF NOT EXISTS service_accounts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT,
+    scopes TEXT NOT NULL,
+    api_key TEXT NOT NULL UNIQUE,
+    previous_api_key TEXT,
+    previous_key_expires_at INTEGER,
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    rotated_at INTEGER,
+    last_used_at INTEGER,
+    disabled_at INTEGER
+);
+
+-- Read-only tokens for wall-mounted displays, optionally limited to a kiosk group's
+-- machines; tokens are stored hashed and revoked ones stay listed
+CREATE TABLE IF NOT EXISTS display_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    token TEXT NOT NULL UNIQUE,
+    kiosk_group TEXT,
+    expires_at INTEGER,
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    last_used_at INTEGER,
+    revoked_at INTEGER
+);
+
+-- Machines granted to users, and those display tokens show under "display:<name>"
+CREATE VIEW IF NOT EXISTS machine_grants AS
+SELECT username, machine_id FROM user_machine_access
+UNION ALL
+SELECT 'display:' || d.name, m.id FROM display_tokens d JOIN machines m
+WHERE d.revoked_at IS NULL AND (d.kiosk_group IS NULL
+    OR m.id IN (SELECT machine_id FROM kiosk_group_machines WHERE group_name = d.kiosk_group));
+
+-- Single-use tokens from forgotten password emails; only a hash of each token is kept
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+    token_hash TEXT PRIMARY KEY,
+    username TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    used_at INTEGER
+);
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_machines_api_key ON machines(api_key);
+CREATE INDEX IF NOT EXISTS idx_speed_history_machine ON speed_history(machine_id);
+CREATE INDEX IF NOT EXISTS idx_maintenance_machine ON maintenance_comments(machine_id);
+CREATE INDEX IF NOT EXISTS idx_maintenance_created ON maintenance_comments(created_at);
+CREATE INDEX IF NOT EXISTS idx_documents_machine ON machine_documents(machine_id);
+CREATE INDEX IF NOT EXISTS idx_work_orders_machine ON work_orders(machine_id);
+CREATE INDEX IF NOT EXISTS idx_work_order_steps_order ON work_order_steps(work_order_id);
+CREATE INDEX IF NOT EXISTS idx_work_order_labor_order ON work_order_labor(work_order_id);
+CREATE INDEX IF NOT EXISTS idx_maintenance_costs_machine ON maintenance_costs(machine_id);
+CREATE INDEX IF NOT EXISTS idx_contracts_machine ON machine_contracts(machine_id);
+CREATE INDEX IF NOT EXISTS idx_metric_readings_machine ON metric_readings(machine_id, metric, timestamp);
+CREATE INDEX IF NOT EXISTS idx_alarm_rules_machine ON alarm_rules(machine_id);
+CREATE INDEX IF NOT EXISTS idx_alarms_machine ON alarms(machine_id, raised_at);
+CREATE INDEX IF NOT EXISTS idx_machine_commands_machine ON machine_commands(machine_id, status);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_email ON users(lower(email));
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_user ON refresh_tokens(username);
+CREATE INDEX IF NOT EXISTS idx_distribution_list_members_list ON distribution_list_members(list_id);
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_session ON refresh_tokens(session_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(username);
+CREATE INDEX IF NOT EXISTS idx_login_history_user ON login_history(username, created_at);
+CREATE INDEX IF NOT EXISTS idx_login_history_created ON login_history(created_at);
+CREATE INDEX IF NOT EXISTS idx_machine_batches_machine ON machine_batches(machine_id, started_at);
+CREATE INDEX IF NOT EXISTS idx_machine_batches_lot ON machine_batches(lot_number);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_machine_batches_running ON machine_batches(machine_id) WHERE ended_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_changeovers_machine ON changeovers(machine_id, started_at);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_changeovers_open ON changeovers(machine_id) WHERE ended_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_operator_assignments_machine ON operator_assignments(machine_id, starts_at);
+CREATE INDEX IF NOT EXISTS idx_operator_assignments_date ON operator_assignments(shift_date);
@@ -0,0 +1,27 @@
+-- Permits to work on a machine, issued against a work order. A permit is requested, approved
+-- with the approver's signature and closed when the work is handed back; while approved and
+-- inside its validity window it is active. isolation_points is a JSON array of the points
+-- that must be isolated, e.g. ["MCC-3 breaker 12", "Valve V-101"].
+CREATE TABLE work_permits (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    work_order_id INTEGER NOT NULL,
+    machine_id INTEGER NOT NULL,
+    permit_type TEXT NOT NULL,
+    description TEXT,
+    isolation_points TEXT NOT NULL DEFAULT '[]',
+    valid_from INTEGER NOT NULL,
+    valid_until INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'requested' CHECK (status IN ('requested', 'approved', 'closed')),
+    requested_by TEXT NOT NULL,
+    requested_at INTEGER NOT NULL,
+    approved_by TEXT,
+    approved_at INTEGER,
+    approval_signature TEXT,
+    closed_by TEXT,
+    closed_at INTEGER,
+    FOREIGN KEY (work_order_id) REFERENCES work_orders (id),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_work_permits_work_order ON work_permits(work_order_id);
+CREATE INDEX idx_work_permits_machine ON work_permits(machine_id, status);
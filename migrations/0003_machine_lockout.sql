@@ -0,0 +1,8 @@
+-- Lockout/tagout: while locked_out_at is set the machine is isolated for work and the command
+-- queue refuses every command whose type is not marked allowed_during_lockout, so nothing can
+-- start it or change its speed until the lock is released.
+ALTER TABLE machines ADD COLUMN locked_out_at INTEGER;
+ALTER TABLE machines ADD COLUMN locked_out_by TEXT;
+ALTER TABLE machines ADD COLUMN lockout_reason TEXT;
+
+ALTER TABLE command_types ADD COLUMN allowed_during_lockout BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,44 @@
+-- Environmental and regulatory limits on machine metrics, kept apart from operational alarm
+-- rules. A limit applies to one machine, or to every machine reporting the metric when
+-- machine_id is NULL. reference names the permit or regulation clause the limit comes from.
+CREATE TABLE compliance_limits (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    machine_id INTEGER,
+    min_value REAL,
+    max_value REAL,
+    unit TEXT,
+    reference TEXT,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    CHECK (min_value IS NOT NULL OR max_value IS NOT NULL),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_compliance_limits_metric ON compliance_limits(metric);
+
+-- Periods a machine's metric spent outside a limit, from the first reading outside it to the
+-- first reading back inside. The limit's name, bounds and unit are copied so the log keeps
+-- what applied at the time even after the limit is changed.
+CREATE TABLE compliance_exceedances (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    limit_id INTEGER NOT NULL,
+    machine_id INTEGER NOT NULL,
+    limit_name TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    min_value REAL,
+    max_value REAL,
+    unit TEXT,
+    reference TEXT,
+    started_at INTEGER NOT NULL,
+    ended_at INTEGER,
+    peak_value REAL NOT NULL,
+    FOREIGN KEY (limit_id) REFERENCES compliance_limits (id),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_compliance_exceedances_machine ON compliance_exceedances(machine_id, started_at);
+CREATE INDEX idx_compliance_exceedances_limit ON compliance_exceedances(limit_id, ended_at);
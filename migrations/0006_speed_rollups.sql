@@ -0,0 +1,34 @@
+-- Speed history rolled up per machine into hours and UTC days, so long ranges are read from a
+-- few rows per bucket instead of every reading. bucket_start is the start of the hour or day.
+CREATE TABLE speed_history_hourly (
+    machine_id INTEGER NOT NULL,
+    bucket_start INTEGER NOT NULL,
+    avg_speed REAL NOT NULL,
+    min_speed REAL NOT NULL,
+    max_speed REAL NOT NULL,
+    samples INTEGER NOT NULL,
+    PRIMARY KEY (machine_id, bucket_start),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE TABLE speed_history_daily (
+    machine_id INTEGER NOT NULL,
+    bucket_start INTEGER NOT NULL,
+    avg_speed REAL NOT NULL,
+    min_speed REAL NOT NULL,
+    max_speed REAL NOT NULL,
+    samples INTEGER NOT NULL,
+    PRIMARY KEY (machine_id, bucket_start),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- The last speed history row included in the rollups; rows after it are rolled up next
+CREATE TABLE speed_rollup_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    last_history_id INTEGER NOT NULL
+);
+
+INSERT INTO speed_rollup_state (id, last_history_id) VALUES (1, 0);
+
+-- Rolling up an hour reads the machine's readings in it
+CREATE INDEX idx_speed_history_machine_time ON speed_history(machine_id, timestamp);
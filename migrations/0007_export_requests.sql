@@ -0,0 +1,27 @@
+-- Manager who owns the machine's data and approves exports of it; any manager may approve
+-- when NULL
+ALTER TABLE machines ADD COLUMN data_owner TEXT;
+
+-- Requests to export a machine's history, approved or rejected by a manager before the export
+-- runs. Kept as the log of production data leaving the plant, including how often the
+-- approved export was downloaded.
+CREATE TABLE export_requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    range_from INTEGER NOT NULL,
+    range_to INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    requested_by TEXT NOT NULL,
+    requested_at INTEGER NOT NULL,
+    decided_by TEXT,
+    decided_at INTEGER,
+    decision_note TEXT,
+    job_id TEXT,
+    downloads INTEGER NOT NULL DEFAULT 0,
+    last_downloaded_at INTEGER,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_export_requests_status ON export_requests(status, requested_at);
+CREATE INDEX idx_export_requests_job ON export_requests(job_id);
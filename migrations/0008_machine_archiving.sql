@@ -0,0 +1,4 @@
+-- Archived machines are kept with all their data but left out of listings, and their API
+-- keys stop working until they are restored
+ALTER TABLE machines ADD COLUMN archived_at INTEGER;
+ALTER TABLE machines ADD COLUMN archived_by TEXT;
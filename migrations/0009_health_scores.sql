@@ -0,0 +1,25 @@
+-- A composite 0-100 health score per machine, computed each morning from the week before it.
+-- The latest score is kept on the machine for sorting listings; the table keeps every day's
+-- score with the parts it was built from.
+CREATE TABLE machine_health_scores (
+    machine_id INTEGER NOT NULL,
+    -- Site-local date the score was computed on, YYYY-MM-DD
+    day TEXT NOT NULL,
+    score REAL NOT NULL,
+    -- Alarms raised per day
+    alarm_rate REAL NOT NULL,
+    -- Compliance exceedances started and flagged metric readings
+    anomalies INTEGER NOT NULL,
+    -- Share of hours with speed history
+    data_coverage REAL NOT NULL,
+    -- Change in the share of reported hours spent stopped against the week before, in
+    -- percentage points; NULL without history in both weeks
+    downtime_change REAL,
+    computed_at INTEGER NOT NULL,
+    PRIMARY KEY (machine_id, day),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_machine_health_scores_day ON machine_health_scores(day, score);
+
+ALTER TABLE machines ADD COLUMN health_score REAL;
@@ -0,0 +1,51 @@
+-- Levels the trend prediction model extrapolates metrics towards. A threshold applies to one
+-- machine, or to every machine reporting the metric when machine_id is NULL. Values are in
+-- the unit the machine reports the metric in.
+CREATE TABLE failure_thresholds (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    metric TEXT NOT NULL,
+    machine_id INTEGER,
+    min_value REAL,
+    max_value REAL,
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    CHECK (min_value IS NOT NULL OR max_value IS NOT NULL),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_failure_thresholds_metric ON failure_thresholds(metric);
+
+-- The latest failure risk the prediction model gave each machine, from 0 to 1
+CREATE TABLE failure_predictions (
+    machine_id INTEGER PRIMARY KEY,
+    model TEXT NOT NULL,
+    risk REAL NOT NULL,
+    -- Metric the risk comes from, if the model names one
+    metric TEXT,
+    reason TEXT NOT NULL,
+    predicted_failure_at INTEGER,
+    computed_at INTEGER NOT NULL,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Advisories are kept apart from alarms: an alarm reports a condition that exists, an
+-- advisory a failure the model expects. One is open per machine while its predicted risk
+-- stays at or above the advisory level, and cleared once it drops below.
+CREATE TABLE maintenance_advisories (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    machine_id INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    risk REAL NOT NULL,
+    peak_risk REAL NOT NULL,
+    metric TEXT,
+    reason TEXT NOT NULL,
+    predicted_failure_at INTEGER,
+    raised_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    cleared_at INTEGER,
+    acknowledged_by TEXT,
+    acknowledged_at INTEGER,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_maintenance_advisories_machine ON maintenance_advisories(machine_id, cleared_at);
@@ -0,0 +1,28 @@
+-- Every write to a machine row takes the next number of a counter, so change feeds can resume
+-- from the last change a client saw instead of from a timestamp with one-second resolution.
+-- The counter only ever grows, also when machines are deleted.
+CREATE TABLE machine_change_counter (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    value INTEGER NOT NULL
+);
+
+ALTER TABLE machines ADD COLUMN change_seq INTEGER NOT NULL DEFAULT 0;
+
+UPDATE machines SET change_seq = id;
+INSERT INTO machine_change_counter (id, value) SELECT 1, COALESCE(MAX(id), 0) FROM machines;
+
+CREATE INDEX idx_machines_change_seq ON machines(change_seq);
+
+CREATE TRIGGER machines_change_seq_insert AFTER INSERT ON machines
+BEGIN
+    UPDATE machine_change_counter SET value = value + 1 WHERE id = 1;
+    UPDATE machines SET change_seq = (SELECT value FROM machine_change_counter WHERE id = 1) WHERE id = NEW.id;
+END;
+
+-- Setting change_seq itself is not a change
+CREATE TRIGGER machines_change_seq_update AFTER UPDATE ON machines
+WHEN NEW.change_seq = OLD.change_seq
+BEGIN
+    UPDATE machine_change_counter SET value = value + 1 WHERE id = 1;
+    UPDATE machines SET change_seq = (SELECT value FROM machine_change_counter WHERE id = 1) WHERE id = NEW.id;
+END;
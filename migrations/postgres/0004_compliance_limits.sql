@@ -0,0 +1,44 @@
+-- Environmental and regulatory limits on machine metrics, kept apart from operational alarm
+-- rules. A limit applies to one machine, or to every machine reporting the metric when
+-- machine_id is NULL. reference names the permit or regulation clause the limit comes from.
+CREATE TABLE compliance_limits (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    machine_id BIGINT,
+    min_value DOUBLE PRECISION,
+    max_value DOUBLE PRECISION,
+    unit TEXT,
+    reference TEXT,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    CHECK (min_value IS NOT NULL OR max_value IS NOT NULL),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_compliance_limits_metric ON compliance_limits(metric);
+
+-- Periods a machine's metric spent outside a limit, from the first reading outside it to the
+-- first reading back inside. The limit's name, bounds and unit are copied so the log keeps
+-- what applied at the time even after the limit is changed.
+CREATE TABLE compliance_exceedances (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    limit_id BIGINT NOT NULL,
+    machine_id BIGINT NOT NULL,
+    limit_name TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    min_value DOUBLE PRECISION,
+    max_value DOUBLE PRECISION,
+    unit TEXT,
+    reference TEXT,
+    started_at BIGINT NOT NULL,
+    ended_at BIGINT,
+    peak_value DOUBLE PRECISION NOT NULL,
+    FOREIGN KEY (limit_id) REFERENCES compliance_limits (id),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_compliance_exceedances_machine ON compliance_exceedances(machine_id, started_at);
+CREATE INDEX idx_compliance_exceedances_limit ON compliance_exceedances(limit_id, ended_at);
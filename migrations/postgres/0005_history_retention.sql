@@ -0,0 +1,3 @@
+-- Days of speed history kept for the machine; NULL uses SCADA_HISTORY_RETENTION_DAYS and 0
+-- keeps it forever
+ALTER TABLE machines ADD COLUMN history_retention_days BIGINT;
@@ -0,0 +1,51 @@
+-- Levels the trend prediction model extrapolates metrics towards. A threshold applies to one
+-- machine, or to every machine reporting the metric when machine_id is NULL. Values are in
+-- the unit the machine reports the metric in.
+CREATE TABLE failure_thresholds (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    metric TEXT NOT NULL,
+    machine_id BIGINT,
+    min_value DOUBLE PRECISION,
+    max_value DOUBLE PRECISION,
+    created_by TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    CHECK (min_value IS NOT NULL OR max_value IS NOT NULL),
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_failure_thresholds_metric ON failure_thresholds(metric);
+
+-- The latest failure risk the prediction model gave each machine, from 0 to 1
+CREATE TABLE failure_predictions (
+    machine_id BIGINT PRIMARY KEY,
+    model TEXT NOT NULL,
+    risk DOUBLE PRECISION NOT NULL,
+    -- Metric the risk comes from, if the model names one
+    metric TEXT,
+    reason TEXT NOT NULL,
+    predicted_failure_at BIGINT,
+    computed_at BIGINT NOT NULL,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+-- Advisories are kept apart from alarms: an alarm reports a condition that exists, an
+-- advisory a failure the model expects. One is open per machine while its predicted risk
+-- stays at or above the advisory level, and cleared once it drops below.
+CREATE TABLE maintenance_advisories (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    machine_id BIGINT NOT NULL,
+    model TEXT NOT NULL,
+    risk DOUBLE PRECISION NOT NULL,
+    peak_risk DOUBLE PRECISION NOT NULL,
+    metric TEXT,
+    reason TEXT NOT NULL,
+    predicted_failure_at BIGINT,
+    raised_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    cleared_at BIGINT,
+    acknowledged_by TEXT,
+    acknowledged_at BIGINT,
+    FOREIGN KEY (machine_id) REFERENCES machines (id)
+);
+
+CREATE INDEX idx_maintenance_advisories_machine ON maintenance_advisories(machine_id, cleared_at);
@@ -0,0 +1,29 @@
+-- Every write to a machine row takes the next number of a counter, so change feeds can resume
+-- from the last change a client saw instead of from a timestamp with one-second resolution.
+-- The counter only ever grows, also when machines are deleted. It is a row rather than a
+-- sequence so that writers to machines wait on each other and commit in counter order.
+CREATE TABLE machine_change_counter (
+    id BIGINT PRIMARY KEY CHECK (id = 1),
+    value BIGINT NOT NULL
+);
+
+ALTER TABLE machines ADD COLUMN change_seq BIGINT NOT NULL DEFAULT 0;
+
+UPDATE machines SET change_seq = id;
+INSERT INTO machine_change_counter (id, value) SELECT 1, COALESCE(MAX(id), 0) FROM machines;
+
+CREATE INDEX idx_machines_change_seq ON machines(change_seq);
+
+-- Setting change_seq itself is not a change
+CREATE FUNCTION machines_next_change_seq() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'INSERT' OR NEW.change_seq = OLD.change_seq THEN
+        UPDATE machine_change_counter SET value = value + 1 WHERE id = 1
+        RETURNING value INTO NEW.change_seq;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER machines_change_seq BEFORE INSERT OR UPDATE ON machines
+FOR EACH ROW EXECUTE FUNCTION machines_next_change_seq();
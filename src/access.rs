@@ -0,0 +1,51 @@
+use crate::{
+    auth::{AuthUser, Role},
+    database::DbPool,
+};
+
+// Condition on `machines.id` limiting a query to the machines a user may see. Bind
+// `sees_all(user)` as parameter `first` and the username after it. The machine_grants view adds
+// the machines display tokens show to the ones granted to users.
+pub fn visible_machines(first: usize) -> String {
+    format!(
+        "(${} OR machines.id IN (SELECT machine_id FROM machine_grants WHERE username = ${}))",
+        first,
+        first + 1
+    )
+}
+
+// Managers and admins see every machine; technicians and display tokens only the ones granted to them
+pub fn sees_all(user: &AuthUser) -> bool {
+    user.role >= Role::Manager
+}
+
+// Whether the machine exists, is not archived and the user may see it. Callers answer 404
+// either way, so a technician cannot probe for machines outside their assignment.
+pub async fn can_see_machine(pool: &DbPool, user: &AuthUser, machine_id: i64) -> sqlx::Result<bool> {
+    sqlx::query_scalar(&format!(
+        "SELECT EXISTS (SELECT 1 FROM machines WHERE machines.id = $1 AND machines.archived_at IS NULL AND {})",
+        visible_machines(2)
+    ))
+    .bind(machine_id)
+    .bind(sees_all(user))
+    .bind(&user.username)
+    .fetch_one(pool)
+    .await
+}
+
+// Whether row `id` of `table` exists and belongs to a machine the user may see, by the same
+// rules as can_see_machine. For endpoints that address a machine's records by their own id;
+// `table` must have a machine_id column.
+pub async fn can_see_machine_of(pool: &DbPool, user: &AuthUser, table: &str, id: i64) -> sqlx::Result<bool> {
+    sqlx::query_scalar(&format!(
+        "SELECT EXISTS (SELECT 1 FROM {0} JOIN machines ON machines.id = {0}.machine_id \
+         WHERE {0}.id = $1 AND machines.archived_at IS NULL AND {1})",
+        table,
+        visible_machines(2)
+    ))
+    .bind(id)
+    .bind(sees_all(user))
+    .bind(&user.username)
+    .fetch_one(pool)
+    .await
+}
@@ -0,0 +1,181 @@
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{
+    database::{DbPool, current_timestamp},
+    models::AlarmWorkOrderPolicy,
+    notifications,
+};
+
+// The saved policy, or the disabled default if none was saved yet
+pub async fn load_policy(pool: &DbPool) -> sqlx::Result<AlarmWorkOrderPolicy> {
+    let policy = sqlx::query_as::<_, AlarmWorkOrderPolicy>("SELECT * FROM alarm_work_order_policy WHERE id = 1")
+        .fetch_optional(pool)
+        .await?;
+    Ok(policy.unwrap_or_default())
+}
+
+// Periodically creates a work order for every critical alarm that has stayed active longer
+// than the policy allows
+pub fn spawn_alarm_work_orders(pool: DbPool) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            match create_due_work_orders(&pool).await {
+                Ok(0) => {},
+                Ok(count) => tracing::info!("Created {} work order(s) from critical alarms", count),
+                Err(e) => tracing::error!("Alarm work order check failed: {}", e),
+            }
+        }
+    })
+}
+
+// Who a work order for an alarm on the machine goes to under the policy's assignee rule
+async fn assignee(pool: &DbPool, policy: &AlarmWorkOrderPolicy, machine_id: i64) -> sqlx::Result<Option<String>> {
+    match policy.assignee_rule.as_str() {
+        "user" => Ok(policy.assign_to.clone()),
+        "machine_technician" => {
+            let technician: Option<String> = sqlx::query_scalar(
+                "SELECT u.username FROM users u \
+                 JOIN user_machine_access a ON a.username = u.username AND a.machine_id = $1 \
+                 WHERE u.role = 'technician' \
+                 ORDER BY (SELECT COUNT(*) FROM work_orders w \
+                 WHERE w.assigned_to = u.username AND w.status IN ('open', 'in_progress')), u.username \
+                 LIMIT 1"
+            )
+            .bind(machine_id)
+            .fetch_optional(pool)
+            .await?;
+            Ok(technician.or_else(|| policy.assign_to.clone()))
+        },
+        _ => Ok(None),
+    }
+}
+
+async fn create_due_work_orders(pool: &DbPool) -> sqlx::Result<u64> {
+    let policy = load_policy(pool).await?;
+    if !policy.enabled {
+        return Ok(0);
+    }
+
+    // Each alarm gets at most one work order, even if it is still active once that is closed
+    let due: Vec<(i64, i64, String, i64)> = sqlx::query_as(
+        "SELECT a.id, a.machine_id, a.message, a.raised_at FROM alarms a \
+         WHERE a.severity = 'critical' AND a.cleared_at IS NULL AND a.raised_at <= $1 \
+         AND NOT EXISTS (SELECT 1 FROM work_orders w WHERE w.alarm_id = a.id) \
+         ORDER BY a.raised_at"
+    )
+    .bind(current_timestamp() - policy.persist_minutes * 60)
+    .fetch_all(pool)
+    .await?;
+
+    let mut created = 0;
+    for (alarm_id, machine_id, message, raised_at) in due {
+        let assigned_to = assignee(pool, &policy, machine_id).await?;
+        let now = current_timestamp();
+
+        let mut tx = pool.begin().await?;
+        let work_order_id: i64 = sqlx::query_scalar(
+            "INSERT INTO work_orders (machine_id, title, description, priority, assigned_to, created_by, created_at, updated_at, alarm_id) \
+             VALUES ($1, $2, $3, $4, $5, 'system', $6, $7, $8) RETURNING id"
+        )
+        .bind(machine_id)
+        .bind(format!("Critical alarm: {}", message))
+        .bind(format!(
+            "Created automatically because alarm {} has been active for more than {} minutes.",
+            alarm_id,
+            (now - raised_at) / 60
+        ))
+        .bind(&policy.priority)
+        .bind(&assigned_to)
+        .bind(now)
+        .bind(now)
+        .bind(alarm_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if let Some(template_id) = policy.checklist_template_id {
+            sqlx::query(
+                "INSERT INTO work_order_steps (work_order_id, position, text, required) \
+                 SELECT $1, ROW_NUMBER() OVER (ORDER BY position), text, required \
+                 FROM checklist_template_steps WHERE template_id = $2"
+            )
+            .bind(work_order_id)
+            .bind(template_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        let machine_name: String = sqlx::query_scalar("SELECT name FROM machines WHERE id = $1")
+            .bind(machine_id)
+            .fetch_one(pool)
+            .await?;
+        notifications::notify(
+            pool,
+            "alarm_work_order",
+            Some(machine_id),
+            &format!("Work order {} created for the critical alarm on machine {}: {}", work_order_id, machine_name, message),
+        )
+        .await?;
+        created += 1;
+    }
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn persistent_critical_alarms_get_one_work_order_for_the_least_busy_technician() {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key1'), (2, 'Line 2', 'L2', 'key2')",
+            "INSERT INTO users (username, password, role) VALUES ('bob', 'x', 'technician'), ('carol', 'x', 'technician')",
+            "INSERT INTO user_machine_access (username, machine_id, granted_at) VALUES ('bob', 1, 0), ('carol', 1, 0)",
+            "INSERT INTO work_orders (machine_id, title, assigned_to, created_by) VALUES (1, 'Belt', 'bob', 'boss')",
+            "INSERT INTO alarm_work_order_policy (id, enabled, persist_minutes, priority, assignee_rule, assign_to) \
+             VALUES (1, TRUE, 10, 'high', 'machine_technician', 'boss')",
+            "INSERT INTO alarm_rules (machine_id, name, expression, severity, created_by) VALUES (1, 'Hot', 'temperature > 90', 'critical', 'boss')",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        let now = current_timestamp();
+        // (machine, severity, raised minutes ago)
+        for (machine_id, severity, minutes_ago) in [(1, "critical", 20), (2, "critical", 15), (1, "warning", 60), (1, "critical", 1)] {
+            sqlx::query(
+                "INSERT INTO alarms (rule_id, machine_id, severity, message, raised_at) VALUES (1, $1, $2, 'Overheating', $3)"
+            )
+            .bind(machine_id)
+            .bind(severity)
+            .bind(now - minutes_ago * 60)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(create_due_work_orders(&pool).await.unwrap(), 2);
+        assert_eq!(create_due_work_orders(&pool).await.unwrap(), 0);
+        let created: Vec<(i64, String, String, Option<String>)> = sqlx::query_as(
+            "SELECT machine_id, title, priority, assigned_to FROM work_orders WHERE alarm_id IS NOT NULL ORDER BY id"
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        let title = "Critical alarm: Overheating".to_string();
+        assert_eq!(
+            created,
+            [
+                (1, title.clone(), "high".to_string(), Some("carol".to_string())),
+                // No technician has access to line 2
+                (2, title, "high".to_string(), Some("boss".to_string())),
+            ]
+        );
+
+        sqlx::query("UPDATE alarm_work_order_policy SET enabled = FALSE").execute(&pool).await.unwrap();
+        sqlx::query("DELETE FROM work_orders WHERE alarm_id IS NOT NULL").execute(&pool).await.unwrap();
+        assert_eq!(create_due_work_orders(&pool).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,287 @@
+use sqlx::Transaction;
+use std::collections::HashMap;
+
+use crate::{
+    database::{Db, DbPool, current_timestamp},
+    expr::Expr,
+    models::{Backtest, Trip},
+};
+
+// Most trips listed in a backtest timeline
+const MAX_LISTED_TRIPS: usize = 100;
+
+// Name of the rule created for every machine that reports device timestamps
+const CLOCK_DRIFT_RULE: &str = "Clock drift";
+
+// Latest known value of every metric of a machine, including its speed and, once measured,
+// its clock drift
+async fn current_values(pool: &DbPool, machine_id: i64) -> sqlx::Result<HashMap<String, f64>> {
+    let mut values: HashMap<String, f64> = sqlx::query_as::<_, (String, f64)>(
+        "SELECT metric, value FROM machine_metrics WHERE machine_id = $1"
+    )
+    .bind(machine_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+
+    let (speed, clock_drift): (f64, Option<i64>) = sqlx::query_as("SELECT current_speed, clock_drift FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_one(pool)
+        .await?;
+    values.insert("speed".to_string(), speed);
+    if let Some(clock_drift) = clock_drift {
+        values.insert("clock_drift".to_string(), clock_drift as f64);
+    }
+    Ok(values)
+}
+
+// Adds a warning rule on `clock_drift` to a machine the first time it reports a device
+// timestamp. It is an ordinary rule afterwards, so admins can tighten, relax or disable it
+// per machine.
+pub async fn ensure_clock_drift_rule(tx: &mut Transaction<'_, Db>, machine_id: i64, max_drift: i64) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO alarm_rules (machine_id, name, expression, severity, created_by, created_at, updated_at) \
+         SELECT $1, $2, $3, 'warning', 'system', $4, $5 \
+         WHERE NOT EXISTS (SELECT 1 FROM alarm_rules WHERE machine_id = $6 AND name = $7 AND created_by = 'system')"
+    )
+    .bind(machine_id)
+    .bind(CLOCK_DRIFT_RULE)
+    .bind(format!("clock_drift > {0} || clock_drift < -{0}", max_drift))
+    .bind(current_timestamp())
+    .bind(current_timestamp())
+    .bind(machine_id)
+    .bind(CLOCK_DRIFT_RULE)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+// Re-evaluates the machine's enabled rules after new data arrived: raises an alarm when a
+// condition becomes true and clears the active alarm once it no longer holds
+pub async fn evaluate_machine(pool: &DbPool, machine_id: i64) -> sqlx::Result<()> {
+    let rules: Vec<(i64, String, String, String)> = sqlx::query_as(
+        "SELECT id, name, expression, severity FROM alarm_rules WHERE machine_id = $1 AND enabled = TRUE"
+    )
+    .bind(machine_id)
+    .fetch_all(pool)
+    .await?;
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let values = current_values(pool, machine_id).await?;
+    let now = current_timestamp();
+    for (rule_id, name, expression, severity) in rules {
+        // Rules are validated on save, so a parse failure means the grammar changed underneath
+        let Ok(condition) = Expr::parse(&expression) else {
+            continue;
+        };
+        let active: Option<i64> = sqlx::query_scalar(
+            "SELECT id FROM alarms WHERE rule_id = $1 AND cleared_at IS NULL"
+        )
+        .bind(rule_id)
+        .fetch_optional(pool)
+        .await?;
+
+        match (condition.evaluate(&values), active) {
+            (Some(true), None) => {
+                sqlx::query(
+                    "INSERT INTO alarms (rule_id, machine_id, severity, message, raised_at) VALUES ($1, $2, $3, $4, $5)"
+                )
+                .bind(rule_id)
+                .bind(machine_id)
+                .bind(&severity)
+                .bind(format!("{}: {}", name, expression))
+                .bind(now)
+                .execute(pool)
+                .await?;
+                tracing::info!("Alarm raised on machine ID {}: {}", machine_id, name);
+            },
+            (Some(false), Some(alarm_id)) => {
+                sqlx::query("UPDATE alarms SET cleared_at = $1 WHERE id = $2")
+                    .bind(now)
+                    .bind(alarm_id)
+                    .execute(pool)
+                    .await?;
+                tracing::info!("Alarm cleared on machine ID {}: {}", machine_id, name);
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+// Replays recorded speed and metric history through a condition and returns when it would
+// have tripped and cleared. Values are carried forward from their last reading within the range.
+pub async fn backtest(pool: &DbPool, machine_id: i64, condition: &Expr, from: i64, to: i64) -> sqlx::Result<Backtest> {
+    let metrics = condition.metrics();
+    let readings: Vec<(i64, String, f64)> = sqlx::query_as(
+        "SELECT timestamp, 'speed', speed FROM speed_history WHERE machine_id = $1 AND timestamp >= $2 AND timestamp <= $3 \
+         UNION ALL \
+         SELECT timestamp, metric, value FROM metric_readings \
+         WHERE machine_id = $4 AND flagged = FALSE AND timestamp >= $5 AND timestamp <= $6 \
+         ORDER BY 1"
+    )
+    .bind(machine_id)
+    .bind(from)
+    .bind(to)
+    .bind(machine_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+
+    let mut result = Backtest {
+        machine_id,
+        from,
+        to,
+        evaluations: 0,
+        trips: 0,
+        active_seconds: 0,
+        timeline: Vec::new(),
+    };
+    let mut values = HashMap::new();
+    let mut tripped_at: Option<i64> = None;
+    let record = |result: &mut Backtest, start: i64, end: Option<i64>| {
+        let duration_seconds = end.unwrap_or_else(|| to.min(current_timestamp())) - start;
+        result.active_seconds += duration_seconds;
+        if result.timeline.len() < MAX_LISTED_TRIPS {
+            result.timeline.push(Trip {
+                tripped_at: start,
+                cleared_at: end,
+                duration_seconds,
+            });
+        }
+    };
+
+    let mut index = 0;
+    while index < readings.len() {
+        // Apply every reading stamped with the same second before evaluating
+        let timestamp = readings[index].0;
+        while index < readings.len() && readings[index].0 == timestamp {
+            let (_, metric, value) = &readings[index];
+            if metrics.contains(metric.as_str()) {
+                values.insert(metric.clone(), *value);
+            }
+            index += 1;
+        }
+
+        result.evaluations += 1;
+        let holds = condition.evaluate(&values) == Some(true);
+        match (holds, tripped_at) {
+            (true, None) => {
+                tripped_at = Some(timestamp);
+                result.trips += 1;
+            },
+            (false, Some(start)) => {
+                record(&mut result, start, Some(timestamp));
+                tripped_at = None;
+            },
+            _ => {},
+        }
+    }
+    if let Some(start) = tripped_at {
+        record(&mut result, start, None);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn database() -> DbPool {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key, current_speed) VALUES (1, 'Line 1', 'L1', 'key', 120.0)",
+            "INSERT INTO machine_metrics (machine_id, metric, unit, value, updated_at) VALUES (1, 'temperature', 'degC', 90.0, 0)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    async fn alarms(pool: &DbPool) -> Vec<(String, String, bool)> {
+        sqlx::query_as("SELECT severity, message, cleared_at IS NOT NULL FROM alarms ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rules_raise_one_alarm_until_their_condition_clears() {
+        let pool = database().await;
+        sqlx::query(
+            "INSERT INTO alarm_rules (machine_id, name, expression, severity, created_by) \
+             VALUES (1, 'Hot at speed', 'speed > 100 && temperature > 80', 'critical', 'boss')"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        evaluate_machine(&pool, 1).await.unwrap();
+        evaluate_machine(&pool, 1).await.unwrap();
+        let message = "Hot at speed: speed > 100 && temperature > 80".to_string();
+        assert_eq!(alarms(&pool).await, [("critical".to_string(), message.clone(), false)]);
+
+        sqlx::query("UPDATE machine_metrics SET value = 70.0").execute(&pool).await.unwrap();
+        evaluate_machine(&pool, 1).await.unwrap();
+        assert_eq!(alarms(&pool).await, [("critical".to_string(), message, true)]);
+    }
+
+    #[tokio::test]
+    async fn backtests_carry_values_forward_between_readings() {
+        let pool = database().await;
+        for (timestamp, speed) in [(100, 50.0), (110, 120.0), (120, 130.0), (130, 60.0)] {
+            sqlx::query("INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES (1, $1, 'running', $2)")
+                .bind(speed)
+                .bind(timestamp)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        // The flagged reading is left out
+        for (timestamp, value, flagged) in [(105, 90.0, false), (115, 10.0, true), (125, 70.0, false)] {
+            sqlx::query(
+                "INSERT INTO metric_readings (machine_id, metric, value, unit, flagged, timestamp) \
+                 VALUES (1, 'temperature', $1, 'degC', $2, $3)"
+            )
+            .bind(value)
+            .bind(flagged)
+            .bind(timestamp)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let condition = Expr::parse("speed > 100 && temperature > 80").unwrap();
+        let result = backtest(&pool, 1, &condition, 0, 200).await.unwrap();
+        assert_eq!((result.evaluations, result.trips, result.active_seconds), (6, 1, 15));
+        assert_eq!((result.timeline[0].tripped_at, result.timeline[0].cleared_at), (110, Some(125)));
+
+        // Still tripped when the range ends
+        let result = backtest(&pool, 1, &condition, 0, 122).await.unwrap();
+        assert_eq!((result.trips, result.active_seconds, result.timeline[0].cleared_at), (1, 12, None));
+    }
+
+    #[tokio::test]
+    async fn clock_drift_is_alarmed_by_a_rule_added_once() {
+        let pool = database().await;
+        for max_drift in [30, 60] {
+            let mut tx = pool.begin().await.unwrap();
+            ensure_clock_drift_rule(&mut tx, 1, max_drift).await.unwrap();
+            tx.commit().await.unwrap();
+        }
+        let rules: Vec<String> = sqlx::query_scalar("SELECT expression FROM alarm_rules").fetch_all(&pool).await.unwrap();
+        assert_eq!(rules, ["clock_drift > 30 || clock_drift < -30"]);
+
+        // No alarm before the drift is measured
+        evaluate_machine(&pool, 1).await.unwrap();
+        assert!(alarms(&pool).await.is_empty());
+        sqlx::query("UPDATE machines SET clock_drift = -45").execute(&pool).await.unwrap();
+        evaluate_machine(&pool, 1).await.unwrap();
+        let message = "Clock drift: clock_drift > 30 || clock_drift < -30".to_string();
+        assert_eq!(alarms(&pool).await, [("warning".to_string(), message, false)]);
+    }
+}
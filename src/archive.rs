@@ -0,0 +1,236 @@
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+use std::io::{Cursor, Write};
+use tokio_stream::StreamExt;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    database::{self, DbPool, DbRow, current_timestamp},
+    export,
+    jobs::JobContext,
+    machine_data::MACHINE_TABLES,
+    site,
+};
+
+// A column as JSON, by the type the database stored it with; blobs become hex
+fn column_value(row: &DbRow, index: usize) -> Value {
+    let storage = match row.try_get_raw(index) {
+        Ok(raw) if !raw.is_null() => raw.type_info().name().to_string(),
+        _ => return Value::Null,
+    };
+    let value = match storage.as_str() {
+        "INTEGER" | "INT8" => row.try_get_unchecked::<i64, _>(index).map(Value::from),
+        "INT4" => row.try_get_unchecked::<i32, _>(index).map(Value::from),
+        "REAL" | "FLOAT8" => row.try_get_unchecked::<f64, _>(index).map(Value::from),
+        "BOOL" => row.try_get_unchecked::<bool, _>(index).map(Value::from),
+        "BLOB" | "BYTEA" => row.try_get_unchecked::<Vec<u8>, _>(index).map(|bytes| Value::from(hex::encode(bytes))),
+        _ => row.try_get_unchecked::<String, _>(index).map(Value::from),
+    };
+    value.unwrap_or(Value::Null)
+}
+
+fn row_to_json(row: &DbRow) -> Value {
+    Value::Object(
+        row.columns()
+            .iter()
+            .map(|column| (column.name().to_string(), column_value(row, column.ordinal())))
+            .collect(),
+    )
+}
+
+// A table's column names in their declared order
+async fn column_names(pool: &DbPool, table: &str) -> sqlx::Result<Vec<String>> {
+    let query = if database::POSTGRES {
+        "SELECT CAST(column_name AS TEXT) FROM information_schema.columns \
+         WHERE table_schema = current_schema() AND table_name = $1 ORDER BY ordinal_position"
+    } else {
+        "SELECT name FROM pragma_table_info($1) ORDER BY cid"
+    };
+    sqlx::query_scalar(query).bind(table).fetch_all(pool).await
+}
+
+// Rows are archived in the order they were added; the few tables without an id are keyed by
+// machine and one other column
+fn row_order(table: &str) -> &'static str {
+    match table {
+        "machine_health_scores" => "day",
+        "machine_metrics" => "metric",
+        "machine_display_names" => "locale",
+        _ => "id",
+    }
+}
+
+fn csv_line(fields: impl Iterator<Item = String>) -> String {
+    let mut line = fields.map(|field| export::csv_field(&field)).collect::<Vec<_>>().join(",");
+    line.push('\n');
+    line
+}
+
+// A zip file with everything stored about a machine
+pub struct ArchiveFile {
+    pub file_name: String,
+    pub contents: Vec<u8>,
+    pub sha256: String,
+    // Rows archived from each table
+    pub row_counts: Map<String, Value>,
+}
+
+// Packages a machine's record, data and uploaded document files. Rows are streamed into the
+// compressed archive, so only the archive itself is held in memory.
+pub async fn build(pool: &DbPool, job: &JobContext, machine_id: i64, archived_by: &str) -> anyhow::Result<ArchiveFile> {
+    let machine = sqlx::query("SELECT * FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Machine {} no longer exists", machine_id))?;
+    let mut machine = row_to_json(&machine);
+    // Only a hash is stored, but there is no reason to keep even that
+    if let Some(machine) = machine.as_object_mut() {
+        machine.remove("api_key");
+    }
+
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut row_counts = Map::new();
+
+    let archived = MACHINE_TABLES.iter().filter(|table| table.file.is_some());
+    let table_count = archived.clone().count();
+    for (done, table) in archived.enumerate() {
+        let file = table.file.unwrap_or_default();
+        zip.start_file(file, options)?;
+
+        let query = format!("SELECT * FROM {} WHERE {} ORDER BY {}", table.table, table.condition, row_order(table.table));
+        let csv = file.ends_with(".csv");
+        if csv {
+            let header = column_names(pool, table.table).await?;
+            zip.write_all(csv_line(header.into_iter()).as_bytes())?;
+        } else {
+            zip.write_all(b"[")?;
+        }
+
+        let mut rows = sqlx::query(&query).bind(machine_id).fetch(pool);
+        let mut count: i64 = 0;
+        while let Some(row) = rows.next().await {
+            let row = row?;
+            if csv {
+                let fields = (0..row.columns().len()).map(|index| match column_value(&row, index) {
+                    Value::Null => String::new(),
+                    Value::String(text) => text,
+                    value => value.to_string(),
+                });
+                zip.write_all(csv_line(fields).as_bytes())?;
+            } else {
+                if count > 0 {
+                    zip.write_all(b",")?;
+                }
+                zip.write_all(b"\n")?;
+                serde_json::to_writer(&mut zip, &row_to_json(&row))?;
+            }
+            count += 1;
+        }
+        if !csv {
+            zip.write_all(b"\n]\n")?;
+        }
+        row_counts.insert(table.table.to_string(), Value::from(count));
+        job.set_progress(0.8 * (done + 1) as f64 / table_count as f64).await;
+    }
+
+    // Uploaded files go under documents/, prefixed with the document id to keep names unique
+    let documents: Vec<(i64, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, file_path, file_name FROM machine_documents WHERE machine_id = $1 AND file_path IS NOT NULL"
+    )
+    .bind(machine_id)
+    .fetch_all(pool)
+    .await?;
+    for (id, path, file_name) in documents {
+        match tokio::fs::read(&path).await {
+            Ok(contents) => {
+                let name = file_name.unwrap_or_default().replace(['/', '\\'], "_");
+                zip.start_file(format!("documents/{}-{}", id, name), options)?;
+                zip.write_all(&contents)?;
+            },
+            Err(e) => tracing::warn!("Document file {} of machine {} not archived: {}", path, machine_id, e),
+        }
+    }
+
+    let archived_at = current_timestamp();
+    let plant_name = site::plant_name(pool).await?;
+    zip.start_file("manifest.json", options)?;
+    serde_json::to_writer_pretty(&mut zip, &json!({
+        "plant_name": plant_name,
+        "machine": machine,
+        "archived_at": archived_at,
+        "archived_by": archived_by,
+        "row_counts": row_counts,
+    }))?;
+
+    let contents = zip.finish()?.into_inner();
+    Ok(ArchiveFile {
+        file_name: format!("{}-machine-{}-archive-{}.zip", site::slug(&plant_name), machine_id, archived_at),
+        sha256: hex::encode(Sha256::digest(&contents)),
+        contents,
+        row_counts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::{ArtifactStore, Jobs};
+    use std::{io::Read, time::Duration};
+
+    // Builds the archive inside a job, which is the only way to get a JobContext
+    async fn build_in_job(pool: &DbPool, machine_id: i64) -> anyhow::Result<ArchiveFile> {
+        let artifacts = ArtifactStore::new(std::env::temp_dir(), Duration::from_secs(60), b"key".to_vec());
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let job_pool = pool.clone();
+        Jobs::new(pool.clone(), 1, artifacts)
+            .enqueue("machine_archive", "boss", async move |job| {
+                let _ = sender.send(build(&job_pool, &job, machine_id, "boss").await);
+                Ok(Value::Null)
+            })
+            .await
+            .unwrap();
+        receiver.await.unwrap()
+    }
+
+    fn read(archive: &ArchiveFile, file: &str) -> String {
+        let mut zip = zip::ZipArchive::new(Cursor::new(&archive.contents)).unwrap();
+        let mut contents = String::new();
+        zip.by_name(file).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[tokio::test]
+    async fn packages_the_machine_and_its_data_without_the_key() {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key1'), (2, 'Line 2', 'L2', 'key2')",
+            "INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES \
+             (1, 10.5, 'running, fast', 100), (1, 0.0, NULL, 200), (2, 5.0, 'running', 100)",
+            "INSERT INTO work_orders (machine_id, title, created_by) VALUES (1, 'Replace belt', 'boss')",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        let archive = build_in_job(&pool, 1).await.unwrap();
+        assert!(archive.file_name.starts_with("scada-machine-1-archive-"), "{}", archive.file_name);
+        assert_eq!(archive.sha256, hex::encode(Sha256::digest(&archive.contents)));
+        assert_eq!(archive.row_counts["speed_history"], 2);
+        assert_eq!(archive.row_counts["alarms"], 0);
+
+        let history: Vec<String> = read(&archive, "speed_history.csv").lines().map(str::to_string).collect();
+        assert_eq!(history.len(), 3);
+        assert!(history[0].starts_with("id,machine_id,speed"), "{}", history[0]);
+        assert!(history[1].contains(",10.5,\"running, fast\",100"), "{}", history[1]);
+        let work_orders: Value = serde_json::from_str(&read(&archive, "work_orders.json")).unwrap();
+        assert_eq!(work_orders[0]["title"], "Replace belt");
+        let manifest: Value = serde_json::from_str(&read(&archive, "manifest.json")).unwrap();
+        assert_eq!(manifest["machine"]["code"], "L1");
+        assert_eq!(manifest["archived_by"], "boss");
+        assert!(manifest["machine"].get("api_key").is_none());
+
+        assert!(build_in_job(&pool, 3).await.is_err());
+    }
+}
@@ -1,59 +1,1327 @@
-use crate::database::DbPool;
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts, Query, Request},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{
+    config::Config,
+    database::{current_timestamp, DbPool},
+    display_tokens::DisplayPrincipal,
+    models::{ErrorResponse, MACHINE_KEY_SCOPES},
+    network,
+    password_policy::PasswordPolicy,
+    service_accounts::ServicePrincipal,
+    signing::SignedMachine,
+};
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    net::SocketAddr,
+    ops::Deref,
+    str::FromStr,
+    sync::{Arc, LazyLock, OnceLock, RwLock},
+    time::Duration,
+};
 use uuid::Uuid;
 use sqlx::Row;
 
-#[derive(Debug, Clone)]
-pub enum AuthResult {
-    Admin,
-    User(String), // username
-    Machine(i64), // machine_id
+// Signing key and token lifetimes for user sessions, set once at startup
+struct SessionKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    access_ttl: Duration,
+    refresh_ttl: Duration,
 }
 
-pub async fn validate_token(token: &str, pool: &DbPool) -> Option<AuthResult> {
-    // Check hardcoded admin token
-    if token == "admin_token_12345" {
-        return Some(AuthResult::Admin);
-    }
-    
-    // Check if it's a machine API key
-    if token.starts_with("machine_") {
-        if let Ok(row) = sqlx::query("SELECT id FROM machines WHERE api_key = ?")
-            .bind(token)
-            .fetch_one(pool)
-            .await
-        {
-            let machine_id: i64 = row.get("id");
-            return Some(AuthResult::Machine(machine_id));
+static SESSION_KEYS: OnceLock<SessionKeys> = OnceLock::new();
+
+// Access tokens invalidated before their expiry. Kept in memory so validating a token stays
+// free of database lookups, and persisted so revocations survive a restart.
+#[derive(Default)]
+struct Revocations {
+    // jti -> expiry of a logged out token; dropped once the token would have expired anyway
+    tokens: HashMap<String, i64>,
+    // username -> cutoff; tokens the user was issued at or before it are rejected
+    users: HashMap<String, i64>,
+    // session id -> when its last access token expires; every token of the session is rejected
+    sessions: HashMap<String, i64>,
+}
+
+static REVOCATIONS: LazyLock<RwLock<Revocations>> = LazyLock::new(Default::default);
+
+// Claims carried by a session token, so requests are authorized without a database lookup
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionClaims {
+    sub: String, // username
+    role: String,
+    jti: String,
+    // Session the token belongs to; tokens issued before sessions were tracked have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sid: Option<String>,
+    // Admin acting as the subject, on impersonation tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    act: Option<String>,
+    iat: i64,
+    exp: i64,
+}
+
+pub fn init_sessions(secret: &[u8], access_ttl: Duration, refresh_ttl: Duration) {
+    let _ = SESSION_KEYS.set(SessionKeys {
+        encoding: EncodingKey::from_secret(secret),
+        decoding: DecodingKey::from_secret(secret),
+        access_ttl,
+        refresh_ttl,
+    });
+}
+
+fn session_keys() -> &'static SessionKeys {
+    SESSION_KEYS.get().expect("auth::init_sessions must run at startup")
+}
+
+// Signs a short-lived HS256 access token for a user; returns the token and its expiry
+pub fn issue_session_token(username: &str, role: &str, session_id: Option<&str>) -> jsonwebtoken::errors::Result<(String, i64)> {
+    let keys = session_keys();
+    let now = current_timestamp();
+    // A session started in the same second its user's tokens were revoked (as when a
+    // password change hands out a new one) must still outlive the cutoff
+    let cutoff = REVOCATIONS.read().unwrap().users.get(username).copied();
+    let claims = SessionClaims {
+        sub: username.to_string(),
+        role: role.to_string(),
+        jti: Uuid::new_v4().simple().to_string(),
+        sid: session_id.map(str::to_string),
+        act: None,
+        iat: cutoff.map_or(now, |cutoff| now.max(cutoff + 1)),
+        exp: now + keys.access_ttl.as_secs() as i64,
+    };
+    let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &keys.encoding)?;
+    Ok((token, claims.exp))
+}
+
+// Signs an access token that acts as a user on behalf of an admin. It belongs to no session
+// and cannot be refreshed, and ends with the user's other tokens when those are revoked.
+pub fn issue_impersonation_token(
+    username: &str,
+    role: &str,
+    admin: &str,
+    ttl: Duration,
+) -> jsonwebtoken::errors::Result<(String, i64)> {
+    let now = current_timestamp();
+    let claims = SessionClaims {
+        sub: username.to_string(),
+        role: role.to_string(),
+        jti: Uuid::new_v4().simple().to_string(),
+        sid: None,
+        act: Some(admin.to_string()),
+        iat: now,
+        exp: now + ttl.as_secs() as i64,
+    };
+    let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &session_keys().encoding)?;
+    Ok((token, claims.exp))
+}
+
+fn decode_session_token(token: &str) -> Option<SessionClaims> {
+    jsonwebtoken::decode::<SessionClaims>(token, &session_keys().decoding, &Validation::new(Algorithm::HS256))
+        .ok()
+        .map(|data| data.claims)
+}
+
+// User a session token was issued to, whether or not it has been revoked since
+pub fn session_subject(token: &str) -> Option<String> {
+    decode_session_token(token).map(|claims| claims.sub)
+}
+
+fn is_revoked(claims: &SessionClaims) -> bool {
+    let revocations = REVOCATIONS.read().unwrap();
+    revocations.tokens.contains_key(&claims.jti)
+        || claims.sid.as_ref().is_some_and(|sid| revocations.sessions.contains_key(sid))
+        || revocations.users.get(&claims.sub).is_some_and(|cutoff| claims.iat <= *cutoff)
+}
+
+// Loads persisted revocations into memory; called once at startup
+pub async fn load_revocations(pool: &DbPool) -> sqlx::Result<()> {
+    let now = current_timestamp();
+    sqlx::query("DELETE FROM revoked_tokens WHERE expires_at < $1")
+        .bind(now)
+        .execute(pool)
+        .await?;
+    let tokens: Vec<(String, i64)> = sqlx::query_as("SELECT jti, expires_at FROM revoked_tokens")
+        .fetch_all(pool)
+        .await?;
+    let users: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT username, tokens_revoked_at FROM users WHERE tokens_revoked_at IS NOT NULL"
+    )
+    .fetch_all(pool)
+    .await?;
+    // Sessions ended so recently that some of their access tokens may still be unexpired
+    let access_ttl = session_keys().access_ttl.as_secs() as i64;
+    let sessions: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT id, revoked_at + $1 FROM sessions WHERE revoked_at IS NOT NULL AND revoked_at + $2 >= $3"
+    )
+    .bind(access_ttl)
+    .bind(access_ttl)
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+
+    let mut revocations = REVOCATIONS.write().unwrap();
+    revocations.tokens = tokens.into_iter().collect();
+    revocations.users = users.into_iter().collect();
+    revocations.sessions = sessions.into_iter().collect();
+    Ok(())
+}
+
+// Where a session was started or last refreshed from; the address is the client's as given by
+// a trusted proxy, like the one login rate limiting goes by
+#[derive(Debug, Clone, Default)]
+pub struct SessionClient {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl SessionClient {
+    pub fn new(headers: &HeaderMap, addr: SocketAddr, config: &Config) -> Self {
+        Self {
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|agent| agent.chars().take(256).collect()),
+            ip: Some(network::client_ip(headers, addr.ip(), &config.trusted_proxies).to_string()),
         }
     }
-    
-    // Check user tokens
-    if let Ok(row) = sqlx::query("SELECT username FROM users WHERE token = ?")
-        .bind(token)
+}
+
+// Records a new session for a user and returns its id
+pub async fn start_session(pool: &DbPool, username: &str, client: &SessionClient) -> sqlx::Result<String> {
+    let id = Uuid::new_v4().simple().to_string();
+    let now = current_timestamp();
+    // An expired session has no usable token left, so it is only history
+    sqlx::query("DELETE FROM sessions WHERE expires_at < $1")
+        .bind(now)
+        .execute(pool)
+        .await?;
+    sqlx::query(
+        "INSERT INTO sessions (id, username, user_agent, ip, created_at, last_seen_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7)"
+    )
+    .bind(&id)
+    .bind(username)
+    .bind(&client.user_agent)
+    .bind(&client.ip)
+    .bind(now)
+    .bind(now)
+    .bind(now + session_keys().refresh_ttl.as_secs() as i64)
+    .execute(pool)
+    .await?;
+    Ok(id)
+}
+
+// Ends a session: its access tokens stop working at once and its refresh token is revoked.
+// Returns false if the session was already ended.
+pub async fn revoke_session(pool: &DbPool, session_id: &str) -> sqlx::Result<bool> {
+    let now = current_timestamp();
+
+    // Memory first, so the tokens stop working even if persisting fails
+    {
+        let mut revocations = REVOCATIONS.write().unwrap();
+        revocations.sessions.retain(|_, expires_at| *expires_at >= now);
+        revocations.sessions.insert(session_id.to_string(), now + session_keys().access_ttl.as_secs() as i64);
+    }
+    let revoked = sqlx::query("UPDATE sessions SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL")
+        .bind(now)
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = $1 WHERE session_id = $2 AND revoked_at IS NULL")
+        .bind(now)
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+    Ok(revoked.rows_affected() > 0)
+}
+
+// Ends the session an access token belongs to. Tokens from before sessions were tracked
+// only end along with the refresh token issued with them if the client passes that too.
+// Returns false if the access token is not a valid session.
+pub async fn logout(pool: &DbPool, token: &str, refresh_token: Option<&str>) -> sqlx::Result<bool> {
+    let Some(claims) = decode_session_token(token).filter(|claims| !is_revoked(claims)) else {
+        return Ok(false);
+    };
+    if let Some(session_id) = &claims.sid {
+        revoke_session(pool, session_id).await?;
+        return Ok(true);
+    }
+    let now = current_timestamp();
+
+    // Memory first, so the token stops working even if persisting fails
+    {
+        let mut revocations = REVOCATIONS.write().unwrap();
+        revocations.tokens.retain(|_, expires_at| *expires_at >= now);
+        revocations.tokens.insert(claims.jti.clone(), claims.exp);
+    }
+    sqlx::query("DELETE FROM revoked_tokens WHERE expires_at < $1")
+        .bind(now)
+        .execute(pool)
+        .await?;
+    sqlx::query("INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(&claims.jti)
+        .bind(claims.exp)
+        .execute(pool)
+        .await?;
+
+    if let Some(refresh_token) = refresh_token {
+        sqlx::query("UPDATE refresh_tokens SET revoked_at = $1 WHERE token_hash = $2 AND username = $3 AND revoked_at IS NULL")
+            .bind(now)
+            .bind(hash_token(refresh_token))
+            .bind(&claims.sub)
+            .execute(pool)
+            .await?;
+    }
+    Ok(true)
+}
+
+// Invalidates every access and refresh token a user currently holds
+pub async fn revoke_user_tokens(pool: &DbPool, username: &str) -> sqlx::Result<()> {
+    let now = current_timestamp();
+    REVOCATIONS.write().unwrap().users.insert(username.to_string(), now);
+
+    sqlx::query("UPDATE users SET tokens_revoked_at = $1 WHERE username = $2")
+        .bind(now)
+        .bind(username)
+        .execute(pool)
+        .await?;
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = $1 WHERE username = $2 AND revoked_at IS NULL")
+        .bind(now)
+        .bind(username)
+        .execute(pool)
+        .await?;
+    sqlx::query("UPDATE sessions SET revoked_at = $1 WHERE username = $2 AND revoked_at IS NULL")
+        .bind(now)
+        .bind(username)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// Outcome of presenting a refresh token
+pub enum RefreshOutcome {
+    // The token was valid and has been replaced: (username, session id, new refresh token, its expiry)
+    Rotated(String, String, String, i64),
+    // Unknown, expired or already used
+    Rejected,
+}
+
+// Machine API keys, refresh tokens and password reset tokens are stored hashed, so a copy
+// of the database holds no usable credentials. They are random and long, so a fast hash
+// is enough and keeps lookups indexed.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// Stores a new refresh token for a session, extending the session to the token's expiry;
+// returns the token and its expiry
+pub async fn issue_refresh_token(pool: &DbPool, username: &str, session_id: &str) -> sqlx::Result<(String, i64)> {
+    let token = format!("refresh_{}", Uuid::new_v4().simple());
+    let now = current_timestamp();
+    let expires_at = now + session_keys().refresh_ttl.as_secs() as i64;
+
+    sqlx::query("DELETE FROM refresh_tokens WHERE expires_at < $1")
+        .bind(now)
+        .execute(pool)
+        .await?;
+    sqlx::query(
+        "INSERT INTO refresh_tokens (token_hash, username, session_id, created_at, expires_at) VALUES ($1, $2, $3, $4, $5)"
+    )
+    .bind(hash_token(&token))
+    .bind(username)
+    .bind(session_id)
+    .bind(now)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+    sqlx::query("UPDATE sessions SET expires_at = $1 WHERE id = $2")
+        .bind(expires_at)
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+    Ok((token, expires_at))
+}
+
+// id, username, session_id, expires_at, revoked_at, replaced_by
+type RefreshTokenRow = (i64, String, Option<String>, i64, Option<i64>, Option<i64>);
+
+// Exchanges a refresh token for a new one and records where the session was seen. Each
+// token works once: presenting a token that was already rotated means it leaked, so every
+// token and session of that user is revoked.
+pub async fn rotate_refresh_token(pool: &DbPool, token: &str, client: &SessionClient) -> sqlx::Result<RefreshOutcome> {
+    let now = current_timestamp();
+    let row: Option<RefreshTokenRow> = sqlx::query_as(
+        "SELECT id, username, session_id, expires_at, revoked_at, replaced_by FROM refresh_tokens WHERE token_hash = $1"
+    )
+    .bind(hash_token(token))
+    .fetch_optional(pool)
+    .await?;
+
+    let Some((id, username, session_id, expires_at, revoked_at, replaced_by)) = row else {
+        return Ok(RefreshOutcome::Rejected);
+    };
+    if revoked_at.is_some() && replaced_by.is_some() {
+        tracing::warn!("Reused refresh token for user {}, revoking all of their sessions", username);
+        // Access tokens issued from the stolen token end too, not only the refresh tokens
+        revoke_user_tokens(pool, &username).await?;
+        return Ok(RefreshOutcome::Rejected);
+    }
+    if revoked_at.is_some() || expires_at < now {
+        return Ok(RefreshOutcome::Rejected);
+    }
+
+    // Claim the token first so two concurrent refreshes cannot both succeed
+    let claimed = sqlx::query("UPDATE refresh_tokens SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL")
+        .bind(now)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    if claimed.rows_affected() == 0 {
+        return Ok(RefreshOutcome::Rejected);
+    }
+
+    // Refresh tokens from before sessions were tracked start one now
+    let session_id = match session_id {
+        Some(session_id) => {
+            sqlx::query(
+                "UPDATE sessions SET last_seen_at = $1, ip = COALESCE($2, ip), user_agent = COALESCE($3, user_agent) WHERE id = $4"
+            )
+            .bind(now)
+            .bind(&client.ip)
+            .bind(&client.user_agent)
+            .bind(&session_id)
+            .execute(pool)
+            .await?;
+            session_id
+        },
+        None => start_session(pool, &username, client).await?,
+    };
+    let (new_token, new_expires_at) = issue_refresh_token(pool, &username, &session_id).await?;
+    sqlx::query("UPDATE refresh_tokens SET replaced_by = (SELECT id FROM refresh_tokens WHERE token_hash = $1) WHERE id = $2")
+        .bind(hash_token(&new_token))
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(RefreshOutcome::Rotated(username, session_id, new_token, new_expires_at))
+}
+
+// Stores a single-use password reset token for a user and returns it. Earlier unused
+// tokens of the user stop working, so only the latest email is valid.
+pub async fn issue_password_reset_token(pool: &DbPool, username: &str, ttl: Duration) -> sqlx::Result<String> {
+    let token = format!("reset_{}", Uuid::new_v4().simple());
+    let now = current_timestamp();
+
+    sqlx::query("DELETE FROM password_reset_tokens WHERE expires_at < $1 OR (username = $2 AND used_at IS NULL)")
+        .bind(now)
+        .bind(username)
+        .execute(pool)
+        .await?;
+    sqlx::query("INSERT INTO password_reset_tokens (token_hash, username, created_at, expires_at) VALUES ($1, $2, $3, $4)")
+        .bind(hash_token(&token))
+        .bind(username)
+        .bind(now)
+        .bind(now + ttl.as_secs() as i64)
+        .execute(pool)
+        .await?;
+    Ok(token)
+}
+
+// The user a password reset token was issued to, without using it up; None if the token is
+// unknown, expired or already used
+pub async fn password_reset_token_user(pool: &DbPool, token: &str) -> sqlx::Result<Option<String>> {
+    sqlx::query_scalar(
+        "SELECT username FROM password_reset_tokens WHERE token_hash = $1 AND used_at IS NULL AND expires_at > $2"
+    )
+    .bind(hash_token(token))
+    .bind(current_timestamp())
+    .fetch_optional(pool)
+    .await
+}
+
+// Uses up a password reset token; returns the user it was issued to, or None if the token
+// is unknown, expired or already used
+pub async fn redeem_password_reset_token(pool: &DbPool, token: &str) -> sqlx::Result<Option<String>> {
+    let now = current_timestamp();
+    sqlx::query_scalar(
+        "UPDATE password_reset_tokens SET used_at = $1 \
+         WHERE token_hash = $2 AND used_at IS NULL AND expires_at > $3 RETURNING username"
+    )
+    .bind(now)
+    .bind(hash_token(token))
+    .bind(now)
+    .fetch_optional(pool)
+    .await
+}
+
+// The machine and scopes of a machine API key. The key created with the machine carries every
+// scope; additional keys carry the scopes they were issued with until revoked or, for a key
+// replaced by a rotation, until its grace period ends. Keys of archived machines are refused.
+pub async fn validate_machine_key(token: &str, pool: &DbPool) -> Option<(i64, Vec<String>)> {
+    if !token.starts_with("machine_") {
+        return None;
+    }
+    let key_hash = hash_token(token);
+    if let Ok(row) = sqlx::query("SELECT id FROM machines WHERE api_key = $1 AND archived_at IS NULL")
+        .bind(&key_hash)
         .fetch_one(pool)
         .await
     {
-        let username: String = row.get("username");
-        return Some(AuthResult::User(username));
+        let scopes = MACHINE_KEY_SCOPES.iter().map(|scope| scope.to_string()).collect();
+        return Some((row.get("id"), scopes));
+    }
+    let (id, scopes) = sqlx::query_as::<_, (i64, String)>(
+        "SELECT machine_id, scopes FROM machine_api_keys \
+         WHERE api_key = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > $2) \
+         AND machine_id IN (SELECT id FROM machines WHERE archived_at IS NULL)"
+    )
+    .bind(&key_hash)
+    .bind(current_timestamp())
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()?;
+    Some((id, scopes.split(',').filter(|scope| !scope.is_empty()).map(str::to_string).collect()))
+}
+
+// Helper function to extract token from headers
+pub fn extract_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer "))
+        .map(|s| s.to_string())
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: String,
+}
+
+// Lets a route take the token from the `token` query parameter when the request has no
+// Authorization header, for browser EventSource clients that cannot set headers
+pub async fn token_from_query(mut request: Request, next: Next) -> Response {
+    if !request.headers().contains_key(header::AUTHORIZATION)
+        && let Ok(Query(TokenQuery { token })) = Query::<TokenQuery>::try_from_uri(request.uri())
+        && let Ok(value) = HeaderValue::from_str(&format!("Bearer {}", token))
+    {
+        request.headers_mut().insert(header::AUTHORIZATION, value);
+    }
+    next.run(request).await
+}
+
+// User roles, ordered by privilege: each role may do everything the ones below it can
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Technician,
+    Manager,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Technician => "technician",
+            Self::Manager => "manager",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "technician" => Ok(Self::Technician),
+            "manager" => Ok(Self::Manager),
+            "admin" => Ok(Self::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+type AuthRejection = (StatusCode, Json<ErrorResponse>);
+
+fn unauthorized(error: &str) -> AuthRejection {
+    (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: error.to_string() }))
+}
+
+// The signed-in user behind a request. Taking it as a handler argument rejects requests
+// without a valid session token; machine API keys are not users and are rejected too.
+// Service accounts pass as managers named "service:<name>", but only reach the endpoints
+// their scopes open. Display tokens pass as technicians named "display:<name>", seeing the
+// machines of their kiosk group or all of them, and only reach the display endpoints.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub username: String,
+    pub role: Role,
+    // Session the request's token belongs to, if it was issued with one
+    pub session_id: Option<String>,
+    // Admin behind the request when it uses an impersonation token
+    pub impersonated_by: Option<String>,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(service) = parts.extensions.get::<ServicePrincipal>() {
+            return Ok(AuthUser {
+                username: service.username(),
+                role: Role::Manager,
+                session_id: None,
+                impersonated_by: None,
+            });
+        }
+        if let Some(display) = parts.extensions.get::<DisplayPrincipal>() {
+            return Ok(AuthUser {
+                username: display.username(),
+                role: Role::Technician,
+                session_id: None,
+                impersonated_by: None,
+            });
+        }
+        let token = extract_token(&parts.headers).ok_or_else(|| unauthorized("Missing token"))?;
+        let claims = decode_session_token(&token)
+            .filter(|claims| !is_revoked(claims))
+            .ok_or_else(|| unauthorized("Invalid token"))?;
+        let role = claims.role.parse().map_err(|_| unauthorized("Invalid token"))?;
+        // Every request made while impersonating is attributed to the admin in the logs
+        if let Some(admin) = &claims.act {
+            tracing::Span::current().record("impersonated_by", admin.as_str());
+            tracing::info!("{} acting as {} by impersonation", admin, claims.sub);
+        }
+        Ok(AuthUser { username: claims.sub, role, session_id: claims.sid, impersonated_by: claims.act })
+    }
+}
+
+// Route policies for RequireRole, named after the least privileged role they admit. Routes
+// open to every role, technicians included, take AuthUser instead.
+pub mod roles {
+    use super::Role;
+
+    pub trait Policy {
+        const MINIMUM: Role;
+    }
+
+    #[derive(Debug)]
+    pub struct Admin;
+
+    #[derive(Debug)]
+    pub struct Manager;
+
+    impl Policy for Admin {
+        const MINIMUM: Role = Role::Admin;
+    }
+
+    impl Policy for Manager {
+        const MINIMUM: Role = Role::Manager;
+    }
+}
+
+// A signed-in user holding at least the role of policy `R`, e.g. `RequireRole<roles::Admin>`.
+// Users below it get 403 Forbidden.
+#[derive(Debug)]
+pub struct RequireRole<R> {
+    pub user: AuthUser,
+    policy: PhantomData<R>,
+}
+
+impl<R> Deref for RequireRole<R> {
+    type Target = AuthUser;
+
+    fn deref(&self) -> &AuthUser {
+        &self.user
+    }
+}
+
+impl<S: Send + Sync, R: roles::Policy> FromRequestParts<S> for RequireRole<R> {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        if user.role < R::MINIMUM {
+            let mut required = R::MINIMUM.as_str().to_string();
+            required[..1].make_ascii_uppercase();
+            return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+                error: format!("{} access required", required),
+            })));
+        }
+        Ok(RequireRole { user, policy: PhantomData })
+    }
+}
+
+// Scopes a machine API key can be limited to, for MachineKey
+pub mod scopes {
+    pub trait Scope {
+        const NAME: &'static str;
+    }
+
+    // Speed and metric updates
+    #[derive(Debug)]
+    pub struct TelemetryWrite;
+
+    // Receiving commands and reporting their outcome
+    #[derive(Debug)]
+    pub struct CommandsRead;
+
+    // The machine's own configuration
+    #[derive(Debug)]
+    pub struct ConfigRead;
+
+    impl Scope for TelemetryWrite {
+        const NAME: &'static str = "telemetry:write";
+    }
+
+    impl Scope for CommandsRead {
+        const NAME: &'static str = "commands:read";
+    }
+
+    impl Scope for ConfigRead {
+        const NAME: &'static str = "config:read";
+    }
+}
+
+// A machine authenticated by an API key holding scope `S`, e.g.
+// `MachineKey<scopes::TelemetryWrite>`. Keys without the scope, and requests from outside
+// the machine's allowed networks, get 403 Forbidden.
+#[derive(Debug)]
+pub struct MachineKey<S> {
+    pub machine_id: i64,
+    scope: PhantomData<S>,
+}
+
+impl<S, P> FromRequestParts<S> for MachineKey<P>
+where
+    S: Send + Sync,
+    P: scopes::Scope,
+    DbPool: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+{
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // A request signed with one of the machine's keys was verified by the signing middleware
+        let (id, scopes) = match parts.extensions.get::<SignedMachine>() {
+            Some(signed) => (signed.machine_id, signed.scopes.clone()),
+            None => {
+                let token = extract_token(&parts.headers).ok_or_else(|| unauthorized("Missing token"))?;
+                let Some((id, scopes)) = validate_machine_key(&token, &DbPool::from_ref(state)).await else {
+                    return Err(unauthorized("Invalid machine API key"));
+                };
+                (id, scopes)
+            },
+        };
+        tracing::Span::current().record("machine_id", id);
+        if !scopes.iter().any(|scope| scope == P::NAME) {
+            tracing::warn!("Machine ID {} used an API key without the {} scope", id, P::NAME);
+            return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+                error: format!("API key lacks the {} scope", P::NAME),
+            })));
+        }
+
+        let allowed_cidrs: Option<String> = sqlx::query_scalar("SELECT allowed_cidrs FROM machines WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&DbPool::from_ref(state))
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })))?
+            .flatten();
+        if let Some(allowed_cidrs) = allowed_cidrs {
+            let config = Arc::<Config>::from_ref(state);
+            let source = parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(peer)| network::client_ip(&parts.headers, peer.ip(), &config.trusted_proxies));
+            if !source.is_some_and(|source| network::allows(&allowed_cidrs, source)) {
+                tracing::warn!("Machine ID {} called from {:?}, outside its allowed networks", id, source);
+                return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+                    error: "Source address not allowed for this machine".to_string(),
+                })));
+            }
+        }
+        Ok(MachineKey { machine_id: id, scope: PhantomData })
     }
-    
-    None
 }
 
 pub fn generate_machine_api_key() -> String {
     format!("machine_{}", Uuid::new_v4().simple())
 }
 
-pub fn generate_user_token() -> String {
-    format!("user_{}", Uuid::new_v4().simple())
+// Databases from older releases hold machine API keys in plaintext and may still carry
+// session tokens in users.token. Keys are replaced by their hash, so devices keep working;
+// the unused session tokens are dropped. Returns how many keys were hashed.
+pub async fn hash_plaintext_keys(pool: &DbPool) -> sqlx::Result<u64> {
+    let mut hashed = 0;
+    for table in ["machines", "machine_api_keys"] {
+        let keys: Vec<(i64, String)> = sqlx::query_as(&format!(
+            "SELECT id, api_key FROM {} WHERE api_key LIKE 'machine\\_%' ESCAPE '\\'",
+            table
+        ))
+        .fetch_all(pool)
+        .await?;
+        for (id, key) in keys {
+            sqlx::query(&format!("UPDATE {} SET api_key = $1 WHERE id = $2", table))
+                .bind(hash_token(&key))
+                .bind(id)
+                .execute(pool)
+                .await?;
+            hashed += 1;
+        }
+    }
+    sqlx::query("UPDATE users SET token = NULL WHERE token IS NOT NULL").execute(pool).await?;
+    Ok(hashed)
+}
+
+// Argon2id PHC string for storing in users.password. Hashing is deliberately slow, so it
+// runs on the blocking pool.
+pub async fn hash_password(password: &str) -> anyhow::Result<String> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))
+    })
+    .await?
 }
 
+// Accounts created before hashing was introduced still hold the plaintext password
+fn is_hashed(stored: &str) -> bool {
+    stored.starts_with("$argon2")
+}
+
+async fn verify_password(password: &str, stored: &str) -> bool {
+    if !is_hashed(stored) {
+        return password == stored;
+    }
+
+    let password = password.to_string();
+    let stored = stored.to_string();
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&stored)
+            .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+    })
+    .await
+    .unwrap_or(false)
+}
+
+// Checked against when the username is unknown, so that a miss costs as much as a wrong
+// password and the response time does not tell which usernames exist
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$aAfj1gl4w4NzTNhJ/9zI8w$c3JCSmNnGyM5KL1YXk70ZEerRwj+Ak0I3XK5PhrTpzY";
+
 pub async fn authenticate_user(username: &str, password: &str, pool: &DbPool) -> Option<crate::models::User> {
-    sqlx::query_as::<_, crate::models::User>("SELECT * FROM users WHERE username = ? AND password = ?")
+    let row = sqlx::query("SELECT password FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    let Some(row) = row else {
+        verify_password(password, DUMMY_PASSWORD_HASH).await;
+        return None;
+    };
+    let stored: String = row.get("password");
+    if !verify_password(password, &stored).await {
+        return None;
+    }
+
+    // Legacy plaintext row: replace it with a hash now that we know the password
+    if !is_hashed(&stored) {
+        match hash_password(password).await {
+            Ok(hash) => {
+                if sqlx::query("UPDATE users SET password = $1 WHERE username = $2 AND password = $3")
+                    .bind(&hash)
+                    .bind(username)
+                    .bind(&stored)
+                    .execute(pool)
+                    .await
+                    .is_ok()
+                {
+                    tracing::info!("Re-hashed legacy plaintext password for user: {}", username);
+                }
+            },
+            Err(e) => tracing::error!("{}", e),
+        }
+    }
+
+    sqlx::query_as::<_, crate::models::User>("SELECT * FROM users WHERE username = $1")
         .bind(username)
-        .bind(password)
         .fetch_optional(pool)
         .await
         .ok()
         .flatten()
-}
\ No newline at end of file
+}
+
+// Password older releases seeded the admin account with
+const LEGACY_ADMIN_PASSWORD: &str = "admin123";
+
+// Creates the first admin account on a database without one. The password comes from
+// SCADA_ADMIN_PASSWORD, which must meet the password policy, or is generated and printed
+// once to stderr, where it stays out of the exported logs.
+pub async fn bootstrap_admin(pool: &DbPool, username: &str, password: Option<&str>, policy: &PasswordPolicy) -> anyhow::Result<()> {
+    let admins: Vec<(String, String)> = sqlx::query_as("SELECT username, password FROM users WHERE role = 'admin'")
+        .fetch_all(pool)
+        .await?;
+    if !admins.is_empty() {
+        for (admin, stored) in admins {
+            if verify_password(LEGACY_ADMIN_PASSWORD, &stored).await {
+                tracing::warn!("Admin user {} still has the default password, change it now", admin);
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(password) = password
+        && let Err(violations) = policy.check(password, username)
+    {
+        let rules: Vec<String> = violations.into_iter().map(|violation| violation.message).collect();
+        anyhow::bail!("SCADA_ADMIN_PASSWORD does not meet the password policy: {}", rules.join("; "));
+    }
+
+    let generated = password.is_none();
+    let password = password.map(str::to_string).unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+    sqlx::query("INSERT INTO users (username, password, role, password_changed_at) VALUES ($1, $2, 'admin', $3)")
+        .bind(username)
+        .bind(hash_password(&password).await?)
+        .bind(current_timestamp())
+        .execute(pool)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to create admin user {}: {}", username, e))?;
+
+    if generated {
+        eprintln!("Created admin user '{}' with password: {}", username, password);
+        eprintln!("This password is shown only once; log in and change it.");
+    }
+    tracing::info!("Created admin user: {}", username);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    fn session(username: &str, role: &str) -> String {
+        init_sessions(b"test secret", Duration::from_secs(60), Duration::from_secs(60));
+        issue_session_token(username, role, None).unwrap().0
+    }
+
+    fn parts(token: Option<&str>) -> Parts {
+        let mut request = Request::builder();
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        request.body(()).unwrap().into_parts().0
+    }
+
+    async fn extract<T: FromRequestParts<(), Rejection = AuthRejection>>(token: Option<&str>) -> Result<T, (StatusCode, String)> {
+        T::from_request_parts(&mut parts(token), &())
+            .await
+            .map_err(|(status, Json(body))| (status, body.error))
+    }
+
+    #[derive(Clone)]
+    struct MachineState {
+        pool: DbPool,
+        config: Arc<Config>,
+    }
+
+    impl FromRef<MachineState> for DbPool {
+        fn from_ref(state: &MachineState) -> Self {
+            state.pool.clone()
+        }
+    }
+
+    impl FromRef<MachineState> for Arc<Config> {
+        fn from_ref(state: &MachineState) -> Self {
+            state.config.clone()
+        }
+    }
+
+    // Machine 1 with key "machine_line1", reachable only from 10.20.0.0/16, and machine 2 with
+    // key "machine_line2", an additional config:read key "machine_reader" and no restriction
+    async fn machines() -> MachineState {
+        let pool = crate::database::test_database().await;
+        sqlx::query(
+            "INSERT INTO machines (id, name, code, api_key, allowed_cidrs) \
+             VALUES (1, 'Line 1', 'L1', $1, '10.20.0.0/16'), (2, 'Line 2', 'L2', $2, NULL)"
+        )
+        .bind(hash_token("machine_line1"))
+        .bind(hash_token("machine_line2"))
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO machine_api_keys (machine_id, name, api_key, scopes, created_at) VALUES (2, 'reader', $1, 'config:read', 0)"
+        )
+        .bind(hash_token("machine_reader"))
+        .execute(&pool)
+        .await
+        .unwrap();
+        MachineState { pool, config: Arc::new(Config::from_env().unwrap()) }
+    }
+
+    async fn machine_key<P: scopes::Scope>(state: &MachineState, key: &str, peer: &str) -> Result<i64, StatusCode> {
+        let mut parts = parts(Some(key));
+        parts.extensions.insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+        MachineKey::<P>::from_request_parts(&mut parts, state)
+            .await
+            .map(|key| key.machine_id)
+            .map_err(|(status, _)| status)
+    }
+
+    #[test]
+    fn roles_are_ordered_by_privilege() {
+        assert!(Role::Admin > Role::Manager);
+        assert!(Role::Manager > Role::Technician);
+        for role in [Role::Technician, Role::Manager, Role::Admin] {
+            assert_eq!(role.as_str().parse(), Ok(role));
+        }
+        assert!("operator".parse::<Role>().is_err());
+    }
+
+    #[tokio::test]
+    async fn auth_user_requires_a_valid_session() {
+        let token = session("tech1", "technician");
+        let user = extract::<AuthUser>(Some(&token)).await.unwrap();
+        assert_eq!(user.username, "tech1");
+        assert_eq!(user.role, Role::Technician);
+
+        assert_eq!(extract::<AuthUser>(None).await.unwrap_err(), (StatusCode::UNAUTHORIZED, "Missing token".to_string()));
+        assert_eq!(extract::<AuthUser>(Some("not-a-jwt")).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+        assert_eq!(extract::<AuthUser>(Some("machine_0123")).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn auth_user_rejects_unknown_roles_and_revoked_sessions() {
+        let token = session("legacy", "operator");
+        assert_eq!(extract::<AuthUser>(Some(&token)).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+
+        let token = session("revoked", "admin");
+        REVOCATIONS.write().unwrap().users.insert("revoked".to_string(), current_timestamp());
+        assert_eq!(extract::<AuthUser>(Some(&token)).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn require_role_admits_the_policy_role_and_above() {
+        let admin = session("root", "admin");
+        let manager = session("boss", "manager");
+        let technician = session("tech2", "technician");
+
+        assert_eq!(extract::<RequireRole<roles::Admin>>(Some(&admin)).await.unwrap().username, "root");
+        assert!(extract::<RequireRole<roles::Manager>>(Some(&admin)).await.is_ok());
+        assert!(extract::<RequireRole<roles::Manager>>(Some(&manager)).await.is_ok());
+
+        assert_eq!(
+            extract::<RequireRole<roles::Admin>>(Some(&manager)).await.unwrap_err(),
+            (StatusCode::FORBIDDEN, "Admin access required".to_string()),
+        );
+        assert_eq!(
+            extract::<RequireRole<roles::Manager>>(Some(&technician)).await.unwrap_err(),
+            (StatusCode::FORBIDDEN, "Manager access required".to_string()),
+        );
+        assert_eq!(extract::<RequireRole<roles::Admin>>(None).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn impersonation_tokens_act_as_the_user_and_name_the_admin() {
+        session("root", "admin");
+        let (token, _) = issue_impersonation_token("tech3", "technician", "root", Duration::from_secs(60)).unwrap();
+        let user = extract::<AuthUser>(Some(&token)).await.unwrap();
+        assert_eq!(user.username, "tech3");
+        assert_eq!(user.role, Role::Technician);
+        assert_eq!(user.impersonated_by.as_deref(), Some("root"));
+        assert_eq!(user.session_id, None);
+
+        assert_eq!(
+            extract::<RequireRole<roles::Manager>>(Some(&token)).await.unwrap_err().0,
+            StatusCode::FORBIDDEN,
+        );
+        assert_eq!(extract::<AuthUser>(Some(&session("tech3", "technician"))).await.unwrap().impersonated_by, None);
+    }
+
+    async fn stored_admins(pool: &DbPool) -> Vec<(String, String)> {
+        sqlx::query_as("SELECT username, password FROM users WHERE role = 'admin'").fetch_all(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn bootstrap_leaves_an_existing_admin_alone() {
+        let pool = crate::database::test_database().await;
+        let policy = Config::from_env().unwrap().password_policy;
+        sqlx::query("INSERT INTO users (username, password, role) VALUES ('boss', 'stored', 'admin')").execute(&pool).await.unwrap();
+
+        bootstrap_admin(&pool, "admin", Some("Conveyor-Belt7"), &policy).await.unwrap();
+        assert_eq!(stored_admins(&pool).await, [("boss".to_string(), "stored".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn bootstrap_uses_the_configured_password_if_it_meets_the_policy() {
+        let pool = crate::database::test_database().await;
+        let policy = Config::from_env().unwrap().password_policy;
+        assert!(bootstrap_admin(&pool, "admin", Some("admin123"), &policy).await.is_err());
+        assert!(stored_admins(&pool).await.is_empty());
+
+        bootstrap_admin(&pool, "admin", Some("Conveyor-Belt7"), &policy).await.unwrap();
+        assert!(authenticate_user("admin", "Conveyor-Belt7", &pool).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn bootstrap_stores_a_generated_password_hashed() {
+        let pool = crate::database::test_database().await;
+        let policy = Config::from_env().unwrap().password_policy;
+        bootstrap_admin(&pool, "admin", None, &policy).await.unwrap();
+
+        let admins = stored_admins(&pool).await;
+        assert_eq!(admins.len(), 1);
+        assert_eq!(admins[0].0, "admin");
+        assert!(is_hashed(&admins[0].1));
+    }
+
+    #[tokio::test]
+    async fn reused_refresh_token_ends_every_token_of_the_user() {
+        session("victim", "technician");
+        let pool = crate::database::test_database().await;
+        let session_id = start_session(&pool, "victim", &SessionClient::default()).await.unwrap();
+        let (stolen, _) = issue_refresh_token(&pool, "victim", &session_id).await.unwrap();
+        let (access, _) = issue_session_token("victim", "technician", Some(&session_id)).unwrap();
+
+        let RefreshOutcome::Rotated(_, _, rotated, _) = rotate_refresh_token(&pool, &stolen, &SessionClient::default()).await.unwrap() else {
+            panic!("a fresh refresh token was rejected");
+        };
+        assert!(extract::<AuthUser>(Some(&access)).await.is_ok());
+
+        // Presenting the rotated token again gives the theft away
+        assert!(matches!(rotate_refresh_token(&pool, &stolen, &SessionClient::default()).await.unwrap(), RefreshOutcome::Rejected));
+        assert_eq!(extract::<AuthUser>(Some(&access)).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+        assert!(matches!(rotate_refresh_token(&pool, &rotated, &SessionClient::default()).await.unwrap(), RefreshOutcome::Rejected));
+    }
+
+    #[test]
+    fn session_client_takes_the_address_forwarded_by_a_trusted_proxy() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "198.51.100.7".parse().unwrap());
+        let proxy: SocketAddr = "10.0.0.2:40000".parse().unwrap();
+        let mut config = Config::from_env().unwrap();
+        assert_eq!(SessionClient::new(&headers, proxy, &config).ip.as_deref(), Some("10.0.0.2"));
+        config.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+        assert_eq!(SessionClient::new(&headers, proxy, &config).ip.as_deref(), Some("198.51.100.7"));
+    }
+
+    #[tokio::test]
+    async fn password_reset_tokens_are_stored_hashed_and_work_once_until_they_expire() {
+        let pool = crate::database::test_database().await;
+        let token = issue_password_reset_token(&pool, "tech1", Duration::from_secs(900)).await.unwrap();
+        let stored: Vec<String> = sqlx::query_scalar("SELECT token_hash FROM password_reset_tokens").fetch_all(&pool).await.unwrap();
+        assert_eq!(stored, [hash_token(&token)]);
+
+        assert_eq!(password_reset_token_user(&pool, &token).await.unwrap().as_deref(), Some("tech1"));
+        assert_eq!(redeem_password_reset_token(&pool, &token).await.unwrap().as_deref(), Some("tech1"));
+        assert_eq!(redeem_password_reset_token(&pool, &token).await.unwrap(), None);
+        assert_eq!(password_reset_token_user(&pool, &token).await.unwrap(), None);
+
+        let expired = issue_password_reset_token(&pool, "tech1", Duration::ZERO).await.unwrap();
+        assert_eq!(password_reset_token_user(&pool, &expired).await.unwrap(), None);
+        assert_eq!(redeem_password_reset_token(&pool, &expired).await.unwrap(), None);
+    }
+
+    #[test]
+    fn tokens_hash_to_hex_sha256() {
+        assert_eq!(hash_token("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[tokio::test]
+    async fn plaintext_keys_from_older_releases_are_hashed_and_keep_working() {
+        let pool = crate::database::test_database().await;
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'machine_legacy')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO machine_api_keys (machine_id, name, api_key, scopes, created_at) \
+             VALUES (1, 'reader', 'machine_reader', 'config:read', 0)"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO users (username, password, role, token) VALUES ('tech1', 'x', 'technician', 'old-session')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(hash_plaintext_keys(&pool).await.unwrap(), 2);
+        let stored: Vec<String> = sqlx::query_scalar("SELECT api_key FROM machines UNION ALL SELECT api_key FROM machine_api_keys")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert!(stored.iter().all(|key| !key.starts_with("machine_")));
+        let token: Option<String> = sqlx::query_scalar("SELECT token FROM users").fetch_one(&pool).await.unwrap();
+        assert_eq!(token, None);
+
+        assert_eq!(validate_machine_key("machine_legacy", &pool).await.map(|(id, _)| id), Some(1));
+        assert_eq!(validate_machine_key("machine_reader", &pool).await, Some((1, vec!["config:read".to_string()])));
+        // Hashed keys are left alone on the next start
+        assert_eq!(hash_plaintext_keys(&pool).await.unwrap(), 0);
+        assert_eq!(validate_machine_key("machine_legacy", &pool).await.map(|(id, _)| id), Some(1));
+    }
+
+    #[tokio::test]
+    async fn machine_keys_are_refused_outside_their_allowed_networks() {
+        let state = machines().await;
+        assert_eq!(machine_key::<scopes::TelemetryWrite>(&state, "machine_line1", "10.20.4.5:40000").await, Ok(1));
+        assert_eq!(
+            machine_key::<scopes::TelemetryWrite>(&state, "machine_line1", "192.0.2.10:40000").await,
+            Err(StatusCode::FORBIDDEN),
+        );
+        assert_eq!(machine_key::<scopes::TelemetryWrite>(&state, "machine_line2", "192.0.2.10:40000").await, Ok(2));
+        assert_eq!(
+            machine_key::<scopes::TelemetryWrite>(&state, "machine_unknown", "10.20.4.5:40000").await,
+            Err(StatusCode::UNAUTHORIZED),
+        );
+    }
+
+    #[tokio::test]
+    async fn machine_keys_reach_only_the_routes_of_their_scopes() {
+        let state = machines().await;
+        let peer = "192.0.2.10:40000";
+        // The key created with the machine carries every scope
+        assert_eq!(machine_key::<scopes::TelemetryWrite>(&state, "machine_line2", peer).await, Ok(2));
+        assert_eq!(machine_key::<scopes::CommandsRead>(&state, "machine_line2", peer).await, Ok(2));
+        assert_eq!(machine_key::<scopes::ConfigRead>(&state, "machine_reader", peer).await, Ok(2));
+        assert_eq!(machine_key::<scopes::TelemetryWrite>(&state, "machine_reader", peer).await, Err(StatusCode::FORBIDDEN));
+        assert_eq!(machine_key::<scopes::CommandsRead>(&state, "machine_reader", peer).await, Err(StatusCode::FORBIDDEN));
+        // Session tokens are no machine keys
+        let session = session("tech1", "technician");
+        assert_eq!(machine_key::<scopes::ConfigRead>(&state, &session, peer).await, Err(StatusCode::UNAUTHORIZED));
+    }
+
+    #[tokio::test]
+    async fn auth_user_rejects_expired_and_foreign_tokens() {
+        session("tech1", "technician");
+        let now = current_timestamp();
+        let claims = |exp| SessionClaims {
+            sub: "tech1".to_string(),
+            role: "technician".to_string(),
+            jti: Uuid::new_v4().simple().to_string(),
+            sid: None,
+            act: None,
+            iat: now - 3600,
+            exp,
+        };
+        let sign = |claims: &SessionClaims, secret: &[u8]| {
+            jsonwebtoken::encode(&Header::new(Algorithm::HS256), claims, &EncodingKey::from_secret(secret)).unwrap()
+        };
+        assert!(extract::<AuthUser>(Some(&sign(&claims(now + 60), b"test secret"))).await.is_ok());
+        // Beyond the clock leeway the decoder allows
+        assert_eq!(extract::<AuthUser>(Some(&sign(&claims(now - 120), b"test secret"))).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+        assert_eq!(extract::<AuthUser>(Some(&sign(&claims(now + 60), b"other secret"))).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn passwords_are_verified_against_argon2_hashes_and_legacy_ones_rehashed() {
+        let pool = crate::database::test_database().await;
+        let hash = hash_password("Conveyor-Belt7").await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        for (username, password) in [("hashed", hash.as_str()), ("legacy", "plain-old-secret")] {
+            sqlx::query("INSERT INTO users (username, password, role) VALUES ($1, $2, 'technician')")
+                .bind(username)
+                .bind(password)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let stored = async |username: &str| -> String {
+            sqlx::query_scalar("SELECT password FROM users WHERE username = $1").bind(username).fetch_one(&pool).await.unwrap()
+        };
+
+        assert_eq!(authenticate_user("hashed", "Conveyor-Belt7", &pool).await.unwrap().username, "hashed");
+        assert!(authenticate_user("hashed", "conveyor-belt7", &pool).await.is_none());
+        assert!(authenticate_user("nobody", "Conveyor-Belt7", &pool).await.is_none());
+        // Unknown usernames are checked against a hash as costly as a real one
+        assert_eq!(
+            PasswordHash::new(DUMMY_PASSWORD_HASH).unwrap().params,
+            PasswordHash::new(&hash).unwrap().params
+        );
+        // Someone who read the stored hash cannot log in with it
+        assert!(authenticate_user("hashed", &hash, &pool).await.is_none());
+
+        assert!(authenticate_user("legacy", "wrong", &pool).await.is_none());
+        assert_eq!(stored("legacy").await, "plain-old-secret");
+        assert!(authenticate_user("legacy", "plain-old-secret", &pool).await.is_some());
+        assert!(is_hashed(&stored("legacy").await));
+        assert!(authenticate_user("legacy", "plain-old-secret", &pool).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn logout_ends_only_the_session_of_the_token() {
+        session("leaver", "technician");
+        let pool = crate::database::test_database().await;
+        let session_id = start_session(&pool, "leaver", &SessionClient::default()).await.unwrap();
+        let (refresh, _) = issue_refresh_token(&pool, "leaver", &session_id).await.unwrap();
+        let (access, _) = issue_session_token("leaver", "technician", Some(&session_id)).unwrap();
+        let (untracked, _) = issue_session_token("leaver", "technician", None).unwrap();
+
+        assert!(logout(&pool, &access, None).await.unwrap());
+        assert_eq!(extract::<AuthUser>(Some(&access)).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+        assert!(matches!(rotate_refresh_token(&pool, &refresh, &SessionClient::default()).await.unwrap(), RefreshOutcome::Rejected));
+        assert!(!logout(&pool, &access, None).await.unwrap());
+        assert!(extract::<AuthUser>(Some(&untracked)).await.is_ok());
+
+        // A token from before sessions were tracked ends by its id, and the refresh token with it
+        // when the client passes that along
+        let other_session = start_session(&pool, "leaver", &SessionClient::default()).await.unwrap();
+        let (other_refresh, _) = issue_refresh_token(&pool, "leaver", &other_session).await.unwrap();
+        assert!(logout(&pool, &untracked, Some(&other_refresh)).await.unwrap());
+        assert_eq!(extract::<AuthUser>(Some(&untracked)).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+        assert!(matches!(
+            rotate_refresh_token(&pool, &other_refresh, &SessionClient::default()).await.unwrap(),
+            RefreshOutcome::Rejected,
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_tokens_rotate_within_their_session_until_they_expire() {
+        session("dashboard", "manager");
+        let pool = crate::database::test_database().await;
+        let session_id = start_session(&pool, "dashboard", &SessionClient::default()).await.unwrap();
+        let (first, _) = issue_refresh_token(&pool, "dashboard", &session_id).await.unwrap();
+        let stored: Vec<String> = sqlx::query_scalar("SELECT token_hash FROM refresh_tokens").fetch_all(&pool).await.unwrap();
+        assert_eq!(stored, [hash_token(&first)]);
+
+        let client = SessionClient { user_agent: Some("wallboard".to_string()), ip: Some("192.0.2.10".to_string()) };
+        let RefreshOutcome::Rotated(username, rotated_session, second, _) = rotate_refresh_token(&pool, &first, &client).await.unwrap() else {
+            panic!("a fresh refresh token was rejected");
+        };
+        assert_eq!((username.as_str(), rotated_session.as_str()), ("dashboard", session_id.as_str()));
+        let seen_from: Option<String> = sqlx::query_scalar("SELECT ip FROM sessions WHERE id = $1")
+            .bind(&session_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(seen_from.as_deref(), Some("192.0.2.10"));
+
+        sqlx::query("UPDATE refresh_tokens SET expires_at = $1 WHERE token_hash = $2")
+            .bind(current_timestamp() - 1)
+            .bind(hash_token(&second))
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(matches!(rotate_refresh_token(&pool, &second, &client).await.unwrap(), RefreshOutcome::Rejected));
+        assert!(matches!(rotate_refresh_token(&pool, "refresh_unknown", &client).await.unwrap(), RefreshOutcome::Rejected));
+    }
+}
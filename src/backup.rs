@@ -0,0 +1,243 @@
+use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Connection};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::Config,
+    database::{self, DbPool, current_timestamp},
+    models::BackupFile,
+    timerange,
+};
+
+const FILE_PREFIX: &str = "scada-backup-";
+const FILE_SUFFIX: &str = ".db";
+
+// Copies the live database into a new timestamped file in `dir`. VACUUM INTO reads one
+// snapshot, so the copy is consistent while telemetry keeps being written, and it is written
+// under a temporary name first so a failed backup never looks like a finished one.
+pub async fn create(pool: &DbPool, dir: &Path) -> anyhow::Result<BackupFile> {
+    tokio::fs::create_dir_all(dir).await?;
+    let created_at = current_timestamp();
+    let stamp = Utc.timestamp_opt(created_at, 0).single().unwrap_or_default().format("%Y%m%d-%H%M%S");
+    // Backups taken within the same second are numbered
+    let mut file_name = format!("{}{}{}", FILE_PREFIX, stamp, FILE_SUFFIX);
+    let mut copy = 1;
+    while tokio::fs::try_exists(dir.join(&file_name)).await? {
+        copy += 1;
+        file_name = format!("{}{}-{}{}", FILE_PREFIX, stamp, copy, FILE_SUFFIX);
+    }
+    let path = dir.join(&file_name);
+
+    let partial = dir.join(format!("{}.partial", file_name));
+    if tokio::fs::try_exists(&partial).await? {
+        tokio::fs::remove_file(&partial).await?;
+    }
+    sqlx::query("VACUUM INTO $1")
+        .bind(partial.to_string_lossy().to_string())
+        .execute(pool)
+        .await?;
+    tokio::fs::rename(&partial, &path).await?;
+
+    let size_bytes = tokio::fs::metadata(&path).await?.len() as i64;
+    tracing::info!("Database backed up to {} ({} bytes)", path.display(), size_bytes);
+    Ok(BackupFile { file_name, size_bytes, created_at })
+}
+
+// The backups in `dir`, newest first
+pub async fn list(dir: &Path) -> std::io::Result<Vec<BackupFile>> {
+    let mut backups = Vec::new();
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(backups),
+        Err(e) => return Err(e),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if !file_name.starts_with(FILE_PREFIX) || !file_name.ends_with(FILE_SUFFIX) {
+            continue;
+        }
+        let metadata = entry.metadata().await?;
+        let created_at = metadata
+            .modified()?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|age| age.as_secs() as i64)
+            .unwrap_or_default();
+        backups.push(BackupFile { file_name, size_bytes: metadata.len() as i64, created_at });
+    }
+    // Numbered copies from the same second sort after the first
+    backups.sort_by_key(|backup| std::cmp::Reverse((backup.created_at, backup.file_name.len(), backup.file_name.clone())));
+    Ok(backups)
+}
+
+// Deletes backups older than `retention`, keeping the newest one whatever its age. Returns
+// the files deleted.
+pub async fn prune(dir: &Path, retention: Duration) -> std::io::Result<usize> {
+    let cutoff = current_timestamp() - retention.as_secs() as i64;
+    let mut deleted = 0;
+    for backup in list(dir).await?.iter().skip(1).filter(|backup| backup.created_at < cutoff) {
+        tokio::fs::remove_file(dir.join(&backup.file_name)).await?;
+        deleted += 1;
+    }
+    Ok(deleted)
+}
+
+// When the backup of the local day `today` is due. A time the clocks skip that night is due
+// when they jump, and one they repeat is due the first time it comes round.
+fn due_at(tz: Tz, today: NaiveDate, backup_time: NaiveTime) -> Option<i64> {
+    timerange::local_instant(tz, today.and_time(backup_time)).map(|at| at.timestamp())
+}
+
+// Takes a backup once a night after the configured local time and deletes expired ones;
+// does nothing while SCADA_BACKUP_TIME is empty
+pub fn spawn_nightly_backup(pool: DbPool, config: &Config) -> Option<JoinHandle<()>> {
+    let backup_time = config.backup_time?;
+    if database::POSTGRES {
+        tracing::warn!("Nightly backups need the SQLite database; back up PostgreSQL with pg_dump");
+        return None;
+    }
+    let (tz, dir, retention) = (config.site_timezone, config.backup_dir.clone(), config.backup_retention);
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(10 * 60));
+        let mut backed_up: Option<NaiveDate> = None;
+        loop {
+            interval.tick().await;
+            let now = current_timestamp();
+            let Some(today) = tz.timestamp_opt(now, 0).single().map(|local_now| local_now.date_naive()) else {
+                continue;
+            };
+            let Some(due) = due_at(tz, today, backup_time) else {
+                continue;
+            };
+            if now < due || backed_up == Some(today) {
+                continue;
+            }
+            // A restart after tonight's backup must not take another one
+            let taken_tonight = |backup: &BackupFile| backup.created_at >= due;
+            if list(&dir).await.is_ok_and(|backups| backups.first().is_some_and(taken_tonight)) {
+                backed_up = Some(today);
+                continue;
+            }
+            match create(&pool, &dir).await {
+                Ok(_) => backed_up = Some(today),
+                Err(e) => {
+                    tracing::error!("Nightly backup failed: {}", e);
+                    continue;
+                },
+            }
+            if !retention.is_zero() {
+                match prune(&dir, retention).await {
+                    Ok(0) => {},
+                    Ok(count) => tracing::info!("Deleted {} expired backup(s)", count),
+                    Err(e) => tracing::error!("Failed to delete expired backups: {}", e),
+                }
+            }
+        }
+    }))
+}
+
+// Replaces the configured database with a backup, for `--restore <file>` while the server is
+// stopped. The backup is checked first, and the database it replaces is kept next to it with
+// a .before-restore suffix, together with its -wal and -shm files.
+pub async fn restore(config: &Config, backup: &Path) -> anyhow::Result<()> {
+    if database::POSTGRES {
+        anyhow::bail!("--restore needs the SQLite database; restore PostgreSQL with pg_restore");
+    }
+    let mut conn = SqliteConnectOptions::new().filename(backup).read_only(true).connect().await?;
+    let integrity: String = sqlx::query_scalar("PRAGMA integrity_check").fetch_one(&mut conn).await?;
+    if integrity != "ok" {
+        anyhow::bail!("{} failed the integrity check: {}", backup.display(), integrity);
+    }
+    let migrated: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations')")
+            .fetch_one(&mut conn)
+            .await?;
+    conn.close().await?;
+    if !migrated {
+        anyhow::bail!("{} is not a backup of this server's database", backup.display());
+    }
+
+    let database = &config.database_path;
+    let suffix = format!(".before-restore-{}", current_timestamp());
+    for extension in ["", "-wal", "-shm"] {
+        let file = PathBuf::from(format!("{}{}", database.display(), extension));
+        if tokio::fs::try_exists(&file).await? {
+            tokio::fs::rename(&file, format!("{}{}", file.display(), suffix)).await?;
+        }
+    }
+    tokio::fs::copy(backup, database).await?;
+    tracing::info!("Restored {} from {}", database.display(), backup.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("scada-backup-test-{}", uuid::Uuid::new_v4().simple()))
+    }
+
+    // Creates a backup-named file last modified `age` ago
+    fn aged_file(dir: &Path, file_name: &str, age: Duration) {
+        let file = std::fs::File::create(dir.join(file_name)).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[tokio::test]
+    async fn prunes_expired_backups_but_keeps_the_newest() {
+        let dir = scratch_dir();
+        assert!(list(&dir).await.unwrap().is_empty());
+        std::fs::create_dir_all(&dir).unwrap();
+        let day = Duration::from_secs(86_400);
+        aged_file(&dir, "scada-backup-20260101-020000.db", 40 * day);
+        aged_file(&dir, "scada-backup-20260105-020000.db", 35 * day);
+        aged_file(&dir, "notes.db", 50 * day);
+        aged_file(&dir, "scada-backup-20260205-020000.db.partial", 50 * day);
+
+        assert_eq!(prune(&dir, 30 * day).await.unwrap(), 1);
+        let names: Vec<String> = list(&dir).await.unwrap().into_iter().map(|backup| backup.file_name).collect();
+        assert_eq!(names, ["scada-backup-20260105-020000.db"]);
+        assert!(dir.join("notes.db").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    #[tokio::test]
+    async fn backups_taken_in_the_same_second_are_numbered() {
+        // An in-memory database would be vacuumed into memory as well
+        let dir = scratch_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = SqliteConnectOptions::new().filename(dir.join("live.db")).create_if_missing(true);
+        let pool = sqlx::SqlitePool::connect_with(options).await.unwrap();
+        sqlx::query("CREATE TABLE machines (id INTEGER PRIMARY KEY)").execute(&pool).await.unwrap();
+        let first = create(&pool, &dir).await.unwrap();
+        let second = create(&pool, &dir).await.unwrap();
+        assert!(second.size_bytes > 0);
+        if first.created_at == second.created_at {
+            assert_eq!(second.file_name, first.file_name.replace(".db", "-2.db"));
+        }
+        let names: Vec<String> = list(&dir).await.unwrap().into_iter().map(|backup| backup.file_name).collect();
+        assert_eq!(names, [second.file_name, first.file_name]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nightly_backup_is_due_once_across_clock_changes() {
+        let at = |hour: u32, minute: u32| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        let berlin = Tz::Europe__Berlin;
+        // 02:30 is skipped on 2026-03-29, so the backup is due at 03:00 summer time (01:00 UTC)
+        let spring = NaiveDate::from_ymd_opt(2026, 3, 29).unwrap();
+        assert_eq!(due_at(berlin, spring, at(2, 30)), Some(1_774_746_000));
+        // 02:30 happens twice on 2026-10-25; the backup is due the first time (00:30 UTC)
+        let fall = NaiveDate::from_ymd_opt(2026, 10, 25).unwrap();
+        assert_eq!(due_at(berlin, fall, at(2, 30)), Some(1_792_888_200));
+        // The night after, 02:30 is winter time (01:30 UTC)
+        let after = NaiveDate::from_ymd_opt(2026, 10, 26).unwrap();
+        assert_eq!(due_at(berlin, after, at(2, 30)), Some(1_792_978_200));
+    }
+}
@@ -0,0 +1,86 @@
+use sqlx::Transaction;
+
+use crate::{changeovers, database::{Db, DbPool}, models::MachineBatch};
+
+// Longest lot number or product accepted from operators and machines
+pub const MAX_LOT_LENGTH: usize = 100;
+
+// Starts a batch on a machine at `at`, ending the one running there. Reporting the running lot
+// again changes nothing, so machines can send their lot with every update. `started_by` is
+// None when the machine reported the lot itself. Switching to another product opens a changeover.
+pub async fn start(
+    tx: &mut Transaction<'_, Db>,
+    machine_id: i64,
+    lot_number: &str,
+    product: Option<&str>,
+    started_by: Option<&str>,
+    at: i64,
+) -> sqlx::Result<MachineBatch> {
+    let running = sqlx::query_as::<_, MachineBatch>("SELECT * FROM machine_batches WHERE machine_id = $1 AND ended_at IS NULL")
+        .bind(machine_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    if let Some(running) = running {
+        if running.lot_number == lot_number && (product.is_none() || running.product.as_deref() == product) {
+            return Ok(running);
+        }
+        sqlx::query("UPDATE machine_batches SET ended_at = $1, ended_by = $2 WHERE id = $3")
+            .bind(at)
+            .bind(started_by)
+            .bind(running.id)
+            .execute(&mut **tx)
+            .await?;
+        if let Some(product) = product {
+            changeovers::open_on_product_change(tx, machine_id, &running, product, started_by, at).await?;
+        }
+    }
+    sqlx::query_as::<_, MachineBatch>(
+        "INSERT INTO machine_batches (machine_id, lot_number, product, started_at, started_by) \
+         VALUES ($1, $2, $3, $4, $5) RETURNING *"
+    )
+    .bind(machine_id)
+    .bind(lot_number)
+    .bind(product)
+    .bind(at)
+    .bind(started_by)
+    .fetch_one(&mut **tx)
+    .await
+}
+
+// A batch of the machine, if it has one by that id
+pub async fn find(pool: &DbPool, machine_id: i64, batch_id: i64) -> sqlx::Result<Option<MachineBatch>> {
+    sqlx::query_as::<_, MachineBatch>("SELECT * FROM machine_batches WHERE id = $1 AND machine_id = $2")
+        .bind(batch_id)
+        .bind(machine_id)
+        .fetch_optional(pool)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn starting_a_batch_ends_the_running_one_unless_it_is_the_same_lot() {
+        let pool = crate::database::test_database().await;
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        let first = start(&mut tx, 1, "L-1", Some("A"), None, 100).await.unwrap();
+        // Machines report their lot with every update
+        let again = start(&mut tx, 1, "L-1", None, None, 110).await.unwrap();
+        assert_eq!((again.id, again.started_at), (first.id, 100));
+        let second = start(&mut tx, 1, "L-2", Some("A"), Some("alice"), 200).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let first = find(&pool, 1, first.id).await.unwrap().unwrap();
+        assert_eq!((first.ended_at, first.ended_by.as_deref()), (Some(200), Some("alice")));
+        assert_eq!((second.ended_at, second.started_by.as_deref()), (None, Some("alice")));
+        assert!(find(&pool, 2, second.id).await.unwrap().is_none());
+        // Same product, so no changeover
+        let changeovers: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM changeovers").fetch_one(&pool).await.unwrap();
+        assert_eq!(changeovers, 0);
+    }
+}
@@ -0,0 +1,178 @@
+use sqlx::Row;
+
+use crate::{
+    database::DbPool,
+    models::{BenchmarkEntry, BenchmarkStatistics},
+    products,
+};
+
+pub const BENCHMARK_METRICS: &[&str] = &["avg_speed", "max_speed", "target_attainment"];
+pub const BENCHMARK_PERIODS: &[&str] = &["day", "week", "month", "quarter", "year"];
+
+// Length of a benchmark period in seconds, ending now
+pub fn period_seconds(period: &str) -> Option<i64> {
+    let days = match period {
+        "day" => 1,
+        "week" => 7,
+        "month" => 30,
+        "quarter" => 91,
+        "year" => 365,
+        _ => return None,
+    };
+    Some(days * 24 * 60 * 60)
+}
+
+// The metric of every machine of the type over [from, to), read from the hourly rollups, ranked
+// best first. Archived machines and those without readings in the window are left out. target_attainment is the
+// average speed as a share of the machine's current target speed, so machines running
+// different products compare fairly.
+pub async fn rank_machines(
+    pool: &DbPool,
+    machine_type: &str,
+    metric: &str,
+    (from, to): (i64, i64),
+) -> sqlx::Result<Vec<BenchmarkEntry>> {
+    let value = match metric {
+        "max_speed" => "MAX(r.max_speed)".to_string(),
+        "target_attainment" => format!("SUM(r.avg_speed * r.samples) / SUM(r.samples) / NULLIF({}, 0)", products::TARGET_SPEED),
+        _ => "SUM(r.avg_speed * r.samples) / SUM(r.samples)".to_string(),
+    };
+    let rows = sqlx::query(&format!(
+        "SELECT machines.id, machines.name, machines.code, machines.location, {} AS value, CAST(SUM(r.samples) AS BIGINT) AS samples \
+         FROM machines JOIN speed_history_hourly r ON r.machine_id = machines.id \
+         WHERE machines.machine_type = $1 AND machines.archived_at IS NULL AND r.bucket_start >= $2 AND r.bucket_start < $3 \
+         GROUP BY machines.id",
+        value
+    ))
+    .bind(machine_type)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+
+    // Machines without a target have no attainment
+    let mut entries: Vec<BenchmarkEntry> = rows
+        .iter()
+        .filter_map(|row| {
+            Some(BenchmarkEntry {
+                machine_id: row.get("id"),
+                name: row.get("name"),
+                code: row.get("code"),
+                location: row.get("location"),
+                value: row.get::<Option<f64>, _>("value")?,
+                samples: row.get("samples"),
+                rank: 0,
+                percentile: 0.0,
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| b.value.total_cmp(&a.value).then(a.machine_id.cmp(&b.machine_id)));
+
+    // Percent of the other machines doing worse; ties share the better rank
+    let count = entries.len();
+    let values: Vec<f64> = entries.iter().map(|entry| entry.value).collect();
+    for entry in &mut entries {
+        let better = values.iter().filter(|value| **value > entry.value).count();
+        let worse = values.iter().filter(|value| **value < entry.value).count();
+        entry.rank = better as i64 + 1;
+        entry.percentile = if count > 1 { 100.0 * worse as f64 / (count - 1) as f64 } else { 100.0 };
+    }
+    Ok(entries)
+}
+
+// Spread of the ranked values; None without any
+pub fn statistics(entries: &[BenchmarkEntry]) -> Option<BenchmarkStatistics> {
+    let mut values: Vec<f64> = entries.iter().map(|entry| entry.value).collect();
+    values.sort_by(f64::total_cmp);
+    let (&min, &max) = (values.first()?, values.last()?);
+    Some(BenchmarkStatistics {
+        machines: values.len() as i64,
+        min,
+        p10: percentile(&values, 0.10),
+        p25: percentile(&values, 0.25),
+        median: percentile(&values, 0.50),
+        p75: percentile(&values, 0.75),
+        p90: percentile(&values, 0.90),
+        max,
+        mean: values.iter().sum::<f64>() / values.len() as f64,
+    })
+}
+
+// Linear interpolation between the closest ranks of sorted, non-empty values
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let position = p * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ranks_machines_of_the_type_with_ties_sharing_a_rank() {
+        let pool = crate::database::test_database().await;
+        for (id, machine_type, archived_at, avg_speed) in [
+            (1, "press", None, 10.0),
+            (2, "press", None, 20.0),
+            (3, "press", None, 20.0),
+            (4, "press", Some(100), 50.0),
+            (5, "lathe", None, 90.0),
+        ] {
+            sqlx::query(
+                "INSERT INTO machines (id, name, code, api_key, machine_type, archived_at) VALUES ($1, $2, $3, $4, $5, $6)"
+            )
+            .bind(id)
+            .bind(format!("Machine {}", id))
+            .bind(format!("M{}", id))
+            .bind(format!("key{}", id))
+            .bind(machine_type)
+            .bind(archived_at)
+            .execute(&pool)
+            .await
+            .unwrap();
+            sqlx::query(
+                "INSERT INTO speed_history_hourly (machine_id, bucket_start, avg_speed, min_speed, max_speed, samples) \
+                 VALUES ($1, 3600, $2, $3, $4, 60)"
+            )
+            .bind(id)
+            .bind(avg_speed)
+            .bind(avg_speed)
+            .bind(avg_speed)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let entries = rank_machines(&pool, "press", "avg_speed", (0, 7200)).await.unwrap();
+        let ranked: Vec<(i64, f64, i64, f64)> =
+            entries.iter().map(|entry| (entry.machine_id, entry.value, entry.rank, entry.percentile)).collect();
+        assert_eq!(ranked, [(2, 20.0, 1, 50.0), (3, 20.0, 1, 50.0), (1, 10.0, 3, 0.0)]);
+        assert!(rank_machines(&pool, "press", "avg_speed", (7200, 10800)).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn summarizes_the_spread_of_values() {
+        let entries: Vec<BenchmarkEntry> = [30.0, 10.0, 50.0, 20.0, 40.0]
+            .into_iter()
+            .enumerate()
+            .map(|(id, value)| BenchmarkEntry {
+                machine_id: id as i64,
+                name: String::new(),
+                code: String::new(),
+                location: None,
+                value,
+                samples: 1,
+                rank: 0,
+                percentile: 0.0,
+            })
+            .collect();
+        let statistics = statistics(&entries).unwrap();
+        assert_eq!((statistics.machines, statistics.min, statistics.max), (5, 10.0, 50.0));
+        assert_eq!((statistics.p25, statistics.median, statistics.mean), (20.0, 30.0, 30.0));
+        assert_eq!(statistics.p90, 46.0);
+        assert!(super::statistics(&[]).is_none());
+        assert_eq!(period_seconds("week"), Some(7 * 24 * 60 * 60));
+        assert_eq!(period_seconds("decade"), None);
+    }
+}
@@ -0,0 +1,2911 @@
+// Lightweight stand-in for the SCADA backend that serves every route crates/scada-client
+// calls with canned, deterministic data and no database. Frontend and gateway teams can
+// develop and run contract tests against it; responses use the same types as the real server.
+use axum::{
+    body::Bytes,
+    extract::{Path, Query},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, net::SocketAddr, time::Duration};
+use tower_http::cors::CorsLayer;
+
+use roles::Policy;
+use scada_models::*;
+
+// Every fixture timestamp is relative to this instant so responses never change between runs
+const BASE_TIME: i64 = 1_700_000_000;
+const ADMIN_TOKEN: &str = "mock_admin_token";
+const MANAGER_TOKEN: &str = "mock_manager_token";
+const USER_TOKEN: &str = "mock_user_token";
+const MACHINE_COUNT: i64 = 3;
+// Every machine last changed at this cursor of the long-poll feed
+const CHANGE_CURSOR: i64 = 1;
+const JOB_ID: &str = "00000000-0000-4000-8000-000000000001";
+const PLANT_NAME: &str = "Mock Plant";
+
+// Downloads are small placeholders, not real archives or databases
+const ARCHIVE_CONTENTS: &[u8] = b"scada-mock machine archive\n";
+const BACKUP_CONTENTS: &[u8] = b"SQLite format 3\0";
+const DOCUMENT_CONTENTS: &[u8] = b"%PDF-1.4\n% scada-mock operating manual\n";
+const LOGO_CONTENTS: &str = "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"64\" height=\"64\">\
+                             <rect width=\"64\" height=\"64\" fill=\"#2563eb\"/></svg>";
+
+type ApiResult<T> = Result<T, (StatusCode, Json<ErrorResponse>)>;
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (status, Json(ErrorResponse { error: message.to_string() }))
+}
+
+fn bearer(headers: &HeaderMap) -> ApiResult<&str> {
+    headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer "))
+        .ok_or_else(|| error(StatusCode::UNAUTHORIZED, "Missing token"))
+}
+
+fn token_role(token: &str) -> Option<&'static str> {
+    match token {
+        ADMIN_TOKEN => Some("admin"),
+        MANAGER_TOKEN => Some("manager"),
+        USER_TOKEN => Some("technician"),
+        _ => None,
+    }
+}
+
+fn caller_role(headers: &HeaderMap) -> ApiResult<&'static str> {
+    token_role(bearer(headers)?).ok_or_else(|| error(StatusCode::UNAUTHORIZED, "Invalid token"))
+}
+
+fn require_user(headers: &HeaderMap) -> ApiResult<()> {
+    caller_role(headers).map(|_| ())
+}
+
+fn require_manager(headers: &HeaderMap) -> ApiResult<()> {
+    match caller_role(headers)? {
+        "admin" | "manager" => Ok(()),
+        _ => Err(error(StatusCode::FORBIDDEN, "Manager access required")),
+    }
+}
+
+fn require_admin(headers: &HeaderMap) -> ApiResult<()> {
+    match caller_role(headers)? {
+        "admin" => Ok(()),
+        _ => Err(error(StatusCode::FORBIDDEN, "Admin access required")),
+    }
+}
+
+// Machine API keys are "mock_machine_key_<id>"
+fn machine_from_key(headers: &HeaderMap) -> ApiResult<i64> {
+    bearer(headers)?
+        .strip_prefix("mock_machine_key_")
+        .and_then(|id| id.parse().ok())
+        .filter(|id| (1..=MACHINE_COUNT).contains(id))
+        .ok_or_else(|| error(StatusCode::UNAUTHORIZED, "Invalid machine API key"))
+}
+
+// Who may call a route served by the generic handlers, named after the real server's
+// route policies so each route admits the same accounts here as there
+mod roles {
+    use super::{require_admin, require_manager, require_user, ApiResult};
+    use axum::http::HeaderMap;
+
+    pub trait Policy {
+        fn check(headers: &HeaderMap) -> ApiResult<()>;
+    }
+
+    pub struct Public;
+
+    pub struct User;
+
+    pub struct Manager;
+
+    pub struct Admin;
+
+    impl Policy for Public {
+        fn check(_headers: &HeaderMap) -> ApiResult<()> {
+            Ok(())
+        }
+    }
+
+    impl Policy for User {
+        fn check(headers: &HeaderMap) -> ApiResult<()> {
+            require_user(headers)
+        }
+    }
+
+    impl Policy for Manager {
+        fn check(headers: &HeaderMap) -> ApiResult<()> {
+            require_manager(headers)
+        }
+    }
+
+    impl Policy for Admin {
+        fn check(headers: &HeaderMap) -> ApiResult<()> {
+            require_admin(headers)
+        }
+    }
+}
+
+fn machine(id: i64) -> ApiResult<Machine> {
+    let (name, code, location, machine_type) = match id {
+        1 => ("Conveyor A", "CNV-001", "Plant 1", "Conveyor"),
+        2 => ("Press B", "PRS-002", "Plant 1", "Press"),
+        3 => ("Mixer C", "MIX-003", "Plant 2", "Mixer"),
+        _ => return Err(error(StatusCode::NOT_FOUND, "Machine not found")),
+    };
+
+    Ok(Machine {
+        id,
+        name: name.to_string(),
+        code: code.to_string(),
+        location: Some(location.to_string()),
+        machine_type: Some(machine_type.to_string()),
+        current_speed: speed_at(id, 0),
+        status_message: if id == 3 { "Offline".to_string() } else { "Running normally".to_string() },
+        is_online: id != 3,
+        locked_out_at: None,
+        locked_out_by: None,
+        lockout_reason: None,
+        last_update: BASE_TIME,
+        updated_at: BASE_TIME,
+        report_interval: Some(if id == 2 { 1 } else { 10 }),
+        clock_drift: Some(if id == 3 { 95 } else { 0 }),
+        history_retention_days: None,
+        data_owner: None,
+        archived_at: None,
+        archived_by: None,
+        health_score: None,
+        display_name: None,
+    })
+}
+
+// Deterministic speed for a machine `hours_ago` hours before BASE_TIME
+fn speed_at(machine_id: i64, hours_ago: i64) -> f64 {
+    let wave = [0.0, 2.5, 4.0, 2.5, 0.0, -2.5, -4.0, -2.5];
+    100.0 * machine_id as f64 + wave[(hours_ago % 8) as usize]
+}
+
+fn history(machine_id: i64) -> Vec<SpeedHistory> {
+    (0..24)
+        .rev()
+        .map(|hours_ago| SpeedHistory {
+            speed: speed_at(machine_id, hours_ago),
+            message: None,
+            timestamp: BASE_TIME - hours_ago * 3600,
+            min_speed: None,
+            max_speed: None,
+            samples: None,
+        })
+        .collect()
+}
+
+fn aggregates(history: &[SpeedHistory]) -> SpeedAggregates {
+    let speeds: Vec<f64> = history.iter().map(|h| h.speed).collect();
+    SpeedAggregates {
+        samples: speeds.len() as i64,
+        avg_speed: Some(speeds.iter().sum::<f64>() / speeds.len() as f64),
+        min_speed: speeds.iter().copied().reduce(f64::min),
+        max_speed: speeds.iter().copied().reduce(f64::max),
+    }
+}
+
+fn comments(machine_id: i64) -> Vec<MaintenanceComment> {
+    vec![
+        MaintenanceComment {
+            id: machine_id * 10 + 2,
+            machine_id,
+            comment: "Belt tension adjusted".to_string(),
+            priority: "normal".to_string(),
+            username: "technician".to_string(),
+            created_at: BASE_TIME - 3600,
+            category_id: Some(1),
+            pinned: false,
+            resolved_by: Some("technician".to_string()),
+            resolved_at: Some(BASE_TIME - 3000),
+        },
+        MaintenanceComment {
+            id: machine_id * 10 + 1,
+            machine_id,
+            comment: "Bearing noise reported".to_string(),
+            priority: "high".to_string(),
+            username: "operator".to_string(),
+            created_at: BASE_TIME - 86400,
+            category_id: Some(1),
+            pinned: true,
+            resolved_by: None,
+            resolved_at: None,
+        },
+    ]
+}
+
+// Machine 1 has a speed change waiting for its gateway
+fn commands(machine_id: i64) -> Vec<MachineCommand> {
+    if machine_id != 1 {
+        return Vec::new();
+    }
+    vec![MachineCommand {
+        id: 1,
+        machine_id,
+        command_type: "set_speed".to_string(),
+        parameters: serde_json::json!({ "speed": 110.0 }),
+        status: "pending".to_string(),
+        created_by: "admin".to_string(),
+        created_at: BASE_TIME - 60,
+        delivered_at: None,
+        completed_at: None,
+        result: None,
+        expires_at: Some(BASE_TIME + 3600),
+        cancelled_by: None,
+    }]
+}
+
+fn user(id: i64, username: &str, role: &str, email: Option<&str>) -> User {
+    User {
+        id,
+        username: username.to_string(),
+        role: role.to_string(),
+        email: email.map(str::to_string),
+        last_login: Some(BASE_TIME - 3600 * id),
+        is_active: true,
+        deactivated_at: None,
+        deactivation_reason: None,
+    }
+}
+
+fn alarm_presentation(severity: &str, sound_id: Option<&str>, color: &str, auto_popup: bool) -> AlarmPresentation {
+    AlarmPresentation {
+        severity: severity.to_string(),
+        sound_id: sound_id.map(str::to_string),
+        color: color.to_string(),
+        auto_popup,
+        updated_at: BASE_TIME - 7 * 86400,
+    }
+}
+
+fn unit(symbol: &str, dimension: &str, factor: f64, description: &str) -> Unit {
+    Unit {
+        symbol: symbol.to_string(),
+        dimension: dimension.to_string(),
+        factor,
+        description: Some(description.to_string()),
+    }
+}
+
+fn checklist_step(id: i64, text: &str, completed: bool) -> WorkOrderStep {
+    WorkOrderStep {
+        id,
+        position: id,
+        text: text.to_string(),
+        required: true,
+        completed_by: completed.then(|| "technician".to_string()),
+        completed_at: completed.then_some(BASE_TIME - 5000),
+        notes: None,
+    }
+}
+
+fn sha256_hex(contents: &[u8]) -> String {
+    hex::encode(Sha256::digest(contents))
+}
+
+// Canned record the generic handlers answer with for a response type. Lists hold the
+// single records of their item type, so ids and names agree across routes.
+trait Fixture {
+    fn fixture() -> Self;
+}
+
+impl Fixture for Machine {
+    fn fixture() -> Self {
+        machine(1).expect("machine 1 is a fixture")
+    }
+}
+
+impl Fixture for MachineResponse {
+    fn fixture() -> Self {
+        let machine = Machine::fixture();
+        MachineResponse {
+            id: machine.id,
+            name: machine.name,
+            code: machine.code,
+            api_key: Some(format!("mock_machine_key_{}", machine.id)),
+            signing_secret: None,
+            location: machine.location,
+            machine_type: machine.machine_type,
+            allowed_cidrs: Vec::new(),
+        }
+    }
+}
+
+impl Fixture for PasswordPolicyResponse {
+    fn fixture() -> Self {
+        PasswordPolicyResponse {
+            min_length: 12,
+            required_classes: vec!["lowercase".to_string(), "uppercase".to_string(), "digit".to_string()],
+            max_age_days: None,
+        }
+    }
+}
+
+impl Fixture for SiteSettings {
+    fn fixture() -> Self {
+        SiteSettings {
+            plant_name: PLANT_NAME.to_string(),
+            default_locale: None,
+            shift_names: vec!["Early".to_string(), "Late".to_string(), "Night".to_string()],
+            logo_url: Some("/api/site-settings/logo".to_string()),
+            updated_at: Some(BASE_TIME - 30 * 86400),
+        }
+    }
+}
+
+impl Fixture for Vec<FeatureFlag> {
+    fn fixture() -> Self {
+        ["automation_rules", "graphql", "mqtt"]
+            .into_iter()
+            .map(|name| FeatureFlag {
+                name: name.to_string(),
+                enabled: false,
+                updated_by: None,
+                updated_at: None,
+            })
+            .collect()
+    }
+}
+
+impl Fixture for FeatureFlag {
+    fn fixture() -> Self {
+        Vec::<FeatureFlag>::fixture().remove(0)
+    }
+}
+
+impl Fixture for User {
+    fn fixture() -> Self {
+        user(3, "technician", "technician", None)
+    }
+}
+
+impl Fixture for UserListResponse {
+    fn fixture() -> Self {
+        UserListResponse {
+            users: vec![
+                user(1, "admin", "admin", None),
+                user(2, "manager", "manager", Some("manager@example.com")),
+                User::fixture(),
+            ],
+        }
+    }
+}
+
+impl Fixture for Session {
+    fn fixture() -> Self {
+        Session {
+            id: "mock-session-1".to_string(),
+            user_agent: Some("Mozilla/5.0".to_string()),
+            ip: Some("10.0.0.21".to_string()),
+            created_at: BASE_TIME - 3600,
+            last_seen_at: BASE_TIME,
+            expires_at: BASE_TIME + 30 * 86400,
+            current: true,
+        }
+    }
+}
+
+impl Fixture for SessionListResponse {
+    fn fixture() -> Self {
+        SessionListResponse { sessions: vec![Session::fixture()] }
+    }
+}
+
+impl Fixture for LoginHistoryResponse {
+    fn fixture() -> Self {
+        LoginHistoryResponse {
+            logins: vec![LoginHistoryEntry {
+                id: 1,
+                username: "technician".to_string(),
+                succeeded: true,
+                failure_reason: None,
+                ip: Some("10.0.0.21".to_string()),
+                user_agent: Some("Mozilla/5.0".to_string()),
+                created_at: BASE_TIME - 3600,
+            }],
+        }
+    }
+}
+
+impl Fixture for MachineListView {
+    fn fixture() -> Self {
+        MachineListView::default()
+    }
+}
+
+// Impersonating hands out the technician's token, so the session works like theirs
+impl Fixture for ImpersonationResponse {
+    fn fixture() -> Self {
+        ImpersonationResponse {
+            token: USER_TOKEN.to_string(),
+            username: "technician".to_string(),
+            role: "technician".to_string(),
+            expires_at: BASE_TIME + 1800,
+            impersonated_by: "admin".to_string(),
+        }
+    }
+}
+
+impl Fixture for ImpersonationListResponse {
+    fn fixture() -> Self {
+        ImpersonationListResponse {
+            impersonations: vec![Impersonation {
+                id: 1,
+                admin_username: "admin".to_string(),
+                username: "technician".to_string(),
+                reason: Some("Reproducing a reported issue".to_string()),
+                created_at: BASE_TIME - 600,
+                expires_at: BASE_TIME + 1200,
+            }],
+        }
+    }
+}
+
+impl Fixture for ServiceAccount {
+    fn fixture() -> Self {
+        ServiceAccount {
+            id: 1,
+            name: "mes-gateway".to_string(),
+            description: Some("MES integration".to_string()),
+            scopes: vec!["machines:read".to_string(), "work_orders:write".to_string()],
+            created_by: "admin".to_string(),
+            created_at: BASE_TIME - 60 * 86400,
+            rotated_at: None,
+            previous_key_expires_at: None,
+            last_used_at: Some(BASE_TIME - 300),
+            disabled_at: None,
+            api_key: None,
+        }
+    }
+}
+
+impl Fixture for ServiceAccountListResponse {
+    fn fixture() -> Self {
+        ServiceAccountListResponse { service_accounts: vec![ServiceAccount::fixture()] }
+    }
+}
+
+impl Fixture for Certification {
+    fn fixture() -> Self {
+        Certification {
+            id: 1,
+            username: "technician".to_string(),
+            skill: "electrical".to_string(),
+            reference: Some("EL-2023-117".to_string()),
+            issued_at: Some(BASE_TIME - 200 * 86400),
+            expires_at: Some(BASE_TIME + 165 * 86400),
+            notified_at: None,
+            recorded_by: "manager".to_string(),
+            updated_at: BASE_TIME - 200 * 86400,
+        }
+    }
+}
+
+impl Fixture for CertificationListResponse {
+    fn fixture() -> Self {
+        CertificationListResponse { certifications: vec![Certification::fixture()] }
+    }
+}
+
+impl Fixture for MachineAccessListResponse {
+    fn fixture() -> Self {
+        MachineAccessListResponse {
+            username: "technician".to_string(),
+            sees_all_machines: false,
+            machines: vec![MachineAccessGrant {
+                machine_id: 1,
+                machine_name: "Conveyor A".to_string(),
+                granted_by: Some("admin".to_string()),
+                granted_at: BASE_TIME - 30 * 86400,
+            }],
+        }
+    }
+}
+
+impl Fixture for MachineMetricListResponse {
+    fn fixture() -> Self {
+        MachineMetricListResponse {
+            metrics: vec![MachineMetric {
+                metric: "temperature".to_string(),
+                unit: "degC".to_string(),
+                value: 41.5,
+                updated_at: BASE_TIME,
+            }],
+        }
+    }
+}
+
+impl Fixture for MachineDisplayName {
+    fn fixture() -> Self {
+        MachineDisplayName {
+            locale: "de".to_string(),
+            display_name: "Förderband A".to_string(),
+        }
+    }
+}
+
+impl Fixture for DisplayNameListResponse {
+    fn fixture() -> Self {
+        DisplayNameListResponse { display_names: vec![MachineDisplayName::fixture()] }
+    }
+}
+
+impl Fixture for MachineGapsResponse {
+    fn fixture() -> Self {
+        MachineGapsResponse {
+            machine_id: 1,
+            from: BASE_TIME - 86400,
+            to: BASE_TIME,
+            min_gap: 600,
+            total_gap_seconds: 1800,
+            coverage: 1.0 - 1800.0 / 86400.0,
+            gaps: vec![HistoryGap {
+                start: BASE_TIME - 7200,
+                end: BASE_TIME - 5400,
+                duration: 1800,
+            }],
+        }
+    }
+}
+
+impl Fixture for GapReportResponse {
+    fn fixture() -> Self {
+        let gaps = MachineGapsResponse::fixture();
+        GapReportResponse {
+            plant_name: PLANT_NAME.to_string(),
+            from: gaps.from,
+            to: gaps.to,
+            min_gap: gaps.min_gap,
+            gaps: 1,
+            total_gap_seconds: gaps.total_gap_seconds,
+            machines: vec![GapReportEntry {
+                machine_id: 1,
+                machine_name: "Conveyor A".to_string(),
+                gaps: 1,
+                total_gap_seconds: gaps.total_gap_seconds,
+                longest_gap: gaps.total_gap_seconds,
+                coverage: gaps.coverage,
+            }],
+        }
+    }
+}
+
+impl Fixture for JobCreatedResponse {
+    fn fixture() -> Self {
+        JobCreatedResponse {
+            job_id: JOB_ID.to_string(),
+            status: "queued".to_string(),
+        }
+    }
+}
+
+impl Fixture for JobResponse {
+    fn fixture() -> Self {
+        JobResponse {
+            id: JOB_ID.to_string(),
+            kind: "history_export".to_string(),
+            status: "completed".to_string(),
+            progress: 1.0,
+            result: Some(serde_json::json!({ "rows": 24 })),
+            error: None,
+            created_by: "admin".to_string(),
+            created_at: BASE_TIME - 120,
+            started_at: Some(BASE_TIME - 119),
+            finished_at: Some(BASE_TIME - 118),
+            artifact: Some(JobArtifact {
+                file_name: "history-CNV-001.csv".to_string(),
+                expires_at: BASE_TIME + 86400,
+            }),
+        }
+    }
+}
+
+impl Fixture for ExportRequest {
+    fn fixture() -> Self {
+        ExportRequest {
+            id: 1,
+            machine_id: 1,
+            range_from: BASE_TIME - 86400,
+            range_to: BASE_TIME,
+            reason: "Quality audit".to_string(),
+            status: "approved".to_string(),
+            requested_by: "technician".to_string(),
+            requested_at: BASE_TIME - 7200,
+            decided_by: Some("manager".to_string()),
+            decided_at: Some(BASE_TIME - 3600),
+            decision_note: None,
+            job_id: Some(JOB_ID.to_string()),
+            downloads: 0,
+            last_downloaded_at: None,
+        }
+    }
+}
+
+impl Fixture for ExportRequestListResponse {
+    fn fixture() -> Self {
+        ExportRequestListResponse { requests: vec![ExportRequest::fixture()] }
+    }
+}
+
+impl Fixture for HistoryShiftListResponse {
+    fn fixture() -> Self {
+        HistoryShiftListResponse {
+            shifts: vec![HistoryShift {
+                id: 1,
+                machine_id: 1,
+                range_from: BASE_TIME - 86400,
+                range_to: BASE_TIME - 43200,
+                offset_seconds: 3600,
+                rows_shifted: 12,
+                reason: Some("Gateway clock ran an hour behind".to_string()),
+                job_id: Some(JOB_ID.to_string()),
+                created_by: "admin".to_string(),
+                created_at: BASE_TIME - 1800,
+                undone_by: None,
+                undone_at: None,
+            }],
+        }
+    }
+}
+
+impl Fixture for MachineMergeResponse {
+    fn fixture() -> Self {
+        MachineMergeResponse {
+            machine_id: 1,
+            merged_machine_id: 2,
+            dry_run: true,
+            moved: BTreeMap::from([("maintenance_comments".to_string(), 2), ("speed_history".to_string(), 24)]),
+            dropped: BTreeMap::new(),
+        }
+    }
+}
+
+impl Fixture for MachineArchiveListResponse {
+    fn fixture() -> Self {
+        MachineArchiveListResponse {
+            archives: vec![MachineArchive {
+                id: 1,
+                machine_id: 3,
+                machine_name: "Mixer C".to_string(),
+                machine_code: Some("MIX-003".to_string()),
+                file_name: "machine-MIX-003.zip".to_string(),
+                size_bytes: ARCHIVE_CONTENTS.len() as i64,
+                sha256: sha256_hex(ARCHIVE_CONTENTS),
+                row_counts: serde_json::json!({ "speed_history": 24, "maintenance_comments": 2 }),
+                created_by: "admin".to_string(),
+                created_at: BASE_TIME - 86400,
+            }],
+        }
+    }
+}
+
+impl Fixture for TrendAnnotation {
+    fn fixture() -> Self {
+        TrendAnnotation {
+            id: 1,
+            machine_id: 1,
+            starts_at: BASE_TIME - 14400,
+            ends_at: Some(BASE_TIME - 12600),
+            text: "Belt replaced".to_string(),
+            created_by: "technician".to_string(),
+            created_at: BASE_TIME - 12000,
+        }
+    }
+}
+
+impl Fixture for TrendAnnotationListResponse {
+    fn fixture() -> Self {
+        TrendAnnotationListResponse { annotations: vec![TrendAnnotation::fixture()] }
+    }
+}
+
+impl Fixture for MachineBatch {
+    fn fixture() -> Self {
+        MachineBatch {
+            id: 1,
+            machine_id: 1,
+            lot_number: "LOT-2023-1114".to_string(),
+            product: Some("WIDGET-100".to_string()),
+            started_at: BASE_TIME - 28800,
+            ended_at: None,
+            started_by: Some("technician".to_string()),
+            ended_by: None,
+        }
+    }
+}
+
+impl Fixture for MachineBatchListResponse {
+    fn fixture() -> Self {
+        MachineBatchListResponse { batches: vec![MachineBatch::fixture()] }
+    }
+}
+
+impl Fixture for Changeover {
+    fn fixture() -> Self {
+        Changeover {
+            id: 1,
+            machine_id: 1,
+            from_product: Some("WIDGET-50".to_string()),
+            to_product: Some("WIDGET-100".to_string()),
+            source: "operator".to_string(),
+            started_at: BASE_TIME - 30600,
+            ended_at: Some(BASE_TIME - 28800),
+            started_by: Some("technician".to_string()),
+            ended_by: Some("technician".to_string()),
+        }
+    }
+}
+
+impl Fixture for ChangeoverListResponse {
+    fn fixture() -> Self {
+        ChangeoverListResponse { changeovers: vec![Changeover::fixture()] }
+    }
+}
+
+impl Fixture for ChangeoverReportResponse {
+    fn fixture() -> Self {
+        ChangeoverReportResponse {
+            plant_name: PLANT_NAME.to_string(),
+            from: BASE_TIME - 30 * 86400,
+            to: BASE_TIME,
+            changeovers: vec![ChangeoverReportEntry {
+                machine_id: 1,
+                machine_name: "Conveyor A".to_string(),
+                from_product: Some("WIDGET-50".to_string()),
+                to_product: Some("WIDGET-100".to_string()),
+                month: "2023-11".to_string(),
+                changeovers: 1,
+                total_minutes: 30.0,
+                avg_minutes: 30.0,
+                min_minutes: 30.0,
+                max_minutes: 30.0,
+            }],
+        }
+    }
+}
+
+// The technician works the early shift on machine 1 on BASE_TIME's day
+impl Fixture for OperatorAssignment {
+    fn fixture() -> Self {
+        let day = BASE_TIME - BASE_TIME % 86400;
+        OperatorAssignment {
+            id: 1,
+            machine_id: 1,
+            username: "technician".to_string(),
+            shift_date: "2023-11-14".to_string(),
+            shift: 0,
+            starts_at: day + 6 * 3600,
+            ends_at: day + 14 * 3600,
+            assigned_by: "manager".to_string(),
+            assigned_at: day - 86400,
+        }
+    }
+}
+
+impl Fixture for ShiftScheduleResponse {
+    fn fixture() -> Self {
+        ShiftScheduleResponse {
+            from: "2023-11-14".to_string(),
+            to: "2023-11-14".to_string(),
+            shift_names: SiteSettings::fixture().shift_names,
+            assignments: vec![OperatorAssignment::fixture()],
+        }
+    }
+}
+
+impl Fixture for OperatorReportResponse {
+    fn fixture() -> Self {
+        OperatorReportResponse {
+            plant_name: PLANT_NAME.to_string(),
+            from: BASE_TIME - 86400,
+            to: BASE_TIME,
+            operators: vec![OperatorReportEntry {
+                username: "technician".to_string(),
+                machine_id: 1,
+                machine_name: "Conveyor A".to_string(),
+                shifts: 1,
+                hours: 8.0,
+                avg_speed: Some(100.0),
+                alarms: 0,
+                flagged_readings: 0,
+            }],
+        }
+    }
+}
+
+impl Fixture for MaintenanceComment {
+    fn fixture() -> Self {
+        comments(1).remove(0)
+    }
+}
+
+impl Fixture for CommentFeedResponse {
+    fn fixture() -> Self {
+        let machine = Machine::fixture();
+        CommentFeedResponse {
+            comments: comments(machine.id)
+                .into_iter()
+                .map(|comment| CommentFeedEntry {
+                    id: comment.id,
+                    machine_id: comment.machine_id,
+                    machine_name: machine.name.clone(),
+                    machine_code: machine.code.clone(),
+                    comment: comment.comment,
+                    priority: comment.priority,
+                    username: comment.username,
+                    created_at: comment.created_at,
+                    category_id: comment.category_id,
+                    pinned: comment.pinned,
+                    resolved_by: comment.resolved_by,
+                    resolved_at: comment.resolved_at,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Fixture for CommentCategory {
+    fn fixture() -> Self {
+        CommentCategory {
+            id: 1,
+            name: "Mechanical".to_string(),
+            description: Some("Belts, bearings and drives".to_string()),
+            created_at: BASE_TIME - 90 * 86400,
+        }
+    }
+}
+
+impl Fixture for CommentCategoryListResponse {
+    fn fixture() -> Self {
+        CommentCategoryListResponse { categories: vec![CommentCategory::fixture()] }
+    }
+}
+
+// Every machine has one high and one normal priority comment in category 1
+impl Fixture for CategoryReportResponse {
+    fn fixture() -> Self {
+        CategoryReportResponse {
+            plant_name: PLANT_NAME.to_string(),
+            from: BASE_TIME - 30 * 86400,
+            to: BASE_TIME,
+            categories: vec![CategoryReportEntry {
+                category_id: Some(1),
+                category: Some("Mechanical".to_string()),
+                comments: 2 * MACHINE_COUNT,
+                high_priority: MACHINE_COUNT,
+                machines: MACHINE_COUNT,
+            }],
+        }
+    }
+}
+
+impl Fixture for MachineApiKey {
+    fn fixture() -> Self {
+        MachineApiKey {
+            id: 1,
+            machine_id: 1,
+            name: "gateway".to_string(),
+            scopes: MACHINE_KEY_SCOPES.iter().map(|scope| scope.to_string()).collect(),
+            created_at: BASE_TIME - 30 * 86400,
+            revoked_at: None,
+            expires_at: None,
+            api_key: None,
+            signing_secret: None,
+        }
+    }
+}
+
+impl Fixture for MachineApiKeyListResponse {
+    fn fixture() -> Self {
+        MachineApiKeyListResponse { keys: vec![MachineApiKey::fixture()] }
+    }
+}
+
+// The "new" key is machine 1's usual one, so agents keep working against the mock
+impl Fixture for MachineKeyRotationResponse {
+    fn fixture() -> Self {
+        MachineKeyRotationResponse {
+            api_key: "mock_machine_key_1".to_string(),
+            signing_secret: None,
+            previous_key: Some(MachineApiKey {
+                expires_at: Some(BASE_TIME + 3600),
+                ..MachineApiKey::fixture()
+            }),
+        }
+    }
+}
+
+impl Fixture for MachineDocument {
+    fn fixture() -> Self {
+        MachineDocument {
+            id: 1,
+            machine_id: 1,
+            title: "Operating manual".to_string(),
+            doc_type: "manual".to_string(),
+            url: Some("/api/documents/1/file".to_string()),
+            file_name: Some("conveyor-manual.pdf".to_string()),
+            created_by: "admin".to_string(),
+            created_at: BASE_TIME - 90 * 86400,
+        }
+    }
+}
+
+impl Fixture for DocumentListResponse {
+    fn fixture() -> Self {
+        DocumentListResponse { documents: vec![MachineDocument::fixture()] }
+    }
+}
+
+impl Fixture for WorkOrder {
+    fn fixture() -> Self {
+        WorkOrder {
+            id: 1,
+            machine_id: 1,
+            title: "Replace drive belt".to_string(),
+            description: Some("Belt shows cracks near the splice".to_string()),
+            priority: "high".to_string(),
+            status: "in_progress".to_string(),
+            category_id: Some(1),
+            assigned_to: Some("technician".to_string()),
+            created_by: "manager".to_string(),
+            created_at: BASE_TIME - 7200,
+            updated_at: BASE_TIME - 5400,
+            signed_off_by: None,
+            signed_off_at: None,
+            alarm_id: None,
+            required_skill: None,
+        }
+    }
+}
+
+impl Fixture for WorkOrderListResponse {
+    fn fixture() -> Self {
+        WorkOrderListResponse { work_orders: vec![WorkOrder::fixture()] }
+    }
+}
+
+impl Fixture for WorkOrderDetailResponse {
+    fn fixture() -> Self {
+        WorkOrderDetailResponse {
+            work_order: WorkOrder::fixture(),
+            checklist: vec![
+                checklist_step(1, "Isolate and lock out the drive", true),
+                checklist_step(2, "Fit the new belt and tension it", false),
+            ],
+        }
+    }
+}
+
+impl Fixture for WorkPermit {
+    fn fixture() -> Self {
+        WorkPermit {
+            id: 1,
+            work_order_id: 1,
+            machine_id: 1,
+            permit_type: "mechanical".to_string(),
+            description: Some("Belt change on the drive end".to_string()),
+            isolation_points: vec!["Main isolator Q1".to_string()],
+            valid_from: BASE_TIME - 6000,
+            valid_until: BASE_TIME + 8400,
+            status: "approved".to_string(),
+            requested_by: "technician".to_string(),
+            requested_at: BASE_TIME - 6600,
+            approved_by: Some("manager".to_string()),
+            approved_at: Some(BASE_TIME - 6300),
+            approval_signature: Some("M. Manager".to_string()),
+            closed_by: None,
+            closed_at: None,
+        }
+    }
+}
+
+impl Fixture for WorkPermitListResponse {
+    fn fixture() -> Self {
+        WorkPermitListResponse { permits: vec![WorkPermit::fixture()] }
+    }
+}
+
+impl Fixture for LaborEntry {
+    fn fixture() -> Self {
+        LaborEntry {
+            id: 1,
+            work_order_id: 1,
+            username: "technician".to_string(),
+            started_at: BASE_TIME - 5400,
+            stopped_at: Some(BASE_TIME - 1800),
+        }
+    }
+}
+
+impl Fixture for LaborListResponse {
+    fn fixture() -> Self {
+        LaborListResponse {
+            entries: vec![LaborEntry::fixture()],
+            total_hours: 1.0,
+        }
+    }
+}
+
+impl Fixture for LaborReportResponse {
+    fn fixture() -> Self {
+        LaborReportResponse {
+            plant_name: PLANT_NAME.to_string(),
+            from: BASE_TIME - 30 * 86400,
+            to: BASE_TIME,
+            machines: vec![LaborReportEntry {
+                machine_id: 1,
+                machine_name: "Conveyor A".to_string(),
+                month: "2023-11".to_string(),
+                hours: 1.0,
+                entries: 1,
+                technicians: 1,
+            }],
+        }
+    }
+}
+
+impl Fixture for ChecklistTemplateResponse {
+    fn fixture() -> Self {
+        ChecklistTemplateResponse {
+            template: ChecklistTemplate {
+                id: 1,
+                name: "Belt replacement".to_string(),
+                description: Some("Steps for changing a conveyor belt".to_string()),
+                created_at: BASE_TIME - 90 * 86400,
+            },
+            steps: WorkOrderDetailResponse::fixture()
+                .checklist
+                .into_iter()
+                .map(|step| ChecklistTemplateStep { text: step.text, required: step.required })
+                .collect(),
+        }
+    }
+}
+
+impl Fixture for ChecklistTemplateListResponse {
+    fn fixture() -> Self {
+        ChecklistTemplateListResponse { templates: vec![ChecklistTemplateResponse::fixture()] }
+    }
+}
+
+impl Fixture for CostEntry {
+    fn fixture() -> Self {
+        CostEntry {
+            id: 1,
+            machine_id: 1,
+            work_order_id: Some(1),
+            description: "Drive belt".to_string(),
+            amount: 184.5,
+            incurred_at: BASE_TIME - 5400,
+            created_by: "technician".to_string(),
+            created_at: BASE_TIME - 5400,
+        }
+    }
+}
+
+// An hour of labor at 65 plus the belt from the CostEntry fixture
+impl Fixture for CostSummary {
+    fn fixture() -> Self {
+        CostSummary {
+            labor_hours: 1.0,
+            labor_cost: 65.0,
+            parts_cost: 184.5,
+            total_cost: 249.5,
+        }
+    }
+}
+
+impl Fixture for MachineCostResponse {
+    fn fixture() -> Self {
+        MachineCostResponse {
+            machine_id: 1,
+            from: BASE_TIME - 30 * 86400,
+            to: BASE_TIME,
+            labor_rate: 65.0,
+            periods: vec![CostPeriod {
+                period: "2023-11".to_string(),
+                summary: CostSummary::fixture(),
+            }],
+            total: CostSummary::fixture(),
+        }
+    }
+}
+
+impl Fixture for MachineContract {
+    fn fixture() -> Self {
+        MachineContract {
+            id: 1,
+            machine_id: 1,
+            kind: "warranty".to_string(),
+            vendor: "Siemens".to_string(),
+            reference: Some("W-4711".to_string()),
+            coverage: Some("Drive and controls".to_string()),
+            starts_at: Some(BASE_TIME - 355 * 86400),
+            expires_at: BASE_TIME + 10 * 86400,
+            notified_at: Some(BASE_TIME - 7200),
+            created_at: BASE_TIME - 355 * 86400,
+        }
+    }
+}
+
+impl Fixture for ContractListResponse {
+    fn fixture() -> Self {
+        ContractListResponse { contracts: vec![MachineContract::fixture()] }
+    }
+}
+
+impl Fixture for ExpiredContractReportResponse {
+    fn fixture() -> Self {
+        ExpiredContractReportResponse {
+            plant_name: PLANT_NAME.to_string(),
+            as_of: BASE_TIME,
+            contracts: vec![ExpiredContractEntry {
+                machine_id: 2,
+                machine_name: "Press B".to_string(),
+                location: Some("Plant 1".to_string()),
+                machine_type: Some("Press".to_string()),
+                contract_id: 2,
+                kind: "service_contract".to_string(),
+                vendor: "Schuler Service".to_string(),
+                expires_at: BASE_TIME - 30 * 86400,
+            }],
+        }
+    }
+}
+
+impl Fixture for Notification {
+    fn fixture() -> Self {
+        Notification {
+            id: 1,
+            kind: "contract_expiry".to_string(),
+            machine_id: Some(1),
+            message: "The warranty from Siemens for machine Conveyor A expires in 10 days".to_string(),
+            created_at: BASE_TIME - 7200,
+            acknowledged_by: None,
+            acknowledged_at: None,
+        }
+    }
+}
+
+impl Fixture for NotificationListResponse {
+    fn fixture() -> Self {
+        NotificationListResponse { notifications: vec![Notification::fixture()] }
+    }
+}
+
+impl Fixture for MachineSnooze {
+    fn fixture() -> Self {
+        MachineSnooze {
+            machine_id: 1,
+            machine_name: "Conveyor A".to_string(),
+            until: BASE_TIME + 3600,
+            created_at: BASE_TIME,
+        }
+    }
+}
+
+impl Fixture for MachineSnoozeListResponse {
+    fn fixture() -> Self {
+        MachineSnoozeListResponse { snoozes: vec![MachineSnooze::fixture()] }
+    }
+}
+
+impl Fixture for AlarmRule {
+    fn fixture() -> Self {
+        AlarmRule {
+            id: 1,
+            machine_id: 2,
+            name: "Overspeed".to_string(),
+            expression: "speed > 195".to_string(),
+            severity: "warning".to_string(),
+            enabled: true,
+            created_by: "admin".to_string(),
+            created_at: BASE_TIME - 30 * 86400,
+            updated_at: BASE_TIME - 30 * 86400,
+        }
+    }
+}
+
+impl Fixture for AlarmRuleListResponse {
+    fn fixture() -> Self {
+        AlarmRuleListResponse { rules: vec![AlarmRule::fixture()] }
+    }
+}
+
+impl Fixture for Backtest {
+    fn fixture() -> Self {
+        Backtest {
+            machine_id: 2,
+            from: BASE_TIME - 7 * 86400,
+            to: BASE_TIME,
+            evaluations: 7 * 1440,
+            trips: 1,
+            active_seconds: 600,
+            timeline: vec![Trip {
+                tripped_at: BASE_TIME - 600,
+                cleared_at: None,
+                duration_seconds: 600,
+            }],
+        }
+    }
+}
+
+impl Fixture for Alarm {
+    fn fixture() -> Self {
+        Alarm {
+            id: 1,
+            rule_id: 1,
+            machine_id: 2,
+            severity: "warning".to_string(),
+            message: "Press B speed above 195".to_string(),
+            raised_at: BASE_TIME - 600,
+            cleared_at: None,
+            acknowledged_by: None,
+            acknowledged_at: None,
+            root_cause: None,
+            notes: None,
+            annotated_by: None,
+            annotated_at: None,
+        }
+    }
+}
+
+impl Fixture for AlarmListResponse {
+    fn fixture() -> Self {
+        AlarmListResponse { alarms: vec![Alarm::fixture()] }
+    }
+}
+
+impl Fixture for ComplianceLimit {
+    fn fixture() -> Self {
+        ComplianceLimit {
+            id: 1,
+            name: "Dryer temperature".to_string(),
+            metric: "temperature".to_string(),
+            machine_id: Some(3),
+            min_value: None,
+            max_value: Some(80.0),
+            unit: Some("degC".to_string()),
+            reference: Some("Permit 4.2".to_string()),
+            enabled: true,
+            created_by: "admin".to_string(),
+            created_at: BASE_TIME - 90 * 86400,
+            updated_at: BASE_TIME - 90 * 86400,
+        }
+    }
+}
+
+impl Fixture for ComplianceLimitListResponse {
+    fn fixture() -> Self {
+        ComplianceLimitListResponse { limits: vec![ComplianceLimit::fixture()] }
+    }
+}
+
+impl Fixture for ComplianceExceedance {
+    fn fixture() -> Self {
+        let limit = ComplianceLimit::fixture();
+        ComplianceExceedance {
+            id: 1,
+            limit_id: limit.id,
+            machine_id: 3,
+            limit_name: limit.name,
+            metric: limit.metric,
+            min_value: limit.min_value,
+            max_value: limit.max_value,
+            unit: limit.unit,
+            reference: limit.reference,
+            started_at: BASE_TIME - 5400,
+            ended_at: Some(BASE_TIME - 4200),
+            peak_value: 83.5,
+        }
+    }
+}
+
+impl Fixture for ComplianceExceedanceListResponse {
+    fn fixture() -> Self {
+        ComplianceExceedanceListResponse { exceedances: vec![ComplianceExceedance::fixture()] }
+    }
+}
+
+impl Fixture for FailureThreshold {
+    fn fixture() -> Self {
+        FailureThreshold {
+            id: 1,
+            metric: "vibration".to_string(),
+            machine_id: None,
+            min_value: None,
+            max_value: Some(12.0),
+            created_by: "admin".to_string(),
+            created_at: BASE_TIME - 30 * 86400,
+        }
+    }
+}
+
+impl Fixture for FailureThresholdListResponse {
+    fn fixture() -> Self {
+        FailureThresholdListResponse { thresholds: vec![FailureThreshold::fixture()] }
+    }
+}
+
+impl Fixture for FailurePredictionListResponse {
+    fn fixture() -> Self {
+        let advisory = MaintenanceAdvisory::fixture();
+        FailurePredictionListResponse {
+            predictions: vec![FailurePrediction {
+                machine_id: advisory.machine_id,
+                machine_name: advisory.machine_name,
+                model: advisory.model,
+                risk: advisory.risk,
+                metric: advisory.metric,
+                reason: advisory.reason,
+                predicted_failure_at: advisory.predicted_failure_at,
+                computed_at: advisory.updated_at,
+            }],
+        }
+    }
+}
+
+impl Fixture for MaintenanceAdvisory {
+    fn fixture() -> Self {
+        MaintenanceAdvisory {
+            id: 1,
+            machine_id: 2,
+            machine_name: "Press B".to_string(),
+            model: "trend".to_string(),
+            risk: 0.72,
+            peak_risk: 0.72,
+            metric: Some("vibration".to_string()),
+            reason: "Vibration trending towards 12.0 within 5 days".to_string(),
+            predicted_failure_at: Some(BASE_TIME + 5 * 86400),
+            raised_at: BASE_TIME - 3600,
+            updated_at: BASE_TIME - 3600,
+            cleared_at: None,
+            acknowledged_by: None,
+            acknowledged_at: None,
+        }
+    }
+}
+
+impl Fixture for MaintenanceAdvisoryListResponse {
+    fn fixture() -> Self {
+        MaintenanceAdvisoryListResponse { advisories: vec![MaintenanceAdvisory::fixture()] }
+    }
+}
+
+impl Fixture for AlarmWorkOrderPolicy {
+    fn fixture() -> Self {
+        AlarmWorkOrderPolicy::default()
+    }
+}
+
+impl Fixture for AlarmRootCause {
+    fn fixture() -> Self {
+        AlarmRootCause {
+            code: "belt_slip".to_string(),
+            description: Some("Belt slipping on the drive roller".to_string()),
+            created_at: BASE_TIME - 90 * 86400,
+        }
+    }
+}
+
+impl Fixture for AlarmRootCauseListResponse {
+    fn fixture() -> Self {
+        AlarmRootCauseListResponse { root_causes: vec![AlarmRootCause::fixture()] }
+    }
+}
+
+impl Fixture for RootCauseReportResponse {
+    fn fixture() -> Self {
+        RootCauseReportResponse {
+            plant_name: PLANT_NAME.to_string(),
+            from: BASE_TIME - 30 * 86400,
+            to: BASE_TIME,
+            root_causes: vec![RootCauseReportEntry {
+                root_cause: Some("belt_slip".to_string()),
+                alarms: 1,
+                machines: 1,
+                active_seconds: 600,
+                mean_time_to_clear_seconds: None,
+            }],
+        }
+    }
+}
+
+impl Fixture for AlarmPresentation {
+    fn fixture() -> Self {
+        alarm_presentation("warning", Some("chime"), "#f59e0b", false)
+    }
+}
+
+impl Fixture for AlarmPresentationListResponse {
+    fn fixture() -> Self {
+        AlarmPresentationListResponse {
+            severities: vec![
+                alarm_presentation("info", None, "#2563eb", false),
+                AlarmPresentation::fixture(),
+                alarm_presentation("critical", Some("siren"), "#dc2626", true),
+            ],
+        }
+    }
+}
+
+impl Fixture for CommandType {
+    fn fixture() -> Self {
+        CommandType {
+            id: 1,
+            machine_type: "Conveyor".to_string(),
+            name: "set_speed".to_string(),
+            description: Some("Change the belt speed".to_string()),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "properties": { "speed": { "type": "number" } },
+                "required": ["speed"],
+            }),
+            min_role: "manager".to_string(),
+            allowed_during_lockout: false,
+            created_at: BASE_TIME - 30 * 86400,
+            updated_at: BASE_TIME - 30 * 86400,
+        }
+    }
+}
+
+impl Fixture for CommandTypeListResponse {
+    fn fixture() -> Self {
+        CommandTypeListResponse { command_types: vec![CommandType::fixture()] }
+    }
+}
+
+impl Fixture for MachineCommand {
+    fn fixture() -> Self {
+        commands(1).remove(0)
+    }
+}
+
+impl Fixture for MachineCommandListResponse {
+    fn fixture() -> Self {
+        MachineCommandListResponse { commands: commands(1) }
+    }
+}
+
+impl Fixture for Unit {
+    fn fixture() -> Self {
+        unit("m/min", "velocity", 0.0166666667, "Meters per minute")
+    }
+}
+
+impl Fixture for UnitListResponse {
+    fn fixture() -> Self {
+        UnitListResponse {
+            units: vec![
+                unit("degC", "temperature", 1.0, "Degrees Celsius"),
+                unit("m/s", "velocity", 1.0, "Meters per second"),
+                Unit::fixture(),
+            ],
+        }
+    }
+}
+
+impl Fixture for MetricPrecision {
+    fn fixture() -> Self {
+        MetricPrecision {
+            metric: "temperature".to_string(),
+            decimals: 1,
+            updated_at: BASE_TIME - 30 * 86400,
+        }
+    }
+}
+
+impl Fixture for MetricPrecisionListResponse {
+    fn fixture() -> Self {
+        MetricPrecisionListResponse { metrics: vec![MetricPrecision::fixture()] }
+    }
+}
+
+impl Fixture for DownloadLinkResponse {
+    fn fixture() -> Self {
+        DownloadLinkResponse {
+            url: format!("/api/jobs/{}/artifact?expires={}&signature=mock_signature", JOB_ID, BASE_TIME + 3600),
+            expires_at: BASE_TIME + 3600,
+        }
+    }
+}
+
+// Taken at 02:00 on BASE_TIME's day, like the nightly backup
+impl Fixture for BackupFile {
+    fn fixture() -> Self {
+        BackupFile {
+            file_name: "scada-backup-20231114-020000.db".to_string(),
+            size_bytes: BACKUP_CONTENTS.len() as i64,
+            created_at: BASE_TIME - BASE_TIME % 86400 + 2 * 3600,
+        }
+    }
+}
+
+impl Fixture for BackupListResponse {
+    fn fixture() -> Self {
+        BackupListResponse { backups: vec![BackupFile::fixture()] }
+    }
+}
+
+impl Fixture for ReadOnlyResponse {
+    fn fixture() -> Self {
+        ReadOnlyResponse {
+            read_only: false,
+            reason: None,
+            since: None,
+        }
+    }
+}
+
+// Machine 1's gateway reports once a minute
+impl Fixture for ApiUsageResponse {
+    fn fixture() -> Self {
+        ApiUsageResponse {
+            from: BASE_TIME - 86400,
+            to: BASE_TIME,
+            requests: 1440,
+            principals: vec![ApiUsagePrincipal {
+                kind: "machine".to_string(),
+                name: Some("Conveyor A".to_string()),
+                machine_id: Some(1),
+                api_key_id: Some(1),
+                requests: 1440,
+                client_errors: 0,
+                server_errors: 0,
+                error_rate: 0.0,
+                endpoints: vec![ApiUsageEndpoint {
+                    endpoint: "POST /api/machines/update".to_string(),
+                    requests: 1440,
+                    client_errors: 0,
+                    server_errors: 0,
+                    avg_duration_ms: 3.2,
+                }],
+            }],
+        }
+    }
+}
+
+impl Fixture for KioskGroup {
+    fn fixture() -> Self {
+        KioskGroup {
+            name: "hall-1".to_string(),
+            rotation_interval_secs: Some(15),
+            machine_ids: vec![1, 2],
+            updated_by: "manager".to_string(),
+            updated_at: BASE_TIME - 7 * 86400,
+        }
+    }
+}
+
+impl Fixture for KioskGroupListResponse {
+    fn fixture() -> Self {
+        KioskGroupListResponse { groups: vec![KioskGroup::fixture()] }
+    }
+}
+
+impl Fixture for KioskRotationResponse {
+    fn fixture() -> Self {
+        let group = KioskGroup::fixture();
+        let slides = group
+            .machine_ids
+            .iter()
+            .map(|&machine_id| {
+                let history = history(machine_id);
+                KioskSlide {
+                    machine: machine(machine_id).expect("kiosk machines are fixtures"),
+                    target_speed: Some(100.0 * machine_id as f64),
+                    product: (machine_id == 1).then(|| "WIDGET-100".to_string()),
+                    last_hour: aggregates(&history[history.len() - 1..]),
+                    trend: history
+                        .iter()
+                        .map(|h| KioskTrendPoint { timestamp: h.timestamp, speed: h.speed })
+                        .collect(),
+                    active_alarms: if machine_id == 2 { 1 } else { 0 },
+                    alarm_severity: (machine_id == 2).then(|| "warning".to_string()),
+                    open_work_orders: if machine_id == 1 { 1 } else { 0 },
+                }
+            })
+            .collect();
+        KioskRotationResponse {
+            group: Some(group.name),
+            rotation_interval_secs: group.rotation_interval_secs.unwrap_or(15),
+            generated_at: BASE_TIME,
+            slides,
+        }
+    }
+}
+
+impl Fixture for DisplayToken {
+    fn fixture() -> Self {
+        DisplayToken {
+            id: 1,
+            name: "Hall 1 screen".to_string(),
+            kiosk_group: Some("hall-1".to_string()),
+            expires_at: None,
+            created_by: "admin".to_string(),
+            created_at: BASE_TIME - 7 * 86400,
+            last_used_at: Some(BASE_TIME - 60),
+            revoked_at: None,
+            token: None,
+        }
+    }
+}
+
+impl Fixture for DisplayTokenListResponse {
+    fn fixture() -> Self {
+        DisplayTokenListResponse { display_tokens: vec![DisplayToken::fixture()] }
+    }
+}
+
+impl Fixture for Product {
+    fn fixture() -> Self {
+        Product {
+            sku: "WIDGET-100".to_string(),
+            name: "Widget 100".to_string(),
+            description: Some("Standard widget".to_string()),
+            standards: vec![ProductSpeedStandard {
+                machine_type: "Conveyor".to_string(),
+                standard_speed: 100.0,
+            }],
+            updated_by: "manager".to_string(),
+            updated_at: BASE_TIME - 30 * 86400,
+        }
+    }
+}
+
+impl Fixture for ProductListResponse {
+    fn fixture() -> Self {
+        ProductListResponse { products: vec![Product::fixture()] }
+    }
+}
+
+impl Fixture for KpiDefinition {
+    fn fixture() -> Self {
+        KpiDefinition {
+            id: 1,
+            label: "Average speed".to_string(),
+            aggregation: "avg".to_string(),
+            expression: Some("speed".to_string()),
+            filter: None,
+            location: None,
+            machine_type: None,
+            kiosk_group: None,
+            target: Some(150.0),
+            unit: Some("m/min".to_string()),
+            position: 0,
+            updated_by: "admin".to_string(),
+            created_at: BASE_TIME - 30 * 86400,
+            updated_at: BASE_TIME - 30 * 86400,
+        }
+    }
+}
+
+impl Fixture for KpiDefinitionListResponse {
+    fn fixture() -> Self {
+        KpiDefinitionListResponse { kpis: vec![KpiDefinition::fixture()] }
+    }
+}
+
+// The average of the machines' current speeds: (100 + 200 + 300) / 3
+impl Fixture for KpiValuesResponse {
+    fn fixture() -> Self {
+        let kpi = KpiDefinition::fixture();
+        KpiValuesResponse {
+            generated_at: BASE_TIME,
+            from: None,
+            to: None,
+            kpis: vec![KpiValue {
+                id: kpi.id,
+                label: kpi.label,
+                value: Some(200.0),
+                target: kpi.target,
+                unit: kpi.unit,
+                machine_count: MACHINE_COUNT,
+            }],
+        }
+    }
+}
+
+impl Fixture for BenchmarkResponse {
+    fn fixture() -> Self {
+        BenchmarkResponse {
+            machine_type: "Conveyor".to_string(),
+            metric: "avg_speed".to_string(),
+            period: "month".to_string(),
+            from: BASE_TIME - 30 * 86400,
+            to: BASE_TIME,
+            statistics: Some(BenchmarkStatistics {
+                machines: 1,
+                min: 100.0,
+                p10: 100.0,
+                p25: 100.0,
+                median: 100.0,
+                p75: 100.0,
+                p90: 100.0,
+                max: 100.0,
+                mean: 100.0,
+            }),
+            machines: vec![BenchmarkEntry {
+                machine_id: 1,
+                name: "Conveyor A".to_string(),
+                code: "CNV-001".to_string(),
+                location: Some("Plant 1".to_string()),
+                value: 100.0,
+                samples: 24,
+                rank: 1,
+                percentile: 100.0,
+            }],
+        }
+    }
+}
+
+impl Fixture for HealthScoreListResponse {
+    fn fixture() -> Self {
+        HealthScoreListResponse {
+            day: Some("2023-11-13".to_string()),
+            scores: vec![MachineHealthScore {
+                machine_id: 1,
+                machine_name: "Conveyor A".to_string(),
+                machine_code: "CNV-001".to_string(),
+                location: Some("Plant 1".to_string()),
+                day: "2023-11-13".to_string(),
+                score: 92.5,
+                alarm_rate: 0.0,
+                anomalies: 0,
+                data_coverage: 0.98,
+                downtime_change: None,
+                computed_at: BASE_TIME - 3600,
+            }],
+        }
+    }
+}
+
+impl Fixture for DistributionList {
+    fn fixture() -> Self {
+        DistributionList {
+            id: 1,
+            name: "Shift leads".to_string(),
+            description: Some("Alarm escalations".to_string()),
+            members: vec![
+                DistributionListMember { email: None, username: Some("manager".to_string()) },
+                DistributionListMember { email: Some("maintenance@example.com".to_string()), username: None },
+            ],
+            recipients: vec!["manager@example.com".to_string(), "maintenance@example.com".to_string()],
+            created_by: "manager".to_string(),
+            created_at: BASE_TIME - 30 * 86400,
+            updated_at: BASE_TIME - 30 * 86400,
+        }
+    }
+}
+
+impl Fixture for DistributionListListResponse {
+    fn fixture() -> Self {
+        DistributionListListResponse { lists: vec![DistributionList::fixture()] }
+    }
+}
+
+impl Fixture for ChaosStatus {
+    fn fixture() -> Self {
+        ChaosStatus {
+            latency: None,
+            failing_db_calls: 0,
+            tasks: ["health_scores", "nightly_backup", "offline_check", "rollups"]
+                .into_iter()
+                .map(|task| (task.to_string(), true))
+                .collect(),
+        }
+    }
+}
+
+// Routes without a handler of their own answer with the fixture of their response type
+// once the caller passes the route's policy. JSON bodies are parsed, so a request of the
+// wrong shape is refused like on the real server, but nothing is stored.
+
+async fn respond<R: Policy, T: Fixture + Serialize>(headers: HeaderMap) -> ApiResult<Json<T>> {
+    R::check(&headers)?;
+    Ok(Json(T::fixture()))
+}
+
+async fn update<R: Policy, B: DeserializeOwned, T: Fixture + Serialize>(
+    headers: HeaderMap,
+    Json(_payload): Json<B>,
+) -> ApiResult<Json<T>> {
+    R::check(&headers)?;
+    Ok(Json(T::fixture()))
+}
+
+async fn create<R: Policy, B: DeserializeOwned, T: Fixture + Serialize>(
+    headers: HeaderMap,
+    Json(_payload): Json<B>,
+) -> ApiResult<(StatusCode, Json<T>)> {
+    R::check(&headers)?;
+    Ok((StatusCode::CREATED, Json(T::fixture())))
+}
+
+async fn remove<R: Policy>(headers: HeaderMap) -> ApiResult<StatusCode> {
+    R::check(&headers)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Body-less writes the real server answers with an empty 204, e.g. PUT for a grant
+async fn accept<R: Policy>(headers: HeaderMap) -> ApiResult<StatusCode> {
+    remove::<R>(headers).await
+}
+
+async fn accept_with<R: Policy, B: DeserializeOwned>(headers: HeaderMap, Json(_payload): Json<B>) -> ApiResult<StatusCode> {
+    R::check(&headers)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn queue<R: Policy>(headers: HeaderMap) -> ApiResult<(StatusCode, Json<JobCreatedResponse>)> {
+    R::check(&headers)?;
+    Ok((StatusCode::ACCEPTED, Json(JobCreatedResponse::fixture())))
+}
+
+async fn queue_with<R: Policy, B: DeserializeOwned>(
+    headers: HeaderMap,
+    Json(_payload): Json<B>,
+) -> ApiResult<(StatusCode, Json<JobCreatedResponse>)> {
+    queue::<R>(headers).await
+}
+
+fn attachment(content_type: &str, file_name: &str, contents: impl Into<Vec<u8>>) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+        ],
+        contents.into(),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct MockExportQuery {
+    format: Option<String>,
+}
+
+// Tabular exports come as CSV only; the real server's XLSX rendering is not mocked
+fn csv_export(params: &MockExportQuery, file_name: &str, contents: &str) -> ApiResult<Response> {
+    match params.format.as_deref() {
+        None | Some("csv") => Ok(attachment("text/csv", file_name, contents)),
+        Some(format) => Err(error(StatusCode::NOT_IMPLEMENTED, &format!("The mock server only exports CSV, not {}", format))),
+    }
+}
+
+// POST /api/login
+async fn login(Json(payload): Json<LoginRequest>) -> ApiResult<Json<LoginResponse>> {
+    let token = match (payload.username.as_str(), payload.password.as_str()) {
+        ("admin", "admin123") => ADMIN_TOKEN,
+        ("manager", "manager123") => MANAGER_TOKEN,
+        ("technician", "tech123") => USER_TOKEN,
+        _ => return Err(error(StatusCode::UNAUTHORIZED, "Invalid credentials")),
+    };
+    Ok(Json(session(token, payload.username)))
+}
+
+fn session(token: &str, username: String) -> LoginResponse {
+    LoginResponse {
+        token: token.to_string(),
+        role: token_role(token).unwrap_or_default().to_string(),
+        username,
+        expires_at: BASE_TIME + 15 * 60,
+        refresh_token: format!("mock_refresh_{}", token),
+        refresh_expires_at: BASE_TIME + 30 * 24 * 3600,
+    }
+}
+
+fn username_of(token: &str) -> String {
+    match token {
+        USER_TOKEN => "technician".to_string(),
+        _ => token_role(token).unwrap_or_default().to_string(),
+    }
+}
+
+// POST /api/token/refresh
+// Refresh tokens are "mock_refresh_<token>" and stay valid, so refreshing never rotates
+async fn refresh(Json(payload): Json<RefreshTokenRequest>) -> ApiResult<Json<LoginResponse>> {
+    let token = payload
+        .refresh_token
+        .strip_prefix("mock_refresh_")
+        .filter(|token| token_role(token).is_some())
+        .ok_or_else(|| error(StatusCode::UNAUTHORIZED, "Invalid refresh token"))?;
+    Ok(Json(session(token, username_of(token))))
+}
+
+// POST /api/users/me/password
+async fn change_password(headers: HeaderMap, Json(_payload): Json<ChangePasswordRequest>) -> ApiResult<Json<LoginResponse>> {
+    require_user(&headers)?;
+    let token = bearer(&headers)?;
+    Ok(Json(session(token, username_of(token))))
+}
+
+// GET /api/info
+async fn get_info() -> Json<InfoResponse> {
+    Json(InfoResponse {
+        name: "scada-mock".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        features: Vec::<FeatureFlag>::fixture().into_iter().map(|flag| (flag.name, flag.enabled)).collect(),
+        read_only: ReadOnlyResponse::fixture().read_only,
+        site: SiteSettings::fixture(),
+    })
+}
+
+// GET /api/machines
+async fn list_machines(headers: HeaderMap) -> ApiResult<Json<MachineListResponse>> {
+    require_user(&headers)?;
+    let machines = (1..=MACHINE_COUNT).map(machine).collect::<ApiResult<Vec<_>>>()?;
+    Ok(Json(MachineListResponse { machines }))
+}
+
+#[derive(Deserialize)]
+struct MockChangesQuery {
+    cursor: Option<i64>,
+    timeout: Option<u64>,
+}
+
+// GET /api/machines/changes
+// Callers already at CHANGE_CURSOR wait out their timeout and see no changes, as nothing
+// ever changes here
+async fn wait_for_machine_changes(headers: HeaderMap, Query(params): Query<MockChangesQuery>) -> ApiResult<Json<MachineChangesResponse>> {
+    require_user(&headers)?;
+    let machines = if params.cursor.is_some_and(|cursor| cursor >= CHANGE_CURSOR) {
+        tokio::time::sleep(Duration::from_secs(params.timeout.unwrap_or(30).clamp(1, 120))).await;
+        Vec::new()
+    } else {
+        (1..=MACHINE_COUNT).map(machine).collect::<ApiResult<Vec<_>>>()?
+    };
+    Ok(Json(MachineChangesResponse {
+        machines,
+        archived: Vec::new(),
+        cursor: CHANGE_CURSOR,
+        timestamp: BASE_TIME,
+    }))
+}
+
+// GET /api/machines/{id}/full
+async fn get_machine_detail(headers: HeaderMap, Path(machine_id): Path<i64>) -> ApiResult<Json<MachineDetailResponse>> {
+    require_user(&headers)?;
+    let machine = machine(machine_id)?;
+
+    Ok(Json(MachineDetailResponse {
+        machine,
+        last_24h: aggregates(&history(machine_id)),
+        recent_comments: comments(machine_id),
+        open_work_orders: Vec::new(),
+        operators: Vec::new(),
+        active_permits: Vec::new(),
+        active_alarms: Vec::new(),
+    }))
+}
+
+#[derive(Deserialize)]
+struct MockHistoryQuery {
+    limit: Option<usize>,
+}
+
+// GET /api/machines/{id}/history
+async fn get_history(
+    headers: HeaderMap,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<MockHistoryQuery>,
+) -> ApiResult<Json<HistoryResponse>> {
+    require_user(&headers)?;
+    machine(machine_id)?;
+
+    let mut history = history(machine_id);
+    history.reverse();
+    history.truncate(params.limit.unwrap_or(100));
+    Ok(Json(HistoryResponse { history, resolution: "raw".to_string(), annotations: Vec::new() }))
+}
+
+// GET /api/machines/{id}/comments
+async fn get_comments(headers: HeaderMap, Path(machine_id): Path<i64>) -> ApiResult<Json<CommentListResponse>> {
+    require_user(&headers)?;
+    machine(machine_id)?;
+    Ok(Json(CommentListResponse { comments: comments(machine_id) }))
+}
+
+// POST /api/machines/{id}/comments
+// Echoes the comment back without storing it, so later reads stay deterministic
+async fn add_comment(
+    headers: HeaderMap,
+    Path(machine_id): Path<i64>,
+    Json(payload): Json<AddCommentRequest>,
+) -> ApiResult<(StatusCode, Json<MaintenanceComment>)> {
+    require_user(&headers)?;
+    machine(machine_id)?;
+
+    let priority = payload.priority.unwrap_or_else(|| "normal".to_string());
+    if !PRIORITIES.contains(&priority.as_str()) {
+        return Err(error(StatusCode::BAD_REQUEST, "Invalid priority. Must be one of: low, normal, high, critical"));
+    }
+
+    Ok((StatusCode::CREATED, Json(MaintenanceComment {
+        id: 1000,
+        machine_id,
+        comment: payload.comment,
+        priority,
+        username: "admin".to_string(),
+        created_at: BASE_TIME,
+        category_id: payload.category_id,
+        pinned: false,
+        resolved_by: None,
+        resolved_at: None,
+    })))
+}
+
+// GET /api/comments/export
+async fn export_comments(headers: HeaderMap, Query(params): Query<MockExportQuery>) -> ApiResult<Response> {
+    require_manager(&headers)?;
+    csv_export(
+        &params,
+        "comments.csv",
+        "created_at,machine,code,location,author,priority,category,comment\n\
+         2023-11-13 22:13,Conveyor A,CNV-001,Plant 1,operator,high,Mechanical,Bearing noise reported\n\
+         2023-11-14 21:13,Conveyor A,CNV-001,Plant 1,technician,normal,Mechanical,Belt tension adjusted\n",
+    )
+}
+
+// GET /api/alarms/export
+async fn export_alarms(headers: HeaderMap, Query(params): Query<MockExportQuery>) -> ApiResult<Response> {
+    require_manager(&headers)?;
+    csv_export(
+        &params,
+        "alarms.csv",
+        "raised_at,cleared_at,machine,code,severity,message,acknowledged_by,root_cause,notes\n\
+         2023-11-14 22:03:20,,Press B,PRS-002,warning,Press B speed above 195,,,\n",
+    )
+}
+
+// GET /api/reports/compliance-exceedances
+async fn compliance_exceedance_report(headers: HeaderMap, Query(params): Query<MockExportQuery>) -> ApiResult<Response> {
+    require_manager(&headers)?;
+    csv_export(
+        &params,
+        "compliance-2023-11.csv",
+        "started_at,ended_at,minutes_in_month,machine,code,limit,reference,metric,min_value,max_value,peak_value,unit\n\
+         2023-11-14 20:43:20,2023-11-14 21:03:20,20,Mixer C,MIX-003,Dryer temperature,Permit 4.2,temperature,,80,83.5,degC\n",
+    )
+}
+
+// GET /api/machine-archives/{id}/download
+async fn download_machine_archive(headers: HeaderMap) -> ApiResult<Response> {
+    require_admin(&headers)?;
+    Ok(attachment("application/zip", "machine-MIX-003.zip", ARCHIVE_CONTENTS))
+}
+
+// PUT /api/documents/{id}/file
+async fn upload_document_file(headers: HeaderMap, Query(params): Query<BTreeMap<String, String>>, body: Bytes) -> ApiResult<Json<MachineDocument>> {
+    require_admin(&headers)?;
+    if body.is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "The file is empty"));
+    }
+    Ok(Json(MachineDocument {
+        file_name: params.get("file_name").cloned(),
+        ..MachineDocument::fixture()
+    }))
+}
+
+// GET /api/documents/{id}/file
+async fn download_document_file(headers: HeaderMap) -> ApiResult<Response> {
+    require_user(&headers)?;
+    Ok(attachment("application/pdf", "conveyor-manual.pdf", DOCUMENT_CONTENTS))
+}
+
+// GET /api/jobs/{id}/artifact
+// Signed download links work without a token, like on the real server
+async fn download_job_artifact(headers: HeaderMap, Query(params): Query<BTreeMap<String, String>>) -> ApiResult<Response> {
+    if !params.contains_key("signature") {
+        require_user(&headers)?;
+    }
+    let csv = history(1).iter().fold("timestamp,speed\n".to_string(), |csv, h| csv + &format!("{},{}\n", h.timestamp, h.speed));
+    Ok(attachment("text/csv", "history-CNV-001.csv", csv))
+}
+
+// GET /api/jobs/{id}/artifact/link
+async fn create_download_link(headers: HeaderMap) -> ApiResult<Json<DownloadLinkResponse>> {
+    require_user(&headers)?;
+    let link = DownloadLinkResponse::fixture();
+    let host = headers.get(header::HOST).and_then(|v| v.to_str().ok()).unwrap_or("localhost:8080");
+    Ok(Json(DownloadLinkResponse { url: format!("http://{}{}", host, link.url), ..link }))
+}
+
+// POST /api/machines/{id}/api-keys; the key is only part of the answer when created
+async fn create_machine_api_key(headers: HeaderMap, Json(_payload): Json<CreateMachineApiKeyRequest>) -> ApiResult<(StatusCode, Json<MachineApiKey>)> {
+    require_admin(&headers)?;
+    Ok((StatusCode::CREATED, Json(MachineApiKey {
+        api_key: Some("mock_machine_key_1".to_string()),
+        ..MachineApiKey::fixture()
+    })))
+}
+
+// POST /api/service-accounts
+async fn create_service_account(headers: HeaderMap, Json(_payload): Json<CreateServiceAccountRequest>) -> ApiResult<(StatusCode, Json<ServiceAccount>)> {
+    require_admin(&headers)?;
+    Ok((StatusCode::CREATED, Json(ServiceAccount {
+        api_key: Some("mock_service_account_key".to_string()),
+        ..ServiceAccount::fixture()
+    })))
+}
+
+// POST /api/service-accounts/{id}/rotate-key
+async fn rotate_service_account_key(headers: HeaderMap, Json(_payload): Json<RotateMachineKeyRequest>) -> ApiResult<Json<ServiceAccount>> {
+    require_admin(&headers)?;
+    Ok(Json(ServiceAccount {
+        rotated_at: Some(BASE_TIME),
+        previous_key_expires_at: Some(BASE_TIME + 3600),
+        api_key: Some("mock_service_account_key".to_string()),
+        ..ServiceAccount::fixture()
+    }))
+}
+
+// POST /api/display-tokens
+async fn create_display_token(headers: HeaderMap, Json(_payload): Json<CreateDisplayTokenRequest>) -> ApiResult<(StatusCode, Json<DisplayToken>)> {
+    require_admin(&headers)?;
+    Ok((StatusCode::CREATED, Json(DisplayToken {
+        token: Some("mock_display_token".to_string()),
+        ..DisplayToken::fixture()
+    })))
+}
+
+#[derive(Deserialize)]
+struct MockBackupQuery {
+    #[serde(default)]
+    download: bool,
+}
+
+// POST /api/admin/backup
+async fn create_backup(headers: HeaderMap, Query(params): Query<MockBackupQuery>) -> ApiResult<Response> {
+    require_admin(&headers)?;
+    let backup = BackupFile::fixture();
+    if params.download {
+        return Ok(attachment("application/vnd.sqlite3", &backup.file_name, BACKUP_CONTENTS));
+    }
+    Ok((StatusCode::CREATED, Json(backup)).into_response())
+}
+
+// PUT /api/site-settings/logo
+async fn upload_site_logo(headers: HeaderMap, body: Bytes) -> ApiResult<Json<SiteSettings>> {
+    require_admin(&headers)?;
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+    if !content_type.starts_with("image/") {
+        return Err(error(StatusCode::UNSUPPORTED_MEDIA_TYPE, "The logo must be an image"));
+    }
+    if body.is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "The logo is empty"));
+    }
+    Ok(Json(SiteSettings::fixture()))
+}
+
+// GET /api/site-settings/logo
+async fn download_site_logo() -> Response {
+    ([(header::CONTENT_TYPE, "image/svg+xml")], LOGO_CONTENTS).into_response()
+}
+
+// POST /api/machines/update
+async fn update_machine_speed(headers: HeaderMap, Json(_payload): Json<SpeedUpdateRequest>) -> ApiResult<Json<UpdateResponse>> {
+    machine_from_key(&headers)?;
+    Ok(Json(UpdateResponse {
+        success: true,
+        timestamp: BASE_TIME,
+        flagged: Vec::new(),
+        deduplicated: false,
+    }))
+}
+
+// GET /api/machines/me
+async fn get_own_machine(headers: HeaderMap) -> ApiResult<Json<MachineSelfResponse>> {
+    let machine = machine(machine_from_key(&headers)?)?;
+    Ok(Json(MachineSelfResponse {
+        id: machine.id,
+        name: machine.name,
+        code: machine.code,
+        location: machine.location,
+        machine_type: machine.machine_type,
+        target_speed: Some(100.0 * machine.id as f64),
+        report_interval: machine.report_interval,
+        pending_commands: commands(machine.id).len() as i64,
+    }))
+}
+
+// GET /api/machines/commands
+// Hands the pending commands out as delivered every time, so later polls stay deterministic
+async fn poll_commands(headers: HeaderMap) -> ApiResult<Json<MachineCommandListResponse>> {
+    let machine_id = machine_from_key(&headers)?;
+    let commands = commands(machine_id)
+        .into_iter()
+        .map(|command| MachineCommand {
+            status: "delivered".to_string(),
+            delivered_at: Some(BASE_TIME),
+            ..command
+        })
+        .collect();
+    Ok(Json(MachineCommandListResponse { commands }))
+}
+
+// POST /api/machines/commands/{id}/result
+async fn report_command_result(
+    headers: HeaderMap,
+    Path(command_id): Path<i64>,
+    Json(payload): Json<CommandResultRequest>,
+) -> ApiResult<Json<MachineCommand>> {
+    let machine_id = machine_from_key(&headers)?;
+    let command = commands(machine_id)
+        .into_iter()
+        .find(|command| command.id == command_id)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Command not found"))?;
+    Ok(Json(MachineCommand {
+        status: if payload.success { "completed" } else { "failed" }.to_string(),
+        delivered_at: Some(BASE_TIME),
+        completed_at: Some(BASE_TIME),
+        result: payload.result,
+        ..command
+    }))
+}
+
+// Endpoints of the real server the mock has no fixtures for
+async fn not_implemented(method: Method, uri: Uri) -> (StatusCode, Json<ErrorResponse>) {
+    error(StatusCode::NOT_IMPLEMENTED, &format!("{} {} is not implemented by the mock server", method, uri.path()))
+}
+
+fn app() -> Router {
+    use roles::{Admin, Manager, Public, User};
+
+    Router::new()
+        // Authentication and server
+        .route("/api/login", post(login))
+        .route("/api/password-policy", get(respond::<Public, PasswordPolicyResponse>))
+        .route("/api/token/refresh", post(refresh))
+        .route("/api/logout", post(accept_with::<Public, LogoutRequest>))
+        .route("/api/info", get(get_info))
+        // Machines
+        .route("/api/machines", get(list_machines).post(create::<Admin, CreateMachineRequest, MachineResponse>))
+        .route("/api/machines/update", post(update_machine_speed))
+        .route("/api/machines/me", get(get_own_machine))
+        .route("/api/machines/changes", get(wait_for_machine_changes))
+        .route("/api/machines/commands", get(poll_commands))
+        .route("/api/machines/commands/{id}/result", post(report_command_result))
+        .route("/api/machines/{id}", put(update::<Admin, UpdateMachineRequest, MachineResponse>).delete(remove::<Admin>))
+        .route("/api/machines/{id}/restore", post(accept::<Admin>))
+        .route("/api/machines/{id}/full", get(get_machine_detail))
+        .route("/api/machines/{id}/history", get(get_history))
+        .route("/api/machines/{id}/gaps", get(respond::<User, MachineGapsResponse>))
+        .route("/api/machines/{id}/history/export", post(queue_with::<User, HistoryExportRequest>))
+        .route("/api/machines/{id}/history/shift", post(queue_with::<Admin, HistoryShiftRequest>))
+        .route("/api/machines/{id}/history/shifts", get(respond::<Admin, HistoryShiftListResponse>))
+        .route("/api/history-shifts/{id}/undo", post(queue::<Admin>))
+        .route("/api/export-requests", get(respond::<User, ExportRequestListResponse>).post(create::<User, CreateExportRequest, ExportRequest>))
+        .route("/api/export-requests/{id}", get(respond::<User, ExportRequest>))
+        .route("/api/export-requests/{id}/approve", post(update::<Manager, DecideExportRequest, ExportRequest>))
+        .route("/api/export-requests/{id}/reject", post(update::<Manager, DecideExportRequest, ExportRequest>))
+        .route("/api/machines/{id}/merge", post(update::<Admin, MergeMachinesRequest, MachineMergeResponse>))
+        .route("/api/machines/{id}/decommission", post(queue::<Admin>))
+        .route("/api/machine-archives", get(respond::<Admin, MachineArchiveListResponse>))
+        .route("/api/machine-archives/{id}/download", get(download_machine_archive))
+        .route("/api/machines/{id}/metrics", get(respond::<User, MachineMetricListResponse>))
+        .route("/api/machines/{id}/display-names", get(respond::<User, DisplayNameListResponse>))
+        .route(
+            "/api/machines/{id}/display-names/{locale}",
+            put(update::<Admin, SetDisplayNameRequest, MachineDisplayName>).delete(remove::<Admin>),
+        )
+        // Trend annotations, batches, changeovers and assignments
+        .route(
+            "/api/machines/{id}/annotations",
+            get(respond::<User, TrendAnnotationListResponse>).post(create::<User, CreateTrendAnnotationRequest, TrendAnnotation>),
+        )
+        .route("/api/annotations/{id}", delete(remove::<User>))
+        .route(
+            "/api/machines/{id}/batches",
+            get(respond::<User, MachineBatchListResponse>).post(create::<User, StartBatchRequest, MachineBatch>),
+        )
+        .route("/api/batches", get(respond::<User, MachineBatchListResponse>))
+        .route("/api/batches/{id}/end", post(respond::<User, MachineBatch>))
+        .route(
+            "/api/machines/{id}/changeovers",
+            get(respond::<User, ChangeoverListResponse>).post(create::<User, StartChangeoverRequest, Changeover>),
+        )
+        .route("/api/changeovers/{id}/end", post(respond::<User, Changeover>))
+        .route("/api/machines/{id}/assignments", post(create::<Manager, AssignOperatorRequest, OperatorAssignment>))
+        .route("/api/assignments/{id}", delete(remove::<Manager>))
+        .route("/api/shift-schedule", get(respond::<Manager, ShiftScheduleResponse>))
+        // Maintenance comments
+        .route("/api/machines/{id}/comments", get(get_comments).post(add_comment))
+        .route("/api/comments", get(respond::<User, CommentFeedResponse>))
+        .route("/api/comments/{id}/pin", post(respond::<User, MaintenanceComment>).delete(respond::<User, MaintenanceComment>))
+        .route("/api/comments/{id}/resolve", post(respond::<User, MaintenanceComment>).delete(respond::<User, MaintenanceComment>))
+        .route("/api/comments/export", get(export_comments))
+        .route(
+            "/api/comment-categories",
+            get(respond::<User, CommentCategoryListResponse>).post(create::<Admin, CreateCommentCategoryRequest, CommentCategory>),
+        )
+        // Machine API keys
+        .route("/api/machines/{id}/api-keys", get(respond::<Admin, MachineApiKeyListResponse>).post(create_machine_api_key))
+        .route("/api/machines/{id}/rotate-key", post(update::<Admin, RotateMachineKeyRequest, MachineKeyRotationResponse>))
+        .route(
+            "/api/machine-api-keys/{id}",
+            put(update::<Admin, UpdateMachineApiKeyRequest, MachineApiKey>).delete(remove::<Admin>),
+        )
+        // Documents
+        .route(
+            "/api/machines/{id}/documents",
+            get(respond::<User, DocumentListResponse>).post(create::<Admin, CreateDocumentRequest, MachineDocument>),
+        )
+        .route("/api/documents/{id}", put(update::<Admin, UpdateDocumentRequest, MachineDocument>).delete(remove::<Admin>))
+        .route("/api/documents/{id}/file", put(upload_document_file).get(download_document_file))
+        // Work orders, permits and labor
+        .route(
+            "/api/machines/{id}/work-orders",
+            get(respond::<User, WorkOrderListResponse>).post(create::<User, CreateWorkOrderRequest, WorkOrderDetailResponse>),
+        )
+        .route(
+            "/api/work-orders/{id}",
+            get(respond::<User, WorkOrderDetailResponse>).put(update::<User, UpdateWorkOrderRequest, WorkOrderDetailResponse>),
+        )
+        .route("/api/work-orders/{id}/checklist", post(update::<User, AttachChecklistRequest, WorkOrderDetailResponse>))
+        .route("/api/work-orders/{id}/steps/{step_id}", put(update::<User, UpdateWorkOrderStepRequest, WorkOrderDetailResponse>))
+        .route("/api/work-orders/{id}/sign-off", post(respond::<User, WorkOrderDetailResponse>))
+        .route(
+            "/api/work-orders/{id}/permits",
+            get(respond::<User, WorkPermitListResponse>).post(create::<User, CreateWorkPermitRequest, WorkPermit>),
+        )
+        .route("/api/permits/{id}/approve", post(update::<Manager, ApproveWorkPermitRequest, WorkPermit>))
+        .route("/api/permits/{id}/close", post(respond::<User, WorkPermit>))
+        .route("/api/work-orders/{id}/labor", get(respond::<User, LaborListResponse>))
+        .route("/api/work-orders/{id}/labor/start", post(respond::<User, LaborEntry>))
+        .route("/api/work-orders/{id}/labor/stop", post(respond::<User, LaborEntry>))
+        .route(
+            "/api/checklist-templates",
+            get(respond::<User, ChecklistTemplateListResponse>)
+                .post(create::<Admin, CreateChecklistTemplateRequest, ChecklistTemplateResponse>),
+        )
+        // Costs and contracts
+        .route("/api/machines/{id}/costs", get(respond::<User, MachineCostResponse>).post(create::<User, CreateCostRequest, CostEntry>))
+        .route(
+            "/api/machines/{id}/contracts",
+            get(respond::<User, ContractListResponse>).post(create::<Admin, CreateContractRequest, MachineContract>),
+        )
+        .route("/api/contracts/{id}", put(update::<Admin, UpdateContractRequest, MachineContract>).delete(remove::<Admin>))
+        // Notifications
+        .route("/api/notifications", get(respond::<User, NotificationListResponse>))
+        .route("/api/notifications/{id}/acknowledge", post(respond::<User, Notification>))
+        .route("/api/machines/{id}/snooze", post(respond::<User, MachineSnooze>).delete(remove::<User>))
+        .route("/api/users/me/snoozes", get(respond::<User, MachineSnoozeListResponse>))
+        // Alarms, compliance and predictions
+        .route(
+            "/api/machines/{id}/alarm-rules",
+            get(respond::<User, AlarmRuleListResponse>).post(create::<Admin, CreateAlarmRuleRequest, AlarmRule>),
+        )
+        .route("/api/alarm-rules/{id}", put(update::<Admin, UpdateAlarmRuleRequest, AlarmRule>))
+        .route("/api/alarm-rules/backtest", post(update::<User, BacktestAlarmRuleRequest, Backtest>))
+        .route("/api/alarms", get(respond::<User, AlarmListResponse>))
+        .route("/api/alarms/{id}/acknowledge", post(respond::<User, Alarm>))
+        .route("/api/alarms/{id}/annotation", put(update::<User, AnnotateAlarmRequest, Alarm>))
+        .route("/api/alarms/export", get(export_alarms))
+        .route(
+            "/api/compliance-limits",
+            get(respond::<Manager, ComplianceLimitListResponse>).post(create::<Admin, CreateComplianceLimitRequest, ComplianceLimit>),
+        )
+        .route(
+            "/api/compliance-limits/{id}",
+            put(update::<Admin, UpdateComplianceLimitRequest, ComplianceLimit>).delete(remove::<Admin>),
+        )
+        .route("/api/compliance-exceedances", get(respond::<Manager, ComplianceExceedanceListResponse>))
+        .route("/api/reports/compliance-exceedances", get(compliance_exceedance_report))
+        .route(
+            "/api/failure-thresholds",
+            get(respond::<Manager, FailureThresholdListResponse>).post(create::<Admin, CreateFailureThresholdRequest, FailureThreshold>),
+        )
+        .route("/api/failure-thresholds/{id}", delete(remove::<Admin>))
+        .route("/api/predictions", get(respond::<User, FailurePredictionListResponse>))
+        .route("/api/advisories", get(respond::<User, MaintenanceAdvisoryListResponse>))
+        .route("/api/advisories/{id}/acknowledge", post(respond::<User, MaintenanceAdvisory>))
+        .route("/api/admin/predictions/run", post(queue::<Admin>))
+        .route(
+            "/api/alarm-work-order-policy",
+            get(respond::<Manager, AlarmWorkOrderPolicy>).put(update::<Admin, UpdateAlarmWorkOrderPolicyRequest, AlarmWorkOrderPolicy>),
+        )
+        .route(
+            "/api/alarm-root-causes",
+            get(respond::<User, AlarmRootCauseListResponse>).post(create::<Admin, CreateAlarmRootCauseRequest, AlarmRootCause>),
+        )
+        .route("/api/alarm-presentation", get(respond::<User, AlarmPresentationListResponse>))
+        .route("/api/alarm-presentation/{severity}", put(update::<Admin, UpdateAlarmPresentationRequest, AlarmPresentation>))
+        // Commands
+        .route(
+            "/api/command-types",
+            get(respond::<User, CommandTypeListResponse>).post(create::<Admin, CreateCommandTypeRequest, CommandType>),
+        )
+        .route("/api/command-types/{id}", put(update::<Admin, UpdateCommandTypeRequest, CommandType>).delete(remove::<Admin>))
+        .route(
+            "/api/machines/{id}/commands",
+            get(respond::<User, MachineCommandListResponse>).post(create::<User, SendCommandRequest, MachineCommand>),
+        )
+        .route("/api/machines/{id}/lockout", put(update::<Manager, LockoutRequest, Machine>).delete(respond::<Manager, Machine>))
+        .route("/api/commands/{id}", delete(respond::<Manager, MachineCommand>))
+        // Units and precision
+        .route("/api/units", get(respond::<User, UnitListResponse>).post(create::<Admin, Unit, Unit>))
+        .route("/api/metric-precision", get(respond::<User, MetricPrecisionListResponse>))
+        .route("/api/metric-precision/{metric}", put(update::<Admin, UpdateMetricPrecisionRequest, MetricPrecision>))
+        // Reports
+        .route("/api/reports/comments-by-category", get(respond::<Manager, CategoryReportResponse>))
+        .route("/api/reports/alarm-root-causes", get(respond::<Manager, RootCauseReportResponse>))
+        .route("/api/reports/labor-hours", get(respond::<Manager, LaborReportResponse>))
+        .route("/api/reports/changeovers", get(respond::<Manager, ChangeoverReportResponse>))
+        .route("/api/reports/operators", get(respond::<Manager, OperatorReportResponse>))
+        .route("/api/reports/expired-contracts", get(respond::<Manager, ExpiredContractReportResponse>))
+        .route("/api/reports/history-gaps", get(respond::<Manager, GapReportResponse>))
+        // Background jobs
+        .route("/api/jobs/{id}", get(respond::<User, JobResponse>))
+        .route("/api/jobs/{id}/artifact", get(download_job_artifact))
+        .route("/api/jobs/{id}/artifact/link", get(create_download_link))
+        // Users, sessions and access
+        .route("/api/users", get(respond::<Admin, UserListResponse>).post(create::<Admin, CreateUserRequest, scada_models::User>))
+        .route("/api/users/{id}", put(update::<Admin, UpdateUserRequest, scada_models::User>))
+        .route("/api/users/me/password", post(change_password))
+        .route("/api/password-reset/request", post(accept_with::<Public, PasswordResetRequest>))
+        .route("/api/password-reset/confirm", post(accept_with::<Public, PasswordResetConfirmRequest>))
+        .route("/api/users/{id}/unlock", post(accept::<Admin>))
+        .route("/api/users/{id}/deactivate", post(accept::<Admin>))
+        .route("/api/users/{id}/reactivate", post(accept::<Admin>))
+        .route("/api/users/{id}/revoke-tokens", post(accept::<Admin>))
+        .route("/api/users/me/sessions", get(respond::<User, SessionListResponse>))
+        .route(
+            "/api/users/me/views/machines",
+            get(respond::<User, MachineListView>).put(update::<User, MachineListView, MachineListView>),
+        )
+        .route("/api/users/{id}/sessions", get(respond::<Admin, SessionListResponse>))
+        .route("/api/users/{id}/logins", get(respond::<Admin, LoginHistoryResponse>))
+        .route("/api/sessions/{id}", delete(remove::<User>))
+        .route("/api/admin/impersonate/{user_id}", post(update::<Admin, ImpersonateRequest, ImpersonationResponse>))
+        .route("/api/admin/impersonations", get(respond::<Admin, ImpersonationListResponse>))
+        .route("/api/service-accounts", get(respond::<Admin, ServiceAccountListResponse>).post(create_service_account))
+        .route(
+            "/api/service-accounts/{id}",
+            put(update::<Admin, UpdateServiceAccountRequest, ServiceAccount>).delete(remove::<Admin>),
+        )
+        .route("/api/service-accounts/{id}/rotate-key", post(rotate_service_account_key))
+        .route("/api/users/{id}/certifications", get(respond::<Manager, CertificationListResponse>))
+        .route(
+            "/api/users/{id}/certifications/{skill}",
+            put(update::<Manager, SetCertificationRequest, Certification>).delete(remove::<Manager>),
+        )
+        .route("/api/certifications", get(respond::<Manager, CertificationListResponse>))
+        .route("/api/users/{id}/machines", get(respond::<Admin, MachineAccessListResponse>))
+        .route("/api/users/{id}/machines/{machine_id}", put(accept::<Admin>).delete(remove::<Admin>))
+        // Administration
+        .route("/api/admin/sandbox", post(queue_with::<Admin, SandboxRequest>))
+        .route("/api/admin/backup", post(create_backup))
+        .route("/api/admin/backups", get(respond::<Admin, BackupListResponse>))
+        .route("/api/admin/read-only", get(respond::<Admin, ReadOnlyResponse>).put(update::<Admin, UpdateReadOnlyRequest, ReadOnlyResponse>))
+        .route("/api/admin/features", get(respond::<Admin, Vec<FeatureFlag>>))
+        .route("/api/admin/features/{name}", put(update::<Admin, UpdateFeatureFlagRequest, FeatureFlag>))
+        .route("/api/admin/usage", get(respond::<Admin, ApiUsageResponse>))
+        .route("/api/admin/history/purge", post(queue::<Admin>))
+        // Kiosks, display tokens and products
+        .route("/api/kiosk/rotation", get(respond::<User, KioskRotationResponse>))
+        .route("/api/kiosk/groups", get(respond::<User, KioskGroupListResponse>))
+        .route("/api/kiosk/groups/{name}", put(update::<Manager, SetKioskGroupRequest, KioskGroup>).delete(remove::<Manager>))
+        .route("/api/display-tokens", get(respond::<Admin, DisplayTokenListResponse>).post(create_display_token))
+        .route("/api/display-tokens/{id}", delete(remove::<Admin>))
+        .route("/api/products", get(respond::<User, ProductListResponse>))
+        .route("/api/products/{sku}", put(update::<Manager, SetProductRequest, Product>).delete(remove::<Manager>))
+        // KPIs and analytics
+        .route("/api/kpis", get(respond::<User, KpiValuesResponse>))
+        .route("/api/analytics/benchmark", get(respond::<Manager, BenchmarkResponse>))
+        .route("/api/health-scores", get(respond::<User, HealthScoreListResponse>))
+        .route(
+            "/api/kpi-definitions",
+            get(respond::<User, KpiDefinitionListResponse>).post(create::<Admin, SetKpiDefinitionRequest, KpiDefinition>),
+        )
+        .route("/api/kpi-definitions/{id}", put(update::<Admin, SetKpiDefinitionRequest, KpiDefinition>).delete(remove::<Admin>))
+        // Distribution lists
+        .route(
+            "/api/distribution-lists",
+            get(respond::<Manager, DistributionListListResponse>)
+                .post(create::<Manager, CreateDistributionListRequest, DistributionList>),
+        )
+        .route(
+            "/api/distribution-lists/{id}",
+            get(respond::<Manager, DistributionList>)
+                .put(update::<Manager, UpdateDistributionListRequest, DistributionList>)
+                .delete(remove::<Manager>),
+        )
+        .route("/api/distribution-lists/{id}/test", post(respond::<Manager, DistributionList>))
+        // Site settings
+        .route("/api/site-settings", get(respond::<User, SiteSettings>).put(update::<Admin, UpdateSiteSettingsRequest, SiteSettings>))
+        .route("/api/site-settings/logo", put(upload_site_logo).get(download_site_logo).delete(remove::<Admin>))
+        // Chaos testing
+        .route("/api/dev/chaos", get(respond::<Admin, ChaosStatus>).delete(respond::<Admin, ChaosStatus>))
+        .route("/api/dev/chaos/latency", put(update::<Admin, ChaosLatency, ChaosStatus>))
+        .route("/api/dev/chaos/db-failures", put(update::<Admin, FailDbCallsRequest, ChaosStatus>))
+        .route("/api/dev/chaos/tasks/{name}/kill", post(respond::<Admin, ChaosStatus>))
+        .fallback(not_implemented)
+        .method_not_allowed_fallback(not_implemented)
+        .layer(CorsLayer::permissive())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let port: u16 = match std::env::var("SCADA_PORT") {
+        Ok(value) => value.parse().map_err(|e| anyhow::anyhow!("Invalid SCADA_PORT '{}': {}", value, e))?,
+        Err(_) => 8080,
+    };
+
+    let app = app();
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    println!("Mock server running on http://{}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::{to_bytes, Body}, http::Request};
+    use scada_client::{Client, ExpiredContractFilter};
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    // Sends a GET with `token` and parses the body as the scada-models type `T`
+    async fn fetch<T: DeserializeOwned>(path: &str, token: &str) -> T {
+        let request = Request::builder()
+            .uri(path)
+            .header("authorization", format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+    }
+
+    // A request body from JSON; optional fields left out are None
+    fn body<T: DeserializeOwned>(value: serde_json::Value) -> T {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn fixtures_parse_as_the_shared_types() {
+        fetch::<InfoResponse>("/api/info", USER_TOKEN).await;
+        let machines: MachineListResponse = fetch("/api/machines", USER_TOKEN).await;
+        assert_eq!(machines.machines.len(), MACHINE_COUNT as usize);
+        fetch::<MachineDetailResponse>("/api/machines/1/full", USER_TOKEN).await;
+        fetch::<HistoryResponse>("/api/machines/1/history", USER_TOKEN).await;
+        fetch::<CommentListResponse>("/api/machines/1/comments", USER_TOKEN).await;
+        fetch::<AlarmListResponse>("/api/alarms", USER_TOKEN).await;
+        fetch::<NotificationListResponse>("/api/notifications", USER_TOKEN).await;
+        fetch::<UserListResponse>("/api/users", ADMIN_TOKEN).await;
+
+        let own: MachineSelfResponse = fetch("/api/machines/me", "mock_machine_key_1").await;
+        let polled: MachineCommandListResponse = fetch("/api/machines/commands", "mock_machine_key_1").await;
+        assert_eq!(own.pending_commands, polled.commands.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn routes_admit_the_roles_of_the_real_server() {
+        let status = |path: &'static str, token: &'static str| async move {
+            let request = Request::builder()
+                .uri(path)
+                .header("authorization", format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap();
+            app().oneshot(request).await.unwrap().status()
+        };
+
+        assert_eq!(status("/api/reports/operators", USER_TOKEN).await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/api/reports/operators", MANAGER_TOKEN).await, StatusCode::OK);
+        assert_eq!(status("/api/service-accounts", MANAGER_TOKEN).await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/api/service-accounts", ADMIN_TOKEN).await, StatusCode::OK);
+        assert_eq!(status("/api/kpis", "mock_machine_key_1").await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn endpoints_without_fixtures_answer_not_implemented() {
+        for (method, path) in [("GET", "/api/work-orders"), ("GET", "/api/machines/1")] {
+            let request = Request::builder().method(method).uri(path).body(Body::empty()).unwrap();
+            let response = app().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED, "{} {}", method, path);
+            let body: ErrorResponse = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+            assert_eq!(body.error, format!("{} {} is not implemented by the mock server", method, path));
+        }
+    }
+
+    // Calls every method of scada-client against the mock over HTTP. The count at the end
+    // fails the test when a client method is added without being walked here.
+    #[tokio::test]
+    async fn every_client_method_is_served() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app()).await.unwrap() });
+
+        let mut walked = 0;
+        macro_rules! walk {
+            ($call:expr) => {{
+                walked += 1;
+                $call.await.unwrap_or_else(|e| panic!("{}: {}", stringify!($call), e))
+            }};
+        }
+
+        let mut client = Client::new(&base_url);
+        walk!(client.get_password_policy());
+        walk!(client.get_info());
+        walk!(client.request_password_reset("technician@example.com"));
+        walk!(client.confirm_password_reset("mock_reset_token", "Correct-Horse-1"));
+        walk!(client.download_site_logo());
+        let session = walk!(client.login("admin", "admin123"));
+        walk!(client.refresh(&session.refresh_token));
+        walk!(client.login_changing_password("admin", "admin123", "Correct-Horse-1"));
+        assert_eq!(client.token(), Some(ADMIN_TOKEN));
+
+        // Machines
+        walk!(client.list_machines(None));
+        walk!(client.list_all_machines(Some("de")));
+        walk!(client.list_machines_by_health());
+        walk!(client.create_machine(&body(json!({ "name": "Conveyor D", "code": "CNV-004" }))));
+        walk!(client.update_machine(1, &body(json!({ "name": "Conveyor A1" }))));
+        walk!(client.archive_machine(3));
+        walk!(client.restore_machine(3));
+        walk!(client.wait_for_machine_changes(None, Some(1), None));
+        walk!(client.get_machine_detail(1, None));
+        walk!(client.get_history(1, Some(10), None, None));
+        walk!(client.get_history_between(1, BASE_TIME - 3600, BASE_TIME, Some("raw"), None));
+        walk!(client.get_machine_gaps(1, Some("10m"), None, None));
+        let job = walk!(client.export_history(1, &body(json!({ "range": "last_24h" }))));
+        walk!(client.create_export_request(&body(json!({ "machine_id": 1, "range": "last_24h", "reason": "Audit" }))));
+        walk!(client.list_export_requests(Some("approved"), None));
+        walk!(client.get_export_request(1));
+        walk!(client.approve_export_request(1, &body(json!({}))));
+        walk!(client.reject_export_request(1, &body(json!({ "note": "Not needed" }))));
+        walk!(client.shift_history(1, &body(json!({ "from": BASE_TIME - 7200, "to": BASE_TIME, "offset_seconds": 3600 }))));
+        walk!(client.list_history_shifts(1));
+        walk!(client.undo_history_shift(1));
+        walk!(client.merge_machines(1, &body(json!({ "source_id": 2, "dry_run": true }))));
+        walk!(client.decommission_machine(3));
+        walk!(client.list_machine_archives());
+        walk!(client.download_machine_archive(1));
+        walk!(client.get_machine_metrics(1));
+        walk!(client.list_display_names(1));
+        walk!(client.set_display_name(1, "de", &body(json!({ "display_name": "Förderband A" }))));
+        walk!(client.delete_display_name(1, "de"));
+
+        // Annotations, batches, changeovers and assignments
+        walk!(client.list_annotations(1, None, None));
+        walk!(client.add_annotation(1, &body(json!({ "starts_at": BASE_TIME, "text": "Belt replaced" }))));
+        walk!(client.delete_annotation(1));
+        walk!(client.list_machine_batches(1, None, None));
+        walk!(client.start_batch(1, &body(json!({ "lot_number": "LOT-1" }))));
+        walk!(client.end_batch(1));
+        walk!(client.find_lot_batches("LOT-1"));
+        walk!(client.list_machine_changeovers(1, None, None));
+        walk!(client.start_changeover(1, &body(json!({ "to_product": "WIDGET-100" }))));
+        walk!(client.end_changeover(1));
+        walk!(client.assign_operator(1, &body(json!({ "username": "technician", "shift_date": "2023-11-14", "shift": 0 }))));
+        walk!(client.delete_assignment(1));
+        walk!(client.get_shift_schedule(Some("2023-11-14"), None, None));
+
+        // Comments
+        walk!(client.get_comments(1, Some("open")));
+        walk!(client.add_comment(1, &body(json!({ "comment": "Oil leak", "priority": "high" }))));
+        walk!(client.list_recent_comments(None, None, None, Some(10)));
+        walk!(client.pin_comment(11));
+        walk!(client.unpin_comment(11));
+        walk!(client.resolve_comment(11));
+        walk!(client.unresolve_comment(11));
+        walk!(client.export_comments(None, None, None, None));
+        walk!(client.list_comment_categories());
+        walk!(client.create_comment_category(&body(json!({ "name": "Electrical" }))));
+
+        // Machine API keys and documents
+        walk!(client.list_machine_api_keys(1));
+        walk!(client.create_machine_api_key(1, &body(json!({ "name": "gateway", "scopes": ["telemetry:write"] }))));
+        walk!(client.rotate_machine_key(1, &body(json!({ "grace_secs": 3600 }))));
+        walk!(client.update_machine_api_key(1, &body(json!({ "name": "gateway 2" }))));
+        walk!(client.revoke_machine_api_key(1));
+        walk!(client.list_documents(1));
+        walk!(client.create_document(1, &body(json!({ "title": "Manual", "doc_type": "manual" }))));
+        walk!(client.update_document(1, &body(json!({ "title": "Operating manual" }))));
+        walk!(client.delete_document(1));
+        walk!(client.upload_document_file(1, "manual.pdf", DOCUMENT_CONTENTS.to_vec()));
+        walk!(client.download_document_file(1));
+
+        // Work orders
+        walk!(client.list_work_orders(1, Some("open")));
+        walk!(client.create_work_order(1, &body(json!({ "title": "Replace drive belt" }))));
+        walk!(client.get_work_order(1));
+        walk!(client.update_work_order(1, &body(json!({ "status": "completed" }))));
+        walk!(client.attach_work_order_checklist(1, &body(json!({ "template_id": 1 }))));
+        walk!(client.update_work_order_step(1, 2, &body(json!({ "completed": true }))));
+        walk!(client.sign_off_work_order(1));
+        walk!(client.list_work_permits(1));
+        walk!(client.request_work_permit(1, &body(json!({
+            "permit_type": "mechanical",
+            "isolation_points": ["Main isolator Q1"],
+            "valid_from": BASE_TIME,
+            "valid_until": BASE_TIME + 3600,
+        }))));
+        walk!(client.approve_work_permit(1, &body(json!({ "signature": "M. Manager", "password": "admin123" }))));
+        walk!(client.close_work_permit(1));
+        walk!(client.list_labor(1));
+        walk!(client.start_labor(1));
+        walk!(client.stop_labor(1));
+        walk!(client.list_checklist_templates());
+        walk!(client.create_checklist_template(&body(json!({ "name": "Belt", "steps": [{ "text": "Isolate", "required": true }] }))));
+
+        // Costs, contracts and notifications
+        walk!(client.get_machine_costs(1, None, None, Some("month")));
+        walk!(client.create_cost(1, &body(json!({ "description": "Drive belt", "amount": 184.5 }))));
+        walk!(client.list_contracts(1));
+        walk!(client.create_contract(1, &body(json!({ "kind": "warranty", "vendor": "Siemens", "expires_at": BASE_TIME }))));
+        walk!(client.update_contract(1, &body(json!({ "vendor": "Siemens AG" }))));
+        walk!(client.delete_contract(1));
+        walk!(client.list_notifications(Some(true)));
+        walk!(client.snooze_machine(1, BASE_TIME + 3600));
+        walk!(client.unsnooze_machine(1));
+        walk!(client.list_own_snoozes());
+        walk!(client.acknowledge_notification(1));
+
+        // Alarms, compliance and predictions
+        walk!(client.list_alarm_rules(2));
+        walk!(client.create_alarm_rule(2, &body(json!({ "name": "Overspeed", "expression": "speed > 195", "severity": "warning" }))));
+        walk!(client.update_alarm_rule(1, &body(json!({ "enabled": false }))));
+        walk!(client.backtest_alarm_rule(&body(json!({ "machine_id": 2, "expression": "speed > 195" }))));
+        walk!(client.list_alarms(None, Some(true), None));
+        walk!(client.acknowledge_alarm(1));
+        walk!(client.annotate_alarm(1, &body(json!({ "root_cause": "belt_slip" }))));
+        walk!(client.export_alarms(None, None, None, Some("csv")));
+        walk!(client.list_compliance_limits(None, None));
+        walk!(client.create_compliance_limit(&body(json!({ "name": "Dryer temperature", "metric": "temperature", "max_value": 80.0 }))));
+        walk!(client.update_compliance_limit(1, &body(json!({ "max_value": 85.0 }))));
+        walk!(client.delete_compliance_limit(1));
+        walk!(client.list_compliance_exceedances(None, None, None, None));
+        walk!(client.list_failure_thresholds(None, None));
+        walk!(client.create_failure_threshold(&body(json!({ "metric": "vibration", "max_value": 12.0 }))));
+        walk!(client.delete_failure_threshold(1));
+        walk!(client.list_predictions());
+        walk!(client.list_advisories(None, true));
+        walk!(client.acknowledge_advisory(1));
+        walk!(client.run_predictions());
+        walk!(client.compliance_exceedance_report(Some("2023-11"), None, None));
+        walk!(client.get_alarm_work_order_policy());
+        walk!(client.update_alarm_work_order_policy(&body(json!({ "enabled": true }))));
+        walk!(client.list_alarm_root_causes());
+        walk!(client.create_alarm_root_cause(&body(json!({ "code": "belt_slip" }))));
+        walk!(client.list_alarm_presentation());
+        walk!(client.update_alarm_presentation("warning", &body(json!({ "color": "#f59e0b", "auto_popup": false }))));
+
+        // Commands, units and precision
+        walk!(client.list_command_types(Some("Conveyor")));
+        walk!(client.create_command_type(&body(json!({
+            "machine_type": "Conveyor",
+            "name": "set_speed",
+            "parameters_schema": { "type": "object" },
+            "allowed_during_lockout": false,
+        }))));
+        walk!(client.update_command_type(1, &body(json!({ "min_role": "admin" }))));
+        walk!(client.delete_command_type(1));
+        walk!(client.send_command(1, &body(json!({ "command_type": "set_speed", "parameters": { "speed": 110.0 } }))));
+        walk!(client.lock_out_machine(1, &body(json!({ "reason": "Belt change" }))));
+        walk!(client.release_lockout(1));
+        walk!(client.cancel_command(1));
+        walk!(client.list_machine_commands(1, None, None));
+        walk!(client.list_units());
+        walk!(client.create_unit(&Unit::fixture()));
+        walk!(client.list_metric_precision());
+        walk!(client.update_metric_precision("temperature", &body(json!({ "decimals": 2 }))));
+
+        // Reports and jobs
+        walk!(client.comments_by_category_report(None, None));
+        walk!(client.alarm_root_cause_report(None, None));
+        walk!(client.labor_hours_report(Some("last_24h"), None, None, None));
+        walk!(client.changeover_report(None, None, None, Some(1)));
+        walk!(client.operator_report(None, None, None, None));
+        walk!(client.expired_contracts_report(&ExpiredContractFilter::default()));
+        walk!(client.history_gaps_report(Some("10m"), None, None));
+        walk!(client.get_job(&job.job_id));
+        walk!(client.download_job_artifact(&job.job_id));
+        let link = walk!(client.create_download_link(&job.job_id, Some(3600)));
+        assert!(link.url.starts_with(&base_url), "{}", link.url);
+
+        // Users, sessions and access
+        walk!(client.list_users());
+        walk!(client.create_user(&body(json!({ "username": "operator", "password": "Correct-Horse-1", "role": "technician" }))));
+        walk!(client.update_user(3, &body(json!({ "role": "manager" }))));
+        walk!(client.unlock_user(3));
+        walk!(client.deactivate_user(3));
+        walk!(client.reactivate_user(3));
+        walk!(client.revoke_user_tokens(3));
+        walk!(client.list_own_sessions());
+        let view = walk!(client.get_machine_list_view());
+        walk!(client.set_machine_list_view(&view));
+        walk!(client.list_user_sessions(3));
+        walk!(client.list_user_logins(3, Some(10)));
+        walk!(client.revoke_session("mock-session-1"));
+        walk!(client.impersonate_user(3, &Default::default()));
+        walk!(client.list_impersonations());
+        walk!(client.list_service_accounts());
+        walk!(client.create_service_account(&body(json!({ "name": "mes-gateway", "scopes": ["machines:read"] }))));
+        walk!(client.update_service_account(1, &body(json!({ "description": "MES" }))));
+        walk!(client.rotate_service_account_key(1, &body(json!({}))));
+        walk!(client.disable_service_account(1));
+        walk!(client.list_user_certifications(3));
+        walk!(client.set_certification(3, "electrical", &body(json!({ "expires_at": BASE_TIME + 86400 }))));
+        walk!(client.delete_certification(3, "electrical"));
+        walk!(client.list_certifications(Some("electrical"), None));
+        walk!(client.list_machine_access(3));
+        walk!(client.grant_machine_access(3, 2));
+        walk!(client.revoke_machine_access(3, 2));
+
+        // Administration
+        walk!(client.create_sandbox(&body(json!({ "history_days": 7 }))));
+        walk!(client.create_backup());
+        walk!(client.download_backup());
+        walk!(client.list_backups());
+        walk!(client.get_read_only());
+        walk!(client.update_read_only(false));
+        walk!(client.list_feature_flags());
+        walk!(client.update_feature_flag("mqtt", true));
+        walk!(client.get_api_usage(None, None));
+        walk!(client.purge_history(Some(1)));
+
+        // Kiosks, display tokens, products, KPIs and distribution lists
+        walk!(client.kiosk_rotation(Some("hall-1"), None));
+        walk!(client.list_kiosk_groups());
+        walk!(client.set_kiosk_group("hall-1", &body(json!({ "machine_ids": [1, 2], "rotation_interval_secs": 15 }))));
+        walk!(client.delete_kiosk_group("hall-1"));
+        walk!(client.list_display_tokens());
+        walk!(client.create_display_token(&body(json!({ "name": "Hall 1 screen" }))));
+        walk!(client.revoke_display_token(1));
+        walk!(client.list_products());
+        walk!(client.set_product("WIDGET-100", &body(json!({ "name": "Widget 100" }))));
+        walk!(client.delete_product("WIDGET-100"));
+        walk!(client.get_kpis(Some("today")));
+        walk!(client.benchmark_machines("Conveyor", None, None));
+        walk!(client.list_health_scores(None));
+        walk!(client.list_kpi_definitions());
+        walk!(client.create_kpi_definition(&body(json!({ "label": "Average speed", "aggregation": "avg", "expression": "speed" }))));
+        walk!(client.update_kpi_definition(1, &body(json!({ "label": "Mean speed", "aggregation": "avg" }))));
+        walk!(client.delete_kpi_definition(1));
+        walk!(client.list_distribution_lists());
+        walk!(client.get_distribution_list(1));
+        walk!(client.create_distribution_list(&body(json!({ "name": "Shift leads", "members": [{ "username": "manager" }] }))));
+        walk!(client.update_distribution_list(1, &body(json!({ "description": "Escalations" }))));
+        walk!(client.delete_distribution_list(1));
+        walk!(client.send_distribution_list_test(1));
+
+        // Site settings and chaos testing
+        walk!(client.get_site_settings());
+        walk!(client.update_site_settings(&body(json!({ "plant_name": "Mock Plant" }))));
+        walk!(client.upload_site_logo("image/svg+xml", LOGO_CONTENTS.as_bytes().to_vec()));
+        walk!(client.delete_site_logo());
+        walk!(client.get_chaos());
+        walk!(client.reset_chaos());
+        walk!(client.set_chaos_latency(&body(json!({ "millis": 200 }))));
+        walk!(client.set_chaos_db_failures(3));
+        walk!(client.kill_background_task("rollups"));
+
+        // Machine-facing endpoints, with the machine's API key
+        let agent = Client::new(&base_url).with_token("mock_machine_key_1");
+        walk!(agent.update_machine_speed(&body(json!({ "speed": 101.5, "message": null }))));
+        walk!(agent.get_own_machine());
+        walk!(agent.poll_commands());
+        walk!(agent.report_command_result(1, &body(json!({ "success": true }))));
+
+        walk!(client.change_password("admin123", "Correct-Horse-1"));
+        walk!(client.logout(Some(&session.refresh_token)));
+        assert_eq!(client.token(), None);
+
+        let client_methods = include_str!("../../crates/scada-client/src/lib.rs").matches("pub async fn ").count();
+        assert_eq!(walked, client_methods);
+    }
+}
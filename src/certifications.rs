@@ -0,0 +1,157 @@
+use std::{str::FromStr, time::Duration};
+use tokio::task::JoinHandle;
+
+use crate::{
+    database::{DbPool, current_timestamp},
+    notifications,
+};
+
+// What happens when a work order needing a skill goes to someone without a valid certification
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CertificationPolicy {
+    // Refuse the assignment
+    Enforce,
+    // Allow it, raising a notification so a supervisor can follow up
+    Warn,
+}
+
+impl FromStr for CertificationPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "enforce" => Ok(Self::Enforce),
+            "warn" => Ok(Self::Warn),
+            _ => Err("expected 'enforce' or 'warn'".to_string()),
+        }
+    }
+}
+
+// Skills are short identifiers such as "electrical" or "forklift"
+pub fn is_valid_skill(skill: &str) -> bool {
+    !skill.is_empty()
+        && skill.len() <= 40
+        && skill.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+// Whether `username` holds a certification for `skill` that has not expired
+pub async fn is_qualified(pool: &DbPool, username: &str, skill: &str) -> sqlx::Result<bool> {
+    sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM certifications WHERE username = $1 AND skill = $2 AND (expires_at IS NULL OR expires_at > $3))"
+    )
+    .bind(username)
+    .bind(skill)
+    .bind(current_timestamp())
+    .fetch_one(pool)
+    .await
+}
+
+// Periodically reminds supervisors of certifications about to expire, once per certification.
+// Renewing a certification clears the reminder so the next expiry is announced again.
+pub fn spawn_certification_expiry_check(pool: DbPool, notice: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = notify_expiring(&pool, notice).await {
+                tracing::error!("Certification expiry check failed: {}", e);
+            }
+        }
+    })
+}
+
+async fn notify_expiring(pool: &DbPool, notice: Duration) -> sqlx::Result<()> {
+    let now = current_timestamp();
+    let expiring: Vec<(i64, String, String, i64)> = sqlx::query_as(
+        "SELECT id, username, skill, expires_at FROM certifications \
+         WHERE notified_at IS NULL AND expires_at IS NOT NULL AND expires_at <= $1"
+    )
+    .bind(now + notice.as_secs() as i64)
+    .fetch_all(pool)
+    .await?;
+
+    for (id, username, skill, expires_at) in expiring {
+        let message = if expires_at <= now {
+            format!("The {} certification of {} has expired", skill, username)
+        } else {
+            format!(
+                "The {} certification of {} expires in {} days",
+                skill,
+                username,
+                (expires_at - now + 86_399) / 86_400
+            )
+        };
+        notifications::notify(pool, "certification_expiry", None, &message).await?;
+        sqlx::query("UPDATE certifications SET notified_at = $1 WHERE id = $2")
+            .bind(now)
+            .bind(id)
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400;
+
+    async fn database() -> DbPool {
+        let pool = crate::database::test_database().await;
+        let now = current_timestamp();
+        for (username, skill, expires_at) in [
+            ("bob", "electrical", None),
+            ("bob", "forklift", Some(now - DAY)),
+            ("carol", "electrical", Some(now + 3 * DAY)),
+            ("carol", "forklift", Some(now + 90 * DAY)),
+        ] {
+            sqlx::query(
+                "INSERT INTO certifications (username, skill, expires_at, recorded_by, updated_at) VALUES ($1, $2, $3, 'boss', 0)"
+            )
+            .bind(username)
+            .bind(skill)
+            .bind(expires_at)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        pool
+    }
+
+    #[test]
+    fn skills_are_short_identifiers() {
+        assert!(is_valid_skill("high-voltage_2"));
+        assert!(!is_valid_skill(""));
+        assert!(!is_valid_skill("Forklift"));
+        assert!(!is_valid_skill("fork lift"));
+        assert!(!is_valid_skill(&"a".repeat(41)));
+        assert_eq!("warn".parse(), Ok(CertificationPolicy::Warn));
+        assert!("strict".parse::<CertificationPolicy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn only_unexpired_certifications_qualify() {
+        let pool = database().await;
+        assert!(is_qualified(&pool, "bob", "electrical").await.unwrap());
+        assert!(!is_qualified(&pool, "bob", "forklift").await.unwrap());
+        assert!(is_qualified(&pool, "carol", "electrical").await.unwrap());
+        assert!(!is_qualified(&pool, "carol", "welding").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn reminds_once_of_each_certification_within_the_notice() {
+        let pool = database().await;
+        let notice = Duration::from_secs(30 * DAY as u64);
+        notify_expiring(&pool, notice).await.unwrap();
+        notify_expiring(&pool, notice).await.unwrap();
+        let messages: Vec<String> = sqlx::query_scalar("SELECT message FROM notifications ORDER BY message")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            messages,
+            ["The electrical certification of carol expires in 3 days", "The forklift certification of bob has expired"]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use sqlx::Transaction;
+
+use crate::{database::Db, models::MachineBatch};
+
+// Opens a changeover when a machine switches from one product to another. The changeover starts
+// at the last reading of the old batch with the machine running, since that is when production
+// of the old product stopped. An operator-marked changeover already open just learns the
+// products instead.
+pub async fn open_on_product_change(
+    tx: &mut Transaction<'_, Db>,
+    machine_id: i64,
+    running: &MachineBatch,
+    product: &str,
+    started_by: Option<&str>,
+    at: i64,
+) -> sqlx::Result<()> {
+    let Some(from_product) = running.product.as_deref().filter(|from_product| *from_product != product) else {
+        return Ok(());
+    };
+
+    let updated = sqlx::query(
+        "UPDATE changeovers SET from_product = COALESCE(from_product, $1), to_product = COALESCE(to_product, $2) \
+         WHERE machine_id = $3 AND ended_at IS NULL"
+    )
+    .bind(from_product)
+    .bind(product)
+    .bind(machine_id)
+    .execute(&mut **tx)
+    .await?;
+    if updated.rows_affected() > 0 {
+        return Ok(());
+    }
+
+    let last_running: Option<i64> = sqlx::query_scalar(
+        "SELECT MAX(timestamp) FROM speed_history WHERE machine_id = $1 AND speed > 0 AND timestamp >= $2 AND timestamp <= $3"
+    )
+    .bind(machine_id)
+    .bind(running.started_at)
+    .bind(at)
+    .fetch_one(&mut **tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO changeovers (machine_id, from_product, to_product, source, started_at, started_by) \
+         VALUES ($1, $2, $3, 'product_change', $4, $5)"
+    )
+    .bind(machine_id)
+    .bind(from_product)
+    .bind(product)
+    .bind(last_running.unwrap_or(at))
+    .bind(started_by)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+// Ends a detected changeover once the machine runs again. Operator-marked changeovers run until
+// an operator ends them, as the first good part may come well after the line starts moving.
+pub async fn end_on_running(tx: &mut Transaction<'_, Db>, machine_id: i64, at: i64) -> sqlx::Result<()> {
+    sqlx::query(
+        "UPDATE changeovers SET ended_at = $1 \
+         WHERE machine_id = $2 AND ended_at IS NULL AND source = 'product_change' AND started_at < $3"
+    )
+    .bind(at)
+    .bind(machine_id)
+    .bind(at)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DbPool;
+
+    async fn changeovers(pool: &DbPool) -> Vec<(Option<String>, Option<String>, String, i64, Option<i64>)> {
+        sqlx::query_as("SELECT from_product, to_product, source, started_at, ended_at FROM changeovers ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    fn batch(product: &str) -> MachineBatch {
+        MachineBatch {
+            id: 1,
+            machine_id: 1,
+            lot_number: "L-1".to_string(),
+            product: Some(product.to_string()),
+            started_at: 100,
+            ended_at: None,
+            started_by: None,
+            ended_by: None,
+        }
+    }
+
+    async fn database() -> DbPool {
+        let pool = crate::database::test_database().await;
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (speed, timestamp) in [(10.0, 150), (10.0, 200), (0.0, 250)] {
+            sqlx::query("INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES (1, $1, 'running', $2)")
+                .bind(speed)
+                .bind(timestamp)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool
+    }
+
+    #[tokio::test]
+    async fn product_changes_run_from_the_last_production_until_running_again() {
+        let pool = database().await;
+        let mut tx = pool.begin().await.unwrap();
+        open_on_product_change(&mut tx, 1, &batch("A"), "A", None, 300).await.unwrap();
+        open_on_product_change(&mut tx, 1, &batch("A"), "B", None, 300).await.unwrap();
+        tx.commit().await.unwrap();
+        let (from, to) = (Some("A".to_string()), Some("B".to_string()));
+        let source = "product_change".to_string();
+        assert_eq!(changeovers(&pool).await, [(from.clone(), to.clone(), source.clone(), 200, None)]);
+
+        let mut tx = pool.begin().await.unwrap();
+        end_on_running(&mut tx, 1, 400).await.unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(changeovers(&pool).await, [(from, to, source, 200, Some(400))]);
+    }
+
+    #[tokio::test]
+    async fn operator_changeovers_learn_the_products_and_stay_open() {
+        let pool = database().await;
+        sqlx::query("INSERT INTO changeovers (machine_id, source, started_at, started_by) VALUES (1, 'operator', 260, 'alice')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        open_on_product_change(&mut tx, 1, &batch("A"), "B", None, 300).await.unwrap();
+        end_on_running(&mut tx, 1, 400).await.unwrap();
+        tx.commit().await.unwrap();
+        let operator = (Some("A".to_string()), Some("B".to_string()), "operator".to_string(), 260, None);
+        assert_eq!(changeovers(&pool).await, [operator]);
+    }
+}
@@ -0,0 +1,137 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::task::AbortHandle;
+
+use crate::models::{ChaosLatency, ChaosStatus};
+
+// Fault injection for exercising clients against a misbehaving server. Only wired up when
+// SCADA_DEV_CHAOS is set; the /api/dev endpoints drive it.
+#[derive(Clone, Default)]
+pub struct Chaos {
+    latency: Arc<Mutex<Option<ChaosLatency>>>,
+    failing_db_calls: Arc<AtomicU32>,
+    tasks: Arc<Mutex<BTreeMap<&'static str, AbortHandle>>>,
+}
+
+impl Chaos {
+    pub fn status(&self) -> ChaosStatus {
+        ChaosStatus {
+            latency: self.latency.lock().unwrap().clone(),
+            failing_db_calls: self.failing_db_calls.load(Ordering::Relaxed),
+            tasks: self
+                .tasks
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, handle)| (name.to_string(), !handle.is_finished()))
+                .collect(),
+        }
+    }
+
+    pub fn set_latency(&self, latency: Option<ChaosLatency>) {
+        *self.latency.lock().unwrap() = latency;
+    }
+
+    pub fn fail_db_calls(&self, count: u32) {
+        self.failing_db_calls.store(count, Ordering::Relaxed);
+    }
+
+    // Consumes one injected failure, if any are left
+    pub fn take_db_failure(&self) -> bool {
+        self.failing_db_calls
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    pub fn register_task(&self, name: &'static str, handle: AbortHandle) {
+        self.tasks.lock().unwrap().insert(name, handle);
+    }
+
+    // Returns false if no background task has that name
+    pub fn kill_task(&self, name: &str) -> bool {
+        match self.tasks.lock().unwrap().get(name) {
+            Some(handle) => {
+                handle.abort();
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn reset(&self) {
+        self.set_latency(None);
+        self.fail_db_calls(0);
+    }
+}
+
+pub async fn inject_latency(State(chaos): State<Chaos>, request: Request, next: Next) -> Response {
+    let delay = chaos.latency.lock().unwrap().as_ref().and_then(|latency| {
+        let path = request.uri().path();
+        let applies = !path.starts_with("/api/dev/")
+            && latency.path_prefix.as_deref().is_none_or(|prefix| path.starts_with(prefix));
+        applies.then(|| Duration::from_millis(latency.millis))
+    });
+
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    #[test]
+    fn injected_database_failures_are_used_up() {
+        let chaos = Chaos::default();
+        chaos.fail_db_calls(2);
+        assert_eq!([chaos.take_db_failure(), chaos.take_db_failure(), chaos.take_db_failure()], [true, true, false]);
+        chaos.fail_db_calls(5);
+        chaos.reset();
+        assert!(!chaos.take_db_failure());
+    }
+
+    #[tokio::test]
+    async fn killed_tasks_are_reported_as_stopped() {
+        let chaos = Chaos::default();
+        let task = tokio::spawn(std::future::pending::<()>());
+        chaos.register_task("rollups", task.abort_handle());
+        assert_eq!(chaos.status().tasks.get("rollups"), Some(&true));
+        assert!(chaos.kill_task("rollups"));
+        assert!(!chaos.kill_task("retention"));
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert_eq!(chaos.status().tasks.get("rollups"), Some(&false));
+    }
+
+    #[tokio::test]
+    async fn latency_applies_to_matching_paths_but_never_the_dev_endpoints() {
+        let chaos = Chaos::default();
+        chaos.set_latency(Some(ChaosLatency { millis: 60_000, path_prefix: Some("/api/machines".to_string()) }));
+        let app = Router::new()
+            .route("/api/machines", get(|| async { "machines" }))
+            .route("/api/users", get(|| async { "users" }))
+            .route("/api/dev/chaos", get(|| async { "chaos" }))
+            .layer(middleware::from_fn_with_state(chaos, inject_latency));
+        let answers_promptly = async |path: &str| {
+            let request = app.clone().oneshot(Request::builder().uri(path).body(Body::empty()).unwrap());
+            tokio::time::timeout(Duration::from_millis(500), request).await.is_ok()
+        };
+        assert!(!answers_promptly("/api/machines").await);
+        assert!(answers_promptly("/api/users").await);
+        assert!(answers_promptly("/api/dev/chaos").await);
+    }
+}
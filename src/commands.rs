@@ -0,0 +1,153 @@
+use serde_json::Value;
+use sqlx::Row;
+
+use crate::{
+    database::{DbPool, DbRow, current_timestamp},
+    models::{CommandType, MachineCommand},
+};
+
+// Compiles a command type's parameter schema, so broken schemas are refused when they are
+// registered rather than when the first command is sent
+pub fn compile_schema(schema: &Value) -> Result<jsonschema::Validator, String> {
+    jsonschema::validator_for(schema).map_err(|e| format!("Invalid parameters schema: {}", e))
+}
+
+// Checks a command's parameters against its type's schema before it is queued, so a
+// malformed setpoint never reaches the gateway. The error lists every violation found.
+pub fn validate_parameters(schema: &Value, parameters: &Value) -> Result<(), String> {
+    let validator = compile_schema(schema)?;
+    let problems: Vec<String> = validator
+        .iter_errors(parameters)
+        .map(|error| match error.instance_path().to_string() {
+            path if path.is_empty() => error.to_string(),
+            path => format!("{}: {}", path, error),
+        })
+        .collect();
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Invalid parameters: {}", problems.join("; ")))
+    }
+}
+
+pub fn command_type_from_row(row: &DbRow) -> CommandType {
+    CommandType {
+        id: row.get("id"),
+        machine_type: row.get("machine_type"),
+        name: row.get("name"),
+        description: row.get("description"),
+        parameters_schema: serde_json::from_str(row.get("parameters_schema")).unwrap_or(Value::Null),
+        min_role: row.get("min_role"),
+        allowed_during_lockout: row.get("allowed_during_lockout"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+pub fn command_from_row(row: &DbRow) -> MachineCommand {
+    MachineCommand {
+        id: row.get("id"),
+        machine_id: row.get("machine_id"),
+        command_type: row.get("command_type"),
+        parameters: serde_json::from_str(row.get("parameters")).unwrap_or(Value::Null),
+        status: row.get("status"),
+        created_by: row.get("created_by"),
+        created_at: row.get("created_at"),
+        delivered_at: row.get("delivered_at"),
+        completed_at: row.get("completed_at"),
+        result: row.get("result"),
+        expires_at: row.get("expires_at"),
+        cancelled_by: row.get("cancelled_by"),
+    }
+}
+
+// Moves a machine's commands that waited past their TTL from pending to expired, so a
+// gateway reconnecting after an outage never receives stale orders. Runs before commands
+// are handed out or listed.
+pub async fn expire_pending(pool: &DbPool, machine_id: i64) -> sqlx::Result<u64> {
+    let result = sqlx::query(
+        "UPDATE machine_commands SET status = 'expired', completed_at = expires_at \
+         WHERE machine_id = $1 AND status = 'pending' AND expires_at <= $2"
+    )
+    .bind(machine_id)
+    .bind(current_timestamp())
+    .execute(pool)
+    .await?;
+    if result.rows_affected() > 0 {
+        tracing::info!("Expired {} undelivered command(s) for machine ID: {}", result.rows_affected(), machine_id);
+    }
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn setpoint_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "speed": { "type": "number", "minimum": 0, "maximum": 120 },
+                "ramp": { "type": "string", "enum": ["soft", "hard"] }
+            },
+            "required": ["speed"],
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn validates_parameters_against_the_schema() {
+        assert_eq!(validate_parameters(&setpoint_schema(), &json!({ "speed": 80, "ramp": "soft" })), Ok(()));
+        let error = validate_parameters(&setpoint_schema(), &json!({ "speed": 150, "ramp": "fast" })).unwrap_err();
+        // Every violation is reported, with where it is
+        assert!(error.starts_with("Invalid parameters: "), "{}", error);
+        assert!(error.contains("/speed: ") && error.contains("/ramp: "), "{}", error);
+        assert!(validate_parameters(&setpoint_schema(), &json!({})).is_err());
+    }
+
+    #[test]
+    fn refuses_broken_schemas() {
+        let error = compile_schema(&json!({ "type": "setpoint" })).unwrap_err();
+        assert!(error.starts_with("Invalid parameters schema: "), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn expires_pending_commands_past_their_ttl() {
+        let pool = crate::database::test_database().await;
+        let now = current_timestamp();
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (id, status, expires_at) in [
+            (1, "pending", Some(now - 10)),
+            (2, "pending", Some(now + 600)),
+            (3, "pending", None),
+            (4, "delivered", Some(now - 10)),
+        ] {
+            sqlx::query(
+                "INSERT INTO machine_commands (id, machine_id, command_type, parameters, status, created_by, created_at, expires_at) \
+                 VALUES ($1, 1, 'start', '{}', $2, 'boss', 0, $3)"
+            )
+            .bind(id)
+            .bind(status)
+            .bind(expires_at)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        assert_eq!(expire_pending(&pool, 1).await.unwrap(), 1);
+        let commands: Vec<(String, Option<i64>)> = sqlx::query_as("SELECT status, completed_at FROM machine_commands ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(commands, [
+            ("expired".to_string(), Some(now - 10)),
+            ("pending".to_string(), None),
+            ("pending".to_string(), None),
+            ("delivered".to_string(), None),
+        ]);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use crate::{
+    database::{DbPool, current_timestamp},
+    models::ComplianceLimit,
+    notifications,
+    units,
+};
+
+// Checks the machine's current values against the compliance limits that apply to it: opens
+// an exceedance when a value leaves a limit, tracks its worst value while it stays outside and
+// closes it once a reading is back inside. Readings are converted into the limit's unit first.
+pub async fn evaluate_machine(pool: &DbPool, machine_id: i64) -> sqlx::Result<()> {
+    let limits = sqlx::query_as::<_, ComplianceLimit>(
+        "SELECT * FROM compliance_limits WHERE enabled = TRUE AND (machine_id IS NULL OR machine_id = $1)"
+    )
+    .bind(machine_id)
+    .fetch_all(pool)
+    .await?;
+    if limits.is_empty() {
+        return Ok(());
+    }
+
+    let mut values: HashMap<String, (f64, Option<String>)> = sqlx::query_as::<_, (String, f64, String)>(
+        "SELECT metric, value, unit FROM machine_metrics WHERE machine_id = $1"
+    )
+    .bind(machine_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|(metric, value, unit)| (metric, (value, Some(unit))))
+    .collect();
+    let speed: f64 = sqlx::query_scalar("SELECT current_speed FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_one(pool)
+        .await?;
+    values.insert("speed".to_string(), (speed, None));
+
+    let now = current_timestamp();
+    for ComplianceLimit { id: limit_id, name, metric, min_value, max_value, unit, reference, .. } in limits {
+        let Some((value, reading_unit)) = values.get(&metric) else {
+            continue;
+        };
+        // A limit in kPa applies to a machine reporting in bar, but not to one reporting in rpm
+        let value = match (&unit, reading_unit) {
+            (Some(unit), Some(reading_unit)) => match units::convert(pool, *value, reading_unit, unit).await? {
+                Some(value) => value,
+                None => {
+                    tracing::warn!(
+                        "Compliance limit {} is in {} but machine ID {} reports {} in {}",
+                        name, unit, machine_id, metric, reading_unit,
+                    );
+                    continue;
+                },
+            },
+            _ => *value,
+        };
+
+        let above = max_value.is_some_and(|max| value > max);
+        let below = min_value.is_some_and(|min| value < min);
+        let open: Option<(i64, f64)> = sqlx::query_as(
+            "SELECT id, peak_value FROM compliance_exceedances WHERE limit_id = $1 AND machine_id = $2 AND ended_at IS NULL"
+        )
+        .bind(limit_id)
+        .bind(machine_id)
+        .fetch_optional(pool)
+        .await?;
+
+        match open {
+            None if above || below => {
+                sqlx::query(
+                    "INSERT INTO compliance_exceedances \
+                     (limit_id, machine_id, limit_name, metric, min_value, max_value, unit, reference, started_at, peak_value) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"
+                )
+                .bind(limit_id)
+                .bind(machine_id)
+                .bind(&name)
+                .bind(&metric)
+                .bind(min_value)
+                .bind(max_value)
+                .bind(&unit)
+                .bind(&reference)
+                .bind(now)
+                .bind(value)
+                .execute(pool)
+                .await?;
+                let message = format!(
+                    "{} {} the compliance limit {}: {} {}",
+                    metric,
+                    if above { "exceeds" } else { "is below" },
+                    name,
+                    value,
+                    unit.as_deref().unwrap_or_default(),
+                );
+                notifications::notify(pool, "compliance_exceedance", Some(machine_id), message.trim_end()).await?;
+                tracing::warn!("Compliance exceedance on machine ID {}: {}", machine_id, message.trim_end());
+            },
+            Some((exceedance_id, peak_value)) if above || below => {
+                let worse = if above { value > peak_value } else { value < peak_value };
+                if worse {
+                    sqlx::query("UPDATE compliance_exceedances SET peak_value = $1 WHERE id = $2")
+                        .bind(value)
+                        .bind(exceedance_id)
+                        .execute(pool)
+                        .await?;
+                }
+            },
+            Some((exceedance_id, _)) => {
+                sqlx::query("UPDATE compliance_exceedances SET ended_at = $1 WHERE id = $2")
+                    .bind(now)
+                    .bind(exceedance_id)
+                    .execute(pool)
+                    .await?;
+                tracing::info!("Compliance exceedance of {} ended on machine ID {}", name, machine_id);
+            },
+            None => {},
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn report(pool: &DbPool, bar: f64) -> Vec<(Option<i64>, f64)> {
+        sqlx::query("UPDATE machine_metrics SET value = $1 WHERE machine_id = 1 AND metric = 'pressure'")
+            .bind(bar)
+            .execute(pool)
+            .await
+            .unwrap();
+        evaluate_machine(pool, 1).await.unwrap();
+        sqlx::query_as("SELECT ended_at, peak_value FROM compliance_exceedances ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn tracks_exceedances_in_the_limit_unit() {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key')",
+            "INSERT INTO machine_metrics (machine_id, metric, unit, value, updated_at) VALUES (1, 'pressure', 'bar', 2.0, 0)",
+            "INSERT INTO compliance_limits (name, metric, max_value, unit, created_by, created_at, updated_at) \
+             VALUES ('Boiler pressure', 'pressure', 300.0, 'kPa', 'boss', 0, 0)",
+            // Cannot apply to a pressure reading
+            "INSERT INTO compliance_limits (name, metric, max_value, unit, created_by, created_at, updated_at) \
+             VALUES ('Misconfigured', 'pressure', 1.0, 'rpm', 'boss', 0, 0)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        assert!(report(&pool, 2.0).await.is_empty());
+        assert_eq!(report(&pool, 3.5).await, [(None, 350.0)]);
+        assert_eq!(report(&pool, 4.0).await, [(None, 400.0)]);
+        // The peak is the worst value while outside the limit
+        assert_eq!(report(&pool, 3.2).await, [(None, 400.0)]);
+        let exceedances = report(&pool, 2.0).await;
+        assert!(exceedances[0].0.is_some());
+
+        let messages: Vec<String> = sqlx::query_scalar("SELECT message FROM notifications WHERE kind = 'compliance_exceedance'")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(messages, ["pressure exceeds the compliance limit Boiler pressure: 350 kPa"]);
+    }
+}
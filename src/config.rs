@@ -0,0 +1,444 @@
+use chrono::NaiveTime;
+use chrono_tz::Tz;
+use ipnet::IpNet;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr, time::Duration};
+use tracing::level_filters::LevelFilter;
+
+use crate::{
+    certifications::CertificationPolicy, database, features, mailer::SmtpSecurity, network, password_policy::PasswordPolicy,
+    predictions::PredictionModel, units::UnitPolicy,
+};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // SQLite database file (SCADA_DATABASE, or a sqlite: DATABASE_URL)
+    pub database_path: PathBuf,
+    // PostgreSQL database of a server built with the postgres feature (a postgres: DATABASE_URL)
+    pub database_url: Option<String>,
+    // Connections kept in the database pool (SCADA_DB_MAX_CONNECTIONS)
+    pub db_max_connections: u32,
+    // How long a statement waits for a lock held by another connection (SCADA_DB_BUSY_TIMEOUT_MS)
+    pub db_busy_timeout: Duration,
+    // SQLite journal mode; WAL lets readers run alongside telemetry writes (SCADA_DB_JOURNAL_MODE)
+    pub db_journal_mode: SqliteJournalMode,
+    // Port the HTTP server listens on (SCADA_PORT)
+    pub port: u16,
+    // Site timezone used for day, shift and month boundaries, with its daylight saving rules
+    // (SCADA_SITE_TIMEZONE, e.g. "Europe/Berlin")
+    pub site_timezone: Tz,
+    // Local start times of each shift, sorted (SCADA_SHIFT_STARTS, e.g. "06:00,14:00,22:00")
+    pub shift_starts: Vec<NaiveTime>,
+    // Maximum concurrent expensive requests before load shedding, at least 1 (SCADA_EXPENSIVE_CONCURRENCY)
+    pub expensive_concurrency: usize,
+    // How long an expensive request may queue for a slot (SCADA_EXPENSIVE_QUEUE_TIMEOUT_MS)
+    pub expensive_queue_timeout: Duration,
+    // Number of background jobs allowed to run at once (SCADA_JOB_WORKERS)
+    pub job_workers: usize,
+    // Directory holding job output files (SCADA_ARTIFACT_DIR)
+    pub artifact_dir: PathBuf,
+    // How long job output files are kept (SCADA_ARTIFACT_RETENTION_HOURS)
+    pub artifact_retention: Duration,
+    // Longest lifetime a download link may be issued with (SCADA_DOWNLOAD_LINK_MAX_HOURS)
+    pub download_link_max_age: Duration,
+    // Secret for signing download links (SCADA_DOWNLOAD_SECRET); random per process if unset
+    pub download_secret: Vec<u8>,
+    // Externally reachable base URL used in shared links (SCADA_PUBLIC_URL); relative links if unset
+    pub public_url: String,
+    // Directory holding uploaded machine documents (SCADA_DOCUMENT_DIR)
+    pub document_dir: PathBuf,
+    // Directory holding the archives of decommissioned machines (SCADA_ARCHIVE_DIR)
+    pub archive_dir: PathBuf,
+    // Directory database backups are written to (SCADA_BACKUP_DIR)
+    pub backup_dir: PathBuf,
+    // Site-local time after which the nightly backup is taken, None to take none (SCADA_BACKUP_TIME, e.g. "02:00", empty to disable)
+    pub backup_time: Option<NaiveTime>,
+    // Backups older than this are deleted after the nightly backup, zero to keep them all (SCADA_BACKUP_RETENTION_DAYS)
+    pub backup_retention: Duration,
+    // Speed history older than this is purged, zero to keep it forever; machines can override it (SCADA_HISTORY_RETENTION_DAYS)
+    pub history_retention: Duration,
+    // Write purged speed history to CSV files under the archive directory first (SCADA_HISTORY_ARCHIVE)
+    pub history_archive: bool,
+    // History exports by anyone but admins go through a request a manager approves (SCADA_EXPORT_APPROVAL)
+    pub export_approval: bool,
+    // Site-local time after which each day's machine health scores are computed (SCADA_HEALTH_SCORE_TIME, e.g. "05:00")
+    pub health_score_time: NaiveTime,
+    // Model predicting machine failures, "trend" or "http" (SCADA_PREDICTION_MODEL, with SCADA_PREDICTION_URL for "http")
+    pub prediction_model: PredictionModel,
+    // Time between failure prediction runs, 0 to only run them on request (SCADA_PREDICTION_INTERVAL_MINUTES)
+    pub prediction_interval: Duration,
+    // Predicted failure risk, 0 to 1, at which a maintenance advisory is raised (SCADA_ADVISORY_RISK)
+    pub advisory_risk: f64,
+    // Hourly labor rate used to cost work order time logs (SCADA_LABOR_RATE)
+    pub labor_rate: f64,
+    // How long before expiry a warranty or service contract raises a notification (SCADA_CONTRACT_NOTICE_DAYS)
+    pub contract_notice: Duration,
+    // How long before expiry a technician certification raises a notification (SCADA_CERTIFICATION_NOTICE_DAYS)
+    pub certification_notice: Duration,
+    // Whether work orders needing a skill may go to technicians without it (SCADA_CERTIFICATION_POLICY)
+    pub certification_policy: CertificationPolicy,
+    // Handling of ingested metrics in unknown or mismatched units (SCADA_UNIT_POLICY)
+    pub unit_policy: UnitPolicy,
+    // Database file sandbox copies are written to (SCADA_SANDBOX_DATABASE)
+    pub sandbox_database_path: PathBuf,
+    // Experimental features enabled on first start (SCADA_FEATURES, e.g. "mqtt,graphql"); admins toggle them afterwards
+    pub features: Vec<String>,
+    // Log line format, "text" or "json" (SCADA_LOG_FORMAT)
+    pub log_format: LogFormat,
+    // Per-module level overrides on top of RUST_LOG (SCADA_LOG_LEVELS, e.g. "scada_with_rust_backend::jobs=debug,sqlx=warn")
+    pub log_levels: BTreeMap<String, LevelFilter>,
+    // Start with writes refused, e.g. during database maintenance (SCADA_READ_ONLY)
+    pub read_only: bool,
+    // Consecutive failed writes that switch the server to read-only mode, 0 to never (SCADA_READ_ONLY_AFTER_FAILURES)
+    pub read_only_after_failures: u32,
+    // Expose the /api/dev fault injection endpoints; never enable in production (SCADA_DEV_CHAOS)
+    pub dev_chaos: bool,
+    // Username of the admin account created on first start (SCADA_ADMIN_USER)
+    pub admin_user: String,
+    // Password for that account (SCADA_ADMIN_PASSWORD); generated and printed once if unset
+    pub admin_password: Option<String>,
+    // Secret for signing user session tokens (SCADA_JWT_SECRET); random per process if unset, logging everyone out on restart
+    pub jwt_secret: Vec<u8>,
+    // How long an access token from login or refresh stays valid (SCADA_ACCESS_TOKEN_TTL_MINUTES)
+    pub access_token_ttl: Duration,
+    // How long an unused refresh token stays valid (SCADA_REFRESH_TOKEN_TTL_DAYS)
+    pub refresh_token_ttl: Duration,
+    // How long a token from POST /api/admin/impersonate stays valid (SCADA_IMPERSONATION_TTL_MINUTES)
+    pub impersonation_ttl: Duration,
+    // Login attempts allowed per client IP per minute, 0 for no limit (SCADA_LOGIN_RATE_PER_MINUTE)
+    pub login_rate_per_minute: u32,
+    // Requests allowed per user or service account per minute, 0 for no limit (SCADA_USER_RATE_PER_MINUTE)
+    pub user_rate_per_minute: u32,
+    // Requests allowed per machine API key per minute, 0 for no limit (SCADA_MACHINE_RATE_PER_MINUTE)
+    pub machine_rate_per_minute: u32,
+    // Requests allowed per client IP per minute without a valid token, 0 for no limit
+    // (SCADA_ANONYMOUS_RATE_PER_MINUTE)
+    pub anonymous_rate_per_minute: u32,
+    // Consecutive failed logins that lock an account, 0 to never lock (SCADA_LOGIN_MAX_ATTEMPTS)
+    pub login_max_attempts: u32,
+    // How long a locked account stays locked (SCADA_LOGIN_LOCKOUT_MINUTES)
+    pub login_lockout: Duration,
+    // How long login attempts are kept in the login history (SCADA_LOGIN_HISTORY_RETENTION_DAYS)
+    pub login_history_retention: Duration,
+    // Time without use after which a non-admin account is disabled, 0 to never disable (SCADA_INACTIVE_USER_DAYS)
+    pub inactive_user_after: Duration,
+    // Expected seconds between reports for machines without their own report_interval (SCADA_DEFAULT_REPORT_INTERVAL_SECS)
+    pub default_report_interval: Duration,
+    // Report intervals a machine may miss before it is marked offline (SCADA_OFFLINE_AFTER_INTERVALS)
+    pub offline_after_intervals: u32,
+    // Clock drift between a machine and the server that raises a warning alarm, 0 to not watch drift (SCADA_MAX_CLOCK_DRIFT_SECS)
+    pub max_clock_drift: i64,
+    // Repeated identical speed updates within this window only refresh last_update, 0 to store every update (SCADA_DEDUP_WINDOW_SECS)
+    pub dedup_window: Duration,
+    // Mail server for outgoing email such as password resets (SCADA_SMTP_HOST); no mail is sent if unset
+    pub smtp_host: Option<String>,
+    // Mail server port (SCADA_SMTP_PORT)
+    pub smtp_port: u16,
+    // How the connection to the mail server is secured (SCADA_SMTP_SECURITY: "starttls", "tls" or "none")
+    pub smtp_security: SmtpSecurity,
+    // Mail server login (SCADA_SMTP_USERNAME, SCADA_SMTP_PASSWORD); no authentication if unset
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    // Sender address of outgoing email (SCADA_MAIL_FROM)
+    pub mail_from: String,
+    // How long a password reset token stays valid (SCADA_PASSWORD_RESET_TTL_MINUTES)
+    pub password_reset_ttl: Duration,
+    // Rules for new passwords (SCADA_PASSWORD_MIN_LENGTH, SCADA_PASSWORD_REQUIRED_CLASSES,
+    // SCADA_PASSWORD_BANNED_FILE, SCADA_PASSWORD_MAX_AGE_DAYS)
+    pub password_policy: PasswordPolicy,
+    // How long a machine command waits for delivery unless the sender sets its own TTL (SCADA_COMMAND_TTL_SECS)
+    pub command_ttl: Duration,
+    // How long a machine's or service account's old API key keeps working after a rotation
+    // (SCADA_KEY_ROTATION_GRACE_HOURS)
+    pub key_rotation_grace: Duration,
+    // How long hourly API usage counts are kept (SCADA_USAGE_RETENTION_DAYS)
+    pub usage_retention: Duration,
+    // Seconds a wallboard shows each machine unless its kiosk group sets its own (SCADA_KIOSK_ROTATION_SECS)
+    pub kiosk_rotation_interval: Duration,
+    // Reject speed updates without an HMAC signature (SCADA_REQUIRE_SIGNED_UPDATES); signed
+    // updates are verified either way
+    pub require_signed_updates: bool,
+    // How far a signed update's timestamp may be from the server clock (SCADA_SIGNATURE_MAX_AGE_SECS)
+    pub signature_max_age: Duration,
+    // Secret the signing secrets of machine API keys are derived from (SCADA_SIGNING_SECRET);
+    // signed updates are refused while unset
+    pub signing_secret: Option<Vec<u8>>,
+    // Proxies whose X-Forwarded-For header is believed when checking a machine's allowed
+    // networks (SCADA_TRUSTED_PROXIES, e.g. "10.0.0.5,10.1.0.0/24"); none while unset
+    pub trusted_proxies: Vec<IpNet>,
+    // Browser origins allowed to call the operator API (SCADA_CORS_ORIGINS, e.g.
+    // "https://hmi.plant.example"); any origin while unset, which is deprecated
+    pub cors_origins: Vec<String>,
+    // Largest request body accepted, except document and logo uploads (SCADA_MAX_BODY_BYTES)
+    pub max_body_bytes: usize,
+    // How long browsers remember to only use HTTPS, 0 to send no Strict-Transport-Security header
+    // (SCADA_HSTS_MAX_AGE_SECS)
+    pub hsts_max_age: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err("expected 'text' or 'json'".to_string()),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        // A fixed offset would put day and shift boundaries an hour off for half the year, so
+        // the old setting is refused rather than silently ignored
+        if std::env::var("SCADA_SITE_UTC_OFFSET").is_ok() {
+            anyhow::bail!("SCADA_SITE_UTC_OFFSET was replaced by SCADA_SITE_TIMEZONE, e.g. SCADA_SITE_TIMEZONE=Europe/Berlin");
+        }
+        let site_timezone = match std::env::var("SCADA_SITE_TIMEZONE") {
+            Ok(value) => value
+                .parse::<Tz>()
+                .map_err(|e| anyhow::anyhow!("Invalid SCADA_SITE_TIMEZONE '{}': {}", value, e))?,
+            Err(_) => Tz::UTC,
+        };
+
+        let shift_starts = parse_shift_starts(
+            &std::env::var("SCADA_SHIFT_STARTS").unwrap_or_else(|_| "06:00,14:00,22:00".into()),
+        )?;
+
+        // The engine is chosen when the server is built, so a deployment pointed at the other
+        // one must not quietly start on a local SQLite file instead
+        let (database_path, database_url) = match std::env::var("DATABASE_URL") {
+            Ok(url) if url.starts_with("sqlite:") && !database::POSTGRES => {
+                let options = SqliteConnectOptions::from_str(&url)
+                    .map_err(|e| anyhow::anyhow!("Invalid DATABASE_URL: {}", e))?;
+                (options.get_filename().to_path_buf(), None)
+            },
+            Ok(url) if (url.starts_with("postgres:") || url.starts_with("postgresql:")) && database::POSTGRES => {
+                (PathBuf::from("database.db"), Some(url))
+            },
+            Ok(url) if url.starts_with("postgres:") || url.starts_with("postgresql:") => anyhow::bail!(
+                "DATABASE_URL points at PostgreSQL, which needs the server built with --features postgres"
+            ),
+            Ok(url) => {
+                let scheme = url.split(':').next().unwrap_or_default();
+                anyhow::bail!(
+                    "DATABASE_URL ({}) is not supported: this server runs on {}",
+                    if scheme.is_empty() { "empty" } else { scheme },
+                    if database::POSTGRES { "PostgreSQL, use a postgres: URL" } else { "SQLite, use a sqlite: URL or set SCADA_DATABASE" }
+                );
+            },
+            Err(_) if database::POSTGRES => anyhow::bail!("DATABASE_URL must point at the PostgreSQL database"),
+            Err(_) => (env_or("SCADA_DATABASE", PathBuf::from("database.db"))?, None),
+        };
+
+        let prediction_model = match env_or("SCADA_PREDICTION_MODEL", "trend".to_string())?.as_str() {
+            "trend" => PredictionModel::Trend,
+            "http" => match std::env::var("SCADA_PREDICTION_URL") {
+                Ok(url) if !url.is_empty() => PredictionModel::Http(url),
+                _ => anyhow::bail!("SCADA_PREDICTION_URL is required with SCADA_PREDICTION_MODEL=http"),
+            },
+            other => anyhow::bail!("Invalid SCADA_PREDICTION_MODEL '{}': expected 'trend' or 'http'", other),
+        };
+        // Zero slots would shed every expensive request
+        let expensive_concurrency: usize = env_or("SCADA_EXPENSIVE_CONCURRENCY", 4)?;
+        if expensive_concurrency == 0 {
+            anyhow::bail!("Invalid SCADA_EXPENSIVE_CONCURRENCY '0': expected at least 1");
+        }
+
+        let download_link_max_hours: u64 = env_or("SCADA_DOWNLOAD_LINK_MAX_HOURS", 168)?;
+        if download_link_max_hours == 0 {
+            anyhow::bail!("Invalid SCADA_DOWNLOAD_LINK_MAX_HOURS '0': expected at least 1");
+        }
+
+        let advisory_risk: f64 = env_or("SCADA_ADVISORY_RISK", 0.7)?;
+        if !(advisory_risk > 0.0 && advisory_risk <= 1.0) {
+            anyhow::bail!("Invalid SCADA_ADVISORY_RISK '{}': expected a risk above 0 and at most 1", advisory_risk);
+        }
+
+        // Requiring signatures nobody can produce would lock out every machine
+        let require_signed_updates: bool = env_or("SCADA_REQUIRE_SIGNED_UPDATES", false)?;
+        let signing_secret = std::env::var("SCADA_SIGNING_SECRET").ok().filter(|secret| !secret.is_empty());
+        if require_signed_updates && signing_secret.is_none() {
+            anyhow::bail!("SCADA_REQUIRE_SIGNED_UPDATES needs SCADA_SIGNING_SECRET");
+        }
+
+        Ok(Self {
+            database_path,
+            database_url,
+            db_max_connections: env_or("SCADA_DB_MAX_CONNECTIONS", 10)?,
+            db_busy_timeout: Duration::from_millis(env_or("SCADA_DB_BUSY_TIMEOUT_MS", 5000)?),
+            db_journal_mode: env_or("SCADA_DB_JOURNAL_MODE", SqliteJournalMode::Wal)?,
+            port: env_or("SCADA_PORT", 8080)?,
+            site_timezone,
+            shift_starts,
+            expensive_concurrency,
+            expensive_queue_timeout: Duration::from_millis(env_or("SCADA_EXPENSIVE_QUEUE_TIMEOUT_MS", 2000)?),
+            job_workers: env_or("SCADA_JOB_WORKERS", 2)?,
+            artifact_dir: env_or("SCADA_ARTIFACT_DIR", PathBuf::from("artifacts"))?,
+            artifact_retention: Duration::from_secs(env_or("SCADA_ARTIFACT_RETENTION_HOURS", 168u64)? * 60 * 60),
+            download_link_max_age: Duration::from_secs(download_link_max_hours * 60 * 60),
+            download_secret: std::env::var("SCADA_DOWNLOAD_SECRET")
+                .map(String::into_bytes)
+                .unwrap_or_else(|_| uuid::Uuid::new_v4().as_bytes().to_vec()),
+            public_url: std::env::var("SCADA_PUBLIC_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_default(),
+            document_dir: env_or("SCADA_DOCUMENT_DIR", PathBuf::from("documents"))?,
+            archive_dir: env_or("SCADA_ARCHIVE_DIR", PathBuf::from("archives"))?,
+            backup_dir: env_or("SCADA_BACKUP_DIR", PathBuf::from("backups"))?,
+            backup_time: match env_or("SCADA_BACKUP_TIME", "02:00".to_string())?.trim() {
+                "" => None,
+                time => Some(
+                    NaiveTime::parse_from_str(time, "%H:%M").map_err(|e| anyhow::anyhow!("Invalid SCADA_BACKUP_TIME: {}", e))?,
+                ),
+            },
+            backup_retention: Duration::from_secs(env_or("SCADA_BACKUP_RETENTION_DAYS", 14u64)? * 24 * 60 * 60),
+            history_retention: Duration::from_secs(env_or("SCADA_HISTORY_RETENTION_DAYS", 0u64)? * 24 * 60 * 60),
+            history_archive: env_or("SCADA_HISTORY_ARCHIVE", false)?,
+            export_approval: env_or("SCADA_EXPORT_APPROVAL", false)?,
+            health_score_time: NaiveTime::parse_from_str(&env_or("SCADA_HEALTH_SCORE_TIME", "05:00".to_string())?, "%H:%M")
+                .map_err(|e| anyhow::anyhow!("Invalid SCADA_HEALTH_SCORE_TIME: {}", e))?,
+            prediction_model,
+            prediction_interval: Duration::from_secs(env_or("SCADA_PREDICTION_INTERVAL_MINUTES", 60u64)? * 60),
+            advisory_risk,
+            labor_rate: env_or("SCADA_LABOR_RATE", 0.0)?,
+            contract_notice: Duration::from_secs(env_or("SCADA_CONTRACT_NOTICE_DAYS", 30u64)? * 24 * 60 * 60),
+            certification_notice: Duration::from_secs(env_or("SCADA_CERTIFICATION_NOTICE_DAYS", 30u64)? * 24 * 60 * 60),
+            certification_policy: env_or("SCADA_CERTIFICATION_POLICY", CertificationPolicy::Enforce)?,
+            unit_policy: env_or("SCADA_UNIT_POLICY", UnitPolicy::Reject)?,
+            sandbox_database_path: env_or("SCADA_SANDBOX_DATABASE", PathBuf::from("sandbox.db"))?,
+            features: features::parse_features(&std::env::var("SCADA_FEATURES").unwrap_or_default())?,
+            log_format: env_or("SCADA_LOG_FORMAT", LogFormat::Text)?,
+            log_levels: parse_log_levels(&std::env::var("SCADA_LOG_LEVELS").unwrap_or_default())?,
+            read_only: env_or("SCADA_READ_ONLY", false)?,
+            read_only_after_failures: env_or("SCADA_READ_ONLY_AFTER_FAILURES", 5)?,
+            dev_chaos: env_or("SCADA_DEV_CHAOS", false)?,
+            admin_user: env_or("SCADA_ADMIN_USER", "admin".to_string())?,
+            admin_password: std::env::var("SCADA_ADMIN_PASSWORD").ok().filter(|password| !password.is_empty()),
+            jwt_secret: std::env::var("SCADA_JWT_SECRET")
+                .map(String::into_bytes)
+                .unwrap_or_else(|_| uuid::Uuid::new_v4().as_bytes().to_vec()),
+            access_token_ttl: Duration::from_secs(env_or("SCADA_ACCESS_TOKEN_TTL_MINUTES", 15u64)? * 60),
+            refresh_token_ttl: Duration::from_secs(env_or("SCADA_REFRESH_TOKEN_TTL_DAYS", 30u64)? * 24 * 60 * 60),
+            impersonation_ttl: Duration::from_secs(env_or("SCADA_IMPERSONATION_TTL_MINUTES", 10u64)? * 60),
+            login_rate_per_minute: env_or("SCADA_LOGIN_RATE_PER_MINUTE", 10)?,
+            user_rate_per_minute: env_or("SCADA_USER_RATE_PER_MINUTE", 600)?,
+            machine_rate_per_minute: env_or("SCADA_MACHINE_RATE_PER_MINUTE", 300)?,
+            anonymous_rate_per_minute: env_or("SCADA_ANONYMOUS_RATE_PER_MINUTE", 600)?,
+            login_max_attempts: env_or("SCADA_LOGIN_MAX_ATTEMPTS", 5)?,
+            login_lockout: Duration::from_secs(env_or("SCADA_LOGIN_LOCKOUT_MINUTES", 15u64)? * 60),
+            login_history_retention: Duration::from_secs(env_or("SCADA_LOGIN_HISTORY_RETENTION_DAYS", 365u64)? * 24 * 60 * 60),
+            inactive_user_after: Duration::from_secs(env_or("SCADA_INACTIVE_USER_DAYS", 0u64)? * 24 * 60 * 60),
+            dedup_window: Duration::from_secs(env_or("SCADA_DEDUP_WINDOW_SECS", 300)?),
+            default_report_interval: Duration::from_secs(env_or("SCADA_DEFAULT_REPORT_INTERVAL_SECS", 60)?),
+            offline_after_intervals: env_or("SCADA_OFFLINE_AFTER_INTERVALS", 3)?,
+            max_clock_drift: env_or("SCADA_MAX_CLOCK_DRIFT_SECS", 30)?,
+            smtp_host: std::env::var("SCADA_SMTP_HOST").ok().filter(|host| !host.is_empty()),
+            smtp_port: env_or("SCADA_SMTP_PORT", 587)?,
+            smtp_security: env_or("SCADA_SMTP_SECURITY", SmtpSecurity::StartTls)?,
+            smtp_username: std::env::var("SCADA_SMTP_USERNAME").ok().filter(|username| !username.is_empty()),
+            smtp_password: std::env::var("SCADA_SMTP_PASSWORD").ok(),
+            mail_from: env_or("SCADA_MAIL_FROM", "SCADA <scada@localhost>".to_string())?,
+            password_reset_ttl: Duration::from_secs(env_or("SCADA_PASSWORD_RESET_TTL_MINUTES", 30u64)? * 60),
+            password_policy: PasswordPolicy::new(
+                env_or("SCADA_PASSWORD_MIN_LENGTH", 10)?,
+                &env_or("SCADA_PASSWORD_REQUIRED_CLASSES", "lowercase,uppercase,digit".to_string())?,
+                std::env::var("SCADA_PASSWORD_BANNED_FILE").ok().filter(|path| !path.is_empty()).map(PathBuf::from).as_deref(),
+                env_or("SCADA_PASSWORD_MAX_AGE_DAYS", 0)?,
+            )?,
+            command_ttl: Duration::from_secs(env_or("SCADA_COMMAND_TTL_SECS", 300)?),
+            key_rotation_grace: Duration::from_secs(env_or("SCADA_KEY_ROTATION_GRACE_HOURS", 24u64)? * 3600),
+            usage_retention: Duration::from_secs(env_or("SCADA_USAGE_RETENTION_DAYS", 90u64)? * 24 * 60 * 60),
+            kiosk_rotation_interval: Duration::from_secs(env_or("SCADA_KIOSK_ROTATION_SECS", 15)?),
+            require_signed_updates,
+            signature_max_age: Duration::from_secs(env_or("SCADA_SIGNATURE_MAX_AGE_SECS", 300)?),
+            signing_secret: signing_secret.map(String::into_bytes),
+            trusted_proxies: parse_trusted_proxies(&std::env::var("SCADA_TRUSTED_PROXIES").unwrap_or_default())?,
+            cors_origins: parse_cors_origins(&std::env::var("SCADA_CORS_ORIGINS").unwrap_or_default())?,
+            max_body_bytes: env_or("SCADA_MAX_BODY_BYTES", 2 * 1024 * 1024)?,
+            hsts_max_age: Duration::from_secs(env_or("SCADA_HSTS_MAX_AGE_SECS", 365 * 24 * 60 * 60)?),
+        })
+    }
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> anyhow::Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid {} '{}': {}", name, value, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+fn parse_shift_starts(value: &str) -> anyhow::Result<Vec<NaiveTime>> {
+    let mut starts = value
+        .split(',')
+        .map(|s| {
+            NaiveTime::parse_from_str(s.trim(), "%H:%M")
+                .map_err(|e| anyhow::anyhow!("Invalid shift start '{}' in SCADA_SHIFT_STARTS: {}", s, e))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    starts.sort();
+    starts.dedup();
+    Ok(starts)
+}
+
+fn parse_trusted_proxies(value: &str) -> anyhow::Result<Vec<IpNet>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .map(|proxy| {
+            network::parse_cidr(proxy)
+                .ok_or_else(|| anyhow::anyhow!("Invalid address '{}' in SCADA_TRUSTED_PROXIES, expected e.g. 10.0.0.5 or 10.1.0.0/24", proxy))
+        })
+        .collect()
+}
+
+fn parse_cors_origins(value: &str) -> anyhow::Result<Vec<String>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            let origin = origin.trim_end_matches('/');
+            let host = origin
+                .strip_prefix("https://")
+                .or_else(|| origin.strip_prefix("http://"))
+                .ok_or_else(|| anyhow::anyhow!("Invalid origin '{}' in SCADA_CORS_ORIGINS, expected e.g. https://hmi.example.com", origin))?;
+            if host.is_empty() || host.contains(['/', '*']) || !host.chars().all(|c| c.is_ascii_graphic()) {
+                anyhow::bail!("Invalid origin '{}' in SCADA_CORS_ORIGINS, expected scheme and host only", origin);
+            }
+            Ok(origin.to_string())
+        })
+        .collect()
+}
+
+fn parse_log_levels(value: &str) -> anyhow::Result<BTreeMap<String, LevelFilter>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (module, level) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Invalid entry '{}' in SCADA_LOG_LEVELS, expected module=level", entry))?;
+            let level = level
+                .trim()
+                .parse::<LevelFilter>()
+                .map_err(|e| anyhow::anyhow!("Invalid level for '{}' in SCADA_LOG_LEVELS: {}", module, e))?;
+            Ok((module.trim().to_string(), level))
+        })
+        .collect()
+}
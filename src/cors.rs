@@ -0,0 +1,81 @@
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{Any, CorsLayer};
+
+const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+// Browsers may cache a preflight answer for this long
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+// Operator API: only the configured origins, with the headers the web UI needs for
+// authentication, conditional requests and localization
+pub fn operator_policy(origins: &[String]) -> CorsLayer {
+    if origins.is_empty() {
+        tracing::warn!(
+            "SCADA_CORS_ORIGINS is not set, so any website may call the operator API from a browser. \
+             This is deprecated; set it to the origins of your web clients."
+        );
+        return CorsLayer::permissive();
+    }
+
+    let origins: Vec<HeaderValue> = origins
+        .iter()
+        .map(|origin| HeaderValue::from_str(origin).expect("origins are validated when the config is loaded"))
+        .collect();
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::ACCEPT_LANGUAGE,
+            header::IF_NONE_MATCH,
+            header::IF_MODIFIED_SINCE,
+            REQUEST_ID,
+        ])
+        .expose_headers([header::ETAG, header::LAST_MODIFIED, header::RETRY_AFTER, header::CONTENT_DISPOSITION, REQUEST_ID])
+        .max_age(PREFLIGHT_MAX_AGE)
+}
+
+// Public status: anyone may read it, nothing else
+pub fn public_policy() -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods([Method::GET])
+        .max_age(PREFLIGHT_MAX_AGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::Request, http::StatusCode, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn preflight(policy: CorsLayer, origin: &str, method: &str) -> Option<String> {
+        let app = Router::new().route("/api/machines", get(|| async { "machines" })).layer(policy);
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/api/machines")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn operator_api_answers_only_the_configured_origins() {
+        let origins = ["https://hmi.example.com".to_string()];
+        assert_eq!(
+            preflight(operator_policy(&origins), "https://hmi.example.com", "PUT").await.as_deref(),
+            Some("https://hmi.example.com"),
+        );
+        assert_eq!(preflight(operator_policy(&origins), "https://evil.example.net", "PUT").await, None);
+        assert_eq!(preflight(public_policy(), "https://evil.example.net", "GET").await.as_deref(), Some("*"));
+    }
+}
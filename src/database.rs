@@ -1,15 +1,62 @@
-use sqlx::SqlitePool;
+use sqlx::{migrate::Migrator, SqlitePool};
+#[cfg(not(feature = "postgres"))]
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+#[cfg(feature = "postgres")]
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::path::Path;
+#[cfg(not(feature = "postgres"))]
 use std::fs;
 
-pub type DbPool = SqlitePool;
+use crate::{auth, chaos::Chaos, config::Config};
 
-pub async fn init_database() -> anyhow::Result<DbPool> {
-    let db_path = "database.db";
-    
+// The server runs on SQLite, or on PostgreSQL when built with the postgres feature. Queries
+// are written once for both: placeholders are numbered ($1, $2, ...) and anything the two
+// engines spell differently goes through the helpers at the end of this file.
+#[cfg(not(feature = "postgres"))]
+pub type Db = sqlx::Sqlite;
+#[cfg(feature = "postgres")]
+pub type Db = sqlx::Postgres;
+pub type DbPool = sqlx::Pool<Db>;
+pub type DbRow = <Db as sqlx::Database>::Row;
+
+// Whether the server was built for PostgreSQL. File backups, sandbox copies and injected
+// statement failures work on the SQLite file and are unavailable there.
+pub const POSTGRES: bool = cfg!(feature = "postgres");
+
+// Schema changes are files in migrations/, applied in order at startup. Applied migrations are
+// recorded with a checksum, so a migration must never be edited once released; change the
+// schema with a new one instead. migrations/postgres/ holds the same migrations for
+// PostgreSQL, and every new migration needs a version there too.
+#[cfg(not(feature = "postgres"))]
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+#[cfg(feature = "postgres")]
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+#[cfg(feature = "postgres")]
+pub async fn init_database(config: &Config, _chaos: Option<Chaos>) -> anyhow::Result<DbPool> {
+    let url = config
+        .database_url
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("DATABASE_URL must point at the PostgreSQL database"))?;
+    // Waiting for locks is bounded like SQLite's busy timeout
+    let lock_timeout = format!("{}ms", config.db_busy_timeout.as_millis());
+    let connect_options = url
+        .parse::<PgConnectOptions>()?
+        .options([("lock_timeout", lock_timeout.as_str())]);
+    let pool = PgPoolOptions::new()
+        .max_connections(config.db_max_connections)
+        .connect_with(connect_options)
+        .await?;
+    MIGRATOR.run(&pool).await?;
+    Ok(pool)
+}
+
+#[cfg(not(feature = "postgres"))]
+pub async fn init_database(config: &Config, chaos: Option<Chaos>) -> anyhow::Result<DbPool> {
+    let db_path = config.database_path.as_path();
     // Check if database file exists and is writable
-    if Path::new(db_path).exists() {
+    if db_path.exists() {
         // Check if file is writable
         if let Err(e) = fs::OpenOptions::new()
             .write(true)
@@ -19,76 +66,272 @@ pub async fn init_database() -> anyhow::Result<DbPool> {
         }
     }
     
-    let pool = SqlitePool::connect(&format!("sqlite:{}", db_path)).await?;
-    
-    // Create tables
-    sqlx::query(r#"
-        CREATE TABLE IF NOT EXISTS machines (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL UNIQUE,
-            code TEXT NOT NULL UNIQUE,
-            api_key TEXT NOT NULL UNIQUE,
-            location TEXT,
-            machine_type TEXT,
-            current_speed REAL DEFAULT 0.0,
-            status_message TEXT DEFAULT '',
-            last_update INTEGER DEFAULT 0,
-            is_online BOOLEAN DEFAULT 0,
-            created_at INTEGER DEFAULT (strftime('%s', 'now'))
-        )
-    "#).execute(&pool).await?;
-
-    sqlx::query(r#"
-        CREATE TABLE IF NOT EXISTS users (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            username TEXT NOT NULL UNIQUE,
-            password TEXT NOT NULL,
-            role TEXT NOT NULL CHECK (role IN ('admin', 'manager', 'technician')),
-            token TEXT UNIQUE,
-            created_at INTEGER DEFAULT (strftime('%s', 'now'))
-        )
-    "#).execute(&pool).await?;
-
-    sqlx::query(r#"
-        CREATE TABLE IF NOT EXISTS maintenance_comments (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            machine_id INTEGER NOT NULL,
-            username TEXT NOT NULL,
-            comment TEXT NOT NULL,
-            priority TEXT DEFAULT 'normal' CHECK (priority IN ('low', 'normal', 'high', 'critical')),
-            created_at INTEGER DEFAULT (strftime('%s', 'now')),
-            FOREIGN KEY (machine_id) REFERENCES machines (id)
-        )
-    "#).execute(&pool).await?;
-
-    sqlx::query(r#"
-        CREATE TABLE IF NOT EXISTS speed_history (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            machine_id INTEGER NOT NULL,
-            speed REAL NOT NULL,
-            message TEXT,
-            timestamp INTEGER DEFAULT (strftime('%s', 'now')),
-            FOREIGN KEY (machine_id) REFERENCES machines (id)
-        )
-    "#).execute(&pool).await?;
-
-    // Insert hardcoded admin user
-    sqlx::query(r#"
-        INSERT OR IGNORE INTO users (username, password, role, token) 
-        VALUES ('admin', 'admin123', 'admin', 'admin_token_12345')
-    "#).execute(&pool).await?;
-
-    // Create indexes
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_machines_api_key ON machines(api_key)").execute(&pool).await?;
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_speed_history_machine ON speed_history(machine_id)").execute(&pool).await?;
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_maintenance_machine ON maintenance_comments(machine_id)").execute(&pool).await?;
+    let connect_options = SqliteConnectOptions::new()
+        .filename(db_path)
+        .journal_mode(config.db_journal_mode)
+        .busy_timeout(config.db_busy_timeout)
+        .foreign_keys(true);
+
+    let mut options = SqlitePoolOptions::new().max_connections(config.db_max_connections);
+    if let Some(chaos) = chaos {
+        // Interrupt statements while injected failures are pending; the callback runs on the first
+        // step of every statement, so each injected failure fails exactly one statement
+        options = options.after_connect(move |conn, _| {
+            let chaos = chaos.clone();
+            Box::pin(async move {
+                conn.lock_handle().await?.set_progress_handler(1, move || !chaos.take_db_failure());
+                Ok(())
+            })
+        });
+    }
+    let pool = options.connect_with(connect_options).await?;
+
+    if !is_migrated(&pool).await? {
+        upgrade_legacy_schema(&pool).await?;
+    }
+    MIGRATOR.run(&pool).await?;
 
     Ok(pool)
 }
 
+// Whether the schema is managed by migrations. Databases created before migrations have
+// tables but no record of applied migrations.
+#[cfg(not(feature = "postgres"))]
+async fn is_migrated(pool: &DbPool) -> anyhow::Result<bool> {
+    let (has_migrations, has_machines): (bool, bool) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'), \
+         EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'machines')"
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(has_migrations || !has_machines)
+}
+
+// Columns added to existing databases before schema changes moved to migrations; the initial
+// migration creates tables with all of them
+#[cfg(not(feature = "postgres"))]
+const LEGACY_COLUMNS: &[(&str, &str, &str)] = &[
+    ("machines", "updated_at", "INTEGER DEFAULT 0"),
+    ("machines", "target_speed", "REAL"),
+    ("machines", "report_interval", "INTEGER"),
+    ("machines", "allowed_cidrs", "TEXT"),
+    ("machines", "clock_drift", "INTEGER"),
+    ("machines", "dedup_updates", "BOOLEAN DEFAULT 1"),
+    ("users", "tokens_revoked_at", "INTEGER"),
+    ("users", "email", "TEXT"),
+    ("users", "password_changed_at", "INTEGER"),
+    ("users", "last_login", "INTEGER"),
+    ("users", "is_active", "INTEGER NOT NULL DEFAULT 1"),
+    ("users", "deactivated_at", "INTEGER"),
+    ("users", "deactivation_reason", "TEXT"),
+    ("users", "reactivated_at", "INTEGER"),
+    ("command_types", "min_role", "TEXT NOT NULL DEFAULT 'manager'"),
+    ("machine_commands", "expires_at", "INTEGER"),
+    ("machine_commands", "cancelled_by", "TEXT"),
+    ("machine_api_keys", "expires_at", "INTEGER"),
+    ("jobs", "artifact_path", "TEXT"),
+    ("jobs", "artifact_name", "TEXT"),
+    ("jobs", "artifact_content_type", "TEXT"),
+    ("jobs", "artifact_expires_at", "INTEGER"),
+    ("alarms", "root_cause", "TEXT REFERENCES alarm_root_causes (code)"),
+    ("alarms", "notes", "TEXT"),
+    ("alarms", "annotated_by", "TEXT"),
+    ("alarms", "annotated_at", "INTEGER"),
+    ("work_orders", "alarm_id", "INTEGER REFERENCES alarms (id)"),
+    ("work_orders", "required_skill", "TEXT"),
+    ("maintenance_comments", "category_id", "INTEGER REFERENCES comment_categories (id)"),
+    ("maintenance_comments", "pinned", "BOOLEAN NOT NULL DEFAULT 0"),
+    ("maintenance_comments", "resolved_by", "TEXT"),
+    ("maintenance_comments", "resolved_at", "INTEGER"),
+    ("maintenance_comments", "updated_at", "INTEGER"),
+    ("refresh_tokens", "session_id", "TEXT"),
+];
+
+// Brings a database from before migrations to the schema of the initial migration, which then
+// only adds the tables and indexes it is missing
+#[cfg(not(feature = "postgres"))]
+async fn upgrade_legacy_schema(pool: &DbPool) -> anyhow::Result<()> {
+    tracing::info!("Upgrading database created before schema migrations");
+    for (table, column, definition) in LEGACY_COLUMNS {
+        add_column_if_missing(pool, table, column, definition).await?;
+    }
+
+    // Passwords set before their age was tracked count as changed at the upgrade
+    sqlx::query("UPDATE users SET password_changed_at = $1 WHERE password_changed_at IS NULL")
+        .bind(current_timestamp())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// Tables missing altogether are left to the initial migration
+#[cfg(not(feature = "postgres"))]
+async fn add_column_if_missing(pool: &DbPool, table: &str, column: &str, definition: &str) -> anyhow::Result<()> {
+    let (table_exists, column_exists): (bool, bool) = sqlx::query_as(&format!(
+        "SELECT COUNT(*) > 0, COALESCE(SUM(name = $1), 0) > 0 FROM pragma_table_info('{}')",
+        table
+    ))
+    .bind(column)
+    .fetch_one(pool)
+    .await?;
+
+    if table_exists && !column_exists {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// Account the sandbox is administered with; every production account is left behind
+pub const SANDBOX_ADMIN: &str = "sandbox-admin";
+
+// Copy the production database into a sandbox file. Configuration is kept as-is;
+// operational records are dropped and history older than the cutoff is pruned.
+// Every production credential is removed before the file takes the sandbox's name, and
+// SANDBOX_ADMIN is created with `admin_password`. Returns the number of speed history rows
+// carried over.
+pub async fn clone_to_sandbox(pool: &DbPool, sandbox_path: &Path, history_from: Option<i64>, admin_password: &str) -> anyhow::Result<i64> {
+    let partial_path = sandbox_path.with_extension("partial");
+    if partial_path.exists() {
+        tokio::fs::remove_file(&partial_path).await?;
+    }
+
+    sqlx::query("VACUUM INTO $1")
+        .bind(partial_path.to_string_lossy().to_string())
+        .execute(pool)
+        .await?;
+
+    let sandbox = SqlitePool::connect(&format!("sqlite:{}", partial_path.display())).await?;
+    let cutoff = history_from.unwrap_or(i64::MAX);
+
+    // Jobs and documents reference production files on disk, which the sandbox must never touch
+    for statement in [
+        "DELETE FROM jobs",
+        "DELETE FROM export_requests",
+        "UPDATE machine_documents SET file_path = NULL, file_name = NULL, content_type = NULL",
+        "DELETE FROM notifications",
+        "DELETE FROM alarms",
+        "DELETE FROM compliance_exceedances",
+        "DELETE FROM maintenance_advisories",
+        "DELETE FROM work_order_labor",
+        "DELETE FROM work_order_steps",
+        "DELETE FROM maintenance_costs",
+        "DELETE FROM work_permits",
+        "DELETE FROM work_orders",
+        "DELETE FROM machine_commands",
+    ] {
+        sqlx::query(statement).execute(&sandbox).await?;
+    }
+
+    // A leaked sandbox file must not let anyone into production: accounts, sessions and
+    // every kind of key go, and machines get keys nobody knows
+    for statement in [
+        "DELETE FROM users",
+        "DELETE FROM user_machine_access",
+        "DELETE FROM sessions",
+        "DELETE FROM refresh_tokens",
+        "DELETE FROM password_reset_tokens",
+        "DELETE FROM revoked_tokens",
+        "DELETE FROM impersonations",
+        "DELETE FROM login_attempts",
+        "DELETE FROM login_history",
+        "DELETE FROM machine_api_keys",
+        "DELETE FROM signed_request_nonces",
+        "DELETE FROM service_accounts",
+        "DELETE FROM display_tokens",
+        "UPDATE machines SET api_key = lower(hex(randomblob(32)))",
+    ] {
+        sqlx::query(statement).execute(&sandbox).await?;
+    }
+    sqlx::query("INSERT INTO users (username, password, role, password_changed_at) VALUES ($1, $2, 'admin', $3)")
+        .bind(SANDBOX_ADMIN)
+        .bind(auth::hash_password(admin_password).await?)
+        .bind(current_timestamp())
+        .execute(&sandbox)
+        .await?;
+
+    sqlx::query("DELETE FROM speed_history WHERE timestamp < $1").bind(cutoff).execute(&sandbox).await?;
+    sqlx::query("DELETE FROM speed_history_hourly WHERE bucket_start < $1").bind(cutoff).execute(&sandbox).await?;
+    sqlx::query("DELETE FROM speed_history_daily WHERE bucket_start < $1").bind(cutoff).execute(&sandbox).await?;
+    sqlx::query("DELETE FROM metric_readings WHERE timestamp < $1").bind(cutoff).execute(&sandbox).await?;
+    sqlx::query("DELETE FROM maintenance_comments WHERE created_at < $1").bind(cutoff).execute(&sandbox).await?;
+
+    let history_rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM speed_history")
+        .fetch_one(&sandbox)
+        .await?;
+
+    // Deleted rows would otherwise linger in free pages of the file
+    sqlx::query("VACUUM").execute(&sandbox).await?;
+    sandbox.close().await;
+    tokio::fs::rename(&partial_path, sandbox_path).await?;
+
+    Ok(history_rows)
+}
+
 pub fn current_timestamp() -> i64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs() as i64
-}
\ No newline at end of file
+}
+
+// SQL that SQLite and PostgreSQL spell differently
+
+// The largest of the comma-separated `values`, SQLite's MAX() with several arguments. None of
+// the values may be NULL: PostgreSQL's GREATEST() skips them where SQLite returns NULL.
+pub fn greatest(values: &str) -> String {
+    if POSTGRES {
+        format!("GREATEST({})", values)
+    } else {
+        format!("MAX({})", values)
+    }
+}
+
+// The UTC date of a unix timestamp expression as text, in a strftime `format` made of %Y and %m
+pub fn format_date(timestamp: &str, format: &str) -> String {
+    if POSTGRES {
+        let pattern = format.replace("%Y", "YYYY").replace("%m", "MM");
+        format!("to_char(to_timestamp({}) AT TIME ZONE 'UTC', '{}')", timestamp, pattern)
+    } else {
+        format!("strftime('{}', {}, 'unixepoch')", format, timestamp)
+    }
+}
+
+// The values of a column across a group, joined with commas
+pub fn string_list(column: &str) -> String {
+    if POSTGRES {
+        format!("string_agg(CAST({} AS TEXT), ',')", column)
+    } else {
+        format!("GROUP_CONCAT({})", column)
+    }
+}
+
+// A migrated, empty database for tests: in memory on SQLite, and on PostgreSQL a schema of its
+// own in the database at DATABASE_URL, so tests can run in parallel. Those schemas are left
+// behind; point DATABASE_URL at a scratch database.
+#[cfg(all(test, not(feature = "postgres")))]
+pub async fn test_database() -> DbPool {
+    // Every connection to :memory: opens its own database
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    MIGRATOR.run(&pool).await.unwrap();
+    pool
+}
+
+#[cfg(all(test, feature = "postgres"))]
+pub async fn test_database() -> DbPool {
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must point at a PostgreSQL database for the tests");
+    let schema = format!("test_{}", uuid::Uuid::new_v4().simple());
+    let options = url.parse::<PgConnectOptions>().unwrap();
+    let pool = PgPoolOptions::new().max_connections(1).connect_with(options.clone()).await.unwrap();
+    sqlx::query(&format!("CREATE SCHEMA {}", schema)).execute(&pool).await.unwrap();
+    pool.close().await;
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect_with(options.options([("search_path", schema.as_str())]))
+        .await
+        .unwrap();
+    MIGRATOR.run(&pool).await.unwrap();
+    pool
+}
@@ -0,0 +1,150 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::{self, extract_token},
+    database::{DbPool, current_timestamp},
+    models::ErrorResponse,
+};
+
+// Display tokens are told apart from session tokens and other keys by this prefix
+pub const KEY_PREFIX: &str = "display_";
+
+// Everything a wall-mounted display needs: machine status and history. Comments, users and
+// every write stay closed to display tokens.
+const DISPLAY_ROUTES: &[(Method, &str)] = &[
+    (Method::GET, "/api/machines"),
+    (Method::GET, "/api/machines/{id}/history"),
+    (Method::GET, "/api/kiosk/rotation"),
+];
+
+// A request authenticated with a display token, for AuthUser to pick up
+#[derive(Debug, Clone)]
+pub struct DisplayPrincipal {
+    pub name: String,
+}
+
+impl DisplayPrincipal {
+    // Name the token's machine visibility is keyed by, e.g. "display:packing-hall"
+    pub fn username(&self) -> String {
+        format!("display:{}", self.name)
+    }
+}
+
+pub fn generate_token() -> String {
+    format!("{}{}", KEY_PREFIX, Uuid::new_v4().simple())
+}
+
+fn reject(status: StatusCode, error: String) -> Response {
+    (status, Json(ErrorResponse { error })).into_response()
+}
+
+// Authenticates requests carrying a display token and lets them through only to the read-only
+// display endpoints. Revoked and expired tokens are refused.
+pub async fn authenticate(State(pool): State<DbPool>, mut request: Request, next: Next) -> Response {
+    let Some(token) = extract_token(request.headers()).filter(|token| token.starts_with(KEY_PREFIX)) else {
+        return next.run(request).await;
+    };
+
+    let now = current_timestamp();
+    let display = sqlx::query_as::<_, (i64, String)>(
+        "SELECT id, name FROM display_tokens WHERE token = $1 AND revoked_at IS NULL \
+         AND (expires_at IS NULL OR expires_at > $2)"
+    )
+    .bind(auth::hash_token(&token))
+    .bind(now)
+    .fetch_optional(&pool)
+    .await;
+    let (token_id, name) = match display {
+        Ok(Some(display)) => display,
+        Ok(None) => return reject(StatusCode::UNAUTHORIZED, "Invalid or expired display token".to_string()),
+        Err(_) => return reject(StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string()),
+    };
+
+    let route = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string());
+    if !DISPLAY_ROUTES
+        .iter()
+        .any(|(method, path)| method == request.method() && Some(*path) == route.as_deref())
+    {
+        return reject(StatusCode::FORBIDDEN, "Endpoint not available to display tokens".to_string());
+    }
+
+    // Displays poll constantly, so last use is kept to the minute like service accounts'
+    if let Err(e) = sqlx::query(
+        "UPDATE display_tokens SET last_used_at = $1 WHERE id = $2 AND (last_used_at IS NULL OR last_used_at < $3)"
+    )
+    .bind(now)
+    .bind(token_id)
+    .bind(now - 60)
+    .execute(&pool)
+    .await
+    {
+        tracing::warn!("Failed to record use of display token {}: {}", name, e);
+    }
+
+    request.extensions_mut().insert(DisplayPrincipal { name });
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Extension, Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    fn app(pool: DbPool) -> Router {
+        let caller = |principal: Option<Extension<DisplayPrincipal>>| async move {
+            principal.map_or("anonymous".to_string(), |Extension(principal)| principal.username())
+        };
+        Router::new()
+            .route("/api/machines", get(caller).post(caller))
+            .route("/api/machines/{id}/comments", get(caller))
+            .layer(middleware::from_fn_with_state(pool, authenticate))
+    }
+
+    async fn send(app: &Router, method: Method, path: &str, token: &str) -> (StatusCode, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header("authorization", format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn tokens_only_read_display_endpoints_until_revoked() {
+        let pool = crate::database::test_database().await;
+        let (token, expired) = (generate_token(), generate_token());
+        for (name, token, expires_at) in [("packing-hall", &token, None), ("lobby", &expired, Some(1))] {
+            sqlx::query(
+                "INSERT INTO display_tokens (name, token, expires_at, created_by, created_at) VALUES ($1, $2, $3, 'boss', 0)"
+            )
+            .bind(name)
+            .bind(auth::hash_token(token))
+            .bind(expires_at)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let app = app(pool.clone());
+
+        let display = (StatusCode::OK, "display:packing-hall".to_string());
+        assert_eq!(send(&app, Method::GET, "/api/machines", &token).await, display);
+        assert_eq!(send(&app, Method::POST, "/api/machines", &token).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, Method::GET, "/api/machines/1/comments", &token).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, Method::GET, "/api/machines", &expired).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(&app, Method::GET, "/api/machines", "session").await, (StatusCode::OK, "anonymous".to_string()));
+
+        sqlx::query("UPDATE display_tokens SET revoked_at = 1 WHERE name = 'packing-hall'").execute(&pool).await.unwrap();
+        assert_eq!(send(&app, Method::GET, "/api/machines", &token).await.0, StatusCode::UNAUTHORIZED);
+    }
+}
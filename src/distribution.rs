@@ -0,0 +1,111 @@
+use sqlx::{Row, Transaction};
+
+use crate::{
+    database::{Db, DbPool},
+    models::{DistributionList, DistributionListMember},
+};
+
+async fn members(pool: &DbPool, list_id: i64) -> sqlx::Result<Vec<DistributionListMember>> {
+    sqlx::query_as::<_, (Option<String>, Option<String>)>(
+        "SELECT email, username FROM distribution_list_members WHERE list_id = $1 ORDER BY id"
+    )
+    .bind(list_id)
+    .fetch_all(pool)
+    .await
+    .map(|rows| rows.into_iter().map(|(email, username)| DistributionListMember { email, username }).collect())
+}
+
+// Email addresses a list currently reaches. Users are resolved to the address on their
+// account at send time, so an address change follows the user; users without an address
+// are skipped. Addresses are compared case-insensitively.
+pub async fn recipients(pool: &DbPool, list_id: i64) -> sqlx::Result<Vec<String>> {
+    sqlx::query_scalar(
+        "SELECT address FROM ( \
+         SELECT COALESCE(u.email, m.email) AS address, m.id, \
+         ROW_NUMBER() OVER (PARTITION BY lower(COALESCE(u.email, m.email)) ORDER BY m.id) AS position \
+         FROM distribution_list_members m LEFT JOIN users u ON u.username = m.username \
+         WHERE m.list_id = $1 AND COALESCE(u.email, m.email) IS NOT NULL) addresses \
+         WHERE position = 1 ORDER BY id"
+    )
+    .bind(list_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn load(pool: &DbPool, list_id: i64) -> sqlx::Result<Option<DistributionList>> {
+    let Some(row) = sqlx::query("SELECT * FROM distribution_lists WHERE id = $1")
+        .bind(list_id)
+        .fetch_optional(pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+    Ok(Some(DistributionList {
+        id: row.get("id"),
+        name: row.get("name"),
+        description: row.get("description"),
+        members: members(pool, list_id).await?,
+        recipients: recipients(pool, list_id).await?,
+        created_by: row.get("created_by"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }))
+}
+
+pub async fn replace_members(
+    tx: &mut Transaction<'_, Db>,
+    list_id: i64,
+    members: &[DistributionListMember],
+) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM distribution_list_members WHERE list_id = $1")
+        .bind(list_id)
+        .execute(&mut **tx)
+        .await?;
+    for member in members {
+        sqlx::query("INSERT INTO distribution_list_members (list_id, email, username) VALUES ($1, $2, $3)")
+            .bind(list_id)
+            .bind(&member.email)
+            .bind(&member.username)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(address: &str) -> DistributionListMember {
+        DistributionListMember { email: Some(address.to_string()), username: None }
+    }
+
+    fn user(username: &str) -> DistributionListMember {
+        DistributionListMember { email: None, username: Some(username.to_string()) }
+    }
+
+    #[tokio::test]
+    async fn recipients_follow_user_addresses_without_duplicates() {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO users (username, password, role, email) VALUES \
+             ('alice', 'x', 'manager', 'ops@plant.example'), ('bob', 'x', 'technician', NULL), \
+             ('carol', 'x', 'technician', 'carol@plant.example')",
+            "INSERT INTO distribution_lists (name, created_by, created_at, updated_at) VALUES ('Shift reports', 'boss', 0, 0)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        let mut tx = pool.begin().await.unwrap();
+        replace_members(&mut tx, 1, &[email("qa@plant.example"), user("carol")]).await.unwrap();
+        let members = [email("Ops@plant.example"), user("alice"), user("bob"), user("carol"), email("qa@plant.example")];
+        replace_members(&mut tx, 1, &members).await.unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(recipients(&pool, 1).await.unwrap(), ["Ops@plant.example", "carol@plant.example", "qa@plant.example"]);
+
+        sqlx::query("UPDATE users SET email = 'carol@home.example' WHERE username = 'carol'").execute(&pool).await.unwrap();
+        let list = load(&pool, 1).await.unwrap().unwrap();
+        assert_eq!(list.members.len(), 5);
+        assert_eq!(list.recipients, ["Ops@plant.example", "carol@home.example", "qa@plant.example"]);
+        assert!(load(&pool, 2).await.unwrap().is_none());
+    }
+}
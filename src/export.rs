@@ -0,0 +1,124 @@
+use rust_xlsxwriter::{Format, Workbook, XlsxError};
+use std::str::FromStr;
+
+// File formats for tabular downloads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "csv" => Ok(Self::Csv),
+            "xlsx" => Ok(Self::Xlsx),
+            _ => Err("expected 'csv' or 'xlsx'".to_string()),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv",
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+        }
+    }
+
+    // Renders a table with a header row; XLSX files get a single sheet named `sheet`
+    pub fn render(self, sheet: &str, header: &[&str], rows: &[Vec<String>]) -> Result<Vec<u8>, XlsxError> {
+        match self {
+            Self::Csv => Ok(to_csv(header, rows)),
+            Self::Xlsx => to_xlsx(sheet, header, rows),
+        }
+    }
+}
+
+// Quotes a CSV field when it contains separators, quotes or newlines
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn to_csv(header: &[&str], rows: &[Vec<String>]) -> Vec<u8> {
+    let mut csv = header.join(",");
+    csv.push('\n');
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv.into_bytes()
+}
+
+fn to_xlsx(sheet: &str, header: &[&str], rows: &[Vec<String>]) -> Result<Vec<u8>, XlsxError> {
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(sheet)?;
+
+    let bold = Format::new().set_bold();
+    for (column, title) in header.iter().enumerate() {
+        worksheet.write_string_with_format(0, column as u16, *title, &bold)?;
+    }
+    for (row, fields) in rows.iter().enumerate() {
+        for (column, field) in fields.iter().enumerate() {
+            worksheet.write_string(row as u32 + 1, column as u16, field)?;
+        }
+    }
+    worksheet.set_freeze_panes(1, 0)?;
+    worksheet.autofilter(0, 0, rows.len() as u32, header.len().saturating_sub(1) as u16)?;
+    worksheet.autofit();
+
+    workbook.save_to_buffer()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<Vec<String>> {
+        vec![
+            vec!["1".to_string(), "Belt \"B2\" slipping, again".to_string()],
+            vec!["2".to_string(), "Two\nlines".to_string()],
+        ]
+    }
+
+    #[test]
+    fn csv_quotes_only_fields_that_need_it() {
+        let csv = ExportFormat::Csv.render("Comments", &["id", "comment"], &rows()).unwrap();
+        let expected = "id,comment\n1,\"Belt \"\"B2\"\" slipping, again\"\n2,\"Two\nlines\"\n";
+        assert_eq!(String::from_utf8(csv).unwrap(), expected);
+        assert_eq!(csv_field("plain"), "plain");
+    }
+
+    #[test]
+    fn xlsx_is_a_workbook_with_the_named_sheet() {
+        let xlsx = ExportFormat::Xlsx.render("Comments", &["id", "comment"], &rows()).unwrap();
+        let mut workbook = zip::ZipArchive::new(std::io::Cursor::new(xlsx)).unwrap();
+        let mut manifest = String::new();
+        std::io::Read::read_to_string(&mut workbook.by_name("xl/workbook.xml").unwrap(), &mut manifest).unwrap();
+        assert!(manifest.contains(r#"name="Comments""#), "{}", manifest);
+        assert!(workbook.by_name("xl/worksheets/sheet1.xml").is_ok());
+    }
+
+    #[test]
+    fn formats_parse_from_their_extension() {
+        for format in [ExportFormat::Csv, ExportFormat::Xlsx] {
+            assert_eq!(format.extension().parse(), Ok(format));
+        }
+        assert!("pdf".parse::<ExportFormat>().is_err());
+    }
+}
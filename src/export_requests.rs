@@ -0,0 +1,79 @@
+use crate::{
+    auth::{AuthUser, Role},
+    database::DbPool,
+    models::ExportRequest,
+};
+
+pub async fn fetch(pool: &DbPool, request_id: i64) -> sqlx::Result<Option<ExportRequest>> {
+    sqlx::query_as::<_, ExportRequest>("SELECT * FROM export_requests WHERE id = $1")
+        .bind(request_id)
+        .fetch_optional(pool)
+        .await
+}
+
+// Why `user` may not decide the request, or None when they may: nobody approves their own
+// request, and a machine with a data owner only has its exports decided by that owner or an admin
+pub async fn refusal(pool: &DbPool, user: &AuthUser, request: &ExportRequest) -> sqlx::Result<Option<&'static str>> {
+    if user.username == request.requested_by {
+        return Ok(Some("Export requests must be decided by someone other than the requester"));
+    }
+    let data_owner: Option<String> = sqlx::query_scalar("SELECT data_owner FROM machines WHERE id = $1")
+        .bind(request.machine_id)
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+    match data_owner {
+        Some(owner) if user.role != Role::Admin && owner != user.username => {
+            Ok(Some("Only the machine's data owner or an admin can decide this request"))
+        },
+        _ => Ok(None),
+    }
+}
+
+// Counts a download of an export job's file against the request it was approved under
+pub async fn record_download(pool: &DbPool, job_id: &str, now: i64) -> sqlx::Result<()> {
+    sqlx::query("UPDATE export_requests SET downloads = downloads + 1, last_downloaded_at = $1 WHERE job_id = $2")
+        .bind(now)
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(username: &str, role: Role) -> AuthUser {
+        AuthUser { username: username.to_string(), role, session_id: None, impersonated_by: None }
+    }
+
+    #[tokio::test]
+    async fn requests_are_decided_by_someone_else_and_the_data_owner() {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key, data_owner) VALUES (1, 'Line 1', 'L1', 'key1', 'dora'), \
+             (2, 'Line 2', 'L2', 'key2', NULL)",
+            "INSERT INTO export_requests (machine_id, range_from, range_to, reason, requested_by, requested_at, job_id) \
+             VALUES (1, 0, 100, 'Audit', 'bob', 0, 'job-1'), (2, 0, 100, 'Audit', 'bob', 0, NULL)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        let owned = fetch(&pool, 1).await.unwrap().unwrap();
+        let unowned = fetch(&pool, 2).await.unwrap().unwrap();
+        let may_decide =
+            async |user: &AuthUser, request: &ExportRequest| refusal(&pool, user, request).await.unwrap().is_none();
+
+        assert!(!may_decide(&user("bob", Role::Admin), &unowned).await);
+        assert!(may_decide(&user("alice", Role::Manager), &unowned).await);
+        assert!(!may_decide(&user("alice", Role::Manager), &owned).await);
+        assert!(may_decide(&user("dora", Role::Manager), &owned).await);
+        assert!(may_decide(&user("root", Role::Admin), &owned).await);
+
+        record_download(&pool, "job-1", 500).await.unwrap();
+        record_download(&pool, "job-1", 600).await.unwrap();
+        let owned = fetch(&pool, 1).await.unwrap().unwrap();
+        assert_eq!((owned.downloads, owned.last_downloaded_at), (2, Some(600)));
+        assert!(fetch(&pool, 3).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::{BTreeSet, HashMap};
+
+// Alarm condition language: metric names and numbers combined with + - * /, compared with
+// < <= > >= == !=, and joined with && || ! and parentheses, e.g. `speed > 0 && temperature > 80`
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Number(f64),
+    Metric(String),
+    Neg(Box<Expr>),
+    Arith(char, Box<Expr>, Box<Expr>),
+    Compare(&'static str, Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(&'static str),
+}
+
+// Limits that keep hostile expressions from exhausting the stack of the thread parsing or
+// evaluating them; real conditions stay far below either
+const MAX_LENGTH: usize = 1000;
+const MAX_DEPTH: usize = 64;
+
+const OPERATORS: &[&str] = &["&&", "||", "<=", ">=", "==", "!=", "<", ">", "!", "+", "-", "*", "/", "(", ")"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c.is_ascii_digit() || c == '.' {
+            let end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+            let number = rest[..end].parse().map_err(|_| format!("Invalid number '{}'", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!("Unexpected character '{}'", c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if self.peek_op() == Some(op) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Runs `parse` one nesting level deeper
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err("Expression nested too deeply".to_string());
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.eat("||") {
+            let right = self.and()?;
+            left = Expr::Or(Box::new(condition(left)?), Box::new(condition(right)?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.not()?;
+        while self.eat("&&") {
+            let right = self.not()?;
+            left = Expr::And(Box::new(condition(left)?), Box::new(condition(right)?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(condition(self.nested(Self::not)?)?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        match self.peek_op() {
+            Some(op @ ("<" | "<=" | ">" | ">=" | "==" | "!=")) => {
+                self.position += 1;
+                let right = self.sum()?;
+                Ok(Expr::Compare(op, Box::new(value(left)?), Box::new(value(right)?)))
+            },
+            _ => Ok(left),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.product()?;
+        while let Some(op @ ("+" | "-")) = self.peek_op() {
+            self.position += 1;
+            let right = self.product()?;
+            left = Expr::Arith(op.chars().next().unwrap_or('+'), Box::new(value(left)?), Box::new(value(right)?));
+        }
+        Ok(left)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(op @ ("*" | "/")) = self.peek_op() {
+            self.position += 1;
+            let right = self.unary()?;
+            left = Expr::Arith(op.chars().next().unwrap_or('*'), Box::new(value(left)?), Box::new(value(right)?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(value(self.nested(Self::unary)?)?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::Ident(name)) => Ok(Expr::Metric(name)),
+            Some(Token::Op("(")) => {
+                let inner = self.nested(Self::or)?;
+                if !self.eat(")") {
+                    return Err("Missing closing parenthesis".to_string());
+                }
+                Ok(inner)
+            },
+            Some(Token::Op(op)) => Err(format!("Unexpected '{}'", op)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn condition(expr: Expr) -> Result<Expr, String> {
+    if expr.is_condition() {
+        Ok(expr)
+    } else {
+        Err("Expected a comparison where a condition is required".to_string())
+    }
+}
+
+fn value(expr: Expr) -> Result<Expr, String> {
+    if expr.is_condition() {
+        Err("Expected a number or metric where a value is required".to_string())
+    } else {
+        Ok(expr)
+    }
+}
+
+impl Expr {
+    fn parse_any(source: &str) -> Result<Expr, String> {
+        if source.len() > MAX_LENGTH {
+            return Err(format!("Expression is longer than {} characters", MAX_LENGTH));
+        }
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(format!("Unexpected {:?} after end of expression", token));
+        }
+        Ok(expr)
+    }
+
+    // Parses a condition; the whole expression must evaluate to true or false
+    pub fn parse(source: &str) -> Result<Expr, String> {
+        condition(Self::parse_any(source)?)
+            .map_err(|_| "Expression must be a condition, e.g. `temperature > 80`".to_string())
+    }
+
+    // Parses a numeric expression such as `speed * 60`
+    pub fn parse_value(source: &str) -> Result<Expr, String> {
+        value(Self::parse_any(source)?).map_err(|_| "Expression must be a value, e.g. `speed * 60`".to_string())
+    }
+
+    fn is_condition(&self) -> bool {
+        matches!(self, Expr::Compare(..) | Expr::Not(_) | Expr::And(..) | Expr::Or(..))
+    }
+
+    // Metric names the expression reads
+    pub fn metrics(&self) -> BTreeSet<&str> {
+        let mut metrics = BTreeSet::new();
+        self.collect_metrics(&mut metrics);
+        metrics
+    }
+
+    fn collect_metrics<'a>(&'a self, metrics: &mut BTreeSet<&'a str>) {
+        match self {
+            Expr::Number(_) => {},
+            Expr::Metric(name) => {
+                metrics.insert(name);
+            },
+            Expr::Neg(inner) | Expr::Not(inner) => inner.collect_metrics(metrics),
+            Expr::Arith(_, left, right)
+            | Expr::Compare(_, left, right)
+            | Expr::And(left, right)
+            | Expr::Or(left, right) => {
+                left.collect_metrics(metrics);
+                right.collect_metrics(metrics);
+            },
+        }
+    }
+
+    // None when a metric the condition depends on has no value yet
+    pub fn evaluate(&self, values: &HashMap<String, f64>) -> Option<bool> {
+        match self {
+            Expr::Compare(op, left, right) => {
+                let (left, right) = (left.value(values)?, right.value(values)?);
+                Some(match *op {
+                    "<" => left < right,
+                    "<=" => left <= right,
+                    ">" => left > right,
+                    ">=" => left >= right,
+                    "==" => left == right,
+                    _ => left != right,
+                })
+            },
+            Expr::Not(inner) => inner.evaluate(values).map(|result| !result),
+            Expr::And(left, right) => Some(left.evaluate(values)? && right.evaluate(values)?),
+            Expr::Or(left, right) => Some(left.evaluate(values)? || right.evaluate(values)?),
+            _ => None,
+        }
+    }
+
+    // None when a metric the value depends on has no value yet
+    pub fn value(&self, values: &HashMap<String, f64>) -> Option<f64> {
+        match self {
+            Expr::Number(number) => Some(*number),
+            Expr::Metric(name) => values.get(name).copied(),
+            Expr::Neg(inner) => inner.value(values).map(|value| -value),
+            Expr::Arith(op, left, right) => {
+                let (left, right) = (left.value(values)?, right.value(values)?);
+                Some(match op {
+                    '+' => left + right,
+                    '-' => left - right,
+                    '*' => left * right,
+                    _ => left / right,
+                })
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        pairs.iter().map(|(name, value)| (name.to_string(), *value)).collect()
+    }
+
+    #[test]
+    fn binds_operators_by_precedence() {
+        let none = values(&[]);
+        assert_eq!(Expr::parse_value("1 + 2 * 3 - 4 / 2").unwrap().value(&none), Some(5.0));
+        assert_eq!(Expr::parse_value("(1 + 2) * -3").unwrap().value(&none), Some(-9.0));
+        // && binds tighter than ||, and ! only negates the comparison after it
+        let condition = Expr::parse("speed > 5 || speed > 0 && temperature > 80").unwrap();
+        assert_eq!(condition.evaluate(&values(&[("speed", 10.0), ("temperature", 20.0)])), Some(true));
+        assert_eq!(condition.evaluate(&values(&[("speed", 1.0), ("temperature", 20.0)])), Some(false));
+        let condition = Expr::parse("!speed > 0 && temperature > 80").unwrap();
+        assert_eq!(condition.evaluate(&values(&[("speed", 0.0), ("temperature", 90.0)])), Some(true));
+        assert_eq!(condition.metrics().into_iter().collect::<Vec<_>>(), ["speed", "temperature"]);
+    }
+
+    #[test]
+    fn rejects_values_where_conditions_belong_and_the_reverse() {
+        assert!(Expr::parse("speed * 60").is_err());
+        assert!(Expr::parse_value("speed > 0").is_err());
+        assert!(Expr::parse("speed && temperature > 80").is_err());
+        assert!(Expr::parse("(speed > 0) + 1 > 2").is_err());
+        assert!(Expr::parse("-(speed > 0) < 1").is_err());
+        assert!(Expr::parse("speed > 0 speed").is_err());
+        assert!(Expr::parse("(speed > 0").is_err());
+        assert!(Expr::parse("speed > 0 # 1").is_err());
+    }
+
+    #[test]
+    fn evaluates_to_none_while_a_metric_is_missing() {
+        let condition = Expr::parse("pressure > 2 || speed > 0").unwrap();
+        assert_eq!(condition.evaluate(&values(&[("speed", 10.0)])), None);
+        assert_eq!(Expr::parse_value("pressure * 2").unwrap().value(&values(&[("speed", 1.0)])), None);
+    }
+
+    #[test]
+    fn refuses_deeply_nested_and_overlong_expressions() {
+        let nested = |depth: usize| format!("{}speed > 0{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Expr::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(Expr::parse(&nested(MAX_DEPTH + 1)).unwrap_err(), "Expression nested too deeply");
+        assert_eq!(Expr::parse(&format!("{}speed > 0", "!".repeat(100))).unwrap_err(), "Expression nested too deeply");
+        assert_eq!(Expr::parse_value(&format!("{}speed", "-".repeat(100))).unwrap_err(), "Expression nested too deeply");
+        // Far too long to parse, and refused before tokenizing
+        assert!(Expr::parse(&"(".repeat(200_000)).unwrap_err().starts_with("Expression is longer than"));
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+
+use crate::database::DbPool;
+
+// Experimental subsystems that can be switched on plant by plant
+pub const FEATURES: &[&str] = &["mqtt", "automation_rules", "graphql"];
+
+pub fn parse_features(value: &str) -> anyhow::Result<Vec<String>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            if FEATURES.contains(&name) {
+                Ok(name.to_string())
+            } else {
+                Err(anyhow::anyhow!(
+                    "Unknown feature '{}' in SCADA_FEATURES, expected one of: {}",
+                    name,
+                    FEATURES.join(", ")
+                ))
+            }
+        })
+        .collect()
+}
+
+// Adds a row for every known feature, enabled if configured. Flags already in the
+// database keep the value an admin last set.
+pub async fn seed(pool: &DbPool, enabled: &[String]) -> sqlx::Result<()> {
+    for name in FEATURES {
+        sqlx::query("INSERT INTO feature_flags (name, enabled) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(name)
+            .bind(enabled.iter().any(|e| e == name))
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+pub async fn load(pool: &DbPool) -> sqlx::Result<BTreeMap<String, bool>> {
+    let rows = sqlx::query_as::<_, (String, bool)>("SELECT name, enabled FROM feature_flags")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_features_only() {
+        assert_eq!(parse_features(" mqtt, graphql ,").unwrap(), ["mqtt", "graphql"]);
+        assert!(parse_features("").unwrap().is_empty());
+        let error = parse_features("mqtt,telepathy").unwrap_err().to_string();
+        assert!(error.contains("Unknown feature 'telepathy'"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn seeding_keeps_flags_an_admin_has_set() {
+        let pool = crate::database::test_database().await;
+        seed(&pool, &["mqtt".to_string()]).await.unwrap();
+        sqlx::query("UPDATE feature_flags SET enabled = TRUE WHERE name = 'graphql'").execute(&pool).await.unwrap();
+        seed(&pool, &[]).await.unwrap();
+        let flags = load(&pool).await.unwrap();
+        assert_eq!(flags.len(), FEATURES.len());
+        assert_eq!((flags["mqtt"], flags["graphql"], flags["automation_rules"]), (true, true, false));
+    }
+}
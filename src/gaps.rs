@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use crate::{database::DbPool, models::HistoryGap};
+
+// Gaps shorter than this are normal jitter between reports
+pub const DEFAULT_MIN_GAP: i64 = 10 * 60;
+
+// Reports cover the last week unless a range is given
+pub const DEFAULT_RANGE: i64 = 7 * 24 * 60 * 60;
+
+// Parses a gap length such as "90", "30s", "10m", "2h" or "1d" into seconds
+pub fn parse_duration(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => value.split_at(split),
+        None => (value, "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    number.parse::<i64>().ok()?.checked_mul(multiplier).filter(|seconds| *seconds > 0)
+}
+
+// The shortest gap worth reporting under update dedup: a steady machine stores one row a
+// report interval after each dedup window, so anything up to twice the window is normal
+pub fn effective_min_gap(min_gap: i64, dedup_window: Duration) -> i64 {
+    min_gap.max(2 * dedup_window.as_secs() as i64)
+}
+
+// Intervals of at least `min_gap` seconds within `[from, to]` in which a machine stored no
+// speed history. The stretches before the first and after the last sample count as gaps
+// too, so a machine that recorded nothing at all is one gap spanning the whole range.
+pub async fn find_gaps(pool: &DbPool, machine_id: i64, from: i64, to: i64, min_gap: i64) -> sqlx::Result<Vec<HistoryGap>> {
+    let interior: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT gap_start, gap_end FROM ( \
+         SELECT LAG(timestamp) OVER (ORDER BY timestamp, id) AS gap_start, timestamp AS gap_end \
+         FROM speed_history WHERE machine_id = $1 AND timestamp >= $2 AND timestamp <= $3) samples \
+         WHERE gap_start IS NOT NULL AND gap_end - gap_start >= $4 ORDER BY gap_start"
+    )
+    .bind(machine_id)
+    .bind(from)
+    .bind(to)
+    .bind(min_gap)
+    .fetch_all(pool)
+    .await?;
+
+    let (first, last): (Option<i64>, Option<i64>) = sqlx::query_as(
+        "SELECT MIN(timestamp), MAX(timestamp) FROM speed_history WHERE machine_id = $1 AND timestamp >= $2 AND timestamp <= $3"
+    )
+    .bind(machine_id)
+    .bind(from)
+    .bind(to)
+    .fetch_one(pool)
+    .await?;
+
+    let mut bounds = Vec::with_capacity(interior.len() + 2);
+    match (first, last) {
+        (Some(first), Some(last)) => {
+            bounds.push((from, first));
+            bounds.extend(interior);
+            bounds.push((last, to));
+        },
+        _ => bounds.push((from, to)),
+    }
+
+    Ok(bounds
+        .into_iter()
+        .filter(|(start, end)| end - start >= min_gap)
+        .map(|(start, end)| HistoryGap { start, end, duration: end - start })
+        .collect())
+}
+
+// Share of `[from, to]` not covered by gaps
+pub fn coverage(from: i64, to: i64, gaps: &[HistoryGap]) -> f64 {
+    if to <= from {
+        return 1.0;
+    }
+    let missing: i64 = gaps.iter().map(|gap| gap.duration).sum();
+    1.0 - missing as f64 / (to - from) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_gap_lengths() {
+        assert_eq!(parse_duration("90"), Some(90));
+        assert_eq!(parse_duration("30s"), Some(30));
+        assert_eq!(parse_duration(" 10m "), Some(600));
+        assert_eq!(parse_duration("2h"), Some(7200));
+        assert_eq!(parse_duration("1d"), Some(86400));
+        for invalid in ["", "0", "5w", "m", "-5m", "99999999999999999d"] {
+            assert_eq!(parse_duration(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn gaps_are_never_shorter_than_deduplicated_history() {
+        assert_eq!(effective_min_gap(60, Duration::from_secs(300)), 600);
+        assert_eq!(effective_min_gap(3600, Duration::from_secs(300)), 3600);
+        assert_eq!(effective_min_gap(60, Duration::ZERO), 60);
+    }
+
+    #[tokio::test]
+    async fn finds_gaps_at_the_edges_and_between_samples() {
+        let pool = crate::database::test_database().await;
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key1'), (2, 'Line 2', 'L2', 'key2')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for timestamp in [1300, 1360, 2500, 2560] {
+            sqlx::query("INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES (1, 10.0, 'running', $1)")
+                .bind(timestamp)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let gaps: Vec<(i64, i64)> = find_gaps(&pool, 1, 1000, 4000, 600)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|gap| (gap.start, gap.end))
+            .collect();
+        // The 300s before the first sample is short enough to be jitter
+        assert_eq!(gaps, [(1360, 2500), (2560, 4000)]);
+
+        let silent = find_gaps(&pool, 2, 1000, 4000, 600).await.unwrap();
+        assert_eq!(silent.iter().map(|gap| (gap.start, gap.end)).collect::<Vec<_>>(), [(1000, 4000)]);
+        assert_eq!(coverage(1000, 4000, &silent), 0.0);
+        assert_eq!(coverage(1000, 4000, &[]), 1.0);
+    }
+}
@@ -1,97 +1,460 @@
 use axum::{
-    extract::{Path, State, Query},
-    http::{StatusCode, HeaderMap},
-    response::Json,
+    body::Body,
+    extract::{ConnectInfo, Path, State, Query},
+    http::{header, StatusCode, HeaderMap},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
 };
-use serde::Deserialize;
-use sqlx::Row;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, QueryBuilder, Row};
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{io::AsyncReadExt, sync::mpsc};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::Instrument;
 
 use crate::{
-    auth::{self, AuthResult},
-    database::{DbPool, current_timestamp},
+    access,
+    alarm_work_orders,
+    alarms,
+    archive,
+    auth::{self, extract_token, roles, scopes, AuthUser, MachineKey, RequireRole, Role, SessionClient},
+    backup,
+    batches,
+    benchmark::{self, BENCHMARK_METRICS, BENCHMARK_PERIODS},
+    certifications::{self, CertificationPolicy},
+    changeovers,
+    chaos::Chaos,
+    commands,
+    compliance,
+    config::Config,
+    database::{self, Db, DbPool, DbRow, current_timestamp},
+    display_tokens,
+    distribution,
+    export::{self, ExportFormat},
+    export_requests,
+    expr::Expr,
+    features,
+    gaps,
+    history_shift,
+    inactive_users,
+    login_guard,
+    mailer::Mailer,
     models::*,
+    network,
+    notifications,
+    password_policy,
+    permits,
+    jobs::Jobs,
+    kiosk,
+    kpis,
+    lockout,
+    machine_data,
+    precision::Precision,
+    predictions,
+    products,
+    read_only::ReadOnlyMode,
+    retention::{self, HistoryRetention},
+    rollups::{RESOLUTION_NAMES, Resolution},
+    service_accounts,
+    signing,
+    site,
+    state::{AppState, MachineChanges},
+    timerange,
+    usage::UsageRecorder,
+    units::{self, UnitPolicy},
 };
 
-// Helper function to extract token from headers
-fn extract_token(headers: &HeaderMap) -> Option<String> {
+// Helper function to format a Unix timestamp as an HTTP-date
+fn http_date(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+// Helper function to evaluate If-None-Match / If-Modified-Since against a resource version
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: i64) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since (RFC 9110 13.2.2)
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        let etag = etag.trim_start_matches("W/");
+        return if_none_match
+            .split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+
     headers
-        .get("authorization")
+        .get(header::IF_MODIFIED_SINCE)
         .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.strip_prefix("Bearer "))
-        .map(|s| s.to_string())
+        .and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok())
+        .is_some_and(|since| last_modified <= since.timestamp())
 }
 
-// Helper function for admin-only routes
-async fn require_admin(headers: &HeaderMap, pool: &DbPool) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
-    let token = extract_token(headers)
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Missing token".to_string() })))?;
-    
-    match auth::validate_token(&token, pool).await {
-        Some(AuthResult::Admin) => Ok(()),
-        _ => Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Admin access required".to_string() }))),
+// Helper function to build an entity tag from a hash of the response body, for resources whose
+// version cannot be read off a single counter
+fn content_etag(prefix: &str, body: &impl Serialize) -> String {
+    let digest = Sha256::digest(serde_json::to_vec(body).unwrap_or_default());
+    format!("W/\"{}-{}\"", prefix, &hex::encode(digest)[..16])
+}
+
+// Helper function to build the validator headers sent with conditional GET responses
+fn cache_validators(etag: &str, last_modified: i64) -> [(header::HeaderName, String); 3] {
+    [
+        (header::ETAG, etag.to_string()),
+        (header::LAST_MODIFIED, http_date(last_modified)),
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+    ]
+}
+
+// Locale requested via `?locale=` or, failing that, the first Accept-Language entry and
+// then the site's default locale
+async fn requested_locale(pool: &DbPool, headers: &HeaderMap, locale: Option<&str>) -> Option<String> {
+    let locale = match locale {
+        Some(locale) => Some(locale),
+        None => headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|v| v.split(';').next()),
+    };
+    let locale = locale.map(|locale| locale.trim().to_lowercase()).filter(|locale| !locale.is_empty() && locale != "*");
+    match locale {
+        Some(locale) => Some(locale),
+        None => site::default_locale(pool).await.ok().flatten(),
+    }
+}
+
+// Fills in each machine's display name for the locale, falling back from "de-at" to "de"
+// and finally to the machine's own name
+async fn apply_display_names(pool: &DbPool, machines: &mut [Machine], locale: &str) -> Result<(), sqlx::Error> {
+    let language = locale.split('-').next().unwrap_or(locale);
+    let overrides: Vec<(i64, String, String)> = sqlx::query_as(
+        "SELECT machine_id, locale, display_name FROM machine_display_names WHERE locale IN ($1, $2)"
+    )
+    .bind(locale)
+    .bind(language)
+    .fetch_all(pool)
+    .await?;
+
+    for machine in machines.iter_mut() {
+        let mut names = overrides.iter().filter(|(id, _, _)| *id == machine.id);
+        let exact = names.clone().find(|(_, l, _)| l == locale);
+        machine.display_name = Some(
+            exact
+                .or_else(|| names.next())
+                .map(|(_, _, name)| name.clone())
+                .unwrap_or_else(|| machine.name.clone()),
+        );
     }
+    Ok(())
+}
+
+// Loads the metric rounding policy applied to responses and exports
+async fn load_precision(pool: &DbPool) -> Result<Precision, (StatusCode, Json<ErrorResponse>)> {
+    Precision::load(pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() })))
+}
+
+// Loads the plant name that reports are branded with
+async fn load_plant_name(pool: &DbPool) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    site::plant_name(pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() })))
+}
+
+fn round_machines(precision: &Precision, machines: &mut [Machine]) {
+    for machine in machines {
+        machine.current_speed = precision.round("speed", machine.current_speed);
+    }
+}
+
+// Starts a session for a user and returns its access and refresh tokens
+async fn new_session(
+    pool: &DbPool,
+    username: String,
+    role: String,
+    client: &SessionClient,
+) -> Result<LoginResponse, (StatusCode, Json<ErrorResponse>)> {
+    let session_error = |e: &dyn std::fmt::Display| {
+        tracing::error!("Failed to create session for {}: {}", username, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to create session".to_string(),
+        }))
+    };
+    let session_id = auth::start_session(pool, &username, client).await.map_err(|e| session_error(&e))?;
+    let (token, expires_at) = auth::issue_session_token(&username, &role, Some(&session_id)).map_err(|e| session_error(&e))?;
+    let (refresh_token, refresh_expires_at) = auth::issue_refresh_token(pool, &username, &session_id)
+        .await
+        .map_err(|e| session_error(&e))?;
+
+    Ok(LoginResponse {
+        token,
+        role,
+        username,
+        expires_at,
+        refresh_token,
+        refresh_expires_at,
+    })
+}
+
+// Stores a new password for a user and restarts its age
+async fn set_password(pool: &DbPool, username: &str, password: &str) -> Result<(), Response> {
+    let password_hash = auth::hash_password(password).await.map_err(|e| {
+        tracing::error!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to set password".to_string(),
+        })).into_response()
+    })?;
+    sqlx::query("UPDATE users SET password = $1, password_changed_at = $2 WHERE username = $3")
+        .bind(&password_hash)
+        .bind(current_timestamp())
+        .bind(username)
+        .execute(pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        })).into_response())?;
+    Ok(())
+}
+
+// GET /api/password-policy
+pub async fn get_password_policy(State(config): State<Arc<Config>>) -> Json<PasswordPolicyResponse> {
+    Json(config.password_policy.describe())
 }
 
 // POST /api/login
 pub async fn login(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
     Json(payload): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, (StatusCode, Json<ErrorResponse>)> {
-    println!("[LOG] Login request received for user: {}", payload.username);
+) -> Result<Json<LoginResponse>, Response> {
+    tracing::info!("Login request received for user: {}", payload.username);
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })).into_response();
+    let locked = |locked_until: i64| {
+        tracing::warn!("Login refused for locked account: {}", payload.username);
+        let retry_after = (locked_until - current_timestamp()).max(1).to_string();
+        (StatusCode::LOCKED, [(header::RETRY_AFTER, retry_after)], Json(ErrorResponse {
+            error: "Account locked after too many failed logins".to_string(),
+        })).into_response()
+    };
+    let client = SessionClient::new(&headers, addr, &config);
+    // A history that cannot be written is logged rather than keeping everyone out
+    let record = async |failure_reason: Option<&str>| {
+        let recorded = login_guard::record_attempt(&pool, &payload.username, failure_reason, &client, config.login_history_retention).await;
+        if let Err(e) = recorded {
+            tracing::error!("Failed to record login attempt for {}: {}", payload.username, e);
+        }
+    };
+
+    // A locked account is refused before the password is checked, so guessing gets nowhere
+    if let Some(locked_until) = login_guard::locked_until(&pool, &payload.username).await.map_err(database_error)? {
+        record(Some("locked")).await;
+        return Err(locked(locked_until));
+    }
+
     match auth::authenticate_user(&payload.username, &payload.password, &pool).await {
+        Some(user) if !user.is_active => {
+            tracing::warn!("Login refused for disabled account: {}", user.username);
+            record(Some("disabled")).await;
+            Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+                error: "Account disabled, ask an admin to reactivate it".to_string(),
+            })).into_response())
+        },
         Some(user) => {
-            println!("[LOG] Login successful for user: {}", user.username);
-            Ok(Json(LoginResponse {
-                token: user.token,
-                role: user.role,
-                username: user.username,
-            }))
+            login_guard::clear(&pool, &user.username).await.map_err(database_error)?;
+            let password_changed_at: Option<i64> = sqlx::query_scalar("SELECT password_changed_at FROM users WHERE id = $1")
+                .bind(user.id)
+                .fetch_one(&pool)
+                .await
+                .map_err(database_error)?;
+            match &payload.new_password {
+                // The password is replaced before the session is opened; sessions opened
+                // with the old one end, as after a password change
+                Some(new_password) => {
+                    if new_password == &payload.password {
+                        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                            error: "New password must differ from the current one".to_string(),
+                        })).into_response());
+                    }
+                    config.password_policy.check(new_password, &user.username).map_err(password_policy::rejection)?;
+                    set_password(&pool, &user.username, new_password).await?;
+                    auth::revoke_user_tokens(&pool, &user.username).await.map_err(database_error)?;
+                    tracing::info!("Expired password replaced at login for user: {}", user.username);
+                },
+                None if config.password_policy.is_expired(password_changed_at, current_timestamp()) => {
+                    tracing::warn!("Login refused, password expired for user: {}", user.username);
+                    record(Some("password_expired")).await;
+                    return Err(password_policy::expired_rejection());
+                },
+                None => {},
+            }
+            let session = new_session(&pool, user.username, user.role, &client).await.map_err(IntoResponse::into_response)?;
+            record(None).await;
+            tracing::info!("Login successful for user: {}", session.username);
+            Ok(Json(session))
         },
         None => {
-            println!("[LOG] Login failed for user: {}", payload.username);
+            tracing::warn!("Login failed for user: {}", payload.username);
+            record(Some("invalid_credentials")).await;
+            let lock = login_guard::record_failure(&pool, &payload.username, config.login_max_attempts, config.login_lockout)
+                .await
+                .map_err(database_error)?;
+            if let Some(locked_until) = lock {
+                return Err(locked(locked_until));
+            }
             Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse {
                 error: "Invalid credentials".to_string(),
+            })).into_response())
+        },
+    }
+}
+
+// POST /api/logout
+pub async fn logout(
+    headers: HeaderMap,
+    State(pool): State<DbPool>,
+    payload: Option<Json<LogoutRequest>>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let token = extract_token(&headers)
+        .ok_or_else(|| (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Missing token".to_string() })))?;
+    let refresh_token = payload.and_then(|Json(payload)| payload.refresh_token);
+
+    match auth::logout(&pool, &token, refresh_token.as_deref()).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Invalid token".to_string() }))),
+        Err(e) => {
+            tracing::error!("Failed to persist logout: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
             })))
         },
     }
 }
 
+// POST /api/token/refresh
+pub async fn refresh_token(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<RefreshTokenRequest>,
+) -> Result<Json<LoginResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let client = SessionClient::new(&headers, addr, &config);
+    let (username, session_id, refresh_token, refresh_expires_at) = match auth::rotate_refresh_token(&pool, &payload.refresh_token, &client)
+        .await
+        .map_err(database_error)?
+    {
+        auth::RefreshOutcome::Rotated(username, session_id, token, expires_at) => (username, session_id, token, expires_at),
+        auth::RefreshOutcome::Rejected => {
+            return Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse {
+                error: "Invalid or expired refresh token".to_string(),
+            })));
+        },
+    };
+
+    // The role is read again so changes made by an admin apply from the next refresh, and a
+    // disabled account gets no new token
+    let role: Option<String> = sqlx::query_scalar("SELECT role FROM users WHERE username = $1 AND is_active = TRUE")
+        .bind(&username)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?;
+    let Some(role) = role else {
+        return Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse {
+            error: "Invalid or expired refresh token".to_string(),
+        })));
+    };
+
+    let (token, expires_at) = auth::issue_session_token(&username, &role, Some(&session_id)).map_err(|e| {
+        tracing::error!("Failed to sign session token: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to create session".to_string(),
+        }))
+    })?;
+    Ok(Json(LoginResponse {
+        token,
+        role,
+        username,
+        expires_at,
+        refresh_token,
+        refresh_expires_at,
+    }))
+}
+
+// Checks the configuration an admin sets for a machine's edge agent
+fn validate_machine_config(
+    target_speed: Option<f64>,
+    report_interval: Option<i64>,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if target_speed.is_some_and(|speed| !speed.is_finite() || speed < 0.0) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "target_speed must be a non-negative number".to_string(),
+        })));
+    }
+    if report_interval.is_some_and(|interval| interval <= 0) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "report_interval must be a positive number of seconds".to_string(),
+        })));
+    }
+    Ok(())
+}
+
 // POST /api/machines
 pub async fn create_machine(
-    headers: HeaderMap,
+    _admin: RequireRole<roles::Admin>,
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    State(changes): State<MachineChanges>,
     Json(payload): Json<CreateMachineRequest>,
 ) -> Result<(StatusCode, Json<MachineResponse>), (StatusCode, Json<ErrorResponse>)> {
-    println!("[LOG] Create machine request received: {}", payload.name);
-    require_admin(&headers, &pool).await?;
+    tracing::info!("Create machine request received: {}", payload.name);
+    validate_machine_config(payload.target_speed, payload.report_interval)?;
     
     let api_key = auth::generate_machine_api_key();
     
-    match sqlx::query(
-        "INSERT INTO machines (name, code, api_key, location, machine_type) VALUES (?, ?, ?, ?, ?)"
+    match sqlx::query_scalar::<_, i64>(
+        "INSERT INTO machines (name, code, api_key, location, machine_type, target_speed, report_interval, dedup_updates, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id"
     )
     .bind(&payload.name)
     .bind(&payload.code)
-    .bind(&api_key)
+    .bind(auth::hash_token(&api_key))
     .bind(&payload.location)
     .bind(&payload.machine_type)
-    .execute(&pool)
+    .bind(payload.target_speed)
+    .bind(payload.report_interval)
+    .bind(payload.dedup_updates.unwrap_or(true))
+    .bind(current_timestamp())
+    .fetch_one(&pool)
     .await
     {
-        Ok(result) => {
-            let machine_id = result.last_insert_rowid();
-            println!("[LOG] Machine created successfully: {}", payload.name);
+        Ok(machine_id) => {
+            changes.notify(current_timestamp());
+            tracing::info!("Machine created successfully: {}", payload.name);
             Ok((StatusCode::CREATED, Json(MachineResponse {
                 id: machine_id,
                 name: payload.name,
                 code: payload.code,
-                api_key,
+                signing_secret: signing::issue_signing_secret(&config, &api_key),
+                api_key: Some(api_key),
                 location: payload.location,
                 machine_type: payload.machine_type,
+                allowed_cidrs: Vec::new(),
             })))
         },
         Err(_) => {
-            println!("[LOG] Failed to create machine: {}", payload.name);
+            tracing::error!("Failed to create machine: {}", payload.name);
             Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
                 error: "Machine code already exists".to_string(),
             })))
@@ -99,443 +462,8436 @@ pub async fn create_machine(
     }
 }
 
+#[derive(Deserialize)]
+pub struct LocaleQuery {
+    locale: Option<String>,
+}
+
 // GET /api/machines
-pub async fn list_machines(
-    headers: HeaderMap,
-    State(pool): State<DbPool>,
-) -> Result<Json<MachineListResponse>, (StatusCode, Json<ErrorResponse>)> {
-    println!("[LOG] List machines request received");
-    let token = extract_token(&headers)
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Missing token".to_string() })))?;
-    
-    // Verify token is valid (admin or user)
-    match auth::validate_token(&token, &pool).await {
-        Some(AuthResult::Admin) | Some(AuthResult::User(_)) => {},
-        _ => return Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Invalid token".to_string() }))),
-    }
-    
-    match sqlx::query_as::<_, Machine>("SELECT * FROM machines ORDER BY name").fetch_all(&pool).await {
-        Ok(machines) => {
-            println!("[LOG] Machines listed successfully");
-            Ok(Json(MachineListResponse { machines }))
-        },
-        Err(_) => {
-            println!("[LOG] Failed to list machines");
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
-                error: "Database error".to_string(),
-            })))
-        },
-    }
+#[derive(Deserialize)]
+pub struct MachineListQuery {
+    locale: Option<String>,
+    #[serde(default)]
+    include_archived: bool,
+    // "name" (default) or "health", worst health score first
+    sort: Option<String>,
 }
 
-// POST /api/machines/update
-pub async fn update_machine_speed(
+pub async fn list_machines(
     headers: HeaderMap,
+    user: AuthUser,
+    Query(params): Query<MachineListQuery>,
     State(pool): State<DbPool>,
-    Json(payload): Json<SpeedUpdateRequest>,
-) -> Result<Json<UpdateResponse>, (StatusCode, Json<ErrorResponse>)> {
-    println!("[LOG] Update machine speed request received");
-    let token = extract_token(&headers)
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Missing token".to_string() })))?;
-    
-    // Only machine API keys can update speed
-    let machine_id = match auth::validate_token(&token, &pool).await {
-        Some(AuthResult::Machine(id)) => id,
-        _ => return Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Invalid machine API key".to_string() }))),
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("List machines request received");
+    let order = match params.sort.as_deref() {
+        None | Some("name") => "name",
+        Some("health") => "health_score IS NULL, health_score, name",
+        Some(_) => {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "sort must be one of: name, health".to_string(),
+            })));
+        },
     };
-    
-    let timestamp = current_timestamp();
-    let message = payload.message.unwrap_or_else(|| "".to_string());
-    
-    // Update machine status
-    match sqlx::query(
-        "UPDATE machines SET current_speed = ?, status_message = ?, last_update = ?, is_online = 1 WHERE id = ?"
-    )
-    .bind(payload.speed)
-    .bind(&message)
-    .bind(timestamp)
-    .bind(machine_id)
-    .execute(&pool)
+    // Cheap version check first so unchanged polls never load the machine rows. Every write to
+    // a machine takes a new change sequence number, so the highest one among the visible
+    // machines moves on any change; the ids catch machines becoming visible or hidden.
+    let (machine_count, change_seq, machine_ids, last_modified): (i64, i64, String, i64) = match sqlx::query_as(&format!(
+        "SELECT COUNT(*), COALESCE(MAX(change_seq), 0), COALESCE({}, ''), \
+         {} \
+         FROM (SELECT * FROM machines WHERE {} AND ($4 OR archived_at IS NULL) ORDER BY id) visible",
+        database::string_list("id"),
+        database::greatest(&format!(
+            "COALESCE(MAX({}), 0), (SELECT COALESCE(MAX(granted_at), 0) FROM user_machine_access WHERE username = $1)",
+            database::greatest("last_update, updated_at")
+        )),
+        access::visible_machines(2)
+    ))
+    .bind(&user.username)
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .bind(params.include_archived)
+    .fetch_one(&pool)
     .await
     {
-        Ok(_) => {
-            // Insert into history
-            let _ = sqlx::query(
-                "INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES (?, ?, ?, ?)"
-            )
-            .bind(machine_id)
-            .bind(payload.speed)
-            .bind(&message)
-            .bind(timestamp)
-            .execute(&pool)
-            .await;
-            
-            println!("[LOG] Machine speed updated successfully for machine ID: {}", machine_id);
-            Ok(Json(UpdateResponse {
-                success: true,
-                timestamp,
-            }))
-        },
+        Ok(version) => version,
         Err(_) => {
-            println!("[LOG] Failed to update machine speed for machine ID: {}", machine_id);
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
-                error: "Failed to update machine".to_string(),
-            })))
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })));
         },
+    };
+    let locale = requested_locale(&pool, &headers, params.locale.as_deref()).await;
+    let etag = format!(
+        "W/\"machines-{}-{}-{}-{}{}{}\"",
+        machine_count,
+        change_seq,
+        &hex::encode(Sha256::digest(machine_ids.as_bytes()))[..16],
+        locale.as_deref().unwrap_or(""),
+        if params.include_archived { "-archived" } else { "" },
+        if order == "name" { "" } else { "-health" },
+    );
+
+    if is_not_modified(&headers, &etag, last_modified) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_validators(&etag, last_modified)).into_response());
     }
-}
 
-// POST /api/machines/{id}/comments
-pub async fn add_comment(
-    headers: HeaderMap,
-    Path(machine_id): Path<i64>,
-    State(pool): State<DbPool>,
-    Json(payload): Json<AddCommentRequest>,
-) -> Result<(StatusCode, Json<MaintenanceComment>), (StatusCode, Json<ErrorResponse>)> {
-    println!("[LOG] Add comment request received for machine ID: {}", machine_id);
-    let token = extract_token(&headers)
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Missing token".to_string() })))?;
-    
-    let username = match auth::validate_token(&token, &pool).await {
-        Some(AuthResult::Admin) => "admin".to_string(),
-        Some(AuthResult::User(username)) => username,
-        _ => return Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Invalid token".to_string() }))),
-    };
-    
-    // Check if machine exists
-    if let Err(_) = sqlx::query("SELECT id FROM machines WHERE id = ?")
-        .bind(machine_id)
-        .fetch_one(&pool)
-        .await
+    let precision = load_precision(&pool).await?;
+    let mut machines = sqlx::query_as::<_, Machine>(&format!(
+        "SELECT * FROM machines WHERE {} AND ($3 OR archived_at IS NULL) ORDER BY {}",
+        access::visible_machines(1),
+        order
+    ))
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .bind(params.include_archived)
+    .fetch_all(&pool)
+    .await;
+    if let Ok(machines) = &mut machines {
+        round_machines(&precision, machines);
+    }
+    if let (Ok(machines), Some(locale)) = (&mut machines, &locale)
+        && apply_display_names(&pool, machines, locale).await.is_err()
     {
-        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
-            error: "Machine not found".to_string(),
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
         })));
     }
-    
-    let priority = payload.priority.unwrap_or_else(|| "normal".to_string());
-    let timestamp = current_timestamp();
-    
-    match sqlx::query(
-        "INSERT INTO maintenance_comments (machine_id, username, comment, priority, created_at) VALUES (?, ?, ?, ?, ?)"
-    )
-    .bind(machine_id)
-    .bind(&username)
-    .bind(&payload.comment)
-    .bind(&priority)
-    .bind(timestamp)
-    .execute(&pool)
-    .await
-    {
-        Ok(result) => {
-            let comment_id = result.last_insert_rowid();
-            println!("[LOG] Comment added successfully for machine ID: {}", machine_id);
-            Ok((StatusCode::CREATED, Json(MaintenanceComment {
-                id: comment_id,
-                machine_id,
-                comment: payload.comment,
-                priority,
-                username,
-                created_at: timestamp,
-            })))
+
+    match machines {
+        Ok(machines) => {
+            tracing::info!("Machines listed successfully");
+            Ok((cache_validators(&etag, last_modified), Json(MachineListResponse { machines })).into_response())
         },
         Err(_) => {
-            println!("[LOG] Failed to add comment for machine ID: {}", machine_id);
+            tracing::error!("Failed to list machines");
             Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
-                error: "Failed to add comment".to_string(),
+                error: "Database error".to_string(),
             })))
         },
     }
 }
 
-// GET /api/machines/{id}/comments
-pub async fn get_comments(
-    headers: HeaderMap,
-    Path(machine_id): Path<i64>,
+// GET /api/machines/me
+// Lets an edge agent look up its own configuration with nothing but its API key
+pub async fn get_own_machine(
+    MachineKey { machine_id, .. }: MachineKey<scopes::ConfigRead>,
     State(pool): State<DbPool>,
-) -> Result<Json<CommentListResponse>, (StatusCode, Json<ErrorResponse>)> {
-    println!("[LOG] Get comments request received for machine ID: {}", machine_id);
-    let token = extract_token(&headers)
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Missing token".to_string() })))?;
-    
-    // Verify token is valid (admin or user)
-    match auth::validate_token(&token, &pool).await {
-        Some(AuthResult::Admin) | Some(AuthResult::User(_)) => {},
-        _ => return Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Invalid token".to_string() }))),
+) -> Result<Json<MachineSelfResponse>, (StatusCode, Json<ErrorResponse>)> {
+    // Counted like poll_commands hands them out: unexpired and not blocked by a lockout
+    sqlx::query_as::<_, MachineSelfResponse>(&format!(
+        "SELECT id, name, code, location, machine_type, target_speed, report_interval, \
+         (SELECT COUNT(*) FROM machine_commands WHERE machine_id = machines.id AND status = 'pending' \
+         AND (expires_at IS NULL OR expires_at > $2) AND {}) AS pending_commands \
+         FROM machines WHERE id = $1",
+        lockout::NOT_BLOCKED
+    ))
+    .bind(machine_id)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    .map(Json)
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))
+}
+
+// Whether an update repeats the machine's last stored speed, message and metric values
+// within the dedup window. Updates with flagged readings are always stored.
+async fn is_repeated_update(
+    pool: &DbPool,
+    machine_id: i64,
+    speed: f64,
+    message: &str,
+    readings: &[(&str, f64, String, bool)],
+    now: i64,
+    window: Duration,
+) -> sqlx::Result<bool> {
+    if window.is_zero() || readings.iter().any(|(_, _, _, is_flagged)| *is_flagged) {
+        return Ok(false);
     }
-    
-    // Check if machine exists
-    if let Err(_) = sqlx::query("SELECT id FROM machines WHERE id = ?")
+
+    let enabled: bool = sqlx::query_scalar("SELECT COALESCE(dedup_updates, TRUE) FROM machines WHERE id = $1")
         .bind(machine_id)
-        .fetch_one(&pool)
-        .await
-    {
-        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
-            error: "Machine not found".to_string(),
-        })));
+        .fetch_one(pool)
+        .await?;
+    if !enabled {
+        return Ok(false);
     }
-    
-    match sqlx::query_as::<_, MaintenanceComment>(
-        "SELECT * FROM maintenance_comments WHERE machine_id = ? ORDER BY created_at DESC"
+
+    let last: Option<(f64, Option<String>)> = sqlx::query_as(
+        "SELECT speed, message FROM speed_history WHERE machine_id = $1 AND timestamp >= $2 ORDER BY timestamp DESC, id DESC LIMIT 1"
     )
     .bind(machine_id)
-    .fetch_all(&pool)
-    .await
-    {
-        Ok(comments) => {
-            println!("[LOG] Comments retrieved successfully for machine ID: {}", machine_id);
-            Ok(Json(CommentListResponse { comments }))
-        },
-        Err(_) => {
-            println!("[LOG] Failed to retrieve comments for machine ID: {}", machine_id);
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
-                error: "Database error".to_string(),
-            })))
-        },
+    .bind(now - window.as_secs() as i64)
+    .fetch_optional(pool)
+    .await?;
+    if !matches!(last, Some((last_speed, last_message)) if last_speed == speed && last_message.as_deref().unwrap_or("") == message) {
+        return Ok(false);
     }
-}
 
-// GET /api/machines/{id}/history
-#[derive(Deserialize)]
-pub struct HistoryQuery {
-    limit: Option<i64>,
+    for (metric, value, unit, _) in readings {
+        let current: Option<(f64, String)> = sqlx::query_as(
+            "SELECT value, unit FROM machine_metrics WHERE machine_id = $1 AND metric = $2"
+        )
+        .bind(machine_id)
+        .bind(metric)
+        .fetch_optional(pool)
+        .await?;
+        if current != Some((*value, unit.clone())) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
 }
 
-pub async fn get_history(
-    headers: HeaderMap,
-    Path(machine_id): Path<i64>,
-    Query(params): Query<HistoryQuery>,
+// POST /api/machines/update
+pub async fn update_machine_speed(
+    // Only machine API keys can update speed
+    MachineKey { machine_id, .. }: MachineKey<scopes::TelemetryWrite>,
     State(pool): State<DbPool>,
-) -> Result<Json<HistoryResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let token = extract_token(&headers)
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Missing token".to_string() })))?;
+    State(config): State<Arc<Config>>,
+    State(changes): State<MachineChanges>,
+    Json(payload): Json<SpeedUpdateRequest>,
+) -> Result<Json<UpdateResponse>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update machine speed request received");
     
-    // Verify token is valid (admin or user)
-    match auth::validate_token(&token, &pool).await {
-        Some(AuthResult::Admin) | Some(AuthResult::User(_)) => {},
-        _ => return Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Invalid token".to_string() }))),
+    // Check every metric's unit before writing anything so a rejected update leaves no trace
+    let mut readings = Vec::with_capacity(payload.metrics.len());
+    let mut flagged = Vec::new();
+    for reading in &payload.metrics {
+        if !is_valid_metric_name(&reading.name) {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: format!("Invalid metric name '{}'. Use lowercase letters, digits and underscores", reading.name),
+            })));
+        }
+        match units::normalize_reading(&pool, machine_id, &reading.name, reading.value, &reading.unit).await {
+            Ok(Ok((value, unit))) => readings.push((reading.name.as_str(), value, unit, false)),
+            Ok(Err(problem)) if config.unit_policy == UnitPolicy::Flag => {
+                readings.push((reading.name.as_str(), reading.value, reading.unit.clone(), true));
+                flagged.push(problem);
+            },
+            Ok(Err(problem)) => {
+                tracing::warn!("Rejected update for machine ID {}: {}", machine_id, problem);
+                return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse { error: problem })));
+            },
+            Err(_) => {
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                    error: "Database error".to_string(),
+                })));
+            },
+        }
     }
-    
-    // Check if machine exists
-    if let Err(_) = sqlx::query("SELECT id FROM machines WHERE id = ?")
-        .bind(machine_id)
-        .fetch_one(&pool)
+
+    let lot_number = payload.lot_number.as_deref().map(str::trim).filter(|lot| !lot.is_empty());
+    let product = payload.product.as_deref().map(str::trim).filter(|product| !product.is_empty());
+    if lot_number.into_iter().chain(product).any(|value| value.chars().count() > batches::MAX_LOT_LENGTH) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("lot_number and product must be at most {} characters", batches::MAX_LOT_LENGTH),
+        })));
+    }
+
+    let timestamp = current_timestamp();
+    let message = payload.message.unwrap_or_else(|| "".to_string());
+    // Positive drift means the device clock is ahead of the server
+    let clock_drift = payload.device_timestamp.map(|device_timestamp| device_timestamp - timestamp);
+
+    // A steady-state machine keeps reporting the same values; only refresh its heartbeat
+    let repeated = is_repeated_update(&pool, machine_id, payload.speed, &message, &readings, timestamp, config.dedup_window)
         .await
-    {
-        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
-            error: "Machine not found".to_string(),
+        .unwrap_or(false);
+
+    let update = TelemetryUpdate {
+        machine_id,
+        speed: payload.speed,
+        message: &message,
+        clock_drift,
+        readings: &readings,
+        lot_number,
+        product,
+        max_clock_drift: config.max_clock_drift,
+        repeated,
+        timestamp,
+    };
+    if let Err(e) = store_update(&pool, &update).await {
+        tracing::error!("Failed to update machine speed for machine ID {}: {}", machine_id, e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to update machine".to_string(),
         })));
     }
-    
-    let limit = params.limit.unwrap_or(100);
-    
-    match sqlx::query_as::<_, SpeedHistory>(
-        "SELECT speed, message, timestamp FROM speed_history WHERE machine_id = ? ORDER BY timestamp DESC LIMIT ?"
-    )
-    .bind(machine_id)
-    .bind(limit)
-    .fetch_all(&pool)
-    .await
-    {
-        Ok(history) => Ok(Json(HistoryResponse { history })),
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
-            error: "Database error".to_string(),
-        }))),
+
+    if let Err(e) = alarms::evaluate_machine(&pool, machine_id).await {
+        tracing::error!("Alarm evaluation failed for machine ID {}: {}", machine_id, e);
+    }
+    if let Err(e) = compliance::evaluate_machine(&pool, machine_id).await {
+        tracing::error!("Compliance evaluation failed for machine ID {}: {}", machine_id, e);
     }
+
+    changes.notify(timestamp);
+    if repeated {
+        tracing::debug!("Repeated update for machine ID {} not stored in history", machine_id);
+    } else {
+        tracing::info!("Machine speed updated successfully for machine ID: {}", machine_id);
+    }
+    Ok(Json(UpdateResponse {
+        success: true,
+        timestamp,
+        flagged,
+        deduplicated: repeated,
+    }))
 }
 
-// POST /api/users
-pub async fn create_user(
-    headers: HeaderMap,
-    State(pool): State<DbPool>,
-    Json(payload): Json<CreateUserRequest>,
-) -> Result<(StatusCode, Json<User>), (StatusCode, Json<ErrorResponse>)> {
-    println!("[LOG] Create user request received for user: {}", payload.username);
-    require_admin(&headers, &pool).await?;
-    
-    let token = auth::generate_user_token();
-    
-    match sqlx::query(
-        "INSERT INTO users (username, password, role, token) VALUES (?, ?, ?, ?)"
-    )
-    .bind(&payload.username)
-    .bind(&payload.password)
-    .bind(&payload.role)
-    .bind(&token)
-    .execute(&pool)
-    .await
-    {
-        Ok(result) => {
-            let user_id = result.last_insert_rowid();
-            println!("[LOG] User created successfully: {}", payload.username);
-            Ok((StatusCode::CREATED, Json(User {
-                id: user_id,
-                username: payload.username,
-                role: payload.role,
-                token,
-            })))
-        },
-        Err(_) => {
-            println!("[LOG] Failed to create user: {}", payload.username);
-            Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
-                error: "Username already exists".to_string(),
-            })))
-        },
+// A machine update whose readings passed validation
+struct TelemetryUpdate<'a> {
+    machine_id: i64,
+    speed: f64,
+    message: &'a str,
+    clock_drift: Option<i64>,
+    readings: &'a [(&'a str, f64, String, bool)],
+    lot_number: Option<&'a str>,
+    product: Option<&'a str>,
+    // Drift the clock drift rule trips at, 0 to add no rule
+    max_clock_drift: i64,
+    // A repeat of the stored values only refreshes the heartbeat and adds no history
+    repeated: bool,
+    timestamp: i64,
+}
+
+// Stores an update in one transaction: the lot it reports, the end of a changeover once the
+// machine runs, its clock drift rule, the machine's current state, and its speed history row
+// and metric readings. If any write fails nothing is stored.
+async fn store_update(pool: &DbPool, update: &TelemetryUpdate<'_>) -> sqlx::Result<()> {
+    let machine_id = update.machine_id;
+    let mut tx = pool.begin().await?;
+
+    // The reported lot is tracked even when the readings themselves are a repeat
+    if let Some(lot_number) = update.lot_number {
+        batches::start(&mut tx, machine_id, lot_number, update.product, None, update.timestamp).await?;
+    }
+    if update.speed > 0.0 {
+        changeovers::end_on_running(&mut tx, machine_id, update.timestamp).await?;
+    }
+    if update.clock_drift.is_some() && update.max_clock_drift > 0 {
+        alarms::ensure_clock_drift_rule(&mut tx, machine_id, update.max_clock_drift).await?;
+    }
+
+    let updated = if update.repeated {
+        sqlx::query("UPDATE machines SET last_update = $1, clock_drift = COALESCE($2, clock_drift), is_online = TRUE WHERE id = $3")
+            .bind(update.timestamp)
+            .bind(update.clock_drift)
+            .bind(machine_id)
+            .execute(&mut *tx)
+            .await?
+    } else {
+        sqlx::query(
+            "UPDATE machines SET current_speed = $1, status_message = $2, last_update = $3, \
+             clock_drift = COALESCE($4, clock_drift), is_online = TRUE WHERE id = $5"
+        )
+        .bind(update.speed)
+        .bind(update.message)
+        .bind(update.timestamp)
+        .bind(update.clock_drift)
+        .bind(machine_id)
+        .execute(&mut *tx)
+        .await?
+    };
+    if updated.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
     }
+    if update.repeated {
+        return tx.commit().await;
+    }
+
+    sqlx::query("INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES ($1, $2, $3, $4)")
+        .bind(machine_id)
+        .bind(update.speed)
+        .bind(update.message)
+        .bind(update.timestamp)
+        .execute(&mut *tx)
+        .await?;
+
+    for (metric, value, unit, is_flagged) in update.readings {
+        sqlx::query(
+            "INSERT INTO metric_readings (machine_id, metric, value, unit, flagged, timestamp) VALUES ($1, $2, $3, $4, $5, $6)"
+        )
+        .bind(machine_id)
+        .bind(metric)
+        .bind(value)
+        .bind(unit)
+        .bind(is_flagged)
+        .bind(update.timestamp)
+        .execute(&mut *tx)
+        .await?;
+
+        // Flagged readings are kept for review but never become the current value
+        if !is_flagged {
+            sqlx::query(
+                "INSERT INTO machine_metrics (machine_id, metric, unit, value, updated_at) VALUES ($1, $2, $3, $4, $5) \
+                 ON CONFLICT (machine_id, metric) DO UPDATE SET unit = excluded.unit, value = excluded.value, \
+                 updated_at = excluded.updated_at"
+            )
+            .bind(machine_id)
+            .bind(metric)
+            .bind(unit)
+            .bind(value)
+            .bind(update.timestamp)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    tx.commit().await
 }
 
-// PUT /api/users/{id}
-pub async fn update_user(
+// GET /api/machines/changes
+#[derive(Deserialize)]
+pub struct MachineChangesQuery {
+    cursor: Option<i64>,
+    // Superseded by cursor; kept for clients that resume from a timestamp
+    since: Option<i64>,
+    timeout: Option<u64>,
+    locale: Option<String>,
+}
+
+// Where a change feed resumes: after a change sequence number, or after a timestamp for
+// clients that have not moved to cursors
+#[derive(Clone, Copy)]
+enum ChangesAfter {
+    Cursor(i64),
+    Timestamp(i64),
+}
+
+// Machines the user may see written after the position, oldest change first. Archived machines
+// are only included when resuming from a cursor, so the client learns they were archived.
+async fn fetch_changed_machines(pool: &DbPool, user: &AuthUser, after: ChangesAfter) -> Result<Vec<(i64, Machine)>, sqlx::Error> {
+    let (condition, position, resuming) = match after {
+        ChangesAfter::Cursor(cursor) => ("change_seq > $1".to_string(), cursor, cursor > 0),
+        ChangesAfter::Timestamp(since) => (format!("{} > $1", database::greatest("last_update, updated_at")), since, false),
+    };
+    let rows = sqlx::query(&format!(
+        "SELECT * FROM machines WHERE {} AND ($2 OR archived_at IS NULL) AND {} ORDER BY change_seq",
+        condition,
+        access::visible_machines(3)
+    ))
+    .bind(position)
+    .bind(resuming)
+    .bind(access::sees_all(user))
+    .bind(&user.username)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| Ok((row.try_get("change_seq")?, Machine::from_row(row)?)))
+        .collect()
+}
+
+// The response for machines fetched after `after`: the cursor is the last change delivered,
+// and archived machines are reported by id only
+fn machine_changes_response(after: ChangesAfter, changed: Vec<(i64, Machine)>) -> MachineChangesResponse {
+    let cursor = changed.last().map(|(change_seq, _)| *change_seq);
+    let (archived, machines): (Vec<Machine>, Vec<Machine>) =
+        changed.into_iter().map(|(_, machine)| machine).partition(|machine| machine.archived_at.is_some());
+    let archived = archived.into_iter().map(|machine| machine.id).collect();
+    let timestamp = machines.iter().map(|m| m.last_update.max(m.updated_at)).max();
+    let (cursor, timestamp) = match after {
+        ChangesAfter::Cursor(previous) => (cursor.unwrap_or(previous), timestamp.unwrap_or(0)),
+        ChangesAfter::Timestamp(since) => (cursor.unwrap_or(0), timestamp.unwrap_or(since)),
+    };
+    MachineChangesResponse { machines, archived, cursor, timestamp }
+}
+
+pub async fn wait_for_machine_changes(
     headers: HeaderMap,
-    Path(user_id): Path<i64>,
-    State(pool): State<DbPool>,
-    Json(payload): Json<UpdateUserRequest>,
-) -> Result<Json<User>, (StatusCode, Json<ErrorResponse>)> {
-    println!("[LOG] Update user request received for user ID: {}", user_id);
-    require_admin(&headers, &pool).await?;
+    user: AuthUser,
+    Query(params): Query<MachineChangesQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<MachineChangesResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let pool = &state.db;
+    let after = match (params.cursor, params.since) {
+        (None, Some(since)) => ChangesAfter::Timestamp(since),
+        (cursor, _) => ChangesAfter::Cursor(cursor.unwrap_or(0)),
+    };
+    let timeout = std::time::Duration::from_secs(params.timeout.unwrap_or(30).clamp(1, 120));
 
-    // Check if user exists
-    if let Err(_) = sqlx::query("SELECT id FROM users WHERE id = ?")
-        .bind(user_id)
-        .fetch_one(&pool)
-        .await
+    // Subscribe before querying so a write landing in between still wakes us up
+    let mut receiver = state.machine_changes.subscribe();
+    receiver.borrow_and_update();
+
+    // Every write wakes us, including ones to machines the user cannot see, so wait on until
+    // the deadline for a change that shows up in their feed
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut machines = fetch_changed_machines(pool, &user, after).await;
+    while matches!(&machines, Ok(m) if m.is_empty())
+        && tokio::time::timeout_at(deadline, receiver.changed()).await.is_ok_and(|changed| changed.is_ok())
     {
-        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
-            error: "User not found".to_string(),
+        machines = fetch_changed_machines(pool, &user, after).await;
+    }
+    let mut machines = machines.map(|changed| machine_changes_response(after, changed));
+
+    if let Ok(MachineChangesResponse { machines, .. }) = &mut machines {
+        round_machines(&load_precision(pool).await?, machines);
+    }
+    if let (Ok(MachineChangesResponse { machines, .. }), Some(locale)) =
+        (&mut machines, requested_locale(pool, &headers, params.locale.as_deref()).await)
+        && apply_display_names(pool, machines, &locale).await.is_err()
+    {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
         })));
     }
 
-    // Build update query dynamically based on provided fields
-    let mut query = String::from("UPDATE users SET ");
-    let mut params: Vec<String> = Vec::new();
-    let mut query_builder = sqlx::query("");
+    machines.map(Json).map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))
+}
+
+// GET /api/machines/stream
+// EventSource clients cannot set headers; the route takes the token from `?token=` as well
+#[derive(Deserialize)]
+pub struct MachineStreamQuery {
+    machine_id: Option<i64>,
+    replay_from: Option<i64>,
+    replay_to: Option<i64>,
+    replay_speed: Option<u32>,
+}
 
-    if let Some(password) = &payload.password {
-        params.push("password = ?".to_string());
-        query_builder = query_builder.bind(password);
+pub async fn stream_machines(
+    user: AuthUser,
+    Query(params): Query<MachineStreamQuery>,
+    State(state): State<AppState>,
+) -> Result<Sse<impl tokio_stream::Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<ErrorResponse>)> {
+    if let Some(machine_id) = params.machine_id
+        && !access::can_see_machine(&state.db, &user, machine_id).await.unwrap_or(false)
+    {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
     }
 
-    if let Some(role) = &payload.role {
-        if !["admin", "manager", "technician"].contains(&role.as_str()) {
+    let (sender, receiver) = mpsc::channel::<Event>(16);
+    match (params.replay_from, params.replay_to) {
+        (Some(from), Some(to)) => {
+            let speed = params.replay_speed.unwrap_or(1);
+            if !(1..=MAX_REPLAY_SPEED).contains(&speed) || to <= from {
+                return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                    error: format!("Invalid replay. replay_to must follow replay_from and replay_speed be 1 to {}", MAX_REPLAY_SPEED),
+                })));
+            }
+            tracing::info!("Replay of {}..{} started at {}x", from, to, speed);
+            tokio::spawn(replay_history(state.db.clone(), user, sender, params.machine_id, from, to, speed).in_current_span());
+        },
+        (None, None) => {
+            tokio::spawn(stream_live_changes(state.db.clone(), state.machine_changes.clone(), user, sender, params.machine_id).in_current_span());
+        },
+        _ => {
             return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
-                error: "Invalid role. Must be one of: admin, manager, technician".to_string(),
+                error: "Replay needs both replay_from and replay_to".to_string(),
             })));
+        },
+    }
+
+    Ok(Sse::new(ReceiverStream::new(receiver).map(Ok)).keep_alive(KeepAlive::default()))
+}
+
+// Pushes a `machines` event whenever machines change; ends once the client disconnects
+async fn stream_live_changes(
+    pool: DbPool,
+    changes: MachineChanges,
+    user: AuthUser,
+    sender: mpsc::Sender<Event>,
+    machine_id: Option<i64>,
+) {
+    let mut receiver = changes.subscribe();
+    // The first event is a snapshot of every machine the user may see
+    let mut after = ChangesAfter::Cursor(i64::MIN);
+    loop {
+        receiver.borrow_and_update();
+        let Ok(mut changed) = fetch_changed_machines(&pool, &user, after).await else {
+            return;
+        };
+        // Machines outside the filter still move the cursor
+        let cursor = changed.last().map(|(change_seq, _)| *change_seq);
+        changed.retain(|(_, machine)| machine_id.is_none_or(|id| machine.id == id));
+        if !changed.is_empty() {
+            let mut response = machine_changes_response(after, changed);
+            response.cursor = cursor.unwrap_or(response.cursor);
+            if let Ok(precision) = Precision::load(&pool).await {
+                round_machines(&precision, &mut response.machines);
+            }
+            let Ok(event) = Event::default().event("machines").json_data(response) else {
+                return;
+            };
+            if sender.send(event).await.is_err() {
+                return;
+            }
+        }
+        if let Some(cursor) = cursor {
+            after = ChangesAfter::Cursor(cursor);
+        }
+
+        tokio::select! {
+            changed = receiver.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            },
+            _ = sender.closed() => return,
         }
-        params.push("role = ?".to_string());
-        query_builder = query_builder.bind(role);
     }
+}
+
+// Streams recorded readings from the window as `replay` events, compressing the gaps between
+// them by the replay speed. Nothing is written, so live state is never touched.
+async fn replay_history(
+    pool: DbPool,
+    user: AuthUser,
+    sender: mpsc::Sender<Event>,
+    machine_id: Option<i64>,
+    from: i64,
+    to: i64,
+    speed: u32,
+) {
+    let precision = Precision::load(&pool).await.unwrap_or_default();
+    // Pages follow on from the last reading sent by (timestamp, table, id), which orders every
+    // reading of both tables, so readings sharing a timestamp are neither repeated nor skipped
+    let mut after: (i64, i64, i64) = (i64::MIN, 0, 0);
+    let mut previous: Option<i64> = None;
+    loop {
+        let rows = sqlx::query(&format!(
+            "SELECT CAST(0 AS BIGINT) AS source, id, machine_id, 'speed' AS metric, speed AS value, NULL AS unit, message, timestamp FROM speed_history \
+             WHERE timestamp >= $1 AND timestamp < $2 AND ($3 IS NULL OR machine_id = $4) \
+             AND machine_id IN (SELECT id FROM machines WHERE {}) AND (timestamp, 0, id) > ($7, $8, $9) \
+             UNION ALL \
+             SELECT CAST(1 AS BIGINT), id, machine_id, metric, value, unit, NULL, timestamp FROM metric_readings \
+             WHERE flagged = FALSE AND timestamp >= $10 AND timestamp < $11 AND ($12 IS NULL OR machine_id = $13) \
+             AND machine_id IN (SELECT id FROM machines WHERE {}) AND (timestamp, 1, id) > ($16, $17, $18) \
+             ORDER BY timestamp, source, id LIMIT 1000",
+            access::visible_machines(5),
+            access::visible_machines(14)
+        ))
+        .bind(from)
+        .bind(to)
+        .bind(machine_id)
+        .bind(machine_id)
+        .bind(access::sees_all(&user))
+        .bind(&user.username)
+        .bind(after.0)
+        .bind(after.1)
+        .bind(after.2)
+        .bind(from)
+        .bind(to)
+        .bind(machine_id)
+        .bind(machine_id)
+        .bind(access::sees_all(&user))
+        .bind(&user.username)
+        .bind(after.0)
+        .bind(after.1)
+        .bind(after.2)
+        .fetch_all(&pool)
+        .await;
+
+        let page = rows.and_then(|rows| {
+            rows.iter()
+                .map(|row| Ok(((row.try_get("timestamp")?, row.try_get("source")?, row.try_get("id")?), ReplayReading::from_row(row)?)))
+                .collect::<sqlx::Result<Vec<((i64, i64, i64), ReplayReading)>>>()
+        });
+        let Ok(page) = page else {
+            return;
+        };
+        let Some((last, _)) = page.last() else {
+            break;
+        };
+        after = *last;
+
+        for (_, mut reading) in page {
+            if let Some(previous) = previous
+                && reading.timestamp > previous
+            {
+                tokio::time::sleep(Duration::from_secs_f64((reading.timestamp - previous) as f64 / speed as f64)).await;
+            }
+            previous = Some(reading.timestamp);
 
-    if let Some(is_active) = &payload.is_active {
-        params.push("is_active = ?".to_string());
-        query_builder = query_builder.bind(is_active);
+            reading.value = precision.round(&reading.metric, reading.value);
+            let Ok(event) = Event::default().event("replay").json_data(ReplayEvent { sandbox: true, reading }) else {
+                return;
+            };
+            if sender.send(event).await.is_err() {
+                return;
+            }
+        }
     }
+    let _ = sender.send(Event::default().event("replay_end").data("{\"sandbox\":true}")).await;
+}
 
-    if params.is_empty() {
+// POST /api/machines/{id}/comments
+pub async fn add_comment(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<AddCommentRequest>,
+) -> Result<(StatusCode, Json<MaintenanceComment>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Add comment request received for machine ID: {}", machine_id);
+    // Machines outside a technician's assignment look the same as missing ones
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+    
+    // Check if category exists
+    if let Some(category_id) = payload.category_id
+        && sqlx::query("SELECT id FROM comment_categories WHERE id = $1")
+            .bind(category_id)
+            .fetch_one(&pool)
+            .await
+            .is_err()
+    {
         return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "No fields to update".to_string(),
+            error: "Unknown category".to_string(),
         })));
     }
 
-    query.push_str(&params.join(", "));
-    query.push_str(" WHERE id = ?");
-    query_builder = query_builder.bind(user_id);
-
-    // Execute update
-    match query_builder.execute(&pool).await {
-        Ok(_) => {
-            // Fetch updated user
-            match sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
-                .bind(user_id)
-                .fetch_one(&pool)
-                .await
-            {
-                Ok(user) => {
-                    println!("[LOG] User updated successfully: {}", user.username);
-                    Ok(Json(user))
-                },
-                Err(_) => {
-                    println!("[LOG] Failed to fetch updated user: {}", user_id);
-                    Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
-                        error: "Failed to fetch updated user".to_string(),
-                    })))
-                },
-            }
+    let username = user.username;
+    let priority = payload.priority.unwrap_or_else(|| "normal".to_string());
+    let timestamp = current_timestamp();
+    
+    match sqlx::query_scalar::<_, i64>(
+        "INSERT INTO maintenance_comments (machine_id, username, comment, priority, created_at, category_id) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING id"
+    )
+    .bind(machine_id)
+    .bind(&username)
+    .bind(&payload.comment)
+    .bind(&priority)
+    .bind(timestamp)
+    .bind(payload.category_id)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(comment_id) => {
+            tracing::info!("Comment added successfully for machine ID: {}", machine_id);
+            Ok((StatusCode::CREATED, Json(MaintenanceComment {
+                id: comment_id,
+                machine_id,
+                comment: payload.comment,
+                priority,
+                username,
+                created_at: timestamp,
+                category_id: payload.category_id,
+                pinned: false,
+                resolved_by: None,
+                resolved_at: None,
+            })))
         },
         Err(_) => {
-            println!("[LOG] Failed to update user: {}", user_id);
+            tracing::error!("Failed to add comment for machine ID: {}", machine_id);
             Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
-                error: "Failed to update user".to_string(),
+                error: "Failed to add comment".to_string(),
             })))
         },
     }
 }
 
-// PUT /api/machines/{id}
-pub async fn update_machine(
-    headers: HeaderMap,
+// Comment list filter on resolution: "open" or "resolved"
+#[derive(Deserialize)]
+pub struct CommentStatusQuery {
+    status: Option<String>,
+}
+
+fn validate_comment_status(status: Option<&str>) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    match status {
+        None | Some("open") | Some("resolved") => Ok(()),
+        Some(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Invalid status. Must be one of: open, resolved".to_string(),
+        }))),
+    }
+}
+
+// GET /api/machines/{id}/comments
+pub async fn get_comments(
+    user: AuthUser,
     Path(machine_id): Path<i64>,
+    Query(params): Query<CommentStatusQuery>,
     State(pool): State<DbPool>,
-    Json(payload): Json<UpdateMachineRequest>,
-) -> Result<Json<MachineResponse>, (StatusCode, Json<ErrorResponse>)> {
-    println!("[LOG] Update machine request received for machine ID: {}", machine_id);
-    require_admin(&headers, &pool).await?;
+) -> Result<Json<CommentListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Get comments request received for machine ID: {}", machine_id);
+    validate_comment_status(params.status.as_deref())?;
 
-    // Check if machine exists
-    if let Err(_) = sqlx::query("SELECT id FROM machines WHERE id = ?")
-        .bind(machine_id)
-        .fetch_one(&pool)
-        .await
-    {
+    // Machines outside a technician's assignment look the same as missing ones
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
         return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
             error: "Machine not found".to_string(),
         })));
     }
-
-    // Build update query dynamically based on provided fields
-    let mut query = String::from("UPDATE machines SET ");
-    let mut params: Vec<String> = Vec::new();
-    let mut query_builder = sqlx::query("");
-
-    if let Some(name) = &payload.name {
-        params.push("name = ?".to_string());
-        query_builder = query_builder.bind(name);
+    
+    // Pinned issues stay on top until they are resolved
+    match sqlx::query_as::<_, MaintenanceComment>(
+        "SELECT * FROM maintenance_comments \
+         WHERE machine_id = $1 AND ($2 IS NULL OR ($3 = 'resolved') = (resolved_at IS NOT NULL)) \
+         ORDER BY pinned AND resolved_at IS NULL DESC, created_at DESC, id DESC"
+    )
+    .bind(machine_id)
+    .bind(&params.status)
+    .bind(&params.status)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(comments) => {
+            tracing::info!("Comments retrieved successfully for machine ID: {}", machine_id);
+            Ok(Json(CommentListResponse { comments }))
+        },
+        Err(_) => {
+            tracing::error!("Failed to retrieve comments for machine ID: {}", machine_id);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })))
+        },
     }
+}
 
-    if let Some(code) = &payload.code {
-        params.push("code = ?".to_string());
-        query_builder = query_builder.bind(code);
+// Applies a pin or resolution change to a comment of a machine the user may see and returns
+// the updated comment
+async fn update_comment_state<'q>(
+    pool: &DbPool,
+    user: &AuthUser,
+    comment_id: i64,
+    query: sqlx::query::Query<'q, Db, <Db as sqlx::Database>::Arguments<'q>>,
+) -> Result<Json<MaintenanceComment>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    if !access::can_see_machine_of(pool, user, "maintenance_comments", comment_id).await.map_err(database_error)? {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Comment not found".to_string(),
+        })));
     }
-
-    if let Some(location) = &payload.location {
-        params.push("location = ?".to_string());
-        query_builder = query_builder.bind(location);
+    let result = query.execute(pool).await.map_err(database_error)?;
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Comment not found".to_string(),
+        })));
     }
+    sqlx::query_as::<_, MaintenanceComment>("SELECT * FROM maintenance_comments WHERE id = $1")
+        .bind(comment_id)
+        .fetch_one(pool)
+        .await
+        .map(Json)
+        .map_err(database_error)
+}
 
-    if let Some(machine_type) = &payload.machine_type {
-        params.push("machine_type = ?".to_string());
-        query_builder = query_builder.bind(machine_type);
-    }
+// POST /api/comments/{id}/pin
+pub async fn pin_comment(
+    user: AuthUser,
+    Path(comment_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MaintenanceComment>, (StatusCode, Json<ErrorResponse>)> {
+    let query = sqlx::query("UPDATE maintenance_comments SET pinned = TRUE, updated_at = $1 WHERE id = $2")
+        .bind(current_timestamp())
+        .bind(comment_id);
+    update_comment_state(&pool, &user, comment_id, query).await
+}
 
-    if let Some(true) = payload.regenerate_api_key {
-        params.push("api_key = ?".to_string());
-        query_builder = query_builder.bind(auth::generate_machine_api_key());
-    }
+// DELETE /api/comments/{id}/pin
+pub async fn unpin_comment(
+    user: AuthUser,
+    Path(comment_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MaintenanceComment>, (StatusCode, Json<ErrorResponse>)> {
+    let query = sqlx::query("UPDATE maintenance_comments SET pinned = FALSE, updated_at = $1 WHERE id = $2")
+        .bind(current_timestamp())
+        .bind(comment_id);
+    update_comment_state(&pool, &user, comment_id, query).await
+}
 
-    if params.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
-            error: "No fields to update".to_string(),
-        })));
-    }
+// POST /api/comments/{id}/resolve
+pub async fn resolve_comment(
+    user: AuthUser,
+    Path(comment_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MaintenanceComment>, (StatusCode, Json<ErrorResponse>)> {
+    // Resolving again keeps the original resolver and time
+    let now = current_timestamp();
+    let query = sqlx::query(
+        "UPDATE maintenance_comments SET resolved_by = COALESCE(resolved_by, $1), \
+         resolved_at = COALESCE(resolved_at, $2), updated_at = $3 WHERE id = $4"
+    )
+    .bind(&user.username)
+    .bind(now)
+    .bind(now)
+    .bind(comment_id);
+    update_comment_state(&pool, &user, comment_id, query).await
+}
 
-    query.push_str(&params.join(", "));
-    query.push_str(" WHERE id = ?");
-    query_builder = query_builder.bind(machine_id);
+// DELETE /api/comments/{id}/resolve
+pub async fn unresolve_comment(
+    user: AuthUser,
+    Path(comment_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MaintenanceComment>, (StatusCode, Json<ErrorResponse>)> {
+    let query = sqlx::query(
+        "UPDATE maintenance_comments SET resolved_by = NULL, resolved_at = NULL, updated_at = $1 WHERE id = $2"
+    )
+    .bind(current_timestamp())
+    .bind(comment_id);
+    update_comment_state(&pool, &user, comment_id, query).await
+}
+
+// GET /api/comments
+#[derive(Deserialize)]
+pub struct CommentFeedQuery {
+    priority: Option<String>,
+    status: Option<String>,
+    since: Option<i64>,
+    limit: Option<i64>,
+}
+
+pub async fn list_recent_comments(
+    user: AuthUser,
+    Query(params): Query<CommentFeedQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<CommentFeedResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if let Some(priority) = &params.priority
+        && !PRIORITIES.contains(&priority.as_str())
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid priority. Must be one of: {}", PRIORITIES.join(", ")),
+        })));
+    }
+    validate_comment_status(params.status.as_deref())?;
+    let limit = params.limit.unwrap_or(100).clamp(1, 500);
+
+    match sqlx::query_as::<_, CommentFeedEntry>(&format!(
+        "SELECT c.id, c.machine_id, machines.name AS machine_name, machines.code AS machine_code, c.comment, c.priority, \
+         c.username, c.created_at, c.category_id, c.pinned, c.resolved_by, c.resolved_at \
+         FROM maintenance_comments c JOIN machines ON machines.id = c.machine_id \
+         WHERE c.created_at >= $1 AND ($2 IS NULL OR c.priority = $3) \
+         AND ($4 IS NULL OR ($5 = 'resolved') = (c.resolved_at IS NOT NULL)) AND {} \
+         ORDER BY c.created_at DESC, c.id DESC LIMIT $8",
+        access::visible_machines(6)
+    ))
+    .bind(params.since.unwrap_or(0))
+    .bind(&params.priority)
+    .bind(&params.priority)
+    .bind(&params.status)
+    .bind(&params.status)
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .bind(limit)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(comments) => Ok(Json(CommentFeedResponse { comments })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// A [from, to) pair of Unix timestamps
+type TimeWindow = (i64, i64);
+
+// Resolves the `range` parameter of history and stats endpoints against the site timezone
+// and shift calendar; None when the request names no range
+fn named_range(range: Option<&str>, config: &Config) -> Result<Option<TimeWindow>, (StatusCode, Json<ErrorResponse>)> {
+    range
+        .map(|range| timerange::resolve_range(range, current_timestamp(), config).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: format!("Invalid range. Must be one of: {}", RANGE_NAMES.join(", ")),
+            }))
+        }))
+        .transpose()
+}
+
+// Window of a report: a named range, with `from` and `to` taking precedence where given, or
+// else the `default_span` seconds up to `to`, which defaults to now
+fn report_window(
+    range: Option<&str>,
+    from: Option<i64>,
+    to: Option<i64>,
+    default_span: i64,
+    config: &Config,
+) -> Result<TimeWindow, (StatusCode, Json<ErrorResponse>)> {
+    Ok(match named_range(range, config)? {
+        Some((range_from, range_to)) => (from.unwrap_or(range_from), to.unwrap_or(range_to)),
+        None => {
+            let to = to.unwrap_or_else(current_timestamp);
+            (from.unwrap_or(to - default_span), to)
+        },
+    })
+}
+
+// GET /api/machines/{id}/history
+// Most entries one history request returns; bulk copies of the data go through the export
+const MAX_HISTORY_LIMIT: i64 = 10_000;
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    limit: Option<i64>,
+    range: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    // Only readings taken while this batch ran
+    batch: Option<i64>,
+    // raw, hour or day; chosen from the window's length when omitted
+    resolution: Option<String>,
+}
+
+pub async fn get_history(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<HistoryQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<HistoryResponse>, (StatusCode, Json<ErrorResponse>)> {
+    // Machines outside a technician's assignment look the same as missing ones
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+    
+    let limit = params.limit.unwrap_or(100).clamp(1, MAX_HISTORY_LIMIT);
+
+    // Named ranges are resolved against the site timezone and shift calendar
+    let (from, to) = named_range(params.range.as_deref(), &config)?.unwrap_or((i64::MIN, i64::MAX));
+    let (from, to) = (from.max(params.from.unwrap_or(i64::MIN)), to.min(params.to.unwrap_or(i64::MAX)));
+    let (from, to) = match params.batch {
+        Some(batch_id) => match batches::find(&pool, machine_id, batch_id).await {
+            Ok(Some(batch)) => (from.max(batch.started_at), to.min(batch.ended_at.unwrap_or(i64::MAX))),
+            Ok(None) => {
+                return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                    error: "Batch not found".to_string(),
+                })));
+            },
+            Err(_) => {
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                    error: "Database error".to_string(),
+                })));
+            },
+        },
+        None => (from, to),
+    };
+    let resolution = match params.resolution.as_deref() {
+        Some(name) => Resolution::parse(name).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: format!("Invalid resolution. Must be one of: {}", RESOLUTION_NAMES.join(", ")),
+            }))
+        })?,
+        None => Resolution::for_window(from, to),
+    };
+
+    // Rollup buckets overlapping the window are included whole
+    let query = match resolution.table() {
+        Some((table, width)) => sqlx::query_as::<_, SpeedHistory>(&format!(
+            "SELECT avg_speed AS speed, NULL AS message, bucket_start AS timestamp, min_speed, max_speed, samples \
+             FROM {} WHERE machine_id = $1 AND bucket_start > $2 AND bucket_start < $3 ORDER BY bucket_start DESC LIMIT $4",
+            table
+        ))
+        .bind(machine_id)
+        .bind(from.saturating_sub(width))
+        .bind(to)
+        .bind(limit)
+        .fetch_all(&pool)
+        .await,
+        None => sqlx::query_as::<_, SpeedHistory>(
+            "SELECT speed, message, timestamp FROM speed_history \
+             WHERE machine_id = $1 AND timestamp >= $2 AND timestamp < $3 ORDER BY timestamp DESC LIMIT $4"
+        )
+        .bind(machine_id)
+        .bind(from)
+        .bind(to)
+        .bind(limit)
+        .fetch_all(&pool)
+        .await,
+    };
+    match query {
+        Ok(mut history) => {
+            let precision = load_precision(&pool).await?;
+            for entry in &mut history {
+                entry.speed = precision.round("speed", entry.speed);
+                entry.min_speed = entry.min_speed.map(|speed| precision.round("speed", speed));
+                entry.max_speed = entry.max_speed.map(|speed| precision.round("speed", speed));
+            }
+            // When the limit cut the window short, only annotations the chart can show are returned
+            let shown_from = match history.last() {
+                Some(oldest) if history.len() as i64 >= limit => oldest.timestamp.max(from),
+                _ => from,
+            };
+            let annotations = load_annotations(&pool, machine_id, shown_from, to).await.map_err(|_| {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                    error: "Database error".to_string(),
+                }))
+            })?;
+            Ok(Json(HistoryResponse {
+                history,
+                resolution: resolution.name().to_string(),
+                annotations,
+            }))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/machines/{id}/batches
+// Batches that ran at some point in [from, to); pass from=T&to=T+1 for the lot running at T
+#[derive(Deserialize)]
+pub struct BatchListQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+pub async fn list_machine_batches(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<BatchListQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineBatchListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, MachineBatch>(
+        "SELECT * FROM machine_batches WHERE machine_id = $1 AND started_at < $2 AND (ended_at IS NULL OR ended_at > $3) \
+         ORDER BY started_at DESC, id DESC LIMIT 500"
+    )
+    .bind(machine_id)
+    .bind(params.to.unwrap_or(i64::MAX))
+    .bind(params.from.unwrap_or(i64::MIN))
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(batches) => Ok(Json(MachineBatchListResponse { batches })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/{id}/batches
+// Starts a batch, ending the one running on the machine
+pub async fn start_batch(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<StartBatchRequest>,
+) -> Result<(StatusCode, Json<MachineBatch>), (StatusCode, Json<ErrorResponse>)> {
+    let lot_number = payload.lot_number.trim();
+    let product = payload.product.as_deref().map(str::trim).filter(|product| !product.is_empty());
+    if lot_number.is_empty()
+        || lot_number.chars().count() > batches::MAX_LOT_LENGTH
+        || product.is_some_and(|product| product.chars().count() > batches::MAX_LOT_LENGTH)
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("lot_number is required, and lot_number and product must be at most {} characters", batches::MAX_LOT_LENGTH),
+        })));
+    }
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    let started = async {
+        let mut tx = pool.begin().await?;
+        let batch = batches::start(&mut tx, machine_id, lot_number, product, Some(&user.username), current_timestamp()).await?;
+        tx.commit().await.map(|_| batch)
+    };
+    match started.await {
+        Ok(batch) => {
+            tracing::info!("{} started lot {} on machine ID {}", user.username, lot_number, machine_id);
+            Ok((StatusCode::CREATED, Json(batch)))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to start batch".to_string(),
+        }))),
+    }
+}
+
+// POST /api/batches/{id}/end
+pub async fn end_batch(
+    user: AuthUser,
+    Path(batch_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineBatch>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let not_found = || (StatusCode::NOT_FOUND, Json(ErrorResponse {
+        error: "Batch not found".to_string(),
+    }));
+
+    let machine_id: i64 = sqlx::query_scalar("SELECT machine_id FROM machine_batches WHERE id = $1")
+        .bind(batch_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(not_found)?;
+    if !access::can_see_machine(&pool, &user, machine_id).await.map_err(database_error)? {
+        return Err(not_found());
+    }
+
+    let batch = sqlx::query_as::<_, MachineBatch>(
+        "UPDATE machine_batches SET ended_at = $1, ended_by = $2 WHERE id = $3 AND ended_at IS NULL RETURNING *"
+    )
+    .bind(current_timestamp())
+    .bind(&user.username)
+    .bind(batch_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(database_error)?
+    .ok_or_else(|| (StatusCode::CONFLICT, Json(ErrorResponse {
+        error: "Batch has already ended".to_string(),
+    })))?;
+    tracing::info!("{} ended lot {} on machine ID {}", user.username, batch.lot_number, machine_id);
+    Ok(Json(batch))
+}
+
+// GET /api/batches?lot_number=
+// Every batch of a lot across the machines the user may see, to trace where it ran
+#[derive(Deserialize)]
+pub struct LotQuery {
+    lot_number: String,
+}
+
+pub async fn find_lot_batches(
+    user: AuthUser,
+    Query(params): Query<LotQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineBatchListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, MachineBatch>(&format!(
+        "SELECT machine_batches.* FROM machine_batches JOIN machines ON machines.id = machine_batches.machine_id \
+         WHERE machine_batches.lot_number = $1 AND {} ORDER BY machine_batches.started_at, machine_batches.id",
+        access::visible_machines(2)
+    ))
+    .bind(params.lot_number.trim())
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(batches) => Ok(Json(MachineBatchListResponse { batches })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/machines/{id}/changeovers
+#[derive(Deserialize)]
+pub struct ChangeoverQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+pub async fn list_machine_changeovers(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<ChangeoverQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<ChangeoverListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    // Changeovers overlapping the range, newest first; one still in progress overlaps any later range
+    match sqlx::query_as::<_, Changeover>(
+        "SELECT * FROM changeovers WHERE machine_id = $1 AND started_at < $2 AND COALESCE(ended_at, $3) >= $4 \
+         ORDER BY started_at DESC, id DESC"
+    )
+    .bind(machine_id)
+    .bind(params.to.unwrap_or(i64::MAX))
+    .bind(i64::MAX)
+    .bind(params.from.unwrap_or(i64::MIN))
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(changeovers) => Ok(Json(ChangeoverListResponse { changeovers })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/{id}/changeovers
+// Marks the start of a changeover by hand, from the product of the running batch
+pub async fn start_changeover(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<StartChangeoverRequest>,
+) -> Result<(StatusCode, Json<Changeover>), (StatusCode, Json<ErrorResponse>)> {
+    let to_product = payload.to_product.as_deref().map(str::trim).filter(|product| !product.is_empty());
+    if to_product.is_some_and(|product| product.chars().count() > batches::MAX_LOT_LENGTH) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("to_product must be at most {} characters", batches::MAX_LOT_LENGTH),
+        })));
+    }
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    // The unique index on open changeovers makes a second one per machine a conflict
+    match sqlx::query_as::<_, Changeover>(
+        "INSERT INTO changeovers (machine_id, from_product, to_product, source, started_at, started_by) \
+         SELECT $1, (SELECT product FROM machine_batches WHERE machine_id = $2 AND ended_at IS NULL), $3, 'operator', $4, $5 \
+         WHERE NOT EXISTS (SELECT 1 FROM changeovers WHERE machine_id = $6 AND ended_at IS NULL) RETURNING *"
+    )
+    .bind(machine_id)
+    .bind(machine_id)
+    .bind(to_product)
+    .bind(current_timestamp())
+    .bind(&user.username)
+    .bind(machine_id)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(changeover)) => {
+            tracing::info!("{} started a changeover on machine ID {}", user.username, machine_id);
+            Ok((StatusCode::CREATED, Json(changeover)))
+        },
+        Ok(None) => Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "A changeover is already in progress on this machine".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to start changeover".to_string(),
+        }))),
+    }
+}
+
+// POST /api/changeovers/{id}/end
+pub async fn end_changeover(
+    user: AuthUser,
+    Path(changeover_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<Changeover>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let not_found = || (StatusCode::NOT_FOUND, Json(ErrorResponse {
+        error: "Changeover not found".to_string(),
+    }));
+
+    let machine_id: i64 = sqlx::query_scalar("SELECT machine_id FROM changeovers WHERE id = $1")
+        .bind(changeover_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(not_found)?;
+    if !access::can_see_machine(&pool, &user, machine_id).await.map_err(database_error)? {
+        return Err(not_found());
+    }
+
+    let changeover = sqlx::query_as::<_, Changeover>(
+        "UPDATE changeovers SET ended_at = $1, ended_by = $2 WHERE id = $3 AND ended_at IS NULL RETURNING *"
+    )
+    .bind(current_timestamp())
+    .bind(&user.username)
+    .bind(changeover_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(database_error)?
+    .ok_or_else(|| (StatusCode::CONFLICT, Json(ErrorResponse {
+        error: "Changeover has already ended".to_string(),
+    })))?;
+    tracing::info!("{} ended changeover {} on machine ID {}", user.username, changeover_id, machine_id);
+    Ok(Json(changeover))
+}
+
+// POST /api/machines/{id}/assignments
+pub async fn assign_operator(
+    manager: RequireRole<roles::Manager>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<AssignOperatorRequest>,
+) -> Result<(StatusCode, Json<OperatorAssignment>), (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let window = chrono::NaiveDate::parse_from_str(&payload.shift_date, "%Y-%m-%d")
+        .ok()
+        .zip(usize::try_from(payload.shift).ok())
+        .and_then(|(date, shift)| timerange::shift_window(date, shift, &config))
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!(
+                "shift_date must be a date like 2024-03-18 and shift a shift number from 0 to {}",
+                config.shift_starts.len().saturating_sub(1)
+            ),
+        })))?;
+    if !access::can_see_machine(&pool, &manager, machine_id).await.map_err(database_error)? {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+    let username = payload.username.trim();
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE username = $1 AND is_active = TRUE)")
+        .bind(username)
+        .fetch_one(&pool)
+        .await
+        .map_err(database_error)?;
+    if !exists {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Unknown or disabled user".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, OperatorAssignment>(
+        "INSERT INTO operator_assignments (machine_id, username, shift_date, shift, starts_at, ends_at, assigned_by, assigned_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *"
+    )
+    .bind(machine_id)
+    .bind(username)
+    .bind(&payload.shift_date)
+    .bind(payload.shift)
+    .bind(window.0)
+    .bind(window.1)
+    .bind(&manager.username)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(assignment) => {
+            tracing::info!(
+                "{} assigned {} to machine ID {} for shift {} on {}",
+                manager.username, username, machine_id, payload.shift, payload.shift_date
+            );
+            Ok((StatusCode::CREATED, Json(assignment)))
+        },
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Operator is already assigned to this machine for that shift".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to assign operator".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/assignments/{id}
+pub async fn delete_assignment(
+    manager: RequireRole<roles::Manager>,
+    Path(assignment_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query("DELETE FROM operator_assignments WHERE id = $1")
+        .bind(assignment_id)
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() > 0 => {
+            tracing::info!("{} removed operator assignment {}", manager.username, assignment_id);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Ok(_) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Assignment not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/shift-schedule?from=&to=&machine_id=
+// Days are local dates; the schedule defaults to the week starting today
+#[derive(Deserialize)]
+pub struct ShiftScheduleQuery {
+    from: Option<String>,
+    to: Option<String>,
+    machine_id: Option<i64>,
+}
+
+pub async fn get_shift_schedule(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<ShiftScheduleQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<ShiftScheduleResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let invalid_date = || (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+        error: "from and to must be dates like 2024-03-18".to_string(),
+    }));
+    let parse = |date: &str| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid_date());
+
+    let from = match &params.from {
+        Some(from) => parse(from)?,
+        None => chrono::DateTime::from_timestamp(current_timestamp(), 0)
+            .ok_or_else(invalid_date)?
+            .with_timezone(&config.site_timezone)
+            .date_naive(),
+    };
+    let to = match &params.to {
+        Some(to) => parse(to)?,
+        None => from + chrono::Days::new(6),
+    };
+    let (from, to) = (from.format("%Y-%m-%d").to_string(), to.format("%Y-%m-%d").to_string());
+
+    let assignments = sqlx::query_as::<_, OperatorAssignment>(
+        "SELECT * FROM operator_assignments WHERE shift_date >= $1 AND shift_date <= $2 AND ($3 IS NULL OR machine_id = $4) \
+         ORDER BY shift_date, shift, machine_id, username"
+    )
+    .bind(&from)
+    .bind(&to)
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(database_error)?;
+    let shift_names = site::load(&pool, &config).await.map_err(database_error)?.shift_names;
+    Ok(Json(ShiftScheduleResponse { from, to, shift_names, assignments }))
+}
+
+// Annotations of a machine overlapping [from, to), oldest first
+async fn load_annotations(pool: &DbPool, machine_id: i64, from: i64, to: i64) -> sqlx::Result<Vec<TrendAnnotation>> {
+    sqlx::query_as::<_, TrendAnnotation>(
+        "SELECT * FROM trend_annotations \
+         WHERE machine_id = $1 AND starts_at < $2 AND COALESCE(ends_at, starts_at) >= $3 ORDER BY starts_at, id"
+    )
+    .bind(machine_id)
+    .bind(to)
+    .bind(from)
+    .fetch_all(pool)
+    .await
+}
+
+// GET /api/machines/{id}/annotations
+#[derive(Deserialize)]
+pub struct AnnotationQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+pub async fn list_annotations(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<AnnotationQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<TrendAnnotationListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match load_annotations(&pool, machine_id, params.from.unwrap_or(i64::MIN), params.to.unwrap_or(i64::MAX)).await {
+        Ok(annotations) => Ok(Json(TrendAnnotationListResponse { annotations })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/{id}/annotations
+pub async fn add_annotation(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateTrendAnnotationRequest>,
+) -> Result<(StatusCode, Json<TrendAnnotation>), (StatusCode, Json<ErrorResponse>)> {
+    let text = payload.text.trim();
+    if text.is_empty() || text.chars().count() > 500 {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "text must be between 1 and 500 characters".to_string(),
+        })));
+    }
+    if payload.ends_at.is_some_and(|ends_at| ends_at < payload.starts_at) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "ends_at must not be before starts_at".to_string(),
+        })));
+    }
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, TrendAnnotation>(
+        "INSERT INTO trend_annotations (machine_id, starts_at, ends_at, text, created_by, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"
+    )
+    .bind(machine_id)
+    .bind(payload.starts_at)
+    .bind(payload.ends_at)
+    .bind(text)
+    .bind(&user.username)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(annotation) => {
+            tracing::info!("{} annotated machine ID {} at {}", user.username, machine_id, annotation.starts_at);
+            Ok((StatusCode::CREATED, Json(annotation)))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to add annotation".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/annotations/{id}
+// Authors remove their own annotations; managers and admins remove any on machines they see
+pub async fn delete_annotation(
+    user: AuthUser,
+    Path(annotation_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let not_found = || (StatusCode::NOT_FOUND, Json(ErrorResponse {
+        error: "Annotation not found".to_string(),
+    }));
+
+    let annotation = sqlx::query_as::<_, TrendAnnotation>("SELECT * FROM trend_annotations WHERE id = $1")
+        .bind(annotation_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(not_found)?;
+    if !access::can_see_machine(&pool, &user, annotation.machine_id).await.map_err(database_error)? {
+        return Err(not_found());
+    }
+    if annotation.created_by != user.username && !access::sees_all(&user) {
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: "Only the author or a manager can delete this annotation".to_string(),
+        })));
+    }
+
+    sqlx::query("DELETE FROM trend_annotations WHERE id = $1")
+        .bind(annotation_id)
+        .execute(&pool)
+        .await
+        .map_err(database_error)?;
+    tracing::info!("{} deleted annotation {} of machine ID {}", user.username, annotation_id, annotation.machine_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct KioskRotationQuery {
+    group: Option<String>,
+    locale: Option<String>,
+}
+
+// GET /api/kiosk/rotation
+// Slides for a wallboard, in the order of the kiosk group, or every machine by name without
+// one. Machines the user may not see are left out.
+pub async fn kiosk_rotation(
+    headers: HeaderMap,
+    user: AuthUser,
+    Query(params): Query<KioskRotationQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<KioskRotationResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+
+    let group = match params.group.as_deref() {
+        Some(name) => match kiosk::load_groups(&pool, Some(name)).await.map_err(database_error)?.pop() {
+            Some(group) => Some(group),
+            None => {
+                return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                    error: "Kiosk group not found".to_string(),
+                })));
+            },
+        },
+        None => None,
+    };
+
+    let mut machines = sqlx::query_as::<_, Machine>(&format!(
+        "SELECT * FROM machines WHERE {} AND archived_at IS NULL ORDER BY name",
+        access::visible_machines(1)
+    ))
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .fetch_all(&pool)
+    .await
+    .map_err(database_error)?;
+    if let Some(group) = &group {
+        let mut by_id: HashMap<i64, Machine> = machines.into_iter().map(|machine| (machine.id, machine)).collect();
+        machines = group.machine_ids.iter().filter_map(|id| by_id.remove(id)).collect();
+    }
+
+    let precision = load_precision(&pool).await?;
+    round_machines(&precision, &mut machines);
+    if let Some(locale) = requested_locale(&pool, &headers, params.locale.as_deref()).await {
+        apply_display_names(&pool, &mut machines, &locale).await.map_err(database_error)?;
+    }
+    let mut slides = Vec::with_capacity(machines.len());
+    for machine in machines {
+        slides.push(kiosk::slide(&pool, &precision, machine).await.map_err(database_error)?);
+    }
+
+    Ok(Json(KioskRotationResponse {
+        rotation_interval_secs: group
+            .as_ref()
+            .and_then(|group| group.rotation_interval_secs)
+            .unwrap_or(config.kiosk_rotation_interval.as_secs() as i64),
+        group: group.map(|group| group.name),
+        generated_at: current_timestamp(),
+        slides,
+    }))
+}
+
+// GET /api/kiosk/groups
+pub async fn list_kiosk_groups(
+    _user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<KioskGroupListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match kiosk::load_groups(&pool, None).await {
+        Ok(groups) => Ok(Json(KioskGroupListResponse { groups })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/kiosk/groups/{name}
+pub async fn set_kiosk_group(
+    manager: RequireRole<roles::Manager>,
+    Path(name): Path<String>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<SetKioskGroupRequest>,
+) -> Result<Json<KioskGroup>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }));
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    if name.is_empty() || name.len() > 64 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(bad_request(
+            "Group name must be 1 to 64 letters, digits, dashes or underscores".to_string(),
+        ));
+    }
+    let (min_interval, max_interval) = KIOSK_ROTATION_RANGE;
+    if payload.rotation_interval_secs.is_some_and(|secs| !(min_interval..=max_interval).contains(&secs)) {
+        return Err(bad_request(format!(
+            "rotation_interval_secs must be between {} and {}",
+            min_interval, max_interval
+        )));
+    }
+    if payload.machine_ids.is_empty() {
+        return Err(bad_request("A kiosk group needs at least one machine".to_string()));
+    }
+    for (index, machine_id) in payload.machine_ids.iter().enumerate() {
+        if payload.machine_ids[..index].contains(machine_id) {
+            return Err(bad_request(format!("Machine {} is listed twice", machine_id)));
+        }
+        let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM machines WHERE id = $1")
+            .bind(machine_id)
+            .fetch_one(&pool)
+            .await
+            .map_err(database_error)?;
+        if !exists {
+            return Err(bad_request(format!("Machine {} not found", machine_id)));
+        }
+    }
+
+    kiosk::save_group(&pool, &name, &payload, &manager.username).await.map_err(database_error)?;
+    tracing::info!("{} saved kiosk group {} with {} machine(s)", manager.username, name, payload.machine_ids.len());
+    match kiosk::load_groups(&pool, Some(&name)).await.map_err(database_error)?.pop() {
+        Some(group) => Ok(Json(group)),
+        None => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/kiosk/groups/{name}
+pub async fn delete_kiosk_group(
+    manager: RequireRole<roles::Manager>,
+    Path(name): Path<String>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    // A display limited to the group would go blank, so its token has to be revoked first
+    match sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM display_tokens WHERE kiosk_group = $1 AND revoked_at IS NULL)"
+    )
+    .bind(&name)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(false) => {},
+        Ok(true) => {
+            return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+                error: "Kiosk group is used by a display token".to_string(),
+            })));
+        },
+        Err(_) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })));
+        },
+    }
+
+    match kiosk::delete_group(&pool, &name).await {
+        Ok(true) => {
+            tracing::info!("{} deleted kiosk group {}", manager.username, name);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Ok(false) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Kiosk group not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// Columns of a display token, leaving out the token hash
+const DISPLAY_TOKEN_COLUMNS: &str = "id, name, kiosk_group, expires_at, created_by, created_at, last_used_at, revoked_at";
+
+// GET /api/display-tokens
+pub async fn list_display_tokens(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+) -> Result<Json<DisplayTokenListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, DisplayToken>(&format!("SELECT {} FROM display_tokens ORDER BY name", DISPLAY_TOKEN_COLUMNS))
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(display_tokens) => Ok(Json(DisplayTokenListResponse { display_tokens })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/display-tokens
+pub async fn create_display_token(
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateDisplayTokenRequest>,
+) -> Result<(StatusCode, Json<DisplayToken>), (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    // Names end up in "display:<name>", so they are kept to plain identifiers
+    let name = payload.name.trim().to_lowercase();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Display token names may only contain letters, digits, '-' and '_'".to_string(),
+        })));
+    }
+    let now = current_timestamp();
+    if payload.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "expires_at must be in the future".to_string(),
+        })));
+    }
+    if let Some(group) = &payload.kiosk_group
+        && kiosk::load_groups(&pool, Some(group)).await.map_err(database_error)?.is_empty()
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Kiosk group not found".to_string(),
+        })));
+    }
+
+    let token = display_tokens::generate_token();
+    match sqlx::query_as::<_, DisplayToken>(&format!(
+        "INSERT INTO display_tokens (name, token, kiosk_group, expires_at, created_by, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+        DISPLAY_TOKEN_COLUMNS
+    ))
+    .bind(&name)
+    .bind(auth::hash_token(&token))
+    .bind(&payload.kiosk_group)
+    .bind(payload.expires_at)
+    .bind(&admin.username)
+    .bind(now)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(mut display_token) => {
+            tracing::info!("{} created display token {}", admin.username, name);
+            display_token.token = Some(token);
+            Ok((StatusCode::CREATED, Json(display_token)))
+        },
+        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Display token already exists".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/display-tokens/{id}
+// Revoked tokens stay listed, like disabled service accounts
+pub async fn revoke_display_token(
+    admin: RequireRole<roles::Admin>,
+    Path(token_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query("UPDATE display_tokens SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL")
+        .bind(current_timestamp())
+        .bind(token_id)
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() > 0 => {
+            tracing::info!("{} revoked display token ID {}", admin.username, token_id);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Ok(_) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Display token not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/products
+pub async fn list_products(
+    _user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<ProductListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match products::load_products(&pool, None).await {
+        Ok(products) => Ok(Json(ProductListResponse { products })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/products/{sku}
+// Creates or replaces a product along with all of its speed standards
+pub async fn set_product(
+    manager: RequireRole<roles::Manager>,
+    Path(sku): Path<String>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<SetProductRequest>,
+) -> Result<Json<Product>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }));
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    // Batches name their product by SKU, so it is limited like the batch's product field
+    if sku.trim() != sku || sku.is_empty() || sku.chars().count() > batches::MAX_LOT_LENGTH {
+        return Err(bad_request(format!(
+            "SKU must be 1 to {} characters without leading or trailing spaces",
+            batches::MAX_LOT_LENGTH
+        )));
+    }
+    if payload.name.trim().is_empty() {
+        return Err(bad_request("name must not be empty".to_string()));
+    }
+    for (index, standard) in payload.standards.iter().enumerate() {
+        if standard.machine_type.trim().is_empty() {
+            return Err(bad_request("machine_type must not be empty".to_string()));
+        }
+        if payload.standards[..index].iter().any(|other| other.machine_type == standard.machine_type) {
+            return Err(bad_request(format!("Machine type '{}' is listed twice", standard.machine_type)));
+        }
+        if !standard.standard_speed.is_finite() || standard.standard_speed < 0.0 {
+            return Err(bad_request("standard_speed must be a non-negative number".to_string()));
+        }
+    }
+
+    products::save_product(&pool, &sku, &payload, &manager.username).await.map_err(database_error)?;
+    tracing::info!("{} saved product {} with {} speed standard(s)", manager.username, sku, payload.standards.len());
+    match products::load_products(&pool, Some(&sku)).await.map_err(database_error)?.pop() {
+        Some(product) => Ok(Json(product)),
+        None => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/products/{sku}
+pub async fn delete_product(
+    manager: RequireRole<roles::Manager>,
+    Path(sku): Path<String>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    match products::delete_product(&pool, &sku).await {
+        Ok(true) => {
+            tracing::info!("{} deleted product {}", manager.username, sku);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Ok(false) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Product not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/kpis
+#[derive(Deserialize)]
+pub struct KpiQuery {
+    // Averages speed and metrics over this range instead of using the current values
+    range: Option<String>,
+}
+
+pub async fn get_kpis(
+    user: AuthUser,
+    Query(params): Query<KpiQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<KpiValuesResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let window = named_range(params.range.as_deref(), &config)?;
+    match kpis::values(&pool, &user, window).await {
+        Ok(kpis) => Ok(Json(KpiValuesResponse {
+            generated_at: current_timestamp(),
+            from: window.map(|(from, _)| from),
+            to: window.map(|(_, to)| to),
+            kpis,
+        })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/kpi-definitions
+pub async fn list_kpi_definitions(
+    _user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<KpiDefinitionListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, KpiDefinition>("SELECT * FROM kpi_definitions ORDER BY position, id")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(kpis) => Ok(Json(KpiDefinitionListResponse { kpis })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// Helper function to validate a KPI definition before it is saved
+fn validate_kpi_definition(payload: &SetKpiDefinitionRequest) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }));
+    if payload.label.trim().is_empty() || payload.label.len() > 100 {
+        return Err(bad_request("label must be 1 to 100 characters".to_string()));
+    }
+    if !KPI_AGGREGATIONS.contains(&payload.aggregation.as_str()) {
+        return Err(bad_request(format!("Invalid aggregation. Must be one of: {}", KPI_AGGREGATIONS.join(", "))));
+    }
+    match &payload.expression {
+        Some(expression) => {
+            Expr::parse_value(expression).map_err(|e| bad_request(format!("Invalid expression: {}", e)))?;
+        },
+        None if payload.aggregation != "count" => {
+            return Err(bad_request(format!("An expression is required for {}", payload.aggregation)));
+        },
+        None => {},
+    }
+    if let Some(filter) = &payload.filter {
+        Expr::parse(filter).map_err(|e| bad_request(format!("Invalid filter: {}", e)))?;
+    }
+    if payload.target.is_some_and(|target| !target.is_finite()) {
+        return Err(bad_request("target must be a finite number".to_string()));
+    }
+    if payload.unit.as_ref().is_some_and(|unit| unit.len() > 32) {
+        return Err(bad_request("unit must be at most 32 characters".to_string()));
+    }
+    Ok(())
+}
+
+// POST /api/kpi-definitions
+pub async fn create_kpi_definition(
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<SetKpiDefinitionRequest>,
+) -> Result<(StatusCode, Json<KpiDefinition>), (StatusCode, Json<ErrorResponse>)> {
+    validate_kpi_definition(&payload)?;
+
+    let timestamp = current_timestamp();
+    match sqlx::query_as::<_, KpiDefinition>(
+        "INSERT INTO kpi_definitions (label, aggregation, expression, filter, location, machine_type, kiosk_group, \
+         target, unit, position, updated_by, created_at, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) RETURNING *"
+    )
+    .bind(&payload.label)
+    .bind(&payload.aggregation)
+    .bind(&payload.expression)
+    .bind(&payload.filter)
+    .bind(&payload.location)
+    .bind(&payload.machine_type)
+    .bind(&payload.kiosk_group)
+    .bind(payload.target)
+    .bind(&payload.unit)
+    .bind(payload.position.unwrap_or(0))
+    .bind(&admin.username)
+    .bind(timestamp)
+    .bind(timestamp)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(kpi) => {
+            tracing::info!("{} created KPI {}: {}", admin.username, kpi.id, kpi.label);
+            Ok((StatusCode::CREATED, Json(kpi)))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to create KPI".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/kpi-definitions/{id}
+pub async fn update_kpi_definition(
+    admin: RequireRole<roles::Admin>,
+    Path(kpi_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<SetKpiDefinitionRequest>,
+) -> Result<Json<KpiDefinition>, (StatusCode, Json<ErrorResponse>)> {
+    validate_kpi_definition(&payload)?;
+
+    match sqlx::query_as::<_, KpiDefinition>(
+        "UPDATE kpi_definitions SET label = $1, aggregation = $2, expression = $3, filter = $4, location = $5, \
+         machine_type = $6, kiosk_group = $7, target = $8, unit = $9, position = $10, updated_by = $11, updated_at = $12 \
+         WHERE id = $13 RETURNING *"
+    )
+    .bind(&payload.label)
+    .bind(&payload.aggregation)
+    .bind(&payload.expression)
+    .bind(&payload.filter)
+    .bind(&payload.location)
+    .bind(&payload.machine_type)
+    .bind(&payload.kiosk_group)
+    .bind(payload.target)
+    .bind(&payload.unit)
+    .bind(payload.position.unwrap_or(0))
+    .bind(&admin.username)
+    .bind(current_timestamp())
+    .bind(kpi_id)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(kpi)) => {
+            tracing::info!("{} updated KPI {}: {}", admin.username, kpi.id, kpi.label);
+            Ok(Json(kpi))
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "KPI not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/kpi-definitions/{id}
+pub async fn delete_kpi_definition(
+    admin: RequireRole<roles::Admin>,
+    Path(kpi_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query("DELETE FROM kpi_definitions WHERE id = $1")
+        .bind(kpi_id)
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "KPI not found".to_string(),
+        }))),
+        Ok(_) => {
+            tracing::info!("{} deleted KPI {}", admin.username, kpi_id);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to delete KPI".to_string(),
+        }))),
+    }
+}
+
+// GET /api/machines/{id}/full
+pub async fn get_machine_detail(
+    headers: HeaderMap,
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<LocaleQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Get machine detail request received for machine ID: {}", machine_id);
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    let now = current_timestamp();
+    let locale = requested_locale(&pool, &headers, params.locale.as_deref()).await;
+
+    let mut machine = match sqlx::query_as::<_, Machine>("SELECT * FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_optional(&pool)
+        .await
+    {
+        Ok(Some(machine)) => machine,
+        Ok(None) => {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Machine not found".to_string(),
+            })));
+        },
+        Err(_) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })));
+        },
+    };
+
+    let precision = load_precision(&pool).await?;
+    round_machines(&precision, std::slice::from_mut(&mut machine));
+    if let Some(locale) = &locale
+        && apply_display_names(&pool, std::slice::from_mut(&mut machine), locale).await.is_err()
+    {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        })));
+    }
+
+    let since = now - 24 * 60 * 60;
+    let last_24h = sqlx::query_as::<_, SpeedAggregates>(
+        "SELECT COUNT(*) AS samples, AVG(speed) AS avg_speed, MIN(speed) AS min_speed, MAX(speed) AS max_speed \
+         FROM speed_history WHERE machine_id = $1 AND timestamp >= $2"
+    )
+    .bind(machine_id)
+    .bind(since)
+    .fetch_one(&pool)
+    .await;
+
+    let recent_comments = sqlx::query_as::<_, MaintenanceComment>(
+        "SELECT * FROM maintenance_comments WHERE machine_id = $1 \
+         ORDER BY pinned AND resolved_at IS NULL DESC, created_at DESC, id DESC LIMIT 10"
+    )
+    .bind(machine_id)
+    .fetch_all(&pool)
+    .await;
+
+    let open_work_orders = sqlx::query_as::<_, WorkOrder>(
+        "SELECT * FROM work_orders WHERE machine_id = $1 AND status IN ('open', 'in_progress') \
+         ORDER BY CASE priority WHEN 'critical' THEN 0 WHEN 'high' THEN 1 WHEN 'normal' THEN 2 ELSE 3 END, created_at"
+    )
+    .bind(machine_id)
+    .fetch_all(&pool)
+    .await;
+
+    let operators = sqlx::query_as::<_, OperatorAssignment>(
+        "SELECT * FROM operator_assignments WHERE machine_id = $1 AND starts_at <= $2 AND ends_at > $3 ORDER BY username"
+    )
+    .bind(machine_id)
+    .bind(now)
+    .bind(now)
+    .fetch_all(&pool)
+    .await;
+
+    let active_permits = permits::active_for_machine(&pool, machine_id, now).await;
+
+    let active_alarms = sqlx::query_as::<_, Alarm>(
+        "SELECT * FROM alarms WHERE machine_id = $1 AND (cleared_at IS NULL OR acknowledged_at IS NULL) \
+         ORDER BY CASE severity WHEN 'critical' THEN 0 WHEN 'warning' THEN 1 ELSE 2 END, raised_at DESC, id DESC"
+    )
+    .bind(machine_id)
+    .fetch_all(&pool)
+    .await;
+
+    match (last_24h, recent_comments, open_work_orders, operators, active_permits, active_alarms) {
+        (Ok(mut last_24h), Ok(recent_comments), Ok(open_work_orders), Ok(operators), Ok(active_permits), Ok(active_alarms)) => {
+            for value in [&mut last_24h.avg_speed, &mut last_24h.min_speed, &mut last_24h.max_speed] {
+                *value = value.map(|speed| precision.round("speed", speed));
+            }
+            let detail = MachineDetailResponse {
+                machine,
+                last_24h,
+                recent_comments,
+                open_work_orders,
+                operators,
+                active_permits,
+                active_alarms,
+            };
+            // The 24-hour figures slide with every request, so no modification time can stand
+            // for the detail; only its entity tag, a hash of the body, is sent and compared
+            let etag = content_etag(&format!("machine-{}", machine_id), &detail);
+            let validators = [(header::ETAG, etag.clone()), (header::CACHE_CONTROL, "no-cache".to_string())];
+            if headers.contains_key(header::IF_NONE_MATCH) && is_not_modified(&headers, &etag, 0) {
+                return Ok((StatusCode::NOT_MODIFIED, validators).into_response());
+            }
+            tracing::info!("Machine detail retrieved successfully for machine ID: {}", machine_id);
+            Ok((validators, Json(detail)).into_response())
+        },
+        _ => {
+            tracing::error!("Failed to retrieve machine detail for machine ID: {}", machine_id);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })))
+        },
+    }
+}
+
+// GET /api/comments/export
+#[derive(Deserialize)]
+pub struct CommentExportQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+    priority: Option<String>,
+    format: Option<String>,
+}
+
+pub async fn export_comments(
+    manager: RequireRole<roles::Manager>,
+    Query(params): Query<CommentExportQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let format = match params.format.as_deref() {
+        Some(format) => format.parse::<ExportFormat>().map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Invalid format. Must be one of: csv, xlsx".to_string(),
+        })))?,
+        None => ExportFormat::Csv,
+    };
+    check_export_approval(&config, &manager, "Comment exports are admin-only while export approval is on")?;
+    if let Some(priority) = &params.priority
+        && !PRIORITIES.contains(&priority.as_str())
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid priority. Must be one of: {}", PRIORITIES.join(", ")),
+        })));
+    }
+
+    let comments = sqlx::query_as::<_, CommentExportRecord>(
+        "SELECT c.created_at, m.name AS machine_name, m.code AS machine_code, m.location, c.username, c.priority, \
+         cat.name AS category, c.comment \
+         FROM maintenance_comments c \
+         JOIN machines m ON m.id = c.machine_id \
+         LEFT JOIN comment_categories cat ON cat.id = c.category_id \
+         WHERE c.created_at >= $1 AND c.created_at <= $2 AND ($3 IS NULL OR c.priority = $4) \
+         ORDER BY c.created_at, c.id"
+    )
+    .bind(params.from.unwrap_or(0))
+    .bind(params.to.unwrap_or(i64::MAX))
+    .bind(&params.priority)
+    .bind(&params.priority)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))?;
+
+    // Times are written in the site timezone, as read in the review meeting
+    let rows: Vec<Vec<String>> = comments
+        .into_iter()
+        .map(|record| {
+            let created_at = chrono::DateTime::from_timestamp(record.created_at, 0)
+                .unwrap_or_default()
+                .with_timezone(&config.site_timezone)
+                .format("%Y-%m-%d %H:%M")
+                .to_string();
+            vec![
+                created_at,
+                record.machine_name,
+                record.machine_code,
+                record.location.unwrap_or_default(),
+                record.username,
+                record.priority,
+                record.category.unwrap_or_default(),
+                record.comment,
+            ]
+        })
+        .collect();
+    let header = ["created_at", "machine", "code", "location", "author", "priority", "category", "comment"];
+    let contents = format.render("Comments", &header, &rows).map_err(|e| {
+        tracing::error!("Failed to render comment export: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to render export".to_string(),
+        }))
+    })?;
+
+    tracing::info!("Exported {} comments as {}", rows.len(), format.extension());
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"comments.{}\"", format.extension())),
+        ],
+        contents,
+    ).into_response())
+}
+
+// Refuses a bulk export to anyone but an admin while SCADA_EXPORT_APPROVAL is on
+fn check_export_approval(config: &Config, user: &AuthUser, error: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if config.export_approval && user.role != Role::Admin {
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse { error: error.to_string() })));
+    }
+    Ok(())
+}
+
+// POST /api/machines/{id}/history/export
+pub async fn export_history(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    State(jobs): State<Jobs>,
+    Json(payload): Json<HistoryExportRequest>,
+) -> Result<(StatusCode, Json<JobCreatedResponse>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("History export request received for machine ID: {}", machine_id);
+    check_export_approval(&config, &user, "History exports need approval; request one with POST /api/export-requests")?;
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    let (from, to) = named_range(payload.range.as_deref(), &config)?.unwrap_or((0, i64::MAX));
+    let (from, to) = (payload.from.unwrap_or(from), payload.to.unwrap_or(to));
+
+    let enqueued = queue_history_export(&jobs, &pool, &user.username, machine_id, from, to).await;
+
+    match enqueued {
+        Ok(job_id) => {
+            tracing::info!("History export job {} queued for machine ID: {}", job_id, machine_id);
+            Ok((StatusCode::ACCEPTED, Json(JobCreatedResponse {
+                job_id,
+                status: "queued".to_string(),
+            })))
+        },
+        Err(_) => {
+            tracing::error!("Failed to queue history export for machine ID: {}", machine_id);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to queue export".to_string(),
+            })))
+        },
+    }
+}
+
+// Queues the export of a machine's history in [from, to) as a job owned by `username`
+async fn queue_history_export(
+    jobs: &Jobs,
+    pool: &DbPool,
+    username: &str,
+    machine_id: i64,
+    from: i64,
+    to: i64,
+) -> anyhow::Result<String> {
+    let job_pool = pool.clone();
+    jobs
+        .enqueue("history_export", username, move |job| async move {
+            let total: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM speed_history WHERE machine_id = $1 AND timestamp >= $2 AND timestamp < $3"
+            )
+            .bind(machine_id)
+            .bind(from)
+            .bind(to)
+            .fetch_one(&job_pool)
+            .await?;
+            let precision = Precision::load(&job_pool).await?;
+
+            // Page through the range so progress can be reported on large exports
+            let mut csv = String::from("timestamp,speed,message\n");
+            let mut rows: i64 = 0;
+            loop {
+                let page = sqlx::query_as::<_, SpeedHistory>(
+                    "SELECT speed, message, timestamp FROM speed_history \
+                     WHERE machine_id = $1 AND timestamp >= $2 AND timestamp < $3 \
+                     ORDER BY timestamp, id LIMIT 1000 OFFSET $4"
+                )
+                .bind(machine_id)
+                .bind(from)
+                .bind(to)
+                .bind(rows)
+                .fetch_all(&job_pool)
+                .await?;
+
+                if page.is_empty() {
+                    break;
+                }
+                for entry in &page {
+                    csv.push_str(&format!(
+                        "{},{},{}\n",
+                        entry.timestamp,
+                        precision.round("speed", entry.speed),
+                        export::csv_field(entry.message.as_deref().unwrap_or(""))
+                    ));
+                }
+                rows += page.len() as i64;
+                job.set_progress(rows as f64 / total.max(1) as f64).await;
+            }
+
+            let file_name = format!("{}-machine-{}-history.csv", site::slug(&site::plant_name(&job_pool).await?), machine_id);
+            job.write_artifact(&file_name, "text/csv", csv.into_bytes()).await?;
+
+            Ok(serde_json::json!({
+                "machine_id": machine_id,
+                "from": from,
+                "to": to,
+                "rows": rows,
+            }))
+        })
+        .await
+
+}
+
+// POST /api/export-requests
+// Asks for a history export, which runs once a manager approves it
+pub async fn create_export_request(
+    user: AuthUser,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<CreateExportRequest>,
+) -> Result<(StatusCode, Json<ExportRequest>), (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "A reason is required".to_string(),
+        })));
+    }
+    let (from, to) = named_range(payload.range.as_deref(), &config)?.unwrap_or((0, i64::MAX));
+    let (from, to) = (payload.from.unwrap_or(from), payload.to.unwrap_or(to));
+    if from >= to {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "from must be before to".to_string(),
+        })));
+    }
+    if !access::can_see_machine(&pool, &user, payload.machine_id).await.map_err(database_error)? {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    let request_id: i64 = sqlx::query_scalar(
+        "INSERT INTO export_requests (machine_id, range_from, range_to, reason, requested_by, requested_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING id"
+    )
+    .bind(payload.machine_id)
+    .bind(from)
+    .bind(to)
+    .bind(reason)
+    .bind(&user.username)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    .map_err(database_error)?;
+
+    let message = format!("{} requests an export of machine ID {} history: {}", user.username, payload.machine_id, reason);
+    notifications::notify(&pool, "export_request", Some(payload.machine_id), &message).await.map_err(database_error)?;
+    match export_requests::fetch(&pool, request_id).await.map_err(database_error)? {
+        Some(request) => Ok((StatusCode::CREATED, Json(request))),
+        None => Err(database_error(sqlx::Error::RowNotFound)),
+    }
+}
+
+// GET /api/export-requests
+// Managers see every request, others only their own
+#[derive(Deserialize)]
+pub struct ExportRequestListQuery {
+    status: Option<String>,
+    machine_id: Option<i64>,
+}
+
+pub async fn list_export_requests(
+    user: AuthUser,
+    Query(params): Query<ExportRequestListQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<ExportRequestListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let requested_by = (user.role < Role::Manager).then_some(&user.username);
+    match sqlx::query_as::<_, ExportRequest>(
+        "SELECT * FROM export_requests WHERE ($1 IS NULL OR status = $2) AND ($3 IS NULL OR machine_id = $4) \
+         AND ($5 IS NULL OR requested_by = $6) ORDER BY requested_at DESC, id DESC LIMIT 500"
+    )
+    .bind(&params.status)
+    .bind(&params.status)
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .bind(requested_by)
+    .bind(requested_by)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(requests) => Ok(Json(ExportRequestListResponse { requests })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/export-requests/{id}
+pub async fn get_export_request(
+    user: AuthUser,
+    Path(request_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<ExportRequest>, (StatusCode, Json<ErrorResponse>)> {
+    match export_requests::fetch(&pool, request_id).await {
+        Ok(Some(request)) if user.role >= Role::Manager || request.requested_by == user.username => Ok(Json(request)),
+        Ok(_) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Export request not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/export-requests/{id}/approve and /reject
+// Approval queues the export as a job of the requester, who downloads it like any other job
+pub async fn approve_export_request(
+    manager: RequireRole<roles::Manager>,
+    Path(request_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(jobs): State<Jobs>,
+    payload: Option<Json<DecideExportRequest>>,
+) -> Result<Json<ExportRequest>, (StatusCode, Json<ErrorResponse>)> {
+    let Json(payload) = payload.unwrap_or_default();
+    decide_export_request(&manager, request_id, true, payload.note, &pool, &jobs).await
+}
+
+pub async fn reject_export_request(
+    manager: RequireRole<roles::Manager>,
+    Path(request_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(jobs): State<Jobs>,
+    Json(payload): Json<DecideExportRequest>,
+) -> Result<Json<ExportRequest>, (StatusCode, Json<ErrorResponse>)> {
+    if payload.note.as_deref().is_none_or(|note| note.trim().is_empty()) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "A note explaining the rejection is required".to_string(),
+        })));
+    }
+    decide_export_request(&manager, request_id, false, payload.note, &pool, &jobs).await
+}
+
+async fn decide_export_request(
+    manager: &AuthUser,
+    request_id: i64,
+    approve: bool,
+    note: Option<String>,
+    pool: &DbPool,
+    jobs: &Jobs,
+) -> Result<Json<ExportRequest>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    if manager.impersonated_by.is_some() {
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: "Not allowed while impersonating".to_string(),
+        })));
+    }
+    let Some(request) = export_requests::fetch(pool, request_id).await.map_err(database_error)? else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Export request not found".to_string(),
+        })));
+    };
+    if request.status != "pending" {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: format!("Export request is already {}", request.status),
+        })));
+    }
+    if let Some(refusal) = export_requests::refusal(pool, manager, &request).await.map_err(database_error)? {
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: refusal.to_string(),
+        })));
+    }
+
+    let decided = sqlx::query(
+        "UPDATE export_requests SET status = $1, decided_by = $2, decided_at = $3, decision_note = $4 \
+         WHERE id = $5 AND status = 'pending'"
+    )
+    .bind(if approve { "approved" } else { "rejected" })
+    .bind(&manager.username)
+    .bind(current_timestamp())
+    .bind(note.as_deref().map(str::trim).filter(|note| !note.is_empty()))
+    .bind(request_id)
+    .execute(pool)
+    .await
+    .map_err(database_error)?;
+    if decided.rows_affected() == 0 {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Export request was decided in the meantime".to_string(),
+        })));
+    }
+
+    if approve {
+        let job_id = queue_history_export(
+            jobs,
+            pool,
+            &request.requested_by,
+            request.machine_id,
+            request.range_from,
+            request.range_to,
+        )
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to queue export".to_string(),
+        })))?;
+        sqlx::query("UPDATE export_requests SET job_id = $1 WHERE id = $2")
+            .bind(&job_id)
+            .bind(request_id)
+            .execute(pool)
+            .await
+            .map_err(database_error)?;
+    }
+
+    tracing::info!(
+        "Export request {} of {} {} by {}",
+        request_id,
+        request.requested_by,
+        if approve { "approved" } else { "rejected" },
+        manager.username,
+    );
+    match export_requests::fetch(pool, request_id).await.map_err(database_error)? {
+        Some(request) => Ok(Json(request)),
+        None => Err(database_error(sqlx::Error::RowNotFound)),
+    }
+}
+
+// POST /api/machines/{id}/history/shift
+// Moves a range of history by a fixed offset, e.g. to correct data recorded by a gateway
+// whose clock was off. The shift is recorded and can be undone.
+pub async fn shift_history(
+    admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(jobs): State<Jobs>,
+    Json(payload): Json<HistoryShiftRequest>,
+) -> Result<(StatusCode, Json<JobCreatedResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: &str| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+        error: error.to_string(),
+    }));
+    if payload.from >= payload.to {
+        return Err(bad_request("from must be before to"));
+    }
+    if payload.offset_seconds == 0 {
+        return Err(bad_request("offset_seconds must not be zero"));
+    }
+
+    let newest: Option<Option<i64>> = sqlx::query_scalar(
+        "SELECT (SELECT MAX(timestamp) FROM speed_history WHERE machine_id = machines.id AND timestamp >= $1 AND timestamp < $2) \
+         FROM machines WHERE id = $3"
+    )
+    .bind(payload.from)
+    .bind(payload.to)
+    .bind(machine_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))?;
+    let Some(newest) = newest else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    };
+    if newest.is_some_and(|newest| newest.saturating_add(payload.offset_seconds) > current_timestamp()) {
+        return Err(bad_request("Shift would move history into the future"));
+    }
+
+    let job_pool = pool.clone();
+    let username = admin.username.clone();
+    let enqueued = jobs
+        .enqueue("history_shift", &admin.username, move |job| async move {
+            let (shift_id, rows) = history_shift::apply(
+                &job_pool,
+                machine_id,
+                (payload.from, payload.to),
+                payload.offset_seconds,
+                payload.reason.as_deref(),
+                &username,
+                &job.id,
+            )
+            .await?;
+            Ok(serde_json::json!({
+                "machine_id": machine_id,
+                "shift_id": shift_id,
+                "rows_shifted": rows,
+            }))
+        })
+        .await;
+
+    match enqueued {
+        Ok(job_id) => {
+            tracing::info!("History shift job {} queued for machine ID: {}", job_id, machine_id);
+            Ok((StatusCode::ACCEPTED, Json(JobCreatedResponse {
+                job_id,
+                status: "queued".to_string(),
+            })))
+        },
+        Err(_) => {
+            tracing::error!("Failed to queue history shift for machine ID: {}", machine_id);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to queue history shift".to_string(),
+            })))
+        },
+    }
+}
+
+// GET /api/machines/{id}/history/shifts
+pub async fn list_history_shifts(
+    _admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<HistoryShiftListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, HistoryShift>(
+        "SELECT * FROM history_shifts WHERE machine_id = $1 ORDER BY created_at DESC, id DESC"
+    )
+    .bind(machine_id)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(shifts) => Ok(Json(HistoryShiftListResponse { shifts })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/history-shifts/{id}/undo
+pub async fn undo_history_shift(
+    admin: RequireRole<roles::Admin>,
+    Path(shift_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(jobs): State<Jobs>,
+) -> Result<(StatusCode, Json<JobCreatedResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let undone_at: Option<Option<i64>> = sqlx::query_scalar("SELECT undone_at FROM history_shifts WHERE id = $1")
+        .bind(shift_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        })))?;
+    match undone_at {
+        None => {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "History shift not found".to_string(),
+            })));
+        },
+        Some(Some(_)) => {
+            return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+                error: "History shift already undone".to_string(),
+            })));
+        },
+        Some(None) => {},
+    }
+
+    let job_pool = pool.clone();
+    let username = admin.username.clone();
+    let enqueued = jobs
+        .enqueue("history_shift_undo", &admin.username, move |_job| async move {
+            let restored = history_shift::undo(&job_pool, shift_id, &username).await.map_err(|e| match e {
+                sqlx::Error::RowNotFound => anyhow::anyhow!("History shift {} was already undone", shift_id),
+                e => e.into(),
+            })?;
+            Ok(serde_json::json!({
+                "shift_id": shift_id,
+                "rows_restored": restored,
+            }))
+        })
+        .await;
+
+    match enqueued {
+        Ok(job_id) => {
+            tracing::info!("Undo of history shift {} queued as job {}", shift_id, job_id);
+            Ok((StatusCode::ACCEPTED, Json(JobCreatedResponse {
+                job_id,
+                status: "queued".to_string(),
+            })))
+        },
+        Err(_) => {
+            tracing::error!("Failed to queue undo of history shift {}", shift_id);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to queue history shift undo".to_string(),
+            })))
+        },
+    }
+}
+
+// POST /api/machines/{id}/decommission
+// Archives everything stored about the machine, then deletes the machine and its data. Its
+// API keys stop working right away, so no new data arrives while the archive is built.
+pub async fn decommission_machine(
+    admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    State(jobs): State<Jobs>,
+) -> Result<(StatusCode, Json<JobCreatedResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+
+    // Swap the primary key for one nobody holds and drop the additional keys
+    let mut tx = pool.begin().await.map_err(database_error)?;
+    let updated = sqlx::query("UPDATE machines SET api_key = $1 WHERE id = $2")
+        .bind(auth::hash_token(&auth::generate_machine_api_key()))
+        .bind(machine_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(database_error)?;
+    if updated.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+    sqlx::query("DELETE FROM machine_api_keys WHERE machine_id = $1")
+        .bind(machine_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(database_error)?;
+    tx.commit().await.map_err(database_error)?;
+
+    let job_pool = pool.clone();
+    let archive_dir = config.archive_dir.clone();
+    let username = admin.username.clone();
+    let enqueued = jobs
+        .enqueue("machine_decommission", &admin.username, move |job| async move {
+            let archive = archive::build(&job_pool, &job, machine_id, &username).await?;
+            tokio::fs::create_dir_all(&archive_dir).await?;
+            let path = archive_dir.join(&archive.file_name);
+            tokio::fs::write(&path, &archive.contents).await?;
+
+            // The data is only deleted once the archive is safely on disk
+            let mut tx = job_pool.begin().await?;
+            let stored = async {
+                let archive_id: i64 = sqlx::query_scalar(
+                    "INSERT INTO machine_archives (machine_id, machine_name, machine_code, file_path, file_name, size_bytes, \
+                     sha256, row_counts, created_by, created_at) \
+                     SELECT id, name, code, $1, $2, $3, $4, $5, $6, $7 FROM machines WHERE id = $8 RETURNING id"
+                )
+                .bind(path.to_string_lossy().to_string())
+                .bind(&archive.file_name)
+                .bind(archive.contents.len() as i64)
+                .bind(&archive.sha256)
+                .bind(serde_json::Value::Object(archive.row_counts.clone()).to_string())
+                .bind(&username)
+                .bind(current_timestamp())
+                .bind(machine_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                let document_files = machine_data::purge(&mut tx, machine_id).await?;
+                tx.commit().await?;
+                Ok::<_, sqlx::Error>((archive_id, document_files))
+            }
+            .await;
+            let (archive_id, document_files) = match stored {
+                Ok(stored) => stored,
+                Err(e) => {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return Err(e.into());
+                },
+            };
+            for file in document_files {
+                let _ = tokio::fs::remove_file(file).await;
+            }
+
+            Ok(serde_json::json!({
+                "machine_id": machine_id,
+                "archive_id": archive_id,
+                "file_name": archive.file_name,
+                "size_bytes": archive.contents.len(),
+                "row_counts": archive.row_counts,
+            }))
+        })
+        .await;
+
+    match enqueued {
+        Ok(job_id) => {
+            tracing::info!("Decommission job {} queued for machine ID: {}", job_id, machine_id);
+            Ok((StatusCode::ACCEPTED, Json(JobCreatedResponse {
+                job_id,
+                status: "queued".to_string(),
+            })))
+        },
+        Err(_) => {
+            tracing::error!("Failed to queue decommission of machine ID: {}", machine_id);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to queue decommission".to_string(),
+            })))
+        },
+    }
+}
+
+// POST /api/machines/{id}/merge
+// Folds a duplicate record into the machine in the path. A dry run performs the merge and
+// rolls it back, so the preview counts exactly what a real merge would do.
+pub async fn merge_machines(
+    admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<MergeMachinesRequest>,
+) -> Result<Json<MachineMergeResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if payload.source_id == machine_id {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "A machine cannot be merged into itself".to_string(),
+        })));
+    }
+    let database_error = |e: sqlx::Error| {
+        tracing::error!("Failed to merge machine {} into {}: {}", payload.source_id, machine_id, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))
+    };
+
+    let mut tx = pool.begin().await.map_err(database_error)?;
+    let found: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM machines WHERE id IN ($1, $2)")
+        .bind(machine_id)
+        .bind(payload.source_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(database_error)?;
+    if found != 2 {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    let counts = machine_data::merge(&mut tx, payload.source_id, machine_id).await.map_err(database_error)?;
+    if payload.dry_run {
+        tx.rollback().await.map_err(database_error)?;
+    } else {
+        tx.commit().await.map_err(database_error)?;
+        tracing::info!("Machine {} merged into {} by {}", payload.source_id, machine_id, admin.username);
+    }
+
+    Ok(Json(MachineMergeResponse {
+        machine_id,
+        merged_machine_id: payload.source_id,
+        dry_run: payload.dry_run,
+        moved: counts.moved,
+        dropped: counts.dropped,
+    }))
+}
+
+// GET /api/machine-archives
+pub async fn list_machine_archives(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineArchiveListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, MachineArchiveRecord>("SELECT * FROM machine_archives ORDER BY created_at DESC, id DESC")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(archives) => Ok(Json(MachineArchiveListResponse {
+            archives: archives.into_iter().map(MachineArchive::from).collect(),
+        })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/machine-archives/{id}/download
+pub async fn download_machine_archive(
+    _admin: RequireRole<roles::Admin>,
+    Path(archive_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let archive = sqlx::query_as::<_, MachineArchiveRecord>("SELECT * FROM machine_archives WHERE id = $1")
+        .bind(archive_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        })))?;
+    let Some(archive) = archive else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Archive not found".to_string(),
+        })));
+    };
+
+    match tokio::fs::read(&archive.file_path).await {
+        Ok(contents) => Ok((
+            [
+                (header::CONTENT_TYPE, "application/zip".to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", archive.file_name)),
+            ],
+            contents,
+        ).into_response()),
+        Err(e) => {
+            tracing::error!("Archive file {} is unreadable: {}", archive.file_path, e);
+            Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Archive file not found".to_string(),
+            })))
+        },
+    }
+}
+
+// Helper function to limit a job lookup to the user's own jobs, or none for admins
+fn job_owner(user: AuthUser) -> Option<String> {
+    (user.role != Role::Admin).then_some(user.username)
+}
+
+// GET /api/jobs/{id}
+pub async fn get_job(
+    user: AuthUser,
+    Path(job_id): Path<String>,
+    State(pool): State<DbPool>,
+) -> Result<Json<JobResponse>, (StatusCode, Json<ErrorResponse>)> {
+    // Users can only follow their own jobs; admins can see every job
+    let owner = job_owner(user);
+
+    match sqlx::query_as::<_, JobRecord>("SELECT * FROM jobs WHERE id = $1")
+        .bind(&job_id)
+        .fetch_optional(&pool)
+        .await
+    {
+        Ok(Some(job)) if owner.as_ref().is_none_or(|owner| *owner == job.created_by) => Ok(Json(job.into())),
+        Ok(_) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Job not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/jobs/{id}/artifact
+#[derive(Deserialize)]
+pub struct ArtifactQuery {
+    expires: Option<i64>,
+    signature: Option<String>,
+}
+
+pub async fn download_job_artifact(
+    user: Result<AuthUser, (StatusCode, Json<ErrorResponse>)>,
+    Path(job_id): Path<String>,
+    Query(params): Query<ArtifactQuery>,
+    State(pool): State<DbPool>,
+    State(jobs): State<Jobs>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    // A valid signed link grants access on its own; otherwise the caller must own the job
+    let owner = match (params.expires, params.signature.as_deref()) {
+        (Some(expires), Some(signature)) => {
+            if !jobs.artifacts.verify(&job_id, expires, signature) {
+                return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+                    error: "Invalid or expired download link".to_string(),
+                })));
+            }
+            None
+        },
+        _ => job_owner(user?),
+    };
+
+    let job = match sqlx::query_as::<_, JobRecord>("SELECT * FROM jobs WHERE id = $1")
+        .bind(&job_id)
+        .fetch_optional(&pool)
+        .await
+    {
+        Ok(Some(job)) if owner.as_ref().is_none_or(|owner| *owner == job.created_by) => job,
+        Ok(_) => {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Job not found".to_string(),
+            })));
+        },
+        Err(_) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })));
+        },
+    };
+
+    let (Some(path), Some(file_name)) = (job.artifact_path, job.artifact_name) else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Artifact not available".to_string(),
+        })));
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(contents) => {
+            tracing::info!("Artifact downloaded for job: {}", job_id);
+            if let Err(e) = export_requests::record_download(&pool, &job_id, current_timestamp()).await {
+                tracing::error!("Failed to log download of job {}: {}", job_id, e);
+            }
+            Ok((
+                [
+                    (header::CONTENT_TYPE, job.artifact_content_type.unwrap_or_else(|| "application/octet-stream".to_string())),
+                    (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+                ],
+                contents,
+            ).into_response())
+        },
+        Err(_) => {
+            tracing::error!("Failed to read artifact for job: {}", job_id);
+            Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Artifact not available".to_string(),
+            })))
+        },
+    }
+}
+
+// GET /api/jobs/{id}/artifact/link
+#[derive(Deserialize)]
+pub struct DownloadLinkQuery {
+    expires_in: Option<i64>,
+}
+
+pub async fn create_download_link(
+    user: AuthUser,
+    Path(job_id): Path<String>,
+    Query(params): Query<DownloadLinkQuery>,
+    State(pool): State<DbPool>,
+    State(jobs): State<Jobs>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<DownloadLinkResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let owner = job_owner(user);
+
+    let job = match sqlx::query_as::<_, JobRecord>("SELECT * FROM jobs WHERE id = $1")
+        .bind(&job_id)
+        .fetch_optional(&pool)
+        .await
+    {
+        Ok(Some(job)) if owner.as_ref().is_none_or(|owner| *owner == job.created_by) => job,
+        Ok(_) => {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Job not found".to_string(),
+            })));
+        },
+        Err(_) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })));
+        },
+    };
+
+    let Some(artifact_expires_at) = job.artifact_path.and(job.artifact_expires_at) else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Artifact not available".to_string(),
+        })));
+    };
+
+    let max_expires_in = config.download_link_max_age.as_secs() as i64;
+    let expires_in = params.expires_in.unwrap_or((24 * 60 * 60).min(max_expires_in));
+    if !(60..=max_expires_in).contains(&expires_in) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("expires_in must be between 60 and {} seconds", max_expires_in),
+        })));
+    }
+    // Links never outlive the artifact they point to
+    let expires_at = (current_timestamp() + expires_in).min(artifact_expires_at);
+    let signature = jobs.artifacts.sign(&job_id, expires_at);
+
+    Ok(Json(DownloadLinkResponse {
+        url: format!(
+            "{}/api/jobs/{}/artifact?expires={}&signature={}",
+            config.public_url, job_id, expires_at, signature
+        ),
+        expires_at,
+    }))
+}
+
+// Helper function to validate an alarm condition
+fn parse_condition(expression: &str) -> Result<Expr, (StatusCode, Json<ErrorResponse>)> {
+    Expr::parse(expression).map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+        error: format!("Invalid expression: {}", e),
+    })))
+}
+
+// GET /api/machines/{id}/alarm-rules
+pub async fn list_alarm_rules(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<AlarmRuleListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, AlarmRule>("SELECT * FROM alarm_rules WHERE machine_id = $1 ORDER BY name")
+        .bind(machine_id)
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(rules) => Ok(Json(AlarmRuleListResponse { rules })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/{id}/alarm-rules
+pub async fn create_alarm_rule(
+    admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateAlarmRuleRequest>,
+) -> Result<(StatusCode, Json<AlarmRule>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create alarm rule request received for machine ID: {}", machine_id);
+    parse_condition(&payload.expression)?;
+    if !ALARM_SEVERITIES.contains(&payload.severity.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid severity. Must be one of: {}", ALARM_SEVERITIES.join(", ")),
+        })));
+    }
+
+    // Check if machine exists
+    if sqlx::query("SELECT id FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_one(&pool)
+        .await
+        .is_err()
+    {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    let timestamp = current_timestamp();
+    match sqlx::query_as::<_, AlarmRule>(
+        "INSERT INTO alarm_rules (machine_id, name, expression, severity, enabled, created_by, created_at, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *"
+    )
+    .bind(machine_id)
+    .bind(&payload.name)
+    .bind(&payload.expression)
+    .bind(&payload.severity)
+    .bind(payload.enabled.unwrap_or(true))
+    .bind(&admin.username)
+    .bind(timestamp)
+    .bind(timestamp)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(rule) => {
+            tracing::info!("Alarm rule {} created for machine ID: {}", rule.id, machine_id);
+            Ok((StatusCode::CREATED, Json(rule)))
+        },
+        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Failed to create alarm rule".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/alarm-rules/{id}
+pub async fn update_alarm_rule(
+    _admin: RequireRole<roles::Admin>,
+    Path(rule_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateAlarmRuleRequest>,
+) -> Result<Json<AlarmRule>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update alarm rule request received for rule ID: {}", rule_id);
+    if let Some(expression) = &payload.expression {
+        parse_condition(expression)?;
+    }
+    if let Some(severity) = &payload.severity
+        && !ALARM_SEVERITIES.contains(&severity.as_str())
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid severity. Must be one of: {}", ALARM_SEVERITIES.join(", ")),
+        })));
+    }
+
+    let timestamp = current_timestamp();
+    let rule = match sqlx::query_as::<_, AlarmRule>(
+        "UPDATE alarm_rules SET name = COALESCE($1, name), expression = COALESCE($2, expression), \
+         severity = COALESCE($3, severity), enabled = COALESCE($4, enabled), updated_at = $5 WHERE id = $6 RETURNING *"
+    )
+    .bind(&payload.name)
+    .bind(&payload.expression)
+    .bind(&payload.severity)
+    .bind(payload.enabled)
+    .bind(timestamp)
+    .bind(rule_id)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(rule)) => rule,
+        Ok(None) => {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Alarm rule not found".to_string(),
+            })));
+        },
+        Err(_) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to update alarm rule".to_string(),
+            })));
+        },
+    };
+
+    // A changed or disabled condition no longer vouches for the alarm it raised
+    if payload.expression.is_some() || !rule.enabled {
+        let _ = sqlx::query("UPDATE alarms SET cleared_at = $1 WHERE rule_id = $2 AND cleared_at IS NULL")
+            .bind(timestamp)
+            .bind(rule_id)
+            .execute(&pool)
+            .await;
+    }
+
+    tracing::info!("Alarm rule updated successfully: {}", rule_id);
+    Ok(Json(rule))
+}
+
+// POST /api/alarm-rules/backtest
+pub async fn backtest_alarm_rule(
+    user: AuthUser,
+    State(pool): State<DbPool>,
+    Json(payload): Json<BacktestAlarmRuleRequest>,
+) -> Result<Json<Backtest>, (StatusCode, Json<ErrorResponse>)> {
+    let condition = parse_condition(&payload.expression)?;
+    let days = payload.days.unwrap_or(7);
+    if !(1..=MAX_BACKTEST_DAYS).contains(&days) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid days. Must be between 1 and {}", MAX_BACKTEST_DAYS),
+        })));
+    }
+
+    let machine_id = payload.machine_id;
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    let to = current_timestamp();
+    let from = to - days * 24 * 60 * 60;
+
+    match alarms::backtest(&pool, machine_id, &condition, from, to).await {
+        Ok(result) => Ok(Json(result)),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/alarms
+#[derive(Deserialize)]
+pub struct AlarmListQuery {
+    machine_id: Option<i64>,
+    active: Option<bool>,
+    // Only alarms raised on the batch's machine while it ran
+    batch: Option<i64>,
+}
+
+pub async fn list_alarms(
+    user: AuthUser,
+    Query(params): Query<AlarmListQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<AlarmListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, Alarm>(&format!(
+        "SELECT alarms.* FROM alarms JOIN machines ON machines.id = alarms.machine_id \
+         WHERE ($1 IS NULL OR alarms.machine_id = $2) AND (NOT $3 OR alarms.cleared_at IS NULL) \
+         AND ($4 IS NULL OR EXISTS (SELECT 1 FROM machine_batches b WHERE b.id = $5 AND b.machine_id = alarms.machine_id \
+         AND alarms.raised_at >= b.started_at AND (b.ended_at IS NULL OR alarms.raised_at < b.ended_at))) \
+         AND {} ORDER BY alarms.raised_at DESC LIMIT 500",
+        access::visible_machines(6)
+    ))
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .bind(params.active.unwrap_or(false))
+    .bind(params.batch)
+    .bind(params.batch)
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(alarms) => Ok(Json(AlarmListResponse { alarms })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/alarms/{id}/acknowledge
+pub async fn acknowledge_alarm(
+    user: AuthUser,
+    Path(alarm_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<Alarm>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine_of(&pool, &user, "alarms", alarm_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Alarm not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, Alarm>(
+        "UPDATE alarms SET acknowledged_by = COALESCE(acknowledged_by, $1), \
+         acknowledged_at = COALESCE(acknowledged_at, $2) WHERE id = $3 RETURNING *"
+    )
+    .bind(&user.username)
+    .bind(current_timestamp())
+    .bind(alarm_id)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(alarm)) => {
+            tracing::info!("Alarm {} acknowledged by {}", alarm_id, user.username);
+            Ok(Json(alarm))
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Alarm not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/alarms/{id}/annotation
+pub async fn annotate_alarm(
+    user: AuthUser,
+    Path(alarm_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<AnnotateAlarmRequest>,
+) -> Result<Json<Alarm>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Annotate alarm request received for alarm ID: {}", alarm_id);
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    if !access::can_see_machine_of(&pool, &user, "alarms", alarm_id).await.map_err(database_error)? {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Alarm not found".to_string(),
+        })));
+    }
+
+    let root_cause = payload.root_cause.map(|code| code.trim().to_lowercase());
+    if let Some(code) = &root_cause {
+        let known: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM alarm_root_causes WHERE code = $1)")
+            .bind(code)
+            .fetch_one(&pool)
+            .await
+            .map_err(database_error)?;
+        if !known {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: format!("Unknown root cause: {}", code),
+            })));
+        }
+    }
+    let notes = payload.notes.map(|notes| notes.trim().to_string()).filter(|notes| !notes.is_empty());
+
+    // Only cleared alarms are annotated; the cause of an active alarm is not known yet
+    let cleared: Option<bool> = sqlx::query_scalar("SELECT cleared_at IS NOT NULL FROM alarms WHERE id = $1")
+        .bind(alarm_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?;
+    match cleared {
+        None => return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Alarm not found".to_string(),
+        }))),
+        Some(false) => return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Alarm is still active".to_string(),
+        }))),
+        Some(true) => {},
+    }
+
+    let alarm = sqlx::query_as::<_, Alarm>(
+        "UPDATE alarms SET root_cause = $1, notes = $2, annotated_by = $3, annotated_at = $4 WHERE id = $5 RETURNING *"
+    )
+    .bind(&root_cause)
+    .bind(&notes)
+    .bind(&user.username)
+    .bind(current_timestamp())
+    .bind(alarm_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(database_error)?;
+
+    tracing::info!("Alarm {} annotated by {}", alarm_id, user.username);
+    Ok(Json(alarm))
+}
+
+// GET /api/alarm-work-order-policy
+pub async fn get_alarm_work_order_policy(
+    _manager: RequireRole<roles::Manager>,
+    State(pool): State<DbPool>,
+) -> Result<Json<AlarmWorkOrderPolicy>, (StatusCode, Json<ErrorResponse>)> {
+    match alarm_work_orders::load_policy(&pool).await {
+        Ok(policy) => Ok(Json(policy)),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/alarm-work-order-policy
+pub async fn update_alarm_work_order_policy(
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateAlarmWorkOrderPolicyRequest>,
+) -> Result<Json<AlarmWorkOrderPolicy>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let bad_request = |error: String| Err((StatusCode::BAD_REQUEST, Json(ErrorResponse { error })));
+
+    let defaults = AlarmWorkOrderPolicy::default();
+    let persist_minutes = payload.persist_minutes.unwrap_or(defaults.persist_minutes);
+    let (min_minutes, max_minutes) = ALARM_WORK_ORDER_PERSIST_RANGE;
+    if !(min_minutes..=max_minutes).contains(&persist_minutes) {
+        return bad_request(format!("persist_minutes must be between {} and {}", min_minutes, max_minutes));
+    }
+    let priority = payload.priority.unwrap_or(defaults.priority);
+    if !PRIORITIES.contains(&priority.as_str()) {
+        return bad_request(format!("Invalid priority. Must be one of: {}", PRIORITIES.join(", ")));
+    }
+    let assignee_rule = payload.assignee_rule.unwrap_or(defaults.assignee_rule);
+    if !ALARM_WORK_ORDER_ASSIGNEE_RULES.contains(&assignee_rule.as_str()) {
+        return bad_request(format!(
+            "Invalid assignee rule. Must be one of: {}",
+            ALARM_WORK_ORDER_ASSIGNEE_RULES.join(", ")
+        ));
+    }
+    if assignee_rule == "user" && payload.assign_to.is_none() {
+        return bad_request("assign_to is required for the user assignee rule".to_string());
+    }
+
+    if let Some(assign_to) = &payload.assign_to {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE username = $1)")
+            .bind(assign_to)
+            .fetch_one(&pool)
+            .await
+            .map_err(database_error)?;
+        if !exists {
+            return bad_request(format!("Unknown user: {}", assign_to));
+        }
+    }
+    if let Some(template_id) = payload.checklist_template_id {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM checklist_templates WHERE id = $1)")
+            .bind(template_id)
+            .fetch_one(&pool)
+            .await
+            .map_err(database_error)?;
+        if !exists {
+            return bad_request("Checklist template not found".to_string());
+        }
+    }
+
+    let policy = sqlx::query_as::<_, AlarmWorkOrderPolicy>(
+        "INSERT INTO alarm_work_order_policy \
+         (id, enabled, persist_minutes, priority, checklist_template_id, assignee_rule, assign_to, updated_by, updated_at) \
+         VALUES (1, $1, $2, $3, $4, $5, $6, $7, $8) \
+         ON CONFLICT (id) DO UPDATE SET enabled = excluded.enabled, persist_minutes = excluded.persist_minutes, \
+         priority = excluded.priority, checklist_template_id = excluded.checklist_template_id, \
+         assignee_rule = excluded.assignee_rule, assign_to = excluded.assign_to, updated_by = excluded.updated_by, \
+         updated_at = excluded.updated_at RETURNING *"
+    )
+    .bind(payload.enabled)
+    .bind(persist_minutes)
+    .bind(&priority)
+    .bind(payload.checklist_template_id)
+    .bind(&assignee_rule)
+    .bind(&payload.assign_to)
+    .bind(&admin.username)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    .map_err(database_error)?;
+
+    tracing::info!("Alarm work order policy updated by {}", admin.username);
+    Ok(Json(policy))
+}
+
+// GET /api/alarm-root-causes
+pub async fn list_alarm_root_causes(
+    _user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<AlarmRootCauseListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, AlarmRootCause>("SELECT * FROM alarm_root_causes ORDER BY code")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(root_causes) => Ok(Json(AlarmRootCauseListResponse { root_causes })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/alarm-root-causes
+pub async fn create_alarm_root_cause(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateAlarmRootCauseRequest>,
+) -> Result<(StatusCode, Json<AlarmRootCause>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create alarm root cause request received: {}", payload.code);
+    let code = payload.code.trim().to_lowercase();
+    if code.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Root cause code must not be empty".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, AlarmRootCause>(
+        "INSERT INTO alarm_root_causes (code, description, created_at) VALUES ($1, $2, $3) RETURNING *"
+    )
+    .bind(&code)
+    .bind(&payload.description)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(root_cause) => {
+            tracing::info!("Alarm root cause created successfully: {}", code);
+            Ok((StatusCode::CREATED, Json(root_cause)))
+        },
+        Err(_) => {
+            tracing::error!("Failed to create alarm root cause: {}", code);
+            Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "Root cause already exists".to_string(),
+            })))
+        },
+    }
+}
+
+// GET /api/alarms/export
+#[derive(Deserialize)]
+pub struct AlarmExportQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+    machine_id: Option<i64>,
+    format: Option<String>,
+}
+
+pub async fn export_alarms(
+    manager: RequireRole<roles::Manager>,
+    Query(params): Query<AlarmExportQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let format = match params.format.as_deref() {
+        Some(format) => format.parse::<ExportFormat>().map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Invalid format. Must be one of: csv, xlsx".to_string(),
+        })))?,
+        None => ExportFormat::Csv,
+    };
+    check_export_approval(&config, &manager, "Alarm exports are admin-only while export approval is on")?;
+
+    let alarms = sqlx::query_as::<_, AlarmExportRecord>(
+        "SELECT a.raised_at, a.cleared_at, m.name AS machine_name, m.code AS machine_code, a.severity, a.message, \
+         a.acknowledged_by, a.root_cause, a.notes \
+         FROM alarms a JOIN machines m ON m.id = a.machine_id \
+         WHERE a.raised_at >= $1 AND a.raised_at <= $2 AND ($3 IS NULL OR a.machine_id = $4) \
+         ORDER BY a.raised_at, a.id"
+    )
+    .bind(params.from.unwrap_or(0))
+    .bind(params.to.unwrap_or(i64::MAX))
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))?;
+
+    // Times are written in the site timezone, like the comment export
+    let local_time = |timestamp: i64| {
+        chrono::DateTime::from_timestamp(timestamp, 0)
+            .unwrap_or_default()
+            .with_timezone(&config.site_timezone)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    };
+    let rows: Vec<Vec<String>> = alarms
+        .into_iter()
+        .map(|record| {
+            vec![
+                local_time(record.raised_at),
+                record.cleared_at.map(local_time).unwrap_or_default(),
+                record.machine_name,
+                record.machine_code,
+                record.severity,
+                record.message,
+                record.acknowledged_by.unwrap_or_default(),
+                record.root_cause.unwrap_or_default(),
+                record.notes.unwrap_or_default(),
+            ]
+        })
+        .collect();
+    let header = ["raised_at", "cleared_at", "machine", "code", "severity", "message", "acknowledged_by", "root_cause", "notes"];
+    let contents = format.render("Alarms", &header, &rows).map_err(|e| {
+        tracing::error!("Failed to render alarm export: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to render export".to_string(),
+        }))
+    })?;
+
+    tracing::info!("Exported {} alarms as {}", rows.len(), format.extension());
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"alarms.{}\"", format.extension())),
+        ],
+        contents,
+    ).into_response())
+}
+
+fn validate_compliance_bounds(min_value: Option<f64>, max_value: Option<f64>) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let problem = match (min_value, max_value) {
+        (None, None) => Some("A limit needs min_value, max_value or both"),
+        (Some(min), Some(max)) if min >= max => Some("min_value must be below max_value"),
+        _ if min_value.into_iter().chain(max_value).any(|value| !value.is_finite()) => Some("Limits must be finite numbers"),
+        _ => None,
+    };
+    match problem {
+        Some(error) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse { error: error.to_string() }))),
+        None => Ok(()),
+    }
+}
+
+// Limits use the units of the catalog, so readings in a compatible unit can be converted
+async fn validate_compliance_unit(pool: &DbPool, unit: Option<&str>) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let Some(unit) = unit else {
+        return Ok(());
+    };
+    match units::lookup(pool, unit).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Unknown unit '{}'", unit),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/compliance-limits
+#[derive(Deserialize)]
+pub struct ComplianceLimitQuery {
+    machine_id: Option<i64>,
+    metric: Option<String>,
+}
+
+pub async fn list_compliance_limits(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<ComplianceLimitQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<ComplianceLimitListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    // Limits on every machine apply to each single machine too
+    match sqlx::query_as::<_, ComplianceLimit>(
+        "SELECT * FROM compliance_limits WHERE ($1 IS NULL OR machine_id IS NULL OR machine_id = $2) \
+         AND ($3 IS NULL OR metric = $4) ORDER BY metric, name"
+    )
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .bind(&params.metric)
+    .bind(&params.metric)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(limits) => Ok(Json(ComplianceLimitListResponse { limits })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/compliance-limits
+pub async fn create_compliance_limit(
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateComplianceLimitRequest>,
+) -> Result<(StatusCode, Json<ComplianceLimit>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create compliance limit request received: {} on {}", payload.name, payload.metric);
+    if payload.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Name must not be empty".to_string(),
+        })));
+    }
+    if !is_valid_metric_name(&payload.metric) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid metric name '{}'. Use lowercase letters, digits and underscores", payload.metric),
+        })));
+    }
+    validate_compliance_bounds(payload.min_value, payload.max_value)?;
+    validate_compliance_unit(&pool, payload.unit.as_deref()).await?;
+
+    if let Some(machine_id) = payload.machine_id {
+        let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM machines WHERE id = $1")
+            .bind(machine_id)
+            .fetch_one(&pool)
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })))?;
+        if !exists {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Machine not found".to_string(),
+            })));
+        }
+    }
+
+    let timestamp = current_timestamp();
+    match sqlx::query_as::<_, ComplianceLimit>(
+        "INSERT INTO compliance_limits \
+         (name, metric, machine_id, min_value, max_value, unit, reference, enabled, created_by, created_at, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING *"
+    )
+    .bind(payload.name.trim())
+    .bind(&payload.metric)
+    .bind(payload.machine_id)
+    .bind(payload.min_value)
+    .bind(payload.max_value)
+    .bind(&payload.unit)
+    .bind(&payload.reference)
+    .bind(payload.enabled.unwrap_or(true))
+    .bind(&admin.username)
+    .bind(timestamp)
+    .bind(timestamp)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(limit) => {
+            tracing::info!("Compliance limit {} ({}) created by {}", limit.id, limit.name, admin.username);
+            Ok((StatusCode::CREATED, Json(limit)))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to create compliance limit".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/compliance-limits/{id}
+pub async fn update_compliance_limit(
+    admin: RequireRole<roles::Admin>,
+    Path(limit_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateComplianceLimitRequest>,
+) -> Result<Json<ComplianceLimit>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let Some(limit) = sqlx::query_as::<_, ComplianceLimit>("SELECT * FROM compliance_limits WHERE id = $1")
+        .bind(limit_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?
+    else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Compliance limit not found".to_string(),
+        })));
+    };
+    if payload.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Name must not be empty".to_string(),
+        })));
+    }
+    validate_compliance_bounds(payload.min_value.or(limit.min_value), payload.max_value.or(limit.max_value))?;
+    validate_compliance_unit(&pool, payload.unit.as_deref()).await?;
+
+    // Exceedances already logged keep the bounds that applied when they happened
+    let limit = sqlx::query_as::<_, ComplianceLimit>(
+        "UPDATE compliance_limits SET name = COALESCE($1, name), min_value = COALESCE($2, min_value), \
+         max_value = COALESCE($3, max_value), unit = COALESCE($4, unit), reference = COALESCE($5, reference), \
+         enabled = COALESCE($6, enabled), updated_at = $7 WHERE id = $8 RETURNING *"
+    )
+    .bind(payload.name.as_deref().map(str::trim))
+    .bind(payload.min_value)
+    .bind(payload.max_value)
+    .bind(&payload.unit)
+    .bind(&payload.reference)
+    .bind(payload.enabled)
+    .bind(current_timestamp())
+    .bind(limit_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(database_error)?;
+
+    // A disabled limit is no longer watched, so periods still open end now
+    if !limit.enabled {
+        sqlx::query("UPDATE compliance_exceedances SET ended_at = $1 WHERE limit_id = $2 AND ended_at IS NULL")
+            .bind(limit.updated_at)
+            .bind(limit_id)
+            .execute(&pool)
+            .await
+            .map_err(database_error)?;
+    }
+
+    tracing::info!(
+        "Compliance limit {} ({}) updated by {}: min {:?}, max {:?}, enabled {}",
+        limit.id, limit.name, admin.username, limit.min_value, limit.max_value, limit.enabled,
+    );
+    Ok(Json(limit))
+}
+
+// DELETE /api/compliance-limits/{id}
+pub async fn delete_compliance_limit(
+    admin: RequireRole<roles::Admin>,
+    Path(limit_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+
+    // The exceedance log is a compliance record, so a limit it refers to can only be disabled
+    let logged: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM compliance_exceedances WHERE limit_id = $1")
+        .bind(limit_id)
+        .fetch_one(&pool)
+        .await
+        .map_err(database_error)?;
+    if logged {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Compliance limit has logged exceedances; disable it instead".to_string(),
+        })));
+    }
+
+    match sqlx::query("DELETE FROM compliance_limits WHERE id = $1")
+        .bind(limit_id)
+        .execute(&pool)
+        .await
+        .map_err(database_error)?
+        .rows_affected()
+    {
+        0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Compliance limit not found".to_string(),
+        }))),
+        _ => {
+            tracing::info!("Compliance limit {} deleted by {}", limit_id, admin.username);
+            Ok(StatusCode::NO_CONTENT)
+        },
+    }
+}
+
+// GET /api/compliance-exceedances
+#[derive(Deserialize)]
+pub struct ComplianceExceedanceQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+    machine_id: Option<i64>,
+    limit_id: Option<i64>,
+    active: Option<bool>,
+}
+
+pub async fn list_compliance_exceedances(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<ComplianceExceedanceQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<ComplianceExceedanceListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    // A period overlapping the range is listed, even when it started before it
+    match sqlx::query_as::<_, ComplianceExceedance>(
+        "SELECT * FROM compliance_exceedances WHERE started_at < $1 AND (ended_at IS NULL OR ended_at > $2) \
+         AND ($3 IS NULL OR machine_id = $4) AND ($5 IS NULL OR limit_id = $6) AND (NOT $7 OR ended_at IS NULL) \
+         ORDER BY started_at DESC, id DESC LIMIT 1000"
+    )
+    .bind(params.to.unwrap_or(i64::MAX))
+    .bind(params.from.unwrap_or(0))
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .bind(params.limit_id)
+    .bind(params.limit_id)
+    .bind(params.active.unwrap_or(false))
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(exceedances) => Ok(Json(ComplianceExceedanceListResponse { exceedances })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/failure-thresholds
+pub async fn list_failure_thresholds(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<ComplianceLimitQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<FailureThresholdListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    // Thresholds for every machine apply to each single machine too
+    match sqlx::query_as::<_, FailureThreshold>(
+        "SELECT * FROM failure_thresholds WHERE ($1 IS NULL OR machine_id IS NULL OR machine_id = $2) \
+         AND ($3 IS NULL OR metric = $4) ORDER BY metric, machine_id"
+    )
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .bind(&params.metric)
+    .bind(&params.metric)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(thresholds) => Ok(Json(FailureThresholdListResponse { thresholds })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/failure-thresholds
+pub async fn create_failure_threshold(
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateFailureThresholdRequest>,
+) -> Result<(StatusCode, Json<FailureThreshold>), (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    if !is_valid_metric_name(&payload.metric) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid metric name '{}'. Use lowercase letters, digits and underscores", payload.metric),
+        })));
+    }
+    validate_compliance_bounds(payload.min_value, payload.max_value)?;
+
+    if let Some(machine_id) = payload.machine_id {
+        let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM machines WHERE id = $1")
+            .bind(machine_id)
+            .fetch_one(&pool)
+            .await
+            .map_err(database_error)?;
+        if !exists {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Machine not found".to_string(),
+            })));
+        }
+    }
+    // The trend model takes one threshold per metric and machine
+    let duplicate: bool = sqlx::query_scalar(
+        "SELECT COUNT(*) > 0 FROM failure_thresholds WHERE metric = $1 AND machine_id IS NOT DISTINCT FROM $2"
+    )
+    .bind(&payload.metric)
+    .bind(payload.machine_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(database_error)?;
+    if duplicate {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "A failure threshold for this metric and machine already exists".to_string(),
+        })));
+    }
+
+    let threshold = sqlx::query_as::<_, FailureThreshold>(
+        "INSERT INTO failure_thresholds (metric, machine_id, min_value, max_value, created_by, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"
+    )
+    .bind(&payload.metric)
+    .bind(payload.machine_id)
+    .bind(payload.min_value)
+    .bind(payload.max_value)
+    .bind(&admin.username)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    .map_err(database_error)?;
+    tracing::info!("Failure threshold {} on {} created by {}", threshold.id, threshold.metric, admin.username);
+    Ok((StatusCode::CREATED, Json(threshold)))
+}
+
+// DELETE /api/failure-thresholds/{id}
+pub async fn delete_failure_threshold(
+    admin: RequireRole<roles::Admin>,
+    Path(threshold_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query("DELETE FROM failure_thresholds WHERE id = $1")
+        .bind(threshold_id)
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Failure threshold not found".to_string(),
+        }))),
+        Ok(_) => {
+            tracing::info!("Failure threshold {} deleted by {}", threshold_id, admin.username);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/predictions
+pub async fn list_predictions(
+    user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<FailurePredictionListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, FailurePrediction>(&format!(
+        "SELECT p.*, machines.name AS machine_name FROM failure_predictions p \
+         JOIN machines ON machines.id = p.machine_id \
+         WHERE machines.archived_at IS NULL AND {} ORDER BY p.risk DESC, machines.name",
+        access::visible_machines(1)
+    ))
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(predictions) => Ok(Json(FailurePredictionListResponse { predictions })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/advisories
+#[derive(Deserialize)]
+pub struct AdvisoryQuery {
+    machine_id: Option<i64>,
+    // Cleared advisories too; only open ones by default
+    #[serde(default)]
+    all: bool,
+}
+
+pub async fn list_advisories(
+    user: AuthUser,
+    Query(params): Query<AdvisoryQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MaintenanceAdvisoryListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, MaintenanceAdvisory>(&format!(
+        "SELECT a.*, machines.name AS machine_name FROM maintenance_advisories a \
+         JOIN machines ON machines.id = a.machine_id \
+         WHERE ($1 OR a.cleared_at IS NULL) AND ($2 IS NULL OR a.machine_id = $3) AND {} \
+         ORDER BY a.cleared_at IS NOT NULL, a.risk DESC, a.raised_at DESC LIMIT 1000",
+        access::visible_machines(4)
+    ))
+    .bind(params.all)
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(advisories) => Ok(Json(MaintenanceAdvisoryListResponse { advisories })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/advisories/{id}/acknowledge
+pub async fn acknowledge_advisory(
+    user: AuthUser,
+    Path(advisory_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MaintenanceAdvisory>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let not_found = || (StatusCode::NOT_FOUND, Json(ErrorResponse {
+        error: "Advisory not found".to_string(),
+    }));
+    let machine_id: i64 = sqlx::query_scalar("SELECT machine_id FROM maintenance_advisories WHERE id = $1")
+        .bind(advisory_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(not_found)?;
+    if !access::can_see_machine(&pool, &user, machine_id).await.map_err(database_error)? {
+        return Err(not_found());
+    }
+
+    sqlx::query(
+        "UPDATE maintenance_advisories SET acknowledged_by = COALESCE(acknowledged_by, $1), \
+         acknowledged_at = COALESCE(acknowledged_at, $2) WHERE id = $3"
+    )
+    .bind(&user.username)
+    .bind(current_timestamp())
+    .bind(advisory_id)
+    .execute(&pool)
+    .await
+    .map_err(database_error)?;
+    sqlx::query_as::<_, MaintenanceAdvisory>(
+        "SELECT a.*, machines.name AS machine_name FROM maintenance_advisories a \
+         JOIN machines ON machines.id = a.machine_id WHERE a.id = $1"
+    )
+    .bind(advisory_id)
+    .fetch_one(&pool)
+    .await
+    .map(Json)
+    .map_err(database_error)
+}
+
+// POST /api/admin/predictions/run
+pub async fn run_predictions(
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    State(jobs): State<Jobs>,
+) -> Result<(StatusCode, Json<JobCreatedResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let predictor = predictions::Predictor::from_config(&config);
+    let job_pool = pool.clone();
+    match jobs
+        .enqueue("predictions", &admin.username, move |job| async move {
+            predictions::run_predictions(&job_pool, &predictor, Some(&job)).await
+        })
+        .await
+    {
+        Ok(job_id) => Ok((StatusCode::ACCEPTED, Json(JobCreatedResponse {
+            job_id,
+            status: "queued".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to queue failure predictions".to_string(),
+        }))),
+    }
+}
+
+// GET /api/reports/compliance-exceedances
+#[derive(Deserialize)]
+pub struct ComplianceReportQuery {
+    // Local calendar month, e.g. "2026-09"; defaults to the previous month
+    month: Option<String>,
+    machine_id: Option<i64>,
+    format: Option<String>,
+}
+
+pub async fn compliance_exceedance_report(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<ComplianceReportQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let format = match params.format.as_deref() {
+        Some(format) => format.parse::<ExportFormat>().map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Invalid format. Must be one of: csv, xlsx".to_string(),
+        })))?,
+        None => ExportFormat::Csv,
+    };
+    let invalid_month = || (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+        error: "Invalid month. Use YYYY-MM".to_string(),
+    }));
+    let first_day = match params.month.as_deref() {
+        Some(month) => chrono::NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").map_err(|_| invalid_month())?,
+        None => timerange::previous_month(current_timestamp(), &config).ok_or_else(invalid_month)?,
+    };
+    let (from, to) = timerange::month_window(first_day, &config).ok_or_else(invalid_month)?;
+
+    let exceedances: Vec<(ComplianceExceedance, String, String)> = sqlx::query(
+        "SELECT e.*, m.name AS machine_name, m.code AS machine_code \
+         FROM compliance_exceedances e JOIN machines m ON m.id = e.machine_id \
+         WHERE e.started_at < $1 AND (e.ended_at IS NULL OR e.ended_at > $2) AND ($3 IS NULL OR e.machine_id = $4) \
+         ORDER BY e.started_at, e.id"
+    )
+    .bind(to)
+    .bind(from)
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .fetch_all(&pool)
+    .await
+    .and_then(|rows| {
+        rows.iter()
+            .map(|row| Ok((ComplianceExceedance::from_row(row)?, row.try_get("machine_name")?, row.try_get("machine_code")?)))
+            .collect()
+    })
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))?;
+
+    // Times are written in the site timezone; the minutes count only the part inside the month
+    let local_time = |timestamp: i64| {
+        chrono::DateTime::from_timestamp(timestamp, 0)
+            .unwrap_or_default()
+            .with_timezone(&config.site_timezone)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    };
+    let number = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+    let now = current_timestamp();
+    let rows: Vec<Vec<String>> = exceedances
+        .into_iter()
+        .map(|(exceedance, machine_name, machine_code)| {
+            let minutes = (exceedance.ended_at.unwrap_or(now).min(to) - exceedance.started_at.max(from)).max(0) / 60;
+            vec![
+                local_time(exceedance.started_at),
+                exceedance.ended_at.map(local_time).unwrap_or_default(),
+                minutes.to_string(),
+                machine_name,
+                machine_code,
+                exceedance.limit_name,
+                exceedance.reference.unwrap_or_default(),
+                exceedance.metric,
+                number(exceedance.min_value),
+                number(exceedance.max_value),
+                exceedance.peak_value.to_string(),
+                exceedance.unit.unwrap_or_default(),
+            ]
+        })
+        .collect();
+    let header = [
+        "started_at", "ended_at", "minutes_in_month", "machine", "code", "limit", "reference", "metric",
+        "min_value", "max_value", "peak_value", "unit",
+    ];
+    let contents = format.render("Compliance", &header, &rows).map_err(|e| {
+        tracing::error!("Failed to render compliance report: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to render export".to_string(),
+        }))
+    })?;
+
+    let month = first_day.format("%Y-%m");
+    tracing::info!("Exported {} compliance exceedances for {} as {}", rows.len(), month, format.extension());
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"compliance-exceedances-{}.{}\"", month, format.extension()),
+            ),
+        ],
+        contents,
+    ).into_response())
+}
+
+// GET /api/alarm-presentation
+pub async fn list_alarm_presentation(
+    _user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<AlarmPresentationListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, AlarmPresentation>(
+        "SELECT * FROM alarm_presentation \
+         ORDER BY CASE severity WHEN 'critical' THEN 0 WHEN 'warning' THEN 1 ELSE 2 END"
+    )
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(severities) => Ok(Json(AlarmPresentationListResponse { severities })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/alarm-presentation/{severity}
+pub async fn update_alarm_presentation(
+    _admin: RequireRole<roles::Admin>,
+    Path(severity): Path<String>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateAlarmPresentationRequest>,
+) -> Result<Json<AlarmPresentation>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update alarm presentation request received for severity: {}", severity);
+    if !ALARM_SEVERITIES.contains(&severity.as_str()) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Unknown severity".to_string(),
+        })));
+    }
+
+    let is_hex_color = payload.color.len() == 7
+        && payload.color.starts_with('#')
+        && payload.color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !is_hex_color {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Invalid color. Must be a hex color like #EB5757".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, AlarmPresentation>(
+        "UPDATE alarm_presentation SET sound_id = $1, color = $2, auto_popup = $3, updated_at = $4 \
+         WHERE severity = $5 RETURNING *"
+    )
+    .bind(&payload.sound_id)
+    .bind(&payload.color)
+    .bind(payload.auto_popup)
+    .bind(current_timestamp())
+    .bind(&severity)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(presentation) => {
+            tracing::info!("Alarm presentation updated for severity: {}", severity);
+            Ok(Json(presentation))
+        },
+        Err(_) => {
+            tracing::error!("Failed to update alarm presentation for severity: {}", severity);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to update alarm presentation".to_string(),
+            })))
+        },
+    }
+}
+
+// GET /api/units
+pub async fn list_units(
+    _user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<UnitListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, Unit>("SELECT * FROM units ORDER BY dimension, factor")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(units) => Ok(Json(UnitListResponse { units })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/units
+pub async fn create_unit(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<Unit>,
+) -> Result<(StatusCode, Json<Unit>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create unit request received: {}", payload.symbol);
+    if payload.symbol.trim().is_empty() || payload.dimension.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Symbol and dimension are required".to_string(),
+        })));
+    }
+    if !payload.factor.is_finite() || payload.factor <= 0.0 {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Factor must be a positive number".to_string(),
+        })));
+    }
+
+    match sqlx::query("INSERT INTO units (symbol, dimension, factor, description) VALUES ($1, $2, $3, $4)")
+        .bind(&payload.symbol)
+        .bind(&payload.dimension)
+        .bind(payload.factor)
+        .bind(&payload.description)
+        .execute(&pool)
+        .await
+    {
+        Ok(_) => {
+            tracing::info!("Unit created successfully: {}", payload.symbol);
+            Ok((StatusCode::CREATED, Json(payload)))
+        },
+        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Unit already exists".to_string(),
+        }))),
+    }
+}
+
+// GET /api/machines/{id}/metrics
+pub async fn get_machine_metrics(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineMetricListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, MachineMetric>(
+        "SELECT metric, unit, value, updated_at FROM machine_metrics WHERE machine_id = $1 ORDER BY metric"
+    )
+    .bind(machine_id)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(mut metrics) => {
+            let precision = load_precision(&pool).await?;
+            for metric in &mut metrics {
+                metric.value = precision.round(&metric.metric, metric.value);
+            }
+            Ok(Json(MachineMetricListResponse { metrics }))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/metric-precision
+pub async fn list_metric_precision(
+    _user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<MetricPrecisionListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, MetricPrecision>("SELECT * FROM metric_precision ORDER BY metric")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(metrics) => Ok(Json(MetricPrecisionListResponse { metrics })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/metric-precision/{metric}
+pub async fn update_metric_precision(
+    _admin: RequireRole<roles::Admin>,
+    Path(metric): Path<String>,
+    State(pool): State<DbPool>,
+    State(changes): State<MachineChanges>,
+    Json(payload): Json<UpdateMetricPrecisionRequest>,
+) -> Result<Json<MetricPrecision>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update metric precision request received for metric: {}", metric);
+    if payload.decimals > MAX_METRIC_DECIMALS {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid decimals. Must be between 0 and {}", MAX_METRIC_DECIMALS),
+        })));
+    }
+
+    let timestamp = current_timestamp();
+    match sqlx::query_as::<_, MetricPrecision>(
+        "INSERT INTO metric_precision (metric, decimals, updated_at) VALUES ($1, $2, $3) \
+         ON CONFLICT (metric) DO UPDATE SET decimals = excluded.decimals, updated_at = excluded.updated_at \
+         RETURNING *"
+    )
+    .bind(&metric)
+    .bind(i64::from(payload.decimals))
+    .bind(timestamp)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(precision) => {
+            // Every machine view renders differently now, so invalidate cached copies
+            let _ = sqlx::query("UPDATE machines SET updated_at = $1")
+                .bind(timestamp)
+                .execute(&pool)
+                .await;
+            changes.notify(timestamp);
+            tracing::info!("Metric precision updated for metric: {}", metric);
+            Ok(Json(precision))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to update metric precision".to_string(),
+        }))),
+    }
+}
+
+// GET /api/comment-categories
+pub async fn list_comment_categories(
+    _user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<CommentCategoryListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, CommentCategory>("SELECT * FROM comment_categories ORDER BY name")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(categories) => Ok(Json(CommentCategoryListResponse { categories })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/comment-categories
+pub async fn create_comment_category(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateCommentCategoryRequest>,
+) -> Result<(StatusCode, Json<CommentCategory>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create comment category request received: {}", payload.name);
+    let name = payload.name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Category name must not be empty".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, CommentCategory>(
+        "INSERT INTO comment_categories (name, description, created_at) VALUES ($1, $2, $3) RETURNING *"
+    )
+    .bind(&name)
+    .bind(&payload.description)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(category) => {
+            tracing::info!("Comment category created successfully: {}", name);
+            Ok((StatusCode::CREATED, Json(category)))
+        },
+        Err(_) => {
+            tracing::error!("Failed to create comment category: {}", name);
+            Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "Category already exists".to_string(),
+            })))
+        },
+    }
+}
+
+// GET /api/reports/comments-by-category
+#[derive(Deserialize)]
+pub struct CategoryReportQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+pub async fn comments_by_category_report(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<CategoryReportQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<CategoryReportResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let to = params.to.unwrap_or_else(current_timestamp);
+    let from = params.from.unwrap_or(to - 30 * 24 * 60 * 60);
+    let plant_name = load_plant_name(&pool).await?;
+
+    // Uncategorized comments are reported as a row with a null category
+    match sqlx::query_as::<_, CategoryReportEntry>(
+        "SELECT c.category_id, cat.name AS category, COUNT(*) AS comments, \
+         SUM(CASE WHEN c.priority IN ('high', 'critical') THEN 1 ELSE 0 END) AS high_priority, \
+         COUNT(DISTINCT c.machine_id) AS machines \
+         FROM maintenance_comments c LEFT JOIN comment_categories cat ON cat.id = c.category_id \
+         WHERE c.created_at >= $1 AND c.created_at <= $2 \
+         GROUP BY c.category_id, cat.name ORDER BY comments DESC"
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(categories) => Ok(Json(CategoryReportResponse { plant_name, from, to, categories })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/reports/alarm-root-causes
+pub async fn alarm_root_cause_report(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<CategoryReportQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<RootCauseReportResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let to = params.to.unwrap_or_else(current_timestamp);
+    let from = params.from.unwrap_or(to - 30 * 24 * 60 * 60);
+    let plant_name = load_plant_name(&pool).await?;
+
+    // Alarms without a root cause are reported as a row with a null root cause; active
+    // alarms count towards active_seconds up to now but not towards the time to clear
+    match sqlx::query_as::<_, RootCauseReportEntry>(
+        "SELECT root_cause, COUNT(*) AS alarms, COUNT(DISTINCT machine_id) AS machines, \
+         CAST(SUM(COALESCE(cleared_at, $1) - raised_at) AS BIGINT) AS active_seconds, \
+         CAST(AVG(cleared_at - raised_at) AS DOUBLE PRECISION) AS mean_time_to_clear_seconds \
+         FROM alarms WHERE raised_at >= $2 AND raised_at <= $3 \
+         GROUP BY root_cause ORDER BY alarms DESC"
+    )
+    .bind(current_timestamp())
+    .bind(from)
+    .bind(to)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(root_causes) => Ok(Json(RootCauseReportResponse { plant_name, from, to, root_causes })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/reports/labor-hours
+#[derive(Deserialize)]
+pub struct LaborReportQuery {
+    range: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    machine_id: Option<i64>,
+}
+
+pub async fn labor_hours_report(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<LaborReportQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<LaborReportResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let (from, to) = report_window(params.range.as_deref(), params.from, params.to, 365 * 24 * 60 * 60, &config)?;
+    let plant_name = load_plant_name(&pool).await?;
+
+    // Only finished entries are booked; months follow the site's local calendar
+    match sqlx::query_as::<_, LaborReportEntry>(&format!(
+        "SELECT w.machine_id, m.name AS machine_name, {} AS month, \
+         CAST(SUM(l.stopped_at - l.started_at) AS DOUBLE PRECISION) / 3600 AS hours, COUNT(*) AS entries, \
+         COUNT(DISTINCT l.username) AS technicians \
+         FROM work_order_labor l \
+         JOIN work_orders w ON w.id = l.work_order_id \
+         JOIN machines m ON m.id = w.machine_id \
+         WHERE l.stopped_at IS NOT NULL AND l.started_at >= $1 AND l.started_at <= $2 \
+         AND ($3 IS NULL OR w.machine_id = $4) \
+         GROUP BY w.machine_id, m.name, month ORDER BY month, w.machine_id",
+        database::format_date(&timerange::local_time_sql("l.started_at", from, to, &config), "%Y-%m")
+    ))
+    .bind(from)
+    .bind(to)
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(machines) => Ok(Json(LaborReportResponse { plant_name, from, to, machines })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/reports/changeovers
+#[derive(Deserialize)]
+pub struct ChangeoverReportQuery {
+    range: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    machine_id: Option<i64>,
+}
+
+pub async fn changeover_report(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<ChangeoverReportQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<ChangeoverReportResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let (from, to) = report_window(params.range.as_deref(), params.from, params.to, 365 * 24 * 60 * 60, &config)?;
+    let plant_name = load_plant_name(&pool).await?;
+
+    // Only finished changeovers are measured, grouped by product pair and the site's local month
+    match sqlx::query_as::<_, ChangeoverReportEntry>(&format!(
+        "SELECT c.machine_id, m.name AS machine_name, c.from_product, c.to_product, {} AS month, \
+         COUNT(*) AS changeovers, CAST(SUM(c.ended_at - c.started_at) AS DOUBLE PRECISION) / 60 AS total_minutes, \
+         CAST(AVG(c.ended_at - c.started_at) AS DOUBLE PRECISION) / 60 AS avg_minutes, \
+         CAST(MIN(c.ended_at - c.started_at) AS DOUBLE PRECISION) / 60 AS min_minutes, \
+         CAST(MAX(c.ended_at - c.started_at) AS DOUBLE PRECISION) / 60 AS max_minutes \
+         FROM changeovers c JOIN machines m ON m.id = c.machine_id \
+         WHERE c.ended_at IS NOT NULL AND c.started_at >= $1 AND c.started_at <= $2 \
+         AND ($3 IS NULL OR c.machine_id = $4) \
+         GROUP BY c.machine_id, m.name, c.from_product, c.to_product, month \
+         ORDER BY month, c.machine_id, c.from_product, c.to_product",
+        database::format_date(&timerange::local_time_sql("c.started_at", from, to, &config), "%Y-%m")
+    ))
+    .bind(from)
+    .bind(to)
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(changeovers) => Ok(Json(ChangeoverReportResponse { plant_name, from, to, changeovers })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/reports/operators
+#[derive(Deserialize)]
+pub struct OperatorReportQuery {
+    range: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    machine_id: Option<i64>,
+}
+
+pub async fn operator_report(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<OperatorReportQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<OperatorReportResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let (from, to) = report_window(params.range.as_deref(), params.from, params.to, 30 * 24 * 60 * 60, &config)?;
+    let plant_name = load_plant_name(&pool).await?;
+
+    // Readings, alarms and flagged metric readings are attributed to every operator assigned
+    // to the machine when they were recorded
+    match sqlx::query_as::<_, OperatorReportEntry>(
+        "SELECT username, machine_id, machine_name, COUNT(*) AS shifts, \
+         CAST(SUM(ends_at - starts_at) AS DOUBLE PRECISION) / 3600 AS hours, \
+         SUM(speed_sum) / CAST(NULLIF(SUM(samples), 0) AS DOUBLE PRECISION) AS avg_speed, \
+         CAST(SUM(alarms) AS BIGINT) AS alarms, CAST(SUM(flagged) AS BIGINT) AS flagged_readings \
+         FROM (SELECT a.username, a.machine_id, m.name AS machine_name, a.starts_at, a.ends_at, \
+             (SELECT SUM(speed) FROM speed_history h WHERE h.machine_id = a.machine_id AND h.timestamp >= a.starts_at AND h.timestamp < a.ends_at) AS speed_sum, \
+             (SELECT COUNT(*) FROM speed_history h WHERE h.machine_id = a.machine_id AND h.timestamp >= a.starts_at AND h.timestamp < a.ends_at) AS samples, \
+             (SELECT COUNT(*) FROM alarms l WHERE l.machine_id = a.machine_id AND l.raised_at >= a.starts_at AND l.raised_at < a.ends_at) AS alarms, \
+             (SELECT COUNT(*) FROM metric_readings r WHERE r.machine_id = a.machine_id AND r.flagged = TRUE \
+                 AND r.timestamp >= a.starts_at AND r.timestamp < a.ends_at) AS flagged \
+             FROM operator_assignments a JOIN machines m ON m.id = a.machine_id \
+             WHERE a.starts_at >= $1 AND a.starts_at <= $2 AND ($3 IS NULL OR a.machine_id = $4)) shifts \
+         GROUP BY username, machine_id, machine_name ORDER BY username, machine_name"
+    )
+    .bind(from)
+    .bind(to)
+    .bind(params.machine_id)
+    .bind(params.machine_id)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(operators) => Ok(Json(OperatorReportResponse { plant_name, from, to, operators })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/analytics/benchmark
+#[derive(Deserialize)]
+pub struct BenchmarkQuery {
+    machine_type: Option<String>,
+    metric: Option<String>,
+    period: Option<String>,
+}
+
+// Ranks the machines of one type against each other, wherever they are installed
+pub async fn benchmark_machines(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<BenchmarkQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<BenchmarkResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }));
+    let Some(machine_type) = params.machine_type.filter(|machine_type| !machine_type.trim().is_empty()) else {
+        return Err(bad_request("machine_type is required".to_string()));
+    };
+    let metric = params.metric.unwrap_or_else(|| "avg_speed".to_string());
+    if !BENCHMARK_METRICS.contains(&metric.as_str()) {
+        return Err(bad_request(format!("Invalid metric. Must be one of: {}", BENCHMARK_METRICS.join(", "))));
+    }
+    let period = params.period.unwrap_or_else(|| "month".to_string());
+    let Some(length) = benchmark::period_seconds(&period) else {
+        return Err(bad_request(format!("Invalid period. Must be one of: {}", BENCHMARK_PERIODS.join(", "))));
+    };
+
+    let to = current_timestamp();
+    let from = to - length;
+    match benchmark::rank_machines(&pool, &machine_type, &metric, (from, to)).await {
+        Ok(machines) => Ok(Json(BenchmarkResponse {
+            statistics: benchmark::statistics(&machines),
+            machine_type,
+            metric,
+            period,
+            from,
+            to,
+            machines,
+        })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/health-scores
+#[derive(Deserialize)]
+pub struct HealthScoreQuery {
+    // YYYY-MM-DD; the latest scored day when unset
+    day: Option<String>,
+}
+
+// The health scores of the visible machines on one day, worst first, for maintenance to work
+// down from the top each morning
+pub async fn list_health_scores(
+    user: AuthUser,
+    Query(params): Query<HealthScoreQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<HealthScoreListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let day = match params.day {
+        Some(day) => {
+            if chrono::NaiveDate::parse_from_str(&day, "%Y-%m-%d").is_err() {
+                return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                    error: "day must be a date like 2024-01-31".to_string(),
+                })));
+            }
+            Some(day)
+        },
+        None => sqlx::query_scalar("SELECT MAX(day) FROM machine_health_scores")
+            .fetch_one(&pool)
+            .await
+            .map_err(database_error)?,
+    };
+    let Some(day) = day else {
+        return Ok(Json(HealthScoreListResponse { day: None, scores: Vec::new() }));
+    };
+
+    let scores = sqlx::query_as::<_, MachineHealthScore>(&format!(
+        "SELECT s.machine_id, machines.name AS machine_name, machines.code AS machine_code, machines.location, s.day, \
+         s.score, s.alarm_rate, s.anomalies, s.data_coverage, s.downtime_change, s.computed_at \
+         FROM machine_health_scores s JOIN machines ON machines.id = s.machine_id \
+         WHERE s.day = $1 AND machines.archived_at IS NULL AND {} ORDER BY s.score, machines.name",
+        access::visible_machines(2)
+    ))
+    .bind(&day)
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .fetch_all(&pool)
+    .await
+    .map_err(database_error)?;
+    Ok(Json(HealthScoreListResponse { day: Some(day), scores }))
+}
+
+// Query for the gap endpoints: min_gap like "10m", range defaults to the last week
+#[derive(Deserialize)]
+pub struct GapQuery {
+    min_gap: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+impl GapQuery {
+    // Returns (from, to, min_gap) in seconds, min_gap raised past what update dedup leaves out
+    fn resolve(&self, dedup_window: Duration) -> Result<(i64, i64, i64), (StatusCode, Json<ErrorResponse>)> {
+        let min_gap = match &self.min_gap {
+            Some(value) => gaps::parse_duration(value).ok_or_else(|| (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "Invalid min_gap. Use seconds or a number followed by s, m, h or d, e.g. 10m".to_string(),
+            })))?,
+            None => gaps::DEFAULT_MIN_GAP,
+        };
+        let to = self.to.unwrap_or_else(current_timestamp);
+        let from = self.from.unwrap_or(to - gaps::DEFAULT_RANGE);
+        if from >= to {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "from must be before to".to_string(),
+            })));
+        }
+        Ok((from, to, gaps::effective_min_gap(min_gap, dedup_window)))
+    }
+}
+
+// GET /api/machines/{id}/gaps
+pub async fn get_machine_gaps(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<GapQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<MachineGapsResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let (from, to, min_gap) = params.resolve(config.dedup_window)?;
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+    let created_at: i64 = match sqlx::query_scalar("SELECT created_at FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_optional(&pool)
+        .await
+    {
+        Ok(Some(created_at)) => created_at,
+        Ok(None) => {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Machine not found".to_string(),
+            })));
+        },
+        Err(_) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })));
+        },
+    };
+
+    // Time before the machine existed is not data loss
+    let from = from.max(created_at).min(to);
+    match gaps::find_gaps(&pool, machine_id, from, to, min_gap).await {
+        Ok(gaps) => Ok(Json(MachineGapsResponse {
+            machine_id,
+            from,
+            to,
+            min_gap,
+            total_gap_seconds: gaps.iter().map(|gap| gap.duration).sum(),
+            coverage: gaps::coverage(from, to, &gaps),
+            gaps,
+        })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/reports/history-gaps
+pub async fn history_gaps_report(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<GapQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<GapReportResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let (from, to, min_gap) = params.resolve(config.dedup_window)?;
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let fleet: Vec<(i64, String, i64)> = sqlx::query_as(
+        "SELECT id, name, created_at FROM machines WHERE created_at < $1 AND archived_at IS NULL ORDER BY id"
+    )
+        .bind(to)
+        .fetch_all(&pool)
+        .await
+        .map_err(database_error)?;
+
+    let mut machines = Vec::with_capacity(fleet.len());
+    for (machine_id, machine_name, created_at) in fleet {
+        let machine_from = from.max(created_at);
+        let gaps = gaps::find_gaps(&pool, machine_id, machine_from, to, min_gap)
+            .await
+            .map_err(database_error)?;
+        machines.push(GapReportEntry {
+            machine_id,
+            machine_name,
+            gaps: gaps.len() as i64,
+            total_gap_seconds: gaps.iter().map(|gap| gap.duration).sum(),
+            longest_gap: gaps.iter().map(|gap| gap.duration).max().unwrap_or(0),
+            coverage: gaps::coverage(machine_from, to, &gaps),
+        });
+    }
+
+    Ok(Json(GapReportResponse {
+        plant_name: load_plant_name(&pool).await?,
+        from,
+        to,
+        min_gap,
+        gaps: machines.iter().map(|machine| machine.gaps).sum(),
+        total_gap_seconds: machines.iter().map(|machine| machine.total_gap_seconds).sum(),
+        machines,
+    }))
+}
+
+// GET /api/machines/{id}/display-names
+pub async fn list_display_names(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<DisplayNameListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, MachineDisplayName>(
+        "SELECT locale, display_name FROM machine_display_names WHERE machine_id = $1 ORDER BY locale"
+    )
+    .bind(machine_id)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(display_names) => Ok(Json(DisplayNameListResponse { display_names })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/machines/{id}/display-names/{locale}
+pub async fn set_display_name(
+    _admin: RequireRole<roles::Admin>,
+    Path((machine_id, locale)): Path<(i64, String)>,
+    State(pool): State<DbPool>,
+    State(changes): State<MachineChanges>,
+    Json(payload): Json<SetDisplayNameRequest>,
+) -> Result<Json<MachineDisplayName>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Set display name request received for machine ID: {} ({})", machine_id, locale);
+    let locale = locale.trim().to_lowercase();
+    if locale.is_empty() || payload.display_name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Locale and display name are required".to_string(),
+        })));
+    }
+
+    // Check if machine exists
+    if sqlx::query("SELECT id FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_one(&pool)
+        .await
+        .is_err()
+    {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query(
+        "INSERT INTO machine_display_names (machine_id, locale, display_name) VALUES ($1, $2, $3) \
+         ON CONFLICT (machine_id, locale) DO UPDATE SET display_name = excluded.display_name"
+    )
+    .bind(machine_id)
+    .bind(&locale)
+    .bind(&payload.display_name)
+    .execute(&pool)
+    .await
+    {
+        Ok(_) => {
+            touch_machine(&pool, &changes, machine_id).await;
+            tracing::info!("Display name set for machine ID: {} ({})", machine_id, locale);
+            Ok(Json(MachineDisplayName {
+                locale,
+                display_name: payload.display_name,
+            }))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to set display name".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/machines/{id}/display-names/{locale}
+pub async fn delete_display_name(
+    _admin: RequireRole<roles::Admin>,
+    Path((machine_id, locale)): Path<(i64, String)>,
+    State(pool): State<DbPool>,
+    State(changes): State<MachineChanges>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Delete display name request received for machine ID: {} ({})", machine_id, locale);
+    match sqlx::query("DELETE FROM machine_display_names WHERE machine_id = $1 AND locale = $2")
+        .bind(machine_id)
+        .bind(locale.trim().to_lowercase())
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Display name not found".to_string(),
+        }))),
+        Ok(_) => {
+            touch_machine(&pool, &changes, machine_id).await;
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to delete display name".to_string(),
+        }))),
+    }
+}
+
+// Marks a machine's configuration as changed so cached views and long-polls pick it up
+async fn touch_machine(pool: &DbPool, changes: &MachineChanges, machine_id: i64) {
+    let timestamp = current_timestamp();
+    let _ = sqlx::query("UPDATE machines SET updated_at = $1 WHERE id = $2")
+        .bind(timestamp)
+        .bind(machine_id)
+        .execute(pool)
+        .await;
+    changes.notify(timestamp);
+}
+
+// GET /api/machines/{id}/documents
+pub async fn list_documents(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<DocumentListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, MachineDocumentRecord>(
+        "SELECT * FROM machine_documents WHERE machine_id = $1 ORDER BY doc_type, title"
+    )
+    .bind(machine_id)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(documents) => Ok(Json(DocumentListResponse {
+            documents: documents.into_iter().map(MachineDocument::from).collect(),
+        })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/{id}/documents
+pub async fn create_document(
+    admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateDocumentRequest>,
+) -> Result<(StatusCode, Json<MachineDocument>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create document request received for machine ID: {}", machine_id);
+    if !DOCUMENT_TYPES.contains(&payload.doc_type.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid doc_type. Must be one of: {}", DOCUMENT_TYPES.join(", ")),
+        })));
+    }
+
+    // Check if machine exists
+    if sqlx::query("SELECT id FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_one(&pool)
+        .await
+        .is_err()
+    {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, MachineDocumentRecord>(
+        "INSERT INTO machine_documents (machine_id, title, doc_type, url, created_by, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"
+    )
+    .bind(machine_id)
+    .bind(&payload.title)
+    .bind(&payload.doc_type)
+    .bind(&payload.url)
+    .bind(&admin.username)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(document) => {
+            tracing::info!("Document created successfully: {}", payload.title);
+            Ok((StatusCode::CREATED, Json(document.into())))
+        },
+        Err(_) => {
+            tracing::error!("Failed to create document: {}", payload.title);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to create document".to_string(),
+            })))
+        },
+    }
+}
+
+// PUT /api/documents/{id}
+pub async fn update_document(
+    _admin: RequireRole<roles::Admin>,
+    Path(document_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateDocumentRequest>,
+) -> Result<Json<MachineDocument>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update document request received for document ID: {}", document_id);
+    if let Some(doc_type) = &payload.doc_type
+        && !DOCUMENT_TYPES.contains(&doc_type.as_str())
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid doc_type. Must be one of: {}", DOCUMENT_TYPES.join(", ")),
+        })));
+    }
+
+    match sqlx::query_as::<_, MachineDocumentRecord>(
+        "UPDATE machine_documents SET title = COALESCE($1, title), doc_type = COALESCE($2, doc_type), \
+         url = COALESCE($3, url) WHERE id = $4 RETURNING *"
+    )
+    .bind(&payload.title)
+    .bind(&payload.doc_type)
+    .bind(&payload.url)
+    .bind(document_id)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(document)) => {
+            tracing::info!("Document updated successfully: {}", document_id);
+            Ok(Json(document.into()))
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Document not found".to_string(),
+        }))),
+        Err(_) => {
+            tracing::error!("Failed to update document: {}", document_id);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to update document".to_string(),
+            })))
+        },
+    }
+}
+
+// DELETE /api/documents/{id}
+pub async fn delete_document(
+    _admin: RequireRole<roles::Admin>,
+    Path(document_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Delete document request received for document ID: {}", document_id);
+    match sqlx::query_scalar::<_, Option<String>>("DELETE FROM machine_documents WHERE id = $1 RETURNING file_path")
+        .bind(document_id)
+        .fetch_optional(&pool)
+        .await
+    {
+        Ok(Some(file_path)) => {
+            if let Some(file_path) = file_path {
+                let _ = tokio::fs::remove_file(file_path).await;
+            }
+            tracing::info!("Document deleted successfully: {}", document_id);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Document not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to delete document".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/documents/{id}/file
+#[derive(Deserialize)]
+pub struct DocumentUploadQuery {
+    file_name: String,
+}
+
+pub async fn upload_document_file(
+    headers: HeaderMap,
+    _admin: RequireRole<roles::Admin>,
+    Path(document_id): Path<i64>,
+    Query(params): Query<DocumentUploadQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    body: axum::body::Bytes,
+) -> Result<Json<MachineDocument>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Upload document file request received for document ID: {}", document_id);
+    if body.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "File must not be empty".to_string(),
+        })));
+    }
+
+    // Check if document exists
+    if sqlx::query("SELECT id FROM machine_documents WHERE id = $1")
+        .bind(document_id)
+        .fetch_one(&pool)
+        .await
+        .is_err()
+    {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Document not found".to_string(),
+        })));
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    // Files are stored under the document id; the original name is only kept for downloads
+    let file_path = config.document_dir.join(document_id.to_string());
+    let written = match tokio::fs::create_dir_all(&config.document_dir).await {
+        Ok(_) => tokio::fs::write(&file_path, &body).await,
+        Err(e) => Err(e),
+    };
+    if written.is_err() {
+        tracing::error!("Failed to store document file: {}", document_id);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to store file".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, MachineDocumentRecord>(
+        "UPDATE machine_documents SET file_path = $1, file_name = $2, content_type = $3 WHERE id = $4 RETURNING *"
+    )
+    .bind(file_path.to_string_lossy().to_string())
+    .bind(&params.file_name)
+    .bind(&content_type)
+    .bind(document_id)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(document) => {
+            tracing::info!("Document file uploaded successfully: {}", document_id);
+            Ok(Json(document.into()))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to update document".to_string(),
+        }))),
+    }
+}
+
+// GET /api/documents/{id}/file
+pub async fn download_document_file(
+    user: AuthUser,
+    Path(document_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine_of(&pool, &user, "machine_documents", document_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Document file not found".to_string(),
+        })));
+    }
+    let document = sqlx::query_as::<_, MachineDocumentRecord>("SELECT * FROM machine_documents WHERE id = $1")
+        .bind(document_id)
+        .fetch_optional(&pool)
+        .await
+        .ok()
+        .flatten();
+
+    let Some(MachineDocumentRecord { file_path: Some(file_path), file_name, content_type, .. }) = document else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Document file not found".to_string(),
+        })));
+    };
+
+    match tokio::fs::read(&file_path).await {
+        Ok(contents) => Ok((
+            [
+                (header::CONTENT_TYPE, content_type.unwrap_or_else(|| "application/octet-stream".to_string())),
+                (header::CONTENT_DISPOSITION, format!(
+                    "inline; filename=\"{}\"",
+                    file_name.unwrap_or_else(|| document_id.to_string()).replace('"', "")
+                )),
+            ],
+            contents,
+        ).into_response()),
+        Err(_) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Document file not found".to_string(),
+        }))),
+    }
+}
+
+// Helper function to load a work order of a machine the user may see together with its checklist
+async fn fetch_work_order_detail(
+    pool: &DbPool,
+    user: &AuthUser,
+    work_order_id: i64,
+) -> Result<WorkOrderDetailResponse, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine_of(pool, user, "work_orders", work_order_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Work order not found".to_string(),
+        })));
+    }
+    let work_order = match sqlx::query_as::<_, WorkOrder>("SELECT * FROM work_orders WHERE id = $1")
+        .bind(work_order_id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(Some(work_order)) => work_order,
+        Ok(None) => {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Work order not found".to_string(),
+            })));
+        },
+        Err(_) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })));
+        },
+    };
+
+    match sqlx::query_as::<_, WorkOrderStep>(
+        "SELECT id, position, text, required, completed_by, completed_at, notes \
+         FROM work_order_steps WHERE work_order_id = $1 ORDER BY position"
+    )
+    .bind(work_order_id)
+    .fetch_all(pool)
+    .await
+    {
+        Ok(checklist) => Ok(WorkOrderDetailResponse { work_order, checklist }),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// Helper function to copy a checklist template's steps onto a work order
+async fn attach_checklist_template(pool: &DbPool, work_order_id: i64, template_id: i64) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let steps = sqlx::query_as::<_, ChecklistTemplateStep>(
+        "SELECT text, required FROM checklist_template_steps WHERE template_id = $1 ORDER BY position"
+    )
+    .bind(template_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() })))?;
+
+    if steps.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Checklist template not found".to_string(),
+        })));
+    }
+
+    // Appended after any existing steps so several templates can be combined
+    let offset: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(position), 0) FROM work_order_steps WHERE work_order_id = $1")
+        .bind(work_order_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() })))?;
+
+    let mut query_builder: QueryBuilder<Db> =
+        QueryBuilder::new("INSERT INTO work_order_steps (work_order_id, position, text, required) ");
+    query_builder.push_values(steps.iter().enumerate(), |mut row, (index, step)| {
+        row.push_bind(work_order_id)
+            .push_bind(offset + index as i64 + 1)
+            .push_bind(&step.text)
+            .push_bind(step.required);
+    });
+
+    query_builder
+        .build()
+        .execute(pool)
+        .await
+        .map(|_| ())
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Failed to attach checklist".to_string() })))
+}
+
+// Helper function to check that a work order requiring a skill goes to a holder of a valid
+// certification. Depending on SCADA_CERTIFICATION_POLICY an unqualified assignee is refused or
+// let through with a notification.
+async fn check_assignee_qualified(
+    pool: &DbPool,
+    config: &Config,
+    machine_id: i64,
+    assigned_to: Option<&str>,
+    required_skill: Option<&str>,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let (Some(assigned_to), Some(skill)) = (assigned_to, required_skill) else {
+        return Ok(());
+    };
+    let qualified = certifications::is_qualified(pool, assigned_to, skill)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() })))?;
+    if qualified {
+        return Ok(());
+    }
+
+    let message = format!("{} holds no valid {} certification", assigned_to, skill);
+    match config.certification_policy {
+        CertificationPolicy::Enforce => Err((StatusCode::CONFLICT, Json(ErrorResponse { error: message }))),
+        CertificationPolicy::Warn => {
+            tracing::warn!("Work order on machine ID {} assigned although {}", machine_id, message);
+            if let Err(e) = notifications::notify(
+                pool,
+                "unqualified_assignment",
+                Some(machine_id),
+                &format!("A work order needing {} was assigned although {}", skill, message),
+            )
+            .await
+            {
+                tracing::error!("Failed to record unqualified assignment notification: {}", e);
+            }
+            Ok(())
+        },
+    }
+}
+
+fn validate_required_skill(required_skill: Option<&str>) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    match required_skill {
+        Some(skill) if !certifications::is_valid_skill(skill) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "required_skill must be lowercase letters, digits, '-' or '_' (at most 40)".to_string(),
+        }))),
+        _ => Ok(()),
+    }
+}
+
+// POST /api/machines/{id}/work-orders
+pub async fn create_work_order(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<CreateWorkOrderRequest>,
+) -> Result<(StatusCode, Json<WorkOrderDetailResponse>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create work order request received for machine ID: {}", machine_id);
+    let priority = payload.priority.unwrap_or_else(|| "normal".to_string());
+    if !PRIORITIES.contains(&priority.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid priority. Must be one of: {}", PRIORITIES.join(", ")),
+        })));
+    }
+    validate_required_skill(payload.required_skill.as_deref())?;
+
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+    check_assignee_qualified(&pool, &config, machine_id, payload.assigned_to.as_deref(), payload.required_skill.as_deref()).await?;
+
+    let timestamp = current_timestamp();
+    let work_order_id = match sqlx::query_scalar::<_, i64>(
+        "INSERT INTO work_orders (machine_id, title, description, priority, category_id, assigned_to, required_skill, \
+         created_by, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id"
+    )
+    .bind(machine_id)
+    .bind(&payload.title)
+    .bind(&payload.description)
+    .bind(&priority)
+    .bind(payload.category_id)
+    .bind(&payload.assigned_to)
+    .bind(&payload.required_skill)
+    .bind(&user.username)
+    .bind(timestamp)
+    .bind(timestamp)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(id) => id,
+        Err(_) => {
+            tracing::error!("Failed to create work order for machine ID: {}", machine_id);
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "Failed to create work order".to_string(),
+            })));
+        },
+    };
+
+    if let Some(template_id) = payload.checklist_template_id {
+        attach_checklist_template(&pool, work_order_id, template_id).await?;
+    }
+
+    tracing::info!("Work order {} created for machine ID: {}", work_order_id, machine_id);
+    Ok((StatusCode::CREATED, Json(fetch_work_order_detail(&pool, &user, work_order_id).await?)))
+}
+
+// GET /api/machines/{id}/work-orders
+#[derive(Deserialize)]
+pub struct WorkOrderListQuery {
+    status: Option<String>,
+}
+
+pub async fn list_work_orders(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<WorkOrderListQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<WorkOrderListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, WorkOrder>(
+        "SELECT * FROM work_orders WHERE machine_id = $1 AND ($2 IS NULL OR status = $3) ORDER BY created_at DESC"
+    )
+    .bind(machine_id)
+    .bind(&params.status)
+    .bind(&params.status)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(work_orders) => Ok(Json(WorkOrderListResponse { work_orders })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/work-orders/{id}
+pub async fn get_work_order(
+    user: AuthUser,
+    Path(work_order_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<WorkOrderDetailResponse>, (StatusCode, Json<ErrorResponse>)> {
+    Ok(Json(fetch_work_order_detail(&pool, &user, work_order_id).await?))
+}
+
+// PUT /api/work-orders/{id}
+pub async fn update_work_order(
+    user: AuthUser,
+    Path(work_order_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<UpdateWorkOrderRequest>,
+) -> Result<Json<WorkOrderDetailResponse>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update work order request received for work order ID: {}", work_order_id);
+    if let Some(priority) = &payload.priority
+        && !PRIORITIES.contains(&priority.as_str())
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid priority. Must be one of: {}", PRIORITIES.join(", ")),
+        })));
+    }
+
+    // Completion goes through sign-off so required checklist steps are enforced
+    if let Some(status) = &payload.status
+        && (status == "completed" || !WORK_ORDER_STATUSES.contains(&status.as_str()))
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Invalid status. Must be one of: open, in_progress, cancelled (use sign-off to complete)".to_string(),
+        })));
+    }
+    validate_required_skill(payload.required_skill.as_deref())?;
+
+    // Qualification is checked when the assignee or the required skill changes
+    if payload.assigned_to.is_some() || payload.required_skill.is_some() {
+        let current = fetch_work_order_detail(&pool, &user, work_order_id).await?.work_order;
+        check_assignee_qualified(
+            &pool,
+            &config,
+            current.machine_id,
+            payload.assigned_to.as_deref().or(current.assigned_to.as_deref()),
+            payload.required_skill.as_deref().or(current.required_skill.as_deref()),
+        )
+        .await?;
+    }
+
+    match sqlx::query(
+        "UPDATE work_orders SET title = COALESCE($1, title), description = COALESCE($2, description), \
+         priority = COALESCE($3, priority), status = COALESCE($4, status), category_id = COALESCE($5, category_id), \
+         assigned_to = COALESCE($6, assigned_to), required_skill = COALESCE($7, required_skill), updated_at = $8 \
+         WHERE id = $9 AND status != 'completed'"
+    )
+    .bind(&payload.title)
+    .bind(&payload.description)
+    .bind(&payload.priority)
+    .bind(&payload.status)
+    .bind(payload.category_id)
+    .bind(&payload.assigned_to)
+    .bind(&payload.required_skill)
+    .bind(current_timestamp())
+    .bind(work_order_id)
+    .execute(&pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => {
+            // Either missing or already signed off; the fetch reports a missing order as 404
+            fetch_work_order_detail(&pool, &user, work_order_id).await?;
+            Err((StatusCode::CONFLICT, Json(ErrorResponse {
+                error: "Completed work orders cannot be changed".to_string(),
+            })))
+        },
+        Ok(_) => {
+            tracing::info!("Work order updated successfully: {}", work_order_id);
+            Ok(Json(fetch_work_order_detail(&pool, &user, work_order_id).await?))
+        },
+        Err(_) => {
+            tracing::error!("Failed to update work order: {}", work_order_id);
+            Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "Failed to update work order".to_string(),
+            })))
+        },
+    }
+}
+
+// POST /api/work-orders/{id}/checklist
+pub async fn attach_work_order_checklist(
+    user: AuthUser,
+    Path(work_order_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<AttachChecklistRequest>,
+) -> Result<Json<WorkOrderDetailResponse>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Attach checklist request received for work order ID: {}", work_order_id);
+    let detail = fetch_work_order_detail(&pool, &user, work_order_id).await?;
+    if detail.work_order.status == "completed" {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Completed work orders cannot be changed".to_string(),
+        })));
+    }
+
+    attach_checklist_template(&pool, work_order_id, payload.template_id).await?;
+    let _ = sqlx::query("UPDATE work_orders SET updated_at = $1 WHERE id = $2")
+        .bind(current_timestamp())
+        .bind(work_order_id)
+        .execute(&pool)
+        .await;
+
+    Ok(Json(fetch_work_order_detail(&pool, &user, work_order_id).await?))
+}
+
+// PUT /api/work-orders/{id}/steps/{step_id}
+pub async fn update_work_order_step(
+    user: AuthUser,
+    Path((work_order_id, step_id)): Path<(i64, i64)>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateWorkOrderStepRequest>,
+) -> Result<Json<WorkOrderDetailResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let detail = fetch_work_order_detail(&pool, &user, work_order_id).await?;
+    if detail.work_order.status == "completed" {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Completed work orders cannot be changed".to_string(),
+        })));
+    }
+
+    let (completed_by, completed_at) = if payload.completed {
+        (Some(user.username.clone()), Some(current_timestamp()))
+    } else {
+        (None, None)
+    };
+
+    match sqlx::query(
+        "UPDATE work_order_steps SET completed_by = $1, completed_at = $2, notes = COALESCE($3, notes) \
+         WHERE id = $4 AND work_order_id = $5"
+    )
+    .bind(&completed_by)
+    .bind(completed_at)
+    .bind(&payload.notes)
+    .bind(step_id)
+    .bind(work_order_id)
+    .execute(&pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Checklist step not found".to_string(),
+        }))),
+        Ok(_) => {
+            let _ = sqlx::query("UPDATE work_orders SET updated_at = $1, status = CASE status WHEN 'open' THEN 'in_progress' ELSE status END WHERE id = $2")
+                .bind(current_timestamp())
+                .bind(work_order_id)
+                .execute(&pool)
+                .await;
+            Ok(Json(fetch_work_order_detail(&pool, &user, work_order_id).await?))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to update checklist step".to_string(),
+        }))),
+    }
+}
+
+// POST /api/work-orders/{id}/sign-off
+pub async fn sign_off_work_order(
+    user: AuthUser,
+    Path(work_order_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<WorkOrderDetailResponse>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Sign-off request received for work order ID: {}", work_order_id);
+    let detail = fetch_work_order_detail(&pool, &user, work_order_id).await?;
+    if matches!(detail.work_order.status.as_str(), "completed" | "cancelled") {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: format!("Work order is already {}", detail.work_order.status),
+        })));
+    }
+
+    let outstanding: Vec<&str> = detail
+        .checklist
+        .iter()
+        .filter(|step| step.required && step.completed_at.is_none())
+        .map(|step| step.text.as_str())
+        .collect();
+    if !outstanding.is_empty() {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: format!("Required checklist steps not completed: {}", outstanding.join("; ")),
+        })));
+    }
+
+    let timestamp = current_timestamp();
+    match sqlx::query(
+        "UPDATE work_orders SET status = 'completed', signed_off_by = $1, signed_off_at = $2, updated_at = $3 WHERE id = $4"
+    )
+    .bind(&user.username)
+    .bind(timestamp)
+    .bind(timestamp)
+    .bind(work_order_id)
+    .execute(&pool)
+    .await
+    {
+        Ok(_) => {
+            // Nobody keeps booking time on a closed work order
+            let _ = sqlx::query("UPDATE work_order_labor SET stopped_at = $1 WHERE work_order_id = $2 AND stopped_at IS NULL")
+                .bind(timestamp)
+                .bind(work_order_id)
+                .execute(&pool)
+                .await;
+            tracing::info!("Work order {} signed off by {}", work_order_id, user.username);
+            Ok(Json(fetch_work_order_detail(&pool, &user, work_order_id).await?))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to sign off work order".to_string(),
+        }))),
+    }
+}
+
+// GET /api/work-orders/{id}/permits
+pub async fn list_work_permits(
+    user: AuthUser,
+    Path(work_order_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<WorkPermitListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    fetch_work_order_detail(&pool, &user, work_order_id).await?;
+
+    match sqlx::query("SELECT * FROM work_permits WHERE work_order_id = $1 ORDER BY requested_at, id")
+        .bind(work_order_id)
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(rows) => Ok(Json(WorkPermitListResponse { permits: rows.iter().map(permits::permit_from_row).collect() })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/work-orders/{id}/permits
+pub async fn request_work_permit(
+    user: AuthUser,
+    Path(work_order_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateWorkPermitRequest>,
+) -> Result<(StatusCode, Json<WorkPermit>), (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    if !permits::PERMIT_TYPES.contains(&payload.permit_type.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid permit_type. Must be one of: {}", permits::PERMIT_TYPES.join(", ")),
+        })));
+    }
+    if payload.valid_until <= payload.valid_from {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "valid_until must be after valid_from".to_string(),
+        })));
+    }
+    let isolation_points: Vec<&str> = payload.isolation_points.iter().map(|point| point.trim()).collect();
+    if isolation_points.iter().any(|point| point.is_empty()) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Isolation points must not be empty".to_string(),
+        })));
+    }
+
+    let work_order = fetch_work_order_detail(&pool, &user, work_order_id).await?.work_order;
+    if matches!(work_order.status.as_str(), "completed" | "cancelled") {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: format!("Work order is already {}", work_order.status),
+        })));
+    }
+
+    let permit_id: i64 = sqlx::query_scalar(
+        "INSERT INTO work_permits (work_order_id, machine_id, permit_type, description, isolation_points, \
+         valid_from, valid_until, requested_by, requested_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id"
+    )
+    .bind(work_order_id)
+    .bind(work_order.machine_id)
+    .bind(&payload.permit_type)
+    .bind(&payload.description)
+    .bind(serde_json::to_string(&isolation_points).unwrap_or_else(|_| "[]".to_string()))
+    .bind(payload.valid_from)
+    .bind(payload.valid_until)
+    .bind(&user.username)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    .map_err(database_error)?;
+
+    tracing::info!("{} requested a {} permit for work order {}", user.username, payload.permit_type, work_order_id);
+    match permits::fetch(&pool, permit_id).await.map_err(database_error)? {
+        Some(permit) => Ok((StatusCode::CREATED, Json(permit))),
+        None => Err(database_error(sqlx::Error::RowNotFound)),
+    }
+}
+
+// POST /api/permits/{id}/approve
+// The approver signs with their name and re-enters their password, so a permit can't be
+// approved from a session left open on a shared terminal
+pub async fn approve_work_permit(
+    manager: RequireRole<roles::Manager>,
+    Path(permit_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<ApproveWorkPermitRequest>,
+) -> Result<Json<WorkPermit>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    if manager.impersonated_by.is_some() {
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: "Not allowed while impersonating".to_string(),
+        })));
+    }
+    let signature = payload.signature.trim();
+    if signature.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Signature is required".to_string(),
+        })));
+    }
+
+    let Some(permit) = permits::fetch(&pool, permit_id).await.map_err(database_error)? else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Permit not found".to_string(),
+        })));
+    };
+    if permit.status != "requested" {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: format!("Permit is already {}", permit.status),
+        })));
+    }
+    let now = current_timestamp();
+    if permit.valid_until <= now {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Permit validity window has already passed".to_string(),
+        })));
+    }
+    if auth::authenticate_user(&manager.username, &payload.password, &pool).await.is_none() {
+        tracing::warn!("Permit {} approval refused, wrong password for user: {}", permit_id, manager.username);
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: "Password is incorrect".to_string(),
+        })));
+    }
+
+    sqlx::query(
+        "UPDATE work_permits SET status = 'approved', approved_by = $1, approved_at = $2, approval_signature = $3 \
+         WHERE id = $4 AND status = 'requested'"
+    )
+    .bind(&manager.username)
+    .bind(now)
+    .bind(signature)
+    .bind(permit_id)
+    .execute(&pool)
+    .await
+    .map_err(database_error)?;
+
+    tracing::info!("Permit {} approved by {}", permit_id, manager.username);
+    match permits::fetch(&pool, permit_id).await.map_err(database_error)? {
+        Some(permit) => Ok(Json(permit)),
+        None => Err(database_error(sqlx::Error::RowNotFound)),
+    }
+}
+
+// POST /api/permits/{id}/close
+// Hands the machine back; a permit still awaiting approval is withdrawn
+pub async fn close_work_permit(
+    user: AuthUser,
+    Path(permit_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<WorkPermit>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    if !access::can_see_machine_of(&pool, &user, "work_permits", permit_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Permit not found".to_string(),
+        })));
+    }
+    let closed = sqlx::query(
+        "UPDATE work_permits SET status = 'closed', closed_by = $1, closed_at = $2 WHERE id = $3 AND status <> 'closed'"
+    )
+    .bind(&user.username)
+    .bind(current_timestamp())
+    .bind(permit_id)
+    .execute(&pool)
+    .await
+    .map_err(database_error)?;
+
+    match permits::fetch(&pool, permit_id).await.map_err(database_error)? {
+        Some(permit) if closed.rows_affected() > 0 => {
+            tracing::info!("Permit {} closed by {}", permit_id, user.username);
+            Ok(Json(permit))
+        },
+        Some(_) => Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Permit is already closed".to_string(),
+        }))),
+        None => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Permit not found".to_string(),
+        }))),
+    }
+}
+
+// GET /api/work-orders/{id}/labor
+pub async fn list_labor(
+    user: AuthUser,
+    Path(work_order_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<LaborListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    fetch_work_order_detail(&pool, &user, work_order_id).await?;
+
+    match sqlx::query_as::<_, LaborEntry>(
+        "SELECT * FROM work_order_labor WHERE work_order_id = $1 ORDER BY started_at"
+    )
+    .bind(work_order_id)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(entries) => {
+            // Running timers count up to now
+            let now = current_timestamp();
+            let seconds: i64 = entries
+                .iter()
+                .map(|entry| entry.stopped_at.unwrap_or(now) - entry.started_at)
+                .sum();
+            Ok(Json(LaborListResponse {
+                entries,
+                total_hours: seconds as f64 / 3600.0,
+            }))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/work-orders/{id}/labor/start
+pub async fn start_labor(
+    user: AuthUser,
+    Path(work_order_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<(StatusCode, Json<LaborEntry>), (StatusCode, Json<ErrorResponse>)> {
+    let detail = fetch_work_order_detail(&pool, &user, work_order_id).await?;
+    if matches!(detail.work_order.status.as_str(), "completed" | "cancelled") {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: format!("Work order is already {}", detail.work_order.status),
+        })));
+    }
+
+    let running: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM work_order_labor WHERE work_order_id = $1 AND username = $2 AND stopped_at IS NULL"
+    )
+    .bind(work_order_id)
+    .bind(&user.username)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() })))?;
+    if running.is_some() {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Timer already running for this work order".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, LaborEntry>(
+        "INSERT INTO work_order_labor (work_order_id, username, started_at) VALUES ($1, $2, $3) RETURNING *"
+    )
+    .bind(work_order_id)
+    .bind(&user.username)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(entry) => {
+            // Starting work on an open order puts it in progress
+            let _ = sqlx::query("UPDATE work_orders SET status = 'in_progress', updated_at = $1 WHERE id = $2 AND status = 'open'")
+                .bind(current_timestamp())
+                .bind(work_order_id)
+                .execute(&pool)
+                .await;
+            tracing::info!("{} started labor on work order {}", user.username, work_order_id);
+            Ok((StatusCode::CREATED, Json(entry)))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to start timer".to_string(),
+        }))),
+    }
+}
+
+// POST /api/work-orders/{id}/labor/stop
+pub async fn stop_labor(
+    user: AuthUser,
+    Path(work_order_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<LaborEntry>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine_of(&pool, &user, "work_orders", work_order_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Work order not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, LaborEntry>(
+        "UPDATE work_order_labor SET stopped_at = $1 \
+         WHERE work_order_id = $2 AND username = $3 AND stopped_at IS NULL RETURNING *"
+    )
+    .bind(current_timestamp())
+    .bind(work_order_id)
+    .bind(&user.username)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(entry)) => {
+            tracing::info!("{} stopped labor on work order {}", user.username, work_order_id);
+            Ok(Json(entry))
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "No running timer for this work order".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to stop timer".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/{id}/costs
+pub async fn create_cost(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateCostRequest>,
+) -> Result<(StatusCode, Json<CostEntry>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create cost entry request received for machine ID: {}", machine_id);
+    if !payload.amount.is_finite() || payload.amount < 0.0 {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Amount must be a non-negative number".to_string(),
+        })));
+    }
+
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    if let Some(work_order_id) = payload.work_order_id
+        && sqlx::query("SELECT id FROM work_orders WHERE id = $1 AND machine_id = $2")
+            .bind(work_order_id)
+            .bind(machine_id)
+            .fetch_one(&pool)
+            .await
+            .is_err()
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Work order does not belong to this machine".to_string(),
+        })));
+    }
+
+    let timestamp = current_timestamp();
+    match sqlx::query_as::<_, CostEntry>(
+        "INSERT INTO maintenance_costs (machine_id, work_order_id, description, amount, incurred_at, created_by, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *"
+    )
+    .bind(machine_id)
+    .bind(payload.work_order_id)
+    .bind(&payload.description)
+    .bind(payload.amount)
+    .bind(payload.incurred_at.unwrap_or(timestamp))
+    .bind(&user.username)
+    .bind(timestamp)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(entry) => {
+            tracing::info!("Cost entry {} added for machine ID: {}", entry.id, machine_id);
+            Ok((StatusCode::CREATED, Json(entry)))
+        },
+        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Failed to add cost entry".to_string(),
+        }))),
+    }
+}
+
+// GET /api/machines/{id}/costs
+#[derive(Deserialize)]
+pub struct MachineCostQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+    period: Option<String>,
+}
+
+pub async fn get_machine_costs(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<MachineCostQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<MachineCostResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let period_format = match params.period.as_deref().unwrap_or("month") {
+        "month" => "%Y-%m",
+        "year" => "%Y",
+        _ => {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: format!("Invalid period. Must be one of: {}", COST_PERIODS.join(", ")),
+            })));
+        },
+    };
+
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    let to = params.to.unwrap_or_else(current_timestamp);
+    let from = params.from.unwrap_or(to - 365 * 24 * 60 * 60);
+
+    // Labor is costed from finished time logs, parts from the recorded cost entries
+    let labor = sqlx::query_as::<_, (String, f64)>(&format!(
+        "SELECT {} AS period, CAST(SUM(l.stopped_at - l.started_at) AS DOUBLE PRECISION) / 3600 \
+         FROM work_order_labor l JOIN work_orders w ON w.id = l.work_order_id \
+         WHERE w.machine_id = $1 AND l.stopped_at IS NOT NULL AND l.started_at >= $2 AND l.started_at <= $3 \
+         GROUP BY period",
+        database::format_date(&timerange::local_time_sql("l.started_at", from, to, &config), period_format)
+    ))
+    .bind(machine_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(&pool)
+    .await;
+
+    let parts = sqlx::query_as::<_, (String, f64)>(&format!(
+        "SELECT {} AS period, SUM(amount) \
+         FROM maintenance_costs WHERE machine_id = $1 AND incurred_at >= $2 AND incurred_at <= $3 \
+         GROUP BY period",
+        database::format_date(&timerange::local_time_sql("incurred_at", from, to, &config), period_format)
+    ))
+    .bind(machine_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(&pool)
+    .await;
+
+    let (labor, parts) = match (labor, parts) {
+        (Ok(labor), Ok(parts)) => (labor, parts),
+        _ => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })));
+        },
+    };
+
+    let mut periods: std::collections::BTreeMap<String, CostSummary> = std::collections::BTreeMap::new();
+    for (period, hours) in labor {
+        periods.entry(period).or_default().labor_hours = hours;
+    }
+    for (period, amount) in parts {
+        periods.entry(period).or_default().parts_cost = amount;
+    }
+
+    let mut total = CostSummary::default();
+    let periods = periods
+        .into_iter()
+        .map(|(period, mut summary)| {
+            summary.labor_cost = summary.labor_hours * config.labor_rate;
+            summary.total_cost = summary.labor_cost + summary.parts_cost;
+            total.labor_hours += summary.labor_hours;
+            total.labor_cost += summary.labor_cost;
+            total.parts_cost += summary.parts_cost;
+            total.total_cost += summary.total_cost;
+            CostPeriod { period, summary }
+        })
+        .collect();
+
+    Ok(Json(MachineCostResponse {
+        machine_id,
+        from,
+        to,
+        labor_rate: config.labor_rate,
+        periods,
+        total,
+    }))
+}
+
+// GET /api/machines/{id}/contracts
+pub async fn list_contracts(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<ContractListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, MachineContract>(
+        "SELECT * FROM machine_contracts WHERE machine_id = $1 ORDER BY expires_at"
+    )
+    .bind(machine_id)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(contracts) => Ok(Json(ContractListResponse { contracts })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/{id}/contracts
+pub async fn create_contract(
+    _admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateContractRequest>,
+) -> Result<(StatusCode, Json<MachineContract>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create contract request received for machine ID: {}", machine_id);
+    if !CONTRACT_KINDS.contains(&payload.kind.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid kind. Must be one of: {}", CONTRACT_KINDS.join(", ")),
+        })));
+    }
+
+    // Check if machine exists
+    if sqlx::query("SELECT id FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_one(&pool)
+        .await
+        .is_err()
+    {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query_as::<_, MachineContract>(
+        "INSERT INTO machine_contracts (machine_id, kind, vendor, reference, coverage, starts_at, expires_at, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *"
+    )
+    .bind(machine_id)
+    .bind(&payload.kind)
+    .bind(&payload.vendor)
+    .bind(&payload.reference)
+    .bind(&payload.coverage)
+    .bind(payload.starts_at)
+    .bind(payload.expires_at)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(contract) => {
+            tracing::info!("Contract {} created for machine ID: {}", contract.id, machine_id);
+            Ok((StatusCode::CREATED, Json(contract)))
+        },
+        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Failed to create contract".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/contracts/{id}
+pub async fn update_contract(
+    _admin: RequireRole<roles::Admin>,
+    Path(contract_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateContractRequest>,
+) -> Result<Json<MachineContract>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update contract request received for contract ID: {}", contract_id);
+    // A renewed expiry date gets its own notice
+    match sqlx::query_as::<_, MachineContract>(
+        "UPDATE machine_contracts SET vendor = COALESCE($1, vendor), reference = COALESCE($2, reference), \
+         coverage = COALESCE($3, coverage), starts_at = COALESCE($4, starts_at), \
+         notified_at = CASE WHEN $5 IS NULL OR $6 = expires_at THEN notified_at END, \
+         expires_at = COALESCE($7, expires_at) WHERE id = $8 RETURNING *"
+    )
+    .bind(&payload.vendor)
+    .bind(&payload.reference)
+    .bind(&payload.coverage)
+    .bind(payload.starts_at)
+    .bind(payload.expires_at)
+    .bind(payload.expires_at)
+    .bind(payload.expires_at)
+    .bind(contract_id)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(contract)) => {
+            tracing::info!("Contract updated successfully: {}", contract_id);
+            Ok(Json(contract))
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Contract not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Failed to update contract".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/contracts/{id}
+pub async fn delete_contract(
+    _admin: RequireRole<roles::Admin>,
+    Path(contract_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Delete contract request received for contract ID: {}", contract_id);
+    match sqlx::query("DELETE FROM machine_contracts WHERE id = $1")
+        .bind(contract_id)
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Contract not found".to_string(),
+        }))),
+        Ok(_) => {
+            tracing::info!("Contract deleted successfully: {}", contract_id);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to delete contract".to_string(),
+        }))),
+    }
+}
+
+// GET /api/reports/expired-contracts
+#[derive(Deserialize)]
+pub struct ExpiredContractQuery {
+    as_of: Option<i64>,
+    kind: Option<String>,
+    vendor: Option<String>,
+    location: Option<String>,
+    machine_type: Option<String>,
+}
+
+pub async fn expired_contracts_report(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<ExpiredContractQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<ExpiredContractReportResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let as_of = params.as_of.unwrap_or_else(current_timestamp);
+    let plant_name = load_plant_name(&pool).await?;
+
+    // A lapsed contract no longer counts once a contract of the same kind covers the machine again
+    match sqlx::query_as::<_, ExpiredContractEntry>(
+        "SELECT m.id AS machine_id, m.name AS machine_name, m.location, m.machine_type, \
+         c.id AS contract_id, c.kind, c.vendor, c.expires_at \
+         FROM machine_contracts c JOIN machines m ON m.id = c.machine_id \
+         WHERE c.expires_at <= $1 \
+         AND NOT EXISTS (SELECT 1 FROM machine_contracts r WHERE r.machine_id = c.machine_id \
+             AND r.kind = c.kind AND r.expires_at > $2) \
+         AND ($3 IS NULL OR c.kind = $4) AND ($5 IS NULL OR c.vendor = $6) \
+         AND ($7 IS NULL OR m.location = $8) AND ($9 IS NULL OR m.machine_type = $10) \
+         ORDER BY c.expires_at"
+    )
+    .bind(as_of)
+    .bind(as_of)
+    .bind(&params.kind)
+    .bind(&params.kind)
+    .bind(&params.vendor)
+    .bind(&params.vendor)
+    .bind(&params.location)
+    .bind(&params.location)
+    .bind(&params.machine_type)
+    .bind(&params.machine_type)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(contracts) => Ok(Json(ExpiredContractReportResponse { plant_name, as_of, contracts })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CommandTypeQuery {
+    machine_type: Option<String>,
+}
+
+// GET /api/command-types
+pub async fn list_command_types(
+    _user: AuthUser,
+    Query(params): Query<CommandTypeQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<CommandTypeListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query("SELECT * FROM command_types WHERE $1 IS NULL OR machine_type = $2 ORDER BY machine_type, name")
+        .bind(&params.machine_type)
+        .bind(&params.machine_type)
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(rows) => Ok(Json(CommandTypeListResponse {
+            command_types: rows.iter().map(commands::command_type_from_row).collect(),
+        })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+fn validate_min_role(role: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if !USER_ROLES.contains(&role) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid min_role. Must be one of: {}", USER_ROLES.join(", ")),
+        })));
+    }
+    Ok(())
+}
+
+// POST /api/command-types
+pub async fn create_command_type(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateCommandTypeRequest>,
+) -> Result<(StatusCode, Json<CommandType>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create command type request received: {} for {}", payload.name, payload.machine_type);
+    if payload.machine_type.trim().is_empty() || payload.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "machine_type and name must not be empty".to_string(),
+        })));
+    }
+    commands::compile_schema(&payload.parameters_schema)
+        .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
+    let min_role = payload.min_role.as_deref().unwrap_or(Role::Manager.as_str());
+    validate_min_role(min_role)?;
+
+    let timestamp = current_timestamp();
+    match sqlx::query(
+        "INSERT INTO command_types (machine_type, name, description, parameters_schema, min_role, allowed_during_lockout, created_at, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *"
+    )
+    .bind(&payload.machine_type)
+    .bind(&payload.name)
+    .bind(&payload.description)
+    .bind(payload.parameters_schema.to_string())
+    .bind(min_role)
+    .bind(payload.allowed_during_lockout)
+    .bind(timestamp)
+    .bind(timestamp)
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(row) => {
+            let command_type = commands::command_type_from_row(&row);
+            tracing::info!("Command type {} created for machine type: {}", command_type.id, command_type.machine_type);
+            Ok((StatusCode::CREATED, Json(command_type)))
+        },
+        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Command type already exists for this machine type".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/command-types/{id}
+pub async fn update_command_type(
+    admin: RequireRole<roles::Admin>,
+    Path(command_type_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateCommandTypeRequest>,
+) -> Result<Json<CommandType>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update command type request received for command type ID: {}", command_type_id);
+    if let Some(schema) = &payload.parameters_schema {
+        commands::compile_schema(schema).map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
+    }
+    if let Some(min_role) = &payload.min_role {
+        validate_min_role(min_role)?;
+    }
+
+    // Commands already queued keep the parameters they were validated with
+    match sqlx::query(
+        "UPDATE command_types SET description = COALESCE($1, description), \
+         parameters_schema = COALESCE($2, parameters_schema), min_role = COALESCE($3, min_role), \
+         allowed_during_lockout = COALESCE($4, allowed_during_lockout), updated_at = $5 \
+         WHERE id = $6 RETURNING *"
+    )
+    .bind(&payload.description)
+    .bind(payload.parameters_schema.as_ref().map(|schema| schema.to_string()))
+    .bind(&payload.min_role)
+    .bind(payload.allowed_during_lockout)
+    .bind(current_timestamp())
+    .bind(command_type_id)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(row)) => {
+            let command_type = commands::command_type_from_row(&row);
+            if payload.min_role.is_some() {
+                tracing::info!(
+                    "{} set the role required for {} commands on {} to {}",
+                    admin.username, command_type.name, command_type.machine_type, command_type.min_role,
+                );
+            }
+            if let Some(allowed) = payload.allowed_during_lockout {
+                tracing::info!(
+                    "{} {} {} commands on {} during lockout",
+                    admin.username, if allowed { "allowed" } else { "blocked" }, command_type.name, command_type.machine_type,
+                );
+            }
+            Ok(Json(command_type))
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Command type not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/command-types/{id}
+pub async fn delete_command_type(
+    _admin: RequireRole<roles::Admin>,
+    Path(command_type_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Delete command type request received for command type ID: {}", command_type_id);
+    match sqlx::query("DELETE FROM command_types WHERE id = $1")
+        .bind(command_type_id)
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Command type not found".to_string(),
+        }))),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to delete command type".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/{id}/commands
+pub async fn send_command(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<SendCommandRequest>,
+) -> Result<(StatusCode, Json<MachineCommand>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Command {} requested for machine ID: {}", payload.command_type, machine_id);
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let ttl = payload.ttl_secs.unwrap_or(config.command_ttl.as_secs() as i64);
+    if ttl <= 0 {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "ttl_secs must be positive".to_string(),
+        })));
+    }
+
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    let machine: Option<(Option<String>, Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT machine_type, locked_out_by, lockout_reason FROM machines WHERE id = $1 AND archived_at IS NULL"
+    )
+    .bind(machine_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(database_error)?;
+    let Some((machine_type, locked_out_by, lockout_reason)) = machine else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    };
+
+    let command_type: Option<(String, String, bool)> = match &machine_type {
+        Some(machine_type) => sqlx::query_as(
+            "SELECT parameters_schema, min_role, allowed_during_lockout FROM command_types WHERE machine_type = $1 AND name = $2"
+        )
+        .bind(machine_type)
+        .bind(&payload.command_type)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?,
+        None => None,
+    };
+    let Some((schema, min_role, allowed_during_lockout)) = command_type else {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!(
+                "Unknown command type '{}' for machine type '{}'",
+                payload.command_type,
+                machine_type.as_deref().unwrap_or("none"),
+            ),
+        })));
+    };
+
+    // Each command type names the least privileged role that may send it; refusals are
+    // logged so attempts show up in the audit trail
+    if !min_role.parse::<Role>().is_ok_and(|min_role| user.role >= min_role) {
+        tracing::warn!(
+            "Refused {} command for machine ID {} to {} ({}), requires {}",
+            payload.command_type, machine_id, user.username, user.role.as_str(), min_role,
+        );
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: format!("Sending {} commands requires the {} role", payload.command_type, min_role),
+        })));
+    }
+
+    // A locked-out machine is isolated for work on it; only command types marked safe during
+    // a lockout, such as a stop, may be queued until the lock is released
+    if let Some(locked_out_by) = locked_out_by
+        && !allowed_during_lockout
+    {
+        tracing::warn!(
+            "Refused {} command for locked-out machine ID {} to {}",
+            payload.command_type, machine_id, user.username,
+        );
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: format!(
+                "Machine is locked out by {} ({}); {} commands are blocked until the lockout is released",
+                locked_out_by,
+                lockout_reason.unwrap_or_default(),
+                payload.command_type,
+            ),
+        })));
+    }
+
+    let schema = serde_json::from_str(&schema).unwrap_or(serde_json::Value::Null);
+    if let Err(error) = commands::validate_parameters(&schema, &payload.parameters) {
+        tracing::warn!("Rejected {} command for machine ID {}: {}", payload.command_type, machine_id, error);
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse { error })));
+    }
+
+    let timestamp = current_timestamp();
+    let row = sqlx::query(
+        "INSERT INTO machine_commands (machine_id, command_type, parameters, created_by, created_at, expires_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"
+    )
+    .bind(machine_id)
+    .bind(&payload.command_type)
+    .bind(payload.parameters.to_string())
+    .bind(&user.username)
+    .bind(timestamp)
+    .bind(timestamp.saturating_add(ttl))
+    .fetch_one(&pool)
+    .await
+    .map_err(database_error)?;
+
+    let command = commands::command_from_row(&row);
+    tracing::info!(
+        "Command {} ({}) queued for machine ID {} by {} ({})",
+        command.id, command.command_type, machine_id, user.username, user.role.as_str(),
+    );
+    Ok((StatusCode::CREATED, Json(command)))
+}
+
+#[derive(Deserialize)]
+pub struct CommandListQuery {
+    status: Option<String>,
+    limit: Option<i64>,
+}
+
+// GET /api/machines/{id}/commands
+pub async fn list_machine_commands(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<CommandListQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineCommandListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if let Some(status) = &params.status
+        && !COMMAND_STATUSES.contains(&status.as_str())
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid status. Must be one of: {}", COMMAND_STATUSES.join(", ")),
+        })));
+    }
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+    commands::expire_pending(&pool, machine_id).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))?;
+
+    match sqlx::query(
+        "SELECT * FROM machine_commands WHERE machine_id = $1 AND ($2 IS NULL OR status = $3) \
+         ORDER BY created_at DESC, id DESC LIMIT $4"
+    )
+    .bind(machine_id)
+    .bind(&params.status)
+    .bind(&params.status)
+    .bind(params.limit.unwrap_or(100).clamp(1, 1000))
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(rows) => Ok(Json(MachineCommandListResponse {
+            commands: rows.iter().map(commands::command_from_row).collect(),
+        })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/commands/{id}
+pub async fn cancel_command(
+    manager: RequireRole<roles::Manager>,
+    Path(command_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineCommand>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+
+    // The command stays in the history as cancelled rather than being deleted
+    let row = sqlx::query(
+        "UPDATE machine_commands SET status = 'cancelled', cancelled_by = $1, completed_at = $2 \
+         WHERE id = $3 AND status = 'pending' RETURNING *"
+    )
+    .bind(&manager.username)
+    .bind(current_timestamp())
+    .bind(command_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(database_error)?;
+    if let Some(row) = row {
+        let command = commands::command_from_row(&row);
+        tracing::info!("Command {} for machine ID {} cancelled by {}", command.id, command.machine_id, manager.username);
+        return Ok(Json(command));
+    }
+
+    let status: Option<String> = sqlx::query_scalar("SELECT status FROM machine_commands WHERE id = $1")
+        .bind(command_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?;
+    Err(match status.as_deref() {
+        None => (StatusCode::NOT_FOUND, Json(ErrorResponse { error: "Command not found".to_string() })),
+        Some("delivered") => (StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Command was already delivered to the machine".to_string(),
+        })),
+        Some(status) => (StatusCode::CONFLICT, Json(ErrorResponse {
+            error: format!("Command is already {}", status),
+        })),
+    })
+}
+
+// GET /api/machines/commands
+pub async fn poll_commands(
+    MachineKey { machine_id, .. }: MachineKey<scopes::CommandsRead>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineCommandListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    commands::expire_pending(&pool, machine_id).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))?;
+
+    // Handing a command out marks it delivered, so each one reaches the gateway once. Commands
+    // a lockout blocks are never handed out, even if they were queued before it.
+    match sqlx::query(&format!(
+        "UPDATE machine_commands SET status = 'delivered', delivered_at = $1 \
+         WHERE machine_id = $2 AND status = 'pending' AND {} RETURNING *",
+        lockout::NOT_BLOCKED
+    ))
+    .bind(current_timestamp())
+    .bind(machine_id)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(rows) => {
+            let mut commands: Vec<MachineCommand> = rows.iter().map(commands::command_from_row).collect();
+            commands.sort_by_key(|command| command.id);
+            if !commands.is_empty() {
+                tracing::info!("Delivered {} command(s) to machine ID: {}", commands.len(), machine_id);
+            }
+            Ok(Json(MachineCommandListResponse { commands }))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/commands/{id}/result
+pub async fn report_command_result(
+    MachineKey { machine_id, .. }: MachineKey<scopes::CommandsRead>,
+    Path(command_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CommandResultRequest>,
+) -> Result<Json<MachineCommand>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+
+    let row = sqlx::query(
+        "UPDATE machine_commands SET status = $1, completed_at = $2, result = $3 \
+         WHERE id = $4 AND machine_id = $5 AND status IN ('pending', 'delivered') RETURNING *"
+    )
+    .bind(if payload.success { "completed" } else { "failed" })
+    .bind(current_timestamp())
+    .bind(&payload.result)
+    .bind(command_id)
+    .bind(machine_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(database_error)?;
+
+    match row {
+        Some(row) => {
+            let command = commands::command_from_row(&row);
+            tracing::info!("Command {} on machine ID {} {}", command.id, machine_id, command.status);
+            Ok(Json(command))
+        },
+        None => {
+            let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM machine_commands WHERE id = $1 AND machine_id = $2")
+                .bind(command_id)
+                .bind(machine_id)
+                .fetch_one(&pool)
+                .await
+                .map_err(database_error)?;
+            Err(if exists {
+                (StatusCode::CONFLICT, Json(ErrorResponse { error: "Command already finished".to_string() }))
+            } else {
+                (StatusCode::NOT_FOUND, Json(ErrorResponse { error: "Command not found".to_string() }))
+            })
+        },
+    }
+}
+
+// DELETE /api/machines/{id}
+// Archives the machine instead of deleting it: history, comments and everything else stay,
+// but it leaves listings and its API keys stop working until it is restored
+pub async fn archive_machine(
+    admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(changes): State<MachineChanges>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    set_machine_archived(machine_id, Some(&admin.username), &pool, &changes).await?;
+    tracing::info!("Machine ID {} archived by {}", machine_id, admin.username);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// POST /api/machines/{id}/restore
+pub async fn restore_machine(
+    admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(changes): State<MachineChanges>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    set_machine_archived(machine_id, None, &pool, &changes).await?;
+    tracing::info!("Machine ID {} restored by {}", machine_id, admin.username);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Archives the machine as `archived_by`, or restores it when None
+async fn set_machine_archived(
+    machine_id: i64,
+    archived_by: Option<&str>,
+    pool: &DbPool,
+    changes: &MachineChanges,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let archived: Option<Option<i64>> = sqlx::query_scalar("SELECT archived_at FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(database_error)?;
+    let Some(archived_at) = archived else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    };
+    if archived_at.is_some() == archived_by.is_some() {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: if archived_at.is_some() { "Machine is already archived" } else { "Machine is not archived" }.to_string(),
+        })));
+    }
+
+    let now = current_timestamp();
+    sqlx::query("UPDATE machines SET archived_at = $1, archived_by = $2, updated_at = $3 WHERE id = $4")
+        .bind(archived_by.map(|_| now))
+        .bind(archived_by)
+        .bind(now)
+        .bind(machine_id)
+        .execute(pool)
+        .await
+        .map_err(database_error)?;
+    changes.notify(now);
+    Ok(())
+}
+
+// PUT /api/machines/{id}/lockout
+pub async fn lock_out_machine(
+    manager: RequireRole<roles::Manager>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(changes): State<MachineChanges>,
+    Json(payload): Json<LockoutRequest>,
+) -> Result<Json<Machine>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    // The lock is applied in the user's own name, never on someone else's behalf
+    if manager.impersonated_by.is_some() {
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: "Not allowed while impersonating".to_string(),
+        })));
+    }
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Reason is required".to_string(),
+        })));
+    }
+
+    match lockout::lock_out(&pool, machine_id, &manager.username, reason).await.map_err(database_error)? {
+        Some(cancelled) => {
+            changes.notify(current_timestamp());
+            tracing::warn!(
+                "Machine ID {} locked out by {}: {} ({} pending command(s) cancelled)",
+                machine_id, manager.username, reason, cancelled,
+            );
+        },
+        None => {
+            let locked_out_by: Option<Option<String>> = sqlx::query_scalar("SELECT locked_out_by FROM machines WHERE id = $1")
+                .bind(machine_id)
+                .fetch_optional(&pool)
+                .await
+                .map_err(database_error)?;
+            return Err(match locked_out_by {
+                None => (StatusCode::NOT_FOUND, Json(ErrorResponse { error: "Machine not found".to_string() })),
+                Some(by) => (StatusCode::CONFLICT, Json(ErrorResponse {
+                    error: format!("Machine is already locked out by {}", by.unwrap_or_default()),
+                })),
+            });
+        },
+    }
+
+    sqlx::query_as::<_, Machine>("SELECT * FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_one(&pool)
+        .await
+        .map(Json)
+        .map_err(database_error)
+}
+
+// DELETE /api/machines/{id}/lockout
+pub async fn release_lockout(
+    manager: RequireRole<roles::Manager>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(changes): State<MachineChanges>,
+) -> Result<Json<Machine>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    if manager.impersonated_by.is_some() {
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: "Not allowed while impersonating".to_string(),
+        })));
+    }
+
+    if !lockout::release(&pool, machine_id).await.map_err(database_error)? {
+        let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM machines WHERE id = $1")
+            .bind(machine_id)
+            .fetch_one(&pool)
+            .await
+            .map_err(database_error)?;
+        return Err(if exists {
+            (StatusCode::CONFLICT, Json(ErrorResponse { error: "Machine is not locked out".to_string() }))
+        } else {
+            (StatusCode::NOT_FOUND, Json(ErrorResponse { error: "Machine not found".to_string() }))
+        });
+    }
+    changes.notify(current_timestamp());
+    tracing::warn!("Lockout of machine ID {} released by {}", machine_id, manager.username);
+
+    sqlx::query_as::<_, Machine>("SELECT * FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_one(&pool)
+        .await
+        .map(Json)
+        .map_err(database_error)
+}
+
+// GET /api/notifications
+#[derive(Deserialize)]
+pub struct NotificationListQuery {
+    unacknowledged: Option<bool>,
+}
+
+pub async fn list_notifications(
+    user: AuthUser,
+    Query(params): Query<NotificationListQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<NotificationListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    // Notifications of machines the user has snoozed are held back until the snooze ends, and
+    // those of machines the user may not see are left out
+    match sqlx::query_as::<_, Notification>(&format!(
+        "SELECT * FROM notifications WHERE (NOT $1 OR acknowledged_at IS NULL) \
+         AND (machine_id IS NULL OR machine_id NOT IN \
+         (SELECT machine_id FROM machine_snoozes WHERE username = $2 AND until > $3)) \
+         AND (machine_id IS NULL OR machine_id IN (SELECT id FROM machines WHERE {})) \
+         ORDER BY created_at DESC LIMIT 100",
+        access::visible_machines(4)
+    ))
+    .bind(params.unacknowledged.unwrap_or(false))
+    .bind(&user.username)
+    .bind(current_timestamp())
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(notifications) => Ok(Json(NotificationListResponse { notifications })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/{id}/snooze
+#[derive(Deserialize)]
+pub struct SnoozeQuery {
+    until: i64,
+}
+
+pub async fn snooze_machine(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    Query(params): Query<SnoozeQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineSnooze>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let now = current_timestamp();
+    if params.until <= now || params.until > now + MAX_SNOOZE_SECS {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("until must be in the future and at most {} days ahead", MAX_SNOOZE_SECS / 86_400),
+        })));
+    }
+    if !access::can_see_machine(&pool, &user, machine_id).await.map_err(database_error)? {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    // Expired snoozes have no effect; they are dropped whenever the user snoozes again
+    sqlx::query("DELETE FROM machine_snoozes WHERE username = $1 AND until <= $2")
+        .bind(&user.username)
+        .bind(now)
+        .execute(&pool)
+        .await
+        .map_err(database_error)?;
+    let snooze = sqlx::query_as::<_, MachineSnooze>(
+        "INSERT INTO machine_snoozes (username, machine_id, until, created_at) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (username, machine_id) DO UPDATE SET until = excluded.until, created_at = excluded.created_at \
+         RETURNING machine_id, (SELECT name FROM machines WHERE id = machine_id) AS machine_name, until, created_at"
+    )
+    .bind(&user.username)
+    .bind(machine_id)
+    .bind(params.until)
+    .bind(now)
+    .fetch_one(&pool)
+    .await
+    .map_err(database_error)?;
+
+    tracing::info!("{} snoozed machine ID {} until {}", user.username, machine_id, params.until);
+    Ok(Json(snooze))
+}
+
+// DELETE /api/machines/{id}/snooze
+pub async fn unsnooze_machine(
+    user: AuthUser,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    if !access::can_see_machine(&pool, &user, machine_id).await.unwrap_or(false) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    match sqlx::query("DELETE FROM machine_snoozes WHERE username = $1 AND machine_id = $2 AND until > $3")
+        .bind(&user.username)
+        .bind(machine_id)
+        .bind(current_timestamp())
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine is not snoozed".to_string(),
+        }))),
+        Ok(_) => {
+            tracing::info!("{} ended the snooze of machine ID {}", user.username, machine_id);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/users/me/snoozes
+pub async fn list_own_snoozes(
+    AuthUser { username, .. }: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineSnoozeListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, MachineSnooze>(
+        "SELECT s.machine_id, m.name AS machine_name, s.until, s.created_at \
+         FROM machine_snoozes s JOIN machines m ON m.id = s.machine_id \
+         WHERE s.username = $1 AND s.until > $2 ORDER BY s.until"
+    )
+    .bind(&username)
+    .bind(current_timestamp())
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(snoozes) => Ok(Json(MachineSnoozeListResponse { snoozes })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/notifications/{id}/acknowledge
+pub async fn acknowledge_notification(
+    user: AuthUser,
+    Path(notification_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<Notification>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, Notification>(&format!(
+        "UPDATE notifications SET acknowledged_by = COALESCE(acknowledged_by, $1), \
+         acknowledged_at = COALESCE(acknowledged_at, $2) WHERE id = $3 \
+         AND (machine_id IS NULL OR machine_id IN (SELECT id FROM machines WHERE {})) RETURNING *",
+        access::visible_machines(4)
+    ))
+    .bind(&user.username)
+    .bind(current_timestamp())
+    .bind(notification_id)
+    .bind(access::sees_all(&user))
+    .bind(&user.username)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(notification)) => Ok(Json(notification)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Notification not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/checklist-templates
+pub async fn list_checklist_templates(
+    _user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<ChecklistTemplateListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let templates = match sqlx::query_as::<_, ChecklistTemplate>("SELECT * FROM checklist_templates ORDER BY name")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(templates) => templates,
+        Err(_) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })));
+        },
+    };
+
+    let mut responses = Vec::with_capacity(templates.len());
+    for template in templates {
+        let steps = sqlx::query_as::<_, ChecklistTemplateStep>(
+            "SELECT text, required FROM checklist_template_steps WHERE template_id = $1 ORDER BY position"
+        )
+        .bind(template.id)
+        .fetch_all(&pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() })))?;
+        responses.push(ChecklistTemplateResponse { template, steps });
+    }
+
+    Ok(Json(ChecklistTemplateListResponse { templates: responses }))
+}
+
+// POST /api/checklist-templates
+pub async fn create_checklist_template(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateChecklistTemplateRequest>,
+) -> Result<(StatusCode, Json<ChecklistTemplateResponse>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create checklist template request received: {}", payload.name);
+    if payload.steps.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "A checklist template needs at least one step".to_string(),
+        })));
+    }
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() })))?;
+
+    let template = match sqlx::query_as::<_, ChecklistTemplate>(
+        "INSERT INTO checklist_templates (name, description, created_at) VALUES ($1, $2, $3) RETURNING *"
+    )
+    .bind(&payload.name)
+    .bind(&payload.description)
+    .bind(current_timestamp())
+    .fetch_one(&mut *tx)
+    .await
+    {
+        Ok(template) => template,
+        Err(_) => {
+            tracing::error!("Failed to create checklist template: {}", payload.name);
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "Checklist template already exists".to_string(),
+            })));
+        },
+    };
+
+    let mut query_builder: QueryBuilder<Db> =
+        QueryBuilder::new("INSERT INTO checklist_template_steps (template_id, position, text, required) ");
+    query_builder.push_values(payload.steps.iter().enumerate(), |mut row, (index, step)| {
+        row.push_bind(template.id)
+            .push_bind(index as i64 + 1)
+            .push_bind(&step.text)
+            .push_bind(step.required);
+    });
+
+    let inserted = query_builder.build().execute(&mut *tx).await;
+    if inserted.is_err() || tx.commit().await.is_err() {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to create checklist template".to_string(),
+        })));
+    }
+
+    tracing::info!("Checklist template created successfully: {}", payload.name);
+    Ok((StatusCode::CREATED, Json(ChecklistTemplateResponse {
+        template,
+        steps: payload.steps,
+    })))
+}
+
+// Trimmed email address, None for a blank one
+fn normalize_email(email: Option<&str>) -> Result<Option<String>, (StatusCode, Json<ErrorResponse>)> {
+    match email.map(str::trim).filter(|email| !email.is_empty()) {
+        Some(email) if !is_valid_email(email) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid email address '{}'", email),
+        }))),
+        email => Ok(email.map(str::to_string)),
+    }
+}
+
+// POST /api/users
+pub async fn create_user(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<CreateUserRequest>,
+) -> Result<(StatusCode, Json<User>), Response> {
+    tracing::info!("Create user request received for user: {}", payload.username);
+    let email = normalize_email(payload.email.as_deref()).map_err(IntoResponse::into_response)?;
+    config.password_policy.check(&payload.password, &payload.username).map_err(password_policy::rejection)?;
+    let password_hash = auth::hash_password(&payload.password).await.map_err(|e| {
+        tracing::error!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to create user".to_string(),
+        })).into_response()
+    })?;
+    
+    match sqlx::query_scalar::<_, i64>(
+        "INSERT INTO users (username, password, role, email, password_changed_at) VALUES ($1, $2, $3, $4, $5) RETURNING id"
+    )
+    .bind(&payload.username)
+    .bind(&password_hash)
+    .bind(&payload.role)
+    .bind(&email)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(user_id) => {
+            tracing::info!("User created successfully: {}", payload.username);
+            Ok((StatusCode::CREATED, Json(User {
+                id: user_id,
+                username: payload.username,
+                role: payload.role,
+                email,
+                last_login: None,
+                is_active: true,
+                deactivated_at: None,
+                deactivation_reason: None,
+            })))
+        },
+        Err(_) => {
+            tracing::error!("Failed to create user: {}", payload.username);
+            Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "Username or email already exists".to_string(),
+            })).into_response())
+        },
+    }
+}
+
+// POST /api/users/{id}/revoke-tokens
+pub async fn revoke_user_tokens(
+    _admin: RequireRole<roles::Admin>,
+    Path(user_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let username: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        })))?;
+    let Some(username) = username else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        })));
+    };
+
+    match auth::revoke_user_tokens(&pool, &username).await {
+        Ok(()) => {
+            tracing::info!("Revoked all tokens of user: {}", username);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Err(e) => {
+            tracing::error!("Failed to persist token revocation for {}: {}", username, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })))
+        },
+    }
+}
+
+// POST /api/users/{id}/unlock
+pub async fn unlock_user(
+    _admin: RequireRole<roles::Admin>,
+    Path(user_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let username: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?;
+    let Some(username) = username else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        })));
+    };
+
+    login_guard::clear(&pool, &username).await.map_err(database_error)?;
+    tracing::info!("Unlocked login for user: {}", username);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// POST /api/users/{id}/deactivate
+pub async fn deactivate_user(
+    admin: RequireRole<roles::Admin>,
+    Path(user_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let username: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?;
+    let Some(username) = username else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        })));
+    };
+    if username == admin.username {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "You cannot disable your own account".to_string(),
+        })));
+    }
+
+    if !inactive_users::deactivate(&pool, &username, "admin").await.map_err(database_error)? {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "User is already disabled".to_string(),
+        })));
+    }
+    tracing::info!("{} disabled user: {}", admin.username, username);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// POST /api/users/{id}/reactivate
+pub async fn reactivate_user(
+    admin: RequireRole<roles::Admin>,
+    Path(user_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let username: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?;
+    let Some(username) = username else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        })));
+    };
+
+    if !inactive_users::reactivate(&pool, &username).await.map_err(database_error)? {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "User is not disabled".to_string(),
+        })));
+    }
+    tracing::info!("{} reactivated user: {}", admin.username, username);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// GET /api/users/{id}/machines
+pub async fn list_machine_access(
+    _admin: RequireRole<roles::Admin>,
+    Path(user_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineAccessListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let user: Option<(String, String)> = sqlx::query_as("SELECT username, role FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?;
+    let Some((username, role)) = user else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        })));
+    };
+
+    let machines = sqlx::query_as::<_, MachineAccessGrant>(
+        "SELECT a.machine_id, m.name AS machine_name, a.granted_by, a.granted_at \
+         FROM user_machine_access a JOIN machines m ON m.id = a.machine_id \
+         WHERE a.username = $1 ORDER BY m.name"
+    )
+    .bind(&username)
+    .fetch_all(&pool)
+    .await
+    .map_err(database_error)?;
+
+    Ok(Json(MachineAccessListResponse {
+        username,
+        sees_all_machines: role.parse::<Role>().is_ok_and(|role| role >= Role::Manager),
+        machines,
+    }))
+}
+
+// Username of user `user_id` after checking that machine `machine_id` exists
+async fn machine_access_target(
+    pool: &DbPool,
+    user_id: i64,
+    machine_id: i64,
+) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let username: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(database_error)?;
+    let Some(username) = username else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        })));
+    };
+    let machine: Option<i64> = sqlx::query_scalar("SELECT id FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(database_error)?;
+    if machine.is_none() {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+    Ok(username)
+}
+
+// PUT /api/users/{id}/machines/{machine_id}
+// Granting a machine the user already has keeps the original grant
+pub async fn grant_machine_access(
+    admin: RequireRole<roles::Admin>,
+    Path((user_id, machine_id)): Path<(i64, i64)>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let username = machine_access_target(&pool, user_id, machine_id).await?;
+
+    match sqlx::query(
+        "INSERT INTO user_machine_access (username, machine_id, granted_by, granted_at) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (username, machine_id) DO NOTHING"
+    )
+    .bind(&username)
+    .bind(machine_id)
+    .bind(&admin.username)
+    .bind(current_timestamp())
+    .execute(&pool)
+    .await
+    {
+        Ok(_) => {
+            tracing::info!("Granted machine {} to user {}", machine_id, username);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/users/{id}/machines/{machine_id}
+pub async fn revoke_machine_access(
+    _admin: RequireRole<roles::Admin>,
+    Path((user_id, machine_id)): Path<(i64, i64)>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let username = machine_access_target(&pool, user_id, machine_id).await?;
+
+    match sqlx::query("DELETE FROM user_machine_access WHERE username = $1 AND machine_id = $2")
+        .bind(&username)
+        .bind(machine_id)
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not granted to this user".to_string(),
+        }))),
+        Ok(_) => {
+            tracing::info!("Revoked machine {} from user {}", machine_id, username);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// Username of user `user_id`, or 404
+async fn certification_target(pool: &DbPool, user_id: i64) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_scalar::<_, String>("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(Some(username)) => Ok(username),
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/users/{id}/certifications
+pub async fn list_user_certifications(
+    _manager: RequireRole<roles::Manager>,
+    Path(user_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<CertificationListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let username = certification_target(&pool, user_id).await?;
+    match sqlx::query_as::<_, Certification>("SELECT * FROM certifications WHERE username = $1 ORDER BY skill")
+        .bind(&username)
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(certifications) => Ok(Json(CertificationListResponse { certifications })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/users/{id}/certifications/{skill}
+// Records a certification, or renews it with a new expiry
+pub async fn set_certification(
+    manager: RequireRole<roles::Manager>,
+    Path((user_id, skill)): Path<(i64, String)>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<SetCertificationRequest>,
+) -> Result<Json<Certification>, (StatusCode, Json<ErrorResponse>)> {
+    if !certifications::is_valid_skill(&skill) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Skill must be lowercase letters, digits, '-' or '_' (at most 40)".to_string(),
+        })));
+    }
+    if let (Some(issued_at), Some(expires_at)) = (payload.issued_at, payload.expires_at)
+        && expires_at <= issued_at
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "expires_at must be after issued_at".to_string(),
+        })));
+    }
+    let username = certification_target(&pool, user_id).await?;
+
+    // A renewal announces its own expiry again
+    match sqlx::query_as::<_, Certification>(
+        "INSERT INTO certifications (username, skill, reference, issued_at, expires_at, recorded_by, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7) \
+         ON CONFLICT (username, skill) DO UPDATE SET reference = excluded.reference, issued_at = excluded.issued_at, \
+         expires_at = excluded.expires_at, notified_at = NULL, recorded_by = excluded.recorded_by, updated_at = excluded.updated_at \
+         RETURNING *"
+    )
+    .bind(&username)
+    .bind(&skill)
+    .bind(&payload.reference)
+    .bind(payload.issued_at)
+    .bind(payload.expires_at)
+    .bind(&manager.username)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(certification) => {
+            tracing::info!("{} recorded the {} certification of {}", manager.username, skill, username);
+            Ok(Json(certification))
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to record certification".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/users/{id}/certifications/{skill}
+pub async fn delete_certification(
+    manager: RequireRole<roles::Manager>,
+    Path((user_id, skill)): Path<(i64, String)>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let username = certification_target(&pool, user_id).await?;
+    match sqlx::query("DELETE FROM certifications WHERE username = $1 AND skill = $2")
+        .bind(&username)
+        .bind(&skill)
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() > 0 => {
+            tracing::info!("{} removed the {} certification of {}", manager.username, skill, username);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Ok(_) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Certification not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/certifications?skill=&expires_before=
+#[derive(Deserialize)]
+pub struct CertificationListQuery {
+    skill: Option<String>,
+    expires_before: Option<i64>,
+}
+
+pub async fn list_certifications(
+    _manager: RequireRole<roles::Manager>,
+    Query(params): Query<CertificationListQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<CertificationListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, Certification>(
+        "SELECT * FROM certifications WHERE ($1 IS NULL OR skill = $2) AND ($3 IS NULL OR expires_at < $4) \
+         ORDER BY expires_at IS NULL, expires_at, username, skill"
+    )
+    .bind(&params.skill)
+    .bind(&params.skill)
+    .bind(params.expires_before)
+    .bind(params.expires_before)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(certifications) => Ok(Json(CertificationListResponse { certifications })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// Active sessions of a user, most recently seen first, flagging the one `current_session` names
+async fn list_active_sessions(
+    pool: &DbPool,
+    username: &str,
+    current_session: Option<&str>,
+) -> Result<Json<SessionListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, Session>(
+        "SELECT id, user_agent, ip, created_at, last_seen_at, expires_at, id IS NOT DISTINCT FROM $1 AS current FROM sessions \
+         WHERE username = $2 AND revoked_at IS NULL AND expires_at >= $3 ORDER BY last_seen_at DESC, created_at DESC"
+    )
+    .bind(current_session)
+    .bind(username)
+    .bind(current_timestamp())
+    .fetch_all(pool)
+    .await
+    {
+        Ok(sessions) => Ok(Json(SessionListResponse { sessions })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/users/me/sessions
+pub async fn list_own_sessions(
+    user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<SessionListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    list_active_sessions(&pool, &user.username, user.session_id.as_deref()).await
+}
+
+// GET /api/users/me/views/machines
+pub async fn get_machine_list_view(
+    user: AuthUser,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineListView>, (StatusCode, Json<ErrorResponse>)> {
+    let saved: Option<(String, i64)> = sqlx::query_as(
+        "SELECT settings, updated_at FROM user_list_views WHERE username = $1 AND view = 'machines'"
+    )
+    .bind(&user.username)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))?;
+
+    // A view saved by an older release that no longer parses falls back to the defaults
+    let view = saved
+        .and_then(|(settings, updated_at)| {
+            serde_json::from_str::<MachineListView>(&settings)
+                .ok()
+                .map(|view| MachineListView { updated_at: Some(updated_at), ..view })
+        })
+        .unwrap_or_default();
+    Ok(Json(view))
+}
+
+// PUT /api/users/me/views/machines
+pub async fn set_machine_list_view(
+    user: AuthUser,
+    State(pool): State<DbPool>,
+    Json(mut view): Json<MachineListView>,
+) -> Result<Json<MachineListView>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }));
+    let unknown_column = |column: &str| bad_request(format!(
+        "Unknown column '{}'. Must be one of: {}",
+        column,
+        MACHINE_LIST_COLUMNS.join(", ")
+    ));
+    if view.columns.is_empty() {
+        return Err(bad_request("At least one column must be visible".to_string()));
+    }
+    for (index, column) in view.columns.iter().enumerate() {
+        if !MACHINE_LIST_COLUMNS.contains(&column.as_str()) {
+            return Err(unknown_column(column));
+        }
+        if view.columns[..index].contains(column) {
+            return Err(bad_request(format!("Column '{}' is listed twice", column)));
+        }
+    }
+    if !MACHINE_LIST_COLUMNS.contains(&view.sort_by.as_str()) {
+        return Err(unknown_column(&view.sort_by));
+    }
+    for (column, value) in &view.filters {
+        if !MACHINE_LIST_COLUMNS.contains(&column.as_str()) {
+            return Err(unknown_column(column));
+        }
+        if value.chars().count() > 200 {
+            return Err(bad_request(format!("Filter on '{}' is longer than 200 characters", column)));
+        }
+    }
+
+    let now = current_timestamp();
+    view.updated_at = None;
+    let settings = serde_json::to_string(&view).map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Failed to save view".to_string(),
+    })))?;
+    sqlx::query(
+        "INSERT INTO user_list_views (username, view, settings, updated_at) VALUES ($1, 'machines', $2, $3) \
+         ON CONFLICT (username, view) DO UPDATE SET settings = excluded.settings, updated_at = excluded.updated_at"
+    )
+    .bind(&user.username)
+    .bind(&settings)
+    .bind(now)
+    .execute(&pool)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))?;
+
+    view.updated_at = Some(now);
+    Ok(Json(view))
+}
+
+// GET /api/users/{id}/sessions
+pub async fn list_user_sessions(
+    admin: RequireRole<roles::Admin>,
+    Path(user_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<SessionListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let username: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        })))?;
+    let Some(username) = username else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        })));
+    };
+    list_active_sessions(&pool, &username, admin.session_id.as_deref()).await
+}
+
+// DELETE /api/sessions/{id}
+// Users can end their own sessions, admins anyone's
+pub async fn revoke_session(
+    user: AuthUser,
+    Path(session_id): Path<String>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |e: sqlx::Error| {
+        tracing::error!("Failed to revoke session {}: {}", session_id, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))
+    };
+    let owner: Option<String> = sqlx::query_scalar(
+        "SELECT username FROM sessions WHERE id = $1 AND revoked_at IS NULL AND expires_at >= $2"
+    )
+    .bind(&session_id)
+    .bind(current_timestamp())
+    .fetch_optional(&pool)
+    .await
+    .map_err(database_error)?;
+    if !owner.is_some_and(|owner| owner == user.username || user.role == Role::Admin) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Session not found".to_string(),
+        })));
+    }
+
+    auth::revoke_session(&pool, &session_id).await.map_err(database_error)?;
+    tracing::info!("Session {} revoked by {}", session_id, user.username);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// POST /api/users/me/password
+pub async fn change_password(
+    AuthUser { username, impersonated_by, .. }: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Result<Json<LoginResponse>, Response> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })).into_response();
+    let locked = |locked_until: i64| {
+        let retry_after = (locked_until - current_timestamp()).max(1).to_string();
+        (StatusCode::LOCKED, [(header::RETRY_AFTER, retry_after)], Json(ErrorResponse {
+            error: "Account locked after too many failed logins".to_string(),
+        })).into_response()
+    };
+
+    if impersonated_by.is_some() {
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: "Not allowed while impersonating".to_string(),
+        })).into_response());
+    }
+    if payload.new_password == payload.current_password {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "New password must differ from the current one".to_string(),
+        })).into_response());
+    }
+    config.password_policy.check(&payload.new_password, &username).map_err(password_policy::rejection)?;
+
+    // Wrong current passwords count towards the login lockout, so a stolen session can't
+    // be used to guess the password either
+    if let Some(locked_until) = login_guard::locked_until(&pool, &username).await.map_err(database_error)? {
+        return Err(locked(locked_until));
+    }
+    let Some(user) = auth::authenticate_user(&username, &payload.current_password, &pool).await else {
+        tracing::warn!("Password change refused, wrong current password for user: {}", username);
+        let lock = login_guard::record_failure(&pool, &username, config.login_max_attempts, config.login_lockout)
+            .await
+            .map_err(database_error)?;
+        if let Some(locked_until) = lock {
+            return Err(locked(locked_until));
+        }
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: "Current password is incorrect".to_string(),
+        })).into_response());
+    };
+
+    set_password(&pool, &user.username, &payload.new_password).await?;
+    login_guard::clear(&pool, &username).await.map_err(database_error)?;
+
+    // Every other session, including ones opened with the old password, ends here; the
+    // caller carries on with the session returned below
+    auth::revoke_user_tokens(&pool, &username).await.map_err(|e| {
+        tracing::error!("Failed to persist token revocation for {}: {}", username, e);
+        database_error(e)
+    })?;
+    let client = SessionClient::new(&headers, addr, &config);
+    let session = new_session(&pool, user.username, user.role, &client).await.map_err(IntoResponse::into_response)?;
+    tracing::info!("Password changed by user: {}, all other sessions revoked", session.username);
+    Ok(Json(session))
+}
+
+// POST /api/password-reset/request
+pub async fn request_password_reset(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    State(mailer): State<Mailer>,
+    Json(payload): Json<PasswordResetRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    if !mailer.is_configured() {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, Json(ErrorResponse {
+            error: "Password reset by email is not available".to_string(),
+        })));
+    }
+
+    let user: Option<(String, String)> = sqlx::query_as("SELECT username, email FROM users WHERE lower(email) = lower($1)")
+    .bind(payload.email.trim())
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })))?;
+
+    // The answer is the same whether or not the address belongs to anyone, and the token and
+    // mail are made in the background so response times don't tell either
+    let Some((username, email)) = user else {
+        tracing::info!("Password reset requested for an unknown address");
+        return Ok(StatusCode::ACCEPTED);
+    };
+    tokio::spawn(async move {
+        let token = match auth::issue_password_reset_token(&pool, &username, config.password_reset_ttl).await {
+            Ok(token) => token,
+            Err(e) => {
+                tracing::error!("Failed to issue a password reset token for user {}: {}", username, e);
+                return;
+            },
+        };
+        let plant_name = match site::plant_name(&pool).await {
+            Ok(plant_name) => plant_name,
+            Err(e) => {
+                tracing::error!("Failed to load the plant name for a password reset email: {}", e);
+                return;
+            },
+        };
+        let subject = format!("{} password reset", plant_name);
+        let body = format!(
+            "A password reset was requested for the {} account '{}'.\n\n\
+             Reset code: {}\n\n\
+             The code works once and expires in {} minutes. If you did not ask for this, ignore this email; \
+             your password stays unchanged.\n",
+            plant_name,
+            username,
+            token,
+            config.password_reset_ttl.as_secs() / 60,
+        );
+        match mailer.send(&email, &subject, body).await {
+            Ok(()) => tracing::info!("Password reset email sent to user: {}", username),
+            Err(e) => tracing::error!("Failed to send password reset email to user {}: {}", username, e),
+        }
+    }.in_current_span());
+    Ok(StatusCode::ACCEPTED)
+}
+
+// POST /api/password-reset/confirm
+pub async fn confirm_password_reset(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<PasswordResetConfirmRequest>,
+) -> Result<StatusCode, Response> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    })).into_response();
+    let invalid_token = || (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+        error: "Invalid or expired reset token".to_string(),
+    })).into_response();
+
+    // The token is only used up once the new password is accepted, so a refused password
+    // can be retried with the same email
+    let Some(username) = auth::password_reset_token_user(&pool, &payload.token).await.map_err(database_error)? else {
+        return Err(invalid_token());
+    };
+    config.password_policy.check(&payload.new_password, &username).map_err(password_policy::rejection)?;
+    if auth::redeem_password_reset_token(&pool, &payload.token).await.map_err(database_error)?.is_none() {
+        return Err(invalid_token());
+    }
+    set_password(&pool, &username, &payload.new_password).await?;
+
+    // Whoever knew the old password is signed out, and a lockout from guessing it is lifted
+    login_guard::clear(&pool, &username).await.map_err(database_error)?;
+    auth::revoke_user_tokens(&pool, &username).await.map_err(|e| {
+        tracing::error!("Failed to persist token revocation for {}: {}", username, e);
+        database_error(e)
+    })?;
+    tracing::info!("Password reset by email for user: {}, all sessions revoked", username);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// PUT /api/users/{id}
+pub async fn update_user(
+    admin: RequireRole<roles::Admin>,
+    Path(user_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<UpdateUserRequest>,
+) -> Result<Json<User>, Response> {
+    tracing::info!("Update user request received for user ID: {}", user_id);
+    // Check if user exists
+    let Ok(username) = sqlx::query_scalar::<_, String>("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(&pool)
+        .await
+    else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        })).into_response());
+    };
+
+    if let Some(role) = &payload.role
+        && !USER_ROLES.contains(&role.as_str())
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Invalid role. Must be one of: {}", USER_ROLES.join(", ")),
+        })).into_response());
+    }
+
+    let password_hash = match &payload.password {
+        Some(password) => {
+            config.password_policy.check(password, &username).map_err(password_policy::rejection)?;
+            Some(auth::hash_password(password).await.map_err(|e| {
+                tracing::error!("{}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                    error: "Failed to update user".to_string(),
+                })).into_response()
+            })?)
+        },
+        None => None,
+    };
+
+    // Build update query dynamically based on provided fields
+    let mut query_builder: QueryBuilder<Db> = QueryBuilder::new("UPDATE users SET ");
+    let mut params = query_builder.separated(", ");
+    let mut has_changes = false;
+
+    if let Some(password_hash) = &password_hash {
+        params.push("password = ").push_bind_unseparated(password_hash);
+        params.push("password_changed_at = ").push_bind_unseparated(current_timestamp());
+        has_changes = true;
+    }
+
+    if let Some(role) = &payload.role {
+        params.push("role = ").push_bind_unseparated(role);
+        has_changes = true;
+    }
+
+    // An empty email removes the address
+    let email = payload.email.as_deref().map(|email| normalize_email(Some(email))).transpose().map_err(IntoResponse::into_response)?;
+    if let Some(email) = &email {
+        params.push("email = ").push_bind_unseparated(email);
+        has_changes = true;
+    }
+
+    if !has_changes && payload.is_active.is_none() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "No fields to update".to_string(),
+        })).into_response());
+    }
+    if payload.is_active == Some(false) && username == admin.username {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "You cannot disable your own account".to_string(),
+        })).into_response());
+    }
+
+    query_builder.push(" WHERE id = ").push_bind(user_id);
+
+    // Execute update; disabling and reactivating go through the same path as their own endpoints
+    let updated = if has_changes {
+        query_builder.build().execute(&pool).await.map(|_| ())
+    } else {
+        Ok(())
+    };
+    let updated = match (updated, payload.is_active) {
+        (Ok(()), Some(false)) => inactive_users::deactivate(&pool, &username, "admin").await.map(|_| ()),
+        (Ok(()), Some(true)) => inactive_users::reactivate(&pool, &username).await.map(|_| ()),
+        (updated, _) => updated,
+    };
+    // Sessions carry the role they were issued with, so a new role or password ends them all;
+    // the user logs in again to continue
+    let updated = match updated {
+        Ok(()) if payload.role.is_some() || password_hash.is_some() => auth::revoke_user_tokens(&pool, &username).await,
+        updated => updated,
+    };
+    match updated {
+        Ok(()) => {
+            // Fetch updated user
+            match sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_one(&pool)
+                .await
+            {
+                Ok(user) => {
+                    tracing::info!("User updated successfully: {}", user.username);
+                    Ok(Json(user))
+                },
+                Err(_) => {
+                    tracing::error!("Failed to fetch updated user: {}", user_id);
+                    Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                        error: "Failed to fetch updated user".to_string(),
+                    })).into_response())
+                },
+            }
+        },
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Email already in use".to_string(),
+        })).into_response()),
+        Err(_) => {
+            tracing::error!("Failed to update user: {}", user_id);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to update user".to_string(),
+            })).into_response())
+        },
+    }
+}
+
+// PUT /api/machines/{id}
+pub async fn update_machine(
+    _admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    State(changes): State<MachineChanges>,
+    Json(payload): Json<UpdateMachineRequest>,
+) -> Result<Json<MachineResponse>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update machine request received for machine ID: {}", machine_id);
+    validate_machine_config(payload.target_speed, payload.report_interval)?;
+
+    // Check if machine exists
+    if sqlx::query("SELECT id FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_one(&pool)
+        .await
+        .is_err()
+    {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    // The data owner decides export requests, so only an active manager or admin can be one
+    let data_owner = payload.data_owner.as_deref().map(str::trim);
+    if let Some(owner) = data_owner.filter(|owner| !owner.is_empty()) {
+        let can_approve: bool = sqlx::query_scalar(
+            "SELECT COUNT(*) > 0 FROM users WHERE username = $1 AND is_active = TRUE AND role IN ('manager', 'admin')"
+        )
+        .bind(owner)
+        .fetch_one(&pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        })))?;
+        if !can_approve {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "data_owner must be an active manager or admin".to_string(),
+            })));
+        }
+    }
+
+    // Build update query dynamically based on provided fields
+    let mut query_builder: QueryBuilder<Db> = QueryBuilder::new("UPDATE machines SET ");
+    let mut params = query_builder.separated(", ");
+    let mut has_changes = false;
+
+    if let Some(name) = &payload.name {
+        params.push("name = ").push_bind_unseparated(name);
+        has_changes = true;
+    }
+
+    if let Some(code) = &payload.code {
+        params.push("code = ").push_bind_unseparated(code);
+        has_changes = true;
+    }
+
+    if let Some(location) = &payload.location {
+        params.push("location = ").push_bind_unseparated(location);
+        has_changes = true;
+    }
+
+    if let Some(machine_type) = &payload.machine_type {
+        params.push("machine_type = ").push_bind_unseparated(machine_type);
+        has_changes = true;
+    }
+
+    if let Some(target_speed) = payload.target_speed {
+        params.push("target_speed = ").push_bind_unseparated(target_speed);
+        has_changes = true;
+    }
+
+    if let Some(report_interval) = payload.report_interval {
+        params.push("report_interval = ").push_bind_unseparated(report_interval);
+        has_changes = true;
+    }
+
+    if let Some(dedup_updates) = payload.dedup_updates {
+        params.push("dedup_updates = ").push_bind_unseparated(dedup_updates);
+        has_changes = true;
+    }
+
+    if let Some(days) = payload.history_retention_days {
+        if days < 0 {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "history_retention_days must be 0 or more".to_string(),
+            })));
+        }
+        params.push("history_retention_days = ").push_bind_unseparated(days);
+        has_changes = true;
+    }
+
+    if let Some(owner) = data_owner {
+        params.push("data_owner = ").push_bind_unseparated((!owner.is_empty()).then_some(owner));
+        has_changes = true;
+    }
+
+    if let Some(allowed_cidrs) = &payload.allowed_cidrs {
+        let mut networks = Vec::with_capacity(allowed_cidrs.len());
+        for cidr in allowed_cidrs {
+            let Some(network) = network::parse_cidr(cidr) else {
+                return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                    error: format!("Invalid network '{}', expected e.g. 10.20.0.0/16", cidr),
+                })));
+            };
+            networks.push(network.to_string());
+        }
+        let allowed_cidrs = (!networks.is_empty()).then(|| networks.join(","));
+        params.push("allowed_cidrs = ").push_bind_unseparated(allowed_cidrs);
+        has_changes = true;
+    }
+
+    // Only the hash is stored, so a regenerated key can be shown in this response only
+    let api_key = (payload.regenerate_api_key == Some(true)).then(auth::generate_machine_api_key);
+    if let Some(api_key) = &api_key {
+        params.push("api_key = ").push_bind_unseparated(auth::hash_token(api_key));
+        has_changes = true;
+    }
+
+    if !has_changes {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "No fields to update".to_string(),
+        })));
+    }
+
+    params.push("updated_at = ").push_bind_unseparated(current_timestamp());
+    query_builder.push(" WHERE id = ").push_bind(machine_id);
 
     // Execute update
-    match query_builder.execute(&pool).await {
+    match query_builder.build().execute(&pool).await {
         Ok(_) => {
-            // Fetch updated machine and its API key
-            match sqlx::query("SELECT m.*, m.api_key FROM machines m WHERE m.id = ?")
+            changes.notify(current_timestamp());
+
+            // Fetch updated machine
+            match sqlx::query("SELECT * FROM machines WHERE id = $1")
                 .bind(machine_id)
                 .fetch_one(&pool)
                 .await
@@ -550,61 +8906,2313 @@ pub async fn update_machine(
                         current_speed: row.get("current_speed"),
                         status_message: row.get("status_message"),
                         is_online: row.get("is_online"),
+                        locked_out_at: row.get("locked_out_at"),
+                        locked_out_by: row.get("locked_out_by"),
+                        lockout_reason: row.get("lockout_reason"),
                         last_update: row.get("last_update"),
+                        updated_at: row.get("updated_at"),
+                        report_interval: row.get("report_interval"),
+                        clock_drift: row.get("clock_drift"),
+                        history_retention_days: row.get("history_retention_days"),
+                        data_owner: row.get("data_owner"),
+                        archived_at: row.get("archived_at"),
+                        archived_by: row.get("archived_by"),
+                        health_score: row.get("health_score"),
+                        display_name: None,
                     };
-                    let api_key: String = row.get("api_key");
-                    
-                    println!("[LOG] Machine updated successfully: {}", machine.name);
+                    tracing::info!("Machine updated successfully: {}", machine.name);
                     Ok(Json(MachineResponse {
                         id: machine.id,
                         name: machine.name,
                         code: machine.code,
+                        signing_secret: api_key.as_deref().and_then(|api_key| signing::issue_signing_secret(&config, api_key)),
                         api_key,
                         location: machine.location,
                         machine_type: machine.machine_type,
+                        allowed_cidrs: row
+                            .get::<Option<String>, _>("allowed_cidrs")
+                            .map(|cidrs| cidrs.split(',').map(str::to_string).collect())
+                            .unwrap_or_default(),
                     }))
                 },
-                Err(_) => {
-                    println!("[LOG] Failed to fetch updated machine: {}", machine_id);
-                    Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
-                        error: "Failed to fetch updated machine".to_string(),
-                    })))
+                Err(_) => {
+                    tracing::error!("Failed to fetch updated machine: {}", machine_id);
+                    Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                        error: "Failed to fetch updated machine".to_string(),
+                    })))
+                },
+            }
+        },
+        Err(e) => {
+            tracing::error!("Failed to update machine: {}", machine_id);
+            if e.to_string().contains("UNIQUE constraint failed") {
+                Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                    error: "Machine name or code already exists".to_string(),
+                })))
+            } else {
+                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                    error: "Failed to update machine".to_string(),
+                })))
+            }
+        },
+    }
+}
+
+fn machine_api_key_from_row(row: &DbRow) -> MachineApiKey {
+    let scopes: String = row.get("scopes");
+    MachineApiKey {
+        id: row.get("id"),
+        machine_id: row.get("machine_id"),
+        name: row.get("name"),
+        scopes: scopes.split(',').filter(|scope| !scope.is_empty()).map(str::to_string).collect(),
+        created_at: row.get("created_at"),
+        revoked_at: row.get("revoked_at"),
+        expires_at: row.get("expires_at"),
+        api_key: None,
+        signing_secret: None,
+    }
+}
+
+// Scopes as stored, rejecting unknown or missing ones
+fn join_scopes(scopes: &[String], known: &[&str]) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    if scopes.is_empty() || scopes.iter().any(|scope| !known.contains(&scope.as_str())) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("Scopes must be one or more of: {}", known.join(", ")),
+        })));
+    }
+    let mut scopes = scopes.to_vec();
+    scopes.sort();
+    scopes.dedup();
+    Ok(scopes.join(","))
+}
+
+// POST /api/machines/{id}/rotate-key
+pub async fn rotate_machine_key(
+    admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    payload: Option<Json<RotateMachineKeyRequest>>,
+) -> Result<Json<MachineKeyRotationResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let grace = payload
+        .and_then(|Json(payload)| payload.grace_secs)
+        .unwrap_or(config.key_rotation_grace.as_secs() as i64);
+    if grace < 0 {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "grace_secs must not be negative".to_string(),
+        })));
+    }
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+
+    let mut tx = pool.begin().await.map_err(database_error)?;
+    let Some(old_key_hash) = sqlx::query_scalar::<_, String>("SELECT api_key FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(database_error)?
+    else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    };
+
+    // The old key moves to the additional keys with every scope, so it keeps working
+    // until the device is reprovisioned and can be revoked early like any other key
+    let now = current_timestamp();
+    let previous_key = if grace > 0 {
+        let row = sqlx::query(
+            "INSERT INTO machine_api_keys (machine_id, name, api_key, scopes, created_at, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"
+        )
+        .bind(machine_id)
+        .bind("Previous key (rotated)")
+        .bind(&old_key_hash)
+        .bind(MACHINE_KEY_SCOPES.join(","))
+        .bind(now)
+        .bind(now + grace)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(database_error)?;
+        Some(machine_api_key_from_row(&row))
+    } else {
+        None
+    };
+
+    let api_key = auth::generate_machine_api_key();
+    sqlx::query("UPDATE machines SET api_key = $1, updated_at = $2 WHERE id = $3")
+        .bind(auth::hash_token(&api_key))
+        .bind(now)
+        .bind(machine_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(database_error)?;
+    tx.commit().await.map_err(database_error)?;
+
+    tracing::info!("{} rotated the API key of machine ID {} with a {}s grace period", admin.username, machine_id, grace);
+    let signing_secret = signing::issue_signing_secret(&config, &api_key);
+    Ok(Json(MachineKeyRotationResponse { api_key, signing_secret, previous_key }))
+}
+
+// GET /api/machines/{id}/api-keys
+pub async fn list_machine_api_keys(
+    _admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<MachineApiKeyListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query("SELECT * FROM machine_api_keys WHERE machine_id = $1 ORDER BY created_at, id")
+        .bind(machine_id)
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(rows) => Ok(Json(MachineApiKeyListResponse {
+            keys: rows.iter().map(machine_api_key_from_row).collect(),
+        })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/machines/{id}/api-keys
+pub async fn create_machine_api_key(
+    admin: RequireRole<roles::Admin>,
+    Path(machine_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<CreateMachineApiKeyRequest>,
+) -> Result<(StatusCode, Json<MachineApiKey>), (StatusCode, Json<ErrorResponse>)> {
+    let scopes = join_scopes(&payload.scopes, MACHINE_KEY_SCOPES)?;
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+
+    let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .fetch_one(&pool)
+        .await
+        .map_err(database_error)?;
+    if !exists {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Machine not found".to_string(),
+        })));
+    }
+
+    let api_key = auth::generate_machine_api_key();
+    let row = sqlx::query(
+        "INSERT INTO machine_api_keys (machine_id, name, api_key, scopes, created_at) VALUES ($1, $2, $3, $4, $5) RETURNING *"
+    )
+    .bind(machine_id)
+    .bind(&payload.name)
+    .bind(auth::hash_token(&api_key))
+    .bind(&scopes)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    .map_err(database_error)?;
+
+    let mut key = machine_api_key_from_row(&row);
+    tracing::info!("{} created API key {} ({}) for machine ID {}", admin.username, key.id, scopes, machine_id);
+    key.signing_secret = signing::issue_signing_secret(&config, &api_key);
+    key.api_key = Some(api_key);
+    Ok((StatusCode::CREATED, Json(key)))
+}
+
+// PUT /api/machine-api-keys/{id}
+pub async fn update_machine_api_key(
+    admin: RequireRole<roles::Admin>,
+    Path(key_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateMachineApiKeyRequest>,
+) -> Result<Json<MachineApiKey>, (StatusCode, Json<ErrorResponse>)> {
+    let scopes = payload.scopes.as_deref().map(|scopes| join_scopes(scopes, MACHINE_KEY_SCOPES)).transpose()?;
+
+    match sqlx::query(
+        "UPDATE machine_api_keys SET name = COALESCE($1, name), scopes = COALESCE($2, scopes) \
+         WHERE id = $3 AND revoked_at IS NULL RETURNING *"
+    )
+    .bind(&payload.name)
+    .bind(&scopes)
+    .bind(key_id)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(row)) => {
+            let key = machine_api_key_from_row(&row);
+            if let Some(scopes) = &scopes {
+                tracing::info!("{} set the scopes of API key {} to {}", admin.username, key.id, scopes);
+            }
+            Ok(Json(key))
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "API key not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/machine-api-keys/{id}
+pub async fn revoke_machine_api_key(
+    admin: RequireRole<roles::Admin>,
+    Path(key_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    // Revoked keys stay listed so it remains visible which gateways once had access
+    match sqlx::query("UPDATE machine_api_keys SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL")
+        .bind(current_timestamp())
+        .bind(key_id)
+        .execute(&pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "API key not found".to_string(),
+        }))),
+        Ok(_) => {
+            tracing::info!("{} revoked API key {}", admin.username, key_id);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+fn service_account_from_row(row: &DbRow) -> ServiceAccount {
+    let scopes: String = row.get("scopes");
+    ServiceAccount {
+        id: row.get("id"),
+        name: row.get("name"),
+        description: row.get("description"),
+        scopes: scopes.split(',').filter(|scope| !scope.is_empty()).map(str::to_string).collect(),
+        created_by: row.get("created_by"),
+        created_at: row.get("created_at"),
+        rotated_at: row.get("rotated_at"),
+        previous_key_expires_at: row.get("previous_key_expires_at"),
+        last_used_at: row.get("last_used_at"),
+        disabled_at: row.get("disabled_at"),
+        api_key: None,
+    }
+}
+
+// GET /api/service-accounts
+pub async fn list_service_accounts(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+) -> Result<Json<ServiceAccountListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query("SELECT * FROM service_accounts ORDER BY name").fetch_all(&pool).await {
+        Ok(rows) => Ok(Json(ServiceAccountListResponse {
+            service_accounts: rows.iter().map(service_account_from_row).collect(),
+        })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/service-accounts
+pub async fn create_service_account(
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateServiceAccountRequest>,
+) -> Result<(StatusCode, Json<ServiceAccount>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create service account request received: {}", payload.name);
+    // Names end up in "service:<name>" authorship, so they are kept to plain identifiers
+    let name = payload.name.trim().to_lowercase();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Service account names may only contain letters, digits, '-' and '_'".to_string(),
+        })));
+    }
+    let scopes = join_scopes(&payload.scopes, SERVICE_ACCOUNT_SCOPES)?;
+
+    let api_key = service_accounts::generate_key();
+    match sqlx::query(
+        "INSERT INTO service_accounts (name, description, scopes, api_key, created_by, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"
+    )
+    .bind(&name)
+    .bind(&payload.description)
+    .bind(&scopes)
+    .bind(auth::hash_token(&api_key))
+    .bind(&admin.username)
+    .bind(current_timestamp())
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(row) => {
+            let mut account = service_account_from_row(&row);
+            tracing::info!("{} created service account {} ({})", admin.username, name, scopes);
+            account.api_key = Some(api_key);
+            Ok((StatusCode::CREATED, Json(account)))
+        },
+        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Service account already exists".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/service-accounts/{id}
+pub async fn update_service_account(
+    admin: RequireRole<roles::Admin>,
+    Path(account_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateServiceAccountRequest>,
+) -> Result<Json<ServiceAccount>, (StatusCode, Json<ErrorResponse>)> {
+    let scopes = payload.scopes.as_deref().map(|scopes| join_scopes(scopes, SERVICE_ACCOUNT_SCOPES)).transpose()?;
+
+    match sqlx::query(
+        "UPDATE service_accounts SET description = COALESCE($1, description), scopes = COALESCE($2, scopes) \
+         WHERE id = $3 AND disabled_at IS NULL RETURNING *"
+    )
+    .bind(&payload.description)
+    .bind(&scopes)
+    .bind(account_id)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(row)) => {
+            let account = service_account_from_row(&row);
+            tracing::info!("{} updated service account {} ({})", admin.username, account.name, account.scopes.join(","));
+            Ok(Json(account))
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Service account not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/service-accounts/{id}/rotate-key
+pub async fn rotate_service_account_key(
+    admin: RequireRole<roles::Admin>,
+    Path(account_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    payload: Option<Json<RotateMachineKeyRequest>>,
+) -> Result<Json<ServiceAccount>, (StatusCode, Json<ErrorResponse>)> {
+    let grace = payload
+        .and_then(|Json(payload)| payload.grace_secs)
+        .unwrap_or(config.key_rotation_grace.as_secs() as i64);
+    if grace < 0 {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "grace_secs must not be negative".to_string(),
+        })));
+    }
+
+    // The current key becomes the previous one for the grace period; an earlier previous
+    // key stops working at once
+    let now = current_timestamp();
+    let api_key = service_accounts::generate_key();
+    match sqlx::query(
+        "UPDATE service_accounts SET \
+         previous_api_key = CASE WHEN $1 > 0 THEN api_key END, \
+         previous_key_expires_at = CASE WHEN $2 > 0 THEN $3 END, \
+         api_key = $4, rotated_at = $5 \
+         WHERE id = $6 AND disabled_at IS NULL RETURNING *"
+    )
+    .bind(grace)
+    .bind(grace)
+    .bind(now + grace)
+    .bind(auth::hash_token(&api_key))
+    .bind(now)
+    .bind(account_id)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(row)) => {
+            let mut account = service_account_from_row(&row);
+            tracing::info!("{} rotated the key of service account {}", admin.username, account.name);
+            account.api_key = Some(api_key);
+            Ok(Json(account))
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Service account not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/service-accounts/{id}
+pub async fn disable_service_account(
+    admin: RequireRole<roles::Admin>,
+    Path(account_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    // Disabled accounts stay listed, so their past work remains attributable
+    match sqlx::query(
+        "UPDATE service_accounts SET disabled_at = $1, previous_api_key = NULL, previous_key_expires_at = NULL \
+         WHERE id = $2 AND disabled_at IS NULL"
+    )
+    .bind(current_timestamp())
+    .bind(account_id)
+    .execute(&pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Service account not found".to_string(),
+        }))),
+        Ok(_) => {
+            tracing::info!("{} disabled service account {}", admin.username, account_id);
+            Ok(StatusCode::NO_CONTENT)
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// GET /api/users
+pub async fn list_users(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+) -> Result<Json<UserListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("List users request received");
+    match sqlx::query_as::<_, User>("SELECT * FROM users ORDER BY username").fetch_all(&pool).await {
+        Ok(users) => {
+            tracing::info!("Users listed successfully");
+            Ok(Json(UserListResponse { users }))
+        },
+        Err(_) => {
+            tracing::error!("Failed to list users");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })))
+        },
+    }
+}
+
+// GET /api/users/{id}/logins
+#[derive(Deserialize)]
+pub struct LoginHistoryQuery {
+    limit: Option<i64>,
+}
+
+pub async fn list_user_logins(
+    _admin: RequireRole<roles::Admin>,
+    Path(user_id): Path<i64>,
+    Query(params): Query<LoginHistoryQuery>,
+    State(pool): State<DbPool>,
+) -> Result<Json<LoginHistoryResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let username: String = sqlx::query_scalar("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        })))?;
+
+    let logins = sqlx::query_as::<_, LoginHistoryEntry>(
+        "SELECT * FROM login_history WHERE username = $1 ORDER BY created_at DESC, id DESC LIMIT $2"
+    )
+    .bind(&username)
+    .bind(params.limit.unwrap_or(100).clamp(1, 1000))
+    .fetch_all(&pool)
+    .await
+    .map_err(database_error)?;
+    Ok(Json(LoginHistoryResponse { logins }))
+}
+
+// POST /api/admin/impersonate/{user_id}
+pub async fn impersonate_user(
+    admin: RequireRole<roles::Admin>,
+    Path(user_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    payload: Option<Json<ImpersonateRequest>>,
+) -> Result<Json<ImpersonationResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let reason = payload
+        .and_then(|Json(payload)| payload.reason)
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+
+    let Some((username, role, is_active)) = sqlx::query_as::<_, (String, String, bool)>(
+        "SELECT username, role, is_active FROM users WHERE id = $1"
+    )
+    .bind(user_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(database_error)?
+    else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "User not found".to_string(),
+        })));
+    };
+    // Admins gain nothing from acting as another admin, and it would blur who did what
+    if role == Role::Admin.as_str() {
+        return Err((StatusCode::FORBIDDEN, Json(ErrorResponse {
+            error: "Admins cannot be impersonated".to_string(),
+        })));
+    }
+    if !is_active {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Disabled accounts cannot be impersonated".to_string(),
+        })));
+    }
+
+    let (token, expires_at) = auth::issue_impersonation_token(&username, &role, &admin.username, config.impersonation_ttl)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to issue token".to_string(),
+        })))?;
+    sqlx::query(
+        "INSERT INTO impersonations (admin_username, username, reason, created_at, expires_at) VALUES ($1, $2, $3, $4, $5)"
+    )
+    .bind(&admin.username)
+    .bind(&username)
+    .bind(&reason)
+    .bind(current_timestamp())
+    .bind(expires_at)
+    .execute(&pool)
+    .await
+    .map_err(database_error)?;
+
+    tracing::warn!(
+        "{} is impersonating {} until {} ({})",
+        admin.username,
+        username,
+        expires_at,
+        reason.as_deref().unwrap_or("no reason given")
+    );
+    Ok(Json(ImpersonationResponse {
+        token,
+        username,
+        role,
+        expires_at,
+        impersonated_by: admin.username.clone(),
+    }))
+}
+
+// GET /api/admin/impersonations
+pub async fn list_impersonations(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+) -> Result<Json<ImpersonationListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, Impersonation>("SELECT * FROM impersonations ORDER BY created_at DESC, id DESC LIMIT 100")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(impersonations) => Ok(Json(ImpersonationListResponse { impersonations })),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/admin/sandbox
+pub async fn create_sandbox(
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    State(jobs): State<Jobs>,
+    Json(payload): Json<SandboxRequest>,
+) -> Result<(StatusCode, Json<JobCreatedResponse>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Sandbox copy request received");
+    if database::POSTGRES {
+        return Err((StatusCode::NOT_IMPLEMENTED, Json(ErrorResponse {
+            error: "Sandbox copies need the SQLite database".to_string(),
+        })));
+    }
+    let history_days = payload.history_days.unwrap_or(0);
+    if !(0..=365).contains(&history_days) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "history_days must be between 0 and 365".to_string(),
+        })));
+    }
+
+    if config.sandbox_database_path == config.database_path {
+        return Err((StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Sandbox database path must differ from the production database".to_string(),
+        })));
+    }
+
+    let history_from = (history_days > 0).then(|| current_timestamp() - history_days * 86400);
+    let sandbox_path = config.sandbox_database_path.clone();
+    let job_pool = pool.clone();
+    let enqueued = jobs
+        .enqueue("sandbox_copy", &admin.username, move |job| async move {
+            let admin_password = uuid::Uuid::new_v4().simple().to_string();
+            let history_rows = database::clone_to_sandbox(&job_pool, &sandbox_path, history_from, &admin_password).await?;
+            job.set_progress(1.0).await;
+
+            Ok(serde_json::json!({
+                "path": sandbox_path.to_string_lossy(),
+                "history_days": history_days,
+                "history_rows": history_rows,
+                "admin_username": database::SANDBOX_ADMIN,
+                "admin_password": admin_password,
+            }))
+        })
+        .await;
+
+    match enqueued {
+        Ok(job_id) => {
+            tracing::info!("Sandbox copy job {} queued", job_id);
+            Ok((StatusCode::ACCEPTED, Json(JobCreatedResponse {
+                job_id,
+                status: "queued".to_string(),
+            })))
+        },
+        Err(_) => {
+            tracing::error!("Failed to queue sandbox copy");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to queue sandbox copy".to_string(),
+            })))
+        },
+    }
+}
+
+// POST /api/admin/backup
+#[derive(Deserialize)]
+pub struct BackupQuery {
+    // Stream the new backup back instead of describing it
+    #[serde(default)]
+    download: bool,
+}
+
+pub async fn create_backup(
+    admin: RequireRole<roles::Admin>,
+    Query(params): Query<BackupQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Backup requested by {}", admin.username);
+    if database::POSTGRES {
+        return Err((StatusCode::NOT_IMPLEMENTED, Json(ErrorResponse {
+            error: "Back up a PostgreSQL database with pg_dump".to_string(),
+        })));
+    }
+    let created = match backup::create(&pool, &config.backup_dir).await {
+        Ok(created) => created,
+        Err(e) => {
+            tracing::error!("Backup failed: {}", e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Backup failed".to_string(),
+            })));
+        },
+    };
+    if !params.download {
+        return Ok((StatusCode::CREATED, Json(created)).into_response());
+    }
+
+    // Backups can be far larger than what should sit in memory, so the file is sent in chunks
+    let mut file = match tokio::fs::File::open(config.backup_dir.join(&created.file_name)).await {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!("Failed to open backup {}: {}", created.file_name, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Backup failed".to_string(),
+            })));
+        },
+    };
+    let (sender, receiver) = mpsc::channel::<std::io::Result<Vec<u8>>>(4);
+    tokio::spawn(async move {
+        loop {
+            let mut chunk = vec![0; 64 * 1024];
+            match file.read(&mut chunk).await {
+                Ok(0) => break,
+                Ok(read) => {
+                    chunk.truncate(read);
+                    if sender.send(Ok(chunk)).await.is_err() {
+                        break;
+                    }
+                },
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                    break;
                 },
             }
+        }
+    });
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/vnd.sqlite3".to_string()),
+            (header::CONTENT_LENGTH, created.size_bytes.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", created.file_name)),
+        ],
+        Body::from_stream(ReceiverStream::new(receiver)),
+    ).into_response())
+}
+
+// GET /api/admin/backups
+pub async fn list_backups(
+    _admin: RequireRole<roles::Admin>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<BackupListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match backup::list(&config.backup_dir).await {
+        Ok(backups) => Ok(Json(BackupListResponse { backups })),
+        Err(e) => {
+            tracing::error!("Failed to list backups: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Failed to list backups".to_string(),
+            })))
+        },
+    }
+}
+
+// GET /api/info
+pub async fn get_info(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    State(read_only): State<ReadOnlyMode>,
+) -> Result<Json<InfoResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match tokio::try_join!(features::load(&pool), site::load(&pool, &config)) {
+        Ok((features, site)) => Ok(Json(InfoResponse {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            features,
+            read_only: read_only.is_enabled(),
+            site,
+        })),
+        Err(e) => {
+            tracing::error!("Database error loading server info: {:?}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })))
+        },
+    }
+}
+
+// Trims members and checks that each names exactly one valid address or existing user
+async fn validate_list_members(
+    pool: &DbPool,
+    members: &[DistributionListMember],
+) -> Result<Vec<DistributionListMember>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }));
+    let mut validated = Vec::with_capacity(members.len());
+    for member in members {
+        let email = member.email.as_deref().map(str::trim).filter(|email| !email.is_empty());
+        let username = member.username.as_deref().map(str::trim).filter(|username| !username.is_empty());
+        match (email, username) {
+            (Some(email), None) => {
+                if !is_valid_email(email) {
+                    return Err(bad_request(format!("Invalid email address '{}'", email)));
+                }
+            },
+            (None, Some(username)) => {
+                let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM users WHERE username = $1")
+                    .bind(username)
+                    .fetch_one(pool)
+                    .await
+                    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                        error: "Database error".to_string(),
+                    })))?;
+                if !exists {
+                    return Err(bad_request(format!("Unknown user '{}'", username)));
+                }
+            },
+            _ => return Err(bad_request("Each member needs either an email or a username".to_string())),
+        }
+        validated.push(DistributionListMember {
+            email: email.map(str::to_string),
+            username: username.map(str::to_string),
+        });
+    }
+    Ok(validated)
+}
+
+// GET /api/distribution-lists
+pub async fn list_distribution_lists(
+    _manager: RequireRole<roles::Manager>,
+    State(pool): State<DbPool>,
+) -> Result<Json<DistributionListListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM distribution_lists ORDER BY name")
+        .fetch_all(&pool)
+        .await
+        .map_err(database_error)?;
+
+    let mut lists = Vec::with_capacity(ids.len());
+    for id in ids {
+        if let Some(list) = distribution::load(&pool, id).await.map_err(database_error)? {
+            lists.push(list);
+        }
+    }
+    Ok(Json(DistributionListListResponse { lists }))
+}
+
+// GET /api/distribution-lists/{id}
+pub async fn get_distribution_list(
+    _manager: RequireRole<roles::Manager>,
+    Path(list_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<Json<DistributionList>, (StatusCode, Json<ErrorResponse>)> {
+    match distribution::load(&pool, list_id).await {
+        Ok(Some(list)) => Ok(Json(list)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Distribution list not found".to_string(),
+        }))),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// POST /api/distribution-lists
+pub async fn create_distribution_list(
+    manager: RequireRole<roles::Manager>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateDistributionListRequest>,
+) -> Result<(StatusCode, Json<DistributionList>), (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Create distribution list request received: {}", payload.name);
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Name must not be empty".to_string(),
+        })));
+    }
+    let members = validate_list_members(&pool, &payload.members).await?;
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+
+    let timestamp = current_timestamp();
+    let mut tx = pool.begin().await.map_err(database_error)?;
+    let list_id: i64 = match sqlx::query_scalar(
+        "INSERT INTO distribution_lists (name, description, created_by, created_at, updated_at) \
+         VALUES ($1, $2, $3, $4, $5) RETURNING id"
+    )
+    .bind(name)
+    .bind(&payload.description)
+    .bind(&manager.username)
+    .bind(timestamp)
+    .bind(timestamp)
+    .fetch_one(&mut *tx)
+    .await
+    {
+        Ok(list_id) => list_id,
+        Err(_) => {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "Distribution list already exists".to_string(),
+            })));
+        },
+    };
+    distribution::replace_members(&mut tx, list_id, &members).await.map_err(database_error)?;
+    tx.commit().await.map_err(database_error)?;
+
+    tracing::info!("{} created distribution list {} with {} member(s)", manager.username, name, members.len());
+    match distribution::load(&pool, list_id).await {
+        Ok(Some(list)) => Ok((StatusCode::CREATED, Json(list))),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/distribution-lists/{id}
+pub async fn update_distribution_list(
+    manager: RequireRole<roles::Manager>,
+    Path(list_id): Path<i64>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateDistributionListRequest>,
+) -> Result<Json<DistributionList>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update distribution list request received for list ID: {}", list_id);
+    let name = payload.name.as_deref().map(str::trim);
+    if name == Some("") {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Name must not be empty".to_string(),
+        })));
+    }
+    let members = match &payload.members {
+        Some(members) => Some(validate_list_members(&pool, members).await?),
+        None => None,
+    };
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+
+    let mut tx = pool.begin().await.map_err(database_error)?;
+    let updated = sqlx::query(
+        "UPDATE distribution_lists SET name = COALESCE($1, name), description = COALESCE($2, description), updated_at = $3 \
+         WHERE id = $4"
+    )
+    .bind(name)
+    .bind(&payload.description)
+    .bind(current_timestamp())
+    .bind(list_id)
+    .execute(&mut *tx)
+    .await;
+    match updated {
+        Ok(result) if result.rows_affected() == 0 => {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Distribution list not found".to_string(),
+            })));
+        },
+        Ok(_) => {},
+        Err(_) => {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "Distribution list already exists".to_string(),
+            })));
+        },
+    }
+    if let Some(members) = &members {
+        distribution::replace_members(&mut tx, list_id, members).await.map_err(database_error)?;
+    }
+    tx.commit().await.map_err(database_error)?;
+
+    tracing::info!("{} updated distribution list {}", manager.username, list_id);
+    match distribution::load(&pool, list_id).await {
+        Ok(Some(list)) => Ok(Json(list)),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// DELETE /api/distribution-lists/{id}
+pub async fn delete_distribution_list(
+    manager: RequireRole<roles::Manager>,
+    Path(list_id): Path<i64>,
+    State(pool): State<DbPool>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let mut tx = pool.begin().await.map_err(database_error)?;
+    sqlx::query("DELETE FROM distribution_list_members WHERE list_id = $1")
+        .bind(list_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(database_error)?;
+    let deleted = sqlx::query("DELETE FROM distribution_lists WHERE id = $1")
+        .bind(list_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(database_error)?;
+    if deleted.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Distribution list not found".to_string(),
+        })));
+    }
+    tx.commit().await.map_err(database_error)?;
+
+    tracing::info!("{} deleted distribution list {}", manager.username, list_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// POST /api/distribution-lists/{id}/test
+pub async fn send_distribution_list_test(
+    manager: RequireRole<roles::Manager>,
+    Path(list_id): Path<i64>,
+    State(pool): State<DbPool>,
+    State(mailer): State<Mailer>,
+) -> Result<(StatusCode, Json<DistributionList>), (StatusCode, Json<ErrorResponse>)> {
+    if !mailer.is_configured() {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, Json(ErrorResponse {
+            error: "Email is not configured".to_string(),
+        })));
+    }
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    let Some(list) = distribution::load(&pool, list_id).await.map_err(database_error)? else {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Distribution list not found".to_string(),
+        })));
+    };
+    let plant_name = load_plant_name(&pool).await?;
+
+    // Sent in the background like other mail; failures show up in the log per recipient
+    let subject = format!("{}: test message for {}", plant_name, list.name);
+    let body = format!(
+        "This is a test message from {}, sent by {} to the distribution list '{}'.\n\n\
+         Reports and alerts addressed to this list will reach you at this address.\n",
+        plant_name, manager.username, list.name,
+    );
+    let recipients = list.recipients.clone();
+    tokio::spawn(async move {
+        for recipient in recipients {
+            if let Err(e) = mailer.send(&recipient, &subject, body.clone()).await {
+                tracing::error!("Failed to send test message to {}: {}", recipient, e);
+            }
+        }
+    }.in_current_span());
+
+    tracing::info!("{} sent a test message to distribution list {}", manager.username, list_id);
+    Ok((StatusCode::ACCEPTED, Json(list)))
+}
+
+// GET /api/site-settings
+pub async fn get_site_settings(
+    _user: AuthUser,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<SiteSettings>, (StatusCode, Json<ErrorResponse>)> {
+    match site::load(&pool, &config).await {
+        Ok(settings) => Ok(Json(settings)),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/site-settings
+pub async fn update_site_settings(
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<UpdateSiteSettingsRequest>,
+) -> Result<Json<SiteSettings>, (StatusCode, Json<ErrorResponse>)> {
+    let plant_name = payload.plant_name.as_deref().map(str::trim);
+    if plant_name == Some("") {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "Plant name must not be empty".to_string(),
+        })));
+    }
+    let default_locale = payload.default_locale.as_deref().map(|locale| locale.trim().to_lowercase());
+
+    // Shift names are stored one per line, in the order of SCADA_SHIFT_STARTS
+    let shift_names = match &payload.shift_names {
+        Some(names) if names.len() != config.shift_starts.len() => {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: format!("Expected {} shift names, one per configured shift", config.shift_starts.len()),
+            })));
+        },
+        Some(names) if names.iter().any(|name| name.trim().is_empty() || name.contains('\n')) => {
+            return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+                error: "Shift names must be single non-empty lines".to_string(),
+            })));
+        },
+        Some(names) => Some(names.iter().map(|name| name.trim()).collect::<Vec<_>>().join("\n")),
+        None => None,
+    };
+
+    let updated = sqlx::query(
+        "INSERT INTO site_settings (id, plant_name, default_locale, shift_names, updated_by, updated_at) \
+         VALUES (1, $1, NULLIF($2, ''), $3, $4, $5) \
+         ON CONFLICT (id) DO UPDATE SET \
+         plant_name = COALESCE(excluded.plant_name, site_settings.plant_name), \
+         default_locale = CASE WHEN $6 IS NULL THEN site_settings.default_locale ELSE excluded.default_locale END, \
+         shift_names = COALESCE(excluded.shift_names, site_settings.shift_names), \
+         updated_by = excluded.updated_by, updated_at = excluded.updated_at"
+    )
+    .bind(plant_name)
+    .bind(&default_locale)
+    .bind(&shift_names)
+    .bind(&admin.username)
+    .bind(current_timestamp())
+    .bind(&default_locale)
+    .execute(&pool)
+    .await;
+
+    match updated {
+        Ok(_) => match site::load(&pool, &config).await {
+            Ok(settings) => {
+                tracing::info!("Site settings updated by {}", admin.username);
+                Ok(Json(settings))
+            },
+            Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            }))),
+        },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to update site settings".to_string(),
+        }))),
+    }
+}
+
+// PUT /api/site-settings/logo
+pub async fn upload_site_logo(
+    headers: HeaderMap,
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    body: axum::body::Bytes,
+) -> Result<Json<SiteSettings>, (StatusCode, Json<ErrorResponse>)> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    if !content_type.starts_with("image/") {
+        return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, Json(ErrorResponse {
+            error: "The logo must be an image".to_string(),
+        })));
+    }
+    if body.is_empty() || body.len() > site::MAX_LOGO_BYTES {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("The logo must be between 1 byte and {} KB", site::MAX_LOGO_BYTES / 1024),
+        })));
+    }
+
+    let written = match tokio::fs::create_dir_all(&config.document_dir).await {
+        Ok(_) => tokio::fs::write(site::logo_path(&config), &body).await,
+        Err(e) => Err(e),
+    };
+    if written.is_err() {
+        tracing::error!("Failed to store site logo");
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to store file".to_string(),
+        })));
+    }
+
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    sqlx::query(
+        "INSERT INTO site_settings (id, logo_content_type, updated_by, updated_at) VALUES (1, $1, $2, $3) \
+         ON CONFLICT (id) DO UPDATE SET logo_content_type = excluded.logo_content_type, \
+         updated_by = excluded.updated_by, updated_at = excluded.updated_at"
+    )
+    .bind(&content_type)
+    .bind(&admin.username)
+    .bind(current_timestamp())
+    .execute(&pool)
+    .await
+    .map_err(database_error)?;
+
+    tracing::info!("Site logo uploaded by {}", admin.username);
+    Ok(Json(site::load(&pool, &config).await.map_err(database_error)?))
+}
+
+// GET /api/site-settings/logo
+pub async fn download_site_logo(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    // Public, so login pages and emailed reports can show it
+    let content_type: Option<String> = sqlx::query_scalar("SELECT logo_content_type FROM site_settings WHERE id = 1")
+        .fetch_optional(&pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten();
+    let not_found = || (StatusCode::NOT_FOUND, Json(ErrorResponse {
+        error: "No logo uploaded".to_string(),
+    }));
+    let content_type = content_type.ok_or_else(not_found)?;
+
+    match tokio::fs::read(site::logo_path(&config)).await {
+        Ok(contents) => Ok(([(header::CONTENT_TYPE, content_type)], contents).into_response()),
+        Err(_) => Err(not_found()),
+    }
+}
+
+// DELETE /api/site-settings/logo
+pub async fn delete_site_logo(
+    admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query(
+        "UPDATE site_settings SET logo_content_type = NULL, updated_by = $1, updated_at = $2 \
+         WHERE id = 1 AND logo_content_type IS NOT NULL"
+    )
+    .bind(&admin.username)
+    .bind(current_timestamp())
+    .execute(&pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "No logo uploaded".to_string(),
+        }))),
+        Ok(_) => {
+            let _ = tokio::fs::remove_file(site::logo_path(&config)).await;
+            tracing::info!("Site logo removed by {}", admin.username);
+            Ok(StatusCode::NO_CONTENT)
         },
-        Err(e) => {
-            println!("[LOG] Failed to update machine: {}", machine_id);
-            if e.to_string().contains("UNIQUE constraint failed") {
-                Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
-                    error: "Machine name or code already exists".to_string(),
-                })))
-            } else {
-                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
-                    error: "Failed to update machine".to_string(),
-                })))
-            }
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Database error".to_string(),
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+// POST /api/admin/history/purge
+#[derive(Deserialize)]
+pub struct HistoryPurgeQuery {
+    machine_id: Option<i64>,
+}
+
+pub async fn purge_history(
+    admin: RequireRole<roles::Admin>,
+    Query(params): Query<HistoryPurgeQuery>,
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    State(jobs): State<Jobs>,
+) -> Result<(StatusCode, Json<JobCreatedResponse>), (StatusCode, Json<ErrorResponse>)> {
+    if let Some(machine_id) = params.machine_id {
+        let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM machines WHERE id = $1")
+            .bind(machine_id)
+            .fetch_one(&pool)
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })))?;
+        if !exists {
+            return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Machine not found".to_string(),
+            })));
+        }
+    }
+
+    // The same purge the hourly task runs, so it applies the configured retention only
+    let retention = HistoryRetention::from_config(&config);
+    let job_pool = pool.clone();
+    let machine_id = params.machine_id;
+    match jobs
+        .enqueue("history_purge", &admin.username, move |job| async move {
+            retention::purge_history(&job_pool, &retention, machine_id, Some(&job)).await
+        })
+        .await
+    {
+        Ok(job_id) => {
+            tracing::info!("History purge job {} queued by {}", job_id, admin.username);
+            Ok((StatusCode::ACCEPTED, Json(JobCreatedResponse {
+                job_id,
+                status: "queued".to_string(),
+            })))
         },
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "Failed to queue purge".to_string(),
+        }))),
     }
 }
 
-// GET /api/users
-pub async fn list_users(
-    headers: HeaderMap,
+// GET /api/admin/usage
+pub async fn get_api_usage(
+    _admin: RequireRole<roles::Admin>,
+    Query(params): Query<UsageQuery>,
     State(pool): State<DbPool>,
-) -> Result<Json<UserListResponse>, (StatusCode, Json<ErrorResponse>)> {
-    println!("[LOG] List users request received");
-    require_admin(&headers, &pool).await?;
+    State(usage): State<UsageRecorder>,
+) -> Result<Json<ApiUsageResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let to = params.to.unwrap_or_else(current_timestamp);
+    let from = params.from.unwrap_or(to - 24 * 60 * 60);
+    let database_error = |_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }));
+    // Include the requests of the last minute, which are only counted in memory so far
+    if let Err(e) = usage.flush(&pool).await {
+        tracing::error!("Failed to write API usage: {}", e);
+    }
 
-    match sqlx::query_as::<_, User>("SELECT * FROM users ORDER BY username").fetch_all(&pool).await {
-        Ok(users) => {
-            println!("[LOG] Users listed successfully");
-            Ok(Json(UserListResponse { users }))
+    // Counts are hourly, so every hour overlapping the range is included
+    let rows: Vec<(String, String, String, i64, i64, i64, i64)> = sqlx::query_as(
+        "SELECT principal_kind, principal, endpoint, CAST(SUM(requests) AS BIGINT), CAST(SUM(client_errors) AS BIGINT), \
+         CAST(SUM(server_errors) AS BIGINT), CAST(SUM(total_ms) AS BIGINT) \
+         FROM api_usage WHERE hour > $1 - 3600 AND hour < $2 \
+         GROUP BY principal_kind, principal, endpoint"
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(&pool)
+    .await
+    .map_err(database_error)?;
+
+    let mut principals: Vec<((String, String), ApiUsagePrincipal)> = Vec::new();
+    for (kind, principal, endpoint, requests, client_errors, server_errors, total_ms) in rows {
+        let key = (kind, principal);
+        let index = match principals.iter().position(|(existing, _)| *existing == key) {
+            Some(index) => index,
+            None => {
+                principals.push((key.clone(), ApiUsagePrincipal {
+                    kind: key.0.clone(),
+                    name: (key.0 == "user").then(|| key.1.clone()),
+                    machine_id: None,
+                    api_key_id: None,
+                    requests: 0,
+                    client_errors: 0,
+                    server_errors: 0,
+                    error_rate: 0.0,
+                    endpoints: Vec::new(),
+                }));
+                principals.len() - 1
+            },
+        };
+        let summary = &mut principals[index].1;
+        summary.requests += requests;
+        summary.client_errors += client_errors;
+        summary.server_errors += server_errors;
+        summary.endpoints.push(ApiUsageEndpoint {
+            endpoint,
+            requests,
+            client_errors,
+            server_errors,
+            avg_duration_ms: total_ms as f64 / requests.max(1) as f64,
+        });
+    }
+
+    // Machine keys are recorded by hash; find the machine, and the key if it is an additional one
+    for ((kind, key_hash), summary) in &mut principals {
+        if kind != "machine_key" {
+            continue;
+        }
+        let machine: Option<(i64, String)> = sqlx::query_as("SELECT id, name FROM machines WHERE api_key = $1")
+            .bind(&*key_hash)
+            .fetch_optional(&pool)
+            .await
+            .map_err(database_error)?;
+        let machine = match machine {
+            Some((id, name)) => Some((id, name, None)),
+            None => sqlx::query_as::<_, (i64, String, i64)>(
+                "SELECT m.id, m.name, k.id FROM machine_api_keys k JOIN machines m ON m.id = k.machine_id WHERE k.api_key = $1"
+            )
+            .bind(&*key_hash)
+            .fetch_optional(&pool)
+            .await
+            .map_err(database_error)?
+            .map(|(id, name, key_id)| (id, name, Some(key_id))),
+        };
+        if let Some((machine_id, name, api_key_id)) = machine {
+            summary.machine_id = Some(machine_id);
+            summary.name = Some(name);
+            summary.api_key_id = api_key_id;
+        }
+    }
+
+    // Service account keys too, including a previous key still in its grace period, and display tokens
+    for ((kind, key_hash), summary) in &mut principals {
+        summary.name = match kind.as_str() {
+            "service_account" => sqlx::query_scalar("SELECT name FROM service_accounts WHERE api_key = $1 OR previous_api_key = $2")
+                .bind(&*key_hash)
+                .bind(&*key_hash)
+                .fetch_optional(&pool)
+                .await
+                .map_err(database_error)?,
+            "display_token" => sqlx::query_scalar("SELECT name FROM display_tokens WHERE token = $1")
+                .bind(&*key_hash)
+                .fetch_optional(&pool)
+                .await
+                .map_err(database_error)?,
+            _ => continue,
+        };
+    }
+
+    let mut principals: Vec<ApiUsagePrincipal> = principals.into_iter().map(|(_, summary)| summary).collect();
+    for summary in &mut principals {
+        summary.error_rate = (summary.client_errors + summary.server_errors) as f64 / summary.requests.max(1) as f64;
+        summary.endpoints.sort_by_key(|endpoint| std::cmp::Reverse(endpoint.requests));
+        summary.endpoints.truncate(10);
+    }
+    principals.sort_by_key(|summary| std::cmp::Reverse(summary.requests));
+
+    Ok(Json(ApiUsageResponse {
+        from,
+        to,
+        requests: principals.iter().map(|summary| summary.requests).sum(),
+        principals,
+    }))
+}
+
+// GET /api/admin/features
+pub async fn list_feature_flags(
+    _admin: RequireRole<roles::Admin>,
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<FeatureFlag>>, (StatusCode, Json<ErrorResponse>)> {
+    match sqlx::query_as::<_, FeatureFlag>("SELECT * FROM feature_flags ORDER BY name")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(flags) => Ok(Json(flags)),
+        Err(e) => {
+            tracing::error!("Database error listing feature flags: {:?}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: "Database error".to_string(),
+            })))
         },
-        Err(_) => {
-            println!("[LOG] Failed to list users");
+    }
+}
+
+// PUT /api/admin/features/{name}
+pub async fn update_feature_flag(
+    admin: RequireRole<roles::Admin>,
+    Path(name): Path<String>,
+    State(pool): State<DbPool>,
+    Json(payload): Json<UpdateFeatureFlagRequest>,
+) -> Result<Json<FeatureFlag>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update feature flag request received: {} = {}", name, payload.enabled);
+    match sqlx::query_as::<_, FeatureFlag>(
+        "UPDATE feature_flags SET enabled = $1, updated_by = $2, updated_at = $3 WHERE name = $4 RETURNING *"
+    )
+    .bind(payload.enabled)
+    .bind(&admin.username)
+    .bind(current_timestamp())
+    .bind(&name)
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(flag)) => {
+            tracing::info!("Feature {} {}", name, if flag.enabled { "enabled" } else { "disabled" });
+            Ok(Json(flag))
+        },
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: format!("Unknown feature. Must be one of: {}", features::FEATURES.join(", ")),
+        }))),
+        Err(e) => {
+            tracing::error!("Database error updating feature flag: {:?}", e);
             Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
                 error: "Database error".to_string(),
             })))
         },
     }
-}
\ No newline at end of file
+}
+
+// GET /api/admin/read-only
+pub async fn get_read_only(
+    _admin: RequireRole<roles::Admin>,
+    State(read_only): State<ReadOnlyMode>,
+) -> Result<Json<ReadOnlyResponse>, (StatusCode, Json<ErrorResponse>)> {
+    Ok(Json(read_only.status()))
+}
+
+// PUT /api/admin/read-only
+pub async fn update_read_only(
+    admin: RequireRole<roles::Admin>,
+    State(read_only): State<ReadOnlyMode>,
+    Json(payload): Json<UpdateReadOnlyRequest>,
+) -> Result<Json<ReadOnlyResponse>, (StatusCode, Json<ErrorResponse>)> {
+    tracing::info!("Update read-only mode request received: {}", payload.enabled);
+    read_only.set(payload.enabled, &admin.username);
+    tracing::warn!("Read-only mode {} by {}", if payload.enabled { "enabled" } else { "disabled" }, admin.username);
+    Ok(Json(read_only.status()))
+}
+
+// GET /api/dev/chaos
+pub async fn get_chaos(
+    _admin: RequireRole<roles::Admin>,
+    State(chaos): State<Chaos>,
+) -> Result<Json<ChaosStatus>, (StatusCode, Json<ErrorResponse>)> {
+    Ok(Json(chaos.status()))
+}
+
+// DELETE /api/dev/chaos
+pub async fn reset_chaos(
+    _admin: RequireRole<roles::Admin>,
+    State(chaos): State<Chaos>,
+) -> Result<Json<ChaosStatus>, (StatusCode, Json<ErrorResponse>)> {
+    chaos.reset();
+    tracing::warn!("Chaos injection reset");
+    Ok(Json(chaos.status()))
+}
+
+// PUT /api/dev/chaos/latency
+pub async fn set_chaos_latency(
+    _admin: RequireRole<roles::Admin>,
+    State(chaos): State<Chaos>,
+    Json(payload): Json<ChaosLatency>,
+) -> Result<Json<ChaosStatus>, (StatusCode, Json<ErrorResponse>)> {
+    if payload.millis > 60_000 {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "millis must be at most 60000".to_string(),
+        })));
+    }
+
+    tracing::warn!("Injecting {} ms latency into {}", payload.millis, payload.path_prefix.as_deref().unwrap_or("all requests"));
+    chaos.set_latency((payload.millis > 0).then_some(payload));
+    Ok(Json(chaos.status()))
+}
+
+// PUT /api/dev/chaos/db-failures
+pub async fn set_chaos_db_failures(
+    _admin: RequireRole<roles::Admin>,
+    State(chaos): State<Chaos>,
+    Json(payload): Json<FailDbCallsRequest>,
+) -> Result<Json<ChaosStatus>, (StatusCode, Json<ErrorResponse>)> {
+    if database::POSTGRES {
+        return Err((StatusCode::NOT_IMPLEMENTED, Json(ErrorResponse {
+            error: "Injected statement failures need the SQLite database".to_string(),
+        })));
+    }
+    tracing::warn!("Failing the next {} database statements", payload.count);
+    chaos.fail_db_calls(payload.count);
+    Ok(Json(chaos.status()))
+}
+
+// POST /api/dev/chaos/tasks/{name}/kill
+pub async fn kill_background_task(
+    _admin: RequireRole<roles::Admin>,
+    Path(name): Path<String>,
+    State(chaos): State<Chaos>,
+) -> Result<Json<ChaosStatus>, (StatusCode, Json<ErrorResponse>)> {
+    if !chaos.kill_task(&name) {
+        return Err((StatusCode::NOT_FOUND, Json(ErrorResponse {
+            error: "Background task not found".to_string(),
+        })));
+    }
+
+    tracing::warn!("Background task {} killed", name);
+    Ok(Json(chaos.status()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A migrated in-memory database with one machine, last updated at 100 with speed 10, running
+    // lot L-1 of product A with a product changeover still open
+    async fn database() -> DbPool {
+        let pool = database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key, current_speed, status_message, last_update) \
+             VALUES (1, 'Line 1', 'L1', 'key', 10.0, 'running', 100)",
+            "INSERT INTO machine_batches (machine_id, lot_number, product, started_at) VALUES (1, 'L-1', 'A', 50)",
+            "INSERT INTO changeovers (machine_id, from_product, to_product, source, started_at) \
+             VALUES (1, 'Z', 'A', 'product_change', 40)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    // Makes inserts into `table` of rows matching `condition` fail, like a full disk would
+    async fn fail_inserts(pool: &DbPool, table: &str, condition: &str) {
+        let statements = if database::POSTGRES {
+            vec![
+                "CREATE FUNCTION fail_insert() RETURNS trigger LANGUAGE plpgsql AS $$ BEGIN RAISE EXCEPTION 'disk full'; END $$"
+                    .to_string(),
+                format!(
+                    "CREATE TRIGGER fail_{0} BEFORE INSERT ON {0} FOR EACH ROW WHEN ({1}) EXECUTE FUNCTION fail_insert()",
+                    table, condition
+                ),
+            ]
+        } else {
+            vec![format!(
+                "CREATE TRIGGER fail_{0} BEFORE INSERT ON {0} WHEN {1} BEGIN SELECT RAISE(ABORT, 'disk full'); END",
+                table, condition
+            )]
+        };
+        for statement in statements {
+            sqlx::query(&statement).execute(pool).await.unwrap();
+        }
+    }
+
+    fn signed_in(username: &str, role: Role) -> AuthUser {
+        AuthUser { username: username.to_string(), role, session_id: None, impersonated_by: None }
+    }
+
+    fn admin() -> AuthUser {
+        signed_in("admin", Role::Admin)
+    }
+
+    fn update<'a>(readings: &'a [(&'a str, f64, String, bool)]) -> TelemetryUpdate<'a> {
+        TelemetryUpdate {
+            machine_id: 1,
+            speed: 42.0,
+            message: "fast",
+            clock_drift: Some(3),
+            readings,
+            lot_number: Some("L-2"),
+            product: Some("B"),
+            max_clock_drift: 30,
+            repeated: false,
+            timestamp: 200,
+        }
+    }
+
+    async fn machine_state(pool: &DbPool) -> (f64, String, i64, Option<i64>) {
+        sqlx::query_as("SELECT current_speed, status_message, last_update, clock_drift FROM machines WHERE id = 1")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn count(pool: &DbPool, query: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", query)).fetch_one(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn update_stores_state_and_history_together() {
+        let pool = database().await;
+        let readings = [("pressure", 2.4, "bar".to_string(), false), ("temperature", 900.0, "degC".to_string(), true)];
+        store_update(&pool, &update(&readings)).await.unwrap();
+
+        assert_eq!(machine_state(&pool).await, (42.0, "fast".to_string(), 200, Some(3)));
+        let history: (f64, String, i64) = sqlx::query_as("SELECT speed, message, timestamp FROM speed_history WHERE machine_id = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(history, (42.0, "fast".to_string(), 200));
+        assert_eq!(count(&pool, "metric_readings").await, 2);
+        // The flagged reading is kept but does not become a current value
+        let metrics: Vec<String> = sqlx::query_scalar("SELECT metric FROM machine_metrics").fetch_all(&pool).await.unwrap();
+        assert_eq!(metrics, ["pressure"]);
+        // The new lot replaced the old one, and running again ended the open changeover
+        assert_eq!(count(&pool, "machine_batches WHERE ended_at IS NULL AND lot_number = 'L-2'").await, 1);
+        assert_eq!(count(&pool, "changeovers WHERE ended_at IS NULL AND started_at < 200").await, 0);
+        assert_eq!(count(&pool, "alarm_rules").await, 1);
+    }
+
+    #[tokio::test]
+    async fn failed_history_write_leaves_machine_unchanged() {
+        let pool = database().await;
+        fail_inserts(&pool, "speed_history", "TRUE").await;
+
+        assert!(store_update(&pool, &update(&[])).await.is_err());
+        assert_eq!(machine_state(&pool).await, (10.0, "running".to_string(), 100, None));
+    }
+
+    #[tokio::test]
+    async fn failed_reading_write_rolls_back_the_whole_update() {
+        let pool = database().await;
+        fail_inserts(&pool, "metric_readings", "NEW.metric = 'temperature'").await;
+
+        let readings = [("pressure", 2.4, "bar".to_string(), false), ("temperature", 71.5, "degC".to_string(), false)];
+        assert!(store_update(&pool, &update(&readings)).await.is_err());
+        assert_eq!(machine_state(&pool).await, (10.0, "running".to_string(), 100, None));
+        assert_eq!(count(&pool, "speed_history").await, 0);
+        assert_eq!(count(&pool, "metric_readings").await, 0);
+        assert_eq!(count(&pool, "machine_metrics").await, 0);
+        // Neither the lot change, the end of the changeover nor the clock drift rule is kept
+        let batches: Vec<(String, Option<i64>)> = sqlx::query_as("SELECT lot_number, ended_at FROM machine_batches")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(batches, [("L-1".to_string(), None)]);
+        let changeovers: Vec<(i64, Option<i64>)> = sqlx::query_as("SELECT started_at, ended_at FROM changeovers")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(changeovers, [(40, None)]);
+        assert_eq!(count(&pool, "alarm_rules").await, 0);
+    }
+
+    // Whether an update at `now` of the update() helper's speed and message with `readings`
+    // repeats the stored values within a 300 s window
+    async fn repeats(pool: &DbPool, speed: f64, readings: &[(&str, f64, String, bool)], now: i64) -> bool {
+        is_repeated_update(pool, 1, speed, "fast", readings, now, Duration::from_secs(300)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn repeated_update_only_refreshes_the_heartbeat() {
+        let pool = database().await;
+        let readings = [("pressure", 2.4, "bar".to_string(), false)];
+        store_update(&pool, &update(&readings)).await.unwrap();
+
+        assert!(repeats(&pool, 42.0, &readings, 250).await);
+        store_update(&pool, &TelemetryUpdate { repeated: true, timestamp: 250, ..update(&readings) }).await.unwrap();
+        assert_eq!(machine_state(&pool).await, (42.0, "fast".to_string(), 250, Some(3)));
+        assert_eq!(count(&pool, "speed_history").await, 1);
+        assert_eq!(count(&pool, "metric_readings").await, 1);
+    }
+
+    #[tokio::test]
+    async fn changed_or_flagged_updates_are_stored() {
+        let pool = database().await;
+        let readings = [("pressure", 2.4, "bar".to_string(), false)];
+        store_update(&pool, &update(&readings)).await.unwrap();
+
+        assert!(!repeats(&pool, 43.0, &readings, 250).await);
+        assert!(!repeats(&pool, 42.0, &[("pressure", 2.5, "bar".to_string(), false)], 250).await);
+        assert!(!repeats(&pool, 42.0, &[("pressure", 2.4, "bar".to_string(), true)], 250).await);
+        // Once the window has passed the same values are stored again
+        assert!(!repeats(&pool, 42.0, &readings, 600).await);
+    }
+
+    #[tokio::test]
+    async fn machines_can_opt_out_of_dedup() {
+        let pool = database().await;
+        store_update(&pool, &update(&[])).await.unwrap();
+        assert!(repeats(&pool, 42.0, &[], 250).await);
+
+        sqlx::query("UPDATE machines SET dedup_updates = FALSE WHERE id = 1").execute(&pool).await.unwrap();
+        assert!(!repeats(&pool, 42.0, &[], 250).await);
+    }
+
+    #[test]
+    fn report_windows_take_a_named_range_or_default_span() {
+        let config = Config::from_env().unwrap();
+        let (from, to) = report_window(Some("last_24h"), None, None, 3600, &config).unwrap();
+        assert_eq!(to - from, 24 * 60 * 60);
+        assert_eq!(report_window(Some("last_24h"), Some(5), Some(10), 3600, &config).unwrap(), (5, 10));
+        assert_eq!(report_window(None, None, Some(10_000), 3600, &config).unwrap(), (6400, 10_000));
+        let Err((status, _)) = report_window(Some("next_week"), None, None, 3600, &config) else {
+            panic!("unknown range accepted");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn update_of_unknown_machine_stores_nothing() {
+        let pool = database().await;
+        let update = TelemetryUpdate { machine_id: 2, ..update(&[]) };
+        assert!(store_update(&pool, &update).await.is_err());
+        assert_eq!(count(&pool, "speed_history").await, 0);
+        assert_eq!(count(&pool, "machine_batches").await, 1);
+    }
+
+    #[tokio::test]
+    async fn change_feed_resumes_after_writes_in_the_same_second() {
+        let pool = database().await;
+        let after = ChangesAfter::Cursor(0);
+        let first = machine_changes_response(after, fetch_changed_machines(&pool, &admin(), after).await.unwrap());
+        assert_eq!(first.machines.len(), 1);
+
+        // Both writes carry the same timestamp as the one already delivered
+        store_update(&pool, &TelemetryUpdate { timestamp: 100, ..update(&[]) }).await.unwrap();
+        let after = ChangesAfter::Cursor(first.cursor);
+        let second = machine_changes_response(after, fetch_changed_machines(&pool, &admin(), after).await.unwrap());
+        assert_eq!(second.machines.len(), 1);
+        assert_eq!(second.machines[0].current_speed, 42.0);
+        assert!(second.cursor > first.cursor);
+
+        let after = ChangesAfter::Cursor(second.cursor);
+        assert!(fetch_changed_machines(&pool, &admin(), after).await.unwrap().is_empty());
+        store_update(&pool, &TelemetryUpdate { speed: 7.0, timestamp: 100, ..update(&[]) }).await.unwrap();
+        let third = machine_changes_response(after, fetch_changed_machines(&pool, &admin(), after).await.unwrap());
+        assert_eq!(third.machines[0].current_speed, 7.0);
+    }
+
+    #[tokio::test]
+    async fn every_machine_write_takes_the_next_change_number() {
+        let pool = database().await;
+        let seq = async |id: i64| -> i64 {
+            sqlx::query_scalar("SELECT change_seq FROM machines WHERE id = $1").bind(id).fetch_one(&pool).await.unwrap()
+        };
+        let first = seq(1).await;
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (2, 'Line 2', 'L2', 'key-2')").execute(&pool).await.unwrap();
+        assert_eq!(seq(2).await, first + 1);
+        sqlx::query("UPDATE machines SET name = 'Line One' WHERE id = 1").execute(&pool).await.unwrap();
+        assert_eq!(seq(1).await, first + 2);
+
+        // Setting the number directly is not a change, and numbers are not reused after a delete
+        sqlx::query("UPDATE machines SET change_seq = 0 WHERE id = 1").execute(&pool).await.unwrap();
+        assert_eq!(seq(1).await, 0);
+        sqlx::query("DELETE FROM machines WHERE id = 2").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (3, 'Line 3', 'L3', 'key-3')").execute(&pool).await.unwrap();
+        assert_eq!(seq(3).await, first + 3);
+    }
+
+    #[tokio::test]
+    async fn change_feed_reports_archived_machines_by_id_only() {
+        let pool = database().await;
+        let after = ChangesAfter::Cursor(0);
+        let first = machine_changes_response(after, fetch_changed_machines(&pool, &admin(), after).await.unwrap());
+        sqlx::query("UPDATE machines SET archived_at = 300, archived_by = 'admin' WHERE id = 1").execute(&pool).await.unwrap();
+
+        let after = ChangesAfter::Cursor(first.cursor);
+        let resumed = machine_changes_response(after, fetch_changed_machines(&pool, &admin(), after).await.unwrap());
+        assert!(resumed.machines.is_empty());
+        assert_eq!(resumed.archived, [1]);
+
+        // A fresh snapshot leaves it out altogether
+        let after = ChangesAfter::Cursor(0);
+        assert!(fetch_changed_machines(&pool, &admin(), after).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_group_months_in_the_site_timezone() {
+        auth::init_sessions(b"test secret", Duration::from_secs(60), Duration::from_secs(60));
+        let pool = database().await;
+        sqlx::query("INSERT INTO work_orders (id, machine_id, title, created_by) VALUES (1, 1, 'Belt', 'boss')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        // Berlin is UTC+1 until 2026-03-29 and UTC+2 after: the first entry is still February
+        // there, the last already April
+        for (started_at, username) in [(1_772_317_800, "ann"), (1_772_321_400, "bob"), (1_774_992_600, "ann"), (1_774_996_200, "cy")] {
+            sqlx::query("INSERT INTO work_order_labor (work_order_id, username, started_at, stopped_at) VALUES (1, $1, $2, $3)")
+                .bind(username)
+                .bind(started_at)
+                .bind(started_at + 1800)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO maintenance_costs (machine_id, description, amount, incurred_at, created_by) VALUES (1, 'Part', 10, $1, 'boss')")
+                .bind(started_at)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let mut config = Config::from_env().unwrap();
+        config.site_timezone = chrono_tz::Tz::Europe__Berlin;
+        let config = Arc::new(config);
+        let (from, to) = (1_769_900_000, 1_777_600_000);
+
+        let query = LaborReportQuery { range: None, from: Some(from), to: Some(to), machine_id: None };
+        let Json(report) = labor_hours_report(require_manager().await, Query(query), State(pool.clone()), State(config.clone()))
+            .await
+            .unwrap();
+        let months: Vec<(&str, i64)> = report.machines.iter().map(|entry| (entry.month.as_str(), entry.technicians)).collect();
+        assert_eq!(months, [("2026-02", 1), ("2026-03", 2), ("2026-04", 1)]);
+
+        let query = MachineCostQuery { from: Some(from), to: Some(to), period: None };
+        let Json(costs) = get_machine_costs(admin(), Path(1), Query(query), State(pool), State(config)).await.unwrap();
+        let periods: Vec<(&str, f64)> = costs.periods.iter().map(|period| (period.period.as_str(), period.summary.parts_cost)).collect();
+        assert_eq!(periods, [("2026-02", 10.0), ("2026-03", 20.0), ("2026-04", 10.0)]);
+    }
+
+    #[tokio::test]
+    async fn machine_list_tag_follows_the_visible_machines() {
+        let pool = database().await;
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (2, 'Line 2', 'L2', 'key-2')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let list = |headers: HeaderMap| list_machines(
+            headers,
+            signed_in("tech", Role::Technician),
+            Query(MachineListQuery { locale: None, include_archived: false, sort: None }),
+            State(pool.clone()),
+        );
+        let etag = |response: &Response| response.headers()[header::ETAG].clone();
+        let if_none_match = |etag| HeaderMap::from_iter([(header::IF_NONE_MATCH, etag)]);
+
+        let first = list(HeaderMap::new()).await.unwrap();
+        assert_eq!(list(if_none_match(etag(&first))).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+
+        // Granting a machine changes which ids are listed
+        sqlx::query("INSERT INTO user_machine_access (username, machine_id, granted_at) VALUES ('tech', 2, 100)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let second = list(if_none_match(etag(&first))).await.unwrap();
+        assert_eq!(second.status(), StatusCode::OK);
+        sqlx::query("DELETE FROM user_machine_access").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO user_machine_access (username, machine_id, granted_at) VALUES ('tech', 1, 100)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        // Same number of machines and no writes to them, but a different one
+        let third = list(if_none_match(etag(&second))).await.unwrap();
+        assert_eq!(third.status(), StatusCode::OK);
+        assert_ne!(etag(&third), etag(&second));
+    }
+
+    #[tokio::test]
+    async fn machine_detail_is_validated_by_its_content_alone() {
+        let pool = database().await;
+        let detail = |headers: HeaderMap| get_machine_detail(
+            headers,
+            admin(),
+            Path(1),
+            Query(LocaleQuery { locale: None }),
+            State(pool.clone()),
+        );
+        let first = detail(HeaderMap::new()).await.unwrap();
+        assert!(first.headers().get(header::LAST_MODIFIED).is_none());
+        let etag = first.headers()[header::ETAG].clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+        assert_eq!(detail(headers.clone()).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+
+        // A reading inside the last 24 hours moves the aggregates without touching the machine
+        sqlx::query("INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES (1, 5.0, 'running', $1)")
+            .bind(current_timestamp())
+            .execute(&pool)
+            .await
+            .unwrap();
+        let second = detail(headers).await.unwrap();
+        assert_eq!(second.status(), StatusCode::OK);
+        assert_ne!(second.headers()[header::ETAG], etag);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MODIFIED_SINCE, http_date(current_timestamp() + 60).parse().unwrap());
+        assert_eq!(detail(headers).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn technician_cannot_reach_ungranted_machine() {
+        let pool = database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key, last_update) VALUES (2, 'Line 2', 'L2', 'key-2', 100)",
+            "INSERT INTO user_machine_access (username, machine_id, granted_at) VALUES ('tech', 2, 100)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        let config = Config::from_env().unwrap();
+        let mailer = Mailer::new(&config).unwrap();
+        let state = AppState::new(pool.clone(), config, Chaos::default(), mailer);
+        let technician = || signed_in("tech", Role::Technician);
+
+        let query = serde_json::from_value(serde_json::json!({ "timeout": 1 })).unwrap();
+        let Json(feed) = wait_for_machine_changes(HeaderMap::new(), technician(), Query(query), State(state.clone()))
+            .await
+            .unwrap();
+        assert_eq!(feed.machines.iter().map(|machine| machine.id).collect::<Vec<_>>(), [2]);
+
+        // A write to machine 1 wakes the poll but must not end it empty-handed
+        let query = serde_json::from_value(serde_json::json!({ "cursor": feed.cursor, "timeout": 5 })).unwrap();
+        let poll = tokio::spawn(wait_for_machine_changes(HeaderMap::new(), technician(), Query(query), State(state.clone())));
+        for machine_id in [1, 2] {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            sqlx::query("UPDATE machines SET current_speed = 5, last_update = 200 WHERE id = $1")
+                .bind(machine_id)
+                .execute(&pool)
+                .await
+                .unwrap();
+            state.machine_changes.notify(200);
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            assert_eq!(poll.is_finished(), machine_id == 2);
+        }
+        let Json(feed) = poll.await.unwrap().unwrap();
+        assert_eq!(feed.machines.iter().map(|machine| machine.id).collect::<Vec<_>>(), [2]);
+
+        let query = serde_json::from_value(serde_json::json!({ "machine_id": 1 })).unwrap();
+        let Err((status, _)) = stream_machines(technician(), Query(query), State(state.clone())).await else {
+            panic!("stream of an ungranted machine was opened");
+        };
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let export = |machine_id| export_history(
+            technician(),
+            Path(machine_id),
+            State(pool.clone()),
+            State(state.config.clone()),
+            State(state.jobs.clone()),
+            Json(serde_json::from_value(serde_json::json!({ "from": 0, "to": 200 })).unwrap()),
+        );
+        let Err((status, _)) = export(1).await else {
+            panic!("export of an ungranted machine was started");
+        };
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(export(2).await.is_ok());
+    }
+
+    fn bearer(token: &str) -> axum::http::request::Parts {
+        let request = axum::http::Request::builder().header("authorization", format!("Bearer {}", token)).body(()).unwrap();
+        request.into_parts().0
+    }
+
+    // Whether a request carrying `token` gets past the AuthUser extractor
+    async fn authorizes(token: &str) -> bool {
+        use axum::extract::FromRequestParts;
+
+        AuthUser::from_request_parts(&mut bearer(token), &()).await.is_ok()
+    }
+
+    async fn require_admin() -> RequireRole<roles::Admin> {
+        use axum::extract::FromRequestParts;
+
+        let token = auth::issue_session_token("admin", "admin", None).unwrap().0;
+        RequireRole::from_request_parts(&mut bearer(&token), &()).await.unwrap()
+    }
+
+    async fn require_manager() -> RequireRole<roles::Manager> {
+        use axum::extract::FromRequestParts;
+
+        let token = auth::issue_session_token("boss", "manager", None).unwrap().0;
+        RequireRole::from_request_parts(&mut bearer(&token), &()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn bulk_exports_need_approval_when_it_is_on() {
+        auth::init_sessions(b"test secret", Duration::from_secs(60), Duration::from_secs(60));
+        let pool = database().await;
+        sqlx::query("INSERT INTO user_machine_access (username, machine_id, granted_at) VALUES ('tech', 1, 100)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let config = Config { export_approval: true, ..Config::from_env().unwrap() };
+        let mailer = Mailer::new(&config).unwrap();
+        let state = AppState::new(pool.clone(), config, Chaos::default(), mailer);
+        let config = state.config.clone();
+
+        let Err((status, _)) = export_history(
+            signed_in("tech", Role::Technician),
+            Path(1),
+            State(pool.clone()),
+            State(config.clone()),
+            State(state.jobs.clone()),
+            Json(serde_json::from_value(serde_json::json!({ "from": 0, "to": 200 })).unwrap()),
+        )
+        .await
+        else {
+            panic!("technician exported history without approval");
+        };
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let query = Query(serde_json::from_value(serde_json::json!({})).unwrap());
+        let Err((status, _)) = export_comments(require_manager().await, query, State(pool.clone()), State(config.clone())).await else {
+            panic!("manager exported comments without approval");
+        };
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let query = Query(serde_json::from_value(serde_json::json!({})).unwrap());
+        let Err((status, _)) = export_alarms(require_manager().await, query, State(pool.clone()), State(config.clone())).await else {
+            panic!("manager exported alarms without approval");
+        };
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Nor can the history endpoint be asked for everything at once
+        sqlx::query(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 3) \
+             INSERT INTO speed_history (machine_id, speed, message, timestamp) SELECT 1, 5.0, 'running', 100 + i FROM n"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let history = async |limit: i64| {
+            let query = Query(serde_json::from_value(serde_json::json!({ "limit": limit, "resolution": "raw" })).unwrap());
+            let technician = signed_in("tech", Role::Technician);
+            let Json(response) = get_history(technician, Path(1), query, State(pool.clone()), State(config.clone())).await.unwrap();
+            response.history.len()
+        };
+        assert_eq!(history(-1).await, 1);
+        assert_eq!(history(2).await, 2);
+    }
+
+    #[tokio::test]
+    async fn new_role_or_password_ends_the_users_sessions() {
+        auth::init_sessions(b"test secret", Duration::from_secs(60), Duration::from_secs(60));
+        let pool = database().await;
+        let config = Arc::new(Config::from_env().unwrap());
+        for (id, username, role) in [(1, "boss", "admin"), (2, "welder", "technician"), (3, "clerk", "technician")] {
+            sqlx::query("INSERT INTO users (id, username, password, role) VALUES ($1, $2, 'x', $3)")
+                .bind(id)
+                .bind(username)
+                .bind(role)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let tokens: Vec<String> = ["boss", "welder", "clerk"]
+            .into_iter()
+            .map(|username| auth::issue_session_token(username, "admin", None).unwrap().0)
+            .collect();
+
+        let update = async |id, body| update_user(
+            require_admin().await,
+            Path(id),
+            State(pool.clone()),
+            State(config.clone()),
+            Json(serde_json::from_value(body).unwrap()),
+        ).await;
+        assert!(update(1, serde_json::json!({ "role": "technician" })).await.is_ok());
+        assert!(update(2, serde_json::json!({ "password": "Conveyor-Belt7" })).await.is_ok());
+        assert!(update(3, serde_json::json!({ "email": "clerk@example.com" })).await.is_ok());
+
+        assert!(!authorizes(&tokens[0]).await);
+        assert!(!authorizes(&tokens[1]).await);
+        assert!(authorizes(&tokens[2]).await);
+    }
+
+    #[tokio::test]
+    async fn changing_the_password_checks_the_current_one_and_ends_other_sessions() {
+        auth::init_sessions(b"test secret", Duration::from_secs(60), Duration::from_secs(60));
+        let pool = database().await;
+        let config = Arc::new(Config::from_env().unwrap());
+        sqlx::query("INSERT INTO users (username, password, role) VALUES ('fitter', $1, 'technician')")
+            .bind(auth::hash_password("Old-Gearbox4").await.unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+        let other_session = auth::issue_session_token("fitter", "technician", None).unwrap().0;
+
+        let change = async |current: &str, new: &str| change_password(
+            signed_in("fitter", Role::Technician),
+            HeaderMap::new(),
+            ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))),
+            State(pool.clone()),
+            State(config.clone()),
+            Json(ChangePasswordRequest { current_password: current.to_string(), new_password: new.to_string() }),
+        ).await;
+
+        let Err(refused) = change("Wrong-Gearbox4", "Conveyor-Belt7").await else {
+            panic!("password changed without the current one");
+        };
+        assert_eq!(refused.status(), StatusCode::FORBIDDEN);
+        assert_eq!(count(&pool, "login_attempts WHERE username = 'fitter' AND failed_attempts = 1").await, 1);
+
+        let Err(unchanged) = change("Old-Gearbox4", "Old-Gearbox4").await else {
+            panic!("password changed to itself");
+        };
+        assert_eq!(unchanged.status(), StatusCode::BAD_REQUEST);
+
+        let Ok(Json(session)) = change("Old-Gearbox4", "Conveyor-Belt7").await else {
+            panic!("password change refused");
+        };
+        assert!(!authorizes(&other_session).await);
+        assert!(authorizes(&session.token).await);
+        assert!(auth::authenticate_user("fitter", "Conveyor-Belt7", &pool).await.is_some());
+        // A successful change also clears the failed attempt
+        assert_eq!(count(&pool, "login_attempts WHERE username = 'fitter'").await, 0);
+    }
+
+    #[tokio::test]
+    async fn replay_sends_every_reading_once_across_pages() {
+        let pool = database().await;
+        // More readings sharing one timestamp than fit in a page, from both tables
+        for statement in [
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 700) \
+             INSERT INTO speed_history (machine_id, speed, message, timestamp) SELECT 1, 5.0, 'running', 150 FROM n",
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 700) \
+             INSERT INTO metric_readings (machine_id, metric, value, unit, flagged, timestamp) \
+             SELECT 1, 'pressure', 2.4, 'bar', FALSE, 150 FROM n",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        let (sender, mut receiver) = mpsc::channel(2000);
+        replay_history(pool, admin(), sender, None, 100, 200, MAX_REPLAY_SPEED).await;
+        let mut events = 0;
+        while receiver.recv().await.is_some() {
+            events += 1;
+        }
+        // Every reading, then the end of the replay
+        assert_eq!(events, 1401);
+    }
+
+    #[tokio::test]
+    async fn own_machine_counts_the_commands_a_poll_would_deliver() {
+        let pool = database().await;
+        sqlx::query("UPDATE machines SET api_key = $1 WHERE id = 1")
+            .bind(auth::hash_token("machine_key"))
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (status, expires_at) in [("pending", None), ("pending", Some(i64::MAX)), ("pending", Some(1)), ("delivered", None)] {
+            sqlx::query(
+                "INSERT INTO machine_commands (machine_id, command_type, parameters, status, created_by, created_at, expires_at) \
+                 VALUES (1, 'start', '{}', $1, 'boss', 0, $2)"
+            )
+            .bind(status)
+            .bind(expires_at)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let config = Config::from_env().unwrap();
+        let mailer = Mailer::new(&config).unwrap();
+        let state = AppState::new(pool.clone(), config, Chaos::default(), mailer);
+        let pending = async || {
+            use axum::extract::FromRequestParts;
+
+            let key = MachineKey::from_request_parts(&mut bearer("machine_key"), &state).await.unwrap();
+            let Json(machine) = get_own_machine(key, State(pool.clone())).await.unwrap();
+            machine.pending_commands
+        };
+
+        assert_eq!(pending().await, 2);
+        // A lockout holds back a start command, so the gateway has nothing to fetch
+        sqlx::query("UPDATE machines SET locked_out_at = 300 WHERE id = 1").execute(&pool).await.unwrap();
+        assert_eq!(pending().await, 0);
+    }
+
+    #[tokio::test]
+    async fn new_machine_keys_are_returned_once_and_stored_as_their_hash() {
+        auth::init_sessions(b"test secret", Duration::from_secs(60), Duration::from_secs(60));
+        let pool = database::test_database().await;
+        let (status, Json(machine)) = create_machine(
+            require_admin().await,
+            State(pool.clone()),
+            State(Arc::new(Config::from_env().unwrap())),
+            State(MachineChanges::new()),
+            Json(serde_json::from_value(serde_json::json!({ "name": "Press", "code": "P1" })).unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let api_key = machine.api_key.unwrap();
+
+        let stored: String = sqlx::query_scalar("SELECT api_key FROM machines WHERE id = $1")
+            .bind(machine.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, auth::hash_token(&api_key));
+        assert_eq!(auth::validate_machine_key(&api_key, &pool).await.map(|(id, _)| id), Some(machine.id));
+        // The stored hash is not a key of its own
+        assert_eq!(auth::validate_machine_key(&stored, &pool).await, None);
+    }
+
+    #[tokio::test]
+    async fn rotated_keys_keep_working_for_the_grace_period_only() {
+        auth::init_sessions(b"test secret", Duration::from_secs(60), Duration::from_secs(60));
+        let pool = database().await;
+        sqlx::query("UPDATE machines SET api_key = $1 WHERE id = 1")
+            .bind(auth::hash_token("machine_old"))
+            .execute(&pool)
+            .await
+            .unwrap();
+        let rotate = async |grace_secs: i64| {
+            let Json(rotation) = rotate_machine_key(
+                require_admin().await,
+                Path(1),
+                State(pool.clone()),
+                State(Arc::new(Config::from_env().unwrap())),
+                Some(Json(RotateMachineKeyRequest { grace_secs: Some(grace_secs) })),
+            )
+            .await
+            .unwrap();
+            rotation
+        };
+        let machine = async |key: &str| auth::validate_machine_key(key, &pool).await.map(|(id, _)| id);
+
+        let rotation = rotate(3600).await;
+        assert!(rotation.previous_key.is_some());
+        assert_eq!(machine(&rotation.api_key).await, Some(1));
+        assert_eq!(machine("machine_old").await, Some(1));
+        // Once the grace period is over only the new key is left
+        sqlx::query("UPDATE machine_api_keys SET expires_at = $1").bind(current_timestamp()).execute(&pool).await.unwrap();
+        assert_eq!(machine("machine_old").await, None);
+
+        // Without a grace period the replaced key stops at once
+        let next = rotate(0).await;
+        assert!(next.previous_key.is_none());
+        assert_eq!(machine(&rotation.api_key).await, None);
+        assert_eq!(machine(&next.api_key).await, Some(1));
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    #[tokio::test]
+    async fn sandbox_copy_carries_no_production_credentials() {
+        // An in-memory database would be vacuumed into memory as well
+        let dir = std::env::temp_dir().join(format!("scada-sandbox-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = sqlx::sqlite::SqliteConnectOptions::new().filename(dir.join("live.db")).create_if_missing(true);
+        let pool = sqlx::SqlitePool::connect_with(options).await.unwrap();
+        database::MIGRATOR.run(&pool).await.unwrap();
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key')",
+            "INSERT INTO users (username, password, role, token, email) VALUES ('boss', 'secret', 'admin', 'old-token', 'boss@plant')",
+            "INSERT INTO users (username, password, role) VALUES ('fitter', 'secret', 'technician')",
+            "INSERT INTO user_machine_access (username, machine_id, granted_at) VALUES ('fitter', 1, 0)",
+            "INSERT INTO sessions (id, username, created_at, last_seen_at, expires_at) VALUES ('s1', 'boss', 0, 0, 9999999999)",
+            "INSERT INTO refresh_tokens (token_hash, username, created_at, expires_at) VALUES ('refresh', 'boss', 0, 9999999999)",
+            "INSERT INTO password_reset_tokens (token_hash, username, created_at, expires_at) VALUES ('reset', 'fitter', 0, 9999999999)",
+            "INSERT INTO machine_api_keys (machine_id, name, api_key, scopes, created_at) VALUES (1, 'reader', 'extra-key', 'config:read', 0)",
+            "INSERT INTO service_accounts (name, scopes, api_key, previous_api_key, created_by, created_at) \
+             VALUES ('erp', 'machines:read', 'service-key', 'old-service-key', 'boss', 0)",
+            "INSERT INTO display_tokens (name, token, created_by, created_at) VALUES ('hall', 'display-token', 'boss', 0)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        let path = dir.join("sandbox.db");
+        database::clone_to_sandbox(&pool, &path, None, "sandbox password").await.unwrap();
+        assert!(!path.with_extension("partial").exists());
+        let sandbox = sqlx::SqlitePool::connect(&format!("sqlite:{}", path.display())).await.unwrap();
+
+        for table in [
+            "user_machine_access",
+            "sessions",
+            "refresh_tokens",
+            "password_reset_tokens",
+            "machine_api_keys",
+            "service_accounts",
+            "display_tokens",
+            "machines WHERE api_key = 'key'",
+            "users WHERE username <> 'sandbox-admin'",
+        ] {
+            assert_eq!(count(&sandbox, table).await, 0, "{}", table);
+        }
+        assert_eq!(count(&sandbox, "machines").await, 1);
+        assert!(auth::authenticate_user("boss", "secret", &sandbox).await.is_none());
+        let admin = auth::authenticate_user("sandbox-admin", "sandbox password", &sandbox).await.unwrap();
+        assert_eq!(admin.role, "admin");
+
+        sandbox.close().await;
+        // Nor in pages the deletes freed
+        let file = std::fs::read(&path).unwrap();
+        assert!(!file.windows(b"service-key".len()).any(|window| window == b"service-key"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use std::time::Duration;
+
+use crate::models::ErrorResponse;
+
+// Routes taking a raw file as the body rather than JSON; they check the type and size themselves
+const RAW_BODY_ROUTES: &[&str] = &["/api/documents/{id}/file", "/api/site-settings/logo"];
+
+// Headers sent with every response, telling browsers to only use HTTPS, not to guess content
+// types and not to show the API inside frames
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    hsts: Option<HeaderValue>,
+}
+
+impl SecurityHeaders {
+    // No Strict-Transport-Security header when max_age is zero
+    pub fn new(hsts_max_age: Duration) -> Self {
+        let hsts = (!hsts_max_age.is_zero()).then(|| {
+            HeaderValue::from_str(&format!("max-age={}; includeSubDomains", hsts_max_age.as_secs()))
+                .expect("a number is a valid header value")
+        });
+        Self { hsts }
+    }
+}
+
+pub async fn set_security_headers(State(security): State<SecurityHeaders>, request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    if let Some(hsts) = &security.hsts {
+        headers.insert(header::STRICT_TRANSPORT_SECURITY, hsts.clone());
+    }
+    response
+}
+
+// application/json or a JSON-based type such as application/merge-patch+json, with any parameters
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    essence == "application/json" || essence.starts_with("application/") && essence.ends_with("+json")
+}
+
+// Refuses bodies announced larger than the limit, and writes whose body is not JSON, with an
+// error saying so. Chunked bodies are cut off at the limit by DefaultBodyLimit instead.
+pub async fn check_request_body(State(max_body_bytes): State<usize>, request: Request, next: Next) -> Response {
+    let raw_body = request
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|path| RAW_BODY_ROUTES.contains(&path.as_str()));
+    if raw_body {
+        return next.run(request).await;
+    }
+
+    let headers = request.headers();
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length > max_body_bytes as u64) {
+        return (StatusCode::PAYLOAD_TOO_LARGE, Json(ErrorResponse {
+            error: format!("Request body must be at most {} bytes", max_body_bytes),
+        }))
+            .into_response();
+    }
+
+    let has_body = content_length.is_some_and(|length| length > 0) || headers.contains_key(header::TRANSFER_ENCODING);
+    let is_write = matches!(*request.method(), Method::POST | Method::PUT | Method::PATCH);
+    if is_write && has_body && !is_json(headers) {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, Json(ErrorResponse {
+            error: "Request body must be JSON, sent with Content-Type: application/json".to_string(),
+        }))
+            .into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::post, Router};
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route("/api/machines", post(|| async { "ok" }))
+            .route("/api/site-settings/logo", post(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(16, check_request_body))
+            .layer(middleware::from_fn_with_state(SecurityHeaders::new(Duration::from_secs(3600)), set_security_headers))
+    }
+
+    async fn post_body(path: &str, content_type: &str, body: &str) -> Response {
+        let request = Request::post(path)
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        app().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn sends_security_headers_with_every_response() {
+        let response = post_body("/api/machines", "text/plain", "name").await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let headers = response.headers();
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+        assert_eq!(headers[header::STRICT_TRANSPORT_SECURITY], "max-age=3600; includeSubDomains");
+        assert!(SecurityHeaders::new(Duration::ZERO).hsts.is_none());
+    }
+
+    #[tokio::test]
+    async fn accepts_only_json_bodies_within_the_limit() {
+        assert_eq!(post_body("/api/machines", "application/json", "{}").await.status(), StatusCode::OK);
+        assert_eq!(post_body("/api/machines", "application/merge-patch+json; charset=utf-8", "{}").await.status(), StatusCode::OK);
+        assert_eq!(post_body("/api/machines", "application/json", &"x".repeat(17)).await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        // Uploads check their own type and size
+        assert_eq!(post_body("/api/site-settings/logo", "image/png", &"x".repeat(17)).await.status(), StatusCode::OK);
+    }
+}
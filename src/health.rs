@@ -0,0 +1,201 @@
+use chrono::{NaiveDate, TimeZone};
+use sqlx::Row;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::Config,
+    database::{DbPool, current_timestamp},
+    state::MachineChanges,
+};
+
+const HOUR: i64 = 60 * 60;
+const WEEK: i64 = 7 * 24 * HOUR;
+
+// Points of the 100 a machine can lose to each part of the score
+const ALARM_POINTS: f64 = 30.0;
+const ANOMALY_POINTS: f64 = 20.0;
+const COVERAGE_POINTS: f64 = 25.0;
+const DOWNTIME_POINTS: f64 = 25.0;
+
+// Checks every ten minutes whether today's health scores are due, computing them once a day
+// after the configured local time
+pub fn spawn_health_scores(pool: DbPool, changes: MachineChanges, config: &Config) -> JoinHandle<()> {
+    let (tz, score_time) = (config.site_timezone, config.health_score_time);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(10 * 60));
+        let mut scored_day: Option<NaiveDate> = None;
+        loop {
+            interval.tick().await;
+            let now = current_timestamp();
+            let Some(local_now) = tz.timestamp_opt(now, 0).single() else {
+                continue;
+            };
+            let today = local_now.date_naive();
+            if local_now.time() < score_time || scored_day == Some(today) {
+                continue;
+            }
+            match score_machines(&pool, &today.to_string(), now).await {
+                Ok(count) => {
+                    tracing::info!("Computed health scores of {} machine(s) for {}", count, today);
+                    scored_day = Some(today);
+                    changes.notify(now);
+                },
+                Err(e) => tracing::error!("Health scoring failed: {}", e),
+            }
+        }
+    })
+}
+
+// Scores every machine that is not archived over the week up to `now` and stores the scores
+// as `day`'s, replacing any computed earlier that day. Returns the machines scored.
+//
+// A machine starts at 100 and loses points for alarms (5 per alarm a day), anomalies (4 per
+// compliance exceedance or flagged reading a day), hours without speed history since it was
+// added, and a rise in the share of reported hours it stood still against the week before
+// (a point per percentage point).
+pub async fn score_machines(pool: &DbPool, day: &str, now: i64) -> sqlx::Result<usize> {
+    // Only whole hours have final rollups
+    let to = now - now.rem_euclid(HOUR);
+    let (from, previous) = (to - WEEK, to - 2 * WEEK);
+    let rows = sqlx::query(
+        "SELECT m.id, COALESCE(m.created_at, 0) AS created_at, \
+         (SELECT COUNT(*) FROM alarms a WHERE a.machine_id = m.id AND a.raised_at >= $1 AND a.raised_at < $2) AS alarms, \
+         (SELECT COUNT(*) FROM compliance_exceedances e WHERE e.machine_id = m.id AND e.started_at >= $3 AND e.started_at < $4) \
+         + (SELECT COUNT(*) FROM metric_readings r WHERE r.machine_id = m.id AND r.flagged = TRUE AND r.timestamp >= $5 AND r.timestamp < $6) \
+         AS anomalies, \
+         (SELECT COUNT(*) FROM speed_history_hourly h WHERE h.machine_id = m.id AND h.bucket_start >= $7 AND h.bucket_start < $8) AS hours, \
+         (SELECT CAST(AVG(CASE WHEN h.max_speed = 0 THEN 1 ELSE 0 END) AS DOUBLE PRECISION) FROM speed_history_hourly h WHERE h.machine_id = m.id AND h.bucket_start >= $9 AND h.bucket_start < $10) \
+         AS downtime, \
+         (SELECT CAST(AVG(CASE WHEN h.max_speed = 0 THEN 1 ELSE 0 END) AS DOUBLE PRECISION) FROM speed_history_hourly h WHERE h.machine_id = m.id AND h.bucket_start >= $11 AND h.bucket_start < $12) \
+         AS previous_downtime \
+         FROM machines m WHERE m.archived_at IS NULL"
+    )
+    .bind(from)
+    .bind(now)
+    .bind(from)
+    .bind(now)
+    .bind(from)
+    .bind(now)
+    .bind(from)
+    .bind(to)
+    .bind(from)
+    .bind(to)
+    .bind(previous)
+    .bind(from)
+    .fetch_all(pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    for row in &rows {
+        let machine_id: i64 = row.get("id");
+        let created_at: i64 = row.get("created_at");
+        let alarm_rate = row.get::<i64, _>("alarms") as f64 / 7.0;
+        let anomalies: i64 = row.get("anomalies");
+
+        // Machines added during the week are only expected to report since then
+        let expected_hours = (to - from.max(created_at - created_at.rem_euclid(HOUR))) / HOUR;
+        let data_coverage = if expected_hours > 0 {
+            (row.get::<i64, _>("hours") as f64 / expected_hours as f64).min(1.0)
+        } else {
+            1.0
+        };
+        let downtime_change = match (row.get::<Option<f64>, _>("downtime"), row.get::<Option<f64>, _>("previous_downtime")) {
+            (Some(downtime), Some(previous)) => Some((downtime - previous) * 100.0),
+            _ => None,
+        };
+
+        let penalty = (alarm_rate * 5.0).min(ALARM_POINTS)
+            + (anomalies as f64 / 7.0 * 4.0).min(ANOMALY_POINTS)
+            + (1.0 - data_coverage) * COVERAGE_POINTS
+            + downtime_change.unwrap_or(0.0).clamp(0.0, DOWNTIME_POINTS);
+        let score = ((100.0 - penalty) * 10.0).round() / 10.0;
+
+        sqlx::query(
+            "INSERT INTO machine_health_scores \
+             (machine_id, day, score, alarm_rate, anomalies, data_coverage, downtime_change, computed_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+             ON CONFLICT (machine_id, day) DO UPDATE SET score = excluded.score, alarm_rate = excluded.alarm_rate, \
+             anomalies = excluded.anomalies, data_coverage = excluded.data_coverage, \
+             downtime_change = excluded.downtime_change, computed_at = excluded.computed_at"
+        )
+        .bind(machine_id)
+        .bind(day)
+        .bind(score)
+        .bind(alarm_rate)
+        .bind(anomalies)
+        .bind(data_coverage)
+        .bind(downtime_change)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE machines SET health_score = $1, updated_at = $2 WHERE id = $3")
+            .bind(score)
+            .bind(now)
+            .bind(machine_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn scores_alarms_missing_data_and_rising_downtime() {
+        let pool = crate::database::test_database().await;
+        let now = 3000 * WEEK + HOUR / 2;
+        // Scores cover the week up to the last whole hour
+        let from = now - HOUR / 2 - WEEK;
+        sqlx::query(
+            "INSERT INTO machines (id, name, code, api_key, created_at) \
+             VALUES (1, 'Steady', 'M1', 'key1', 0), (2, 'Troubled', 'M2', 'key2', 0), (3, 'Silent', 'M3', 'key3', 0)"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        // Two weeks of hourly rollups for machines 1 and 2; machine 2 stood still for the first
+        // quarter of this week
+        sqlx::query(
+            "WITH RECURSIVE h(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM h WHERE i < 335) \
+             INSERT INTO speed_history_hourly (machine_id, bucket_start, avg_speed, min_speed, max_speed, samples) \
+             SELECT m.id, $1 + i * 3600, 10.0, 10.0, CASE WHEN m.id = 2 AND i >= 168 AND i < 210 THEN 0.0 ELSE 10.0 END, 60 \
+             FROM h, machines m WHERE m.id < 3"
+        )
+        .bind(from - WEEK)
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO alarm_rules (id, machine_id, name, expression, severity, created_by) \
+             VALUES (1, 2, 'Slow', 'speed < 5', 'warning', 'boss')"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        for raised_at in (0..14).map(|i| from + i * 12 * HOUR) {
+            sqlx::query("INSERT INTO alarms (rule_id, machine_id, severity, message, raised_at) VALUES (1, 2, 'warning', 'Slow', $1)")
+                .bind(raised_at)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        // Scoring again the same day replaces the earlier scores
+        assert_eq!(score_machines(&pool, "2026-10-17", now).await.unwrap(), 3);
+        assert_eq!(score_machines(&pool, "2026-10-17", now).await.unwrap(), 3);
+        let scores: Vec<(i64, f64, f64, f64)> = sqlx::query_as(
+            "SELECT machine_id, score, alarm_rate, data_coverage FROM machine_health_scores WHERE day = '2026-10-17' ORDER BY machine_id"
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        // 5 points per daily alarm and 25 for the rise in downtime; 25 for a week without data
+        assert_eq!(scores, [(1, 100.0, 0.0, 1.0), (2, 65.0, 2.0, 1.0), (3, 75.0, 0.0, 0.0)]);
+        let current: Option<f64> = sqlx::query_scalar("SELECT health_score FROM machines WHERE id = 2").fetch_one(&pool).await.unwrap();
+        assert_eq!(current, Some(65.0));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    database::{Db, DbPool, current_timestamp},
+    rollups,
+};
+
+// Moves a machine's history rows in `[from, to)` by `offset` seconds and records the shift,
+// including which rows it moved, so it can be undone exactly even once the shifted range
+// overlaps history recorded since. Returns the shift's id and the number of rows moved.
+pub async fn apply(
+    pool: &DbPool,
+    machine_id: i64,
+    (from, to): (i64, i64),
+    offset: i64,
+    reason: Option<&str>,
+    created_by: &str,
+    job_id: &str,
+) -> sqlx::Result<(i64, i64)> {
+    let mut tx = pool.begin().await?;
+    let now = current_timestamp();
+    let shift_id: i64 = sqlx::query_scalar(
+        "INSERT INTO history_shifts (machine_id, range_from, range_to, offset_seconds, rows_shifted, reason, job_id, \
+         created_by, created_at) VALUES ($1, $2, $3, $4, 0, $5, $6, $7, $8) RETURNING id"
+    )
+    .bind(machine_id)
+    .bind(from)
+    .bind(to)
+    .bind(offset)
+    .bind(reason)
+    .bind(job_id)
+    .bind(created_by)
+    .bind(now)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let rows = sqlx::query(
+        "INSERT INTO history_shift_rows (shift_id, history_id) \
+         SELECT $1, id FROM speed_history WHERE machine_id = $2 AND timestamp >= $3 AND timestamp < $4"
+    )
+    .bind(shift_id)
+    .bind(machine_id)
+    .bind(from)
+    .bind(to)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected() as i64;
+    move_rows(&mut tx, machine_id, shift_id, offset).await?;
+
+    sqlx::query("UPDATE history_shifts SET rows_shifted = $1 WHERE id = $2")
+        .bind(rows)
+        .bind(shift_id)
+        .execute(&mut *tx)
+        .await?;
+    // Statistics in the machine detail are computed from history, so its cached copies are stale
+    sqlx::query("UPDATE machines SET updated_at = $1 WHERE id = $2")
+        .bind(now)
+        .bind(machine_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok((shift_id, rows))
+}
+
+// Moves the rows of a shift back to where they were. Rows deleted since are skipped.
+// Returns the number of rows restored, or `RowNotFound` if the shift was already undone.
+pub async fn undo(pool: &DbPool, shift_id: i64, undone_by: &str) -> sqlx::Result<i64> {
+    let mut tx = pool.begin().await?;
+    let now = current_timestamp();
+    let (machine_id, offset): (i64, i64) = sqlx::query_as(
+        "UPDATE history_shifts SET undone_by = $1, undone_at = $2 WHERE id = $3 AND undone_at IS NULL \
+         RETURNING machine_id, offset_seconds"
+    )
+    .bind(undone_by)
+    .bind(now)
+    .bind(shift_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let restored = move_rows(&mut tx, machine_id, shift_id, -offset).await?;
+    sqlx::query("UPDATE machines SET updated_at = $1 WHERE id = $2")
+        .bind(now)
+        .bind(machine_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(restored)
+}
+
+// Rollups of both the range the rows left and the range they moved to are rebuilt
+async fn move_rows(tx: &mut sqlx::Transaction<'_, Db>, machine_id: i64, shift_id: i64, offset: i64) -> sqlx::Result<i64> {
+    let moved = sqlx::query(
+        "UPDATE speed_history SET timestamp = timestamp + $1 \
+         WHERE id IN (SELECT history_id FROM history_shift_rows WHERE shift_id = $2)"
+    )
+    .bind(offset)
+    .bind(shift_id)
+    .execute(&mut **tx)
+    .await?;
+
+    let (oldest, newest): (Option<i64>, Option<i64>) = sqlx::query_as(
+        "SELECT MIN(timestamp), MAX(timestamp) FROM speed_history \
+         WHERE id IN (SELECT history_id FROM history_shift_rows WHERE shift_id = $1)"
+    )
+    .bind(shift_id)
+    .fetch_one(&mut **tx)
+    .await?;
+    if let (Some(oldest), Some(newest)) = (oldest, newest) {
+        rollups::rebuild(tx, machine_id, oldest - offset, newest - offset + 1).await?;
+        rollups::rebuild(tx, machine_id, oldest, newest + 1).await?;
+    }
+    Ok(moved.rows_affected() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn timestamps(pool: &DbPool) -> Vec<i64> {
+        sqlx::query_scalar("SELECT timestamp FROM speed_history ORDER BY id").fetch_all(pool).await.unwrap()
+    }
+
+    async fn hourly_samples(pool: &DbPool) -> Vec<(i64, i64)> {
+        sqlx::query_as("SELECT bucket_start, samples FROM speed_history_hourly ORDER BY bucket_start")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn undo_restores_exactly_the_rows_that_were_shifted() {
+        let pool = crate::database::test_database().await;
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let record = async |timestamp: i64| {
+            sqlx::query("INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES (1, 10.0, 'running', $1)")
+                .bind(timestamp)
+                .execute(&pool)
+                .await
+                .unwrap();
+        };
+        for timestamp in [100, 200, 300] {
+            record(timestamp).await;
+        }
+
+        // A gateway clock two hours behind
+        let (shift_id, moved) = apply(&pool, 1, (100, 250), 7200, Some("Clock drift"), "boss", "job-1").await.unwrap();
+        assert_eq!(moved, 2);
+        assert_eq!(timestamps(&pool).await, [7300, 7400, 300]);
+        assert_eq!(hourly_samples(&pool).await, [(0, 1), (7200, 2)]);
+
+        // History recorded since, inside the shifted range, stays where it is
+        record(7350).await;
+        assert_eq!(undo(&pool, shift_id, "boss").await.unwrap(), 2);
+        assert_eq!(timestamps(&pool).await, [100, 200, 300, 7350]);
+        assert_eq!(hourly_samples(&pool).await, [(0, 3), (7200, 1)]);
+        assert!(matches!(undo(&pool, shift_id, "boss").await, Err(sqlx::Error::RowNotFound)));
+    }
+}
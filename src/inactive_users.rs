@@ -0,0 +1,138 @@
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{
+    auth,
+    database::{self, DbPool, current_timestamp},
+};
+
+// Periodically disables accounts nobody has used for `inactive_after`, so contractors who
+// have left lose access without anyone having to remember them. An account counts as used
+// when it logged in, refreshed a session or was created or reactivated. Admins are left
+// alone, so the policy can never lock everyone out.
+pub fn spawn_inactivity_check(pool: DbPool, inactive_after: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match disable_inactive(&pool, inactive_after).await {
+                Ok(0) => {},
+                Ok(count) => tracing::info!("Disabled {} inactive account(s)", count),
+                Err(e) => tracing::error!("Inactive account check failed: {}", e),
+            }
+        }
+    })
+}
+
+async fn disable_inactive(pool: &DbPool, inactive_after: Duration) -> sqlx::Result<usize> {
+    let cutoff = current_timestamp() - inactive_after.as_secs() as i64;
+    let inactive: Vec<String> = sqlx::query_scalar(&format!(
+        "SELECT username FROM users WHERE is_active = TRUE AND role <> 'admin' AND {} < $1",
+        database::greatest(
+            "COALESCE(last_login, 0), COALESCE(created_at, 0), COALESCE(reactivated_at, 0), \
+             COALESCE((SELECT MAX(last_seen_at) FROM sessions WHERE sessions.username = users.username), 0)"
+        )
+    ))
+    .bind(cutoff)
+    .fetch_all(pool)
+    .await?;
+
+    let mut disabled = 0;
+    for username in &inactive {
+        if deactivate(pool, username, "inactivity").await? {
+            tracing::warn!("Disabled account {} after {} days without use", username, inactive_after.as_secs() / (24 * 60 * 60));
+            disabled += 1;
+        }
+    }
+    Ok(disabled)
+}
+
+// Disables an active account and ends every session it has; `reason` is "inactivity" or
+// "admin". Returns whether the account was active.
+pub async fn deactivate(pool: &DbPool, username: &str, reason: &str) -> sqlx::Result<bool> {
+    let updated = sqlx::query(
+        "UPDATE users SET is_active = FALSE, deactivated_at = $1, deactivation_reason = $2 WHERE username = $3 AND is_active = TRUE"
+    )
+    .bind(current_timestamp())
+    .bind(reason)
+    .bind(username)
+    .execute(pool)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    auth::revoke_user_tokens(pool, username).await?;
+    Ok(true)
+}
+
+// Lets a disabled account sign in again. The inactivity period starts over from now.
+pub async fn reactivate(pool: &DbPool, username: &str) -> sqlx::Result<bool> {
+    let updated = sqlx::query(
+        "UPDATE users SET is_active = TRUE, deactivated_at = NULL, deactivation_reason = NULL, reactivated_at = $1 \
+         WHERE username = $2 AND is_active = FALSE"
+    )
+    .bind(current_timestamp())
+    .bind(username)
+    .execute(pool)
+    .await?;
+    Ok(updated.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    async fn active(pool: &DbPool) -> Vec<String> {
+        sqlx::query_scalar("SELECT username FROM users WHERE is_active = TRUE ORDER BY username")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn disables_accounts_unused_for_the_period_except_admins() {
+        let pool = crate::database::test_database().await;
+        let now = current_timestamp();
+        for (username, role, last_login) in [
+            ("boss", "admin", now - 400 * DAY),
+            ("contractor", "technician", now - 100 * DAY),
+            ("welder", "technician", now - DAY),
+            ("night", "technician", now - 100 * DAY),
+        ] {
+            sqlx::query("INSERT INTO users (username, password, role, created_at, last_login) VALUES ($1, 'x', $2, $3, $4)")
+                .bind(username)
+                .bind(role)
+                .bind(now - 500 * DAY)
+                .bind(last_login)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        // Refreshing a session counts as use as much as logging in
+        sqlx::query("INSERT INTO sessions (id, username, created_at, last_seen_at, expires_at) VALUES ('s1', 'night', $1, $2, $3)")
+            .bind(now - 100 * DAY)
+            .bind(now - DAY)
+            .bind(now + DAY)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let inactive_after = Duration::from_secs(90 * DAY as u64);
+        assert_eq!(disable_inactive(&pool, inactive_after).await.unwrap(), 1);
+        assert_eq!(active(&pool).await, ["boss", "night", "welder"]);
+        let reason: Option<String> = sqlx::query_scalar("SELECT deactivation_reason FROM users WHERE username = 'contractor'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(reason.as_deref(), Some("inactivity"));
+
+        // Reactivation starts the period over
+        assert!(reactivate(&pool, "contractor").await.unwrap());
+        assert!(!reactivate(&pool, "contractor").await.unwrap());
+        assert_eq!(disable_inactive(&pool, inactive_after).await.unwrap(), 0);
+        assert!(deactivate(&pool, "welder", "admin").await.unwrap());
+        assert!(!deactivate(&pool, "welder", "admin").await.unwrap());
+    }
+}
@@ -0,0 +1,122 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use std::any::Any;
+use uuid::Uuid;
+
+use crate::models::ErrorResponse;
+
+// Error bodies are small JSON objects; anything larger is passed through untouched
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+// Gives every 5xx response an incident id, in the x-incident-id header and as `incident_id`
+// in the JSON error body, and logs it so support can find the failure from what a user reports.
+// The id is also set as a Sentry tag before the handler runs so error reports carry it.
+pub async fn tag_server_errors(request: Request, next: Next) -> Response {
+    let incident_id = Uuid::new_v4().simple().to_string();
+    sentry::configure_scope(|scope| scope.set_tag("incident_id", &incident_id));
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    if !response.status().is_server_error() {
+        return response;
+    }
+
+    tracing::warn!(
+        incident_id = %incident_id,
+        status = response.status().as_u16(),
+        "Server error response: {} {} returned {}",
+        method,
+        path,
+        response.status()
+    );
+
+    let (mut parts, body) = response.into_parts();
+    parts.headers.insert("x-incident-id", HeaderValue::from_str(&incident_id).expect("uuid is a valid header value"));
+
+    let is_json = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if !is_json {
+        return Response::from_parts(parts, body);
+    }
+
+    let Ok(bytes) = to_bytes(body, MAX_ERROR_BODY).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut object)) => {
+            object.insert("incident_id".to_string(), incident_id.into());
+            serde_json::Value::Object(object).to_string().into()
+        },
+        _ => bytes,
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+// Response for a request whose handler panicked; the panic itself is reported by the panic hook
+pub fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    tracing::error!("Handler panicked: {}", message);
+
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Internal server error".to_string(),
+    }))
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, middleware, routing::get};
+    use tower::ServiceExt;
+
+    async fn send(path: &str) -> (StatusCode, Option<String>, String) {
+        let app = Router::new()
+            .route(
+                "/json",
+                get(|| async {
+                    (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() }))
+                }),
+            )
+            .route("/text", get(|| async { (StatusCode::BAD_GATEWAY, "upstream down") }))
+            .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+            .layer(middleware::from_fn(tag_server_errors));
+        let response = app.oneshot(Request::builder().uri(path).body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let incident_id = response.headers().get("x-incident-id").map(|id| id.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, incident_id, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn server_errors_carry_the_same_incident_id_in_header_and_body() {
+        let (status, incident_id, body) = send("/json").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["error"], "Database error");
+        assert_eq!(body["incident_id"].as_str(), incident_id.as_deref());
+
+        let (status, incident_id, body) = send("/text").await;
+        assert_eq!((status, body.as_str()), (StatusCode::BAD_GATEWAY, "upstream down"));
+        assert!(incident_id.is_some());
+        assert_eq!(send("/missing").await.1, None);
+    }
+
+    #[test]
+    fn panics_answer_with_a_generic_error() {
+        let response = panic_response(Box::new("index out of bounds"));
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,316 @@
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use std::{future::Future, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{sync::Semaphore, task::JoinHandle};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::database::{DbPool, current_timestamp};
+
+pub type JobResult = anyhow::Result<Value>;
+
+// Handle passed to a running job so it can report progress and store its output file
+pub struct JobContext {
+    pub id: String,
+    pool: DbPool,
+    artifacts: Arc<ArtifactStore>,
+}
+
+impl JobContext {
+    pub async fn set_progress(&self, progress: f64) {
+        let _ = sqlx::query("UPDATE jobs SET progress = $1 WHERE id = $2")
+            .bind(progress.clamp(0.0, 1.0))
+            .bind(&self.id)
+            .execute(&self.pool)
+            .await;
+    }
+
+    // Stores the job's downloadable output; it is deleted once the retention period ends
+    pub async fn write_artifact(&self, file_name: &str, content_type: &str, contents: Vec<u8>) -> anyhow::Result<()> {
+        tokio::fs::create_dir_all(&self.artifacts.dir).await?;
+        let path = self.artifacts.dir.join(&self.id);
+        tokio::fs::write(&path, contents).await?;
+
+        sqlx::query(
+            "UPDATE jobs SET artifact_path = $1, artifact_name = $2, artifact_content_type = $3, artifact_expires_at = $4 \
+             WHERE id = $5"
+        )
+        .bind(path.to_string_lossy().to_string())
+        .bind(file_name)
+        .bind(content_type)
+        .bind(current_timestamp() + self.artifacts.retention.as_secs() as i64)
+        .bind(&self.id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+// Where job artifacts live on disk, how long they are kept, and the key used to sign
+// expiring download links for them
+pub struct ArtifactStore {
+    pub dir: PathBuf,
+    pub retention: Duration,
+    signing_key: Vec<u8>,
+}
+
+impl ArtifactStore {
+    pub fn new(dir: PathBuf, retention: Duration, signing_key: Vec<u8>) -> Self {
+        Self {
+            dir,
+            retention,
+            signing_key,
+        }
+    }
+
+    fn mac(&self, job_id: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.signing_key).expect("HMAC accepts any key length");
+        mac.update(format!("{}:{}", job_id, expires).as_bytes());
+        mac
+    }
+
+    pub fn sign(&self, job_id: &str, expires: i64) -> String {
+        hex::encode(self.mac(job_id, expires).finalize().into_bytes())
+    }
+
+    pub fn verify(&self, job_id: &str, expires: i64, signature: &str) -> bool {
+        expires >= current_timestamp()
+            && hex::decode(signature).is_ok_and(|signature| self.mac(job_id, expires).verify_slice(&signature).is_ok())
+    }
+}
+
+// Runs long tasks (exports, reports, rollups) in the background and records their
+// status, progress and result in the `jobs` table so clients can poll for completion.
+#[derive(Clone)]
+pub struct Jobs {
+    pool: DbPool,
+    workers: Arc<Semaphore>,
+    pub artifacts: Arc<ArtifactStore>,
+}
+
+impl Jobs {
+    pub fn new(pool: DbPool, workers: usize, artifacts: ArtifactStore) -> Self {
+        Self {
+            pool,
+            workers: Arc::new(Semaphore::new(workers.max(1))),
+            artifacts: Arc::new(artifacts),
+        }
+    }
+
+    // Periodically deletes artifacts whose retention period has ended
+    pub fn spawn_artifact_cleanup(&self) -> JoinHandle<()> {
+        let jobs = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+            loop {
+                interval.tick().await;
+                if let Err(e) = jobs.purge_expired_artifacts().await {
+                    tracing::error!("Artifact cleanup failed: {}", e);
+                }
+            }
+        })
+    }
+
+    async fn purge_expired_artifacts(&self) -> anyhow::Result<()> {
+        let expired: Vec<(String, String)> = sqlx::query_as(
+            "SELECT id, artifact_path FROM jobs WHERE artifact_path IS NOT NULL AND artifact_expires_at < $1"
+        )
+        .bind(current_timestamp())
+        .fetch_all(&self.pool)
+        .await?;
+
+        for (id, path) in expired {
+            if let Err(e) = tokio::fs::remove_file(&path).await
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                tracing::error!("Failed to delete artifact {}: {}", path, e);
+                continue;
+            }
+            sqlx::query("UPDATE jobs SET artifact_path = NULL WHERE id = $1")
+                .bind(&id)
+                .execute(&self.pool)
+                .await?;
+            tracing::info!("Deleted expired artifact for job {}", id);
+        }
+        Ok(())
+    }
+
+    // Jobs that were queued or running when the process stopped can never finish
+    pub async fn fail_interrupted(&self) -> anyhow::Result<()> {
+        sqlx::query(
+            "UPDATE jobs SET status = 'failed', error = 'Interrupted by server restart', finished_at = $1 \
+             WHERE status IN ('queued', 'running')"
+        )
+        .bind(current_timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn enqueue<F, Fut>(&self, kind: &str, created_by: &str, work: F) -> anyhow::Result<String>
+    where
+        F: FnOnce(JobContext) -> Fut + Send + 'static,
+        Fut: Future<Output = JobResult> + Send + 'static,
+    {
+        let id = Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO jobs (id, kind, status, created_by, created_at) VALUES ($1, $2, 'queued', $3, $4)")
+            .bind(&id)
+            .bind(kind)
+            .bind(created_by)
+            .bind(current_timestamp())
+            .execute(&self.pool)
+            .await?;
+
+        let jobs = self.clone();
+        let job_id = id.clone();
+        let kind = kind.to_string();
+        // The job span is a child of the request that queued it, so the trace covers the work itself
+        let span = tracing::info_span!("job", job.id = %job_id, job.kind = %kind);
+        tokio::spawn(async move {
+            let Ok(_permit) = jobs.workers.clone().acquire_owned().await else {
+                return;
+            };
+
+            let _ = sqlx::query("UPDATE jobs SET status = 'running', started_at = $1 WHERE id = $2")
+                .bind(current_timestamp())
+                .bind(&job_id)
+                .execute(&jobs.pool)
+                .await;
+            tracing::info!("Job {} ({}) started", job_id, kind);
+
+            let context = JobContext {
+                id: job_id.clone(),
+                pool: jobs.pool.clone(),
+                artifacts: jobs.artifacts.clone(),
+            };
+            let outcome = work(context).await;
+
+            let update = match &outcome {
+                Ok(result) => sqlx::query(
+                    "UPDATE jobs SET status = 'completed', progress = 1.0, result = $1, finished_at = $2 WHERE id = $3"
+                )
+                .bind(result.to_string()),
+                Err(e) => sqlx::query("UPDATE jobs SET status = 'failed', error = $1, finished_at = $2 WHERE id = $3")
+                    .bind(e.to_string()),
+            };
+            let _ = update
+                .bind(current_timestamp())
+                .bind(&job_id)
+                .execute(&jobs.pool)
+                .await;
+
+            match outcome {
+                Ok(_) => tracing::info!("Job {} ({}) completed", job_id, kind),
+                Err(e) => tracing::error!("Job {} ({}) failed: {}", job_id, kind, e),
+            }
+        }.instrument(span));
+
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn jobs(pool: &DbPool) -> Jobs {
+        let dir = std::env::temp_dir().join(format!("scada-jobs-test-{}", Uuid::new_v4().simple()));
+        Jobs::new(pool.clone(), 1, ArtifactStore::new(dir, Duration::from_secs(60), b"test secret".to_vec()))
+    }
+
+    // Status, result and error of the job once it has finished
+    async fn finished(pool: &DbPool, id: &str) -> (String, Option<String>, Option<String>) {
+        for _ in 0..500 {
+            let job: (String, Option<String>, Option<String>) =
+                sqlx::query_as("SELECT status, result, error FROM jobs WHERE id = $1")
+                    .bind(id)
+                    .fetch_one(pool)
+                    .await
+                    .unwrap();
+            if job.0 != "queued" && job.0 != "running" {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Job {} did not finish", id);
+    }
+
+    #[tokio::test]
+    async fn jobs_record_their_outcome() {
+        let pool = crate::database::test_database().await;
+        let jobs = jobs(&pool);
+        let id = jobs
+            .enqueue("export", "alice", async |job| {
+                job.set_progress(0.5).await;
+                Ok(json!({ "rows": 1 }))
+            })
+            .await
+            .unwrap();
+        assert_eq!(finished(&pool, &id).await, ("completed".to_string(), Some(r#"{"rows":1}"#.to_string()), None));
+        let id = jobs.enqueue("export", "alice", async |_| anyhow::bail!("Disk full")).await.unwrap();
+        assert_eq!(finished(&pool, &id).await, ("failed".to_string(), None, Some("Disk full".to_string())));
+    }
+
+    #[tokio::test]
+    async fn unfinished_jobs_fail_on_restart() {
+        let pool = crate::database::test_database().await;
+        sqlx::query(
+            "INSERT INTO jobs (id, kind, status, created_by) \
+             VALUES ('a', 'export', 'running', 'alice'), ('b', 'export', 'completed', 'alice')"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        jobs(&pool).fail_interrupted().await.unwrap();
+        let statuses: Vec<String> =
+            sqlx::query_scalar("SELECT status FROM jobs ORDER BY id").fetch_all(&pool).await.unwrap();
+        assert_eq!(statuses, ["failed", "completed"]);
+    }
+
+    #[tokio::test]
+    async fn artifacts_are_deleted_once_they_expire() {
+        let pool = crate::database::test_database().await;
+        let jobs = jobs(&pool);
+        let id = jobs
+            .enqueue("export", "alice", async |job| {
+                job.write_artifact("history.csv", "text/csv", b"id,speed\n".to_vec()).await?;
+                Ok(Value::Null)
+            })
+            .await
+            .unwrap();
+        assert_eq!(finished(&pool, &id).await.0, "completed");
+        let artifact = async || -> (Option<String>, String) {
+            sqlx::query_as("SELECT artifact_path, artifact_name FROM jobs WHERE id = $1")
+                .bind(&id)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+        let (path, name) = artifact().await;
+        let path = path.unwrap();
+        assert_eq!((std::fs::read(&path).unwrap(), name.as_str()), (b"id,speed\n".to_vec(), "history.csv"));
+
+        jobs.purge_expired_artifacts().await.unwrap();
+        assert!(std::path::Path::new(&path).exists());
+        sqlx::query("UPDATE jobs SET artifact_expires_at = 0").execute(&pool).await.unwrap();
+        jobs.purge_expired_artifacts().await.unwrap();
+        assert!(!std::path::Path::new(&path).exists());
+        assert_eq!(artifact().await.0, None);
+        std::fs::remove_dir_all(&jobs.artifacts.dir).unwrap();
+    }
+
+    #[test]
+    fn download_links_expire_and_cannot_be_altered() {
+        let store = ArtifactStore::new(PathBuf::new(), Duration::from_secs(60), b"test secret".to_vec());
+        let expires = current_timestamp() + 60;
+        let signature = store.sign("job-1", expires);
+        assert!(store.verify("job-1", expires, &signature));
+        assert!(!store.verify("job-2", expires, &signature));
+        assert!(!store.verify("job-1", expires + 1, &signature));
+        assert!(!store.verify("job-1", expires, "not hex"));
+        let expired = current_timestamp() - 1;
+        assert!(!store.verify("job-1", expired, &store.sign("job-1", expired)));
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    database::{DbPool, current_timestamp},
+    models::{KioskGroup, KioskSlide, KioskTrendPoint, Machine, SetKioskGroupRequest, SpeedAggregates},
+    precision::Precision,
+    products,
+};
+
+// Kiosk groups by name, or only the named one
+pub async fn load_groups(pool: &DbPool, name: Option<&str>) -> sqlx::Result<Vec<KioskGroup>> {
+    let groups: Vec<(String, Option<i64>, String, i64)> = sqlx::query_as(
+        "SELECT name, rotation_interval_secs, updated_by, updated_at FROM kiosk_groups \
+         WHERE $1 IS NULL OR name = $2 ORDER BY name"
+    )
+    .bind(name)
+    .bind(name)
+    .fetch_all(pool)
+    .await?;
+    let members: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT group_name, machine_id FROM kiosk_group_machines \
+         WHERE $1 IS NULL OR group_name = $2 ORDER BY group_name, position"
+    )
+    .bind(name)
+    .bind(name)
+    .fetch_all(pool)
+    .await?;
+
+    let mut machine_ids: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+    for (group, machine_id) in members {
+        machine_ids.entry(group).or_default().push(machine_id);
+    }
+    Ok(groups
+        .into_iter()
+        .map(|(name, rotation_interval_secs, updated_by, updated_at)| KioskGroup {
+            machine_ids: machine_ids.remove(&name).unwrap_or_default(),
+            name,
+            rotation_interval_secs,
+            updated_by,
+            updated_at,
+        })
+        .collect())
+}
+
+// Creates the group or replaces its machines and interval
+pub async fn save_group(pool: &DbPool, name: &str, group: &SetKioskGroupRequest, username: &str) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO kiosk_groups (name, rotation_interval_secs, updated_by, updated_at) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (name) DO UPDATE SET rotation_interval_secs = excluded.rotation_interval_secs, \
+         updated_by = excluded.updated_by, updated_at = excluded.updated_at"
+    )
+    .bind(name)
+    .bind(group.rotation_interval_secs)
+    .bind(username)
+    .bind(current_timestamp())
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM kiosk_group_machines WHERE group_name = $1")
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+    for (position, machine_id) in group.machine_ids.iter().enumerate() {
+        sqlx::query("INSERT INTO kiosk_group_machines (group_name, machine_id, position) VALUES ($1, $2, $3)")
+            .bind(name)
+            .bind(machine_id)
+            .bind(position as i64)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+// Returns whether the group existed
+pub async fn delete_group(pool: &DbPool, name: &str) -> sqlx::Result<bool> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM kiosk_group_machines WHERE group_name = $1")
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+    let deleted = sqlx::query("DELETE FROM kiosk_groups WHERE name = $1")
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(deleted.rows_affected() > 0)
+}
+
+// Everything a wallboard shows for one machine: its state against target, the last hour at
+// a glance and per minute, and what needs attention
+pub async fn slide(pool: &DbPool, precision: &Precision, machine: Machine) -> sqlx::Result<KioskSlide> {
+    let since = current_timestamp() - 60 * 60;
+    let (target_speed, product): (Option<f64>, Option<String>) = sqlx::query_as(&format!(
+        "SELECT {}, (SELECT product FROM machine_batches WHERE machine_id = machines.id AND ended_at IS NULL) \
+         FROM machines WHERE id = $1",
+        products::TARGET_SPEED
+    ))
+    .bind(machine.id)
+    .fetch_one(pool)
+    .await?;
+    let mut last_hour = sqlx::query_as::<_, SpeedAggregates>(
+        "SELECT COUNT(*) AS samples, AVG(speed) AS avg_speed, MIN(speed) AS min_speed, MAX(speed) AS max_speed \
+         FROM speed_history WHERE machine_id = $1 AND timestamp >= $2"
+    )
+    .bind(machine.id)
+    .bind(since)
+    .fetch_one(pool)
+    .await?;
+    for value in [&mut last_hour.avg_speed, &mut last_hour.min_speed, &mut last_hour.max_speed] {
+        *value = value.map(|speed| precision.round("speed", speed));
+    }
+    let mut trend = sqlx::query_as::<_, KioskTrendPoint>(
+        "SELECT timestamp - timestamp % 60 AS timestamp, AVG(speed) AS speed FROM speed_history \
+         WHERE machine_id = $1 AND timestamp >= $2 GROUP BY timestamp - timestamp % 60 ORDER BY 1"
+    )
+    .bind(machine.id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+    for point in &mut trend {
+        point.speed = precision.round("speed", point.speed);
+    }
+    let (active_alarms, alarm_severity): (i64, Option<String>) = sqlx::query_as(
+        "SELECT COUNT(*), (SELECT severity FROM alarms WHERE machine_id = $1 AND cleared_at IS NULL \
+         ORDER BY CASE severity WHEN 'critical' THEN 0 WHEN 'warning' THEN 1 ELSE 2 END LIMIT 1) \
+         FROM alarms WHERE machine_id = $2 AND cleared_at IS NULL"
+    )
+    .bind(machine.id)
+    .bind(machine.id)
+    .fetch_one(pool)
+    .await?;
+    let open_work_orders: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM work_orders WHERE machine_id = $1 AND status IN ('open', 'in_progress')"
+    )
+    .bind(machine.id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(KioskSlide {
+        machine,
+        target_speed,
+        product,
+        last_hour,
+        trend,
+        active_alarms,
+        alarm_severity,
+        open_work_orders,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(machine_ids: &[i64], rotation_interval_secs: Option<i64>) -> SetKioskGroupRequest {
+        SetKioskGroupRequest { machine_ids: machine_ids.to_vec(), rotation_interval_secs }
+    }
+
+    #[tokio::test]
+    async fn groups_keep_their_machines_in_display_order() {
+        let pool = crate::database::test_database().await;
+        sqlx::query(
+            "INSERT INTO machines (id, name, code, api_key) VALUES \
+             (1, 'Line 1', 'L1', 'key1'), (2, 'Line 2', 'L2', 'key2'), (3, 'Line 3', 'L3', 'key3')"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        save_group(&pool, "packing", &group(&[1, 2], None), "boss").await.unwrap();
+        save_group(&pool, "packing", &group(&[3, 1], Some(20)), "boss").await.unwrap();
+        save_group(&pool, "lobby", &group(&[2], None), "boss").await.unwrap();
+
+        let groups = load_groups(&pool, None).await.unwrap();
+        let summary: Vec<(&str, &[i64], Option<i64>)> = groups
+            .iter()
+            .map(|group| (group.name.as_str(), group.machine_ids.as_slice(), group.rotation_interval_secs))
+            .collect();
+        assert_eq!(summary, [("lobby", &[2][..], None), ("packing", &[3, 1][..], Some(20))]);
+
+        assert!(delete_group(&pool, "packing").await.unwrap());
+        assert!(!delete_group(&pool, "packing").await.unwrap());
+        assert!(load_groups(&pool, Some("packing")).await.unwrap().is_empty());
+        let members: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM kiosk_group_machines").fetch_one(&pool).await.unwrap();
+        assert_eq!(members, 1);
+    }
+}
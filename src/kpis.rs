@@ -0,0 +1,262 @@
+use sqlx::Row;
+use std::collections::HashMap;
+
+use crate::{
+    access,
+    auth::AuthUser,
+    database::DbPool,
+    expr::Expr,
+    models::{KpiDefinition, KpiValue},
+    products,
+};
+
+// What a KPI can select machines by, along with the values its expressions read
+struct MachineState {
+    location: Option<String>,
+    machine_type: Option<String>,
+    kiosk_groups: Vec<String>,
+    values: HashMap<String, f64>,
+}
+
+// Every machine the user may see with its current speed, online state (1 or 0), clock drift
+// once measured, target speed (see products::TARGET_SPEED) when it has one and the latest
+// value of each metric. Over a `[from, to)` window, speed and metrics are instead the
+// averages of the readings stored in it, and missing for machines that stored none.
+async fn machine_states(pool: &DbPool, user: &AuthUser, window: Option<(i64, i64)>) -> sqlx::Result<HashMap<i64, MachineState>> {
+    let machines = sqlx::query(&format!(
+        "SELECT id, location, machine_type, current_speed, is_online, clock_drift, {} AS target_speed FROM machines \
+         WHERE {} AND archived_at IS NULL",
+        products::TARGET_SPEED,
+        access::visible_machines(1)
+    ))
+    .bind(access::sees_all(user))
+    .bind(&user.username)
+    .fetch_all(pool)
+    .await?;
+
+    let mut states: HashMap<i64, MachineState> = machines
+        .iter()
+        .map(|row| {
+            let mut values = HashMap::from([
+                ("speed".to_string(), row.get("current_speed")),
+                ("is_online".to_string(), if row.get("is_online") { 1.0 } else { 0.0 }),
+            ]);
+            if let Some(clock_drift) = row.get::<Option<i64>, _>("clock_drift") {
+                values.insert("clock_drift".to_string(), clock_drift as f64);
+            }
+            if let Some(target_speed) = row.get::<Option<f64>, _>("target_speed") {
+                values.insert("target_speed".to_string(), target_speed);
+            }
+            let state = MachineState {
+                location: row.get("location"),
+                machine_type: row.get("machine_type"),
+                kiosk_groups: Vec::new(),
+                values,
+            };
+            (row.get("id"), state)
+        })
+        .collect();
+
+    let metrics: Vec<(i64, String, f64)> = match window {
+        Some((from, to)) => {
+            let speeds: HashMap<i64, f64> = sqlx::query_as(
+                "SELECT machine_id, AVG(speed) FROM speed_history WHERE timestamp >= $1 AND timestamp < $2 GROUP BY machine_id"
+            )
+            .bind(from)
+            .bind(to)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+            for (machine_id, state) in &mut states {
+                match speeds.get(machine_id) {
+                    Some(speed) => state.values.insert("speed".to_string(), *speed),
+                    None => state.values.remove("speed"),
+                };
+            }
+            sqlx::query_as(
+                "SELECT machine_id, metric, AVG(value) FROM metric_readings \
+                 WHERE flagged = FALSE AND timestamp >= $1 AND timestamp < $2 GROUP BY machine_id, metric"
+            )
+            .bind(from)
+            .bind(to)
+            .fetch_all(pool)
+            .await?
+        },
+        None => sqlx::query_as("SELECT machine_id, metric, value FROM machine_metrics").fetch_all(pool).await?,
+    };
+    for (machine_id, metric, value) in metrics {
+        if let Some(state) = states.get_mut(&machine_id) {
+            state.values.insert(metric, value);
+        }
+    }
+    let members: Vec<(i64, String)> = sqlx::query_as("SELECT machine_id, group_name FROM kiosk_group_machines")
+        .fetch_all(pool)
+        .await?;
+    for (machine_id, group) in members {
+        if let Some(state) = states.get_mut(&machine_id) {
+            state.kiosk_groups.push(group);
+        }
+    }
+    Ok(states)
+}
+
+// Aggregates one KPI over the machines in its scope. Machines missing a value the filter or
+// expression reads are left out rather than counted as zero.
+fn compute(kpi: &KpiDefinition, states: &HashMap<i64, MachineState>) -> KpiValue {
+    // Definitions are validated on save, so a parse failure means the grammar changed underneath
+    let filter = kpi.filter.as_deref().map(Expr::parse);
+    let expression = kpi.expression.as_deref().map(Expr::parse_value);
+
+    let mut values = Vec::new();
+    if !matches!(filter, Some(Err(_))) && !matches!(expression, Some(Err(_))) {
+        for state in states.values() {
+            if kpi.location.is_some() && state.location != kpi.location
+                || kpi.machine_type.is_some() && state.machine_type != kpi.machine_type
+                || kpi.kiosk_group.as_ref().is_some_and(|group| !state.kiosk_groups.contains(group))
+            {
+                continue;
+            }
+            if let Some(Ok(filter)) = &filter
+                && filter.evaluate(&state.values) != Some(true)
+            {
+                continue;
+            }
+            match &expression {
+                Some(Ok(expression)) => {
+                    if let Some(value) = expression.value(&state.values).filter(|value| value.is_finite()) {
+                        values.push(value);
+                    }
+                },
+                _ => values.push(1.0),
+            }
+        }
+    }
+
+    let value = match kpi.aggregation.as_str() {
+        "count" => Some(values.len() as f64),
+        _ if values.is_empty() => None,
+        "sum" => Some(values.iter().sum()),
+        "avg" => Some(values.iter().sum::<f64>() / values.len() as f64),
+        "min" => values.iter().copied().reduce(f64::min),
+        _ => values.iter().copied().reduce(f64::max),
+    };
+    KpiValue {
+        id: kpi.id,
+        label: kpi.label.clone(),
+        value,
+        target: kpi.target,
+        unit: kpi.unit.clone(),
+        machine_count: values.len() as i64,
+    }
+}
+
+// Value of every KPI, in dashboard order, over the machines the user may see: current, or
+// over the readings of a `[from, to)` window
+pub async fn values(pool: &DbPool, user: &AuthUser, window: Option<(i64, i64)>) -> sqlx::Result<Vec<KpiValue>> {
+    let kpis = sqlx::query_as::<_, KpiDefinition>("SELECT * FROM kpi_definitions ORDER BY position, id")
+        .fetch_all(pool)
+        .await?;
+    if kpis.is_empty() {
+        return Ok(Vec::new());
+    }
+    let states = machine_states(pool, user, window).await?;
+    Ok(kpis.iter().map(|kpi| compute(kpi, &states)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kpi(aggregation: &str, expression: Option<&str>, filter: Option<&str>) -> KpiDefinition {
+        KpiDefinition {
+            id: 1,
+            label: "Output".to_string(),
+            aggregation: aggregation.to_string(),
+            expression: expression.map(str::to_string),
+            filter: filter.map(str::to_string),
+            location: None,
+            machine_type: None,
+            kiosk_group: None,
+            target: None,
+            unit: None,
+            position: 0,
+            updated_by: "boss".to_string(),
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    fn states() -> HashMap<i64, MachineState> {
+        let state = |location: &str, group: Option<&str>, values: &[(&str, f64)]| MachineState {
+            location: Some(location.to_string()),
+            machine_type: None,
+            kiosk_groups: group.into_iter().map(str::to_string).collect(),
+            values: values.iter().map(|(metric, value)| (metric.to_string(), *value)).collect(),
+        };
+        HashMap::from([
+            (1, state("Hall A", Some("packing"), &[("speed", 10.0), ("is_online", 1.0), ("temperature", 70.0)])),
+            (2, state("Hall A", None, &[("speed", 30.0), ("is_online", 1.0)])),
+            (3, state("Hall B", Some("packing"), &[("speed", 0.0), ("is_online", 0.0), ("temperature", 20.0)])),
+        ])
+    }
+
+    #[test]
+    fn aggregates_over_the_machines_in_scope() {
+        let states = states();
+        let value = |kpi: &KpiDefinition| {
+            let value = compute(kpi, &states);
+            (value.value, value.machine_count)
+        };
+        assert_eq!(value(&kpi("count", None, Some("is_online == 1"))), (Some(2.0), 2));
+        assert_eq!(value(&kpi("sum", Some("speed * 60"), None)), (Some(2400.0), 3));
+        assert_eq!(value(&kpi("avg", Some("speed"), Some("is_online == 1"))), (Some(20.0), 2));
+        assert_eq!(value(&kpi("max", Some("speed"), None)), (Some(30.0), 3));
+
+        // Machines without a temperature are left out, not counted as zero
+        assert_eq!(value(&kpi("min", Some("temperature"), None)), (Some(20.0), 2));
+        assert_eq!(value(&kpi("count", None, Some("temperature > 50"))), (Some(1.0), 1));
+
+        let mut scoped = kpi("sum", Some("speed"), None);
+        scoped.location = Some("Hall A".to_string());
+        assert_eq!(value(&scoped), (Some(40.0), 2));
+        scoped.kiosk_group = Some("packing".to_string());
+        assert_eq!(value(&scoped), (Some(10.0), 1));
+    }
+
+    #[test]
+    fn no_value_without_machines_except_for_counts() {
+        let states = states();
+        let value = compute(&kpi("avg", Some("pressure"), None), &states);
+        assert_eq!((value.value, value.machine_count), (None, 0));
+        assert_eq!(compute(&kpi("count", None, Some("pressure > 1")), &states).value, Some(0.0));
+    }
+
+    #[tokio::test]
+    async fn a_window_averages_the_readings_stored_in_it() {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key, current_speed) VALUES (1, 'Line 1', 'L1', 'key1', 50.0), \
+             (2, 'Line 2', 'L2', 'key2', 70.0)",
+            "INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES (1, 10.0, '', 100), (1, 20.0, '', 150), \
+             (1, 90.0, '', 300), (2, 70.0, '', 300)",
+            "INSERT INTO metric_readings (machine_id, metric, value, unit, flagged, timestamp) \
+             VALUES (1, 'temperature', 60.0, 'degC', FALSE, 100), (1, 'temperature', 900.0, 'degC', TRUE, 120)",
+            "INSERT INTO machine_metrics (machine_id, metric, unit, value, updated_at) VALUES (1, 'temperature', 'degC', 80.0, 300)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        let admin = AuthUser { username: "boss".to_string(), role: crate::auth::Role::Admin, session_id: None, impersonated_by: None };
+        let speed = |states: &HashMap<i64, MachineState>, id: i64| states[&id].values.get("speed").copied();
+
+        let current = machine_states(&pool, &admin, None).await.unwrap();
+        assert_eq!((speed(&current, 1), speed(&current, 2)), (Some(50.0), Some(70.0)));
+        assert_eq!(current[&1].values.get("temperature"), Some(&80.0));
+
+        // Line 2 stored nothing in the window, so it has no speed to aggregate; the flagged
+        // reading is left out of the average
+        let window = machine_states(&pool, &admin, Some((100, 200))).await.unwrap();
+        assert_eq!((speed(&window, 1), speed(&window, 2)), (Some(15.0), None));
+        assert_eq!(window[&1].values.get("temperature"), Some(&60.0));
+    }
+}
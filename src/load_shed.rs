@@ -0,0 +1,70 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+
+use crate::models::ErrorResponse;
+
+// Caps how many expensive requests (aggregates, bulk reads) run at once so they cannot
+// starve telemetry ingestion of database connections. Excess requests wait briefly in
+// a queue and are then shed with 503 + Retry-After.
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    permits: Arc<Semaphore>,
+    queue_timeout: Duration,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max_concurrent: usize, queue_timeout: Duration) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            queue_timeout,
+        }
+    }
+}
+
+pub async fn shed_load(State(limit): State<ConcurrencyLimit>, request: Request, next: Next) -> Response {
+    match tokio::time::timeout(limit.queue_timeout, limit.permits.clone().acquire_owned()).await {
+        Ok(Ok(_permit)) => next.run(request).await,
+        _ => {
+            tracing::warn!("Shedding request to {}: too many concurrent expensive requests", request.uri().path());
+            let retry_after = limit.queue_timeout.as_secs().max(1).to_string();
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, retry_after)],
+                Json(ErrorResponse {
+                    error: "Server busy, retry later".to_string(),
+                }),
+            )
+                .into_response()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn sheds_requests_that_wait_too_long_for_a_slot() {
+        let limit = ConcurrencyLimit::new(1, Duration::from_millis(50));
+        let app = Router::new()
+            .route("/api/reports/oee", get(|| async { "report" }))
+            .layer(middleware::from_fn_with_state(limit.clone(), shed_load));
+        let send = || app.clone().oneshot(Request::get("/api/reports/oee").body(Body::empty()).unwrap());
+
+        let busy = limit.permits.clone().acquire_owned().await.unwrap();
+        let response = send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        drop(busy);
+        assert_eq!(send().await.unwrap().status(), StatusCode::OK);
+    }
+}
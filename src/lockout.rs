@@ -0,0 +1,97 @@
+use crate::database::{DbPool, current_timestamp};
+
+// Condition on a `machine_commands` row: its machine is not locked out, or its type is one
+// allowed during a lockout. Every other command could start the machine or change its speed.
+pub const NOT_BLOCKED: &str = "((SELECT locked_out_at FROM machines WHERE id = machine_commands.machine_id) IS NULL \
+     OR machine_commands.command_type IN (SELECT t.name FROM command_types t \
+     JOIN machines m ON m.machine_type = t.machine_type \
+     WHERE m.id = machine_commands.machine_id AND t.allowed_during_lockout))";
+
+// Locks the machine out and cancels its pending commands that the lockout blocks, so nothing
+// queued before the lock was applied reaches the gateway afterwards. Returns the number of
+// commands cancelled, or None when the machine does not exist or is already locked out.
+pub async fn lock_out(pool: &DbPool, machine_id: i64, username: &str, reason: &str) -> sqlx::Result<Option<u64>> {
+    let now = current_timestamp();
+    let mut tx = pool.begin().await?;
+    let locked = sqlx::query(
+        "UPDATE machines SET locked_out_at = $1, locked_out_by = $2, lockout_reason = $3, updated_at = $4 \
+         WHERE id = $5 AND locked_out_at IS NULL"
+    )
+    .bind(now)
+    .bind(username)
+    .bind(reason)
+    .bind(now)
+    .bind(machine_id)
+    .execute(&mut *tx)
+    .await?;
+    if locked.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let cancelled = sqlx::query(&format!(
+        "UPDATE machine_commands SET status = 'cancelled', cancelled_by = $1, completed_at = $2 \
+         WHERE machine_id = $3 AND status = 'pending' AND NOT {}",
+        NOT_BLOCKED
+    ))
+    .bind(username)
+    .bind(now)
+    .bind(machine_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some(cancelled.rows_affected()))
+}
+
+// Releases the lockout. Returns false when the machine does not exist or is not locked out.
+pub async fn release(pool: &DbPool, machine_id: i64) -> sqlx::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE machines SET locked_out_at = NULL, locked_out_by = NULL, lockout_reason = NULL, updated_at = $1 \
+         WHERE id = $2 AND locked_out_at IS NOT NULL"
+    )
+    .bind(current_timestamp())
+    .bind(machine_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A press with a pending start and a pending stop; only stopping is allowed during a lockout
+    async fn database() -> DbPool {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key, machine_type) VALUES (1, 'Press 1', 'P1', 'key', 'press')",
+            "INSERT INTO command_types (machine_type, name, parameters_schema, created_at, updated_at, allowed_during_lockout) \
+             VALUES ('press', 'start', '{}', 0, 0, FALSE), ('press', 'stop', '{}', 0, 0, TRUE)",
+            "INSERT INTO machine_commands (id, machine_id, command_type, parameters, created_by, created_at) \
+             VALUES (1, 1, 'start', '{}', 'boss', 0), (2, 1, 'stop', '{}', 'boss', 0)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    async fn statuses(pool: &DbPool) -> Vec<String> {
+        sqlx::query_scalar("SELECT status FROM machine_commands ORDER BY id").fetch_all(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn lockout_cancels_the_pending_commands_it_blocks() {
+        let pool = database().await;
+        assert_eq!(lock_out(&pool, 1, "welder", "Blade change").await.unwrap(), Some(1));
+        assert_eq!(statuses(&pool).await, ["cancelled", "pending"]);
+        let deliverable: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM machine_commands WHERE status = 'pending' AND {}", NOT_BLOCKED))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(deliverable, 1);
+
+        assert_eq!(lock_out(&pool, 1, "welder", "Again").await.unwrap(), None);
+        assert_eq!(lock_out(&pool, 2, "welder", "No such machine").await.unwrap(), None);
+        assert!(release(&pool, 1).await.unwrap());
+        assert!(!release(&pool, 1).await.unwrap());
+    }
+}
@@ -0,0 +1,230 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use ipnet::IpNet;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    auth::SessionClient,
+    database::{current_timestamp, DbPool},
+    models::ErrorResponse,
+    network,
+};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+// Caps login attempts per client IP in a fixed one-minute window, so a single host cannot
+// cycle through usernames faster than the per-account lockout would notice. Excess
+// attempts get 429 + Retry-After. Behind a trusted proxy the client is taken from
+// X-Forwarded-For, so users of the proxy don't all share one budget.
+#[derive(Clone)]
+pub struct LoginRateLimit {
+    per_minute: u32,
+    trusted_proxies: Arc<[IpNet]>,
+    windows: Arc<Mutex<HashMap<IpAddr, (Instant, u32)>>>,
+}
+
+impl LoginRateLimit {
+    pub fn new(per_minute: u32, trusted_proxies: &[IpNet]) -> Self {
+        Self {
+            per_minute,
+            trusted_proxies: trusted_proxies.into(),
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Counts an attempt; returns how long the caller must wait if it is over the limit
+    fn check(&self, ip: IpAddr) -> Option<Duration> {
+        if self.per_minute == 0 {
+            return None;
+        }
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        windows.retain(|_, (started, _)| now.duration_since(*started) < RATE_WINDOW);
+
+        let (started, attempts) = windows.entry(ip).or_insert((now, 0));
+        *attempts += 1;
+        (*attempts > self.per_minute).then(|| RATE_WINDOW - now.duration_since(*started))
+    }
+}
+
+pub async fn limit_login_rate(
+    State(limit): State<LoginRateLimit>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = network::client_ip(&headers, addr.ip(), &limit.trusted_proxies);
+    match limit.check(ip) {
+        None => next.run(request).await,
+        Some(wait) => {
+            tracing::warn!("Rate limiting login attempts from {}", ip);
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, wait.as_secs().max(1).to_string())],
+                Json(ErrorResponse {
+                    error: "Too many login attempts, retry later".to_string(),
+                }),
+            )
+                .into_response()
+        },
+    }
+}
+
+// When the account is locked out, the time the lock ends
+pub async fn locked_until(pool: &DbPool, username: &str) -> sqlx::Result<Option<i64>> {
+    sqlx::query_scalar("SELECT locked_until FROM login_attempts WHERE username = $1 AND locked_until > $2")
+        .bind(username)
+        .bind(current_timestamp())
+        .fetch_optional(pool)
+        .await
+        .map(Option::flatten)
+}
+
+// Counts a failed login for a username, known or not, so lockouts don't reveal which
+// accounts exist. Failures older than the lockout duration are forgotten. After
+// `max_attempts` consecutive failures the account is locked; returns the lock's end then.
+pub async fn record_failure(pool: &DbPool, username: &str, max_attempts: u32, lockout: Duration) -> sqlx::Result<Option<i64>> {
+    if max_attempts == 0 {
+        return Ok(None);
+    }
+    let now = current_timestamp();
+    let lockout = lockout.as_secs() as i64;
+
+    let failed_attempts: i64 = sqlx::query_scalar(
+        "INSERT INTO login_attempts (username, failed_attempts, last_failed_at) VALUES ($1, 1, $2) \
+         ON CONFLICT (username) DO UPDATE SET \
+         failed_attempts = CASE WHEN login_attempts.last_failed_at <= $3 OR login_attempts.locked_until <= $4 THEN 1 \
+         ELSE login_attempts.failed_attempts + 1 END, \
+         locked_until = CASE WHEN login_attempts.locked_until <= $5 THEN NULL ELSE login_attempts.locked_until END, \
+         last_failed_at = excluded.last_failed_at \
+         RETURNING failed_attempts"
+    )
+    .bind(username)
+    .bind(now)
+    .bind(now - lockout)
+    .bind(now)
+    .bind(now)
+    .fetch_one(pool)
+    .await?;
+
+    if failed_attempts < max_attempts as i64 {
+        return Ok(None);
+    }
+    let locked_until = now + lockout;
+    sqlx::query("UPDATE login_attempts SET locked_until = $1 WHERE username = $2")
+        .bind(locked_until)
+        .bind(username)
+        .execute(pool)
+        .await?;
+    Ok(Some(locked_until))
+}
+
+// Forgets failed attempts and lifts any lock, after a successful login or an admin unlock
+pub async fn clear(pool: &DbPool, username: &str) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM login_attempts WHERE username = $1")
+        .bind(username)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// Adds a login attempt to the login history, with `failure_reason` None for a successful one,
+// and drops entries older than `retention`. A successful login also becomes the user's last_login.
+pub async fn record_attempt(
+    pool: &DbPool,
+    username: &str,
+    failure_reason: Option<&str>,
+    client: &SessionClient,
+    retention: Duration,
+) -> sqlx::Result<()> {
+    let now = current_timestamp();
+    // Attempts can name any username, so what is kept of it is bounded
+    let username: String = username.chars().take(256).collect();
+    sqlx::query(
+        "INSERT INTO login_history (username, succeeded, failure_reason, ip, user_agent, created_at) VALUES ($1, $2, $3, $4, $5, $6)"
+    )
+    .bind(&username)
+    .bind(failure_reason.is_none())
+    .bind(failure_reason)
+    .bind(&client.ip)
+    .bind(&client.user_agent)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    if failure_reason.is_none() {
+        sqlx::query("UPDATE users SET last_login = $1 WHERE username = $2")
+            .bind(now)
+            .bind(&username)
+            .execute(pool)
+            .await?;
+    }
+    sqlx::query("DELETE FROM login_history WHERE created_at < $1")
+        .bind(now - retention.as_secs() as i64)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKOUT: Duration = Duration::from_secs(900);
+
+    #[tokio::test]
+    async fn locks_an_account_after_consecutive_failures_until_cleared() {
+        let pool = crate::database::test_database().await;
+        for _ in 0..2 {
+            assert_eq!(record_failure(&pool, "tech1", 3, LOCKOUT).await.unwrap(), None);
+        }
+        assert_eq!(locked_until(&pool, "tech1").await.unwrap(), None);
+        let lock = record_failure(&pool, "tech1", 3, LOCKOUT).await.unwrap().unwrap();
+        assert!(lock >= current_timestamp() + 899);
+        assert_eq!(locked_until(&pool, "tech1").await.unwrap(), Some(lock));
+        // Other accounts, known or not, keep their own count
+        assert_eq!(locked_until(&pool, "nobody").await.unwrap(), None);
+
+        clear(&pool, "tech1").await.unwrap();
+        assert_eq!(locked_until(&pool, "tech1").await.unwrap(), None);
+        assert_eq!(record_failure(&pool, "tech1", 3, LOCKOUT).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn forgets_failures_older_than_the_lockout() {
+        let pool = crate::database::test_database().await;
+        for _ in 0..2 {
+            record_failure(&pool, "tech1", 3, LOCKOUT).await.unwrap();
+        }
+        sqlx::query("UPDATE login_attempts SET last_failed_at = $1")
+            .bind(current_timestamp() - 901)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(record_failure(&pool, "tech1", 3, LOCKOUT).await.unwrap(), None);
+        // No limit at all with max_attempts 0
+        for _ in 0..5 {
+            assert_eq!(record_failure(&pool, "admin", 0, LOCKOUT).await.unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn limits_attempts_per_client_ip() {
+        let limit = LoginRateLimit::new(2, &[]);
+        let (client, other): (IpAddr, IpAddr) = ("192.0.2.10".parse().unwrap(), "192.0.2.11".parse().unwrap());
+        assert_eq!(limit.check(client), None);
+        assert_eq!(limit.check(client), None);
+        assert!(limit.check(client).is_some_and(|wait| wait <= RATE_WINDOW));
+        assert_eq!(limit.check(other), None);
+        assert_eq!(LoginRateLimit::new(0, &[]).check(client), None);
+    }
+}
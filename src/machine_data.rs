@@ -0,0 +1,263 @@
+use sqlx::Transaction;
+use std::collections::BTreeMap;
+
+use crate::{database::{Db, current_timestamp}, models::MACHINE_KEY_SCOPES};
+
+// A table holding data of a machine, selected by `condition` with the machine id bound
+pub struct MachineTable {
+    pub table: &'static str,
+    pub condition: &'static str,
+    // Name of the file in the archive; tables without one are purged but not archived
+    pub file: Option<&'static str>,
+}
+
+pub const BY_MACHINE: &str = "machine_id = $1";
+pub const BY_WORK_ORDER: &str = "work_order_id IN (SELECT id FROM work_orders WHERE machine_id = $1)";
+pub const BY_HISTORY_SHIFT: &str = "shift_id IN (SELECT id FROM history_shifts WHERE machine_id = $1)";
+
+// Everything stored about a machine besides its own row, listed so that rows are purged
+// before the rows they reference. History goes into CSV files, everything else into JSON.
+pub const MACHINE_TABLES: &[MachineTable] = &[
+    MachineTable { table: "speed_history", condition: BY_MACHINE, file: Some("speed_history.csv") },
+    MachineTable { table: "speed_history_hourly", condition: BY_MACHINE, file: None },
+    MachineTable { table: "speed_history_daily", condition: BY_MACHINE, file: None },
+    MachineTable { table: "machine_health_scores", condition: BY_MACHINE, file: Some("health_scores.json") },
+    MachineTable { table: "history_shift_rows", condition: BY_HISTORY_SHIFT, file: None },
+    MachineTable { table: "history_shifts", condition: BY_MACHINE, file: Some("history_shifts.json") },
+    MachineTable { table: "metric_readings", condition: BY_MACHINE, file: Some("metric_readings.csv") },
+    MachineTable { table: "machine_metrics", condition: BY_MACHINE, file: Some("metrics.json") },
+    MachineTable { table: "maintenance_comments", condition: BY_MACHINE, file: Some("comments.json") },
+    MachineTable { table: "work_order_steps", condition: BY_WORK_ORDER, file: Some("work_order_steps.json") },
+    MachineTable { table: "work_order_labor", condition: BY_WORK_ORDER, file: Some("work_order_labor.json") },
+    MachineTable { table: "work_permits", condition: BY_MACHINE, file: Some("permits.json") },
+    MachineTable { table: "maintenance_costs", condition: BY_MACHINE, file: Some("maintenance_costs.json") },
+    MachineTable { table: "work_orders", condition: BY_MACHINE, file: Some("work_orders.json") },
+    MachineTable { table: "alarms", condition: BY_MACHINE, file: Some("alarms.json") },
+    MachineTable { table: "alarm_rules", condition: BY_MACHINE, file: Some("alarm_rules.json") },
+    MachineTable { table: "compliance_exceedances", condition: BY_MACHINE, file: Some("compliance_exceedances.json") },
+    MachineTable { table: "compliance_limits", condition: BY_MACHINE, file: Some("compliance_limits.json") },
+    MachineTable { table: "failure_predictions", condition: BY_MACHINE, file: None },
+    MachineTable { table: "maintenance_advisories", condition: BY_MACHINE, file: Some("advisories.json") },
+    MachineTable { table: "failure_thresholds", condition: BY_MACHINE, file: Some("failure_thresholds.json") },
+    MachineTable { table: "notifications", condition: BY_MACHINE, file: Some("notifications.json") },
+    MachineTable { table: "machine_commands", condition: BY_MACHINE, file: Some("commands.json") },
+    MachineTable { table: "export_requests", condition: BY_MACHINE, file: Some("export_requests.json") },
+    MachineTable { table: "machine_contracts", condition: BY_MACHINE, file: Some("contracts.json") },
+    MachineTable { table: "machine_documents", condition: BY_MACHINE, file: Some("documents.json") },
+    MachineTable { table: "machine_display_names", condition: BY_MACHINE, file: Some("display_names.json") },
+    MachineTable { table: "trend_annotations", condition: BY_MACHINE, file: Some("annotations.json") },
+    MachineTable { table: "machine_batches", condition: BY_MACHINE, file: Some("batches.json") },
+    MachineTable { table: "changeovers", condition: BY_MACHINE, file: Some("changeovers.json") },
+    MachineTable { table: "operator_assignments", condition: BY_MACHINE, file: Some("assignments.json") },
+    MachineTable { table: "machine_api_keys", condition: BY_MACHINE, file: None },
+    MachineTable { table: "user_machine_access", condition: BY_MACHINE, file: None },
+    MachineTable { table: "signed_request_nonces", condition: BY_MACHINE, file: None },
+    MachineTable { table: "kiosk_group_machines", condition: BY_MACHINE, file: None },
+    MachineTable { table: "machine_snoozes", condition: BY_MACHINE, file: None },
+];
+
+// How a row `r` of a table clashes with a row `o` of the same table when both belong to the
+// same machine, for the tables with a row that must be unique per machine
+const MACHINE_UNIQUE_KEYS: &[(&str, &str)] = &[
+    ("speed_history_hourly", "o.bucket_start = r.bucket_start"),
+    ("speed_history_daily", "o.bucket_start = r.bucket_start"),
+    ("machine_health_scores", "o.day = r.day"),
+    ("machine_metrics", "o.metric = r.metric"),
+    ("failure_predictions", "TRUE"),
+    ("machine_display_names", "o.locale = r.locale"),
+    ("machine_batches", "o.ended_at IS NULL AND r.ended_at IS NULL"),
+    ("changeovers", "o.ended_at IS NULL AND r.ended_at IS NULL"),
+    ("operator_assignments", "o.shift_date = r.shift_date AND o.shift = r.shift AND o.username = r.username"),
+    ("user_machine_access", "o.username = r.username"),
+    ("signed_request_nonces", "o.nonce = r.nonce"),
+    ("kiosk_group_machines", "o.group_name = r.group_name"),
+    ("machine_snoozes", "o.username = r.username"),
+];
+
+// Deletes a machine with all of its data and returns the paths of its uploaded document
+// files, which the caller removes once the transaction has committed
+pub async fn purge(tx: &mut Transaction<'_, Db>, machine_id: i64) -> sqlx::Result<Vec<String>> {
+    let document_files: Vec<String> = sqlx::query_scalar(
+        "SELECT file_path FROM machine_documents WHERE machine_id = $1 AND file_path IS NOT NULL"
+    )
+    .bind(machine_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for table in MACHINE_TABLES {
+        sqlx::query(&format!("DELETE FROM {} WHERE {}", table.table, table.condition))
+            .bind(machine_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    let deleted = sqlx::query("DELETE FROM machines WHERE id = $1")
+        .bind(machine_id)
+        .execute(&mut **tx)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(document_files)
+}
+
+// Rows moved onto the surviving machine and rows dropped as duplicates, per table
+#[derive(Default)]
+pub struct MergeCounts {
+    pub moved: BTreeMap<String, i64>,
+    pub dropped: BTreeMap<String, i64>,
+}
+
+// Moves everything stored about machine `source` onto `target` and deletes `source`. Where
+// both machines have a row that must be unique per machine (a metric, a display name, a
+// user's access), the target's is kept, except that a metric keeps its latest value. The
+// source's API key keeps working as an additional key of the target, and the target takes
+// over the source's state if the source reported more recently.
+pub async fn merge(tx: &mut Transaction<'_, Db>, source: i64, target: i64) -> sqlx::Result<MergeCounts> {
+    let mut counts = MergeCounts::default();
+
+    sqlx::query(
+        "DELETE FROM machine_metrics WHERE machine_id = $1 AND EXISTS (SELECT 1 FROM machine_metrics s \
+         WHERE s.machine_id = $2 AND s.metric = machine_metrics.metric AND s.updated_at > machine_metrics.updated_at)"
+    )
+    .bind(target)
+    .bind(source)
+    .execute(&mut **tx)
+    .await?;
+
+    // Rows of other tables follow the rows they reference, e.g. work order steps their work order
+    for table in MACHINE_TABLES.iter().filter(|table| table.condition == BY_MACHINE) {
+        // Rows clashing with one of the target's stay behind and are dropped below
+        let clash = MACHINE_UNIQUE_KEYS.iter().find(|(name, _)| *name == table.table).map_or("FALSE", |(_, clash)| clash);
+        let moved = sqlx::query(&format!(
+            "UPDATE {0} AS r SET machine_id = $1 WHERE machine_id = $2 \
+             AND NOT EXISTS (SELECT 1 FROM {0} o WHERE o.machine_id = $1 AND {1})",
+            table.table, clash
+        ))
+        .bind(target)
+        .bind(source)
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+        let dropped = sqlx::query(&format!("DELETE FROM {} WHERE machine_id = $1", table.table))
+            .bind(source)
+            .execute(&mut **tx)
+            .await?
+            .rows_affected();
+        if moved > 0 {
+            counts.moved.insert(table.table.to_string(), moved as i64);
+        }
+        if dropped > 0 {
+            counts.dropped.insert(table.table.to_string(), dropped as i64);
+        }
+    }
+
+    sqlx::query(
+        "INSERT INTO machine_api_keys (machine_id, name, api_key, scopes, created_at) \
+         SELECT $1, 'Merged from ' || name, api_key, $2, $3 FROM machines WHERE id = $4"
+    )
+    .bind(target)
+    .bind(MACHINE_KEY_SCOPES.join(","))
+    .bind(current_timestamp())
+    .bind(source)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        "UPDATE machines SET (current_speed, status_message, last_update, is_online) = \
+         (SELECT current_speed, status_message, last_update, is_online FROM machines WHERE id = $1) \
+         WHERE id = $2 AND last_update < (SELECT last_update FROM machines WHERE id = $3)"
+    )
+    .bind(source)
+    .bind(target)
+    .bind(source)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query("UPDATE machines SET updated_at = $1 WHERE id = $2")
+        .bind(current_timestamp())
+        .bind(target)
+        .execute(&mut **tx)
+        .await?;
+
+    let deleted = sqlx::query("DELETE FROM machines WHERE id = $1")
+        .bind(source)
+        .execute(&mut **tx)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DbPool;
+
+    // Line 2 is a duplicate of line 1 that reported more recently
+    async fn database() -> DbPool {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key, current_speed, last_update) VALUES \
+             (1, 'Line 1', 'L1', 'key1', 10.0, 100), (2, 'Line 1 (new gateway)', 'L1-NEW', 'key2', 20.0, 200)",
+            "INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES (1, 10.0, 'running', 100), \
+             (2, 20.0, 'running', 200)",
+            "INSERT INTO machine_metrics (machine_id, metric, unit, value, updated_at) VALUES \
+             (1, 'temperature', 'degC', 60.0, 100), (1, 'pressure', 'bar', 2.0, 100), \
+             (2, 'temperature', 'degC', 65.0, 200), (2, 'pressure', 'bar', 1.0, 50)",
+            "INSERT INTO user_machine_access (username, machine_id, granted_at) VALUES \
+             ('alice', 1, 0), ('alice', 2, 0), ('bob', 2, 0)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    #[tokio::test]
+    async fn merging_moves_data_and_keeps_the_latest_values() {
+        let pool = database().await;
+        let mut tx = pool.begin().await.unwrap();
+        let counts = merge(&mut tx, 2, 1).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let count = |table: &str| counts.moved.get(table).copied();
+        assert_eq!(count("speed_history"), Some(1));
+        assert_eq!(count("machine_metrics"), Some(1));
+        assert_eq!(count("user_machine_access"), Some(1));
+        assert_eq!(counts.dropped.get("machine_metrics"), Some(&1));
+        assert_eq!(counts.dropped.get("user_machine_access"), Some(&1));
+
+        let metrics: Vec<(String, f64)> =
+            sqlx::query_as("SELECT metric, value FROM machine_metrics WHERE machine_id = 1 ORDER BY metric")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(metrics, [("pressure".to_string(), 2.0), ("temperature".to_string(), 65.0)]);
+        let (speed, last_update): (f64, i64) =
+            sqlx::query_as("SELECT current_speed, last_update FROM machines WHERE id = 1").fetch_one(&pool).await.unwrap();
+        assert_eq!((speed, last_update), (20.0, 200));
+        let keys: Vec<(i64, String)> =
+            sqlx::query_as("SELECT machine_id, api_key FROM machine_api_keys").fetch_all(&pool).await.unwrap();
+        assert_eq!(keys, [(1, "key2".to_string())]);
+        let machines: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM machines").fetch_one(&pool).await.unwrap();
+        assert_eq!(machines, 1);
+    }
+
+    #[tokio::test]
+    async fn purging_leaves_nothing_of_the_machine() {
+        let pool = database().await;
+        let mut tx = pool.begin().await.unwrap();
+        assert!(purge(&mut tx, 2).await.unwrap().is_empty());
+        assert!(matches!(purge(&mut tx, 2).await, Err(sqlx::Error::RowNotFound)));
+        tx.commit().await.unwrap();
+
+        for table in ["speed_history", "machine_metrics", "user_machine_access"] {
+            let left: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {} WHERE machine_id = 2", table))
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(left, 0, "{}", table);
+        }
+        let history: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM speed_history").fetch_one(&pool).await.unwrap();
+        assert_eq!(history, 1);
+    }
+}
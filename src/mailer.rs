@@ -0,0 +1,87 @@
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::str::FromStr;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpSecurity {
+    // Plain connection upgraded with STARTTLS, usually port 587
+    StartTls,
+    // TLS from the first byte, usually port 465
+    Tls,
+    // Unencrypted, for a relay on the local network
+    None,
+}
+
+impl FromStr for SmtpSecurity {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            "none" => Ok(Self::None),
+            _ => Err("expected 'starttls', 'tls' or 'none'".to_string()),
+        }
+    }
+}
+
+// Sends plain text email over SMTP. Without SCADA_SMTP_HOST every send fails, so features
+// relying on email report that it is not configured instead of silently dropping mail.
+#[derive(Clone)]
+pub struct Mailer {
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let from = config
+            .mail_from
+            .parse::<Mailbox>()
+            .map_err(|e| anyhow::anyhow!("Invalid SCADA_MAIL_FROM '{}': {}", config.mail_from, e))?;
+
+        let transport = match &config.smtp_host {
+            Some(host) => {
+                let mut builder = match config.smtp_security {
+                    SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+                    SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+                    SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+                }
+                .port(config.smtp_port);
+                if let Some(username) = &config.smtp_username {
+                    builder = builder.credentials(Credentials::new(
+                        username.clone(),
+                        config.smtp_password.clone().unwrap_or_default(),
+                    ));
+                }
+                Some(builder.build())
+            },
+            None => None,
+        };
+
+        Ok(Self { transport, from })
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> anyhow::Result<()> {
+        let Some(transport) = &self.transport else {
+            anyhow::bail!("Email is not configured, set SCADA_SMTP_HOST");
+        };
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)?;
+        transport.send(message).await?;
+        Ok(())
+    }
+}
@@ -1,52 +1,462 @@
 use axum::{
-    routing::{get, post, put},
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, post, put},
     Router,
 };
+use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
+use scada_models as models;
 use std::net::SocketAddr;
-use tower_http::cors::CorsLayer;
+use tower_http::{
+    catch_panic::CatchPanicLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
 use tokio::signal;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod access;
+mod alarm_work_orders;
+mod alarms;
+mod archive;
 mod auth;
+mod backup;
+mod batches;
+mod benchmark;
+mod certifications;
+mod changeovers;
+mod chaos;
+mod commands;
+mod compliance;
+mod config;
+mod cors;
 mod database;
+mod display_tokens;
+mod distribution;
+mod export;
+mod export_requests;
+mod expr;
+mod features;
+mod gaps;
 mod handlers;
-mod models;
+mod hardening;
+mod health;
+mod history_shift;
+mod inactive_users;
+mod incidents;
+mod jobs;
+mod kiosk;
+mod kpis;
+mod load_shed;
+mod lockout;
+mod login_guard;
+mod machine_data;
+mod mailer;
+mod network;
+mod notifications;
+mod offline;
+mod password_policy;
+mod permits;
+mod precision;
+mod predictions;
+mod products;
+mod rate_limit;
+mod read_only;
+mod retention;
+mod rollups;
+mod service_accounts;
+mod signing;
+mod site;
+mod state;
+mod telemetry;
+mod timerange;
+mod usage;
+mod units;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Initialize tracing with proper configuration
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-    
+    // Load configuration
+    let config = match config::Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            return Err(e);
+        }
+    };
+
+    // Initialize tracing and error reporting
+    let telemetry = telemetry::init(&config)?;
+    auth::init_sessions(&config.jwt_secret, config.access_token_ttl, config.refresh_token_ttl);
+
+    // Replaces the database with a backup before it is opened, then migrates it and exits
+    let mut restore_args = std::env::args().skip_while(|arg| arg != "--restore");
+    let restore_from = match (restore_args.next(), restore_args.next()) {
+        (None, _) => None,
+        (Some(_), Some(file)) => Some(std::path::PathBuf::from(file)),
+        (Some(_), None) => anyhow::bail!("--restore needs the backup file to restore"),
+    };
+    if let Some(backup) = &restore_from
+        && let Err(e) = backup::restore(&config, backup).await
+    {
+        eprintln!("Failed to restore the database: {}", e);
+        return Err(e);
+    }
+
     // Initialize database
-    let db = match database::init_database().await {
+    let chaos = chaos::Chaos::default();
+    let db = match database::init_database(&config, config.dev_chaos.then(|| chaos.clone())).await {
         Ok(pool) => pool,
         Err(e) => {
             eprintln!("Failed to initialize database: {}", e);
             return Err(e);
         }
     };
+    // Lets ops apply schema changes ahead of a rollout without starting the server
+    if std::env::args().skip(1).any(|arg| arg == "--migrate-only") {
+        tracing::info!("Database migrated, exiting (--migrate-only)");
+        return Ok(());
+    }
+    if restore_from.is_some() {
+        tracing::info!("Database restored and migrated, exiting (--restore)");
+        return Ok(());
+    }
+    if let Err(e) = features::seed(&db, &config.features).await {
+        eprintln!("Failed to seed feature flags: {}", e);
+        return Err(e.into());
+    }
+    if let Err(e) = auth::bootstrap_admin(&db, &config.admin_user, config.admin_password.as_deref(), &config.password_policy).await {
+        eprintln!("Failed to create admin user: {}", e);
+        return Err(e);
+    }
+    match auth::hash_plaintext_keys(&db).await {
+        Ok(0) => {},
+        Ok(count) => tracing::info!("Replaced {} plaintext machine API key(s) with their hash", count),
+        Err(e) => {
+            eprintln!("Failed to hash stored API keys: {}", e);
+            return Err(e.into());
+        }
+    }
+    if let Err(e) = auth::load_revocations(&db).await {
+        eprintln!("Failed to load revoked tokens: {}", e);
+        return Err(e.into());
+    }
     
-    // Build routes
-    let app = Router::new()
-        .route("/api/login", post(handlers::login))
+    // Expensive read endpoints share a concurrency budget so they can't starve ingestion
+    let expensive = middleware::from_fn_with_state(
+        load_shed::ConcurrencyLimit::new(config.expensive_concurrency, config.expensive_queue_timeout),
+        load_shed::shed_load,
+    );
+
+    let port = config.port;
+    let mailer = match mailer::Mailer::new(&config) {
+        Ok(mailer) => mailer,
+        Err(e) => {
+            eprintln!("Invalid mail configuration: {}", e);
+            return Err(e);
+        }
+    };
+    let state = state::AppState::new(db, config, chaos, mailer);
+    if let Err(e) = state.jobs.fail_interrupted().await {
+        eprintln!("Failed to recover interrupted jobs: {}", e);
+        return Err(e);
+    }
+    let cleanup = state.jobs.spawn_artifact_cleanup();
+    state.chaos.register_task("artifact_cleanup", cleanup.abort_handle());
+    let expiry_check = notifications::spawn_contract_expiry_check(state.db.clone(), state.config.contract_notice);
+    state.chaos.register_task("contract_expiry_check", expiry_check.abort_handle());
+    let certification_check =
+        certifications::spawn_certification_expiry_check(state.db.clone(), state.config.certification_notice);
+    state.chaos.register_task("certification_expiry_check", certification_check.abort_handle());
+    let offline_check = offline::spawn_offline_check(
+        state.db.clone(),
+        state.machine_changes.clone(),
+        state.config.default_report_interval,
+        state.config.offline_after_intervals,
+    );
+    state.chaos.register_task("offline_check", offline_check.abort_handle());
+    let usage_flush = usage::spawn_usage_flush(state.db.clone(), state.usage.clone(), state.config.usage_retention);
+    state.chaos.register_task("usage_flush", usage_flush.abort_handle());
+    let rollups = rollups::spawn_rollups(state.db.clone());
+    state.chaos.register_task("rollups", rollups.abort_handle());
+    let history_retention =
+        retention::spawn_history_retention(state.db.clone(), retention::HistoryRetention::from_config(&state.config));
+    state.chaos.register_task("history_retention", history_retention.abort_handle());
+    let health_scores = health::spawn_health_scores(state.db.clone(), state.machine_changes.clone(), &state.config);
+    state.chaos.register_task("health_scores", health_scores.abort_handle());
+    let predictor = predictions::Predictor::from_config(&state.config);
+    if let Some(predictions) = predictions::spawn_predictions(state.db.clone(), predictor, state.config.prediction_interval) {
+        state.chaos.register_task("predictions", predictions.abort_handle());
+    }
+    if let Some(nightly_backup) = backup::spawn_nightly_backup(state.db.clone(), &state.config) {
+        state.chaos.register_task("nightly_backup", nightly_backup.abort_handle());
+    }
+    let alarm_work_orders = alarm_work_orders::spawn_alarm_work_orders(state.db.clone());
+    state.chaos.register_task("alarm_work_orders", alarm_work_orders.abort_handle());
+    if !state.config.inactive_user_after.is_zero() {
+        let inactivity_check = inactive_users::spawn_inactivity_check(state.db.clone(), state.config.inactive_user_after);
+        state.chaos.register_task("inactivity_check", inactivity_check.abort_handle());
+    }
+
+    // Login and password reset share one budget per client IP
+    let login_rate = middleware::from_fn_with_state(
+        login_guard::LoginRateLimit::new(state.config.login_rate_per_minute, &state.config.trusted_proxies),
+        login_guard::limit_login_rate,
+    );
+
+    // Machines and gateways call these directly, never from a browser, so they get no CORS headers
+    let ingest = Router::new()
+        .route(
+            "/api/machines/update",
+            post(handlers::update_machine_speed)
+                .route_layer(middleware::from_fn_with_state(state.clone(), signing::verify_signed_update)),
+        )
+        .route("/api/machines/me", get(handlers::get_own_machine))
+        .route("/api/machines/commands", get(handlers::poll_commands))
+        .route("/api/machines/commands/{id}/result", post(handlers::report_command_result));
+
+    // Public status, readable from any origin
+    let public = Router::new()
+        .route("/api/info", get(handlers::get_info))
+        .layer(cors::public_policy());
+
+    // Operator API, for the configured origins
+    let mut operator = Router::new()
+        .route("/api/login", post(handlers::login).route_layer(login_rate.clone()))
+        .route("/api/password-reset/request", post(handlers::request_password_reset).route_layer(login_rate.clone()))
+        .route("/api/password-policy", get(handlers::get_password_policy))
+        .route("/api/password-reset/confirm", post(handlers::confirm_password_reset).route_layer(login_rate))
+        .route("/api/logout", post(handlers::logout))
+        .route("/api/token/refresh", post(handlers::refresh_token))
         .route("/api/machines", get(handlers::list_machines).post(handlers::create_machine))
-        .route("/api/machines/update", post(handlers::update_machine_speed))
+        .route("/api/machines/stream", get(handlers::stream_machines).route_layer(middleware::from_fn(auth::token_from_query)))
+        .route("/api/machines/changes", get(handlers::wait_for_machine_changes))
         .route("/api/machines/{id}/comments", get(handlers::get_comments).post(handlers::add_comment))
-        .route("/api/machines/{id}/history", get(handlers::get_history))
-        .route("/api/machines/{id}", put(handlers::update_machine))
+        .route("/api/machines/{id}/annotations", get(handlers::list_annotations).post(handlers::add_annotation))
+        .route("/api/annotations/{id}", delete(handlers::delete_annotation))
+        .route("/api/machines/{id}/batches", get(handlers::list_machine_batches).post(handlers::start_batch))
+        .route("/api/batches", get(handlers::find_lot_batches))
+        .route("/api/batches/{id}/end", post(handlers::end_batch))
+        .route("/api/machines/{id}/changeovers", get(handlers::list_machine_changeovers).post(handlers::start_changeover))
+        .route("/api/changeovers/{id}/end", post(handlers::end_changeover))
+        .route("/api/machines/{id}/assignments", post(handlers::assign_operator))
+        .route("/api/assignments/{id}", delete(handlers::delete_assignment))
+        .route("/api/shift-schedule", get(handlers::get_shift_schedule))
+        .route("/api/machines/{id}/history", get(handlers::get_history).route_layer(expensive.clone()))
+        .route("/api/machines/{id}/full", get(handlers::get_machine_detail).route_layer(expensive.clone()))
+        .route("/api/kiosk/rotation", get(handlers::kiosk_rotation).route_layer(expensive.clone()))
+        .route("/api/kiosk/groups", get(handlers::list_kiosk_groups))
+        .route("/api/kiosk/groups/{name}", put(handlers::set_kiosk_group).delete(handlers::delete_kiosk_group))
+        .route("/api/display-tokens", get(handlers::list_display_tokens).post(handlers::create_display_token))
+        .route("/api/display-tokens/{id}", delete(handlers::revoke_display_token))
+        .route("/api/products", get(handlers::list_products))
+        .route("/api/products/{sku}", put(handlers::set_product).delete(handlers::delete_product))
+        .route("/api/kpis", get(handlers::get_kpis).route_layer(expensive.clone()))
+        .route("/api/analytics/benchmark", get(handlers::benchmark_machines).route_layer(expensive.clone()))
+        .route("/api/health-scores", get(handlers::list_health_scores))
+        .route("/api/kpi-definitions", get(handlers::list_kpi_definitions).post(handlers::create_kpi_definition))
+        .route("/api/kpi-definitions/{id}", put(handlers::update_kpi_definition).delete(handlers::delete_kpi_definition))
+        .route("/api/machines/{id}/gaps", get(handlers::get_machine_gaps).route_layer(expensive.clone()))
+        .route("/api/machines/{id}/history/export", post(handlers::export_history))
+        .route("/api/export-requests", get(handlers::list_export_requests).post(handlers::create_export_request))
+        .route("/api/export-requests/{id}", get(handlers::get_export_request))
+        .route("/api/export-requests/{id}/approve", post(handlers::approve_export_request))
+        .route("/api/export-requests/{id}/reject", post(handlers::reject_export_request))
+        .route("/api/machines/{id}/history/shift", post(handlers::shift_history))
+        .route("/api/machines/{id}/history/shifts", get(handlers::list_history_shifts))
+        .route("/api/history-shifts/{id}/undo", post(handlers::undo_history_shift))
+        .route("/api/machines/{id}/decommission", post(handlers::decommission_machine))
+        .route("/api/machines/{id}/merge", post(handlers::merge_machines))
+        .route("/api/machine-archives", get(handlers::list_machine_archives))
+        .route("/api/machine-archives/{id}/download", get(handlers::download_machine_archive))
+        .route("/api/machines/{id}/documents", get(handlers::list_documents).post(handlers::create_document))
+        .route("/api/documents/{id}", put(handlers::update_document).delete(handlers::delete_document))
+        .route(
+            "/api/documents/{id}/file",
+            get(handlers::download_document_file)
+                .put(handlers::upload_document_file)
+                .layer(DefaultBodyLimit::max(50 * 1024 * 1024)),
+        )
+        .route("/api/machines/{id}/work-orders", get(handlers::list_work_orders).post(handlers::create_work_order))
+        .route("/api/machines/{id}/display-names", get(handlers::list_display_names))
+        .route("/api/machines/{id}/display-names/{locale}", put(handlers::set_display_name).delete(handlers::delete_display_name))
+        .route("/api/machines/{id}/contracts", get(handlers::list_contracts).post(handlers::create_contract))
+        .route("/api/contracts/{id}", put(handlers::update_contract).delete(handlers::delete_contract))
+        .route("/api/notifications", get(handlers::list_notifications))
+        .route("/api/machines/{id}/snooze", post(handlers::snooze_machine).delete(handlers::unsnooze_machine))
+        .route("/api/users/me/snoozes", get(handlers::list_own_snoozes))
+        .route("/api/notifications/{id}/acknowledge", post(handlers::acknowledge_notification))
+        .route("/api/machines/{id}/costs", get(handlers::get_machine_costs).post(handlers::create_cost))
+        .route("/api/work-orders/{id}", get(handlers::get_work_order).put(handlers::update_work_order))
+        .route("/api/work-orders/{id}/checklist", post(handlers::attach_work_order_checklist))
+        .route("/api/work-orders/{id}/steps/{step_id}", put(handlers::update_work_order_step))
+        .route("/api/work-orders/{id}/sign-off", post(handlers::sign_off_work_order))
+        .route("/api/work-orders/{id}/permits", get(handlers::list_work_permits).post(handlers::request_work_permit))
+        .route("/api/permits/{id}/approve", post(handlers::approve_work_permit))
+        .route("/api/permits/{id}/close", post(handlers::close_work_permit))
+        .route("/api/work-orders/{id}/labor", get(handlers::list_labor))
+        .route("/api/work-orders/{id}/labor/start", post(handlers::start_labor))
+        .route("/api/work-orders/{id}/labor/stop", post(handlers::stop_labor))
+        .route("/api/checklist-templates", get(handlers::list_checklist_templates).post(handlers::create_checklist_template))
+        .route("/api/jobs/{id}", get(handlers::get_job))
+        .route("/api/jobs/{id}/artifact", get(handlers::download_job_artifact))
+        .route("/api/jobs/{id}/artifact/link", get(handlers::create_download_link))
+        .route("/api/admin/sandbox", post(handlers::create_sandbox))
+        .route("/api/admin/backup", post(handlers::create_backup))
+        .route("/api/admin/backups", get(handlers::list_backups))
+        .route("/api/admin/predictions/run", post(handlers::run_predictions))
+        .route("/api/admin/read-only", get(handlers::get_read_only).put(handlers::update_read_only))
+        .route("/api/admin/features", get(handlers::list_feature_flags))
+        .route("/api/admin/features/{name}", put(handlers::update_feature_flag))
+        .route("/api/admin/usage", get(handlers::get_api_usage))
+        .route("/api/admin/history/purge", post(handlers::purge_history))
+        .route("/api/admin/impersonate/{user_id}", post(handlers::impersonate_user))
+        .route("/api/admin/impersonations", get(handlers::list_impersonations))
+        .route("/api/distribution-lists", get(handlers::list_distribution_lists).post(handlers::create_distribution_list))
+        .route(
+            "/api/distribution-lists/{id}",
+            get(handlers::get_distribution_list)
+                .put(handlers::update_distribution_list)
+                .delete(handlers::delete_distribution_list),
+        )
+        .route("/api/distribution-lists/{id}/test", post(handlers::send_distribution_list_test))
+        .route("/api/site-settings", get(handlers::get_site_settings).put(handlers::update_site_settings))
+        .route(
+            "/api/site-settings/logo",
+            get(handlers::download_site_logo)
+                .put(handlers::upload_site_logo)
+                .delete(handlers::delete_site_logo),
+        )
+        .route("/api/machines/{id}", put(handlers::update_machine).delete(handlers::archive_machine))
+        .route("/api/machines/{id}/restore", post(handlers::restore_machine))
+        .route("/api/machines/{id}/alarm-rules", get(handlers::list_alarm_rules).post(handlers::create_alarm_rule))
+        .route("/api/machines/{id}/commands", get(handlers::list_machine_commands).post(handlers::send_command))
+        .route("/api/machines/{id}/lockout", put(handlers::lock_out_machine).delete(handlers::release_lockout))
+        .route("/api/machines/{id}/rotate-key", post(handlers::rotate_machine_key))
+        .route("/api/machines/{id}/api-keys", get(handlers::list_machine_api_keys).post(handlers::create_machine_api_key))
+        .route("/api/machine-api-keys/{id}", put(handlers::update_machine_api_key).delete(handlers::revoke_machine_api_key))
+        .route("/api/commands/{id}", delete(handlers::cancel_command))
+        .route("/api/command-types", get(handlers::list_command_types).post(handlers::create_command_type))
+        .route("/api/command-types/{id}", put(handlers::update_command_type).delete(handlers::delete_command_type))
+        .route("/api/alarm-rules/backtest", post(handlers::backtest_alarm_rule).route_layer(expensive.clone()))
+        .route("/api/alarm-rules/{id}", put(handlers::update_alarm_rule))
+        .route("/api/alarms", get(handlers::list_alarms))
+        .route("/api/alarms/{id}/acknowledge", post(handlers::acknowledge_alarm))
+        .route("/api/alarms/{id}/annotation", put(handlers::annotate_alarm))
+        .route("/api/alarms/export", get(handlers::export_alarms).route_layer(expensive.clone()))
+        .route("/api/compliance-limits", get(handlers::list_compliance_limits).post(handlers::create_compliance_limit))
+        .route("/api/compliance-limits/{id}", put(handlers::update_compliance_limit).delete(handlers::delete_compliance_limit))
+        .route("/api/compliance-exceedances", get(handlers::list_compliance_exceedances))
+        .route("/api/failure-thresholds", get(handlers::list_failure_thresholds).post(handlers::create_failure_threshold))
+        .route("/api/failure-thresholds/{id}", delete(handlers::delete_failure_threshold))
+        .route("/api/predictions", get(handlers::list_predictions))
+        .route("/api/advisories", get(handlers::list_advisories))
+        .route("/api/advisories/{id}/acknowledge", post(handlers::acknowledge_advisory))
+        .route(
+            "/api/alarm-work-order-policy",
+            get(handlers::get_alarm_work_order_policy).put(handlers::update_alarm_work_order_policy),
+        )
+        .route("/api/alarm-root-causes", get(handlers::list_alarm_root_causes).post(handlers::create_alarm_root_cause))
+        .route("/api/reports/alarm-root-causes", get(handlers::alarm_root_cause_report))
+        .route("/api/alarm-presentation", get(handlers::list_alarm_presentation))
+        .route("/api/alarm-presentation/{severity}", put(handlers::update_alarm_presentation))
+        .route("/api/units", get(handlers::list_units).post(handlers::create_unit))
+        .route("/api/machines/{id}/metrics", get(handlers::get_machine_metrics))
+        .route("/api/metric-precision", get(handlers::list_metric_precision))
+        .route("/api/metric-precision/{metric}", put(handlers::update_metric_precision))
+        .route("/api/comments", get(handlers::list_recent_comments))
+        .route("/api/comments/{id}/pin", post(handlers::pin_comment).delete(handlers::unpin_comment))
+        .route("/api/comments/{id}/resolve", post(handlers::resolve_comment).delete(handlers::unresolve_comment))
+        .route("/api/comments/export", get(handlers::export_comments).route_layer(expensive.clone()))
+        .route("/api/comment-categories", get(handlers::list_comment_categories).post(handlers::create_comment_category))
+        .route("/api/reports/comments-by-category", get(handlers::comments_by_category_report))
+        .route("/api/reports/labor-hours", get(handlers::labor_hours_report))
+        .route("/api/reports/changeovers", get(handlers::changeover_report))
+        .route("/api/reports/operators", get(handlers::operator_report))
+        .route(
+            "/api/reports/compliance-exceedances",
+            get(handlers::compliance_exceedance_report).route_layer(expensive.clone()),
+        )
+        .route("/api/reports/expired-contracts", get(handlers::expired_contracts_report))
+        .route("/api/reports/history-gaps", get(handlers::history_gaps_report).route_layer(expensive.clone()))
         .route("/api/users", get(handlers::list_users).post(handlers::create_user))
+        .route("/api/users/me/password", post(handlers::change_password))
         .route("/api/users/{id}", put(handlers::update_user))
-        .layer(CorsLayer::permissive())
-        .with_state(db);
+        .route("/api/users/{id}/revoke-tokens", post(handlers::revoke_user_tokens))
+        .route("/api/users/{id}/unlock", post(handlers::unlock_user))
+        .route("/api/users/{id}/deactivate", post(handlers::deactivate_user))
+        .route("/api/users/{id}/reactivate", post(handlers::reactivate_user))
+        .route("/api/users/me/sessions", get(handlers::list_own_sessions))
+        .route("/api/users/me/views/machines", get(handlers::get_machine_list_view).put(handlers::set_machine_list_view))
+        .route("/api/users/{id}/sessions", get(handlers::list_user_sessions))
+        .route("/api/users/{id}/logins", get(handlers::list_user_logins))
+        .route("/api/sessions/{id}", delete(handlers::revoke_session))
+        .route("/api/service-accounts", get(handlers::list_service_accounts).post(handlers::create_service_account))
+        .route(
+            "/api/service-accounts/{id}",
+            put(handlers::update_service_account).delete(handlers::disable_service_account),
+        )
+        .route("/api/service-accounts/{id}/rotate-key", post(handlers::rotate_service_account_key))
+        .route("/api/users/{id}/machines", get(handlers::list_machine_access))
+        .route("/api/users/{id}/certifications", get(handlers::list_user_certifications))
+        .route("/api/users/{id}/certifications/{skill}", put(handlers::set_certification).delete(handlers::delete_certification))
+        .route("/api/certifications", get(handlers::list_certifications))
+        .route("/api/users/{id}/machines/{machine_id}", put(handlers::grant_machine_access).delete(handlers::revoke_machine_access));
+
+    // Fault injection for testing clients, only when explicitly enabled
+    if state.config.dev_chaos {
+        tracing::warn!("Chaos endpoints enabled under /api/dev");
+        operator = operator
+            .route("/api/dev/chaos", get(handlers::get_chaos).delete(handlers::reset_chaos))
+            .route("/api/dev/chaos/latency", put(handlers::set_chaos_latency))
+            .route("/api/dev/chaos/db-failures", put(handlers::set_chaos_db_failures))
+            .route("/api/dev/chaos/tasks/{name}/kill", post(handlers::kill_background_task));
+    }
+
+    let mut app = operator
+        .layer(middleware::from_fn_with_state(state.db.clone(), service_accounts::authenticate))
+        .layer(middleware::from_fn_with_state(state.db.clone(), display_tokens::authenticate))
+        .layer(cors::operator_policy(&state.config.cors_origins))
+        .merge(public)
+        .merge(ingest);
+    if state.config.dev_chaos {
+        app = app.layer(middleware::from_fn_with_state(state.chaos.clone(), chaos::inject_latency));
+    }
+
+    // Every caller gets a request budget, counted before the handler's database work: per
+    // principal once it is authenticated, per client IP otherwise
+    let token_rate = rate_limit::TokenRateLimit::new(
+        state.config.user_rate_per_minute,
+        state.config.machine_rate_per_minute,
+        state.config.anonymous_rate_per_minute,
+        &state.config.trusted_proxies,
+        state.db.clone(),
+    );
+    let rate_prune = token_rate.spawn_prune();
+    state.chaos.register_task("rate_limit_prune", rate_prune.abort_handle());
+
+    // Response headers hardening browsers' handling of the API, and a size and type check on request bodies
+    let security_headers = hardening::SecurityHeaders::new(state.config.hsts_max_age);
+    let max_body_bytes = state.config.max_body_bytes;
+
+    let (db, usage_recorder) = (state.db.clone(), state.usage.clone());
+    let app = app
+        .layer(middleware::from_fn_with_state(max_body_bytes, hardening::check_request_body))
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+        .layer(middleware::from_fn_with_state(state.read_only.clone(), read_only::guard_writes))
+        .layer(middleware::from_fn_with_state(token_rate, rate_limit::limit_token_rate))
+        .layer(middleware::from_fn_with_state(state.usage.clone(), usage::record_usage))
+        .layer(CatchPanicLayer::custom(incidents::panic_response))
+        .layer(middleware::from_fn(incidents::tag_server_errors))
+        .layer(middleware::from_fn_with_state(security_headers, hardening::set_security_headers))
+        .layer(TraceLayer::new_for_http().make_span_with(telemetry::request_span))
+        .layer(SentryHttpLayer::new())
+        .layer(NewSentryLayer::new_from_top())
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state);
 
     // Start server
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
-    println!("Server running on http://{}", addr);
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    tracing::info!("Server running on http://{}", addr);
     
     let listener = match tokio::net::TcpListener::bind(addr).await {
         Ok(l) => l,
@@ -57,9 +467,19 @@ async fn main() -> anyhow::Result<()> {
     };
     
     // Handle graceful shutdown
-    let server = axum::serve(listener, app);
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>());
     
-    if let Err(e) = server.with_graceful_shutdown(shutdown_signal()).await {
+    let served = server.with_graceful_shutdown(shutdown_signal()).await;
+
+    // Keep the usage counted since the last periodic write
+    if let Err(e) = usage_recorder.flush(&db).await {
+        eprintln!("Failed to write API usage: {}", e);
+    }
+
+    // Flush buffered spans and error reports before exiting
+    telemetry.shutdown();
+
+    if let Err(e) = served {
         eprintln!("Server error: {}", e);
         return Err(e.into());
     }
@@ -90,5 +510,5 @@ async fn shutdown_signal() {
         _ = terminate => {},
     }
 
-    println!("\nShutting down gracefully...");
+    tracing::info!("Shutting down gracefully...");
 }
\ No newline at end of file
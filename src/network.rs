@@ -0,0 +1,86 @@
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+// A network such as "10.20.0.0/16", or a single address
+pub fn parse_cidr(value: &str) -> Option<IpNet> {
+    let value = value.trim();
+    value
+        .parse::<IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+        .ok()
+        .map(|net| net.trunc())
+}
+
+// The address a request came from. Behind a trusted proxy that is the nearest address in
+// X-Forwarded-For not itself a trusted proxy; otherwise the connecting peer, whatever the
+// header claims, so clients cannot pick their own address.
+pub fn client_ip(headers: &HeaderMap, peer: IpAddr, trusted_proxies: &[IpNet]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+    let mut client = peer.to_canonical();
+    if !is_trusted(&client) {
+        return client;
+    }
+
+    let forwarded: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    for hop in forwarded.into_iter().rev() {
+        let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = hop.to_canonical();
+        if !is_trusted(&client) {
+            break;
+        }
+    }
+    client
+}
+
+// Whether an address lies in one of the comma-separated networks
+pub fn allows(cidrs: &str, ip: IpAddr) -> bool {
+    cidrs.split(',').filter_map(parse_cidr).any(|net| net.contains(&ip.to_canonical()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_networks_and_single_addresses() {
+        assert_eq!(parse_cidr(" 10.20.3.4/16 "), Some("10.20.0.0/16".parse().unwrap()));
+        assert_eq!(parse_cidr("192.0.2.7"), Some("192.0.2.7/32".parse().unwrap()));
+        assert_eq!(parse_cidr("2001:db8::/32"), Some("2001:db8::/32".parse().unwrap()));
+        assert_eq!(parse_cidr("10.0.0.0/33"), None);
+        assert_eq!(parse_cidr("plant-network"), None);
+    }
+
+    #[test]
+    fn allows_addresses_in_any_listed_network() {
+        let cidrs = "10.20.0.0/16, 192.0.2.7";
+        assert!(allows(cidrs, ip("10.20.99.1")));
+        assert!(allows(cidrs, ip("192.0.2.7")));
+        // IPv4 peers on a dual-stack socket arrive mapped into IPv6
+        assert!(allows(cidrs, ip("::ffff:10.20.0.1")));
+        assert!(!allows(cidrs, ip("10.21.0.1")));
+        assert!(!allows("", ip("10.20.0.1")));
+    }
+
+    #[test]
+    fn forwarded_addresses_count_only_behind_a_trusted_proxy() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "203.0.113.9, 198.51.100.7, 10.0.0.3".parse().unwrap());
+        let trusted = [parse_cidr("10.0.0.0/8").unwrap()];
+        assert_eq!(client_ip(&headers, ip("192.0.2.1"), &trusted), ip("192.0.2.1"));
+        assert_eq!(client_ip(&headers, ip("192.0.2.1"), &[]), ip("192.0.2.1"));
+        // The nearest hop that is not a trusted proxy; anything beyond it may be made up
+        assert_eq!(client_ip(&headers, ip("10.0.0.2"), &trusted), ip("198.51.100.7"));
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::database::{DbPool, current_timestamp};
+
+// Records a notification for operators to pick up from the notifications feed
+pub async fn notify(pool: &DbPool, kind: &str, machine_id: Option<i64>, message: &str) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO notifications (kind, machine_id, message, created_at) VALUES ($1, $2, $3, $4)")
+        .bind(kind)
+        .bind(machine_id)
+        .bind(message)
+        .bind(current_timestamp())
+        .execute(pool)
+        .await?;
+    tracing::info!("Notification ({}): {}", kind, message);
+    Ok(())
+}
+
+// Periodically raises a notification for each warranty or service contract nearing expiry
+pub fn spawn_contract_expiry_check(pool: DbPool, notice: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = notify_expiring_contracts(&pool, notice).await {
+                tracing::error!("Contract expiry check failed: {}", e);
+            }
+        }
+    })
+}
+
+async fn notify_expiring_contracts(pool: &DbPool, notice: Duration) -> sqlx::Result<()> {
+    let now = current_timestamp();
+    let expiring: Vec<(i64, i64, String, String, i64, String)> = sqlx::query_as(
+        "SELECT c.id, c.machine_id, c.kind, c.vendor, c.expires_at, m.name \
+         FROM machine_contracts c JOIN machines m ON m.id = c.machine_id \
+         WHERE c.notified_at IS NULL AND c.expires_at <= $1"
+    )
+    .bind(now + notice.as_secs() as i64)
+    .fetch_all(pool)
+    .await?;
+
+    for (id, machine_id, kind, vendor, expires_at, machine_name) in expiring {
+        let kind = kind.replace('_', " ");
+        let message = if expires_at <= now {
+            format!("The {} from {} for machine {} has expired", kind, vendor, machine_name)
+        } else {
+            format!(
+                "The {} from {} for machine {} expires in {} days",
+                kind,
+                vendor,
+                machine_name,
+                (expires_at - now + 86_399) / 86_400
+            )
+        };
+        notify(pool, "contract_expiry", Some(machine_id), &message).await?;
+        sqlx::query("UPDATE machine_contracts SET notified_at = $1 WHERE id = $2")
+            .bind(now)
+            .bind(id)
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400;
+
+    #[tokio::test]
+    async fn announces_each_expiring_contract_once() {
+        let pool = crate::database::test_database().await;
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let now = current_timestamp();
+        for (kind, vendor, expires_at) in [
+            ("warranty", "Krones", now - DAY),
+            ("service_contract", "Sidel", now + 10 * DAY),
+            ("service_contract", "Festo", now + 90 * DAY),
+        ] {
+            sqlx::query("INSERT INTO machine_contracts (machine_id, kind, vendor, expires_at) VALUES (1, $1, $2, $3)")
+                .bind(kind)
+                .bind(vendor)
+                .bind(expires_at)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let notice = Duration::from_secs(30 * DAY as u64);
+        notify_expiring_contracts(&pool, notice).await.unwrap();
+        notify_expiring_contracts(&pool, notice).await.unwrap();
+        let messages: Vec<(String, Option<i64>, String)> =
+            sqlx::query_as("SELECT kind, machine_id, message FROM notifications ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        let expiry = |message: &str| ("contract_expiry".to_string(), Some(1), message.to_string());
+        assert_eq!(
+            messages,
+            [
+                expiry("The warranty from Krones for machine Line 1 has expired"),
+                expiry("The service contract from Sidel for machine Line 1 expires in 10 days"),
+            ]
+        );
+    }
+}
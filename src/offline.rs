@@ -0,0 +1,84 @@
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{
+    database::{DbPool, current_timestamp},
+    state::MachineChanges,
+};
+
+// Periodically marks machines offline once they have missed `missed_intervals` reports.
+// Each machine is judged against its own report_interval, falling back to
+// `default_interval` for machines that don't declare one, so a meter reporting every
+// 15 minutes isn't flagged while a press reporting every second is caught within seconds.
+pub fn spawn_offline_check(
+    pool: DbPool,
+    changes: MachineChanges,
+    default_interval: Duration,
+    missed_intervals: u32,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            match mark_offline(&pool, default_interval, missed_intervals).await {
+                Ok(0) => {},
+                Ok(count) => {
+                    tracing::info!("Marked {} machine(s) offline", count);
+                    changes.notify(current_timestamp());
+                },
+                Err(e) => tracing::error!("Offline check failed: {}", e),
+            }
+        }
+    })
+}
+
+async fn mark_offline(pool: &DbPool, default_interval: Duration, missed_intervals: u32) -> sqlx::Result<u64> {
+    let now = current_timestamp();
+    let result = sqlx::query(
+        "UPDATE machines SET is_online = FALSE, updated_at = $1 \
+         WHERE is_online = TRUE AND archived_at IS NULL AND last_update < $2 - COALESCE(report_interval, $3) * $4"
+    )
+    .bind(now)
+    .bind(now)
+    .bind(default_interval.as_secs() as i64)
+    .bind(i64::from(missed_intervals))
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn judges_each_machine_by_its_own_report_interval() {
+        let pool = crate::database::test_database().await;
+        let now = current_timestamp();
+        // (report_interval, seconds since the last report, archived)
+        let machines = [(None, 10, false), (None, 20, false), (Some(900), 1200, false), (Some(1), 5, false), (None, 60, true)];
+        for (id, (report_interval, silent_for, archived)) in machines.into_iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO machines (name, code, api_key, is_online, report_interval, last_update, archived_at) \
+                 VALUES ($1, $2, $3, TRUE, $4, $5, $6)"
+            )
+            .bind(format!("Line {}", id))
+            .bind(format!("L{}", id))
+            .bind(format!("key{}", id))
+            .bind(report_interval)
+            .bind(now - silent_for)
+            .bind(archived.then_some(now))
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(mark_offline(&pool, Duration::from_secs(5), 3).await.unwrap(), 2);
+        let offline: Vec<String> = sqlx::query_scalar("SELECT code FROM machines WHERE is_online = FALSE ORDER BY code")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(offline, ["L1", "L3"]);
+        assert_eq!(mark_offline(&pool, Duration::from_secs(5), 3).await.unwrap(), 0);
+    }
+}
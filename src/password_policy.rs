@@ -0,0 +1,206 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::{collections::HashSet, path::Path, str::FromStr, time::Duration};
+
+use crate::models::{PasswordPolicyErrorResponse, PasswordPolicyResponse, PasswordRuleViolation};
+
+// Passwords refused regardless of configuration; SCADA_PASSWORD_BANNED_FILE adds to them
+const BANNED_PASSWORDS: &[&str] = &[
+    "123456", "12345678", "123456789", "1234567890", "password", "password1", "password123",
+    "qwerty", "qwerty123", "letmein", "welcome", "welcome1", "admin", "admin123", "administrator",
+    "changeme", "iloveyou", "monkey", "dragon", "football", "abc123", "111111", "000000", "scada",
+    "scada123", "operator",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CharacterClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+}
+
+impl CharacterClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lowercase => "lowercase",
+            Self::Uppercase => "uppercase",
+            Self::Digit => "digit",
+            Self::Symbol => "symbol",
+        }
+    }
+
+    fn matches(self, c: char) -> bool {
+        match self {
+            Self::Lowercase => c.is_lowercase(),
+            Self::Uppercase => c.is_uppercase(),
+            Self::Digit => c.is_numeric(),
+            Self::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
+        }
+    }
+}
+
+impl FromStr for CharacterClass {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "lowercase" => Ok(Self::Lowercase),
+            "uppercase" => Ok(Self::Uppercase),
+            "digit" => Ok(Self::Digit),
+            "symbol" => Ok(Self::Symbol),
+            _ => Err(format!("unknown character class '{}', expected lowercase, uppercase, digit or symbol", value)),
+        }
+    }
+}
+
+// Rules every new password must meet, wherever it is set
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub required_classes: Vec<CharacterClass>,
+    // Lowercased, so matching ignores case
+    pub banned: HashSet<String>,
+    // How long a password may be used before it has to be changed at login; None for no limit
+    pub max_age: Option<Duration>,
+}
+
+impl PasswordPolicy {
+    pub fn new(
+        min_length: usize,
+        required_classes: &str,
+        banned_file: Option<&Path>,
+        max_age_days: u64,
+    ) -> anyhow::Result<Self> {
+        let required_classes = required_classes
+            .split(',')
+            .map(str::trim)
+            .filter(|class| !class.is_empty())
+            .map(|class| class.parse().map_err(|e| anyhow::anyhow!("Invalid SCADA_PASSWORD_REQUIRED_CLASSES: {}", e)))
+            .collect::<anyhow::Result<Vec<CharacterClass>>>()?;
+
+        let mut banned: HashSet<String> = BANNED_PASSWORDS.iter().map(|password| password.to_string()).collect();
+        if let Some(path) = banned_file {
+            let list = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read SCADA_PASSWORD_BANNED_FILE {}: {}", path.display(), e))?;
+            banned.extend(list.lines().map(str::trim).filter(|line| !line.is_empty()).map(str::to_lowercase));
+        }
+
+        Ok(Self {
+            // An empty password is never acceptable
+            min_length: min_length.max(1),
+            required_classes,
+            banned,
+            max_age: (max_age_days > 0).then(|| Duration::from_secs(max_age_days * 24 * 60 * 60)),
+        })
+    }
+
+    // Every rule the password breaks, if it breaks any
+    pub fn check(&self, password: &str, username: &str) -> Result<(), Vec<PasswordRuleViolation>> {
+        let mut violations = Vec::new();
+        let mut violation = |rule: &str, message: String| {
+            violations.push(PasswordRuleViolation { rule: rule.to_string(), message });
+        };
+
+        if password.chars().count() < self.min_length {
+            violation("min_length", format!("Must be at least {} characters long", self.min_length));
+        }
+        for class in &self.required_classes {
+            if !password.chars().any(|c| class.matches(c)) {
+                violation("character_class", format!("Must contain at least one {} character", class.as_str()));
+            }
+        }
+        let lowercase = password.to_lowercase();
+        if self.banned.contains(&lowercase) {
+            violation("banned", "Is too common and easily guessed".to_string());
+        }
+        if !username.is_empty() && lowercase.contains(&username.to_lowercase()) {
+            violation("contains_username", "Must not contain the username".to_string());
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    // Whether a password set at `changed_at` has to be replaced before the user can sign in
+    pub fn is_expired(&self, changed_at: Option<i64>, now: i64) -> bool {
+        match (self.max_age, changed_at) {
+            (Some(max_age), Some(changed_at)) => now - changed_at > max_age.as_secs() as i64,
+            _ => false,
+        }
+    }
+
+    pub fn describe(&self) -> PasswordPolicyResponse {
+        PasswordPolicyResponse {
+            min_length: self.min_length,
+            required_classes: self.required_classes.iter().map(|class| class.as_str().to_string()).collect(),
+            max_age_days: self.max_age.map(|max_age| max_age.as_secs() / (24 * 60 * 60)),
+        }
+    }
+}
+
+// 400 Bad Request listing every rule a new password breaks, so clients can show them all at once
+pub fn rejection(violations: Vec<PasswordRuleViolation>) -> Response {
+    (StatusCode::BAD_REQUEST, Json(PasswordPolicyErrorResponse {
+        error: "Password does not meet the password policy".to_string(),
+        violations,
+    })).into_response()
+}
+
+// 403 Forbidden for a login with an expired password and no new one to replace it
+pub fn expired_rejection() -> Response {
+    (StatusCode::FORBIDDEN, Json(PasswordPolicyErrorResponse {
+        error: "Password expired".to_string(),
+        violations: vec![PasswordRuleViolation {
+            rule: "expired".to_string(),
+            message: "Log in again with new_password set to choose a new password".to_string(),
+        }],
+    })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy::new(10, "lowercase,uppercase,digit", None, 90).unwrap()
+    }
+
+    fn rules(result: Result<(), Vec<PasswordRuleViolation>>) -> Vec<String> {
+        result.err().unwrap_or_default().into_iter().map(|violation| violation.rule).collect()
+    }
+
+    #[test]
+    fn accepts_a_password_meeting_every_rule() {
+        assert!(policy().check("Conveyor-Belt7", "alice").is_ok());
+    }
+
+    #[test]
+    fn reports_every_broken_rule() {
+        assert_eq!(rules(policy().check("alice", "alice")), [
+            "min_length",
+            "character_class",
+            "character_class",
+            "contains_username",
+        ]);
+    }
+
+    #[test]
+    fn refuses_banned_passwords_in_any_case() {
+        let policy = PasswordPolicy::new(0, "", None, 0).unwrap();
+        assert_eq!(rules(policy.check("PassWord123", "bob")), ["banned"]);
+    }
+
+    #[test]
+    fn expires_passwords_older_than_the_maximum_age() {
+        let day = 24 * 60 * 60;
+        assert!(policy().is_expired(Some(0), 91 * day));
+        assert!(!policy().is_expired(Some(0), 89 * day));
+        assert!(!PasswordPolicy::new(10, "", None, 0).unwrap().is_expired(Some(0), 1000 * day));
+    }
+}
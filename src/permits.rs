@@ -0,0 +1,96 @@
+use sqlx::Row;
+
+use crate::{database::{DbPool, DbRow}, models::WorkPermit};
+
+pub const PERMIT_TYPES: &[&str] = &["general", "hot_work", "electrical", "mechanical", "confined_space", "working_at_height"];
+
+pub fn permit_from_row(row: &DbRow) -> WorkPermit {
+    let isolation_points: String = row.get("isolation_points");
+    WorkPermit {
+        id: row.get("id"),
+        work_order_id: row.get("work_order_id"),
+        machine_id: row.get("machine_id"),
+        permit_type: row.get("permit_type"),
+        description: row.get("description"),
+        isolation_points: serde_json::from_str(&isolation_points).unwrap_or_default(),
+        valid_from: row.get("valid_from"),
+        valid_until: row.get("valid_until"),
+        status: row.get("status"),
+        requested_by: row.get("requested_by"),
+        requested_at: row.get("requested_at"),
+        approved_by: row.get("approved_by"),
+        approved_at: row.get("approved_at"),
+        approval_signature: row.get("approval_signature"),
+        closed_by: row.get("closed_by"),
+        closed_at: row.get("closed_at"),
+    }
+}
+
+pub async fn fetch(pool: &DbPool, permit_id: i64) -> sqlx::Result<Option<WorkPermit>> {
+    let row = sqlx::query("SELECT * FROM work_permits WHERE id = $1")
+        .bind(permit_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.as_ref().map(permit_from_row))
+}
+
+// Permits a machine is being worked on under at `now`: approved, not yet handed back and
+// inside their validity window
+pub async fn active_for_machine(pool: &DbPool, machine_id: i64, now: i64) -> sqlx::Result<Vec<WorkPermit>> {
+    let rows = sqlx::query(
+        "SELECT * FROM work_permits WHERE machine_id = $1 AND status = 'approved' AND valid_from <= $2 AND valid_until > $3 \
+         ORDER BY valid_from, id"
+    )
+    .bind(machine_id)
+    .bind(now)
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.iter().map(permit_from_row).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn active_permits_are_approved_and_within_their_window() {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key')",
+            "INSERT INTO work_orders (machine_id, title, created_by) VALUES (1, 'Replace motor', 'boss')",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        // (status, valid from, valid until, isolation points)
+        for (status, valid_from, valid_until, isolation_points) in [
+            ("approved", 100, 200, r#"["Main breaker", "Air supply"]"#),
+            ("requested", 100, 200, "[]"),
+            ("closed", 100, 200, "[]"),
+            ("approved", 50, 150, "[]"),
+            ("approved", 160, 300, "[]"),
+        ] {
+            sqlx::query(
+                "INSERT INTO work_permits \
+                 (work_order_id, machine_id, permit_type, isolation_points, valid_from, valid_until, status, requested_by, requested_at) \
+                 VALUES (1, 1, 'electrical', $1, $2, $3, $4, 'bob', 0)"
+            )
+            .bind(isolation_points)
+            .bind(valid_from)
+            .bind(valid_until)
+            .bind(status)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let active = active_for_machine(&pool, 1, 150).await.unwrap();
+        assert_eq!(active.iter().map(|permit| permit.id).collect::<Vec<_>>(), [1]);
+        assert_eq!(active[0].isolation_points, ["Main breaker", "Air supply"]);
+        let active = active_for_machine(&pool, 1, 170).await.unwrap();
+        assert_eq!(active.iter().map(|permit| permit.id).collect::<Vec<_>>(), [1, 5]);
+        assert!(active_for_machine(&pool, 1, 300).await.unwrap().is_empty());
+        assert_eq!(fetch(&pool, 2).await.unwrap().unwrap().status, "requested");
+        assert!(fetch(&pool, 6).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use crate::database::DbPool;
+
+// Decimal places each metric is rounded to in responses and exports; metrics without a
+// setting are passed through unrounded
+#[derive(Debug, Default)]
+pub struct Precision(HashMap<String, i64>);
+
+impl Precision {
+    pub async fn load(pool: &DbPool) -> sqlx::Result<Self> {
+        let rows: Vec<(String, i64)> = sqlx::query_as("SELECT metric, decimals FROM metric_precision")
+            .fetch_all(pool)
+            .await?;
+        Ok(Self(rows.into_iter().collect()))
+    }
+
+    pub fn round(&self, metric: &str, value: f64) -> f64 {
+        match self.0.get(metric) {
+            Some(&decimals) => {
+                let factor = 10f64.powi(decimals as i32);
+                (value * factor).round() / factor
+            },
+            None => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rounds_configured_metrics_only() {
+        let pool = crate::database::test_database().await;
+        sqlx::query("INSERT INTO metric_precision (metric, decimals) VALUES ('pressure', 2)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let precision = Precision::load(&pool).await.unwrap();
+        assert_eq!(precision.round("speed", 42.46), 42.5);
+        assert_eq!(precision.round("pressure", 2.3456), 2.35);
+        assert_eq!(precision.round("temperature", 901.2345), 901.2345);
+        assert_eq!(Precision::default().round("speed", 42.46), 42.46);
+    }
+}
@@ -0,0 +1,460 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::Config,
+    database::{self, DbPool, current_timestamp},
+    jobs::JobContext,
+    notifications,
+};
+
+const HOUR: i64 = 60 * 60;
+
+// Metric readings a model is given, ending now
+const LOOKBACK: i64 = 24 * HOUR;
+
+// How far ahead the trend model looks: a metric crossing its threshold now is a risk of 1,
+// one crossing it this far out or later a risk of 0
+const HORIZON: i64 = 7 * 24 * HOUR;
+
+// Readings needed before the trend model fits a line through them
+const MIN_TREND_READINGS: usize = 3;
+
+// Which model predicts failures (SCADA_PREDICTION_MODEL)
+#[derive(Debug, Clone)]
+pub enum PredictionModel {
+    // Extrapolates each metric's linear trend towards its failure thresholds
+    Trend,
+    // Posts each machine's signals to an external model at this URL (SCADA_PREDICTION_URL)
+    Http(String),
+}
+
+impl PredictionModel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Trend => "trend",
+            Self::Http(_) => "http",
+        }
+    }
+}
+
+// What a model is given about one machine: its readings of every metric over the lookback
+// window, oldest first, with the failure threshold that applies to each
+#[derive(Debug, Serialize)]
+pub struct MachineSignals {
+    pub machine_id: i64,
+    pub name: String,
+    pub machine_type: Option<String>,
+    pub from: i64,
+    pub to: i64,
+    pub metrics: Vec<MetricSeries>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetricSeries {
+    pub metric: String,
+    pub unit: String,
+    // Failure threshold; a machine's own threshold overrides one for every machine
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    // (timestamp, value) pairs
+    pub readings: Vec<(i64, f64)>,
+}
+
+// A model's answer; external models respond with this as JSON
+#[derive(Debug, Deserialize)]
+pub struct Prediction {
+    // Failure risk from 0 to 1
+    pub risk: f64,
+    pub metric: Option<String>,
+    pub reason: String,
+    pub predicted_failure_at: Option<i64>,
+}
+
+// Runs the configured model over every machine and keeps the advisories in step
+#[derive(Clone)]
+pub struct Predictor {
+    model: PredictionModel,
+    advisory_risk: f64,
+    client: reqwest::Client,
+}
+
+impl Predictor {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            model: config.prediction_model.clone(),
+            advisory_risk: config.advisory_risk,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    async fn predict(&self, signals: &MachineSignals) -> anyhow::Result<Prediction> {
+        let prediction = match &self.model {
+            PredictionModel::Trend => predict_trend(signals, current_timestamp()),
+            PredictionModel::Http(url) => {
+                self.client.post(url).json(signals).send().await?.error_for_status()?.json::<Prediction>().await?
+            },
+        };
+        if !(0.0..=1.0).contains(&prediction.risk) {
+            anyhow::bail!("Model returned a risk of {}, expected 0 to 1", prediction.risk);
+        }
+        Ok(prediction)
+    }
+}
+
+// Runs the predictions every `interval`; None while the interval is zero
+pub fn spawn_predictions(pool: DbPool, predictor: Predictor, interval: Duration) -> Option<JoinHandle<()>> {
+    if interval.is_zero() {
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_predictions(&pool, &predictor, None).await {
+                tracing::error!("Failure prediction failed: {}", e);
+            }
+        }
+    }))
+}
+
+// Predicts the failure risk of every machine that is not archived and reported metrics within
+// the lookback window, raising an advisory for each risk at or above the advisory level and
+// clearing the advisories of machines that dropped below it. A machine the model fails on
+// keeps its previous prediction. Returns the counts of machines predicted and advisories
+// raised and cleared.
+pub async fn run_predictions(pool: &DbPool, predictor: &Predictor, job: Option<&JobContext>) -> anyhow::Result<Value> {
+    let to = current_timestamp();
+    let from = to - LOOKBACK;
+    let machines: Vec<(i64, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, name, machine_type FROM machines WHERE archived_at IS NULL \
+         AND EXISTS (SELECT 1 FROM metric_readings r WHERE r.machine_id = machines.id AND r.timestamp >= $1) ORDER BY id"
+    )
+    .bind(from)
+    .fetch_all(pool)
+    .await?;
+
+    let total = machines.len();
+    let (mut predicted, mut failed, mut raised, mut cleared) = (0, 0, 0, 0);
+    for (done, (machine_id, name, machine_type)) in machines.into_iter().enumerate() {
+        let signals = MachineSignals {
+            metrics: load_metrics(pool, machine_id, from, to).await?,
+            machine_id,
+            name,
+            machine_type,
+            from,
+            to,
+        };
+        match predictor.predict(&signals).await {
+            Ok(prediction) => {
+                predicted += 1;
+                match record(pool, predictor, &signals, &prediction, to).await? {
+                    Some(true) => raised += 1,
+                    Some(false) => cleared += 1,
+                    None => {},
+                }
+            },
+            Err(e) => {
+                failed += 1;
+                tracing::warn!("Failure prediction for machine ID {} failed: {}", machine_id, e);
+            },
+        }
+        if let Some(job) = job {
+            job.set_progress((done + 1) as f64 / total as f64).await;
+        }
+    }
+    if raised + cleared > 0 {
+        tracing::info!("Failure predictions raised {} and cleared {} advisories", raised, cleared);
+    }
+    Ok(json!({
+        "model": predictor.model.name(),
+        "machines": predicted,
+        "failed": failed,
+        "advisories_raised": raised,
+        "advisories_cleared": cleared,
+    }))
+}
+
+async fn load_metrics(pool: &DbPool, machine_id: i64, from: i64, to: i64) -> sqlx::Result<Vec<MetricSeries>> {
+    // Flagged readings are in a unit that could not be converted, so they are left out
+    let readings: Vec<(String, String, i64, f64)> = sqlx::query_as(
+        "SELECT metric, unit, timestamp, value FROM metric_readings \
+         WHERE machine_id = $1 AND flagged = FALSE AND timestamp >= $2 AND timestamp <= $3 ORDER BY metric, timestamp"
+    )
+    .bind(machine_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+    let thresholds: Vec<(String, Option<f64>, Option<f64>)> = sqlx::query_as(
+        "SELECT metric, min_value, max_value FROM failure_thresholds \
+         WHERE machine_id = $1 OR machine_id IS NULL ORDER BY machine_id IS NULL"
+    )
+    .bind(machine_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut metrics: Vec<MetricSeries> = Vec::new();
+    for (metric, unit, timestamp, value) in readings {
+        match metrics.last_mut() {
+            Some(series) if series.metric == metric => series.readings.push((timestamp, value)),
+            _ => {
+                let (min_value, max_value) = thresholds
+                    .iter()
+                    .find(|(threshold_metric, ..)| *threshold_metric == metric)
+                    .map(|(_, min, max)| (*min, *max))
+                    .unwrap_or_default();
+                metrics.push(MetricSeries { metric, unit, min_value, max_value, readings: vec![(timestamp, value)] });
+            },
+        }
+    }
+    Ok(metrics)
+}
+
+// The trend model: each metric with a threshold gets a least-squares line through its
+// readings, and the risk is how soon that line crosses the threshold within the horizon.
+// The machine's risk is that of its most urgent metric.
+pub fn predict_trend(signals: &MachineSignals, now: i64) -> Prediction {
+    let mut worst: Option<(f64, &MetricSeries, i64, f64)> = None;
+    for series in &signals.metrics {
+        let Some(&(last_at, last_value)) = series.readings.last() else {
+            continue;
+        };
+        for (threshold, rising) in [(series.max_value, true), (series.min_value, false)] {
+            let Some(threshold) = threshold else {
+                continue;
+            };
+            let beyond = if rising { last_value >= threshold } else { last_value <= threshold };
+            let crossing_at = if beyond {
+                Some(last_at)
+            } else {
+                trend_crossing(&series.readings, threshold, rising)
+            };
+            let Some(crossing_at) = crossing_at else {
+                continue;
+            };
+            let risk = (1.0 - (crossing_at - now).max(0) as f64 / HORIZON as f64).clamp(0.0, 1.0);
+            if risk > 0.0 && worst.is_none_or(|(worst_risk, ..)| risk > worst_risk) {
+                worst = Some((risk, series, crossing_at, threshold));
+            }
+        }
+    }
+
+    match worst {
+        Some((risk, series, crossing_at, threshold)) => Prediction {
+            risk: (risk * 100.0).round() / 100.0,
+            metric: Some(series.metric.clone()),
+            reason: if crossing_at <= now {
+                format!("{} has reached its failure threshold of {} {}", series.metric, threshold, series.unit)
+            } else {
+                format!(
+                    "{} is trending towards its failure threshold of {} {}, reached in about {} hours",
+                    series.metric,
+                    threshold,
+                    series.unit,
+                    (crossing_at - now + HOUR - 1) / HOUR
+                )
+            },
+            predicted_failure_at: Some(crossing_at.max(now)),
+        },
+        None => Prediction {
+            risk: 0.0,
+            metric: None,
+            reason: "No metric is trending towards a failure threshold".to_string(),
+            predicted_failure_at: None,
+        },
+    }
+}
+
+// When the least-squares line through the readings reaches `threshold`, if it is heading there
+fn trend_crossing(readings: &[(i64, f64)], threshold: f64, rising: bool) -> Option<i64> {
+    if readings.len() < MIN_TREND_READINGS {
+        return None;
+    }
+    // Relative to the first reading, so large timestamps don't cost precision
+    let origin = readings[0].0;
+    let count = readings.len() as f64;
+    let mean_t = readings.iter().map(|(t, _)| (t - origin) as f64).sum::<f64>() / count;
+    let mean_v = readings.iter().map(|(_, v)| v).sum::<f64>() / count;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (t, v) in readings {
+        let dt = (t - origin) as f64 - mean_t;
+        covariance += dt * (v - mean_v);
+        variance += dt * dt;
+    }
+    if variance == 0.0 {
+        return None;
+    }
+    let slope = covariance / variance;
+    if (rising && slope <= 0.0) || (!rising && slope >= 0.0) {
+        return None;
+    }
+    let seconds = mean_t + (threshold - mean_v) / slope;
+    seconds.is_finite().then(|| origin + seconds.min(i64::MAX as f64 / 2.0) as i64)
+}
+
+// Stores the machine's latest prediction and opens, updates or clears its advisory. Returns
+// Some(true) when an advisory was raised and Some(false) when one was cleared.
+async fn record(
+    pool: &DbPool,
+    predictor: &Predictor,
+    signals: &MachineSignals,
+    prediction: &Prediction,
+    now: i64,
+) -> sqlx::Result<Option<bool>> {
+    let model = predictor.model.name();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO failure_predictions \
+         (machine_id, model, risk, metric, reason, predicted_failure_at, computed_at) VALUES ($1, $2, $3, $4, $5, $6, $7) \
+         ON CONFLICT (machine_id) DO UPDATE SET model = excluded.model, risk = excluded.risk, metric = excluded.metric, \
+         reason = excluded.reason, predicted_failure_at = excluded.predicted_failure_at, computed_at = excluded.computed_at"
+    )
+    .bind(signals.machine_id)
+    .bind(model)
+    .bind(prediction.risk)
+    .bind(&prediction.metric)
+    .bind(&prediction.reason)
+    .bind(prediction.predicted_failure_at)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    let open: Option<i64> =
+        sqlx::query_scalar("SELECT id FROM maintenance_advisories WHERE machine_id = $1 AND cleared_at IS NULL")
+            .bind(signals.machine_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let change = match (open, prediction.risk >= predictor.advisory_risk) {
+        (Some(advisory_id), true) => {
+            sqlx::query(&format!(
+                "UPDATE maintenance_advisories SET model = $1, risk = $2, peak_risk = {}, metric = $4, \
+                 reason = $5, predicted_failure_at = $6, updated_at = $7 WHERE id = $8",
+                database::greatest("peak_risk, $3")
+            ))
+            .bind(model)
+            .bind(prediction.risk)
+            .bind(prediction.risk)
+            .bind(&prediction.metric)
+            .bind(&prediction.reason)
+            .bind(prediction.predicted_failure_at)
+            .bind(now)
+            .bind(advisory_id)
+            .execute(&mut *tx)
+            .await?;
+            None
+        },
+        (None, true) => {
+            sqlx::query(
+                "INSERT INTO maintenance_advisories \
+                 (machine_id, model, risk, peak_risk, metric, reason, predicted_failure_at, raised_at, updated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
+            )
+            .bind(signals.machine_id)
+            .bind(model)
+            .bind(prediction.risk)
+            .bind(prediction.risk)
+            .bind(&prediction.metric)
+            .bind(&prediction.reason)
+            .bind(prediction.predicted_failure_at)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+            Some(true)
+        },
+        (Some(advisory_id), false) => {
+            sqlx::query("UPDATE maintenance_advisories SET risk = $1, updated_at = $2, cleared_at = $3 WHERE id = $4")
+                .bind(prediction.risk)
+                .bind(now)
+                .bind(now)
+                .bind(advisory_id)
+                .execute(&mut *tx)
+                .await?;
+            Some(false)
+        },
+        (None, false) => None,
+    };
+    tx.commit().await?;
+
+    if change == Some(true) {
+        let message = format!(
+            "Machine {} has a predicted failure risk of {:.0}%: {}",
+            signals.name,
+            prediction.risk * 100.0,
+            prediction.reason
+        );
+        notifications::notify(pool, "maintenance_advisory", Some(signals.machine_id), &message).await?;
+    }
+    Ok(change)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn signals(min_value: Option<f64>, max_value: Option<f64>, values: &[f64]) -> MachineSignals {
+        let start = NOW - (values.len() as i64 - 1) * HOUR;
+        MachineSignals {
+            machine_id: 1,
+            name: "Line 1".to_string(),
+            machine_type: None,
+            from: NOW - LOOKBACK,
+            to: NOW,
+            metrics: vec![MetricSeries {
+                metric: "temperature".to_string(),
+                unit: "C".to_string(),
+                min_value,
+                max_value,
+                readings: values.iter().enumerate().map(|(i, v)| (start + i as i64 * HOUR, *v)).collect(),
+            }],
+        }
+    }
+
+    #[test]
+    fn risk_grows_as_the_trend_nears_the_threshold() {
+        // One degree an hour, 24 hours from the threshold
+        let prediction = predict_trend(&signals(None, Some(86.0), &[60.0, 61.0, 62.0]), NOW);
+        assert_eq!(prediction.risk, 0.86);
+        assert_eq!(prediction.metric.as_deref(), Some("temperature"));
+        assert_eq!(prediction.predicted_failure_at, Some(NOW + 24 * HOUR));
+        assert!(prediction.reason.ends_with("reached in about 24 hours"), "{}", prediction.reason);
+
+        // Falling towards a minimum
+        let prediction = predict_trend(&signals(Some(50.0), None, &[62.0, 61.0, 60.0]), NOW);
+        assert_eq!(prediction.predicted_failure_at, Some(NOW + 10 * HOUR));
+    }
+
+    #[test]
+    fn a_metric_past_its_threshold_is_a_certain_failure() {
+        let prediction = predict_trend(&signals(None, Some(86.0), &[90.0]), NOW);
+        assert_eq!((prediction.risk, prediction.predicted_failure_at), (1.0, Some(NOW)));
+        assert!(prediction.reason.contains("has reached its failure threshold of 86 C"), "{}", prediction.reason);
+    }
+
+    #[test]
+    fn no_risk_without_a_trend_towards_the_threshold() {
+        for signals in [
+            // Cooling down
+            signals(None, Some(86.0), &[62.0, 61.0, 60.0]),
+            // Flat
+            signals(Some(50.0), Some(86.0), &[60.0, 60.0, 60.0]),
+            // Too few readings to fit a line
+            signals(None, Some(86.0), &[60.0, 61.0]),
+            // No threshold at all
+            signals(None, None, &[60.0, 70.0, 80.0]),
+            // Crossing beyond the horizon
+            signals(None, Some(1000.0), &[60.0, 61.0, 62.0]),
+        ] {
+            let prediction = predict_trend(&signals, NOW);
+            assert_eq!((prediction.risk, prediction.metric, prediction.predicted_failure_at), (0.0, None, None));
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    database::{DbPool, current_timestamp},
+    models::{Product, ProductSpeedStandard, SetProductRequest},
+};
+
+// Speed a machine is measured against, as an expression on a `machines` row: the standard of
+// the product in its running batch for its machine type, or the machine's own target_speed
+// when it runs no catalog product or the product has no standard for its type
+pub const TARGET_SPEED: &str = "COALESCE((SELECT s.standard_speed FROM machine_batches b \
+     JOIN product_speed_standards s ON s.sku = b.product AND s.machine_type = machines.machine_type \
+     WHERE b.machine_id = machines.id AND b.ended_at IS NULL), machines.target_speed)";
+
+// Catalog products by SKU, or only the given one
+pub async fn load_products(pool: &DbPool, sku: Option<&str>) -> sqlx::Result<Vec<Product>> {
+    let products: Vec<(String, String, Option<String>, String, i64)> = sqlx::query_as(
+        "SELECT sku, name, description, updated_by, updated_at FROM products WHERE $1 IS NULL OR sku = $2 ORDER BY sku"
+    )
+    .bind(sku)
+    .bind(sku)
+    .fetch_all(pool)
+    .await?;
+    let standards: Vec<(String, String, f64)> = sqlx::query_as(
+        "SELECT sku, machine_type, standard_speed FROM product_speed_standards \
+         WHERE $1 IS NULL OR sku = $2 ORDER BY sku, machine_type"
+    )
+    .bind(sku)
+    .bind(sku)
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_sku: BTreeMap<String, Vec<ProductSpeedStandard>> = BTreeMap::new();
+    for (sku, machine_type, standard_speed) in standards {
+        by_sku.entry(sku).or_default().push(ProductSpeedStandard { machine_type, standard_speed });
+    }
+    Ok(products
+        .into_iter()
+        .map(|(sku, name, description, updated_by, updated_at)| Product {
+            standards: by_sku.remove(&sku).unwrap_or_default(),
+            sku,
+            name,
+            description,
+            updated_by,
+            updated_at,
+        })
+        .collect())
+}
+
+// Creates the product or replaces its details and standards
+pub async fn save_product(pool: &DbPool, sku: &str, product: &SetProductRequest, username: &str) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO products (sku, name, description, updated_by, updated_at) VALUES ($1, $2, $3, $4, $5) \
+         ON CONFLICT (sku) DO UPDATE SET name = excluded.name, description = excluded.description, \
+         updated_by = excluded.updated_by, updated_at = excluded.updated_at"
+    )
+    .bind(sku)
+    .bind(&product.name)
+    .bind(&product.description)
+    .bind(username)
+    .bind(current_timestamp())
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM product_speed_standards WHERE sku = $1")
+        .bind(sku)
+        .execute(&mut *tx)
+        .await?;
+    for standard in &product.standards {
+        sqlx::query("INSERT INTO product_speed_standards (sku, machine_type, standard_speed) VALUES ($1, $2, $3)")
+            .bind(sku)
+            .bind(&standard.machine_type)
+            .bind(standard.standard_speed)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+// Returns whether the product existed. Batches keep the SKU they ran.
+pub async fn delete_product(pool: &DbPool, sku: &str) -> sqlx::Result<bool> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM product_speed_standards WHERE sku = $1")
+        .bind(sku)
+        .execute(&mut *tx)
+        .await?;
+    let deleted = sqlx::query("DELETE FROM products WHERE sku = $1")
+        .bind(sku)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(deleted.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(standards: &[(&str, f64)]) -> SetProductRequest {
+        SetProductRequest {
+            name: "Cola 0.5l".to_string(),
+            description: None,
+            standards: standards
+                .iter()
+                .map(|(machine_type, standard_speed)| ProductSpeedStandard {
+                    machine_type: machine_type.to_string(),
+                    standard_speed: *standard_speed,
+                })
+                .collect(),
+        }
+    }
+
+    async fn target_speed(pool: &DbPool) -> Option<f64> {
+        sqlx::query_scalar(&format!("SELECT {} FROM machines WHERE id = 1", TARGET_SPEED))
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_running_product_standard_overrides_the_machine_target() {
+        let pool = crate::database::test_database().await;
+        sqlx::query(
+            "INSERT INTO machines (id, name, code, api_key, machine_type, target_speed) \
+             VALUES (1, 'Line 1', 'L1', 'key', 'filler', 50.0)"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        save_product(&pool, "COLA-05", &product(&[("capper", 120.0)]), "boss").await.unwrap();
+        save_product(&pool, "COLA-05", &product(&[("filler", 80.0), ("labeler", 90.0)]), "boss").await.unwrap();
+        let saved = load_products(&pool, Some("COLA-05")).await.unwrap();
+        let standards: Vec<&str> = saved[0].standards.iter().map(|standard| standard.machine_type.as_str()).collect();
+        assert_eq!(standards, ["filler", "labeler"]);
+
+        assert_eq!(target_speed(&pool).await, Some(50.0));
+        sqlx::query("INSERT INTO machine_batches (machine_id, lot_number, product, started_at) VALUES (1, 'L-1', 'COLA-05', 0)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(target_speed(&pool).await, Some(80.0));
+
+        // The batch keeps its SKU, the machine falls back to its own target
+        assert!(delete_product(&pool, "COLA-05").await.unwrap());
+        assert!(!delete_product(&pool, "COLA-05").await.unwrap());
+        assert_eq!(target_speed(&pool).await, Some(50.0));
+        assert!(load_products(&pool, None).await.unwrap().is_empty());
+    }
+}
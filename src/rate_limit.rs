@@ -0,0 +1,308 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use ipnet::IpNet;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    auth::{self, extract_token},
+    database::{DbPool, current_timestamp},
+    display_tokens,
+    models::ErrorResponse,
+    network,
+    service_accounts,
+    usage::Principal,
+};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+// How long a key found among the active keys is trusted without looking it up again
+const VERIFIED_FOR: Duration = Duration::from_secs(60);
+
+// Caps requests per caller in a fixed one-minute window, so one misbehaving gateway or
+// script cannot flood the database. Users, service accounts and display tokens share one
+// quota per account or token, machine API keys have their own per key. Everything else,
+// including tokens that turn out not to be valid, shares one quota per client IP, so made-up
+// tokens neither get a budget of their own nor grow the table of windows. Signed machine
+// updates carry no token; they count against the client IP until their signature is verified
+// and are then moved to the quota of the key that signed them (see SignedRequestQuota).
+#[derive(Clone)]
+pub struct TokenRateLimit {
+    user_per_minute: u32,
+    machine_per_minute: u32,
+    anonymous_per_minute: u32,
+    trusted_proxies: Arc<[IpNet]>,
+    pool: DbPool,
+    // Keyed by principal kind and principal, or "ip" and the client address
+    windows: Arc<Mutex<HashMap<String, (Instant, u32)>>>,
+    // Hashes of keys found among the active machine, service account and display keys
+    verified: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl TokenRateLimit {
+    pub fn new(
+        user_per_minute: u32,
+        machine_per_minute: u32,
+        anonymous_per_minute: u32,
+        trusted_proxies: &[IpNet],
+        pool: DbPool,
+    ) -> Self {
+        Self {
+            user_per_minute,
+            machine_per_minute,
+            anonymous_per_minute,
+            trusted_proxies: trusted_proxies.into(),
+            pool,
+            windows: Arc::new(Mutex::new(HashMap::new())),
+            verified: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Counts a request against a window; returns how long the caller must wait if it is over
+    // `per_minute`
+    fn check(&self, window: String, per_minute: u32) -> Option<Duration> {
+        if per_minute == 0 {
+            return None;
+        }
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        let (started, requests) = windows.entry(window).or_insert((now, 0));
+        if now.duration_since(*started) >= RATE_WINDOW {
+            (*started, *requests) = (now, 0);
+        }
+        *requests += 1;
+        (*requests > per_minute).then(|| RATE_WINDOW - now.duration_since(*started))
+    }
+
+    // Who made the request, and how long it must wait if it is over its quota. Keys are looked
+    // up at most once a minute while they stay valid; a key that is not found is counted
+    // against the client IP like a request without a token.
+    async fn admit(&self, token: Option<String>, ip: IpAddr) -> (Principal, Option<Duration>) {
+        let by_ip = || self.check(format!("ip:{}", ip), self.anonymous_per_minute);
+        let Some(token) = token else {
+            return (Principal::anonymous(), by_ip());
+        };
+        let kind = if token.starts_with("machine_") {
+            "machine_key"
+        } else if token.starts_with(service_accounts::KEY_PREFIX) {
+            "service_account"
+        } else if token.starts_with(display_tokens::KEY_PREFIX) {
+            "display_token"
+        } else {
+            // Session tokens are recognised by their signature alone
+            return match auth::session_subject(&token) {
+                Some(username) => {
+                    let wait = self.check(format!("user:{}", username), self.user_per_minute);
+                    (Principal { kind: "user", id: username }, wait)
+                },
+                None => (Principal::invalid(), by_ip()),
+            };
+        };
+
+        let key_hash = auth::hash_token(&token);
+        let verified_at = self.verified.lock().unwrap().get(&key_hash).copied();
+        let verified = match verified_at {
+            Some(at) if at.elapsed() < VERIFIED_FOR => true,
+            _ => match is_active_key(&self.pool, kind, &key_hash).await {
+                Ok(active) => active,
+                // Authentication reports the database failure
+                Err(e) => {
+                    tracing::error!("Failed to look up {} for rate limiting: {}", kind, e);
+                    false
+                },
+            },
+        };
+        if !verified {
+            return (Principal::invalid(), by_ip());
+        }
+        if verified_at.is_none_or(|at| at.elapsed() >= VERIFIED_FOR) {
+            self.verified.lock().unwrap().insert(key_hash.clone(), Instant::now());
+        }
+        let per_minute = if kind == "machine_key" { self.machine_per_minute } else { self.user_per_minute };
+        let wait = self.check(format!("{}:{}", kind, key_hash), per_minute);
+        (Principal { kind, id: key_hash }, wait)
+    }
+
+    // Drops finished windows and expired verifications once a minute, off the request path
+    pub fn spawn_prune(&self) -> JoinHandle<()> {
+        let limit = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RATE_WINDOW);
+            loop {
+                interval.tick().await;
+                let now = Instant::now();
+                limit.windows.lock().unwrap().retain(|_, (started, _)| now.duration_since(*started) < RATE_WINDOW);
+                limit.verified.lock().unwrap().retain(|_, verified_at| now.duration_since(*verified_at) < VERIFIED_FOR);
+            }
+        })
+    }
+}
+
+// Handed to the signing middleware with a signed request, which has no token to be counted by.
+// Once the signature holds, the request is moved from the client IP's quota to that of the
+// signing key, so machines behind one gateway each get their own budget while made-up
+// signatures stay limited per IP.
+#[derive(Clone)]
+pub struct SignedRequestQuota {
+    limit: TokenRateLimit,
+    ip: IpAddr,
+}
+
+impl SignedRequestQuota {
+    // Returns how long the machine must wait if its key is over its quota
+    pub fn charge_key(&self, key_hash: &str) -> Option<Duration> {
+        if let Some((_, requests)) = self.limit.windows.lock().unwrap().get_mut(&format!("ip:{}", self.ip)) {
+            *requests = requests.saturating_sub(1);
+        }
+        self.limit.check(format!("machine_key:{}", key_hash), self.limit.machine_per_minute)
+    }
+}
+
+pub fn too_many_requests(wait: Duration) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, wait.as_secs().max(1).to_string())],
+        Json(ErrorResponse {
+            error: "Too many requests, retry later".to_string(),
+        }),
+    )
+        .into_response()
+}
+
+// Whether a key hash belongs to an active key of the kind, by the same rules authentication uses
+async fn is_active_key(pool: &DbPool, kind: &str, key_hash: &str) -> sqlx::Result<bool> {
+    let now = current_timestamp();
+    match kind {
+        "machine_key" => sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM machines WHERE api_key = $1 AND archived_at IS NULL) \
+             OR EXISTS (SELECT 1 FROM machine_api_keys WHERE api_key = $2 AND revoked_at IS NULL \
+             AND (expires_at IS NULL OR expires_at > $3) AND machine_id IN (SELECT id FROM machines WHERE archived_at IS NULL))"
+        )
+        .bind(key_hash)
+        .bind(key_hash)
+        .bind(now)
+        .fetch_one(pool)
+        .await,
+        "service_account" => sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM service_accounts WHERE disabled_at IS NULL \
+             AND (api_key = $1 OR (previous_api_key = $2 AND previous_key_expires_at > $3)))"
+        )
+        .bind(key_hash)
+        .bind(key_hash)
+        .bind(now)
+        .fetch_one(pool)
+        .await,
+        _ => sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM display_tokens WHERE token = $1 AND revoked_at IS NULL \
+             AND (expires_at IS NULL OR expires_at > $2))"
+        )
+        .bind(key_hash)
+        .bind(now)
+        .fetch_one(pool)
+        .await,
+    }
+}
+
+pub async fn limit_token_rate(
+    State(limit): State<TokenRateLimit>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let ip = network::client_ip(request.headers(), addr.ip(), &limit.trusted_proxies);
+    let (principal, wait) = limit.admit(extract_token(request.headers()), ip).await;
+    let mut response = match wait {
+        None => {
+            if principal.kind == "anonymous" && request.headers().contains_key("x-signature") {
+                request.extensions_mut().insert(SignedRequestQuota { limit: limit.clone(), ip });
+            }
+            // Read-only mode only lets verified callers' failed writes count
+            request.extensions_mut().insert(principal.clone());
+            next.run(request).await
+        },
+        Some(wait) => {
+            tracing::warn!("Rate limiting {} requests to {}", principal.kind, request.uri().path());
+            too_many_requests(wait)
+        },
+    };
+    // Usage is recorded against the same principal, unless the signing middleware has found
+    // the key that signed the request
+    if response.extensions().get::<Principal>().is_none() {
+        response.extensions_mut().insert(principal);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    const CLIENT: &str = "192.0.2.10:50000";
+
+    async fn app(anonymous_per_minute: u32) -> (TokenRateLimit, Router) {
+        let limit = TokenRateLimit::new(10, 10, anonymous_per_minute, &[], crate::database::test_database().await);
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(limit.clone(), limit_token_rate));
+        (limit, app)
+    }
+
+    // Sends a request with the given bearer token, if any; returns the status and Retry-After
+    async fn send(app: &Router, token: Option<&str>) -> (StatusCode, Option<u64>) {
+        let mut request = Request::get("/");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let mut request = request.body(Body::empty()).unwrap();
+        request.extensions_mut().insert(ConnectInfo(CLIENT.parse::<SocketAddr>().unwrap()));
+        let response = app.clone().oneshot(request).await.unwrap();
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().parse().unwrap());
+        (response.status(), retry_after)
+    }
+
+    // Moves the start of every window back by `elapsed`
+    fn age_windows(limit: &TokenRateLimit, elapsed: Duration) {
+        for (started, _) in limit.windows.lock().unwrap().values_mut() {
+            *started = Instant::now().checked_sub(elapsed).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_requests_over_the_quota_until_the_window_is_over() {
+        let (limit, app) = app(2).await;
+        assert_eq!(send(&app, None).await, (StatusCode::OK, None));
+        assert_eq!(send(&app, None).await, (StatusCode::OK, None));
+
+        age_windows(&limit, Duration::from_secs(45));
+        let (status, retry_after) = send(&app, None).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert!(matches!(retry_after, Some(14..=15)), "Retry-After {:?}", retry_after);
+
+        age_windows(&limit, RATE_WINDOW);
+        assert_eq!(send(&app, None).await, (StatusCode::OK, None));
+    }
+
+    #[tokio::test]
+    async fn invalid_tokens_share_the_quota_of_the_client_ip() {
+        let (limit, app) = app(2).await;
+        assert_eq!(send(&app, Some("machine_not-a-key")).await.0, StatusCode::OK);
+        assert_eq!(send(&app, Some("not-a-session")).await.0, StatusCode::OK);
+        assert_eq!(send(&app, None).await.0, StatusCode::TOO_MANY_REQUESTS);
+        let windows: Vec<String> = limit.windows.lock().unwrap().keys().cloned().collect();
+        assert_eq!(windows, ["ip:192.0.2.10"]);
+    }
+}
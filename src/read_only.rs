@@ -0,0 +1,226 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, Mutex,
+};
+
+use crate::{
+    database::current_timestamp,
+    models::{ErrorResponse, ReadOnlyResponse},
+    usage::Principal,
+};
+
+// Requests that must keep working while writes are refused
+const ALWAYS_ALLOWED: &[&str] = &["/api/login", "/api/logout", "/api/token/refresh", "/api/admin/read-only", "/api/admin/backup"];
+
+// Seconds ingesting machines are told to wait before retrying
+const RETRY_AFTER_SECONDS: &str = "30";
+
+// Read-only mode keeps every read endpoint serving while writes get 503, e.g. during a
+// database maintenance window. It is entered from config, by an admin, or automatically
+// after a run of consecutive failed writes, and left only by an admin.
+#[derive(Clone)]
+pub struct ReadOnlyMode {
+    status: Arc<Mutex<Option<(String, i64)>>>,
+    write_failures: Arc<AtomicU32>,
+    failure_threshold: u32,
+}
+
+impl ReadOnlyMode {
+    pub fn new(enabled: bool, failure_threshold: u32) -> Self {
+        Self {
+            status: Arc::new(Mutex::new(enabled.then(|| ("config".to_string(), current_timestamp())))),
+            write_failures: Arc::new(AtomicU32::new(0)),
+            failure_threshold,
+        }
+    }
+
+    pub fn status(&self) -> ReadOnlyResponse {
+        match self.status.lock().unwrap().clone() {
+            Some((reason, since)) => ReadOnlyResponse {
+                read_only: true,
+                reason: Some(reason),
+                since: Some(since),
+            },
+            None => ReadOnlyResponse {
+                read_only: false,
+                reason: None,
+                since: None,
+            },
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.status.lock().unwrap().is_some()
+    }
+
+    pub fn set(&self, enabled: bool, reason: &str) {
+        let mut status = self.status.lock().unwrap();
+        if !enabled {
+            *status = None;
+            self.write_failures.store(0, Ordering::Relaxed);
+        } else if status.is_none() {
+            *status = Some((reason.to_string(), current_timestamp()));
+        }
+    }
+
+    fn record_write(&self, succeeded: bool) {
+        if succeeded {
+            self.write_failures.store(0, Ordering::Relaxed);
+            return;
+        }
+
+        let failures = self.write_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.failure_threshold > 0 && failures == self.failure_threshold {
+            tracing::error!("{} consecutive writes failed, switching to read-only mode", failures);
+            self.set(true, "write_failures");
+        }
+    }
+}
+
+pub async fn guard_writes(State(mode): State<ReadOnlyMode>, request: Request, next: Next) -> Response {
+    let is_write = !matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if !is_write || ALWAYS_ALLOWED.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    if mode.is_enabled() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, RETRY_AFTER_SECONDS)],
+            Json(ErrorResponse {
+                error: "Server is in read-only mode, writes are temporarily disabled".to_string(),
+            }),
+        )
+            .into_response();
+    }
+
+    // Only a write whose caller was verified counts toward the failure run, and only an
+    // internal error does: a 503 from an unconfigured feature, e.g. password reset without SMTP,
+    // says nothing about the database, and anonymous callers or made-up credentials must not be
+    // able to switch writes off. The rate limit verifies tokens before this runs; a signature is
+    // only verified further in, and marks the response.
+    let verified = |principal: Option<&Principal>| principal.is_some_and(Principal::is_verified);
+    let token_verified = verified(request.extensions().get::<Principal>());
+    let response = next.run(request).await;
+    if !token_verified && !verified(response.extensions().get::<Principal>()) {
+        return response;
+    }
+    if response.status() == StatusCode::INTERNAL_SERVER_ERROR {
+        mode.record_write(false);
+    } else if response.status().is_success() {
+        mode.record_write(true);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::post, Router};
+    use tower::ServiceExt;
+
+    fn app(mode: ReadOnlyMode) -> Router {
+        Router::new()
+            .route("/api/comments", post(|| async { StatusCode::CREATED }).get(|| async { "comments" }))
+            .route("/api/failing", post(|| async { StatusCode::INTERNAL_SERVER_ERROR }))
+            .route("/api/unconfigured", post(|| async { StatusCode::SERVICE_UNAVAILABLE }))
+            .route("/api/login", post(|| async { "session" }))
+            .layer(middleware::from_fn_with_state(mode, guard_writes))
+    }
+
+    // Sends a request as the rate limit passes it on, with the principal it established
+    async fn send_as(app: &Router, method: Method, path: &str, principal: Principal) -> StatusCode {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(header::AUTHORIZATION, "Bearer token")
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(principal);
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    async fn send(app: &Router, method: Method, path: &str) -> StatusCode {
+        send_as(app, method, path, Principal { kind: "user", id: "alice".to_string() }).await
+    }
+
+    async fn send_anonymous(app: &Router, path: &str) -> StatusCode {
+        let request = Request::builder().method(Method::POST).uri(path).body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn refuses_writes_after_consecutive_failures_until_turned_off() {
+        let mode = ReadOnlyMode::new(false, 2);
+        let app = app(mode.clone());
+        assert_eq!(send(&app, Method::POST, "/api/failing").await, StatusCode::INTERNAL_SERVER_ERROR);
+        // A successful write starts the count over
+        assert_eq!(send(&app, Method::POST, "/api/comments").await, StatusCode::CREATED);
+        assert_eq!(send(&app, Method::POST, "/api/failing").await, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!mode.is_enabled());
+        assert_eq!(send(&app, Method::POST, "/api/failing").await, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(mode.status().reason.as_deref(), Some("write_failures"));
+
+        assert_eq!(send(&app, Method::POST, "/api/comments").await, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(send(&app, Method::GET, "/api/comments").await, StatusCode::OK);
+        assert_eq!(send(&app, Method::POST, "/api/login").await, StatusCode::OK);
+
+        mode.set(false, "boss");
+        assert_eq!(send(&app, Method::POST, "/api/comments").await, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn ignores_unconfigured_features_and_anonymous_failures() {
+        let mode = ReadOnlyMode::new(false, 2);
+        let app = app(mode.clone());
+        for _ in 0..3 {
+            assert_eq!(send(&app, Method::POST, "/api/unconfigured").await, StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(send_anonymous(&app, "/api/unconfigured").await, StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(send_anonymous(&app, "/api/failing").await, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        assert!(!mode.is_enabled());
+    }
+
+    #[tokio::test]
+    async fn ignores_failures_of_unverified_credentials() {
+        let mode = ReadOnlyMode::new(false, 2);
+        let app = app(mode.clone());
+        for _ in 0..3 {
+            assert_eq!(send_as(&app, Method::POST, "/api/failing", Principal::invalid()).await, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        assert!(!mode.is_enabled());
+
+        // A verified signature marks the response instead
+        let signed = Router::new()
+            .route(
+                "/api/machines/update",
+                post(|| async {
+                    let mut response = StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                    response.extensions_mut().insert(Principal { kind: "machine_key", id: "hash".to_string() });
+                    response
+                }),
+            )
+            .layer(middleware::from_fn_with_state(mode.clone(), guard_writes));
+        for _ in 0..2 {
+            assert_eq!(send_anonymous(&signed, "/api/machines/update").await, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        assert!(mode.is_enabled());
+    }
+
+    #[test]
+    fn keeps_the_first_reason_while_enabled() {
+        let mode = ReadOnlyMode::new(true, 0);
+        mode.set(true, "boss");
+        let status = mode.status();
+        assert!(status.read_only);
+        assert_eq!(status.reason.as_deref(), Some("config"));
+        mode.set(false, "boss");
+        assert!(!mode.status().read_only);
+    }
+}
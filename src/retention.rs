@@ -0,0 +1,214 @@
+use serde_json::{Value, json};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{io::AsyncWriteExt, task::JoinHandle};
+use tokio_stream::StreamExt;
+
+use crate::{
+    config::Config,
+    database::{DbPool, current_timestamp},
+    export,
+    jobs::JobContext,
+};
+
+// Rows deleted per statement, so telemetry writes never wait behind one long delete
+const PURGE_BATCH: i64 = 5000;
+
+// How long speed history is kept: `default` for machines without their own
+// history_retention_days, zero to keep it forever. With `archive_dir` set, purged rows are
+// written to a CSV file there first.
+#[derive(Clone)]
+pub struct HistoryRetention {
+    pub default: Duration,
+    pub archive_dir: Option<PathBuf>,
+}
+
+impl HistoryRetention {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            default: config.history_retention,
+            archive_dir: config.history_archive.then(|| config.archive_dir.clone()),
+        }
+    }
+}
+
+// Purges expired speed history every hour
+pub fn spawn_history_retention(pool: DbPool, retention: HistoryRetention) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = purge_history(&pool, &retention, None, None).await {
+                tracing::error!("Speed history purge failed: {}", e);
+            }
+        }
+    })
+}
+
+// Deletes speed history older than each machine's retention, or only the given machine's.
+// Returns per machine the rows deleted and the archive file they were written to.
+pub async fn purge_history(
+    pool: &DbPool,
+    retention: &HistoryRetention,
+    machine_id: Option<i64>,
+    job: Option<&JobContext>,
+) -> anyhow::Result<Value> {
+    let default_days = (retention.default.as_secs() / (24 * 60 * 60)) as i64;
+    let machines: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT id, COALESCE(history_retention_days, $1) FROM machines WHERE $2 IS NULL OR id = $3 ORDER BY id"
+    )
+    .bind(default_days)
+    .bind(machine_id)
+    .bind(machine_id)
+    .fetch_all(pool)
+    .await?;
+
+    let now = current_timestamp();
+    let mut purged = Vec::new();
+    let mut total: i64 = 0;
+    for (done, (machine_id, days)) in machines.iter().copied().enumerate() {
+        if days > 0 {
+            let cutoff = now - days * 24 * 60 * 60;
+            let archive_file = match &retention.archive_dir {
+                Some(dir) => archive_rows(pool, dir, machine_id, cutoff).await?,
+                None => None,
+            };
+
+            let mut deleted: i64 = 0;
+            loop {
+                let batch = sqlx::query(
+                    "DELETE FROM speed_history WHERE id IN \
+                     (SELECT id FROM speed_history WHERE machine_id = $1 AND timestamp < $2 LIMIT $3)"
+                )
+                .bind(machine_id)
+                .bind(cutoff)
+                .bind(PURGE_BATCH)
+                .execute(pool)
+                .await?
+                .rows_affected() as i64;
+                deleted += batch;
+                if batch < PURGE_BATCH {
+                    break;
+                }
+            }
+
+            if deleted > 0 {
+                tracing::info!("Purged {} speed history rows older than {} days of machine ID {}", deleted, days, machine_id);
+                total += deleted;
+                purged.push(json!({
+                    "machine_id": machine_id,
+                    "retention_days": days,
+                    "deleted_rows": deleted,
+                    "archive_file": archive_file,
+                }));
+            }
+        }
+        if let Some(job) = job {
+            job.set_progress((done + 1) as f64 / machines.len() as f64).await;
+        }
+    }
+    Ok(json!({ "machines": purged, "deleted_rows": total }))
+}
+
+// Writes the machine's rows older than `cutoff` to a CSV file in `dir`; None when there are none
+async fn archive_rows(pool: &DbPool, dir: &Path, machine_id: i64, cutoff: i64) -> anyhow::Result<Option<String>> {
+    let mut rows = sqlx::query_as::<_, (i64, f64, Option<String>, i64)>(
+        "SELECT id, speed, message, timestamp FROM speed_history WHERE machine_id = $1 AND timestamp < $2 ORDER BY id"
+    )
+    .bind(machine_id)
+    .bind(cutoff)
+    .fetch(pool);
+
+    let mut file = None;
+    let path = dir.join("history").join(format!("speed_history-machine-{}-before-{}.csv", machine_id, cutoff));
+    while let Some(row) = rows.next().await {
+        let (id, speed, message, timestamp) = row?;
+        let writer = match file.as_mut() {
+            Some(writer) => writer,
+            None => {
+                tokio::fs::create_dir_all(dir.join("history")).await?;
+                let mut created = tokio::io::BufWriter::new(tokio::fs::File::create(&path).await?);
+                created.write_all(b"id,machine_id,speed,message,timestamp\n").await?;
+                file.insert(created)
+            },
+        };
+        let line = format!(
+            "{},{},{},{},{}\n",
+            id, machine_id, speed, export::csv_field(message.as_deref().unwrap_or("")), timestamp
+        );
+        writer.write_all(line.as_bytes()).await?;
+    }
+
+    match file {
+        Some(mut file) => {
+            file.flush().await?;
+            Ok(Some(path.to_string_lossy().to_string()))
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Machine 1 keeps the default 30 days, machine 2 has 7 days and machine 3 keeps everything;
+    // each has a sample from 10 and from 60 days ago
+    async fn database() -> DbPool {
+        let pool = crate::database::test_database().await;
+        sqlx::query(
+            "INSERT INTO machines (id, name, code, api_key, history_retention_days) \
+             VALUES (1, 'Line 1', 'L1', 'key1', NULL), (2, 'Line 2', 'L2', 'key2', 7), (3, 'Line 3', 'L3', 'key3', 0)"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let day = 24 * 60 * 60;
+        for machine_id in 1..=3 {
+            for age in [10, 60] {
+                sqlx::query("INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES ($1, 5.0, 'old, slow', $2)")
+                    .bind(machine_id)
+                    .bind(current_timestamp() - age * day)
+                    .execute(&pool)
+                    .await
+                    .unwrap();
+            }
+        }
+        pool
+    }
+
+    async fn remaining(pool: &DbPool) -> Vec<(i64, i64)> {
+        sqlx::query_as("SELECT machine_id, COUNT(*) FROM speed_history GROUP BY machine_id ORDER BY machine_id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn purges_history_past_each_machines_retention() {
+        let pool = database().await;
+        let retention = HistoryRetention { default: Duration::from_secs(30 * 24 * 60 * 60), archive_dir: None };
+        let purged = purge_history(&pool, &retention, None, None).await.unwrap();
+        assert_eq!(purged["deleted_rows"], 3);
+        assert_eq!(remaining(&pool).await, [(1, 1), (3, 2)]);
+    }
+
+    #[tokio::test]
+    async fn archives_purged_rows_of_one_machine() {
+        let pool = database().await;
+        let dir = std::env::temp_dir().join(format!("scada-retention-{}", uuid::Uuid::new_v4().simple()));
+        let retention = HistoryRetention { default: Duration::from_secs(30 * 24 * 60 * 60), archive_dir: Some(dir.clone()) };
+        let purged = purge_history(&pool, &retention, Some(1), None).await.unwrap();
+        assert_eq!(remaining(&pool).await, [(1, 1), (2, 2), (3, 2)]);
+
+        let file = purged["machines"][0]["archive_file"].as_str().unwrap();
+        let csv = std::fs::read_to_string(file).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "id,machine_id,speed,message,timestamp");
+        assert!(lines[1].contains(",1,5,\"old, slow\","), "{}", lines[1]);
+    }
+}
@@ -0,0 +1,239 @@
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::database::{Db, DbPool};
+
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+
+// History rows rolled up per transaction, so a large backlog never holds the write lock for long
+const ROLLUP_BATCH: i64 = 50_000;
+
+// Windows longer than this are served from hourly rollups, and longer than 60 days from daily ones
+const RAW_MAX_SPAN: i64 = 2 * DAY;
+const HOURLY_MAX_SPAN: i64 = 60 * DAY;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Resolution {
+    Raw,
+    Hour,
+    Day,
+}
+
+pub const RESOLUTION_NAMES: &[&str] = &["raw", "hour", "day"];
+
+impl Resolution {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(Self::Raw),
+            "hour" => Some(Self::Hour),
+            "day" => Some(Self::Day),
+            _ => None,
+        }
+    }
+
+    // The resolution for a window of history; open-ended windows are served raw, since they
+    // only return the latest readings up to the limit
+    pub fn for_window(from: i64, to: i64) -> Self {
+        if from == i64::MIN || to == i64::MAX {
+            return Self::Raw;
+        }
+        match to.saturating_sub(from) {
+            span if span <= RAW_MAX_SPAN => Self::Raw,
+            span if span <= HOURLY_MAX_SPAN => Self::Hour,
+            _ => Self::Day,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+            Self::Hour => "hour",
+            Self::Day => "day",
+        }
+    }
+
+    // The rollup table and its bucket width; None for raw history
+    pub fn table(self) -> Option<(&'static str, i64)> {
+        match self {
+            Self::Raw => None,
+            Self::Hour => Some(("speed_history_hourly", HOUR)),
+            Self::Day => Some(("speed_history_daily", DAY)),
+        }
+    }
+}
+
+// Rolls up new speed history every minute
+pub fn spawn_rollups(pool: DbPool) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = roll_up(&pool).await {
+                tracing::error!("Speed history rollup failed: {}", e);
+            }
+        }
+    })
+}
+
+// Recomputes the hours and days that history rows stored since the last run fall into, which
+// also covers readings arriving late with an old timestamp. Returns the rows rolled up.
+pub async fn roll_up(pool: &DbPool) -> sqlx::Result<i64> {
+    let mut total = 0;
+    loop {
+        // Only this task moves the watermark, so it is safe to read outside the transaction
+        let (last, newest): (i64, i64) = sqlx::query_as(
+            "SELECT last_history_id, (SELECT COALESCE(MAX(id), 0) FROM speed_history) FROM speed_rollup_state WHERE id = 1"
+        )
+        .fetch_one(pool)
+        .await?;
+        let upto = newest.min(last + ROLLUP_BATCH);
+        if upto <= last {
+            return Ok(total);
+        }
+
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO speed_history_hourly \
+             (machine_id, bucket_start, avg_speed, min_speed, max_speed, samples) \
+             SELECT h.machine_id, b.bucket, AVG(h.speed), MIN(h.speed), MAX(h.speed), COUNT(*) \
+             FROM (SELECT DISTINCT machine_id, timestamp - timestamp % $1 AS bucket FROM speed_history WHERE id > $2 AND id <= $3) b \
+             JOIN speed_history h ON h.machine_id = b.machine_id AND h.timestamp >= b.bucket AND h.timestamp < b.bucket + $4 \
+             GROUP BY h.machine_id, b.bucket \
+             ON CONFLICT (machine_id, bucket_start) DO UPDATE SET avg_speed = excluded.avg_speed, \
+             min_speed = excluded.min_speed, max_speed = excluded.max_speed, samples = excluded.samples"
+        )
+        .bind(HOUR)
+        .bind(last)
+        .bind(upto)
+        .bind(HOUR)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO speed_history_daily \
+             (machine_id, bucket_start, avg_speed, min_speed, max_speed, samples) \
+             SELECT r.machine_id, b.bucket, SUM(r.avg_speed * r.samples) / SUM(r.samples), MIN(r.min_speed), \
+             MAX(r.max_speed), SUM(r.samples) \
+             FROM (SELECT DISTINCT machine_id, timestamp - timestamp % $1 AS bucket FROM speed_history WHERE id > $2 AND id <= $3) b \
+             JOIN speed_history_hourly r ON r.machine_id = b.machine_id AND r.bucket_start >= b.bucket AND r.bucket_start < b.bucket + $4 \
+             GROUP BY r.machine_id, b.bucket \
+             ON CONFLICT (machine_id, bucket_start) DO UPDATE SET avg_speed = excluded.avg_speed, \
+             min_speed = excluded.min_speed, max_speed = excluded.max_speed, samples = excluded.samples"
+        )
+        .bind(DAY)
+        .bind(last)
+        .bind(upto)
+        .bind(DAY)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE speed_rollup_state SET last_history_id = $1 WHERE id = 1")
+            .bind(upto)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        total += upto - last;
+    }
+}
+
+// Recomputes the machine's rollups of the hours and days overlapping [from, to) from its
+// history, for changes to existing rows such as history shifts. Buckets whose history was
+// purged by retention are rebuilt from what is left of it.
+pub async fn rebuild(conn: &mut <Db as sqlx::Database>::Connection, machine_id: i64, from: i64, to: i64) -> sqlx::Result<()> {
+    let (hour_from, hour_to) = (from - from.rem_euclid(HOUR), to - to.rem_euclid(HOUR) + HOUR);
+    sqlx::query("DELETE FROM speed_history_hourly WHERE machine_id = $1 AND bucket_start >= $2 AND bucket_start < $3")
+        .bind(machine_id)
+        .bind(hour_from)
+        .bind(hour_to)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        "INSERT INTO speed_history_hourly (machine_id, bucket_start, avg_speed, min_speed, max_speed, samples) \
+         SELECT machine_id, timestamp - timestamp % $1 AS bucket, AVG(speed), MIN(speed), MAX(speed), COUNT(*) \
+         FROM speed_history WHERE machine_id = $2 AND timestamp >= $3 AND timestamp < $4 GROUP BY machine_id, bucket"
+    )
+    .bind(HOUR)
+    .bind(machine_id)
+    .bind(hour_from)
+    .bind(hour_to)
+    .execute(&mut *conn)
+    .await?;
+
+    let (day_from, day_to) = (from - from.rem_euclid(DAY), to - to.rem_euclid(DAY) + DAY);
+    sqlx::query("DELETE FROM speed_history_daily WHERE machine_id = $1 AND bucket_start >= $2 AND bucket_start < $3")
+        .bind(machine_id)
+        .bind(day_from)
+        .bind(day_to)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        "INSERT INTO speed_history_daily (machine_id, bucket_start, avg_speed, min_speed, max_speed, samples) \
+         SELECT machine_id, bucket_start - bucket_start % $1 AS bucket, SUM(avg_speed * samples) / SUM(samples), \
+         MIN(min_speed), MAX(max_speed), SUM(samples) \
+         FROM speed_history_hourly WHERE machine_id = $2 AND bucket_start >= $3 AND bucket_start < $4 GROUP BY machine_id, bucket"
+    )
+    .bind(DAY)
+    .bind(machine_id)
+    .bind(day_from)
+    .bind(day_to)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn record(pool: &DbPool, speed: f64, timestamp: i64) {
+        sqlx::query("INSERT INTO speed_history (machine_id, speed, message, timestamp) VALUES (1, $1, 'running', $2)")
+            .bind(speed)
+            .bind(timestamp)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    async fn buckets(pool: &DbPool, table: &str) -> Vec<(i64, f64, f64, f64, i64)> {
+        sqlx::query_as(&format!(
+            "SELECT bucket_start, avg_speed, min_speed, max_speed, samples FROM {} WHERE machine_id = 1 ORDER BY bucket_start",
+            table
+        ))
+        .fetch_all(pool)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rolls_up_new_and_late_history_into_hours_and_days() {
+        let pool = crate::database::test_database().await;
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let day = 20 * DAY;
+        for (speed, timestamp) in [(10.0, day + 60), (20.0, day + 120), (40.0, day + HOUR + 60)] {
+            record(&pool, speed, timestamp).await;
+        }
+        assert_eq!(roll_up(&pool).await.unwrap(), 3);
+        assert_eq!(buckets(&pool, "speed_history_hourly").await, [(day, 15.0, 10.0, 20.0, 2), (day + HOUR, 40.0, 40.0, 40.0, 1)]);
+        assert_eq!(buckets(&pool, "speed_history_daily").await, [(day, 70.0 / 3.0, 10.0, 40.0, 3)]);
+        assert_eq!(roll_up(&pool).await.unwrap(), 0);
+
+        // A reading arriving late for an hour already rolled up
+        record(&pool, 30.0, day + 180).await;
+        assert_eq!(roll_up(&pool).await.unwrap(), 1);
+        assert_eq!(buckets(&pool, "speed_history_hourly").await[0], (day, 20.0, 10.0, 30.0, 3));
+        assert_eq!(buckets(&pool, "speed_history_daily").await, [(day, 25.0, 10.0, 40.0, 4)]);
+    }
+
+    #[test]
+    fn long_windows_are_served_from_coarser_rollups() {
+        assert!(Resolution::for_window(0, DAY) == Resolution::Raw);
+        assert!(Resolution::for_window(0, 7 * DAY) == Resolution::Hour);
+        assert!(Resolution::for_window(0, 365 * DAY) == Resolution::Day);
+        assert!(Resolution::for_window(i64::MIN, 0) == Resolution::Raw);
+        for name in RESOLUTION_NAMES {
+            assert_eq!(Resolution::parse(name).map(Resolution::name), Some(*name));
+        }
+    }
+}
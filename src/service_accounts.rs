@@ -0,0 +1,185 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::{self, extract_token},
+    database::{DbPool, current_timestamp},
+    models::ErrorResponse,
+};
+
+// Service account keys are told apart from session tokens and machine keys by this prefix
+pub const KEY_PREFIX: &str = "service_";
+
+// Operator endpoints each scope opens to service accounts, as method and route. Everything
+// else, user and site administration included, stays closed to them.
+const SCOPE_ROUTES: &[(&str, Method, &str)] = &[
+    ("machines:read", Method::GET, "/api/machines"),
+    ("machines:read", Method::GET, "/api/machines/{id}/full"),
+    ("machines:read", Method::GET, "/api/machines/{id}/history"),
+    ("alarms:read", Method::GET, "/api/alarms"),
+    ("alarms:read", Method::GET, "/api/alarms/export"),
+    ("work_orders:read", Method::GET, "/api/machines/{id}/work-orders"),
+    ("work_orders:read", Method::GET, "/api/work-orders/{id}"),
+    ("work_orders:write", Method::POST, "/api/machines/{id}/work-orders"),
+    ("work_orders:write", Method::PUT, "/api/work-orders/{id}"),
+    ("reports:read", Method::GET, "/api/reports/comments-by-category"),
+    ("reports:read", Method::GET, "/api/reports/labor-hours"),
+    ("reports:read", Method::GET, "/api/reports/alarm-root-causes"),
+    ("reports:read", Method::GET, "/api/reports/expired-contracts"),
+    ("reports:read", Method::GET, "/api/reports/changeovers"),
+    ("reports:read", Method::GET, "/api/reports/operators"),
+    ("reports:read", Method::GET, "/api/reports/compliance-exceedances"),
+    ("reports:read", Method::GET, "/api/analytics/benchmark"),
+    ("reports:read", Method::GET, "/api/health-scores"),
+];
+
+// A request authenticated with a service account key, for AuthUser to pick up
+#[derive(Debug, Clone)]
+pub struct ServicePrincipal {
+    pub name: String,
+}
+
+impl ServicePrincipal {
+    // Name recorded as the author of whatever the account creates, e.g. "service:erp"
+    pub fn username(&self) -> String {
+        format!("service:{}", self.name)
+    }
+}
+
+pub fn generate_key() -> String {
+    format!("{}{}", KEY_PREFIX, Uuid::new_v4().simple())
+}
+
+fn reject(status: StatusCode, error: String) -> Response {
+    (status, Json(ErrorResponse { error })).into_response()
+}
+
+// Authenticates requests carrying a service account key and lets them through only to the
+// endpoints their scopes open. A key replaced by a rotation works until its grace period ends.
+pub async fn authenticate(State(pool): State<DbPool>, mut request: Request, next: Next) -> Response {
+    let Some(token) = extract_token(request.headers()).filter(|token| token.starts_with(KEY_PREFIX)) else {
+        return next.run(request).await;
+    };
+
+    let now = current_timestamp();
+    let key_hash = auth::hash_token(&token);
+    let account = sqlx::query_as::<_, (i64, String, String)>(
+        "SELECT id, name, scopes FROM service_accounts WHERE disabled_at IS NULL \
+         AND (api_key = $1 OR (previous_api_key = $2 AND previous_key_expires_at > $3))"
+    )
+    .bind(&key_hash)
+    .bind(&key_hash)
+    .bind(now)
+    .fetch_optional(&pool)
+    .await;
+    let (account_id, name, scopes) = match account {
+        Ok(Some(account)) => account,
+        Ok(None) => return reject(StatusCode::UNAUTHORIZED, "Invalid service account key".to_string()),
+        Err(_) => return reject(StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string()),
+    };
+
+    let route = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string());
+    let required = SCOPE_ROUTES
+        .iter()
+        .find(|(_, method, path)| method == request.method() && Some(*path) == route.as_deref())
+        .map(|(scope, _, _)| *scope);
+    let Some(required) = required else {
+        return reject(StatusCode::FORBIDDEN, "Endpoint not available to service accounts".to_string());
+    };
+    if !scopes.split(',').any(|scope| scope == required) {
+        tracing::warn!("Service account {} used a key without the {} scope", name, required);
+        return reject(StatusCode::FORBIDDEN, format!("API key lacks the {} scope", required));
+    }
+
+    // Last use is kept to the minute, so busy integrations don't write on every request
+    if let Err(e) = sqlx::query(
+        "UPDATE service_accounts SET last_used_at = $1 WHERE id = $2 AND (last_used_at IS NULL OR last_used_at < $3)"
+    )
+    .bind(now)
+    .bind(account_id)
+    .bind(now - 60)
+    .execute(&pool)
+    .await
+    {
+        tracing::warn!("Failed to record use of service account {}: {}", name, e);
+    }
+
+    request.extensions_mut().insert(ServicePrincipal { name });
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Extension, Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    fn app(pool: DbPool) -> Router {
+        let caller = |principal: Option<Extension<ServicePrincipal>>| async move {
+            principal.map_or("anonymous".to_string(), |Extension(principal)| principal.username())
+        };
+        Router::new()
+            .route("/api/machines", get(caller))
+            .route("/api/alarms", get(caller))
+            .route("/api/users", get(caller))
+            .layer(middleware::from_fn_with_state(pool, authenticate))
+    }
+
+    async fn send(app: &Router, path: &str, key: Option<&str>) -> (StatusCode, String) {
+        let mut request = Request::builder().uri(path);
+        if let Some(key) = key {
+            request = request.header("authorization", format!("Bearer {}", key));
+        }
+        let response = app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn keys_open_only_the_endpoints_of_their_scopes() {
+        let pool = crate::database::test_database().await;
+        let (key, old_key) = (generate_key(), generate_key());
+        sqlx::query(
+            "INSERT INTO service_accounts (name, scopes, api_key, previous_api_key, previous_key_expires_at, created_by, created_at) \
+             VALUES ('erp', 'machines:read,work_orders:write', $1, $2, $3, 'boss', 0)"
+        )
+        .bind(auth::hash_token(&key))
+        .bind(auth::hash_token(&old_key))
+        .bind(current_timestamp() + 60)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let app = app(pool.clone());
+
+        assert_eq!(send(&app, "/api/machines", Some(&key)).await, (StatusCode::OK, "service:erp".to_string()));
+        assert_eq!(send(&app, "/api/machines", Some(&old_key)).await.0, StatusCode::OK);
+        let (status, body) = send(&app, "/api/alarms", Some(&key)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body.contains("API key lacks the alarms:read scope"), "{}", body);
+        assert_eq!(send(&app, "/api/users", Some(&key)).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, "/api/machines", Some(&generate_key())).await.0, StatusCode::UNAUTHORIZED);
+        // Session tokens and anonymous requests are left to the other extractors
+        assert_eq!(send(&app, "/api/users", Some("session")).await, (StatusCode::OK, "anonymous".to_string()));
+        assert_eq!(send(&app, "/api/users", None).await, (StatusCode::OK, "anonymous".to_string()));
+
+        let last_used: Option<i64> =
+            sqlx::query_scalar("SELECT last_used_at FROM service_accounts").fetch_one(&pool).await.unwrap();
+        assert!(last_used.is_some());
+
+        // The replaced key stops working once its grace period ends, as do disabled accounts
+        sqlx::query("UPDATE service_accounts SET previous_key_expires_at = $1")
+            .bind(current_timestamp())
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(send(&app, "/api/machines", Some(&old_key)).await.0, StatusCode::UNAUTHORIZED);
+        sqlx::query("UPDATE service_accounts SET disabled_at = 1").execute(&pool).await.unwrap();
+        assert_eq!(send(&app, "/api/machines", Some(&key)).await.0, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,381 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+
+use crate::{
+    auth,
+    config::Config,
+    database::{DbPool, current_timestamp},
+    models::{ErrorResponse, MACHINE_KEY_SCOPES},
+    rate_limit::{self, SignedRequestQuota},
+    usage::Principal,
+};
+
+// Speed updates are small; a signed body is read into memory before it can be checked
+const MAX_SIGNED_BODY: usize = 1024 * 1024;
+
+// A machine whose request carried a valid signature from one of its API keys, with that
+// key's scopes. MachineKey accepts it in place of the key itself.
+#[derive(Debug, Clone)]
+pub struct SignedMachine {
+    pub machine_id: i64,
+    pub scopes: Vec<String>,
+}
+
+struct Signature {
+    machine_id: i64,
+    timestamp: i64,
+    nonce: String,
+    mac: Vec<u8>,
+}
+
+fn rejected(error: &str) -> Response {
+    (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: error.to_string() })).into_response()
+}
+
+fn parse_signature(headers: &HeaderMap) -> Option<Signature> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let nonce = header("x-nonce")?;
+    if nonce.is_empty() || nonce.len() > 128 {
+        return None;
+    }
+    Some(Signature {
+        machine_id: header("x-machine-id")?.parse().ok()?,
+        timestamp: header("x-timestamp")?.parse().ok()?,
+        nonce: nonce.to_string(),
+        mac: hex::decode(header("x-signature")?).ok()?,
+    })
+}
+
+// The secret an API key signs with, handed out once along with the key. It is derived from
+// the stored hash of the key with SCADA_SIGNING_SECRET, which is not in the database, so a
+// copy of the database is not enough to forge signed updates.
+pub fn signing_secret(server_secret: &[u8], key_hash: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(server_secret).expect("HMAC accepts any key length");
+    mac.update(b"machine-signing\n");
+    mac.update(key_hash.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// Signing secret of a newly issued API key; None while the server has no signing secret
+pub fn issue_signing_secret(config: &Config, api_key: &str) -> Option<String> {
+    config
+        .signing_secret
+        .as_deref()
+        .map(|server_secret| signing_secret(server_secret, &auth::hash_token(api_key)))
+}
+
+// HMAC-SHA256 over "<timestamp>\n<nonce>\n<body>", keyed with the key's signing secret as hex.
+// A signing client never sends the key itself, so a proxy that sees the traffic can neither
+// replay nor forge an update.
+fn verify(server_secret: &[u8], key_hash: &str, signature: &Signature, body: &[u8]) -> bool {
+    let secret = signing_secret(server_secret, key_hash);
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}\n{}\n", signature.timestamp, signature.nonce).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&signature.mac).is_ok()
+}
+
+// Hash and scopes of the machine's current key that produced the signature, if any; archived
+// machines have none
+async fn signing_key(
+    pool: &DbPool,
+    server_secret: &[u8],
+    signature: &Signature,
+    body: &[u8],
+) -> sqlx::Result<Option<(String, Vec<String>)>> {
+    let primary: Option<String> = sqlx::query_scalar("SELECT api_key FROM machines WHERE id = $1 AND archived_at IS NULL")
+        .bind(signature.machine_id)
+        .fetch_optional(pool)
+        .await?;
+    if let Some(key_hash) = primary.filter(|key_hash| verify(server_secret, key_hash, signature, body)) {
+        return Ok(Some((key_hash, MACHINE_KEY_SCOPES.iter().map(|scope| scope.to_string()).collect())));
+    }
+
+    let additional: Vec<(String, String)> = sqlx::query_as(
+        "SELECT api_key, scopes FROM machine_api_keys \
+         WHERE machine_id = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > $2) \
+         AND machine_id IN (SELECT id FROM machines WHERE archived_at IS NULL)"
+    )
+    .bind(signature.machine_id)
+    .bind(current_timestamp())
+    .fetch_all(pool)
+    .await?;
+    Ok(additional
+        .into_iter()
+        .find(|(key_hash, _)| verify(server_secret, key_hash, signature, body))
+        .map(|(key_hash, scopes)| (key_hash, scopes.split(',').filter(|scope| !scope.is_empty()).map(str::to_string).collect())))
+}
+
+// Checks the HMAC signature of a machine update before the handler runs. Signed requests
+// identify the machine by X-Machine-Id instead of sending the key; a timestamp within the
+// allowed age and a nonce never seen before stop captured requests from being replayed.
+// Unsigned requests pass through to the usual API key check unless signing is required.
+pub async fn verify_signed_update(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !request.headers().contains_key("x-signature") {
+        if config.require_signed_updates {
+            return rejected("Signed request required");
+        }
+        return next.run(request).await;
+    }
+    let Some(server_secret) = config.signing_secret.as_deref() else {
+        return rejected("Signed updates are not enabled on this server");
+    };
+    let Some(signature) = parse_signature(request.headers()) else {
+        return rejected("Invalid request signature");
+    };
+    let now = current_timestamp();
+    let max_age = config.signature_max_age.as_secs() as i64;
+    if (now - signature.timestamp).abs() > max_age {
+        return rejected("Request timestamp outside the allowed window");
+    }
+
+    let (parts, body) = request.into_parts();
+    let Ok(body) = to_bytes(body, MAX_SIGNED_BODY).await else {
+        return (StatusCode::PAYLOAD_TOO_LARGE, Json(ErrorResponse {
+            error: "Request body too large".to_string(),
+        }))
+        .into_response();
+    };
+
+    let database_error = || (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+        error: "Database error".to_string(),
+    }))
+    .into_response();
+    let (key_hash, scopes) = match signing_key(&pool, server_secret, &signature, &body).await {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            tracing::warn!("Update for machine ID {} carried an invalid signature", signature.machine_id);
+            return rejected("Invalid request signature");
+        },
+        Err(_) => return database_error(),
+    };
+    // The rate limit counted the request against the client IP until now
+    if let Some(wait) = parts.extensions.get::<SignedRequestQuota>().and_then(|quota| quota.charge_key(&key_hash)) {
+        tracing::warn!("Rate limiting signed updates for machine ID {}", signature.machine_id);
+        return rate_limit::too_many_requests(wait);
+    }
+
+    // Nonces are only recorded once the signature holds, so nobody else can use up a machine's
+    if sqlx::query("DELETE FROM signed_request_nonces WHERE machine_id = $1 AND expires_at < $2")
+        .bind(signature.machine_id)
+        .bind(now)
+        .execute(&pool)
+        .await
+        .is_err()
+    {
+        return database_error();
+    }
+    match sqlx::query("INSERT INTO signed_request_nonces (machine_id, nonce, expires_at) VALUES ($1, $2, $3)")
+        .bind(signature.machine_id)
+        .bind(&signature.nonce)
+        .bind(signature.timestamp + max_age)
+        .execute(&pool)
+        .await
+    {
+        Ok(_) => {},
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            tracing::warn!("Replayed update for machine ID {} rejected", signature.machine_id);
+            return rejected("Nonce already used");
+        },
+        Err(_) => return database_error(),
+    }
+
+    let mut request = Request::from_parts(parts, Body::from(body));
+    request.extensions_mut().insert(SignedMachine {
+        machine_id: signature.machine_id,
+        scopes,
+    });
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(Principal { kind: "machine_key", id: key_hash });
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        extract::{ConnectInfo, FromRef},
+        middleware,
+        routing::post,
+        Router,
+    };
+    use std::net::SocketAddr;
+    use tower::ServiceExt;
+
+    const SERVER_SECRET: &[u8] = b"server secret";
+
+    #[derive(Clone)]
+    struct TestState {
+        pool: DbPool,
+        config: Arc<Config>,
+    }
+
+    impl FromRef<TestState> for DbPool {
+        fn from_ref(state: &TestState) -> Self {
+            state.pool.clone()
+        }
+    }
+
+    impl FromRef<TestState> for Arc<Config> {
+        fn from_ref(state: &TestState) -> Self {
+            state.config.clone()
+        }
+    }
+
+    // Machine 1 with primary key "primary-key" and additional keys "reader-key" (config:read),
+    // "revoked-key" and "expired-key", behind the signing middleware. The route answers with
+    // the verified machine and scopes, or nothing for an unsigned request.
+    async fn app() -> Router {
+        let pool = crate::database::test_database().await;
+        sqlx::query("INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', $1)")
+            .bind(auth::hash_token("primary-key"))
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (key, scopes, revoked_at, expires_at) in [
+            ("reader-key", "config:read", None, None),
+            ("revoked-key", "telemetry:write", Some(100), None),
+            ("expired-key", "telemetry:write", None, Some(100)),
+        ] {
+            sqlx::query(
+                "INSERT INTO machine_api_keys (machine_id, name, api_key, scopes, created_at, revoked_at, expires_at) \
+                 VALUES (1, $1, $2, $3, 0, $4, $5)"
+            )
+            .bind(key)
+            .bind(auth::hash_token(key))
+            .bind(scopes)
+            .bind(revoked_at)
+            .bind(expires_at)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let mut config = Config::from_env().unwrap();
+        config.signing_secret = Some(SERVER_SECRET.to_vec());
+        let state = TestState { pool, config: Arc::new(config) };
+
+        let echo = |request: Request| async move {
+            request
+                .extensions()
+                .get::<SignedMachine>()
+                .map(|machine| format!("{} {}", machine.machine_id, machine.scopes.join(",")))
+                .unwrap_or_default()
+        };
+        Router::new()
+            .route("/update", post(echo))
+            .route_layer(middleware::from_fn_with_state(state.clone(), verify_signed_update))
+            .with_state(state)
+    }
+
+    fn mac(key: &str, timestamp: i64, nonce: &str, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).unwrap();
+        mac.update(format!("{}\n{}\n{}", timestamp, nonce, body).as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    // Sends `body` with the given signature headers, if any; returns the status and response text
+    async fn send(app: &Router, signature: Option<(String, i64, &str)>, body: &str) -> (StatusCode, String) {
+        let mut request = Request::post("/update");
+        if let Some((mac, timestamp, nonce)) = signature {
+            request = request
+                .header("x-machine-id", "1")
+                .header("x-timestamp", timestamp.to_string())
+                .header("x-nonce", nonce)
+                .header("x-signature", mac);
+        }
+        let mut request = request.body(Body::from(body.to_string())).unwrap();
+        request.extensions_mut().insert(ConnectInfo("192.0.2.10:50000".parse::<SocketAddr>().unwrap()));
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    fn signed(api_key: &str, timestamp: i64, nonce: &'static str, body: &str) -> Option<(String, i64, &'static str)> {
+        let secret = signing_secret(SERVER_SECRET, &auth::hash_token(api_key));
+        Some((mac(&secret, timestamp, nonce, body), timestamp, nonce))
+    }
+
+    #[tokio::test]
+    async fn accepts_a_valid_signature_with_the_keys_scopes() {
+        let app = app().await;
+        let now = current_timestamp();
+        let body = r#"{"speed":42.0}"#;
+        assert_eq!(
+            send(&app, signed("primary-key", now, "n1", body), body).await,
+            (StatusCode::OK, format!("1 {}", MACHINE_KEY_SCOPES.join(","))),
+        );
+        assert_eq!(send(&app, signed("reader-key", now, "n2", body), body).await, (StatusCode::OK, "1 config:read".to_string()));
+        // Unsigned requests are left to the API key check
+        assert_eq!(send(&app, None, body).await, (StatusCode::OK, String::new()));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_tampered_body_or_a_signature_made_from_the_stored_hash() {
+        let app = app().await;
+        let now = current_timestamp();
+        let (status, _) = send(&app, signed("primary-key", now, "n1", r#"{"speed":42.0}"#), r#"{"speed":0.0}"#).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // A copy of the database holds the key hashes, which must not be enough to sign
+        let body = r#"{"speed":42.0}"#;
+        let forged = mac(&auth::hash_token("primary-key"), now, "n2", body);
+        assert_eq!(send(&app, Some((forged, now, "n2")), body).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn rejects_stale_timestamps_and_replayed_nonces() {
+        let app = app().await;
+        let now = current_timestamp();
+        let body = r#"{"speed":42.0}"#;
+        let (status, error) = send(&app, signed("primary-key", now - 3600, "n1", body), body).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(error.contains("outside the allowed window"));
+
+        assert_eq!(send(&app, signed("primary-key", now, "n2", body), body).await.0, StatusCode::OK);
+        let (status, error) = send(&app, signed("primary-key", now, "n2", body), body).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(error.contains("Nonce already used"));
+    }
+
+    #[tokio::test]
+    async fn rejects_revoked_and_expired_keys() {
+        let app = app().await;
+        let now = current_timestamp();
+        let body = r#"{"speed":42.0}"#;
+        assert_eq!(send(&app, signed("revoked-key", now, "n1", body), body).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(&app, signed("expired-key", now, "n2", body), body).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn verified_updates_count_against_the_signing_key_instead_of_the_ip() {
+        // One request per minute for the client IP, two for each machine key
+        let limit = rate_limit::TokenRateLimit::new(10, 2, 1, &[], crate::database::test_database().await);
+        let app = app().await.layer(middleware::from_fn_with_state(limit, rate_limit::limit_token_rate));
+        let now = current_timestamp();
+        let body = r#"{"speed":42.0}"#;
+        assert_eq!(send(&app, signed("primary-key", now, "n1", body), body).await.0, StatusCode::OK);
+        assert_eq!(send(&app, signed("primary-key", now, "n2", body), body).await.0, StatusCode::OK);
+        assert_eq!(send(&app, signed("primary-key", now, "n3", body), body).await.0, StatusCode::TOO_MANY_REQUESTS);
+        // The other key of the machine has a budget of its own
+        assert_eq!(send(&app, signed("reader-key", now, "n4", body), body).await.0, StatusCode::OK);
+
+        // Signatures that do not verify stay on the IP's quota
+        let forged = Some(("00".repeat(32), now, "n5"));
+        assert_eq!(send(&app, forged.clone(), body).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(&app, forged, body).await.0, StatusCode::TOO_MANY_REQUESTS);
+    }
+}
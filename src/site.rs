@@ -0,0 +1,95 @@
+use sqlx::Row;
+use std::path::PathBuf;
+
+use crate::{config::Config, database::DbPool, models::SiteSettings};
+
+// Plant name used until an admin sets one
+pub const DEFAULT_PLANT_NAME: &str = "SCADA";
+
+// Largest logo accepted for upload
+pub const MAX_LOGO_BYTES: usize = 1024 * 1024;
+
+// The logo is stored next to the machine documents, which are named by their numeric id
+pub fn logo_path(config: &Config) -> PathBuf {
+    config.document_dir.join("site-logo")
+}
+
+// Names shown for each configured shift. Shifts without a stored name, e.g. after
+// SCADA_SHIFT_STARTS gained a shift, are called "Shift <n>".
+fn shift_names(stored: Option<&str>, shift_count: usize) -> Vec<String> {
+    let stored: Vec<&str> = stored.map(|names| names.split('\n').collect()).unwrap_or_default();
+    (0..shift_count)
+        .map(|index| match stored.get(index) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => format!("Shift {}", index + 1),
+        })
+        .collect()
+}
+
+// The site's branding and naming, with defaults filled in for anything not configured
+pub async fn load(pool: &DbPool, config: &Config) -> sqlx::Result<SiteSettings> {
+    let row = sqlx::query("SELECT * FROM site_settings WHERE id = 1").fetch_optional(pool).await?;
+    let get = |column: &str| row.as_ref().and_then(|row| row.get::<Option<String>, _>(column));
+
+    Ok(SiteSettings {
+        plant_name: get("plant_name").unwrap_or_else(|| DEFAULT_PLANT_NAME.to_string()),
+        default_locale: get("default_locale"),
+        shift_names: shift_names(get("shift_names").as_deref(), config.shift_starts.len()),
+        logo_url: get("logo_content_type").map(|_| format!("{}/api/site-settings/logo", config.public_url)),
+        updated_at: row.as_ref().and_then(|row| row.get("updated_at")),
+    })
+}
+
+// Locale used for clients that don't ask for one
+pub async fn default_locale(pool: &DbPool) -> sqlx::Result<Option<String>> {
+    sqlx::query_scalar("SELECT default_locale FROM site_settings WHERE id = 1")
+        .fetch_optional(pool)
+        .await
+        .map(Option::flatten)
+}
+
+pub async fn plant_name(pool: &DbPool) -> sqlx::Result<String> {
+    let name: Option<Option<String>> = sqlx::query_scalar("SELECT plant_name FROM site_settings WHERE id = 1")
+        .fetch_optional(pool)
+        .await?;
+    Ok(name.flatten().unwrap_or_else(|| DEFAULT_PLANT_NAME.to_string()))
+}
+
+// File-name-safe form of the plant name, e.g. "Plant 2 (Linz)" -> "plant-2-linz"
+pub fn slug(plant_name: &str) -> String {
+    plant_name
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unnamed_shifts_are_numbered() {
+        assert_eq!(shift_names(None, 2), ["Shift 1", "Shift 2"]);
+        assert_eq!(shift_names(Some("Early\n\nNight\nSpare"), 3), ["Early", "Shift 2", "Night"]);
+    }
+
+    #[test]
+    fn slugs_are_file_name_safe() {
+        assert_eq!(slug("Plant 2 (Linz)"), "plant-2-linz");
+        assert_eq!(slug("  North--Hall "), "north-hall");
+    }
+
+    #[tokio::test]
+    async fn defaults_apply_until_an_admin_sets_a_value() {
+        let pool = crate::database::test_database().await;
+        assert_eq!(plant_name(&pool).await.unwrap(), DEFAULT_PLANT_NAME);
+        assert_eq!(default_locale(&pool).await.unwrap(), None);
+        sqlx::query("INSERT INTO site_settings (id, default_locale) VALUES (1, 'de')").execute(&pool).await.unwrap();
+        assert_eq!(plant_name(&pool).await.unwrap(), DEFAULT_PLANT_NAME);
+        assert_eq!(default_locale(&pool).await.unwrap().as_deref(), Some("de"));
+        sqlx::query("UPDATE site_settings SET plant_name = 'Linz'").execute(&pool).await.unwrap();
+        assert_eq!(plant_name(&pool).await.unwrap(), "Linz");
+    }
+}
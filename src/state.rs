@@ -0,0 +1,114 @@
+use axum::extract::FromRef;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+use crate::{
+    chaos::Chaos,
+    config::Config,
+    database::DbPool,
+    jobs::{ArtifactStore, Jobs},
+    mailer::Mailer,
+    read_only::ReadOnlyMode,
+    usage::UsageRecorder,
+};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: DbPool,
+    pub config: Arc<Config>,
+    pub machine_changes: MachineChanges,
+    pub jobs: Jobs,
+    pub read_only: ReadOnlyMode,
+    pub chaos: Chaos,
+    pub mailer: Mailer,
+    pub usage: UsageRecorder,
+}
+
+impl AppState {
+    pub fn new(db: DbPool, config: Config, chaos: Chaos, mailer: Mailer) -> Self {
+        Self {
+            chaos,
+            mailer,
+            usage: UsageRecorder::default(),
+            jobs: Jobs::new(
+                db.clone(),
+                config.job_workers,
+                ArtifactStore::new(
+                    config.artifact_dir.clone(),
+                    config.artifact_retention,
+                    config.download_secret.clone(),
+                ),
+            ),
+            read_only: ReadOnlyMode::new(config.read_only, config.read_only_after_failures),
+            db,
+            config: Arc::new(config),
+            machine_changes: MachineChanges::new(),
+        }
+    }
+}
+
+impl FromRef<AppState> for DbPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for Jobs {
+    fn from_ref(state: &AppState) -> Self {
+        state.jobs.clone()
+    }
+}
+
+impl FromRef<AppState> for ReadOnlyMode {
+    fn from_ref(state: &AppState) -> Self {
+        state.read_only.clone()
+    }
+}
+
+impl FromRef<AppState> for Chaos {
+    fn from_ref(state: &AppState) -> Self {
+        state.chaos.clone()
+    }
+}
+
+impl FromRef<AppState> for UsageRecorder {
+    fn from_ref(state: &AppState) -> Self {
+        state.usage.clone()
+    }
+}
+
+impl FromRef<AppState> for Mailer {
+    fn from_ref(state: &AppState) -> Self {
+        state.mailer.clone()
+    }
+}
+
+impl FromRef<AppState> for MachineChanges {
+    fn from_ref(state: &AppState) -> Self {
+        state.machine_changes.clone()
+    }
+}
+
+// Wakes up long-polling clients whenever a machine record is written
+#[derive(Clone)]
+pub struct MachineChanges(watch::Sender<i64>);
+
+impl MachineChanges {
+    pub fn new() -> Self {
+        Self(watch::Sender::new(0))
+    }
+
+    pub fn notify(&self, timestamp: i64) {
+        self.0.send_replace(timestamp);
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<i64> {
+        self.0.subscribe()
+    }
+}
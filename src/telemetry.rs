@@ -0,0 +1,126 @@
+use axum::{extract::MatchedPath, http::Request};
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::config::{Config, LogFormat};
+
+// Keeps the exporters alive; call shutdown on exit so buffered spans and events are flushed
+pub struct Telemetry {
+    tracer_provider: Option<SdkTracerProvider>,
+    _sentry: Option<sentry::ClientInitGuard>,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        if let Some(provider) = self.tracer_provider
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+// Sets up logging, span export over OTLP/HTTP when OTEL_EXPORTER_OTLP_ENDPOINT is set, and
+// error reporting to Sentry when SENTRY_DSN is set.
+pub fn init(config: &Config) -> anyhow::Result<Telemetry> {
+    let mut log_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into());
+    for (module, level) in &config.log_levels {
+        log_filter.push_str(&format!(",{}={}", module, level));
+    }
+
+    // JSON lines carry the request span's fields (request_id, machine_id) alongside each event
+    let fmt_layer = match config.log_format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    }
+    .with_filter(EnvFilter::new(&log_filter));
+
+    let tracer_provider = if std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").is_ok() {
+        // Exporter endpoint, headers and timeout come from the standard OTEL_EXPORTER_OTLP_* variables
+        let exporter = opentelemetry_otlp::SpanExporter::builder().with_http().build()?;
+        let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| env!("CARGO_PKG_NAME").into());
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        Some(
+            SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name(service_name).build())
+                .build(),
+        )
+    } else {
+        None
+    };
+
+    // Query events from sqlx are exported as span events so slow statements show up in the trace
+    let otel_layer = tracer_provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+            .with_filter(EnvFilter::new(format!("{},sqlx::query=debug", log_filter)))
+    });
+
+    // Panics and error-level events (the cause behind a "Database error" response) become Sentry
+    // events; lower levels are attached as breadcrumbs. DSN and environment come from SENTRY_DSN
+    // and SENTRY_ENVIRONMENT.
+    let sentry = std::env::var("SENTRY_DSN").is_ok().then(|| {
+        sentry::init(sentry::ClientOptions {
+            release: sentry::release_name!(),
+            ..Default::default()
+        })
+    });
+    let sentry_layer = sentry.is_some().then(sentry::integrations::tracing::layer);
+
+    tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(otel_layer)
+        .with(sentry_layer)
+        .init();
+
+    Ok(Telemetry { tracer_provider, _sentry: sentry })
+}
+
+// Request span tagged with the x-request-id header. It continues the caller's trace when a
+// W3C traceparent header is present.
+pub fn request_span<B>(request: &Request<B>) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format!("{} {}", request.method(), route),
+        otel.kind = "server",
+        http.request.method = %request.method(),
+        http.route = %route,
+        url.path = %request.uri().path(),
+        request_id = %request_id,
+        machine_id = tracing::field::Empty,
+        impersonated_by = tracing::field::Empty,
+    );
+
+    // Machine-scoped routes carry the machine in the path; ingestion records it after authentication
+    if route.starts_with("/api/machines/{id}")
+        && let Some(machine_id) = request.uri().path().split('/').nth(3).and_then(|id| id.parse::<i64>().ok())
+    {
+        span.record("machine_id", machine_id);
+    }
+
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(request.headers())));
+    let _ = span.set_parent(parent);
+    span
+}
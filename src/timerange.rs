@@ -0,0 +1,245 @@
+use chrono::{DateTime, Datelike, Days, LocalResult, Months, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeDelta, TimeZone};
+use chrono_tz::Tz;
+
+use crate::config::Config;
+
+// The instant a local wall-clock time falls on. When clocks go back the time happens twice
+// and the first is taken; when they go forward it is skipped and the boundary moves to the
+// first local time after the jump, so a shift starting at 02:30 on that night starts at 03:00.
+pub fn local_instant(tz: Tz, local: NaiveDateTime) -> Option<DateTime<Tz>> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(at) => Some(at),
+        LocalResult::Ambiguous(first, _) => Some(first),
+        LocalResult::None => (1..=24 * 60).find_map(|minutes| tz.from_local_datetime(&(local + TimeDelta::minutes(minutes))).earliest()),
+    }
+}
+
+// Resolves a named range such as `this_shift` into a `[from, to)` pair of Unix timestamps,
+// using the site timezone and shift calendar from the configuration.
+pub fn resolve_range(name: &str, now: i64, config: &Config) -> Option<(i64, i64)> {
+    let tz = config.site_timezone;
+    let local_now = tz.timestamp_opt(now, 0).single()?;
+
+    match name {
+        "last_24h" => Some((now - 24 * 60 * 60, now)),
+        "today" => {
+            let midnight = local_instant(tz, local_now.date_naive().and_time(NaiveTime::MIN))?;
+            Some((midnight.timestamp(), now))
+        },
+        "this_shift" => {
+            let starts = shift_boundaries(&local_now, config)?;
+            let current = starts.iter().rposition(|start| start.timestamp() <= now)?;
+            Some((starts[current].timestamp(), starts.get(current + 1)?.timestamp()))
+        },
+        "last_shift" => {
+            let starts = shift_boundaries(&local_now, config)?;
+            let current = starts.iter().rposition(|start| start.timestamp() <= now)?;
+            let previous = current.checked_sub(1)?;
+            Some((starts[previous].timestamp(), starts[current].timestamp()))
+        },
+        _ => None,
+    }
+}
+
+// Shift start instants from yesterday through tomorrow, in chronological order
+fn shift_boundaries(local_now: &DateTime<Tz>, config: &Config) -> Option<Vec<DateTime<Tz>>> {
+    if config.shift_starts.is_empty() {
+        return None;
+    }
+
+    let today = local_now.date_naive();
+    let mut starts = Vec::with_capacity(config.shift_starts.len() * 3);
+    for day in [today.checked_sub_days(Days::new(1))?, today, today.checked_add_days(Days::new(1))?] {
+        for start in &config.shift_starts {
+            starts.push(local_instant(local_now.timezone(), day.and_time(*start))?);
+        }
+    }
+    Some(starts)
+}
+
+// Start and end of shift `index` on the local day `date`. A shift ends where the next one
+// starts, so the last shift of a day runs into the next morning.
+pub fn shift_window(date: NaiveDate, index: usize, config: &Config) -> Option<(i64, i64)> {
+    let tz = config.site_timezone;
+    let start = config.shift_starts.get(index)?;
+    let end = match config.shift_starts.get(index + 1) {
+        Some(next) => date.and_time(*next),
+        None => date.checked_add_days(Days::new(1))?.and_time(*config.shift_starts.first()?),
+    };
+    Some((local_instant(tz, date.and_time(*start))?.timestamp(), local_instant(tz, end)?.timestamp()))
+}
+
+// Start and end of the local calendar month `first_day` begins
+pub fn month_window(first_day: NaiveDate, config: &Config) -> Option<(i64, i64)> {
+    let tz = config.site_timezone;
+    let start = local_instant(tz, first_day.and_time(NaiveTime::MIN))?;
+    let end = local_instant(tz, first_day.checked_add_months(Months::new(1))?.and_time(NaiveTime::MIN))?;
+    Some((start.timestamp(), end.timestamp()))
+}
+
+// First day of the local calendar month before the one containing `now`
+pub fn previous_month(now: i64, config: &Config) -> Option<NaiveDate> {
+    let today = config.site_timezone.timestamp_opt(now, 0).single()?.date_naive();
+    today.with_day(1)?.checked_sub_months(Months::new(1))
+}
+
+// Seconds the site timezone is ahead of UTC at `timestamp`
+fn utc_offset(tz: Tz, timestamp: i64) -> i64 {
+    let at = DateTime::from_timestamp(timestamp, 0).unwrap_or_default().naive_utc();
+    tz.offset_from_utc_datetime(&at).fix().local_minus_utc() as i64
+}
+
+// SQL for the local wall-clock time of the Unix timestamp `column`, as seconds since the epoch,
+// for grouping rows into local days and months with database::format_date. The database
+// knows no timezone rules, so the offset is spelled out for every daylight saving change
+// between `from` and `to`.
+pub fn local_time_sql(column: &str, from: i64, to: i64, config: &Config) -> String {
+    const DAY: i64 = 24 * 60 * 60;
+    let tz = config.site_timezone;
+    // Far enough either way for any real report, while keeping the scan short
+    let (from, to) = (from.max(0), to.min(from.max(0) + 200 * 366 * DAY));
+
+    let mut offset = utc_offset(tz, from);
+    let mut changes = Vec::new();
+    let mut day = from;
+    while day < to {
+        let next = (day + DAY).min(to);
+        if utc_offset(tz, next) != offset {
+            // Narrow the change down to the second it happens
+            let (mut before, mut after) = (day, next);
+            while after - before > 1 {
+                let middle = before + (after - before) / 2;
+                if utc_offset(tz, middle) == offset {
+                    before = middle;
+                } else {
+                    after = middle;
+                }
+            }
+            changes.push((after, offset));
+            offset = utc_offset(tz, after);
+        }
+        day = next;
+    }
+
+    if changes.is_empty() {
+        return format!("({} + {})", column, offset);
+    }
+    let cases: String = changes.iter().map(|(at, before)| format!(" WHEN {} < {} THEN {}", column, at, before)).collect();
+    format!("({} + CASE{} ELSE {} END)", column, cases, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // UTC+2 with shifts starting at 06:00, 14:00 and 22:00
+    fn config() -> Config {
+        let mut config = Config::from_env().unwrap();
+        config.site_timezone = Tz::Etc__GMTMinus2;
+        config.shift_starts = ["06:00", "14:00", "22:00"]
+            .into_iter()
+            .map(|start| NaiveTime::parse_from_str(start, "%H:%M").unwrap())
+            .collect();
+        config
+    }
+
+    fn local(datetime: &str) -> i64 {
+        DateTime::parse_from_str(&format!("{} +0200", datetime), "%Y-%m-%d %H:%M %z").unwrap().timestamp()
+    }
+
+    #[test]
+    fn resolves_ranges_in_the_site_timezone() {
+        let config = config();
+        let now = local("2026-03-10 23:30");
+        assert_eq!(resolve_range("last_24h", now, &config), Some((now - 86400, now)));
+        assert_eq!(resolve_range("today", now, &config), Some((local("2026-03-10 00:00"), now)));
+        assert_eq!(resolve_range("this_shift", now, &config), Some((local("2026-03-10 22:00"), local("2026-03-11 06:00"))));
+        assert_eq!(resolve_range("last_shift", now, &config), Some((local("2026-03-10 14:00"), local("2026-03-10 22:00"))));
+        assert_eq!(resolve_range("next_week", now, &config), None);
+    }
+
+    #[test]
+    fn night_shift_runs_across_midnight() {
+        let config = config();
+        let now = local("2026-03-11 03:00");
+        assert_eq!(resolve_range("this_shift", now, &config), Some((local("2026-03-10 22:00"), local("2026-03-11 06:00"))));
+        let date = NaiveDate::from_ymd_opt(2026, 3, 10).unwrap();
+        assert_eq!(shift_window(date, 2, &config), Some((local("2026-03-10 22:00"), local("2026-03-11 06:00"))));
+        assert_eq!(shift_window(date, 3, &config), None);
+
+        let mut config = config;
+        config.shift_starts.clear();
+        assert_eq!(resolve_range("this_shift", now, &config), None);
+    }
+
+    #[test]
+    fn months_follow_the_local_calendar() {
+        let config = config();
+        let first = previous_month(local("2026-03-01 01:00"), &config).unwrap();
+        assert_eq!(first, NaiveDate::from_ymd_opt(2026, 2, 1).unwrap());
+        assert_eq!(month_window(first, &config), Some((local("2026-02-01 00:00"), local("2026-03-01 00:00"))));
+    }
+
+    // Berlin, which moves to summer time on 2026-03-29 at 02:00 and back on 2026-10-25 at 03:00
+    fn berlin() -> Config {
+        Config { site_timezone: Tz::Europe__Berlin, ..config() }
+    }
+
+    fn utc(datetime: &str) -> i64 {
+        DateTime::parse_from_str(&format!("{} +0000", datetime), "%Y-%m-%d %H:%M %z").unwrap().timestamp()
+    }
+
+    #[test]
+    fn spring_forward_shortens_the_night_shift() {
+        let config = berlin();
+        let date = NaiveDate::from_ymd_opt(2026, 3, 28).unwrap();
+        // 22:00 winter time to 06:00 summer time is seven hours
+        assert_eq!(shift_window(date, 2, &config), Some((utc("2026-03-28 21:00"), utc("2026-03-29 04:00"))));
+        let now = utc("2026-03-29 10:00");
+        assert_eq!(resolve_range("today", now, &config), Some((utc("2026-03-28 23:00"), now)));
+        assert_eq!(resolve_range("this_shift", now, &config), Some((utc("2026-03-29 04:00"), utc("2026-03-29 12:00"))));
+
+        // 02:30 does not happen that night; the shift starts when the clocks jump to 03:00
+        let mut config = config;
+        config.shift_starts = vec![NaiveTime::from_hms_opt(2, 30, 0).unwrap(), NaiveTime::from_hms_opt(14, 30, 0).unwrap()];
+        let date = NaiveDate::from_ymd_opt(2026, 3, 29).unwrap();
+        assert_eq!(shift_window(date, 0, &config), Some((utc("2026-03-29 01:00"), utc("2026-03-29 12:30"))));
+    }
+
+    #[test]
+    fn fall_back_lengthens_the_night_shift() {
+        let config = berlin();
+        let date = NaiveDate::from_ymd_opt(2026, 10, 24).unwrap();
+        // 22:00 summer time to 06:00 winter time is nine hours
+        assert_eq!(shift_window(date, 2, &config), Some((utc("2026-10-24 20:00"), utc("2026-10-25 05:00"))));
+        let now = utc("2026-10-25 02:30");
+        assert_eq!(resolve_range("last_shift", now, &config), Some((utc("2026-10-24 12:00"), utc("2026-10-24 20:00"))));
+        assert_eq!(resolve_range("today", now, &config), Some((utc("2026-10-24 22:00"), now)));
+
+        // 02:30 happens twice that night; the shift starts the first time
+        let mut config = config;
+        config.shift_starts = vec![NaiveTime::from_hms_opt(2, 30, 0).unwrap(), NaiveTime::from_hms_opt(14, 30, 0).unwrap()];
+        let date = NaiveDate::from_ymd_opt(2026, 10, 25).unwrap();
+        assert_eq!(shift_window(date, 0, &config), Some((utc("2026-10-25 00:30"), utc("2026-10-25 13:30"))));
+    }
+
+    #[test]
+    fn months_across_a_clock_change_start_at_local_midnight() {
+        let config = berlin();
+        let march = NaiveDate::from_ymd_opt(2026, 3, 1).unwrap();
+        assert_eq!(month_window(march, &config), Some((utc("2026-02-28 23:00"), utc("2026-03-31 22:00"))));
+        assert_eq!(previous_month(utc("2026-04-30 22:30"), &config), NaiveDate::from_ymd_opt(2026, 4, 1));
+    }
+
+    #[test]
+    fn local_time_sql_spells_out_each_clock_change() {
+        assert_eq!(local_time_sql("t", 0, 86400, &config()), "(t + 7200)");
+        let config = berlin();
+        let (spring, fall) = (utc("2026-03-29 01:00"), utc("2026-10-25 01:00"));
+        assert_eq!(
+            local_time_sql("t", utc("2026-01-01 00:00"), utc("2026-12-31 00:00"), &config),
+            format!("(t + CASE WHEN t < {} THEN 3600 WHEN t < {} THEN 7200 ELSE 3600 END)", spring, fall)
+        );
+        assert_eq!(local_time_sql("t", utc("2026-05-01 00:00"), utc("2026-06-01 00:00"), &config), "(t + 7200)");
+    }
+}
@@ -0,0 +1,126 @@
+use std::str::FromStr;
+
+use crate::database::DbPool;
+
+// What ingestion does with a reading whose unit is unknown or of the wrong dimension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnitPolicy {
+    // Refuse the whole update
+    Reject,
+    // Store the reading marked as flagged, without updating the machine's current value
+    Flag,
+}
+
+impl FromStr for UnitPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "reject" => Ok(Self::Reject),
+            "flag" => Ok(Self::Flag),
+            _ => Err("expected 'reject' or 'flag'".to_string()),
+        }
+    }
+}
+
+// Checks a reading's unit against the catalog and the unit the machine already reports the
+// metric in. Readings in a compatible unit (bar where psi is established) are converted;
+// the error describes why a reading was not accepted.
+pub async fn normalize_reading(
+    pool: &DbPool,
+    machine_id: i64,
+    metric: &str,
+    value: f64,
+    unit: &str,
+) -> sqlx::Result<Result<(f64, String), String>> {
+    let Some((dimension, factor)) = lookup(pool, unit).await? else {
+        return Ok(Err(format!("Unknown unit '{}' for metric '{}'", unit, metric)));
+    };
+
+    let established: Option<String> = sqlx::query_scalar(
+        "SELECT unit FROM machine_metrics WHERE machine_id = $1 AND metric = $2"
+    )
+    .bind(machine_id)
+    .bind(metric)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(established) = established.filter(|established| established != unit) else {
+        return Ok(Ok((value, unit.to_string())));
+    };
+
+    match lookup(pool, &established).await? {
+        Some((expected_dimension, expected_factor)) if expected_dimension == dimension => {
+            Ok(Ok((value * factor / expected_factor, established)))
+        },
+        Some((expected_dimension, _)) => Ok(Err(format!(
+            "Unit '{}' ({}) does not match metric '{}', which is reported in '{}' ({})",
+            unit, dimension, metric, established, expected_dimension
+        ))),
+        // The established unit was removed from the catalog; start over with the new one
+        None => Ok(Ok((value, unit.to_string()))),
+    }
+}
+
+// Converts a value between two units of the catalog; None when either is unknown or they
+// measure different dimensions
+pub async fn convert(pool: &DbPool, value: f64, from: &str, to: &str) -> sqlx::Result<Option<f64>> {
+    if from == to {
+        return Ok(Some(value));
+    }
+    match (lookup(pool, from).await?, lookup(pool, to).await?) {
+        (Some((from_dimension, from_factor)), Some((to_dimension, to_factor))) if from_dimension == to_dimension => {
+            Ok(Some(value * from_factor / to_factor))
+        },
+        _ => Ok(None),
+    }
+}
+
+// Dimension and factor to the dimension's base unit of a unit in the catalog
+pub async fn lookup(pool: &DbPool, symbol: &str) -> sqlx::Result<Option<(String, f64)>> {
+    sqlx::query_as("SELECT dimension, factor FROM units WHERE symbol = $1")
+        .bind(symbol)
+        .fetch_optional(pool)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Machine 1 already reports pressure in psi
+    async fn database() -> DbPool {
+        let pool = crate::database::test_database().await;
+        for statement in [
+            "INSERT INTO machines (id, name, code, api_key) VALUES (1, 'Line 1', 'L1', 'key')",
+            "INSERT INTO machine_metrics (machine_id, metric, unit, value, updated_at) VALUES (1, 'pressure', 'psi', 30.0, 0)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    #[tokio::test]
+    async fn converts_readings_to_the_established_unit() {
+        let pool = database().await;
+        let (value, unit) = normalize_reading(&pool, 1, "pressure", 2.0, "bar").await.unwrap().unwrap();
+        assert_eq!(unit, "psi");
+        assert!((value - 29.0075).abs() < 1e-3, "{}", value);
+        assert_eq!(normalize_reading(&pool, 1, "pressure", 30.0, "psi").await.unwrap(), Ok((30.0, "psi".to_string())));
+        // A metric without an established unit takes the one it is first reported in
+        assert_eq!(normalize_reading(&pool, 1, "temperature", 80.0, "degC").await.unwrap(), Ok((80.0, "degC".to_string())));
+    }
+
+    #[tokio::test]
+    async fn refuses_unknown_units_and_other_dimensions() {
+        let pool = database().await;
+        let unknown = normalize_reading(&pool, 1, "pressure", 2.0, "atm").await.unwrap().unwrap_err();
+        assert!(unknown.contains("Unknown unit 'atm'"), "{}", unknown);
+        let mismatch = normalize_reading(&pool, 1, "pressure", 2.0, "degC").await.unwrap().unwrap_err();
+        assert!(mismatch.contains("does not match metric 'pressure'"), "{}", mismatch);
+
+        assert_eq!(convert(&pool, 1.5, "kW", "W").await.unwrap(), Some(1500.0));
+        assert_eq!(convert(&pool, 1.5, "kW", "rpm").await.unwrap(), None);
+        assert!("drop".parse::<UnitPolicy>().is_err());
+    }
+}
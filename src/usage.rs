@@ -0,0 +1,217 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+
+use crate::database::{DbPool, current_timestamp};
+
+// Per-hour request counts for each caller and endpoint, kept in memory and written to
+// api_usage once a minute, so counting never adds a database write to a request
+#[derive(Clone, Default)]
+pub struct UsageRecorder {
+    pending: Arc<Mutex<HashMap<UsageKey, UsageCounts>>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct UsageKey {
+    hour: i64,
+    // "user", "machine_key", "service_account", "display_token", "anonymous" or "invalid"
+    principal_kind: &'static str,
+    // Username, or the hash of a machine or service account key
+    principal: String,
+    endpoint: String,
+}
+
+#[derive(Clone, Copy, Default)]
+struct UsageCounts {
+    requests: i64,
+    client_errors: i64,
+    server_errors: i64,
+    total_ms: i64,
+}
+
+impl UsageRecorder {
+    fn record(&self, key: UsageKey, status: u16, elapsed: Duration) {
+        let mut pending = self.pending.lock().unwrap();
+        let counts = pending.entry(key).or_default();
+        counts.requests += 1;
+        counts.client_errors += i64::from((400..500).contains(&status));
+        counts.server_errors += i64::from(status >= 500);
+        counts.total_ms += elapsed.as_millis() as i64;
+    }
+
+    // Writes the counts gathered since the last flush; on failure they are kept for the next one
+    pub async fn flush(&self, pool: &DbPool) -> sqlx::Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = pool.begin().await?;
+        let mut written = Ok(());
+        for (key, counts) in &pending {
+            written = sqlx::query(
+                "INSERT INTO api_usage (hour, principal_kind, principal, endpoint, requests, client_errors, server_errors, total_ms) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+                 ON CONFLICT (hour, principal_kind, principal, endpoint) DO UPDATE SET \
+                 requests = api_usage.requests + excluded.requests, client_errors = api_usage.client_errors + excluded.client_errors, \
+                 server_errors = api_usage.server_errors + excluded.server_errors, total_ms = api_usage.total_ms + excluded.total_ms"
+            )
+            .bind(key.hour)
+            .bind(key.principal_kind)
+            .bind(&key.principal)
+            .bind(&key.endpoint)
+            .bind(counts.requests)
+            .bind(counts.client_errors)
+            .bind(counts.server_errors)
+            .bind(counts.total_ms)
+            .execute(&mut *tx)
+            .await
+            .map(|_| ());
+            if written.is_err() {
+                break;
+            }
+        }
+        if written.is_ok() {
+            written = tx.commit().await;
+        }
+
+        if written.is_err() {
+            let mut current = self.pending.lock().unwrap();
+            for (key, counts) in pending {
+                let merged = current.entry(key).or_default();
+                merged.requests += counts.requests;
+                merged.client_errors += counts.client_errors;
+                merged.server_errors += counts.server_errors;
+                merged.total_ms += counts.total_ms;
+            }
+        }
+        written
+    }
+}
+
+// Who made a request, as established by the rate limit: a user by their session token's
+// signature; a machine key, service account key or display token by its hash once it is found
+// among the active keys, which the summary resolves to the machine, account or display.
+// Requests without a token are "anonymous", ones whose token could not be verified "invalid".
+#[derive(Clone)]
+pub struct Principal {
+    pub kind: &'static str,
+    pub id: String,
+}
+
+impl Principal {
+    pub fn anonymous() -> Self {
+        Self { kind: "anonymous", id: String::new() }
+    }
+
+    pub fn invalid() -> Self {
+        Self { kind: "invalid", id: String::new() }
+    }
+
+    // Whether the request's credentials were checked and found valid
+    pub fn is_verified(&self) -> bool {
+        !matches!(self.kind, "anonymous" | "invalid")
+    }
+}
+
+pub async fn record_usage(State(recorder): State<UsageRecorder>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |path| path.as_str())
+        .to_string();
+    let endpoint = format!("{} {}", request.method(), route);
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let principal = response.extensions().get::<Principal>().cloned().unwrap_or_else(Principal::anonymous);
+    let now = current_timestamp();
+    recorder.record(
+        UsageKey {
+            hour: now - now.rem_euclid(3600),
+            principal_kind: principal.kind,
+            principal: principal.id,
+            endpoint,
+        },
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+    response
+}
+
+// Flushes the recorded usage every minute and drops hours older than `retention`
+pub fn spawn_usage_flush(pool: DbPool, recorder: UsageRecorder, retention: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = recorder.flush(&pool).await {
+                tracing::error!("Failed to write API usage: {}", e);
+            }
+            if let Err(e) = sqlx::query("DELETE FROM api_usage WHERE hour < $1")
+                .bind(current_timestamp() - retention.as_secs() as i64)
+                .execute(&pool)
+                .await
+            {
+                tracing::error!("Failed to prune API usage: {}", e);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::StatusCode, middleware, response::IntoResponse, routing::get};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn counts_requests_per_caller_and_route_across_flushes() {
+        let pool = crate::database::test_database().await;
+        let recorder = UsageRecorder::default();
+        let app = Router::new()
+            .route(
+                "/api/machines/{id}",
+                get(|| async {
+                    let mut response = StatusCode::NOT_FOUND.into_response();
+                    response.extensions_mut().insert(Principal { kind: "user", id: "alice".to_string() });
+                    response
+                }),
+            )
+            .route("/api/failing", get(|| async { StatusCode::INTERNAL_SERVER_ERROR }))
+            .layer(middleware::from_fn_with_state(recorder.clone(), record_usage));
+        let send = async |path: &str| {
+            app.clone().oneshot(Request::builder().uri(path).body(Body::empty()).unwrap()).await.unwrap();
+        };
+
+        send("/api/machines/1").await;
+        send("/api/machines/2").await;
+        send("/api/failing").await;
+        recorder.flush(&pool).await.unwrap();
+        send("/api/machines/3").await;
+        recorder.flush(&pool).await.unwrap();
+        recorder.flush(&pool).await.unwrap();
+
+        let usage: Vec<(String, String, String, i64, i64, i64)> = sqlx::query_as(
+            "SELECT principal_kind, principal, endpoint, requests, client_errors, server_errors FROM api_usage ORDER BY endpoint"
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            usage,
+            [
+                ("anonymous".to_string(), String::new(), "GET /api/failing".to_string(), 1, 0, 1),
+                ("user".to_string(), "alice".to_string(), "GET /api/machines/{id}".to_string(), 3, 3, 0),
+            ]
+        );
+    }
+}